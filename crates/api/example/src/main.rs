@@ -1,6 +1,6 @@
 use ggcat_api::{
-    ColoredQueryOutputFormat, ExtraElaboration, GGCATConfig, GGCATInstance,
-    GeneralSequenceBlockData,
+    BuildOptions, ColoredQueryOutputFormat, ExtraElaboration, GGCATConfig, GGCATInstance,
+    GeneralSequenceBlockData, LogLevel, PacketsPoolsCapacities,
 };
 use itertools::Itertools;
 use std::{path::PathBuf, sync::Mutex};
@@ -11,8 +11,27 @@ fn main() {
         memory: 2.0,
         prefer_memory: true,
         total_threads_count: 16,
+        allow_oversubscribe: false,
         intermediate_compression_level: None,
+        output_compression_level: None,
+        bgzf_output: false,
+        output_flush_sequences: None,
+        output_flush_bytes: None,
         stats_file: None,
+        minimizer_hash_seed: 0,
+        temp_files_prefix: None,
+        extra_temp_dirs: vec![],
+        split_on_softmask: false,
+        iupac_reads_packing: false,
+        interleaved_paired_reads: false,
+        use_second_bucket: false,
+        reads_buffer_capacity: None,
+        links_compaction_links_buffer: None,
+        links_compaction_results_buffer: None,
+        packets_pools_capacities: PacketsPoolsCapacities::default(),
+        sync_checkpoints: false,
+        checkpoint_checksums: false,
+        log_level: LogLevel::Default,
     });
 
     let graph_file = PathBuf::from("/tmp/sal-dbg.fa");
@@ -21,22 +40,27 @@ fn main() {
 
     // Example building of a colored graph from three FASTA files
     // building also bcalm2-style links across maximal unitigs
-    let graph_file = instance.build_graph(
-        vec![
-            GeneralSequenceBlockData::FASTA(PathBuf::from("../../../example-inputs/sal1.fa")),
-            GeneralSequenceBlockData::FASTA(PathBuf::from("../../../example-inputs/sal2.fa")),
-            GeneralSequenceBlockData::FASTA(PathBuf::from("../../../example-inputs/sal3.fa")),
-        ],
-        graph_file.clone(),
-        Some(&["sal1".to_string(), "sal2".to_string(), "sal3".to_string()]),
-        k,
-        threads_count,
-        false,
-        None,
-        true,
-        1,
-        ExtraElaboration::UnitigLinks,
-    );
+    let graph_file = instance
+        .build_graph(
+            vec![
+                GeneralSequenceBlockData::FASTA(PathBuf::from("../../../example-inputs/sal1.fa")),
+                GeneralSequenceBlockData::FASTA(PathBuf::from("../../../example-inputs/sal2.fa")),
+                GeneralSequenceBlockData::FASTA(PathBuf::from("../../../example-inputs/sal3.fa")),
+            ],
+            graph_file.clone(),
+            Some(&["sal1".to_string(), "sal2".to_string(), "sal3".to_string()]),
+            BuildOptions {
+                kmer_length: k,
+                threads_count,
+                colors: true,
+                min_multiplicity: 1,
+                extra_elab: ExtraElaboration::UnitigLinks,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .single_file()
+        .unwrap();
 
     let input_query = PathBuf::from("../../../example-inputs/query.fa");
 
@@ -50,7 +74,12 @@ fn main() {
         None,
         true,
         ColoredQueryOutputFormat::JsonLinesWithNames,
-    );
+        false,
+        None,
+        false,
+        None,
+    )
+    .unwrap();
 
     println!("Output query file: {:?}", output_query.display());
 
@@ -0,0 +1,427 @@
+use crate::utils::HashType;
+use crate::{
+    debug, AssemblerError, BuildOptions, ExtraElaboration, GGCATConfig, GGCATInstance,
+    GraphMetadataMismatch,
+};
+use config::{LogLevel, PacketsPoolsCapacities};
+use io::sequences_stream::general::GeneralSequenceBlockData;
+use querier::ColoredQueryOutputFormat;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+/// A configuration rejected by GGCATInstanceBuilder::build
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuilderError {
+    MissingTempDir,
+    InvalidKmerLength(usize),
+    InvalidMinimizerLength {
+        minimizer_length: usize,
+        kmer_length: usize,
+    },
+    SeqHashKmerTooLong(usize),
+    NoThreads,
+    InvalidMinMultiplicity,
+}
+
+impl Display for BuilderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuilderError::MissingTempDir => write!(f, "A temporary directory is required"),
+            BuilderError::InvalidKmerLength(kmer_length) => {
+                write!(
+                    f,
+                    "Invalid k-mers length {}, it must be at least 2",
+                    kmer_length
+                )
+            }
+            BuilderError::InvalidMinimizerLength {
+                minimizer_length,
+                kmer_length,
+            } => write!(
+                f,
+                "Invalid minimizer length {}, it must be between 1 and the k-mers length {}",
+                minimizer_length, kmer_length
+            ),
+            BuilderError::SeqHashKmerTooLong(kmer_length) => write!(
+                f,
+                "The sequence hash supports k-mers up to 64 bases, not {}",
+                kmer_length
+            ),
+            BuilderError::NoThreads => write!(f, "At least one thread is required"),
+            BuilderError::InvalidMinMultiplicity => {
+                write!(f, "The minimum multiplicity must be at least 1")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+/// Collects the configuration of the instance and of the graphs to build and query, checking it
+/// before creating the instance. The instance is global, so the instance settings (temporary
+/// directory, memory, threads pool) are applied only by the first created instance
+pub struct GGCATInstanceBuilder {
+    config: GGCATConfig,
+    kmer_length: usize,
+    minimizer_length: Option<usize>,
+    forward_only: bool,
+    colors: bool,
+    min_multiplicity: usize,
+    output_format: ExtraElaboration,
+    colored_query_output_format: ColoredQueryOutputFormat,
+    color_quorum: Option<usize>,
+    hash_type: HashType,
+    keep_temp_files: bool,
+}
+
+impl GGCATInstanceBuilder {
+    pub fn new(kmer_length: usize) -> Self {
+        Self {
+            config: GGCATConfig {
+                temp_dir: None,
+                memory: 2.0,
+                prefer_memory: false,
+                total_threads_count: std::thread::available_parallelism()
+                    .map(|cores| cores.get())
+                    .unwrap_or(1),
+                allow_oversubscribe: false,
+                intermediate_compression_level: None,
+                output_compression_level: None,
+                bgzf_output: false,
+                output_flush_sequences: None,
+                output_flush_bytes: None,
+                stats_file: None,
+                minimizer_hash_seed: 0,
+                temp_files_prefix: None,
+                extra_temp_dirs: vec![],
+                split_on_softmask: false,
+                iupac_reads_packing: false,
+                interleaved_paired_reads: false,
+                use_second_bucket: false,
+                reads_buffer_capacity: None,
+                links_compaction_links_buffer: None,
+                links_compaction_results_buffer: None,
+                packets_pools_capacities: PacketsPoolsCapacities::default(),
+                sync_checkpoints: false,
+                checkpoint_checksums: false,
+                log_level: LogLevel::Default,
+            },
+            kmer_length,
+            minimizer_length: None,
+            forward_only: false,
+            colors: false,
+            min_multiplicity: 1,
+            output_format: ExtraElaboration::None,
+            colored_query_output_format: ColoredQueryOutputFormat::JsonLinesWithNumbers,
+            color_quorum: None,
+            hash_type: HashType::Auto,
+            keep_temp_files: false,
+        }
+    }
+
+    /// Overrides the default m-mers (minimizers) length
+    pub fn minimizer_length(mut self, minimizer_length: usize) -> Self {
+        self.minimizer_length = Some(minimizer_length);
+        self
+    }
+
+    pub fn threads(mut self, threads_count: usize) -> Self {
+        self.config.total_threads_count = threads_count;
+        self
+    }
+
+    pub fn temp_dir(mut self, temp_dir: impl Into<PathBuf>) -> Self {
+        self.config.temp_dir = Some(temp_dir.into());
+        self
+    }
+
+    /// Maximum suggested memory usage (GB) for the temporary files
+    pub fn memory(mut self, memory: f64) -> Self {
+        self.config.memory = memory;
+        self
+    }
+
+    /// Use all the given memory before writing the temporary files to disk
+    pub fn prefer_memory(mut self, prefer_memory: bool) -> Self {
+        self.config.prefer_memory = prefer_memory;
+        self
+    }
+
+    pub fn log_level(mut self, log_level: LogLevel) -> Self {
+        self.config.log_level = log_level;
+        self
+    }
+
+    /// Hash type used to identify the kmers
+    pub fn hash_type(mut self, hash_type: HashType) -> Self {
+        self.hash_type = hash_type;
+        self
+    }
+
+    /// Treats reverse complementary kmers as different
+    pub fn forward_only(mut self, forward_only: bool) -> Self {
+        self.forward_only = forward_only;
+        self
+    }
+
+    /// Builds colored graphs, and reports the colors of the matches when querying
+    pub fn colors(mut self, colors: bool) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn colored_query_output_format(mut self, output_format: ColoredQueryOutputFormat) -> Self {
+        self.colored_query_output_format = output_format;
+        self
+    }
+
+    /// Reports only the colors found in at least this number of matched kmers of each query
+    pub fn color_quorum(mut self, color_quorum: usize) -> Self {
+        self.color_quorum = Some(color_quorum);
+        self
+    }
+
+    /// Minimum multiplicity required to keep a kmer
+    pub fn min_multiplicity(mut self, min_multiplicity: usize) -> Self {
+        self.min_multiplicity = min_multiplicity;
+        self
+    }
+
+    /// Links or tigs generated in the output graphs
+    pub fn output_format(mut self, output_format: ExtraElaboration) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Keep the intermediate temporary files instead of removing them
+    pub fn keep_temp_files(mut self, keep_temp_files: bool) -> Self {
+        self.keep_temp_files = keep_temp_files;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), BuilderError> {
+        if self.config.temp_dir.is_none() {
+            return Err(BuilderError::MissingTempDir);
+        }
+        if self.kmer_length < 2 {
+            return Err(BuilderError::InvalidKmerLength(self.kmer_length));
+        }
+        if let Some(minimizer_length) = self.minimizer_length {
+            if minimizer_length == 0 || minimizer_length >= self.kmer_length {
+                return Err(BuilderError::InvalidMinimizerLength {
+                    minimizer_length,
+                    kmer_length: self.kmer_length,
+                });
+            }
+        }
+        if matches!(self.hash_type, HashType::SeqHash) && self.kmer_length > 64 {
+            return Err(BuilderError::SeqHashKmerTooLong(self.kmer_length));
+        }
+        if self.config.total_threads_count == 0 {
+            return Err(BuilderError::NoThreads);
+        }
+        if self.min_multiplicity == 0 {
+            return Err(BuilderError::InvalidMinMultiplicity);
+        }
+        Ok(())
+    }
+
+    /// Validates the configuration and creates the instance
+    pub fn build(self) -> Result<GGCATPipeline, BuilderError> {
+        self.validate()?;
+
+        let threads_count = self.config.total_threads_count;
+        let instance = GGCATInstance::create(self.config);
+
+        Ok(GGCATPipeline {
+            instance,
+            kmer_length: self.kmer_length,
+            minimizer_length: self.minimizer_length,
+            threads_count,
+            forward_only: self.forward_only,
+            colors: self.colors,
+            min_multiplicity: self.min_multiplicity,
+            output_format: self.output_format,
+            colored_query_output_format: self.colored_query_output_format,
+            color_quorum: self.color_quorum,
+            hash_type: self.hash_type,
+            keep_temp_files: self.keep_temp_files,
+        })
+    }
+}
+
+/// An instance with the graphs parameters collected by GGCATInstanceBuilder,
+/// building and querying graphs with the same k, minimizers and hash
+pub struct GGCATPipeline {
+    instance: &'static GGCATInstance,
+    kmer_length: usize,
+    minimizer_length: Option<usize>,
+    threads_count: usize,
+    forward_only: bool,
+    colors: bool,
+    min_multiplicity: usize,
+    output_format: ExtraElaboration,
+    colored_query_output_format: ColoredQueryOutputFormat,
+    color_quorum: Option<usize>,
+    hash_type: HashType,
+    keep_temp_files: bool,
+}
+
+impl GGCATPipeline {
+    pub fn instance(&self) -> &'static GGCATInstance {
+        self.instance
+    }
+
+    pub fn kmer_length(&self) -> usize {
+        self.kmer_length
+    }
+
+    // The hash type and the temporary files policy are global, set them before each run
+    fn apply_global_settings(&self) {
+        *debug::DEBUG_HASH_TYPE.lock() = self.hash_type;
+        debug::DEBUG_KEEP_FILES.store(self.keep_temp_files, Ordering::Relaxed);
+    }
+
+    /// Builds a graph from the input streams, with the colors named by color_names
+    /// (ordered by color index), returning the path of the output graph
+    pub fn build(
+        &self,
+        inputs: Vec<GeneralSequenceBlockData>,
+        color_names: Option<&[String]>,
+        output_file: PathBuf,
+    ) -> Result<PathBuf, AssemblerError> {
+        self.apply_global_settings();
+        let output = self.instance.build_graph(
+            inputs,
+            output_file,
+            color_names,
+            BuildOptions {
+                kmer_length: self.kmer_length,
+                threads_count: self.threads_count,
+                forward_only: self.forward_only,
+                minimizer_length: self.minimizer_length,
+                colors: self.colors,
+                min_multiplicity: self.min_multiplicity,
+                extra_elab: self.output_format,
+                ..Default::default()
+            },
+        )?;
+
+        // Built in a single shard
+        Ok(output.single_file().unwrap())
+    }
+
+    /// Queries a graph built with the same parameters with the sequences of a FASTA/FASTQ file,
+    /// returning the path of the results file
+    pub fn query(
+        &self,
+        graph: PathBuf,
+        queries: PathBuf,
+        output_file_prefix: PathBuf,
+    ) -> Result<PathBuf, GraphMetadataMismatch> {
+        self.apply_global_settings();
+        self.instance.query_graph(
+            graph,
+            queries,
+            output_file_prefix,
+            self.kmer_length,
+            self.threads_count,
+            self.forward_only,
+            self.minimizer_length,
+            self.colors,
+            self.colored_query_output_format,
+            false,
+            None,
+            false,
+            self.color_quorum,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuilderError, GGCATInstanceBuilder};
+    use crate::selftest::SyntheticDataset;
+    use crate::tests;
+    use crate::utils::HashType;
+    use io::sequences_stream::general::GeneralSequenceBlockData;
+    use std::sync::Arc;
+
+    #[test]
+    fn invalid_configurations() {
+        assert_eq!(
+            GGCATInstanceBuilder::new(31).validate(),
+            Err(BuilderError::MissingTempDir)
+        );
+
+        let builder = GGCATInstanceBuilder::new(31).temp_dir("/tmp");
+        assert_eq!(builder.validate(), Ok(()));
+        assert_eq!(
+            builder.minimizer_length(31).validate(),
+            Err(BuilderError::InvalidMinimizerLength {
+                minimizer_length: 31,
+                kmer_length: 31
+            })
+        );
+        assert_eq!(
+            GGCATInstanceBuilder::new(91)
+                .temp_dir("/tmp")
+                .hash_type(HashType::SeqHash)
+                .validate(),
+            Err(BuilderError::SeqHashKmerTooLong(91))
+        );
+        assert_eq!(
+            GGCATInstanceBuilder::new(31)
+                .temp_dir("/tmp")
+                .threads(0)
+                .validate(),
+            Err(BuilderError::NoThreads)
+        );
+    }
+
+    #[test]
+    fn build_and_query_through_the_builder() {
+        // The builder returns the shared instance, configure it the same way
+        let temp_dir = tests::test_instance().0.temp_dir.clone().unwrap();
+        let pipeline = GGCATInstanceBuilder::new(tests::K)
+            .temp_dir(&temp_dir)
+            .memory(0.5)
+            .prefer_memory(true)
+            .threads(tests::THREADS)
+            .build()
+            .unwrap();
+        assert!(std::ptr::eq(pipeline.instance(), tests::test_instance()));
+
+        let test_dir = tests::TestDir::new("builder");
+        let dataset = Arc::new(SyntheticDataset::generate(1, 5000, 250, tests::K, 0x0123));
+        let genome = dataset.genomes[0].clone();
+
+        let _options_guard = tests::GLOBAL_OPTIONS_LOCK.read();
+        let graph_file = pipeline
+            .build(
+                vec![GeneralSequenceBlockData::Dynamic((dataset, 0))],
+                None,
+                test_dir.join("graph.fa"),
+            )
+            .unwrap();
+
+        let query_file =
+            test_dir.write_fasta("query.fa", &[&genome[2000..2200], &b"C".repeat(200)]);
+
+        let output_file = pipeline
+            .query(graph_file, query_file, test_dir.join("query-results"))
+            .unwrap();
+        let output = std::fs::read_to_string(&output_file).unwrap();
+
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "query_index,matched_kmers,query_kmers,match_percentage",
+                "0,170,170,1.00",
+                "1,0,170,0.00",
+            ]
+        );
+    }
+}
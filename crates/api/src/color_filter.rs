@@ -0,0 +1,218 @@
+use crate::graph_reader::{GraphReadError, Unitig};
+use config::ColorIndexType;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+
+/// Returns the indexes of the unitigs that contain the given color in at least one of their kmers
+pub(crate) fn unitigs_with_color(
+    unitigs: impl Iterator<Item = Result<Unitig, GraphReadError>>,
+    color: ColorIndexType,
+) -> Result<HashSet<u64>, GraphReadError> {
+    let mut kept_unitigs = HashSet::new();
+    for unitig in unitigs {
+        let unitig = unitig?;
+        if unitig.colors.binary_search(&color).is_ok() {
+            kept_unitigs.insert(unitig.index);
+        }
+    }
+    Ok(kept_unitigs)
+}
+
+/// Copies the unitigs of a graph whose index is in kept_unitigs, keeping their headers except for the
+/// links, that are kept only if they reach another kept unitig and keep_links is set
+pub(crate) fn write_filtered_unitigs(
+    mut graph: impl BufRead,
+    kept_unitigs: &HashSet<u64>,
+    keep_links: bool,
+    mut output: impl Write,
+) -> std::io::Result<u64> {
+    let mut line = vec![];
+    let mut keep_current = false;
+    let mut written_unitigs = 0;
+
+    loop {
+        line.clear();
+        if graph.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        if line.first() != Some(&b'>') {
+            if keep_current {
+                output.write_all(&line)?;
+            }
+            continue;
+        }
+
+        let header = String::from_utf8_lossy(&line);
+        let mut fields = header.trim_end().split(' ');
+        let index_field = fields.next().unwrap_or_default();
+
+        keep_current = index_field[1..]
+            .parse()
+            .map(|index| kept_unitigs.contains(&index))
+            .unwrap_or(false);
+        if !keep_current {
+            continue;
+        }
+        written_unitigs += 1;
+
+        output.write_all(index_field.as_bytes())?;
+        for field in fields {
+            if let Some(link) = field.strip_prefix("L:") {
+                let target = link.split(':').nth(1).and_then(|t| t.parse().ok());
+                if !keep_links || !target.map(|t| kept_unitigs.contains(&t)).unwrap_or(false) {
+                    continue;
+                }
+            }
+            write!(output, " {}", field)?;
+        }
+        output.write_all(b"\n")?;
+    }
+
+    Ok(written_unitigs)
+}
+
+/// Colors of each unitig, by unitig index
+pub(crate) fn unitigs_colors(
+    unitigs: impl Iterator<Item = Result<Unitig, GraphReadError>>,
+) -> Result<HashMap<u64, Vec<ColorIndexType>>, GraphReadError> {
+    unitigs
+        .map(|unitig| unitig.map(|unitig| (unitig.index, unitig.colors)))
+        .collect()
+}
+
+// Both the colors lists are sorted
+fn share_a_color(a: &[ColorIndexType], b: &[ColorIndexType]) -> bool {
+    let (mut a, mut b) = (a.iter().peekable(), b.iter().peekable());
+    while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+        match x.cmp(y) {
+            std::cmp::Ordering::Less => {
+                a.next();
+            }
+            std::cmp::Ordering::Greater => {
+                b.next();
+            }
+            std::cmp::Ordering::Equal => return true,
+        }
+    }
+    false
+}
+
+/// Copies a graph dropping the links between unitigs that do not share any color, so that
+/// each color forms a separate subgraph. Returns the number of dropped links
+pub(crate) fn write_intra_color_links(
+    mut graph: impl BufRead,
+    unitigs_colors: &HashMap<u64, Vec<ColorIndexType>>,
+    mut output: impl Write,
+) -> std::io::Result<u64> {
+    let mut line = vec![];
+    let mut dropped_links = 0;
+
+    loop {
+        line.clear();
+        if graph.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        if line.first() != Some(&b'>') {
+            output.write_all(&line)?;
+            continue;
+        }
+
+        let header = String::from_utf8_lossy(&line);
+        let mut fields = header.trim_end().split(' ');
+        let index_field = fields.next().unwrap_or_default();
+        let colors = index_field[1..]
+            .parse()
+            .ok()
+            .and_then(|index: u64| unitigs_colors.get(&index));
+
+        output.write_all(index_field.as_bytes())?;
+        for field in fields {
+            if let Some(link) = field.strip_prefix("L:") {
+                let target_colors = link
+                    .split(':')
+                    .nth(1)
+                    .and_then(|t| t.parse().ok())
+                    .and_then(|t: u64| unitigs_colors.get(&t));
+                let same_color = match (colors, target_colors) {
+                    (Some(colors), Some(target_colors)) => share_a_color(colors, target_colors),
+                    _ => false,
+                };
+                if !same_color {
+                    dropped_links += 1;
+                    continue;
+                }
+            }
+            write!(output, " {}", field)?;
+        }
+        output.write_all(b"\n")?;
+    }
+
+    output.flush()?;
+    Ok(dropped_links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        unitigs_colors, unitigs_with_color, write_filtered_unitigs, write_intra_color_links,
+    };
+    use crate::graph_reader::{ColorsSubsetResolver, GraphReader};
+    use std::io::Cursor;
+
+    // Colors subset 0 is {0}, subset 1 is {1} and subset 2 is {0, 1}
+    const GRAPH: &str = ">0 LN:i:6 C:0:2 L:+:1:+ L:-:2:+\nACGTAC\n\
+        >1 LN:i:5 C:1:1 L:-:0:-\nTACGG\n\
+        >2 LN:i:6 C:2:1 C:1:1 L:-:0:+\nCCGTAC\n";
+
+    fn subsets_resolver() -> ColorsSubsetResolver {
+        Box::new(|subset, colors| match subset {
+            0 => colors.push(0),
+            1 => colors.push(1),
+            _ => colors.extend_from_slice(&[0, 1]),
+        })
+    }
+
+    fn filter(color: u32, keep_links: bool) -> String {
+        let kept = unitigs_with_color(
+            GraphReader::new(Cursor::new(GRAPH), Some(subsets_resolver())),
+            color,
+        )
+        .unwrap();
+        let mut output = vec![];
+        write_filtered_unitigs(Cursor::new(GRAPH), &kept, keep_links, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn only_unitigs_with_the_color_are_written() {
+        assert_eq!(
+            filter(0, true),
+            ">0 LN:i:6 C:0:2 L:-:2:+\nACGTAC\n>2 LN:i:6 C:2:1 C:1:1 L:-:0:+\nCCGTAC\n"
+        );
+        assert_eq!(
+            filter(1, false),
+            ">1 LN:i:5 C:1:1\nTACGG\n>2 LN:i:6 C:2:1 C:1:1\nCCGTAC\n"
+        );
+    }
+
+    #[test]
+    fn links_between_different_colors_are_dropped() {
+        let colors = unitigs_colors(GraphReader::new(
+            Cursor::new(GRAPH),
+            Some(subsets_resolver()),
+        ))
+        .unwrap();
+        let mut output = vec![];
+        let dropped = write_intra_color_links(Cursor::new(GRAPH), &colors, &mut output).unwrap();
+
+        // The unitigs 0 and 2 share the color 0, while 1 has only the color 1 that 0 lacks
+        assert_eq!(dropped, 2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            ">0 LN:i:6 C:0:2 L:-:2:+\nACGTAC\n>1 LN:i:5 C:1:1\nTACGG\n\
+            >2 LN:i:6 C:2:1 C:1:1 L:-:0:+\nCCGTAC\n"
+        );
+    }
+}
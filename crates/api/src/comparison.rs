@@ -0,0 +1,287 @@
+use io::concurrent::structured_sequences::canonical_sequence;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use xxhash_rust::xxh3::{xxh3_128, xxh3_64};
+
+/// Memory used by each kmer stored in a hash table with entries of entry_size bytes. Each entry
+/// has a control byte, the table is at most 7/8 full and its capacity is doubled when it grows,
+/// so up to half of it can be unused
+pub(crate) const fn kmers_table_bytes_per_kmer(entry_size: usize) -> u64 {
+    (entry_size as u64 + 1) * 8 / 7 * 2
+}
+
+/// Key identifying a canonical kmer in the partitions hash tables. The kmers up to 64 bases are
+/// packed with 2 bits per base, the longer ones are replaced by their 128 bit hash
+fn kmer_key(kmer: &[u8]) -> u128 {
+    if kmer.len() > 64 {
+        return xxh3_128(kmer);
+    }
+
+    kmer.iter().fold(0, |key, base| {
+        (key << 2)
+            | match base {
+                b'A' => 0,
+                b'C' => 1,
+                b'G' => 2,
+                _ => 3,
+            }
+    })
+}
+
+/// Sizes of the canonical kmers sets of two graphs and of their intersection
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KmersComparisonReport {
+    pub kmers_a: u64,
+    pub kmers_b: u64,
+    pub intersection: u64,
+}
+
+impl KmersComparisonReport {
+    pub fn union(&self) -> u64 {
+        self.kmers_a + self.kmers_b - self.intersection
+    }
+
+    pub fn unique_a(&self) -> u64 {
+        self.kmers_a - self.intersection
+    }
+
+    pub fn unique_b(&self) -> u64 {
+        self.kmers_b - self.intersection
+    }
+
+    /// Jaccard similarity of the two kmers sets, 1.0 if both are empty
+    pub fn jaccard(&self) -> f64 {
+        let union = self.union();
+        if union == 0 {
+            1.0
+        } else {
+            self.intersection as f64 / union as f64
+        }
+    }
+}
+
+impl Display for KmersComparisonReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{\"kmers_a\":{}, \"kmers_b\":{}, \"intersection\":{}, \"union\":{}, \"unique_a\":{}, \"unique_b\":{}, \"jaccard\":{} }}",
+            self.kmers_a,
+            self.kmers_b,
+            self.intersection,
+            self.union(),
+            self.unique_a(),
+            self.unique_b(),
+            self.jaccard()
+        )
+    }
+}
+
+/// Containment of the canonical kmers set of each graph in the other one. Unlike the Jaccard
+/// similarity it is asymmetric, a graph whose kmers are a subset of the other one has containment 1.0
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KmersContainmentReport {
+    pub comparison: KmersComparisonReport,
+}
+
+impl KmersContainmentReport {
+    fn containment(intersection: u64, kmers: u64) -> f64 {
+        if kmers == 0 {
+            1.0
+        } else {
+            intersection as f64 / kmers as f64
+        }
+    }
+
+    /// Fraction of the kmers of A that are also in B, 1.0 if A is empty
+    pub fn a_in_b(&self) -> f64 {
+        Self::containment(self.comparison.intersection, self.comparison.kmers_a)
+    }
+
+    /// Fraction of the kmers of B that are also in A, 1.0 if B is empty
+    pub fn b_in_a(&self) -> f64 {
+        Self::containment(self.comparison.intersection, self.comparison.kmers_b)
+    }
+}
+
+impl Display for KmersContainmentReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{\"kmers_a\":{}, \"kmers_b\":{}, \"intersection\":{}, \"containment_a_in_b\":{}, \"containment_b_in_a\":{} }}",
+            self.comparison.kmers_a,
+            self.comparison.kmers_b,
+            self.comparison.intersection,
+            self.a_in_b(),
+            self.b_in_a()
+        )
+    }
+}
+
+/// Iterates the keys of the canonical kmers of the sequences assigned to the given partition,
+/// skipping the kmers containing non-ACGT bases
+pub(crate) fn for_each_partition_kmer(
+    read_graph: &impl Fn(&mut dyn FnMut(&[u8])),
+    k: usize,
+    partition: u64,
+    partitions_count: u64,
+    mut callback: impl FnMut(u128),
+) {
+    for_each_partition_oriented_kmer(read_graph, k, partition, partitions_count, |kmer, _| {
+        callback(kmer)
+    });
+}
+
+/// As for_each_partition_kmer, also telling if each kmer is the reverse complement
+/// of the one found in the sequence
+pub(crate) fn for_each_partition_oriented_kmer(
+    read_graph: &impl Fn(&mut dyn FnMut(&[u8])),
+    k: usize,
+    partition: u64,
+    partitions_count: u64,
+    mut callback: impl FnMut(u128, bool),
+) {
+    let mut rc_buffer = Vec::with_capacity(k);
+
+    read_graph(&mut |sequence: &[u8]| {
+        if sequence.len() < k {
+            return;
+        }
+
+        for kmer in sequence.windows(k) {
+            if kmer.iter().any(|b| !matches!(b, b'A' | b'C' | b'G' | b'T')) {
+                continue;
+            }
+
+            let (kmer, reversed) = canonical_sequence(kmer, &mut rc_buffer);
+
+            // The hash is not seeded, so both graphs use the same partitioning
+            if xxh3_64(kmer) % partitions_count == partition {
+                callback(kmer_key(kmer), reversed);
+            }
+        }
+    });
+}
+
+/// Memory used by each kmer of the sets of the graphs comparison
+pub(crate) const COMPARISON_BYTES_PER_KMER: u64 =
+    kmers_table_bytes_per_kmer(std::mem::size_of::<u128>());
+
+/// Computes the canonical kmers sets similarity of two graphs, each one given as a function
+/// that streams its unitigs. The kmers are split by hash in partitions_count partitions,
+/// and the graphs are read once per partition to keep only one partition at a time in memory.
+/// The two graphs are read in parallel
+pub fn compare_graphs_kmers(
+    read_graph_a: impl Fn(&mut dyn FnMut(&[u8])) + Sync,
+    read_graph_b: impl Fn(&mut dyn FnMut(&[u8])) + Sync,
+    k: usize,
+    partitions_count: usize,
+) -> KmersComparisonReport {
+    let partitions_count = partitions_count.max(1) as u64;
+    let mut report = KmersComparisonReport::default();
+
+    let mut kmers_a = HashSet::new();
+    let mut kmers_b = HashSet::new();
+
+    for partition in 0..partitions_count {
+        rayon::join(
+            || {
+                for_each_partition_kmer(&read_graph_a, k, partition, partitions_count, |kmer| {
+                    kmers_a.insert(kmer);
+                })
+            },
+            || {
+                for_each_partition_kmer(&read_graph_b, k, partition, partitions_count, |kmer| {
+                    kmers_b.insert(kmer);
+                })
+            },
+        );
+
+        report.kmers_a += kmers_a.len() as u64;
+        report.kmers_b += kmers_b.len() as u64;
+        let (smaller, larger) = if kmers_a.len() <= kmers_b.len() {
+            (&kmers_a, &kmers_b)
+        } else {
+            (&kmers_b, &kmers_a)
+        };
+        report.intersection += smaller.intersection(larger).count() as u64;
+
+        kmers_a.clear();
+        kmers_b.clear();
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_graphs_kmers, KmersContainmentReport};
+
+    // With k = 3 the canonical kmers are:
+    // A: AAA AAC ACG (AAACG), CCC CCA (CCCA)
+    // B: ACG AAC AAA TAA (CGTTTA), skipped kmers with N (ANA)
+    const GRAPH_A: &[&str] = &["AAACG", "CCCA"];
+    const GRAPH_B: &[&str] = &["CGTTTA", "ANA"];
+
+    fn streamer(graph: &'static [&'static str]) -> impl Fn(&mut dyn FnMut(&[u8])) {
+        move |callback: &mut dyn FnMut(&[u8])| {
+            for sequence in graph {
+                callback(sequence.as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_graphs_jaccard() {
+        for partitions_count in [1, 4] {
+            let report =
+                compare_graphs_kmers(streamer(GRAPH_A), streamer(GRAPH_B), 3, partitions_count);
+
+            assert_eq!(report.kmers_a, 5);
+            assert_eq!(report.kmers_b, 4);
+            assert_eq!(report.intersection, 3);
+            assert_eq!(report.union(), 6);
+            assert_eq!(report.unique_a(), 2);
+            assert_eq!(report.unique_b(), 1);
+            assert_eq!(report.jaccard(), 0.5);
+        }
+    }
+
+    #[test]
+    fn identical_graphs() {
+        let report = compare_graphs_kmers(streamer(GRAPH_A), streamer(GRAPH_A), 3, 2);
+        assert_eq!(report.jaccard(), 1.0);
+        assert_eq!(report.unique_a() + report.unique_b(), 0);
+    }
+
+    #[test]
+    fn long_kmers_comparison() {
+        // Longer than 64 bases, so the kmers are stored by hash
+        const LONG_GRAPH: &[&str] =
+            &["ACGTTGCAAGCTAGCTTACGGATCCATGCATTGACCAGTAGGCTAACGTCGATCGGTACCATGATCGACTTGCAAGCTCA"];
+        const LONG_GRAPH_RC: &[&str] =
+            &["TGAGCTTGCAAGTCGATCATGGTACCGATCGACGTTAGCCTACTGGTCAATGCATGGATCCGTAAGCTAGCTTGCAACGT"];
+        const LONG_GRAPH_PREFIX: &[&str] =
+            &["ACGTTGCAAGCTAGCTTACGGATCCATGCATTGACCAGTAGGCTAACGTCGATCGGTACCATGATCGACT"];
+
+        let report = compare_graphs_kmers(streamer(LONG_GRAPH), streamer(LONG_GRAPH_RC), 65, 3);
+        assert_eq!(report.kmers_a, 16);
+        assert_eq!(report.jaccard(), 1.0);
+
+        let report = compare_graphs_kmers(streamer(LONG_GRAPH_PREFIX), streamer(LONG_GRAPH), 65, 3);
+        assert_eq!(report.kmers_a, 6);
+        assert_eq!(report.intersection, 6);
+        assert_eq!(report.unique_b(), 10);
+    }
+
+    #[test]
+    fn subset_graph_containment() {
+        // AAA and AAC, both in graph A
+        const SUBSET_GRAPH: &[&str] = &["AAAC"];
+
+        let report = KmersContainmentReport {
+            comparison: compare_graphs_kmers(streamer(SUBSET_GRAPH), streamer(GRAPH_A), 3, 2),
+        };
+        assert_eq!(report.a_in_b(), 1.0);
+        assert_eq!(report.b_in_a(), 0.4);
+    }
+}
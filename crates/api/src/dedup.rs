@@ -0,0 +1,353 @@
+use crate::graph_reader::{ColorsRun, GraphLink, GraphReadError, GraphReader, UnitigsSource};
+use config::ColorIndexType;
+use io::concurrent::structured_sequences::canonical_sequence;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Unitig dropped because it has the same sequence of a previous one, the survivor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct DroppedUnitig {
+    pub survivor: u64,
+    /// The dropped unitig is the reverse complement of the survivor
+    pub reversed: bool,
+}
+
+/// Unitigs with the same sequence (in any orientation) of a previous unitig, and the merged
+/// colors and links of each survivor
+#[derive(Default)]
+pub(crate) struct DuplicateUnitigs {
+    pub dropped: HashMap<u64, DroppedUnitig>,
+    // Colors runs (in the survivor orientation) and links of the survivors with their duplicates
+    merged: HashMap<u64, (Vec<ColorsRun>, Vec<GraphLink>)>,
+    // Colors subsets ids of the merged colors runs, if they differ from the survivor ones
+    merged_subsets: HashMap<u64, Vec<(u64, u64)>>,
+}
+
+/// Union of the colors of each kmer of two unitigs with the same sequence,
+/// with the runs of the second one already in the orientation of the first one
+fn merge_colors_runs(first: &[ColorsRun], second: &[ColorsRun]) -> Vec<ColorsRun> {
+    let mut merged: Vec<ColorsRun> = vec![];
+    let mut second_runs = second.iter();
+    let mut second_run = second_runs.next().cloned();
+
+    for run in first {
+        let mut remaining = run.kmers_count;
+        while remaining > 0 {
+            let (kmers_count, colors) = match second_run.as_mut() {
+                Some(other) => {
+                    let kmers_count = remaining.min(other.kmers_count);
+                    let mut colors = run.colors.clone();
+                    colors.extend_from_slice(&other.colors);
+                    colors.sort_unstable();
+                    colors.dedup();
+
+                    other.kmers_count -= kmers_count;
+                    if other.kmers_count == 0 {
+                        second_run = second_runs.next().cloned();
+                    }
+                    (kmers_count, colors)
+                }
+                None => (remaining, run.colors.clone()),
+            };
+            remaining -= kmers_count;
+
+            match merged.last_mut() {
+                Some(last) if last.colors == colors => last.kmers_count += kmers_count,
+                _ => merged.push(ColorsRun {
+                    kmers_count,
+                    colors,
+                }),
+            }
+        }
+    }
+
+    merged
+}
+
+impl DuplicateUnitigs {
+    /// Finds the unitigs that repeat the sequence of a previous one. The first pass keeps only
+    /// a hash of the canonical sequence of each unitig, then only the unitigs sharing a hash with
+    /// another one are read again and compared, to exclude the hash collisions
+    pub fn find<S: UnitigsSource>(
+        mut open_graph: impl FnMut() -> GraphReader<S>,
+    ) -> Result<Self, GraphReadError> {
+        let mut rc_buffer = vec![];
+        let mut hashes = HashSet::new();
+        let mut colliding_hashes = HashSet::new();
+        for unitig in open_graph() {
+            let unitig = unitig?;
+            let hash = xxh3_64(canonical_sequence(&unitig.sequence, &mut rc_buffer).0);
+            if !hashes.insert(hash) {
+                colliding_hashes.insert(hash);
+            }
+        }
+        drop(hashes);
+
+        let mut duplicates = Self::default();
+        if colliding_hashes.is_empty() {
+            return Ok(duplicates);
+        }
+
+        // Survivor index and orientation of each canonical sequence
+        let mut survivors = HashMap::new();
+        let mut graph = open_graph();
+        let mut colors_runs = vec![];
+        while let Some(unitig) = graph.next_with_colors_runs(&mut colors_runs)? {
+            let (canonical, reversed) = canonical_sequence(&unitig.sequence, &mut rc_buffer);
+            if !colliding_hashes.contains(&xxh3_64(canonical)) {
+                continue;
+            }
+
+            let (survivor, survivor_reversed) = *survivors
+                .entry(canonical.to_vec())
+                .or_insert((unitig.index, reversed));
+            if survivor == unitig.index {
+                duplicates
+                    .merged
+                    .insert(survivor, (colors_runs.clone(), unitig.links));
+                continue;
+            }
+
+            let reversed = reversed != survivor_reversed;
+            duplicates
+                .dropped
+                .insert(unitig.index, DroppedUnitig { survivor, reversed });
+
+            if reversed {
+                colors_runs.reverse();
+            }
+            let (survivor_runs, survivor_links) = duplicates.merged.get_mut(&survivor).unwrap();
+            *survivor_runs = merge_colors_runs(survivor_runs, &colors_runs);
+            // The beginning of a reversed duplicate is the end of the survivor
+            survivor_links.extend(unitig.links.into_iter().map(|link| GraphLink {
+                from_reverse: link.from_reverse != reversed,
+                ..link
+            }));
+        }
+
+        // Only the survivors with duplicates are rewritten
+        let with_duplicates: HashSet<_> = duplicates
+            .dropped
+            .values()
+            .map(|duplicate| duplicate.survivor)
+            .collect();
+        duplicates
+            .merged
+            .retain(|survivor, _| with_duplicates.contains(survivor));
+        Ok(duplicates)
+    }
+
+    /// Assigns a colors subset to the merged colors runs of each survivor whose colors changed,
+    /// with get_subset_id returning the (possibly new) subset of a colors set
+    pub fn assign_colors_subsets<S: UnitigsSource>(
+        &mut self,
+        mut graph: GraphReader<S>,
+        mut get_subset_id: impl FnMut(&[ColorIndexType]) -> u64,
+    ) -> Result<(), GraphReadError> {
+        let mut colors_runs = vec![];
+        while let Some(unitig) = graph.next_with_colors_runs(&mut colors_runs)? {
+            if let Some((merged_runs, _)) = self.merged.get(&unitig.index) {
+                if merged_runs != &colors_runs {
+                    let subsets = merged_runs
+                        .iter()
+                        .map(|run| (get_subset_id(&run.colors), run.kmers_count))
+                        .collect();
+                    self.merged_subsets.insert(unitig.index, subsets);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remap_link(&self, mut link: GraphLink) -> GraphLink {
+        if let Some(dropped) = self.dropped.get(&link.target) {
+            link.target = dropped.survivor;
+            link.to_reverse ^= dropped.reversed;
+        }
+        link
+    }
+}
+
+fn write_link(output: &mut impl Write, link: &GraphLink) -> std::io::Result<()> {
+    let sign = |reverse: bool| if reverse { '-' } else { '+' };
+    write!(
+        output,
+        " L:{}:{}:{}",
+        sign(link.from_reverse),
+        link.target,
+        sign(link.to_reverse)
+    )
+}
+
+/// Copies the graph without the dropped duplicates, merging their colors and links into
+/// the survivors, and moving the links to the dropped unitigs onto their survivors.
+/// The other fields of the headers are kept. Returns the number of dropped unitigs
+pub(crate) fn write_deduplicated_unitigs(
+    mut graph: impl BufRead,
+    duplicates: &DuplicateUnitigs,
+    mut output: impl Write,
+) -> std::io::Result<u64> {
+    let mut line = vec![];
+    let mut keep_current = true;
+    let mut dropped_unitigs = 0;
+
+    loop {
+        line.clear();
+        if graph.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        if line.first() != Some(&b'>') {
+            if keep_current {
+                output.write_all(&line)?;
+            }
+            continue;
+        }
+
+        let header = String::from_utf8_lossy(&line);
+        let mut fields = header.trim_end().split(' ');
+        let index_field = fields.next().unwrap_or_default();
+        let index = index_field[1..].parse::<u64>().ok();
+
+        keep_current = !index
+            .map(|index| duplicates.dropped.contains_key(&index))
+            .unwrap_or(false);
+        if !keep_current {
+            dropped_unitigs += 1;
+            continue;
+        }
+
+        let merged = index.and_then(|index| duplicates.merged.get(&index));
+        let merged_subsets = index.and_then(|index| duplicates.merged_subsets.get(&index));
+
+        let mut links = vec![];
+        output.write_all(index_field.as_bytes())?;
+        for field in fields {
+            if let Some(link) = field.strip_prefix("L:") {
+                let mut parts = link.split(':');
+                let link = match (
+                    parts.next(),
+                    parts.next().map(|t| t.parse::<u64>()),
+                    parts.next(),
+                ) {
+                    (Some(from), Some(Ok(target)), Some(to)) => GraphLink {
+                        from_reverse: from == "-",
+                        target,
+                        to_reverse: to == "-",
+                    },
+                    _ => {
+                        write!(output, " {}", field)?;
+                        continue;
+                    }
+                };
+                if merged.is_none() {
+                    links.push(link);
+                }
+                continue;
+            }
+            if field.starts_with("C:") && merged_subsets.is_some() {
+                continue;
+            }
+            write!(output, " {}", field)?;
+        }
+
+        for (subset, kmers_count) in merged_subsets.into_iter().flatten() {
+            write!(output, " C:{:x}:{}", subset, kmers_count)?;
+        }
+
+        if let Some((_, merged_links)) = merged {
+            links.extend(merged_links.iter().cloned());
+        }
+        let mut written_links = HashSet::new();
+        for link in links {
+            let link = duplicates.remap_link(link);
+            if written_links.insert((link.from_reverse, link.target, link.to_reverse)) {
+                write_link(&mut output, &link)?;
+            }
+        }
+        output.write_all(b"\n")?;
+    }
+
+    output.flush()?;
+    Ok(dropped_unitigs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_deduplicated_unitigs, DroppedUnitig, DuplicateUnitigs};
+    use crate::graph_reader::{ColorsSubsetResolver, FastaUnitigs, GraphReader};
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    // Colors subset i is {i}, the unitig 2 is the reverse complement of the unitig 0
+    const GRAPH: &str = ">0 LN:i:6 C:0:4 L:+:1:+\nAAACGT\n\
+        >1 LN:i:5 C:1:3 L:-:2:-\nCCGTA\n\
+        >2 LN:i:6 C:2:1 C:1:3 L:+:1:+\nACGTTT\n";
+
+    fn open_graph(graph: &str) -> GraphReader<FastaUnitigs<Cursor<Vec<u8>>>> {
+        let subsets_resolver: ColorsSubsetResolver = Box::new(|subset, colors| {
+            match subset {
+                0..=2 => colors.push(subset),
+                // The subsets added by the deduplication
+                3 => colors.extend_from_slice(&[0, 1]),
+                _ => colors.extend_from_slice(&[0, 2]),
+            }
+        });
+        GraphReader::new(
+            Cursor::new(graph.as_bytes().to_vec()),
+            Some(subsets_resolver),
+        )
+    }
+
+    #[test]
+    fn duplicate_unitig_is_merged_into_the_first() {
+        let mut duplicates = DuplicateUnitigs::find(|| open_graph(GRAPH)).unwrap();
+        assert_eq!(
+            duplicates.dropped,
+            HashMap::from([(
+                2,
+                DroppedUnitig {
+                    survivor: 0,
+                    reversed: true
+                }
+            )])
+        );
+
+        let mut new_subsets = vec![];
+        duplicates
+            .assign_colors_subsets(open_graph(GRAPH), |colors| {
+                new_subsets.push(colors.to_vec());
+                new_subsets.len() as u64 + 2
+            })
+            .unwrap();
+        assert_eq!(new_subsets, [vec![0, 1], vec![0, 2]]);
+
+        let mut output = vec![];
+        let dropped_count =
+            write_deduplicated_unitigs(Cursor::new(GRAPH), &duplicates, &mut output).unwrap();
+        assert_eq!(dropped_count, 1);
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            ">0 LN:i:6 C:3:3 C:4:1 L:+:1:+ L:-:1:+\nAAACGT\n\
+            >1 LN:i:5 C:1:3 L:-:0:+\nCCGTA\n"
+        );
+
+        // The survivor has the colors of both unitigs
+        let unitigs: Vec<_> = open_graph(&output).collect::<Result<_, _>>().unwrap();
+        assert_eq!(unitigs.len(), 2);
+        assert_eq!(unitigs[0].colors, [0, 1, 2]);
+    }
+
+    #[test]
+    fn graph_without_duplicates_is_unchanged() {
+        const UNIQUE_GRAPH: &str = ">0 LN:i:6 C:0:4 L:+:1:+\nAAACGT\n>1 LN:i:5 C:1:3\nCCGTA\n";
+
+        let duplicates = DuplicateUnitigs::find(|| open_graph(UNIQUE_GRAPH)).unwrap();
+        let mut output = vec![];
+        write_deduplicated_unitigs(Cursor::new(UNIQUE_GRAPH), &duplicates, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), UNIQUE_GRAPH);
+    }
+}
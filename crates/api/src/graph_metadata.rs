@@ -0,0 +1,162 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::Path;
+
+/// Parameters a graph was built with, stored in a sidecar next to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphMetadata {
+    pub kmer_length: usize,
+    pub minimizer_length: usize,
+    /// Name of the kmers hash, with the automatic choice already resolved
+    pub hash_type: String,
+    pub forward_only: bool,
+}
+
+/// A query parameter that differs from the one the graph was built with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphMetadataMismatch {
+    KmerLength { graph: usize, query: usize },
+    ForwardOnly { graph: bool, query: bool },
+}
+
+impl Display for GraphMetadataMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphMetadataMismatch::KmerLength { graph, query } => write!(
+                f,
+                "the graph was built with k={} but it is queried with k={}",
+                graph, query
+            ),
+            GraphMetadataMismatch::ForwardOnly { graph, query } => write!(
+                f,
+                "the graph was built {} but it is queried {}",
+                strands_policy(*graph),
+                strands_policy(*query)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GraphMetadataMismatch {}
+
+fn strands_policy(forward_only: bool) -> &'static str {
+    if forward_only {
+        "forward only"
+    } else {
+        "canonical"
+    }
+}
+
+impl GraphMetadata {
+    pub(crate) fn write(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut file = std::fs::File::create(path)?;
+        writeln!(file, "k={}", self.kmer_length)?;
+        writeln!(file, "m={}", self.minimizer_length)?;
+        writeln!(file, "hash={}", self.hash_type)?;
+        writeln!(file, "forward_only={}", self.forward_only)?;
+        Ok(())
+    }
+
+    /// Reads the metadata sidecar, None if it does not exist or cannot be parsed
+    pub(crate) fn read(path: impl AsRef<Path>) -> Option<Self> {
+        let contents = std::fs::read_to_string(path).ok()?;
+
+        let mut kmer_length = None;
+        let mut minimizer_length = None;
+        let mut hash_type = None;
+        let mut forward_only = None;
+
+        for line in contents.lines() {
+            let (key, value) = line.split_once('=')?;
+            match key {
+                "k" => kmer_length = value.parse().ok(),
+                "m" => minimizer_length = value.parse().ok(),
+                "hash" => hash_type = Some(value.to_string()),
+                "forward_only" => forward_only = value.parse().ok(),
+                // Fields added by newer versions
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            kmer_length: kmer_length?,
+            minimizer_length: minimizer_length?,
+            hash_type: hash_type?,
+            forward_only: forward_only?,
+        })
+    }
+
+    /// Checks the query parameters against the ones of the graph. The minimizers and the hash only
+    /// drive the bucketing of the query, where the graph is hashed again together with the queries,
+    /// so they are reported but do not change the results
+    pub(crate) fn check_query(&self, query: &GraphMetadata) -> Result<(), GraphMetadataMismatch> {
+        if self.kmer_length != query.kmer_length {
+            return Err(GraphMetadataMismatch::KmerLength {
+                graph: self.kmer_length,
+                query: query.kmer_length,
+            });
+        }
+        if self.forward_only != query.forward_only {
+            return Err(GraphMetadataMismatch::ForwardOnly {
+                graph: self.forward_only,
+                query: query.forward_only,
+            });
+        }
+
+        if self.minimizer_length != query.minimizer_length {
+            log::info!(
+                "The graph was built with m={}, querying it with m={}",
+                self.minimizer_length,
+                query.minimizer_length
+            );
+        }
+        if self.hash_type != query.hash_type {
+            log::info!(
+                "The graph was built with the {} hash, querying it with the {} hash",
+                self.hash_type,
+                query.hash_type
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GraphMetadata, GraphMetadataMismatch};
+    use crate::tests::TestDir;
+
+    #[test]
+    fn metadata_roundtrip() {
+        let test_dir = TestDir::new("metadata");
+        let path = test_dir.join("graph.meta");
+        let metadata = GraphMetadata {
+            kmer_length: 31,
+            minimizer_length: 12,
+            hash_type: "seqhash".to_string(),
+            forward_only: false,
+        };
+        metadata.write(&path).unwrap();
+        let read = GraphMetadata::read(&path);
+
+        assert_eq!(read, Some(metadata.clone()));
+        assert_eq!(
+            metadata.check_query(&GraphMetadata {
+                forward_only: true,
+                ..metadata.clone()
+            }),
+            Err(GraphMetadataMismatch::ForwardOnly {
+                graph: false,
+                query: true
+            })
+        );
+        assert_eq!(
+            metadata.check_query(&GraphMetadata {
+                minimizer_length: 10,
+                ..metadata.clone()
+            }),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,701 @@
+use colors::storage::deserializer::ColorsDeserializer;
+use colors::DefaultColorsSerializer;
+use config::ColorIndexType;
+use io::compressed_read::CompressedReadIndipendent;
+use io::concurrent::structured_sequences::binary::StructSeqBinaryReader;
+use io::concurrent::structured_sequences::{
+    is_canonical_sequence, write_reverse_complement, IdentSequenceWriter,
+};
+use io::concurrent::temp_reads::extra_data::SequenceExtraData;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Link to another unitig, in BCALM2 format L:<+/->:<target>:<+/->
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphLink {
+    /// The link starts from the beginning of this unitig (its reverse complement end)
+    pub from_reverse: bool,
+    pub target: u64,
+    /// The link reaches the end of the target unitig (its reverse complement beginning)
+    pub to_reverse: bool,
+}
+
+/// A unitig of a finalized graph
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unitig {
+    pub index: u64,
+    pub sequence: Vec<u8>,
+    /// Sorted colors of all the kmers of the unitig, empty if the graph is not colored
+    pub colors: Vec<ColorIndexType>,
+    /// Links to the other unitigs, empty if the graph was built without links
+    pub links: Vec<GraphLink>,
+}
+
+fn parse_orientation(sign: &str) -> Option<bool> {
+    match sign {
+        "+" => Some(false),
+        "-" => Some(true),
+        _ => None,
+    }
+}
+
+/// Colors of a run of consecutive kmers of a unitig, that share the same colors subset
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColorsRun {
+    pub kmers_count: u64,
+    /// Sorted colors of the kmers of the run
+    pub colors: Vec<ColorIndexType>,
+}
+
+/// Parses a graph header in the format ><index> LN:i:<len> [L:<+/->:<index>:<+/->]* [C:<hex>:<count>]*
+/// The colors subsets are returned together with the count of the kmers they refer to
+pub(crate) fn parse_header(
+    header: &[u8],
+    links: &mut Vec<GraphLink>,
+    colors: &mut Vec<(u64, u64)>,
+) -> Option<u64> {
+    let header = std::str::from_utf8(header).ok()?;
+    let mut fields = header.strip_prefix('>').unwrap_or(header).split(' ');
+
+    let index = fields.next()?.parse().ok()?;
+
+    for field in fields {
+        let mut parts = field.split(':');
+        match parts.next()? {
+            "L" => links.push(GraphLink {
+                from_reverse: parse_orientation(parts.next()?)?,
+                target: parts.next()?.parse().ok()?,
+                to_reverse: parse_orientation(parts.next()?)?,
+            }),
+            "C" => colors.push((
+                u64::from_str_radix(parts.next()?, 16).ok()?,
+                parts.next()?.parse().ok()?,
+            )),
+            _ => {}
+        }
+    }
+
+    Some(index)
+}
+
+/// Maps a colors subset of the graph to the colors it contains
+pub type ColorsSubsetResolver = Box<dyn FnMut(ColorIndexType, &mut Vec<ColorIndexType>) + Send>;
+
+/// Error while reading the unitigs of a graph
+#[derive(Debug)]
+pub enum GraphReadError {
+    Io(std::io::Error),
+    /// A header not in the graph format, or a sequence not preceded by a header
+    MalformedHeader(String),
+}
+
+impl Display for GraphReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphReadError::Io(error) => write!(f, "Error while reading the graph: {}", error),
+            GraphReadError::MalformedHeader(header) => {
+                write!(f, "Malformed graph header: {}", header)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphReadError {}
+
+impl From<std::io::Error> for GraphReadError {
+    fn from(error: std::io::Error) -> Self {
+        GraphReadError::Io(error)
+    }
+}
+
+impl From<GraphReadError> for std::io::Error {
+    fn from(error: GraphReadError) -> Self {
+        match error {
+            GraphReadError::Io(error) => error,
+            malformed => std::io::Error::new(std::io::ErrorKind::InvalidData, malformed),
+        }
+    }
+}
+
+/// Source of the records of a graph, each one made of a header in the format written by
+/// FastaWriter and of the sequence of the unitig
+pub trait UnitigsSource {
+    /// Reads the next record, returning false at the end of the graph
+    fn read_record(
+        &mut self,
+        header: &mut Vec<u8>,
+        sequence: &mut Vec<u8>,
+    ) -> Result<bool, GraphReadError>;
+}
+
+/// Records of a graph in FASTA format, with sequences possibly spanning multiple lines
+pub struct FastaUnitigs<R: BufRead> {
+    reader: R,
+    line: Vec<u8>,
+}
+
+impl<R: BufRead> FastaUnitigs<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: vec![],
+        }
+    }
+
+    fn read_line(reader: &mut R, line: &mut Vec<u8>) -> std::io::Result<bool> {
+        line.clear();
+        let count = reader.read_until(b'\n', line)?;
+        while line.last().map(|c| *c == b'\n' || *c == b'\r') == Some(true) {
+            line.pop();
+        }
+        Ok(count > 0)
+    }
+
+    fn next_is_header(&mut self) -> std::io::Result<bool> {
+        Ok(self
+            .reader
+            .fill_buf()?
+            .first()
+            .map(|c| *c == b'>')
+            .unwrap_or(true))
+    }
+}
+
+impl<R: BufRead> UnitigsSource for FastaUnitigs<R> {
+    fn read_record(
+        &mut self,
+        header: &mut Vec<u8>,
+        sequence: &mut Vec<u8>,
+    ) -> Result<bool, GraphReadError> {
+        if !Self::read_line(&mut self.reader, header)? {
+            return Ok(false);
+        }
+
+        sequence.clear();
+        while !self.next_is_header()? && Self::read_line(&mut self.reader, &mut self.line)? {
+            sequence.extend_from_slice(&self.line);
+        }
+        Ok(true)
+    }
+}
+
+/// Records of a graph written by StructSeqBinaryWriter, with their headers formatted as in FASTA
+impl<
+        ColorInfo: IdentSequenceWriter + SequenceExtraData,
+        LinksInfo: IdentSequenceWriter + SequenceExtraData,
+    > UnitigsSource for StructSeqBinaryReader<ColorInfo, LinksInfo>
+{
+    fn read_record(
+        &mut self,
+        header: &mut Vec<u8>,
+        sequence: &mut Vec<u8>,
+    ) -> Result<bool, GraphReadError> {
+        Ok(self.read_fasta_record(header, sequence))
+    }
+}
+
+/// Streaming reader of the unitigs of a finalized graph, with their colors (resolved through the colormap)
+/// and links. The graph is read sequentially, without loading it in memory
+pub struct GraphReader<S: UnitigsSource> {
+    source: S,
+    colors_resolver: Option<ColorsSubsetResolver>,
+    header: Vec<u8>,
+    subsets: Vec<(u64, u64)>,
+}
+
+/// Resolver of the colors subsets of a graph through its colormap file, if it exists
+fn graph_colors_resolver(graph: &Path) -> Option<ColorsSubsetResolver> {
+    let colormap_file = graph.with_extension("colors.dat");
+
+    if colormap_file.exists() {
+        let mut colormap = ColorsDeserializer::<DefaultColorsSerializer>::new(colormap_file, false);
+        Some(Box::new(move |subset, colors| {
+            colormap.get_color_mappings(subset, colors)
+        }))
+    } else {
+        None
+    }
+}
+
+impl GraphReader<FastaUnitigs<BufReader<Box<dyn Read + Send>>>> {
+    /// Opens a (possibly compressed) graph, using its colormap file if it exists
+    pub fn open(graph: impl AsRef<Path>) -> Self {
+        let graph = graph.as_ref();
+        Self::new(
+            BufReader::new(io::lines_reader::open_decompressed_file(graph)),
+            graph_colors_resolver(graph),
+        )
+    }
+}
+
+impl<R: BufRead> GraphReader<FastaUnitigs<R>> {
+    pub fn new(reader: R, colors_resolver: Option<ColorsSubsetResolver>) -> Self {
+        Self::from_source(FastaUnitigs::new(reader), colors_resolver)
+    }
+}
+
+impl<
+        ColorInfo: IdentSequenceWriter + SequenceExtraData,
+        LinksInfo: IdentSequenceWriter + SequenceExtraData,
+    > GraphReader<StructSeqBinaryReader<ColorInfo, LinksInfo>>
+{
+    /// Opens a graph written by StructSeqBinaryWriter, using its colormap file if it exists
+    pub fn open_binary(graph: impl AsRef<Path>) -> Self {
+        let graph = graph.as_ref();
+        Self::from_source(
+            StructSeqBinaryReader::new(graph),
+            graph_colors_resolver(graph),
+        )
+    }
+}
+
+impl<S: UnitigsSource> GraphReader<S> {
+    pub fn from_source(source: S, colors_resolver: Option<ColorsSubsetResolver>) -> Self {
+        Self {
+            source,
+            colors_resolver,
+            header: vec![],
+            subsets: vec![],
+        }
+    }
+
+    /// Iterates all the kmers of the graph with their colors, walking each unitig in order.
+    /// A unitig of length L yields its L - k + 1 kmers, the shorter ones are skipped
+    pub fn kmers(self, k: usize) -> GraphKmers<S> {
+        GraphKmers {
+            reader: self,
+            k,
+            unitig: None,
+            colors_runs: vec![],
+            position: 0,
+            run_index: 0,
+            run_remaining: 0,
+            rc_kmer: vec![],
+        }
+    }
+
+    /// Reads the next unitig, also returning in colors_runs the colors of each run of its kmers
+    /// sharing the same colors subset. No runs are returned if the graph is not colored
+    pub fn next_with_colors_runs(
+        &mut self,
+        colors_runs: &mut Vec<ColorsRun>,
+    ) -> Result<Option<Unitig>, GraphReadError> {
+        colors_runs.clear();
+        self.read_unitig(Some(colors_runs))
+    }
+
+    fn read_unitig(
+        &mut self,
+        mut colors_runs: Option<&mut Vec<ColorsRun>>,
+    ) -> Result<Option<Unitig>, GraphReadError> {
+        let mut sequence = vec![];
+        if !self.source.read_record(&mut self.header, &mut sequence)? {
+            return Ok(None);
+        }
+
+        let mut links = vec![];
+        self.subsets.clear();
+        let index = parse_header(&self.header, &mut links, &mut self.subsets).ok_or_else(|| {
+            GraphReadError::MalformedHeader(String::from_utf8_lossy(&self.header).to_string())
+        })?;
+
+        let mut colors = vec![];
+        if let Some(resolver) = &mut self.colors_resolver {
+            for (subset, kmers_count) in &self.subsets {
+                let run_start = colors.len();
+                resolver(*subset as ColorIndexType, &mut colors);
+                if let Some(colors_runs) = colors_runs.as_mut() {
+                    let mut run_colors = colors[run_start..].to_vec();
+                    run_colors.sort_unstable();
+                    colors_runs.push(ColorsRun {
+                        kmers_count: *kmers_count,
+                        colors: run_colors,
+                    });
+                }
+            }
+            colors.sort_unstable();
+            colors.dedup();
+        }
+
+        Ok(Some(Unitig {
+            index,
+            sequence,
+            colors,
+            links,
+        }))
+    }
+}
+
+impl<S: UnitigsSource> Iterator for GraphReader<S> {
+    type Item = Result<Unitig, GraphReadError>;
+
+    fn next(&mut self) -> Option<Result<Unitig, GraphReadError>> {
+        self.read_unitig(None).transpose()
+    }
+}
+
+/// A kmer of a graph together with its colors
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphKmer {
+    /// The canonical kmer, packed with 2 bits per base as the compressed reads
+    /// (A=0, C=1, T=2, G=3, 4 bases per byte starting from the lowest bits)
+    pub kmer: Vec<u8>,
+    /// Sorted colors of the kmer, empty if the graph is not colored
+    pub colors: Vec<ColorIndexType>,
+}
+
+/// Iterator over the kmers of a graph, returned by GraphReader::kmers
+pub struct GraphKmers<S: UnitigsSource> {
+    reader: GraphReader<S>,
+    k: usize,
+    unitig: Option<Unitig>,
+    colors_runs: Vec<ColorsRun>,
+    position: usize,
+    run_index: usize,
+    run_remaining: u64,
+    rc_kmer: Vec<u8>,
+}
+
+impl<S: UnitigsSource> GraphKmers<S> {
+    /// Colors of the kmer at the current position, advancing through the colors runs of the unitig
+    fn next_kmer_colors(&mut self) -> Vec<ColorIndexType> {
+        while self.run_remaining == 0 && self.run_index < self.colors_runs.len() {
+            self.run_remaining = self.colors_runs[self.run_index].kmers_count;
+            self.run_index += 1;
+        }
+
+        if self.run_remaining > 0 {
+            self.run_remaining -= 1;
+            self.colors_runs[self.run_index - 1].colors.clone()
+        } else {
+            // No per-kmer colors, use the ones of the whole unitig
+            self.unitig.as_ref().unwrap().colors.clone()
+        }
+    }
+}
+
+impl<S: UnitigsSource> Iterator for GraphKmers<S> {
+    type Item = Result<GraphKmer, GraphReadError>;
+
+    fn next(&mut self) -> Option<Result<GraphKmer, GraphReadError>> {
+        while self
+            .unitig
+            .as_ref()
+            .map(|unitig| self.position + self.k > unitig.sequence.len())
+            .unwrap_or(true)
+        {
+            self.unitig = match self.reader.next_with_colors_runs(&mut self.colors_runs) {
+                Ok(unitig) => Some(unitig?),
+                Err(error) => return Some(Err(error)),
+            };
+            self.position = 0;
+            self.run_index = 0;
+            self.run_remaining = 0;
+        }
+
+        let colors = self.next_kmer_colors();
+
+        let unitig = self.unitig.as_ref().unwrap();
+        let kmer = &unitig.sequence[self.position..self.position + self.k];
+        self.position += 1;
+
+        let canonical_kmer = if is_canonical_sequence(kmer) {
+            kmer
+        } else {
+            write_reverse_complement(kmer, &mut self.rc_kmer);
+            &self.rc_kmer
+        };
+
+        let mut packed = Vec::with_capacity((self.k + 3) / 4);
+        CompressedReadIndipendent::from_plain(canonical_kmer, &mut packed);
+
+        Some(Ok(GraphKmer {
+            kmer: packed,
+            colors,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorsSubsetResolver, GraphKmer, GraphLink, GraphReadError, GraphReader, Unitig};
+    use crate::tests::TestDir;
+    use config::get_compression_level_info;
+    use io::compressed_read::CompressedReadIndipendent;
+    use io::concurrent::structured_sequences::binary::{
+        StructSeqBinaryReader, StructSeqBinaryWriter,
+    };
+    use io::concurrent::structured_sequences::concurrent::FastaWriterConcurrentBuffer;
+    use io::concurrent::structured_sequences::{IdentSequenceWriter, StructuredSequenceWriter};
+    use io::concurrent::temp_reads::extra_data::{HasEmptyExtraBuffer, SequenceExtraData};
+    use io::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
+    use parallel_processor::buckets::writers::compressed_binary_writer::CompressedCheckpointSize;
+    use parallel_processor::memory_data_size::MemoryDataSize;
+    use parallel_processor::memory_fs::file::internal::MemoryFileMode;
+    use std::io::{Cursor, Read, Write};
+
+    fn read_byte(reader: &mut impl Read) -> Option<u8> {
+        let mut byte = [0];
+        reader.read_exact(&mut byte).ok()?;
+        Some(byte[0])
+    }
+
+    /// Single colors subset of all the kmers of a unitig, written as in the final graphs
+    #[derive(Clone, Debug)]
+    struct SubsetColors {
+        subset: u64,
+        kmers_count: u64,
+    }
+
+    impl HasEmptyExtraBuffer for SubsetColors {}
+    impl SequenceExtraData for SubsetColors {
+        fn decode_extended(_: &mut (), reader: &mut impl Read) -> Option<Self> {
+            Some(Self {
+                subset: decode_varint(|| read_byte(reader))?,
+                kmers_count: decode_varint(|| read_byte(reader))?,
+            })
+        }
+
+        fn encode_extended(&self, _: &(), writer: &mut impl Write) {
+            encode_varint(|b| writer.write_all(b), self.subset).unwrap();
+            encode_varint(|b| writer.write_all(b), self.kmers_count).unwrap();
+        }
+
+        fn max_size(&self) -> usize {
+            2 * VARINT_MAX_SIZE
+        }
+    }
+
+    impl IdentSequenceWriter for SubsetColors {
+        fn write_as_ident(&self, stream: &mut impl Write, _: &()) {
+            write!(stream, " C:{:x}:{}", self.subset, self.kmers_count).unwrap();
+        }
+
+        fn write_as_gfa(&self, _: &mut impl Write, _: &()) {}
+
+        fn parse_as_ident<'a>(_: &[u8], _: &mut ()) -> Option<Self> {
+            None
+        }
+
+        fn parse_as_gfa<'a>(_: &[u8], _: &mut ()) -> Option<Self> {
+            None
+        }
+    }
+
+    /// Single link of a unitig
+    #[derive(Clone, Debug)]
+    struct SingleLink(GraphLink);
+
+    impl HasEmptyExtraBuffer for SingleLink {}
+    impl SequenceExtraData for SingleLink {
+        fn decode_extended(_: &mut (), reader: &mut impl Read) -> Option<Self> {
+            let target = decode_varint(|| read_byte(reader))?;
+            let flags = read_byte(reader)?;
+            Some(Self(GraphLink {
+                from_reverse: flags & 1 != 0,
+                target,
+                to_reverse: flags & 2 != 0,
+            }))
+        }
+
+        fn encode_extended(&self, _: &(), writer: &mut impl Write) {
+            encode_varint(|b| writer.write_all(b), self.0.target).unwrap();
+            let flags = self.0.from_reverse as u8 | (self.0.to_reverse as u8) << 1;
+            writer.write_all(&[flags]).unwrap();
+        }
+
+        fn max_size(&self) -> usize {
+            VARINT_MAX_SIZE + 1
+        }
+    }
+
+    impl IdentSequenceWriter for SingleLink {
+        fn write_as_ident(&self, stream: &mut impl Write, _: &()) {
+            write!(
+                stream,
+                " L:{}:{}:{}",
+                if self.0.from_reverse { "-" } else { "+" },
+                self.0.target,
+                if self.0.to_reverse { "-" } else { "+" }
+            )
+            .unwrap();
+        }
+
+        fn write_as_gfa(&self, _: &mut impl Write, _: &()) {}
+
+        fn parse_as_ident<'a>(_: &[u8], _: &mut ()) -> Option<Self> {
+            None
+        }
+
+        fn parse_as_gfa<'a>(_: &[u8], _: &mut ()) -> Option<Self> {
+            None
+        }
+    }
+
+    #[test]
+    fn read_back_unitigs() {
+        const UNITIGS_COUNT: u64 = 50;
+
+        // Each colors subset i contains the colors i and i + 1
+        let subsets_resolver: ColorsSubsetResolver =
+            Box::new(|subset, colors| colors.extend_from_slice(&[subset, subset + 1]));
+
+        let unitigs: Vec<_> = (0..UNITIGS_COUNT)
+            .map(|index| Unitig {
+                index,
+                sequence: b"ACGTTGCA".repeat(1 + index as usize % 4),
+                colors: vec![index as u32, index as u32 + 1, index as u32 + 2],
+                links: vec![GraphLink {
+                    from_reverse: index % 2 == 0,
+                    target: (index + 1) % UNITIGS_COUNT,
+                    to_reverse: index % 3 == 0,
+                }],
+            })
+            .collect();
+
+        let mut graph = vec![];
+        for unitig in &unitigs {
+            write!(
+                graph,
+                ">{} LN:i:{} C:{:x}:2 C:{:x}:1",
+                unitig.index,
+                unitig.sequence.len(),
+                unitig.index,
+                unitig.index + 1
+            )
+            .unwrap();
+            for link in &unitig.links {
+                write!(
+                    graph,
+                    " L:{}:{}:{}",
+                    if link.from_reverse { "-" } else { "+" },
+                    link.target,
+                    if link.to_reverse { "-" } else { "+" }
+                )
+                .unwrap();
+            }
+            graph.push(b'\n');
+            graph.extend_from_slice(&unitig.sequence);
+            graph.push(b'\n');
+        }
+
+        let read_unitigs: Vec<_> = GraphReader::new(Cursor::new(graph), Some(subsets_resolver))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read_unitigs, unitigs);
+    }
+
+    #[test]
+    fn kmers_of_the_unitigs() {
+        const K: usize = 5;
+
+        // The unitig 0 has 4 kmers of subset 0 and 2 of subset 1, the unitig 2 is shorter than k
+        let graph =
+            b">0 LN:i:10 C:0:4 C:1:2\nACGTTGCATT\n>1 LN:i:7 C:2:3\nGGGCAAA\n>2 LN:i:3 C:3:0\nACG\n";
+        let subsets_resolver: ColorsSubsetResolver =
+            Box::new(|subset, colors| colors.extend_from_slice(&[subset + 10, subset]));
+
+        let kmers: Vec<_> = GraphReader::new(Cursor::new(graph.to_vec()), Some(subsets_resolver))
+            .kmers(K)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // Each unitig of length L has L - k + 1 kmers
+        assert_eq!(kmers.len(), (10 - K + 1) + (7 - K + 1));
+
+        let packed = |kmer: &[u8]| {
+            let mut storage = vec![];
+            CompressedReadIndipendent::from_plain(kmer, &mut storage);
+            storage
+        };
+
+        // ACGTT is not canonical, its reverse complement AACGT is returned
+        assert_eq!(
+            kmers[0],
+            GraphKmer {
+                kmer: packed(b"AACGT"),
+                colors: vec![0, 10],
+            }
+        );
+        assert_eq!(kmers[3].kmer, packed(b"TGCAA"));
+        assert_eq!(kmers[3].colors, vec![0, 10]);
+        assert_eq!(kmers[4].kmer, packed(b"ATGCA"));
+        assert_eq!(kmers[4].colors, vec![1, 11]);
+        assert_eq!(kmers[5].colors, vec![1, 11]);
+        assert_eq!(kmers[6].kmer, packed(b"GGGCA"));
+        assert_eq!(kmers[6].colors, vec![2, 12]);
+    }
+
+    #[test]
+    fn read_back_binary_unitigs() {
+        const UNITIGS_COUNT: u64 = 50;
+
+        let test_dir = TestDir::new("binary-graph");
+        let path = test_dir.join("graph.bin");
+
+        // Each colors subset i contains the colors i and i + 1
+        let subsets_resolver: ColorsSubsetResolver =
+            Box::new(|subset, colors| colors.extend_from_slice(&[subset, subset + 1]));
+
+        let unitigs: Vec<_> = (0..UNITIGS_COUNT)
+            .map(|index| Unitig {
+                index,
+                sequence: b"ACGTTGCA".repeat(1 + index as usize % 4),
+                colors: vec![index as u32, index as u32 + 1],
+                links: vec![GraphLink {
+                    from_reverse: index % 2 == 0,
+                    target: (index + 1) % UNITIGS_COUNT,
+                    to_reverse: index % 3 == 0,
+                }],
+            })
+            .collect();
+
+        let writer = StructuredSequenceWriter::<SubsetColors, SingleLink, _>::new(
+            StructSeqBinaryWriter::new(
+                &path,
+                &(
+                    MemoryFileMode::DiskOnly,
+                    CompressedCheckpointSize::new_from_size(MemoryDataSize::from_mebioctets(4)),
+                    get_compression_level_info(),
+                ),
+            ),
+        );
+        let mut buffer = FastaWriterConcurrentBuffer::new(&writer, 1024, false);
+        for unitig in &unitigs {
+            buffer.add_read(
+                &unitig.sequence,
+                None,
+                SubsetColors {
+                    subset: unitig.index,
+                    kmers_count: unitig.sequence.len() as u64,
+                },
+                &(),
+                SingleLink(unitig.links[0].clone()),
+                &(),
+            );
+        }
+        buffer.finalize();
+        writer.finalize();
+
+        let read_unitigs = GraphReader::from_source(
+            StructSeqBinaryReader::<SubsetColors, SingleLink>::new(&path),
+            Some(subsets_resolver),
+        )
+        .collect::<Result<Vec<_>, _>>();
+
+        assert_eq!(read_unitigs.unwrap(), unitigs);
+    }
+
+    #[test]
+    fn malformed_header_is_an_error() {
+        let graph = b">0 LN:i:4\nACGT\n>1 L:x:0:+\nACGT\n";
+
+        let mut reader = GraphReader::new(Cursor::new(graph.to_vec()), None);
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(GraphReadError::MalformedHeader(_)))
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::graph_reader::{ColorsRun, GraphReader};
+use config::ColorIndexType;
+use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
+use io::sequences_stream::general::DynamicSequencesStream;
+use io::sequences_stream::SequenceInfo;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Shared colors space of a set of graphs, where the colors with the same name are merged.
+/// An uncolored graph contributes a single color, named after its file
+pub struct UnionColors {
+    pub names: Vec<String>,
+    /// For each graph, the shared index of each of its colors
+    pub mappings: Vec<Vec<ColorIndexType>>,
+}
+
+impl UnionColors {
+    pub fn new(graphs_colors: impl Iterator<Item = Vec<String>>) -> Self {
+        let mut names = vec![];
+        let mut indexes = HashMap::new();
+
+        let mappings = graphs_colors
+            .map(|graph_colors| {
+                graph_colors
+                    .into_iter()
+                    .map(|name| {
+                        *indexes.entry(name.clone()).or_insert_with(|| {
+                            names.push(name);
+                            (names.len() - 1) as ColorIndexType
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { names, mappings }
+    }
+}
+
+/// Streams the kmers of a set of graphs, one block for each graph. Each run of kmers sharing the
+/// same colors is returned once for each of its colors, mapped to the shared colors space,
+/// so that building a colored graph from this stream gives the union of the graphs
+pub struct GraphsUnionStream {
+    graphs: Vec<PathBuf>,
+    colors_mappings: Vec<Vec<ColorIndexType>>,
+    k: usize,
+}
+
+impl GraphsUnionStream {
+    pub fn new(graphs: Vec<PathBuf>, colors_mappings: Vec<Vec<ColorIndexType>>, k: usize) -> Self {
+        assert_eq!(graphs.len(), colors_mappings.len());
+        Self {
+            graphs,
+            colors_mappings,
+            k,
+        }
+    }
+
+    pub fn graph_color_names(graph: &Path) -> Vec<String> {
+        let colormap_file = crate::GGCATInstance::get_colormap_file(graph);
+        if colormap_file.exists() {
+            crate::GGCATInstance::dump_colors(colormap_file).collect()
+        } else {
+            vec![graph
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()]
+        }
+    }
+}
+
+impl DynamicSequencesStream for GraphsUnionStream {
+    fn read_block(
+        &self,
+        block: usize,
+        _copy_ident_data: bool,
+        _partial_read_copyback: Option<usize>,
+        callback: &mut dyn FnMut(DnaSequence, SequenceInfo),
+    ) {
+        let colors_mapping = &self.colors_mappings[block];
+        let mut emit = |sequence: &[u8], color: ColorIndexType| {
+            callback(
+                DnaSequence {
+                    ident_data: &[],
+                    seq: sequence,
+                    format: DnaSequencesFileType::FASTA,
+                },
+                SequenceInfo {
+                    color: Some(colors_mapping[color as usize]),
+                    pair_id: None,
+                },
+            )
+        };
+
+        let mut reader = GraphReader::open(&self.graphs[block]);
+        let mut colors_runs: Vec<ColorsRun> = vec![];
+
+        loop {
+            // As for the other inputs, a read error ends the block
+            let unitig = match reader.next_with_colors_runs(&mut colors_runs) {
+                Ok(Some(unitig)) => unitig,
+                Ok(None) => break,
+                Err(error) => {
+                    log::warn!(
+                        "Error while reading the graph {}: {}",
+                        self.graphs[block].display(),
+                        error
+                    );
+                    break;
+                }
+            };
+
+            if colors_runs.is_empty() {
+                emit(&unitig.sequence, 0);
+                continue;
+            }
+
+            let mut first_kmer = 0;
+            for run in &colors_runs {
+                let run_end =
+                    (first_kmer + run.kmers_count as usize + self.k - 1).min(unitig.sequence.len());
+                for color in &run.colors {
+                    emit(&unitig.sequence[first_kmer..run_end], *color);
+                }
+                first_kmer += run.kmers_count as usize;
+            }
+        }
+    }
+
+    fn estimated_base_count(&self, block: usize) -> u64 {
+        std::fs::metadata(&self.graphs[block])
+            .map(|m| m.len())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnionColors;
+
+    #[test]
+    fn colors_with_the_same_name_are_merged() {
+        let colors = UnionColors::new(
+            [
+                vec!["a".to_string(), "b".to_string()],
+                vec!["c".to_string(), "a".to_string()],
+            ]
+            .into_iter(),
+        );
+
+        assert_eq!(colors.names, ["a", "b", "c"]);
+        assert_eq!(colors.mappings, [vec![0, 1], vec![2, 0]]);
+    }
+}
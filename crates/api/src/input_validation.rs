@@ -0,0 +1,297 @@
+use io::compressed_read::iupac_bases;
+use std::fmt::{Display, Formatter};
+use std::io::BufRead;
+
+/// Issues beyond this count are only counted, without reporting their line
+const MAX_REPORTED_ISSUES: usize = 100;
+
+/// A problem found in a FASTA/FASTQ input
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputIssueKind {
+    /// Sequence data before any record header
+    MissingHeader,
+    /// A character that is not an IUPAC nucleotide code, at the given (1-based) column
+    InvalidCharacter { character: u8, column: usize },
+    /// A record without any base
+    EmptySequence,
+    /// A FASTQ record whose quality line has not the same length of its sequence
+    QualityLengthMismatch { sequence: usize, quality: usize },
+    /// A FASTQ record without the + separator line, or truncated at the end of the file
+    MalformedFastqRecord,
+}
+
+impl InputIssueKind {
+    /// The fatal issues prevent the records from being read as intended, while the others are
+    /// tolerated by the assembly (the invalid bases are read as N and the empty records are skipped)
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::MissingHeader
+            | Self::QualityLengthMismatch { .. }
+            | Self::MalformedFastqRecord => true,
+            Self::InvalidCharacter { .. } | Self::EmptySequence => false,
+        }
+    }
+}
+
+/// An issue with the (1-based) line where it was found
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InputIssue {
+    pub line: u64,
+    pub kind: InputIssueKind,
+}
+
+impl Display for InputIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            InputIssueKind::MissingHeader => write!(f, "sequence without a record header"),
+            InputIssueKind::InvalidCharacter { character, column } => write!(
+                f,
+                "invalid character {:?} at column {}",
+                character as char, column
+            ),
+            InputIssueKind::EmptySequence => write!(f, "record with an empty sequence"),
+            InputIssueKind::QualityLengthMismatch { sequence, quality } => write!(
+                f,
+                "quality length {} differs from the sequence length {}",
+                quality, sequence
+            ),
+            InputIssueKind::MalformedFastqRecord => {
+                write!(f, "malformed or truncated FASTQ record")
+            }
+        }
+    }
+}
+
+/// Issues found in a FASTA/FASTQ input
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputValidationReport {
+    pub records_count: u64,
+    pub issues_count: u64,
+    pub fatal_issues_count: u64,
+    /// The first issues found, in order of line
+    pub issues: Vec<InputIssue>,
+}
+
+impl InputValidationReport {
+    fn add_issue(&mut self, line: u64, kind: InputIssueKind) {
+        self.issues_count += 1;
+        if kind.is_fatal() {
+            self.fatal_issues_count += 1;
+        }
+        if self.issues.len() < MAX_REPORTED_ISSUES {
+            self.issues.push(InputIssue { line, kind });
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.fatal_issues_count == 0
+    }
+}
+
+impl Display for InputValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        if self.issues_count > self.issues.len() as u64 {
+            writeln!(
+                f,
+                "... and {} more issues",
+                self.issues_count - self.issues.len() as u64
+            )?;
+        }
+        write!(
+            f,
+            "Checked {} records, found {} issues ({} fatal)",
+            self.records_count, self.issues_count, self.fatal_issues_count
+        )
+    }
+}
+
+fn check_sequence_line(report: &mut InputValidationReport, line_number: u64, line: &[u8]) {
+    if let Some(column) = line.iter().position(|c| iupac_bases(*c).is_empty()) {
+        report.add_issue(
+            line_number,
+            InputIssueKind::InvalidCharacter {
+                character: line[column],
+                column: column + 1,
+            },
+        );
+    }
+}
+
+/// Lines of the input with their (1-based) numbers, without the line terminators
+fn numbered_lines(input: impl BufRead) -> impl Iterator<Item = (u64, Vec<u8>)> {
+    input.split(b'\n').enumerate().map(|(index, line)| {
+        let mut line = line.expect("Error while reading the input");
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        (index as u64 + 1, line)
+    })
+}
+
+/// Scans a (multi-line) FASTA input, reporting the malformed records
+pub fn validate_fasta(input: impl BufRead) -> InputValidationReport {
+    let mut report = InputValidationReport::default();
+
+    // Line of the header of the current record, with its bases count
+    let mut current_record: Option<(u64, usize)> = None;
+    let mut headerless_sequence = false;
+
+    for (line_number, line) in numbered_lines(input) {
+        if line.is_empty() || line[0] == b';' {
+            continue;
+        }
+
+        if line[0] == b'>' {
+            if let Some((header_line, 0)) = current_record {
+                report.add_issue(header_line, InputIssueKind::EmptySequence);
+            }
+            report.records_count += 1;
+            current_record = Some((line_number, 0));
+            continue;
+        }
+
+        match &mut current_record {
+            Some((_, bases_count)) => *bases_count += line.len(),
+            None => {
+                // Report only the first line of a headerless sequence
+                if !headerless_sequence {
+                    report.add_issue(line_number, InputIssueKind::MissingHeader);
+                    headerless_sequence = true;
+                }
+            }
+        }
+        check_sequence_line(&mut report, line_number, &line);
+    }
+
+    if let Some((header_line, 0)) = current_record {
+        report.add_issue(header_line, InputIssueKind::EmptySequence);
+    }
+
+    report
+}
+
+/// Scans a FASTQ input (4 lines per record), reporting the malformed records
+pub fn validate_fastq(input: impl BufRead) -> InputValidationReport {
+    let mut report = InputValidationReport::default();
+    let mut lines = numbered_lines(input);
+
+    while let Some((header_line, header)) = lines.next() {
+        // The empty lines are allowed only between the records
+        if header.is_empty() {
+            continue;
+        }
+
+        if header[0] != b'@' {
+            // Resynchronize on the next header
+            report.add_issue(header_line, InputIssueKind::MissingHeader);
+            continue;
+        }
+        report.records_count += 1;
+
+        let (
+            Some((sequence_line, sequence)),
+            Some((separator_line, separator)),
+            Some((quality_line, quality)),
+        ) = (lines.next(), lines.next(), lines.next())
+        else {
+            report.add_issue(header_line, InputIssueKind::MalformedFastqRecord);
+            break;
+        };
+
+        if sequence.is_empty() {
+            report.add_issue(header_line, InputIssueKind::EmptySequence);
+        }
+        check_sequence_line(&mut report, sequence_line, &sequence);
+        if separator.first() != Some(&b'+') {
+            report.add_issue(separator_line, InputIssueKind::MalformedFastqRecord);
+        }
+        if quality.len() != sequence.len() {
+            report.add_issue(
+                quality_line,
+                InputIssueKind::QualityLengthMismatch {
+                    sequence: sequence.len(),
+                    quality: quality.len(),
+                },
+            );
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_fasta, validate_fastq, InputIssue, InputIssueKind};
+    use std::io::Cursor;
+
+    #[test]
+    fn valid_inputs() {
+        let report = validate_fasta(Cursor::new(b">r1\nACGTN\nRYacgt\n\n>r2 desc\nTTT\n"));
+        assert!(report.is_valid());
+        assert_eq!(report.records_count, 2);
+        assert_eq!(report.issues_count, 0);
+
+        let report = validate_fastq(Cursor::new(b"@r1\nACGT\n+\nIIII\n@r2\nTT\n+r2\nII\n"));
+        assert!(report.is_valid());
+        assert_eq!(report.records_count, 2);
+        assert_eq!(report.issues_count, 0);
+    }
+
+    #[test]
+    fn fasta_record_without_header() {
+        // A sequence without a header, followed by an empty record and an invalid base
+        let report = validate_fasta(Cursor::new(b"ACGT\nACGT\n>r1\n>r2\nACXT\n".as_slice()));
+        assert!(!report.is_valid());
+        assert_eq!(report.records_count, 2);
+        assert_eq!(
+            report.issues,
+            vec![
+                InputIssue {
+                    line: 1,
+                    kind: InputIssueKind::MissingHeader
+                },
+                InputIssue {
+                    line: 3,
+                    kind: InputIssueKind::EmptySequence
+                },
+                InputIssue {
+                    line: 5,
+                    kind: InputIssueKind::InvalidCharacter {
+                        character: b'X',
+                        column: 3
+                    }
+                },
+            ]
+        );
+        assert_eq!(report.fatal_issues_count, 1);
+        assert!(report
+            .to_string()
+            .starts_with("line 1: sequence without a record header"));
+    }
+
+    #[test]
+    fn fastq_quality_mismatch() {
+        let report = validate_fastq(Cursor::new(b"@r1\nACGT\n+\nIII\n@r2\nAC\n".as_slice()));
+        assert_eq!(report.records_count, 2);
+        assert_eq!(
+            report.issues,
+            vec![
+                InputIssue {
+                    line: 4,
+                    kind: InputIssueKind::QualityLengthMismatch {
+                        sequence: 4,
+                        quality: 3
+                    }
+                },
+                InputIssue {
+                    line: 5,
+                    kind: InputIssueKind::MalformedFastqRecord
+                },
+            ]
+        );
+    }
+}
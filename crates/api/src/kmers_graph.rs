@@ -0,0 +1,184 @@
+use crate::graph_reader::{GraphReader, UnitigsSource};
+use io::concurrent::structured_sequences::{is_canonical_sequence, write_reverse_complement};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+fn orientation_sign(reversed: bool) -> char {
+    if reversed {
+        '-'
+    } else {
+        '+'
+    }
+}
+
+/// Writes the de Bruijn graph of the kmers of a unitigs graph in GFA format, with a segment
+/// for each distinct kmer (canonical unless forward_only) and a link for each k-1 overlap
+/// between two kmers. All the kmers are kept in memory. Returns the segments and links counts
+pub(crate) fn write_kmers_graph<S: UnitigsSource>(
+    graph: GraphReader<S>,
+    k: usize,
+    forward_only: bool,
+    mut output: impl Write,
+) -> std::io::Result<(u64, u64)> {
+    let mut rc_kmer = vec![];
+    let mut nodes: HashMap<Vec<u8>, u64> = HashMap::new();
+
+    for unitig in graph {
+        let unitig = unitig?;
+        for kmer in unitig.sequence.windows(k) {
+            let kmer = if forward_only || is_canonical_sequence(kmer) {
+                kmer
+            } else {
+                write_reverse_complement(kmer, &mut rc_kmer);
+                &rc_kmer
+            };
+            if !nodes.contains_key(kmer) {
+                let index = nodes.len() as u64;
+                nodes.insert(kmer.to_vec(), index);
+            }
+        }
+    }
+
+    // Segments in the order of the first occurrence of their kmer in the unitigs
+    let mut sorted_nodes: Vec<_> = nodes.iter().collect();
+    sorted_nodes.sort_unstable_by_key(|(_, index)| **index);
+
+    writeln!(output, "H\tVN:Z:1.0")?;
+    for (kmer, index) in sorted_nodes.iter() {
+        write!(output, "S\t{}\t", index)?;
+        output.write_all(kmer)?;
+        writeln!(output)?;
+    }
+
+    let mut links_count = 0;
+    let mut oriented_kmer = vec![];
+    let mut next_kmer = vec![];
+    for (kmer, index) in sorted_nodes {
+        for reversed in [false, true] {
+            if reversed && forward_only {
+                break;
+            }
+
+            if reversed {
+                write_reverse_complement(kmer, &mut oriented_kmer);
+            } else {
+                oriented_kmer.clone_from(kmer);
+            }
+
+            for base in BASES {
+                next_kmer.clear();
+                next_kmer.extend_from_slice(&oriented_kmer[1..]);
+                next_kmer.push(base);
+
+                let next_orientations: &[bool] = if forward_only {
+                    &[false]
+                } else {
+                    write_reverse_complement(&next_kmer, &mut rc_kmer);
+                    match next_kmer.cmp(&rc_kmer) {
+                        Ordering::Less => &[false],
+                        Ordering::Greater => {
+                            std::mem::swap(&mut next_kmer, &mut rc_kmer);
+                            &[true]
+                        }
+                        // A palindromic kmer is reached in both the orientations
+                        Ordering::Equal => &[false, true],
+                    }
+                };
+
+                let Some(next_index) = nodes.get(&next_kmer) else {
+                    continue;
+                };
+
+                for &next_reversed in next_orientations {
+                    // A link is also found from the reverse complement of its target,
+                    // write only one of the two
+                    if !forward_only && (*index, reversed) > (*next_index, !next_reversed) {
+                        continue;
+                    }
+
+                    writeln!(
+                        output,
+                        "L\t{}\t{}\t{}\t{}\t{}M",
+                        index,
+                        orientation_sign(reversed),
+                        next_index,
+                        orientation_sign(next_reversed),
+                        k - 1
+                    )?;
+                    links_count += 1;
+                }
+            }
+        }
+    }
+
+    Ok((nodes.len() as u64, links_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_kmers_graph;
+    use crate::graph_reader::{FastaUnitigs, GraphReader};
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    fn open_graph(graph: &str) -> GraphReader<FastaUnitigs<Cursor<Vec<u8>>>> {
+        GraphReader::new(Cursor::new(graph.as_bytes().to_vec()), None)
+    }
+
+    fn gfa_records<'a>(gfa: &'a str, record_type: &str) -> Vec<&'a str> {
+        gfa.lines()
+            .filter(|line| line.split('\t').next() == Some(record_type))
+            .collect()
+    }
+
+    #[test]
+    fn one_segment_for_each_distinct_kmer() {
+        // The second unitig is the reverse complement of the end of the first one
+        const GRAPH: &str = ">0 LN:i:7\nAACGTTG\n>1 LN:i:4\nCAAC\n";
+        const K: usize = 4;
+
+        let mut output = vec![];
+        let (nodes_count, links_count) =
+            write_kmers_graph(open_graph(GRAPH), K, false, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        // Canonical kmers of AACG ACGT CGTT GTTG and CAAC
+        let distinct_kmers: HashSet<_> = ["AACG", "ACGT", "AACG", "CAAC", "CAAC"]
+            .into_iter()
+            .collect();
+        assert_eq!(nodes_count, distinct_kmers.len() as u64);
+        assert_eq!(gfa_records(&output, "S").len(), distinct_kmers.len());
+
+        // AACG -> ACGT -> CGTT (AACG-) -> GTTG (CAAC-), the palindromic ACGT is
+        // entered and left in both its orientations
+        assert_eq!(
+            gfa_records(&output, "L"),
+            [
+                "L\t0\t+\t1\t+\t3M",
+                "L\t0\t+\t1\t-\t3M",
+                "L\t0\t-\t2\t-\t3M",
+            ]
+        );
+        assert_eq!(links_count, 3);
+    }
+
+    #[test]
+    fn forward_only_kmers_are_not_merged_with_their_reverse_complement() {
+        const GRAPH: &str = ">0 LN:i:5\nAACGT\n>1 LN:i:5\nACGTT\n";
+
+        let mut output = vec![];
+        let (nodes_count, links_count) =
+            write_kmers_graph(open_graph(GRAPH), 4, true, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(nodes_count, 3);
+        assert_eq!(
+            gfa_records(&output, "S"),
+            ["S\t0\tAACG", "S\t1\tACGT", "S\t2\tCGTT"]
+        );
+        assert_eq!(links_count, 2);
+    }
+}
@@ -1,4 +1,19 @@
+mod builder;
+mod color_filter;
+mod comparison;
+mod dedup;
+mod graph_metadata;
+mod graph_reader;
+mod graphs_union;
+mod input_validation;
+mod kmers_graph;
+mod memory_estimate;
+mod output_sorting;
+mod selftest;
+mod source_ids;
+mod subgraph;
 mod utils;
+mod validation;
 
 use colors::bundles::graph_querying::ColorBundleGraphQuerying;
 use colors::colors_manager::ColorsManager;
@@ -7,6 +22,7 @@ use colors::{
 };
 use hashes::MinimizerHashFunctionFactory;
 use hashes::{cn_nthash::CanonicalNtHashIteratorFactory, fw_nthash::ForwardNtHashIteratorFactory};
+use io::concurrent::structured_sequences::output_stream::rename_output;
 use io::sequences_stream::fasta::FastaFileSequencesStream;
 use io::sequences_stream::GenericSequencesStream;
 use parallel_processor::enable_counters_logging;
@@ -19,12 +35,29 @@ use std::fs::create_dir_all;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+pub use crate::builder::{BuilderError, GGCATInstanceBuilder, GGCATPipeline};
+pub use crate::comparison::{KmersComparisonReport, KmersContainmentReport};
+pub use crate::graph_metadata::{GraphMetadata, GraphMetadataMismatch};
+pub use crate::graph_reader::{
+    ColorsRun, ColorsSubsetResolver, FastaUnitigs, GraphKmer, GraphKmers, GraphLink,
+    GraphReadError, GraphReader, Unitig, UnitigsSource,
+};
+pub use crate::input_validation::{InputIssue, InputIssueKind, InputValidationReport};
+pub use crate::memory_estimate::MemoryEstimate;
+pub use crate::output_sorting::OutputSorting;
+pub use crate::selftest::SelfTestReport;
 pub use crate::utils::HashType;
-pub use config::ColorIndexType;
+pub use crate::validation::{GraphValidationError, GraphValidationReport};
+pub use assembler::AssemblerError;
+pub use config::{ColorIndexType, LogLevel, PacketsPoolsCapacities};
+pub use io::concurrent::structured_sequences::sharded::ShardedOutput;
 pub use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
 pub use io::sequences_stream::{
+    alignment::{AlignmentFileBlockData, AlignmentRecordsFilter},
+    channel::{ChannelReadsSender, ChannelSequencesStream},
     general::{DynamicSequencesStream, GeneralSequenceBlockData},
     SequenceInfo,
 };
@@ -33,6 +66,8 @@ pub use querier::ColoredQueryOutputFormat;
 pub mod debug {
     use crate::utils::HashType;
     use assembler::AssemblerStartingStep;
+    pub use config::DEBUG_LINKS_DIAGNOSTICS;
+    pub use config::DEBUG_MINIMIZERS_DUMP;
     pub use config::KEEP_FILES as DEBUG_KEEP_FILES;
     use parking_lot::Mutex;
     use querier::QuerierStartingStep;
@@ -71,11 +106,88 @@ pub struct GGCATConfig {
     /// The total threads to be used
     pub total_threads_count: usize,
 
+    /// Allow more threads than the available cores. By default the threads counts are clamped
+    /// to the available cores, to avoid thrashing the executors
+    pub allow_oversubscribe: bool,
+
     /// The default lz4 compression level for the intermediate files
     pub intermediate_compression_level: Option<u32>,
 
+    /// The gzip or lz4 compression level of the output graph, used when its path ends with .gz or .lz4
+    pub output_compression_level: Option<u32>,
+
+    /// Write a .gz output graph as BGZF blocks, with a .gzi index of the blocks and a .fai index
+    /// of the unitigs, so that single unitigs can be fetched without decompressing the whole graph
+    pub bgzf_output: bool,
+
+    /// Flush the output graph every this number of unitigs, so that a partial output
+    /// can be read while the assembly is running
+    pub output_flush_sequences: Option<u64>,
+
+    /// Flush the output graph every this number of unitigs bases
+    pub output_flush_bytes: Option<u64>,
+
     /// The path to an optional json-formatted real time stats file
     pub stats_file: Option<PathBuf>,
+
+    /// Seed used to randomize the minimizers hashes, to avoid skewed buckets on pathological inputs
+    /// A value of 0 keeps the default nthash values
+    pub minimizer_hash_seed: u64,
+
+    /// Run id prepended to the temporary files names, so that concurrent runs can share the
+    /// temporary directories. None generates a random one, a resumed checkpointed run
+    /// must use the same run id of the interrupted one
+    pub temp_files_prefix: Option<String>,
+
+    /// Additional directories for temporary files (for example on different disks),
+    /// the temporary buckets are spread across them and temp_dir
+    pub extra_temp_dirs: Vec<PathBuf>,
+
+    /// Read the soft-masked (lowercase) bases of the inputs as N, splitting the sequences
+    /// at the masked regions instead of keeping them
+    pub split_on_softmask: bool,
+
+    /// Pack the reads of the reference with 4 bits per base instead of 2, keeping their IUPAC
+    /// ambiguity codes instead of splitting them. Only the kmers without ambiguous bases are hashed
+    pub iupac_reads_packing: bool,
+
+    /// Read the FASTQ inputs as interleaved paired-end reads (R1, R2, R1, R2, ...), checking that
+    /// each two consecutive records are mates and giving them the same pair id
+    pub interleaved_paired_reads: bool,
+
+    /// Store the second level bucket of each read in the minimizer buckets, to avoid recomputing
+    /// the minimizers when splitting the buckets, at the cost of one byte per read on disk
+    pub use_second_bucket: bool,
+
+    /// Number of reads in each packet passed from the bucket readers to the k-mers processors
+    /// Smaller packets lower the memory used by the packets in flight, larger ones improve
+    /// the throughput by reducing the packets scheduling overhead. None selects it from k
+    pub reads_buffer_capacity: Option<usize>,
+
+    /// Bytes buffered by each thread for each bucket of the links written by the links compaction.
+    /// Each thread holds such a buffer for every bucket: smaller buffers reduce the memory usage
+    /// with many buckets and threads, larger ones are flushed less often. None keeps 4KB
+    pub links_compaction_links_buffer: Option<usize>,
+
+    /// Bytes buffered by each thread for each bucket of the unitigs mappings written by the links
+    /// compaction. None keeps 4KB
+    pub links_compaction_results_buffer: Option<usize>,
+
+    /// Capacities of the packets pools of the pipeline executors, to give for example more
+    /// buffers to the readers than to the downstream processors. The defaults depend on the threads count
+    pub packets_pools_capacities: PacketsPoolsCapacities,
+
+    /// Sync the checkpointed buckets to disk before recording a checkpoint, so that a completed
+    /// stage survives a crash. Off by default, as syncing slows down the checkpoints
+    pub sync_checkpoints: bool,
+
+    /// Store a checksum of each checkpointed bucket, to detect the buckets corrupted on disk when
+    /// resuming from a checkpoint. Off by default, as computing the checksums reads back the buckets
+    pub checkpoint_checksums: bool,
+
+    /// Verbosity of the messages printed while building and querying the graphs. Ignored if the
+    /// application installed its own logger, that keeps its own maximum level
+    pub log_level: LogLevel,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -89,6 +201,113 @@ pub enum ExtraElaboration {
     Eulertigs,
     /// Generate pathtigs
     Pathtigs,
+    /// Generate maximal unitigs with their connections, in SPAdes FASTG format
+    FastgLinks,
+}
+
+/// The parameters of GGCATInstance::build_graph. The defaults are the ones of the command line
+#[derive(Clone, Debug)]
+pub struct BuildOptions {
+    /// Specifies the k-mers length
+    pub kmer_length: usize,
+    /// The threads to be used
+    pub threads_count: usize,
+    /// Treats reverse complementary kmers as different
+    pub forward_only: bool,
+    /// Overrides the default m-mers (minimizers) length
+    pub minimizer_length: Option<usize>,
+
+    /// Enable colors
+    pub colors: bool,
+
+    /// Minimum multiplicity required to keep a kmer
+    pub min_multiplicity: usize,
+
+    pub extra_elab: ExtraElaboration,
+
+    /// Splits the unitigs longer than this value in pieces overlapping by k - 1 bases.
+    /// Must be at least k, not supported with colors
+    pub max_unitig_length: Option<usize>,
+
+    /// Debug output of the partial unitigs composing each final unitig
+    pub output_unitigs_paths: bool,
+
+    /// Saturates the kmers counts at this value, the kmers are kept anyway
+    pub max_kmer_count: Option<usize>,
+
+    /// Splits the output in this number of shards, returned instead of the output file
+    /// if more than one shard is requested
+    pub output_shards: usize,
+
+    /// Assigns a separate color to each input, named after its file (ignoring color_names),
+    /// overriding the colors provided by the input streams
+    pub color_by_file: bool,
+
+    /// Writes each unitig in canonical orientation (the minimum with its reverse complement),
+    /// flipping the links accordingly. In forward-only graphs each unitig is tagged with ST:A:-
+    /// if it was flipped, ST:A:+ otherwise. Without the canonical output the unitigs already
+    /// follow the strand of the inputs, and they are all tagged with ST:A:+
+    pub canonical_output: bool,
+
+    /// Fails if the links compaction has not converged after this number of iterations
+    pub max_compaction_iterations: Option<usize>,
+
+    /// Dumps the unitigs completed in each links compaction round next to the output file,
+    /// for debugging the compaction convergence
+    pub dump_compaction_rounds: bool,
+
+    /// Checkpoints each links compaction round, allowing to resume the compaction
+    /// after the last completed round. The temporary files of a failed run are kept,
+    /// and found again by the run id (temp_files_prefix) and the output file name
+    pub checkpoint_compaction: bool,
+
+    /// BED file of intervals of the input sequences that are masked with N before the bucketing,
+    /// splitting the reads at the masked regions
+    pub masking_bed: Option<PathBuf>,
+
+    /// FASTA/FASTQ reference whose kmers are kept with the lower reference_min_multiplicity,
+    /// to recover the low coverage regions supported by the reference
+    pub reference: Option<PathBuf>,
+
+    /// Minimum multiplicity required to keep a kmer of the reference
+    pub reference_min_multiplicity: usize,
+
+    /// Positive weight of each input stream, up to config::MAX_INPUT_WEIGHT, the number of times
+    /// each of its kmers is counted towards the minimum multiplicity. None (or a weight of 1 for
+    /// all the inputs) counts each kmer once
+    pub input_weights: Option<Vec<usize>>,
+
+    /// Also writes the unitigs of each color to <graph name>.<color name>.fa in the directory
+    /// of the output file, while the graph is written. Only for colored graphs
+    pub split_output_by_color: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            kmer_length: 31,
+            threads_count: 16,
+            forward_only: false,
+            minimizer_length: None,
+            colors: false,
+            min_multiplicity: 2,
+            extra_elab: ExtraElaboration::None,
+            max_unitig_length: None,
+            output_unitigs_paths: false,
+            max_kmer_count: None,
+            output_shards: 1,
+            color_by_file: false,
+            canonical_output: false,
+            max_compaction_iterations: None,
+            dump_compaction_rounds: false,
+            checkpoint_compaction: false,
+            masking_bed: None,
+            reference: None,
+            reference_min_multiplicity: 1,
+            input_weights: None,
+            split_output_by_color: false,
+        }
+    }
 }
 
 static INSTANCE: Mutex<Option<&'static GGCATInstance>> = Mutex::new(None);
@@ -96,8 +315,12 @@ static INSTANCE: Mutex<Option<&'static GGCATInstance>> = Mutex::new(None);
 pub struct GGCATInstance(GGCATConfig);
 
 fn create_tempdir(base_path: Option<PathBuf>) -> Option<PathBuf> {
+    create_named_tempdir(base_path, &uuid::Uuid::new_v4().to_string())
+}
+
+fn create_named_tempdir(base_path: Option<PathBuf>, name: &str) -> Option<PathBuf> {
     base_path.map(|t| {
-        let temp_dir = t.join(&format!("build_graph_{}", uuid::Uuid::new_v4()));
+        let temp_dir = t.join(&format!("build_graph_{}", name));
         let _ = create_dir_all(&temp_dir);
         temp_dir
     })
@@ -105,15 +328,53 @@ fn create_tempdir(base_path: Option<PathBuf>) -> Option<PathBuf> {
 
 fn remove_tempdir(temp_dir: Option<PathBuf>) {
     if let Some(temp_dir) = temp_dir {
+        if let Some(run_dir_name) = temp_dir.file_name() {
+            for extra_dir in config::EXTRA_TEMP_DIRS.read().unwrap().iter() {
+                let _ = std::fs::remove_dir_all(extra_dir.join(run_dir_name));
+            }
+        }
         let _ = std::fs::remove_dir_all(temp_dir);
     }
 }
 
+/// Adds the action and the path of the graph to an IO error of a pass rewriting a built graph
+fn graph_io_error(action: &str, graph: &Path, error: std::io::Error) -> std::io::Error {
+    std::io::Error::new(
+        error.kind(),
+        format!("Cannot {} the graph {}: {}", action, graph.display(), error),
+    )
+}
+
+/// Opens the graph and the inputs of a pass rewriting a built graph, and creates its output file.
+/// The sequences readers and writers panic on these errors, so they are checked before starting
+fn check_graph_rewrite_files(
+    action: &str,
+    graph: &Path,
+    inputs: &[PathBuf],
+    output_file: &Path,
+) -> std::io::Result<()> {
+    std::fs::File::open(graph).map_err(|error| graph_io_error(action, graph, error))?;
+    for input in inputs {
+        std::fs::File::open(input).map_err(|error| {
+            graph_io_error(
+                action,
+                graph,
+                std::io::Error::new(
+                    error.kind(),
+                    format!("cannot open the input {}: {}", input.display(), error),
+                ),
+            )
+        })?;
+    }
+    std::fs::File::create(output_file).map_err(|error| graph_io_error(action, graph, error))?;
+    Ok(())
+}
+
 /// Main GGCAT struct. It's a singleton and can be create by passing a GGCATConfig.
 /// Successive calls to create will return the same instance, ignoring the new configuration.
 impl GGCATInstance {
     /// Creates a new GGCATInstance. If an instance already exists, it will be returned, ignoring the new config.
-    pub fn create(config: GGCATConfig) -> &'static Self {
+    pub fn create(mut config: GGCATConfig) -> &'static Self {
         let mut instance = INSTANCE.lock();
 
         if let Some(instance) = instance.deref() {
@@ -124,12 +385,68 @@ impl GGCATInstance {
         fdlimit::raise_fd_limit();
 
         config::PREFER_MEMORY.store(config.prefer_memory, Ordering::Relaxed);
+        if let Some(output_compression_level) = config.output_compression_level {
+            config::OUTPUT_COMPRESSION_LEVEL.store(output_compression_level, Ordering::Relaxed);
+        }
+        config::OUTPUT_BGZF.store(config.bgzf_output, Ordering::Relaxed);
+        config::OUTPUT_FLUSH_SEQUENCES.store(
+            config.output_flush_sequences.unwrap_or(0),
+            Ordering::Relaxed,
+        );
+        config::OUTPUT_FLUSH_BYTES.store(config.output_flush_bytes.unwrap_or(0), Ordering::Relaxed);
+        config::MINIMIZER_HASH_SEED.store(config.minimizer_hash_seed, Ordering::Relaxed);
+        config::SPLIT_ON_SOFTMASK.store(config.split_on_softmask, Ordering::Relaxed);
+        config::IUPAC_READS_PACKING.store(config.iupac_reads_packing, Ordering::Relaxed);
+        config::INTERLEAVED_PAIRED_READS.store(config.interleaved_paired_reads, Ordering::Relaxed);
+        config::USE_SECOND_BUCKET.store(config.use_second_bucket, Ordering::Relaxed);
+        config::SYNC_CHECKPOINTS.store(config.sync_checkpoints, Ordering::Relaxed);
+        config::CHECKPOINT_CHECKSUMS.store(config.checkpoint_checksums, Ordering::Relaxed);
+        config::KMERS_TRANSFORM_READS_BUFFER_CAPACITY
+            .store(config.reads_buffer_capacity.unwrap_or(0), Ordering::Relaxed);
+        config::LINKS_COMPACTION_LINKS_BUFFER_SIZE.store(
+            config.links_compaction_links_buffer.unwrap_or(0),
+            Ordering::Relaxed,
+        );
+        config::LINKS_COMPACTION_RESULTS_BUFFER_SIZE.store(
+            config.links_compaction_results_buffer.unwrap_or(0),
+            Ordering::Relaxed,
+        );
+        *config::PACKETS_POOLS_CAPACITIES.write().unwrap() = config.packets_pools_capacities;
+        config::init_logging(config.log_level);
+
+        config::ALLOW_OVERSUBSCRIBE.store(config.allow_oversubscribe, Ordering::Relaxed);
+        config.total_threads_count = config::effective_threads_count(config.total_threads_count);
 
-        rayon::ThreadPoolBuilder::new()
+        let thread_pool = rayon::ThreadPoolBuilder::new()
             .num_threads(config.total_threads_count)
-            .thread_name(|i| format!("rayon-thread-{}", i))
-            .build_global()
-            .unwrap();
+            .thread_name(|i| format!("rayon-thread-{}", i));
+
+        // Pin the threads to the NUMA nodes, so that the memory of the buckets processed by a thread
+        // is allocated on its node. Only the rayon threads are pinned, not the executors ones
+        #[cfg(feature = "numa")]
+        let thread_pool = match ::utils::numa::NumaTopology::detect() {
+            Some(topology) => {
+                log::info!(
+                    "Pinning the threads to {} NUMA nodes",
+                    topology.nodes_count()
+                );
+                let threads_count = config.total_threads_count;
+                thread_pool.start_handler(move |thread_index| {
+                    let node = topology.thread_node(thread_index, threads_count);
+                    if let Err(err) = topology.pin_current_thread(node) {
+                        log::warn!(
+                            "Cannot pin the thread {} to the NUMA node {}: {}",
+                            thread_index,
+                            node,
+                            err
+                        );
+                    }
+                })
+            }
+            None => thread_pool,
+        };
+
+        thread_pool.build_global().unwrap();
 
         if let Some(temp_dir) = &config.temp_dir {
             create_dir_all(temp_dir).unwrap();
@@ -137,6 +454,18 @@ impl GGCATInstance {
             todo!("Force memory-only usage")
         }
 
+        config::set_temp_files_prefix(
+            &config
+                .temp_files_prefix
+                .clone()
+                .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string()[..8].to_string()),
+        );
+
+        for extra_temp_dir in &config.extra_temp_dirs {
+            create_dir_all(extra_temp_dir).unwrap();
+        }
+        *config::EXTRA_TEMP_DIRS.write().unwrap() = config.extra_temp_dirs.clone();
+
         if let Some(stats_file) = &config.stats_file {
             enable_counters_logging(stats_file, Duration::from_millis(1000), |val| {
                 val["phase"] = PHASES_TIMES_MONITOR.read().get_phase_desc().into();
@@ -167,23 +496,34 @@ impl GGCATInstance {
         // The names of the colors, ordered by color index
         color_names: Option<&[String]>,
 
-        // Specifies the k-mers length
-        kmer_length: usize,
-        // The threads to be used
-        threads_count: usize,
-        // Treats reverse complementary kmers as different
-        forward_only: bool,
-        // Overrides the default m-mers (minimizers) length
-        minimizer_length: Option<usize>,
-
-        // Enable colors
-        colors: bool,
-
-        // Minimum multiplicity required to keep a kmer
-        min_multiplicity: usize,
+        // The parameters of the build
+        options: BuildOptions,
+    ) -> Result<ShardedOutput, AssemblerError> {
+        let BuildOptions {
+            kmer_length,
+            threads_count,
+            forward_only,
+            minimizer_length,
+            colors,
+            min_multiplicity,
+            extra_elab,
+            max_unitig_length,
+            output_unitigs_paths,
+            max_kmer_count,
+            output_shards,
+            color_by_file,
+            canonical_output,
+            max_compaction_iterations,
+            dump_compaction_rounds,
+            checkpoint_compaction,
+            masking_bed,
+            reference,
+            reference_min_multiplicity,
+            input_weights,
+            split_output_by_color,
+        } = options;
+        let threads_count = config::effective_threads_count(threads_count);
 
-        extra_elab: ExtraElaboration,
-    ) -> PathBuf {
         let bucketing_hash_dispatch = if forward_only {
             <ForwardNtHashIteratorFactory as MinimizerHashFunctionFactory>::DYNAMIC_DISPATCH_ID
         } else {
@@ -196,13 +536,37 @@ impl GGCATInstance {
             forward_only,
         );
 
-        let colors_hash = if colors {
+        let files_color_names: Vec<_>;
+        let color_names = if color_by_file {
+            files_color_names = input_streams
+                .iter()
+                .enumerate()
+                .map(|(i, input)| input.get_file_color_name(i))
+                .collect();
+            Some(files_color_names.as_slice())
+        } else {
+            color_names
+        };
+
+        let colors_hash = if colors || color_by_file {
             ColorBundleMultifileBuilding::DYNAMIC_DISPATCH_ID
         } else {
             NonColoredManager::DYNAMIC_DISPATCH_ID
         };
 
-        let temp_dir = create_tempdir(self.0.temp_dir.clone());
+        // A checkpointed run uses a temporary directory named after the run id and the output file,
+        // to find again its checkpoints when resuming the links compaction
+        let temp_dir = if checkpoint_compaction {
+            create_named_tempdir(
+                self.0.temp_dir.clone(),
+                &config::get_temp_file_path(PathBuf::from(
+                    output_file.file_name().unwrap_or_default(),
+                ))
+                .to_string_lossy(),
+            )
+        } else {
+            create_tempdir(self.0.temp_dir.clone())
+        };
 
         let output_file = assembler::dynamic_dispatch::run_assembler(
             (bucketing_hash_dispatch, merging_hash_dispatch, colors_hash),
@@ -215,23 +579,61 @@ impl GGCATInstance {
             output_file,
             temp_dir.clone(),
             threads_count,
-            min_multiplicity,
-            *debug::BUCKETS_COUNT_LOG_FORCE.lock(),
-            Some(debug::DEBUG_LINK_PHASE_ITERATION_START_STEP.load(Ordering::Relaxed)),
-            self.0.intermediate_compression_level,
-            extra_elab == ExtraElaboration::UnitigLinks,
-            match extra_elab {
-                ExtraElaboration::GreedyMatchtigs => Some(assembler::MatchtigMode::GreedyTigs),
-                ExtraElaboration::Eulertigs => Some(assembler::MatchtigMode::EulerTigs),
-                ExtraElaboration::Pathtigs => Some(assembler::MatchtigMode::PathTigs),
-                _ => None,
+            assembler::AssemblerOptions {
+                min_multiplicity,
+                buckets_count_log: *debug::BUCKETS_COUNT_LOG_FORCE.lock(),
+                loopit_number: Some(
+                    debug::DEBUG_LINK_PHASE_ITERATION_START_STEP.load(Ordering::Relaxed),
+                ),
+                default_compression_level: self.0.intermediate_compression_level,
+                generate_maximal_unitigs_links: extra_elab == ExtraElaboration::UnitigLinks
+                    || extra_elab == ExtraElaboration::FastgLinks,
+                compute_tigs_mode: match extra_elab {
+                    ExtraElaboration::GreedyMatchtigs => Some(assembler::MatchtigMode::GreedyTigs),
+                    ExtraElaboration::Eulertigs => Some(assembler::MatchtigMode::EulerTigs),
+                    ExtraElaboration::Pathtigs => Some(assembler::MatchtigMode::PathTigs),
+                    _ => None,
+                },
+                only_bstats: debug::DEBUG_ONLY_BSTATS.load(Ordering::Relaxed),
+                max_unitig_length,
+                output_unitigs_paths,
+                max_kmer_count,
+                output_shards,
+                color_by_file,
+                canonical_output,
+                max_compaction_iterations,
+                dump_compaction_rounds,
+                checkpoint_compaction,
+                fastg_output: extra_elab == ExtraElaboration::FastgLinks,
+                strand_tags: forward_only,
+                masking_bed,
+                reference,
+                reference_min_multiplicity,
+                input_weights,
+                split_output_by_color,
             },
-            debug::DEBUG_ONLY_BSTATS.load(Ordering::Relaxed),
         );
 
-        remove_tempdir(temp_dir);
+        // The checkpoints of a failed run are kept, to resume it
+        if output_file.is_ok() || !checkpoint_compaction {
+            remove_tempdir(temp_dir);
+        }
+        let output = output_file?;
 
-        output_file
+        // Each shard is a standalone graph, with its own metadata
+        for graph_file in output.paths() {
+            Self::current_graph_metadata(kmer_length, minimizer_length, forward_only)
+                .write(Self::get_metadata_file(graph_file))
+                .unwrap_or_else(|err| {
+                    panic!(
+                        "Cannot write the metadata of the graph {}: {}",
+                        graph_file.display(),
+                        err
+                    )
+                });
+        }
+
+        Ok(output)
     }
 
     /// Queries a (optionally) colored graph with a specific set of sequences as queries
@@ -259,7 +661,24 @@ impl GGCATInstance {
 
         // Query output format
         color_output_format: ColoredQueryOutputFormat,
-    ) -> PathBuf {
+
+        // Also match the reverse complement of the queries, on forward only graphs
+        query_both_strands: bool,
+
+        // Expands the query kmers with degenerate (IUPAC) bases into their concrete instantiations,
+        // skipping the kmers with more than the given number of them
+        max_degenerate_expansions: Option<usize>,
+
+        // Appends the results to the existing output file as a new batch, instead of replacing it
+        append_output: bool,
+
+        // Reports only the colors found in at least this number of matched kmers of each query
+        color_quorum: Option<usize>,
+    ) -> Result<PathBuf, GraphMetadataMismatch> {
+        let threads_count = config::effective_threads_count(threads_count);
+
+        Self::check_graph_metadata(&input_graph, kmer_length, minimizer_length, forward_only)?;
+
         let bucketing_hash_dispatch = if forward_only {
             <ForwardNtHashIteratorFactory as MinimizerHashFunctionFactory>::DYNAMIC_DISPATCH_ID
         } else {
@@ -272,6 +691,25 @@ impl GGCATInstance {
             forward_only,
         );
 
+        // An uncolored graph has no colormap, in that case the colors output is omitted
+        let colors = if colors && !Self::get_colormap_file(&input_graph).exists() {
+            log::warn!(
+                "The graph {} has no colormap, querying it without colors",
+                input_graph.display()
+            );
+            false
+        } else {
+            colors
+        };
+
+        // Canonical graphs already match both the strands of the queries
+        let query_both_strands = if query_both_strands && !forward_only {
+            log::info!("The graph is canonical, querying both strands has no effect");
+            false
+        } else {
+            query_both_strands
+        };
+
         let colors_hash = if colors {
             ColorBundleGraphQuerying::DYNAMIC_DISPATCH_ID
         } else {
@@ -293,11 +731,15 @@ impl GGCATInstance {
             threads_count,
             self.0.intermediate_compression_level,
             color_output_format,
+            query_both_strands,
+            max_degenerate_expansions,
+            append_output,
+            color_quorum,
         );
 
         remove_tempdir(temp_dir);
 
-        output_file
+        Ok(output_file)
     }
 
     /// Obtains the standard colormap file path from a graph file path
@@ -305,6 +747,44 @@ impl GGCATInstance {
         graph_file.as_ref().with_extension("colors.dat")
     }
 
+    /// Obtains the path of the sidecar storing the parameters the graph was built with
+    pub fn get_metadata_file(graph_file: impl AsRef<Path>) -> PathBuf {
+        graph_file.as_ref().with_extension("meta")
+    }
+
+    fn current_graph_metadata(
+        kmer_length: usize,
+        minimizer_length: Option<usize>,
+        forward_only: bool,
+    ) -> GraphMetadata {
+        GraphMetadata {
+            kmer_length,
+            minimizer_length: minimizer_length.unwrap_or(::utils::compute_best_m(kmer_length)),
+            hash_type: utils::resolve_hash_type(debug::DEBUG_HASH_TYPE.lock().clone(), kmer_length)
+                .name()
+                .to_string(),
+            forward_only,
+        }
+    }
+
+    /// Checks that the graph was built with the same k and strands policy used to query it.
+    /// Graphs without metadata (built by older versions or other tools) are not checked
+    pub fn check_graph_metadata(
+        graph: impl AsRef<Path>,
+        kmer_length: usize,
+        minimizer_length: Option<usize>,
+        forward_only: bool,
+    ) -> Result<(), GraphMetadataMismatch> {
+        match GraphMetadata::read(Self::get_metadata_file(&graph)) {
+            Some(metadata) => metadata.check_query(&Self::current_graph_metadata(
+                kmer_length,
+                minimizer_length,
+                forward_only,
+            )),
+            None => Ok(()),
+        }
+    }
+
     /// Returns an iterator over the color names in the given graph.
     /// The color indexes returned from the dump_unitigs function
     /// can be used to index this (collected) iterator.
@@ -326,50 +806,2089 @@ impl GGCATInstance {
         })
     }
 
-    /// Dumps the unitigs of the given graph, optionally with colors
-    /// It's not guaranteed that maximal unitigs are returned, as only kmers with the same colors subset
-    /// are returned as whole unitigs to speedup colormap reading times
-    pub fn dump_unitigs(
-        &self,
-        graph_input: PathBuf,
-        // Specifies the k-mers length
-        kmer_length: usize,
-        // Overrides the default m-mers (minimizers) length
-        minimizer_length: Option<usize>,
-        colors: bool,
-        // The threads to be used
-        threads_count: usize,
+    /// Writes the colors dictionary of a colormap to a colors.tsv sidecar next to it, returning its path.
+    /// Each color is listed as color<TAB>index<TAB>name and each colors subset (the color ids
+    /// found in the graph) as subset<TAB>index<TAB>comma separated color indices
+    pub fn export_colors_dictionary(
+        // The input colormap
+        input_colormap: impl AsRef<Path>,
+    ) -> PathBuf {
+        use colors::storage::deserializer::ColorsDeserializer;
+        use colors::DefaultColorsSerializer;
 
-        // Call the output function from a single thread at a time,
-        // avoiding the need for synchronization in the user code
-        single_thread_output_function: bool,
-        output_function: impl Fn(&[u8], &[ColorIndexType], bool) + Send + Sync,
-    ) {
-        let temp_dir = create_tempdir(self.0.temp_dir.clone());
+        ColorsDeserializer::<DefaultColorsSerializer>::export_dictionary(&input_colormap)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "Cannot write the colors dictionary of {}: {}",
+                    input_colormap.as_ref().display(),
+                    err
+                )
+            })
+    }
 
-        if colors {
-            dumper::dump_unitigs(
-                kmer_length,
-                minimizer_length.unwrap_or(::utils::compute_best_m(kmer_length)),
-                graph_input,
-                temp_dir.clone(),
-                *debug::BUCKETS_COUNT_LOG_FORCE.lock(),
-                threads_count,
-                single_thread_output_function,
-                self.0.intermediate_compression_level,
-                output_function,
+    /// Writes to output_file only the unitigs of a colored graph that contain the given color in at
+    /// least one of their kmers, optionally keeping the links between them. The graph is streamed
+    /// twice, keeping in memory only the indexes of the selected unitigs, and the colormap is copied
+    /// next to the output so that the colors subsets remain valid
+    pub fn filter_graph_by_color(
+        // The input graph
+        graph_input: PathBuf,
+        // Name of the color to be kept
+        color_name: &str,
+        output_file: PathBuf,
+        keep_links: bool,
+    ) -> PathBuf {
+        let colormap_file = Self::get_colormap_file(&graph_input);
+        if !colormap_file.exists() {
+            panic!(
+                "Cannot filter the graph {} by color, its colormap {} does not exist",
+                graph_input.display(),
+                colormap_file.display()
             );
-        } else {
-            FastaFileSequencesStream::new().read_block(
-                &graph_input,
-                false,
-                Some(kmer_length - 1),
-                |seq, _info| {
-                    output_function(seq.ident_data, &[], false);
-                },
+        }
+
+        let color = Self::dump_colors(&colormap_file)
+            .position(|name| name == color_name)
+            .unwrap_or_else(|| panic!("Color {} not found in the graph", color_name))
+            as ColorIndexType;
+
+        let kept_unitigs = color_filter::unitigs_with_color(GraphReader::open(&graph_input), color)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "Cannot filter the graph {} by color: {}",
+                    graph_input.display(),
+                    err
+                )
+            });
+
+        let output = std::io::BufWriter::new(std::fs::File::create(&output_file).unwrap());
+        let written_unitigs = color_filter::write_filtered_unitigs(
+            std::io::BufReader::new(io::lines_reader::open_decompressed_file(&graph_input)),
+            &kept_unitigs,
+            keep_links,
+            output,
+        )
+        .unwrap();
+        std::fs::copy(&colormap_file, Self::get_colormap_file(&output_file)).unwrap();
+
+        log::info!(
+            "Written {} unitigs with color {} to {}",
+            written_unitigs,
+            color_name,
+            output_file.display()
+        );
+
+        output_file
+    }
+
+    /// Drops the unitigs of a built graph that repeat the sequence (in any orientation) of
+    /// a previous unitig, replacing its file. The colors and links of the dropped unitigs are
+    /// merged into the kept one, adding the new colors subsets to the colormap if needed,
+    /// and the links to them are moved to the kept one. Returns the graph and the number of
+    /// dropped unitigs
+    pub fn dedup_graph_output(&self, graph: PathBuf) -> std::io::Result<(PathBuf, u64)> {
+        use colors::colors_memmap_writer::ColorsMemMapWriter;
+        use colors::DefaultColorsSerializer;
+
+        // The graph reader panics if the graph cannot be opened
+        std::fs::File::open(&graph)
+            .map_err(|error| graph_io_error("deduplicate", &graph, error))?;
+
+        let mut duplicates = dedup::DuplicateUnitigs::find(|| GraphReader::open(&graph))
+            .map_err(|error| graph_io_error("deduplicate", &graph, error.into()))?;
+        if duplicates.dropped.is_empty() {
+            return Ok((graph, 0));
+        }
+
+        // Same extension as the graph, to keep its compression
+        let deduplicated_file = graph.with_extension(format!(
+            "dedup.{}",
+            graph.extension().unwrap_or_default().to_string_lossy()
+        ));
+        check_graph_rewrite_files("deduplicate", &graph, &[], &deduplicated_file)?;
+
+        let colormap_file = Self::get_colormap_file(&graph);
+        // Loaded only if the merged colors need new subsets
+        let mut colors_writer = None;
+        duplicates
+            .assign_colors_subsets(GraphReader::open(&graph), |colors| {
+                colors_writer
+                    .get_or_insert_with(|| {
+                        ColorsMemMapWriter::<DefaultColorsSerializer>::reopen_append(
+                            &colormap_file,
+                            &[],
+                        )
+                    })
+                    .get_id(colors) as u64
+            })
+            .map_err(|error| graph_io_error("deduplicate", &graph, error.into()))?;
+        drop(colors_writer);
+
+        let dropped_unitigs = dedup::write_deduplicated_unitigs(
+            std::io::BufReader::new(io::lines_reader::open_decompressed_file(&graph)),
+            &duplicates,
+            output_sorting::SortedGraphWriter::new(&deduplicated_file),
+        )
+        .map_err(|error| graph_io_error("deduplicate", &graph, error))?;
+        rename_output(&deduplicated_file, &graph)
+            .map_err(|error| graph_io_error("replace", &graph, error))?;
+
+        log::warn!(
+            "Dropped {} duplicate unitigs from {}",
+            dropped_unitigs,
+            graph.display()
+        );
+
+        Ok((graph, dropped_unitigs))
+    }
+
+    /// Drops the links of a built colored graph between unitigs that do not share any color,
+    /// replacing its file, so that each color forms a separate subgraph. The colors of all the
+    /// unitigs are kept in memory. Returns the graph and the number of dropped links
+    pub fn filter_graph_intra_color_links(&self, graph: PathBuf) -> (PathBuf, u64) {
+        let colormap_file = Self::get_colormap_file(&graph);
+        if !colormap_file.exists() {
+            panic!(
+                "Cannot filter the links of the graph {} by color, its colormap {} does not exist",
+                graph.display(),
+                colormap_file.display()
             );
         }
 
-        remove_tempdir(temp_dir);
+        let unitigs_colors = color_filter::unitigs_colors(GraphReader::open(&graph))
+            .unwrap_or_else(|err| {
+                panic!(
+                    "Cannot filter the links of the graph {}: {}",
+                    graph.display(),
+                    err
+                )
+            });
+
+        // Same extension as the graph, to keep its compression
+        let filtered_file = graph.with_extension(format!(
+            "links.{}",
+            graph.extension().unwrap_or_default().to_string_lossy()
+        ));
+        let dropped_links = color_filter::write_intra_color_links(
+            std::io::BufReader::new(io::lines_reader::open_decompressed_file(&graph)),
+            &unitigs_colors,
+            output_sorting::SortedGraphWriter::new(&filtered_file),
+        )
+        .unwrap_or_else(|err| {
+            panic!(
+                "Cannot filter the links of the graph {}: {}",
+                graph.display(),
+                err
+            )
+        });
+        rename_output(&filtered_file, &graph).unwrap();
+
+        log::info!(
+            "Dropped {} links between unitigs of different colors from {}",
+            dropped_links,
+            graph.display()
+        );
+
+        (graph, dropped_links)
+    }
+
+    /// Reorders the unitigs of a built graph with the given sorting, replacing its file. The unitigs
+    /// are sorted in runs of at most the configured memory and merged from temporary files,
+    /// so the graph does not need to fit in memory. Their indexes and links are not changed
+    pub fn sort_graph_output(
+        &self,
+        graph: PathBuf,
+        sorting: OutputSorting,
+    ) -> std::io::Result<PathBuf> {
+        if sorting == OutputSorting::None {
+            return Ok(graph);
+        }
+
+        // Same extension as the graph, to keep its compression
+        let sorted_file = graph.with_extension(format!(
+            "sorted.{}",
+            graph.extension().unwrap_or_default().to_string_lossy()
+        ));
+        check_graph_rewrite_files("sort", &graph, &[], &sorted_file)?;
+
+        let temp_dir = create_tempdir(self.0.temp_dir.clone()).ok_or_else(|| {
+            graph_io_error(
+                "sort",
+                &graph,
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "a temporary directory is needed to sort the graph output",
+                ),
+            )
+        })?;
+        let max_run_bytes = max(
+            MemoryDataSize::OCTET_GIBIOCTET_FACTOR as usize / 4,
+            (self.0.memory * (MemoryDataSize::OCTET_GIBIOCTET_FACTOR as f64)) as usize,
+        );
+
+        let sorted_unitigs = match sorting {
+            OutputSorting::None => unreachable!(),
+            OutputSorting::Length => output_sorting::sort_records_by_length(
+                std::io::BufReader::new(io::lines_reader::open_decompressed_file(&graph)),
+                output_sorting::SortedGraphWriter::new(&sorted_file),
+                &temp_dir,
+                max_run_bytes,
+            ),
+        };
+        remove_tempdir(Some(temp_dir));
+        let sorted_unitigs =
+            sorted_unitigs.map_err(|error| graph_io_error("sort", &graph, error))?;
+
+        rename_output(&sorted_file, &graph)
+            .map_err(|error| graph_io_error("replace", &graph, error))?;
+
+        log::info!(
+            "Sorted {} unitigs of {} by {:?}",
+            sorted_unitigs,
+            graph.display(),
+            sorting
+        );
+
+        Ok(graph)
+    }
+
+    /// Number of partitions of the kmers of the graph, each one kept in memory with the index
+    /// of its unitig, needed to fit them in the configured memory. The size of the graph file
+    /// is an upper bound of its kmers count
+    fn graph_kmers_partitions_count(&self, graph: &Path) -> usize {
+        let graph_size = std::fs::metadata(graph).map(|m| m.len()).unwrap_or(0);
+        let memory_bytes = max(
+            1,
+            (self.0.memory * (MemoryDataSize::OCTET_GIBIOCTET_FACTOR as f64)) as u64,
+        );
+        (graph_size * source_ids::SOURCE_IDS_BYTES_PER_KMER).div_ceil(memory_bytes) as usize
+    }
+
+    /// Adds to the header of each unitig of a built graph the names (the first word of the header)
+    /// of the input records sharing a kmer with it, as SI:Z:<name>[,<name>]*, replacing its file.
+    /// At most max_ids_per_unitig (default 16) names are kept for each unitig, the ones of the
+    /// first records, followed by ... if some were dropped. This is a pass after the build, that
+    /// reads again the graph and all the inputs once for each partition of the graph kmers needed
+    /// to fit them in the configured memory, so it can take longer than the build itself
+    pub fn annotate_graph_source_ids(
+        &self,
+        graph: PathBuf,
+        // The FASTA/FASTQ inputs the graph was built from
+        inputs: &[PathBuf],
+        // Specifies the k-mers length
+        kmer_length: usize,
+        max_ids_per_unitig: Option<usize>,
+    ) -> std::io::Result<PathBuf> {
+        // Same extension as the graph, to keep its compression
+        let annotated_file = graph.with_extension(format!(
+            "annotated.{}",
+            graph.extension().unwrap_or_default().to_string_lossy()
+        ));
+        check_graph_rewrite_files("annotate", &graph, inputs, &annotated_file)?;
+
+        let partitions_count = self.graph_kmers_partitions_count(&graph);
+
+        let source_ids = source_ids::collect_source_ids(
+            |callback: &mut dyn FnMut(&[u8])| {
+                FastaFileSequencesStream::new()
+                    .read_block(&graph, false, None, |seq, _info| callback(seq.seq));
+            },
+            |callback: &mut dyn FnMut(&[u8], &[u8])| {
+                let mut stream = FastaFileSequencesStream::new();
+                for input in inputs {
+                    stream.read_block(input, true, None, |seq, _info| {
+                        callback(seq.ident_data, seq.seq)
+                    });
+                }
+            },
+            kmer_length,
+            partitions_count,
+            max_ids_per_unitig.unwrap_or(source_ids::MAX_SOURCE_IDS_PER_UNITIG),
+        );
+
+        let annotated_unitigs = source_ids::write_source_ids(
+            std::io::BufReader::new(io::lines_reader::open_decompressed_file(&graph)),
+            output_sorting::SortedGraphWriter::new(&annotated_file),
+            &source_ids,
+        )
+        .map_err(|error| graph_io_error("write the source ids of", &graph, error))?;
+        rename_output(&annotated_file, &graph)
+            .map_err(|error| graph_io_error("replace", &graph, error))?;
+
+        log::info!(
+            "Annotated {} unitigs of {} with the ids of {} input records",
+            annotated_unitigs,
+            graph.display(),
+            source_ids.records_names.len()
+        );
+
+        Ok(graph)
+    }
+
+    /// Adds to the header of each unitig of a built graph the strand, relative to the unitig
+    /// sequence, of most of the kmers of the input records matching it, as RS:A:+ or RS:A:-,
+    /// replacing its file. Unitigs matched equally on both the strands are not tagged.
+    /// The strands are not tracked by the kmers merge, so as in annotate_graph_source_ids this is
+    /// a pass after the build, reading again the graph and all the inputs once for each partition
+    pub fn annotate_graph_source_strands(
+        &self,
+        graph: PathBuf,
+        // The FASTA/FASTQ inputs the graph was built from
+        inputs: &[PathBuf],
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> std::io::Result<PathBuf> {
+        // Same extension as the graph, to keep its compression
+        let annotated_file = graph.with_extension(format!(
+            "strands.{}",
+            graph.extension().unwrap_or_default().to_string_lossy()
+        ));
+        check_graph_rewrite_files("annotate", &graph, inputs, &annotated_file)?;
+
+        let partitions_count = self.graph_kmers_partitions_count(&graph);
+
+        let source_strands = source_ids::collect_source_strands(
+            |callback: &mut dyn FnMut(&[u8])| {
+                FastaFileSequencesStream::new()
+                    .read_block(&graph, false, None, |seq, _info| callback(seq.seq));
+            },
+            |callback: &mut dyn FnMut(&[u8])| {
+                let mut stream = FastaFileSequencesStream::new();
+                for input in inputs {
+                    stream.read_block(input, false, None, |seq, _info| callback(seq.seq));
+                }
+            },
+            kmer_length,
+            partitions_count,
+        );
+
+        let annotated_unitigs = source_ids::write_source_strands(
+            std::io::BufReader::new(io::lines_reader::open_decompressed_file(&graph)),
+            output_sorting::SortedGraphWriter::new(&annotated_file),
+            &source_strands,
+        )
+        .map_err(|error| graph_io_error("write the source strands of", &graph, error))?;
+        rename_output(&annotated_file, &graph)
+            .map_err(|error| graph_io_error("replace", &graph, error))?;
+
+        log::info!(
+            "Annotated {} unitigs of {} with the strand of their input records",
+            annotated_unitigs,
+            graph.display()
+        );
+
+        Ok(graph)
+    }
+
+    /// Writes the uncompacted de Bruijn graph of the kmers of a built graph to output_file in GFA
+    /// format, with a segment for each kmer and a link for each k-1 overlap between two kmers.
+    /// All the kmers are kept in memory, and the output is much larger than the unitigs graph
+    pub fn write_kmers_graph(
+        // The input graph
+        graph_input: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // Treats reverse complementary kmers as different
+        forward_only: bool,
+        output_file: PathBuf,
+    ) -> PathBuf {
+        log::warn!(
+            "Writing each kmer of {} as a separate node, the output can be very large",
+            graph_input.display()
+        );
+
+        let output = std::io::BufWriter::new(std::fs::File::create(&output_file).unwrap());
+        let (nodes_count, links_count) = kmers_graph::write_kmers_graph(
+            GraphReader::open(&graph_input),
+            kmer_length,
+            forward_only,
+            output,
+        )
+        .unwrap_or_else(|err| {
+            panic!(
+                "Cannot write the kmers graph of {}: {}",
+                graph_input.display(),
+                err
+            )
+        });
+
+        log::info!(
+            "Written {} kmers and {} links to {}",
+            nodes_count,
+            links_count,
+            output_file.display()
+        );
+
+        output_file
+    }
+
+    /// Writes to output_file in GFA format the unitigs sharing at least one kmer with the query
+    /// sequences, expanded with the unitigs reachable within hops links, and the links between them.
+    /// The query kmers and the links of the graph are kept in memory, and the graph is streamed twice.
+    /// Without links (a graph built without -e) only the matching unitigs are written
+    pub fn extract_subgraph(
+        // The input graph
+        graph_input: PathBuf,
+        // The query sequences, as a FASTA/FASTQ file
+        query_input: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // Treats reverse complementary kmers as different
+        forward_only: bool,
+        // Number of links followed from the matching unitigs
+        hops: usize,
+        output_file: PathBuf,
+    ) -> PathBuf {
+        use io::sequences_reader::SequencesReader;
+
+        if let Err(mismatch) =
+            Self::check_graph_metadata(&graph_input, kmer_length, None, forward_only)
+        {
+            panic!(
+                "Cannot extract a subgraph of {}: {}",
+                graph_input.display(),
+                mismatch
+            );
+        }
+
+        let mut query_kmers = std::collections::HashSet::new();
+        SequencesReader::new().process_file_extended(
+            &query_input,
+            |sequence| {
+                subgraph::add_query_kmers(sequence.seq, kmer_length, forward_only, &mut query_kmers)
+            },
+            None,
+            false,
+            false,
+        );
+
+        let matches = subgraph::find_query_matches(
+            GraphReader::open(&graph_input),
+            &query_kmers,
+            kmer_length,
+            forward_only,
+        )
+        .unwrap_or_else(|err| {
+            panic!(
+                "Cannot extract a subgraph of {}: {}",
+                graph_input.display(),
+                err
+            )
+        });
+        if matches.adjacency.is_empty() && hops > 0 {
+            log::warn!(
+                "The graph {} has no links, only the unitigs matching the query are extracted",
+                graph_input.display()
+            );
+        }
+        let selected = subgraph::expand_neighborhood(&matches, hops);
+
+        let output = std::io::BufWriter::new(std::fs::File::create(&output_file).unwrap());
+        let (segments_count, links_count) = subgraph::write_subgraph_gfa(
+            GraphReader::open(&graph_input),
+            &selected,
+            kmer_length,
+            output,
+        )
+        .unwrap_or_else(|err| {
+            panic!(
+                "Cannot write the subgraph of {}: {}",
+                graph_input.display(),
+                err
+            )
+        });
+
+        log::info!(
+            "Written {} unitigs ({} matching the query) and {} links to {}",
+            segments_count,
+            matches.matching_unitigs.len(),
+            links_count,
+            output_file.display()
+        );
+
+        output_file
+    }
+
+    /// Checks the invariants of a built graph (links targets and overlaps, duplicate unitigs
+    /// and indices and colors subsets, if the graph has a colormap), returning the count of each
+    /// violation. The graph is streamed, keeping in memory only the ends of the unitigs
+    pub fn validate_graph(
+        // The input graph
+        graph_input: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+    ) -> Result<GraphValidationReport, GraphValidationError> {
+        use colors::colors_manager::ColorMapReader;
+        use colors::storage::deserializer::ColorsDeserializer;
+        use colors::DefaultColorsSerializer;
+
+        let colormap_file = Self::get_colormap_file(&graph_input);
+        let colors_subsets_count = if colormap_file.exists() {
+            Some(
+                ColorsDeserializer::<DefaultColorsSerializer>::new(colormap_file, false)
+                    .colors_subsets_count(),
+            )
+        } else {
+            None
+        };
+
+        let mut validator = validation::GraphValidator::new(kmer_length, colors_subsets_count)?;
+        FastaFileSequencesStream::new().read_block(&graph_input, true, None, |seq, _info| {
+            validator.add_unitig(seq.ident_data, seq.seq);
+        });
+
+        Ok(validator.finish())
+    }
+
+    /// Scans a FASTA/FASTQ input (possibly compressed) for malformed records, without assembling it:
+    /// records without a header, non IUPAC characters, empty sequences and FASTQ records with
+    /// a quality not matching the sequence
+    pub fn validate_input(input: PathBuf) -> InputValidationReport {
+        use io::sequences_reader::SequencesReader;
+        use std::io::BufReader;
+
+        let reader = BufReader::new(io::lines_reader::open_decompressed_file(&input));
+        match SequencesReader::get_file_type(&input) {
+            Some(DnaSequencesFileType::FASTQ) => input_validation::validate_fastq(reader),
+            Some(DnaSequencesFileType::FASTA) => input_validation::validate_fasta(reader),
+            _ => panic!("Cannot recognize file type of '{}'", input.display()),
+        }
+    }
+
+    /// Estimates the peak memory in bytes needed to build a graph from total_bases bases of input,
+    /// to request the right allocation from a scheduler. The estimate is an analytical model of the
+    /// largest allocations of the pipeline (see MemoryEstimate), that can be off by a small factor.
+    /// buckets_count is the number of buckets of the run, by default one for each 192MB of input
+    /// bases between 1024 and 8192. colors_count is 0 for uncolored graphs
+    pub fn estimate_memory(
+        total_bases: u64,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The m-mers (minimizers) length
+        minimizer_length: usize,
+        buckets_count: usize,
+        threads_count: usize,
+        colors_count: usize,
+    ) -> u64 {
+        let threads_count = config::effective_threads_count(threads_count);
+        MemoryEstimate::new(
+            total_bases,
+            kmer_length,
+            minimizer_length,
+            buckets_count,
+            threads_count,
+            colors_count,
+        )
+        .peak_bytes()
+    }
+
+    /// Compares the canonical kmers sets of two graphs, reporting the intersection and union sizes
+    /// and their Jaccard similarity. The kmers are processed in hash partitions, to keep in memory
+    /// at most about memory_gb GB of kmers at a time, as the size of the graphs files is an upper
+    /// bound of their kmers count
+    pub fn compare_graphs(
+        graph_a: PathBuf,
+        graph_b: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        memory_gb: f64,
+    ) -> KmersComparisonReport {
+        let graphs_size = std::fs::metadata(&graph_a).map(|m| m.len()).unwrap_or(0)
+            + std::fs::metadata(&graph_b).map(|m| m.len()).unwrap_or(0);
+        let memory_bytes = max(1, (memory_gb * (1u64 << 30) as f64) as u64);
+        let partitions_count =
+            (graphs_size * comparison::COMPARISON_BYTES_PER_KMER).div_ceil(memory_bytes) as usize;
+
+        let read_graph = |graph: &PathBuf| {
+            let graph = graph.clone();
+            move |callback: &mut dyn FnMut(&[u8])| {
+                FastaFileSequencesStream::new()
+                    .read_block(&graph, false, None, |seq, _info| callback(seq.seq));
+            }
+        };
+
+        comparison::compare_graphs_kmers(
+            read_graph(&graph_a),
+            read_graph(&graph_b),
+            kmer_length,
+            partitions_count,
+        )
+    }
+
+    /// Computes the fraction of the kmers of each graph that are contained in the other one,
+    /// for example to check if an assembly is a subset of another
+    pub fn graphs_containment(
+        graph_a: PathBuf,
+        graph_b: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        memory_gb: f64,
+    ) -> KmersContainmentReport {
+        KmersContainmentReport {
+            comparison: Self::compare_graphs(graph_a, graph_b, kmer_length, memory_gb),
+        }
+    }
+
+    /// Builds the colored graph of the union of the kmers of the given graphs. The colors of the
+    /// graphs are merged by name into a shared colors space, an uncolored graph contributes a single
+    /// color named after its file. The graphs are streamed through the building pipeline, one run
+    /// of kmers with the same colors at a time, without loading them in memory
+    pub fn union_graphs(
+        &self,
+        graphs: Vec<PathBuf>,
+        output_file: PathBuf,
+        // Specifies the k-mers length, that must be the same of the input graphs
+        kmer_length: usize,
+        // The threads to be used
+        threads_count: usize,
+        // Treats reverse complementary kmers as different
+        forward_only: bool,
+        // Overrides the default m-mers (minimizers) length
+        minimizer_length: Option<usize>,
+    ) -> Result<PathBuf, AssemblerError> {
+        let union_colors = graphs_union::UnionColors::new(
+            graphs
+                .iter()
+                .map(|graph| graphs_union::GraphsUnionStream::graph_color_names(graph)),
+        );
+
+        let union_stream = Arc::new(graphs_union::GraphsUnionStream::new(
+            graphs.clone(),
+            union_colors.mappings,
+            kmer_length,
+        ));
+
+        let output = self.build_graph(
+            (0..graphs.len())
+                .map(|i| GeneralSequenceBlockData::Dynamic((union_stream.clone(), i)))
+                .collect(),
+            output_file,
+            Some(&union_colors.names),
+            BuildOptions {
+                kmer_length,
+                threads_count,
+                forward_only,
+                minimizer_length,
+                colors: true,
+                min_multiplicity: 1,
+                ..Default::default()
+            },
+        )?;
+
+        // The union is written to a single shard
+        Ok(output.single_file().unwrap())
+    }
+
+    /// Builds the graph of a synthetic in-memory dataset (two genomes sharing half of their sequence
+    /// if colored) and checks that it contains exactly the kmers of the genomes, with the right colors.
+    /// The pipeline runs in memory mode, whatever the instance configuration.
+    /// Useful to check that an installation works and to time it on new hardware
+    pub fn self_test(
+        &self,
+        // Length of each synthetic genome
+        genome_length: usize,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // The threads to be used
+        threads_count: usize,
+        // Enable colors
+        colored: bool,
+    ) -> Result<SelfTestReport, AssemblerError> {
+        const READ_LENGTH: usize = 250;
+        const SEED: u64 = 0x5EED;
+
+        let genomes_count = if colored { 2 } else { 1 };
+        let dataset = Arc::new(selftest::SyntheticDataset::generate(
+            genomes_count,
+            genome_length,
+            max(READ_LENGTH, kmer_length),
+            kmer_length,
+            SEED,
+        ));
+
+        let output_file = self
+            .0
+            .temp_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(format!("selftest-{}.fa", uuid::Uuid::new_v4()));
+
+        let color_names: Vec<_> = (0..genomes_count).map(|i| format!("genome{}", i)).collect();
+
+        // The temporary files are kept in memory, restoring the configured mode at the end
+        let prefer_memory = config::PREFER_MEMORY.swap(true, Ordering::Relaxed);
+
+        let start_time = Instant::now();
+        let output = self.build_graph(
+            (0..genomes_count)
+                .map(|i| GeneralSequenceBlockData::Dynamic((dataset.clone(), i)))
+                .collect(),
+            output_file,
+            Some(&color_names),
+            BuildOptions {
+                kmer_length,
+                threads_count,
+                colors: colored,
+                min_multiplicity: 1,
+                ..Default::default()
+            },
+        );
+        let elapsed = start_time.elapsed();
+        config::PREFER_MEMORY.store(prefer_memory, Ordering::Relaxed);
+        let output = output?;
+        let graph_file = output.single_file().unwrap();
+
+        let mut report = selftest::check_reconstruction(
+            &dataset,
+            GraphReader::open(&graph_file),
+            kmer_length,
+            colored,
+        )
+        .unwrap_or_else(|err| {
+            panic!(
+                "Cannot read the self test graph {}: {}",
+                graph_file.display(),
+                err
+            )
+        });
+        report.elapsed = elapsed;
+
+        let _ = std::fs::remove_file(Self::get_colormap_file(&graph_file));
+        let _ = std::fs::remove_file(Self::get_metadata_file(&graph_file));
+        let _ = std::fs::remove_file(graph_file);
+
+        Ok(report)
+    }
+
+    /// Dumps the unitigs of the given graph, optionally with colors
+    /// It's not guaranteed that maximal unitigs are returned, as only kmers with the same colors subset
+    /// are returned as whole unitigs to speedup colormap reading times
+    pub fn dump_unitigs(
+        &self,
+        graph_input: PathBuf,
+        // Specifies the k-mers length
+        kmer_length: usize,
+        // Overrides the default m-mers (minimizers) length
+        minimizer_length: Option<usize>,
+        colors: bool,
+        // The threads to be used
+        threads_count: usize,
+
+        // Call the output function from a single thread at a time,
+        // avoiding the need for synchronization in the user code
+        single_thread_output_function: bool,
+        output_function: impl Fn(&[u8], &[ColorIndexType], bool) + Send + Sync,
+    ) {
+        let threads_count = config::effective_threads_count(threads_count);
+
+        let temp_dir = create_tempdir(self.0.temp_dir.clone());
+
+        if colors {
+            dumper::dump_unitigs(
+                kmer_length,
+                minimizer_length.unwrap_or(::utils::compute_best_m(kmer_length)),
+                graph_input,
+                temp_dir.clone(),
+                *debug::BUCKETS_COUNT_LOG_FORCE.lock(),
+                threads_count,
+                single_thread_output_function,
+                self.0.intermediate_compression_level,
+                output_function,
+            );
+        } else {
+            FastaFileSequencesStream::new().read_block(
+                &graph_input,
+                false,
+                Some(kmer_length - 1),
+                |seq, _info| {
+                    output_function(seq.ident_data, &[], false);
+                },
+            );
+        }
+
+        remove_tempdir(temp_dir);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::selftest::{self, SyntheticDataset};
+    use crate::{
+        debug, AssemblerError, BuildOptions, ChannelSequencesStream, ColoredQueryOutputFormat,
+        ExtraElaboration, GGCATConfig, GGCATInstance, GeneralSequenceBlockData,
+        GraphMetadataMismatch, GraphReader, LogLevel, OutputSorting, PacketsPoolsCapacities,
+    };
+    use assembler::AssemblerStartingStep;
+    use io::concurrent::structured_sequences::write_reverse_complement;
+    use parking_lot::{Mutex, RwLock};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+
+    pub(crate) const K: usize = 31;
+    pub(crate) const THREADS: usize = 4;
+
+    /// Held shared by the tests running a pipeline, and exclusively by the ones changing the
+    /// global options, so that each pipeline sees the same options from start to end
+    pub(crate) static GLOBAL_OPTIONS_LOCK: RwLock<()> = RwLock::new(());
+
+    /// Logger of the tests, installed before the instance so that it is kept by it. The records
+    /// are printed, or collected while a test is capturing them
+    struct CapturingLogger {
+        capturing: AtomicBool,
+        records: Mutex<Vec<(log::Level, String)>>,
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::max_level()
+        }
+
+        fn log(&self, record: &log::Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+
+            if self.capturing.load(Ordering::Relaxed) {
+                self.records
+                    .lock()
+                    .push((record.level(), record.args().to_string()));
+            } else {
+                println!("{}", record.args());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURING_LOGGER: CapturingLogger = CapturingLogger {
+        capturing: AtomicBool::new(false),
+        records: Mutex::new(vec![]),
+    };
+
+    /// The instance is global, so all the tests share the one created by the first of them.
+    /// Its temporary directory is never removed, each test works in its own subdirectory
+    pub(crate) fn test_instance() -> &'static GGCATInstance {
+        static TEST_INSTANCE: OnceLock<&'static GGCATInstance> = OnceLock::new();
+        TEST_INSTANCE.get_or_init(|| {
+            log::set_logger(&CAPTURING_LOGGER).unwrap();
+            log::set_max_level(LogLevel::Default.level_filter());
+
+            GGCATInstance::create(GGCATConfig {
+                temp_dir: Some(
+                    std::env::temp_dir().join(format!("ggcat-api-tests-{}", std::process::id())),
+                ),
+                memory: 0.5,
+                prefer_memory: true,
+                total_threads_count: THREADS,
+                allow_oversubscribe: false,
+                intermediate_compression_level: None,
+                output_compression_level: None,
+                bgzf_output: false,
+                output_flush_sequences: None,
+                output_flush_bytes: None,
+                stats_file: None,
+                minimizer_hash_seed: 0,
+                temp_files_prefix: None,
+                extra_temp_dirs: vec![],
+                split_on_softmask: false,
+                iupac_reads_packing: false,
+                interleaved_paired_reads: false,
+                use_second_bucket: false,
+                reads_buffer_capacity: None,
+                links_compaction_links_buffer: None,
+                links_compaction_results_buffer: None,
+                packets_pools_capacities: PacketsPoolsCapacities::default(),
+                sync_checkpoints: false,
+                checkpoint_checksums: false,
+                log_level: LogLevel::Default,
+            })
+        })
+    }
+
+    /// Directory of the files of a test inside the shared temporary directory, removed when dropped
+    pub(crate) struct TestDir(PathBuf);
+
+    impl TestDir {
+        pub(crate) fn new(name: &str) -> Self {
+            let path = test_instance().0.temp_dir.as_ref().unwrap().join(format!(
+                "{}-{}",
+                name,
+                uuid::Uuid::new_v4()
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        pub(crate) fn path(&self) -> &Path {
+            &self.0
+        }
+
+        pub(crate) fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+            self.0.join(name)
+        }
+
+        /// Writes the sequences to a FASTA file of the test directory, named by their index
+        pub(crate) fn write_fasta(&self, name: &str, sequences: &[&[u8]]) -> PathBuf {
+            let path = self.join(name);
+            let mut writer = std::fs::File::create(&path).unwrap();
+            for (index, sequence) in sequences.iter().enumerate() {
+                writeln!(writer, ">{}", index).unwrap();
+                writer.write_all(sequence).unwrap();
+                writeln!(writer).unwrap();
+            }
+            path
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// The build_graph parameters changed by the tests, the other ones keep their defaults
+    pub(crate) struct TestBuild<'a> {
+        pub color_names: Option<&'a [String]>,
+        pub forward_only: bool,
+        pub colors: bool,
+        pub min_multiplicity: usize,
+        pub extra_elab: ExtraElaboration,
+        pub max_unitig_length: Option<usize>,
+        pub output_unitigs_paths: bool,
+        pub canonical_output: bool,
+        pub max_compaction_iterations: Option<usize>,
+        pub dump_compaction_rounds: bool,
+        pub checkpoint_compaction: bool,
+        pub masking_bed: Option<PathBuf>,
+        pub input_weights: Option<Vec<usize>>,
+        pub split_output_by_color: bool,
+        pub color_by_file: bool,
+    }
+
+    impl Default for TestBuild<'_> {
+        fn default() -> Self {
+            Self {
+                color_names: None,
+                forward_only: false,
+                colors: false,
+                min_multiplicity: 1,
+                extra_elab: ExtraElaboration::None,
+                max_unitig_length: None,
+                output_unitigs_paths: false,
+                canonical_output: false,
+                max_compaction_iterations: None,
+                dump_compaction_rounds: false,
+                checkpoint_compaction: false,
+                masking_bed: None,
+                input_weights: None,
+                split_output_by_color: false,
+                color_by_file: false,
+            }
+        }
+    }
+
+    pub(crate) fn build_test_graph(
+        inputs: Vec<GeneralSequenceBlockData>,
+        output_file: PathBuf,
+        options: TestBuild,
+    ) -> Result<PathBuf, AssemblerError> {
+        let _options_guard = GLOBAL_OPTIONS_LOCK.read();
+        build_test_graph_locked(inputs, output_file, options)
+    }
+
+    /// As build_test_graph, with GLOBAL_OPTIONS_LOCK already held by the caller
+    fn build_test_graph_locked(
+        inputs: Vec<GeneralSequenceBlockData>,
+        output_file: PathBuf,
+        options: TestBuild,
+    ) -> Result<PathBuf, AssemblerError> {
+        let output = test_instance().build_graph(
+            inputs,
+            output_file,
+            options.color_names,
+            BuildOptions {
+                kmer_length: K,
+                threads_count: THREADS,
+                forward_only: options.forward_only,
+                colors: options.colors,
+                min_multiplicity: options.min_multiplicity,
+                extra_elab: options.extra_elab,
+                max_unitig_length: options.max_unitig_length,
+                output_unitigs_paths: options.output_unitigs_paths,
+                canonical_output: options.canonical_output,
+                max_compaction_iterations: options.max_compaction_iterations,
+                dump_compaction_rounds: options.dump_compaction_rounds,
+                checkpoint_compaction: options.checkpoint_compaction,
+                masking_bed: options.masking_bed,
+                input_weights: options.input_weights,
+                split_output_by_color: options.split_output_by_color,
+                color_by_file: options.color_by_file,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(output.single_file().unwrap())
+    }
+
+    /// The query_graph parameters changed by the tests, the other ones keep their defaults
+    #[derive(Default)]
+    pub(crate) struct TestQuery {
+        pub forward_only: bool,
+        pub colors: bool,
+        pub query_both_strands: bool,
+        pub max_degenerate_expansions: Option<usize>,
+    }
+
+    /// Queries the graph, returning the content of the results file
+    pub(crate) fn query_test_graph(
+        graph_file: PathBuf,
+        query_file: PathBuf,
+        output_file_prefix: PathBuf,
+        options: TestQuery,
+    ) -> (PathBuf, String) {
+        let _options_guard = GLOBAL_OPTIONS_LOCK.read();
+        let output_file = test_instance()
+            .query_graph(
+                graph_file,
+                query_file,
+                output_file_prefix,
+                K,
+                THREADS,
+                options.forward_only,
+                None,
+                options.colors,
+                ColoredQueryOutputFormat::JsonLinesWithNumbers,
+                options.query_both_strands,
+                options.max_degenerate_expansions,
+                false,
+                None,
+            )
+            .unwrap();
+        let output = std::fs::read_to_string(&output_file).unwrap();
+        (output_file, output)
+    }
+
+    #[test]
+    fn query_uncolored_graph() {
+        let test_dir = TestDir::new("query");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 5000, 250, K, 0x0123));
+        let genome = dataset.genomes[0].clone();
+
+        let graph_file = build_test_graph(
+            vec![GeneralSequenceBlockData::Dynamic((dataset, 0))],
+            test_dir.join("graph.fa"),
+            TestBuild::default(),
+        )
+        .unwrap();
+        assert!(!GGCATInstance::get_colormap_file(&graph_file).exists());
+
+        // A query taken from the genome and one that does not share any kmer with it
+        let query_file =
+            test_dir.write_fasta("query.fa", &[&genome[1000..1200], &b"A".repeat(200)]);
+
+        // Asking for colors on an uncolored graph falls back to the membership output
+        let (output_file, output) = query_test_graph(
+            graph_file,
+            query_file,
+            test_dir.join("query-results"),
+            TestQuery {
+                colors: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(output_file.extension().unwrap(), "csv");
+
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "query_index,matched_kmers,query_kmers,match_percentage",
+                "0,170,170,1.00",
+                "1,0,170,0.00",
+            ]
+        );
+    }
+
+    #[test]
+    fn query_both_strands_forward_only() {
+        let test_dir = TestDir::new("strands");
+
+        // The genome is given only in the forward orientation
+        let genome = SyntheticDataset::generate(1, 5000, 250, K, 0x4567).genomes[0].clone();
+        let genome_file = test_dir.write_fasta("genome.fa", &[&genome]);
+
+        let graph_file = build_test_graph(
+            vec![GeneralSequenceBlockData::FASTA(genome_file)],
+            test_dir.join("graph.fa"),
+            TestBuild {
+                forward_only: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // A query from the reverse strand of the genome
+        let mut reverse_query = vec![];
+        write_reverse_complement(&genome[1000..1200], &mut reverse_query);
+        let query_file = test_dir.write_fasta("query.fa", &[&reverse_query]);
+
+        let query = |query_both_strands, output_name| {
+            query_test_graph(
+                graph_file.clone(),
+                query_file.clone(),
+                test_dir.join(output_name),
+                TestQuery {
+                    forward_only: true,
+                    query_both_strands,
+                    ..Default::default()
+                },
+            )
+            .1
+        };
+
+        let forward_output = query(false, "forward-results");
+        let both_strands_output = query(true, "both-strands-results");
+
+        assert_eq!(
+            forward_output.lines().collect::<Vec<_>>(),
+            [
+                "query_index,matched_kmers,query_kmers,match_percentage",
+                "0,0,170,0.00",
+            ]
+        );
+        assert_eq!(
+            both_strands_output.lines().collect::<Vec<_>>(),
+            [
+                "query_index,matched_kmers,query_kmers,match_percentage,orientation",
+                "0,0,170,0.00,+",
+                "0,170,170,1.00,-",
+            ]
+        );
+    }
+
+    #[test]
+    fn weighted_input_kmers_survive_the_multiplicity_cutoff() {
+        let test_dir = TestDir::new("weights");
+
+        // The reads cover their genome twice, the reference only once
+        let reads_genome = SyntheticDataset::generate(1, 2000, 250, K, 0x89ab).genomes[0].clone();
+        let reference_genome =
+            SyntheticDataset::generate(1, 2000, 250, K, 0xcdef).genomes[0].clone();
+        let reads_file = test_dir.write_fasta("reads.fa", &[&reads_genome, &reads_genome]);
+        let reference_file = test_dir.write_fasta("reference.fa", &[&reference_genome]);
+        let color_names = ["reads".to_string(), "reference".to_string()];
+
+        let build_kmers_count =
+            |output_name: &str, input_weights: Option<Vec<usize>>, colors: bool| {
+                let graph_file = build_test_graph(
+                    vec![
+                        GeneralSequenceBlockData::FASTA(reads_file.clone()),
+                        GeneralSequenceBlockData::FASTA(reference_file.clone()),
+                    ],
+                    test_dir.join(output_name),
+                    TestBuild {
+                        color_names: Some(&color_names),
+                        colors,
+                        min_multiplicity: 2,
+                        input_weights,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                GraphReader::open(&graph_file)
+                    .map(|unitig| unitig.unwrap().sequence.len() - K + 1)
+                    .sum::<usize>()
+            };
+
+        let unweighted = build_kmers_count("unweighted.fa", None, false);
+        let unit_weights = build_kmers_count("unit-weights.fa", Some(vec![1, 1]), false);
+        let weighted = build_kmers_count("weighted.fa", Some(vec![1, 2]), false);
+        let colored_weighted = build_kmers_count("colored-weighted.fa", Some(vec![1, 2]), true);
+
+        let genome_kmers = 2000 - K + 1;
+        assert_eq!(unweighted, genome_kmers);
+        // A weight of 1 counts each kmer once, as without weights
+        assert_eq!(unit_weights, unweighted);
+        assert_eq!(weighted, 2 * genome_kmers);
+        assert_eq!(colored_weighted, weighted);
+    }
+
+    #[test]
+    fn invalid_input_weights_are_rejected() {
+        let test_dir = TestDir::new("invalid-weights");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 2000, 250, K, 0x89ab));
+        let build = |input_weights| {
+            build_test_graph(
+                vec![GeneralSequenceBlockData::Dynamic((dataset.clone(), 0))],
+                test_dir.join("graph.fa"),
+                TestBuild {
+                    input_weights: Some(input_weights),
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert!(matches!(
+            build(vec![1, 2]),
+            Err(AssemblerError::InputWeightsCountMismatch {
+                weights_count: 2,
+                inputs_count: 1
+            })
+        ));
+        assert!(matches!(
+            build(vec![0]),
+            Err(AssemblerError::ZeroInputWeight { input_index: 0 })
+        ));
+        assert!(matches!(
+            build(vec![config::MAX_INPUT_WEIGHT + 1]),
+            Err(AssemblerError::InputWeightTooLarge {
+                input_index: 0,
+                weight
+            }) if weight == config::MAX_INPUT_WEIGHT + 1
+        ));
+    }
+
+    #[test]
+    fn invalid_masking_beds_are_rejected() {
+        let test_dir = TestDir::new("invalid-masking");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 2000, 250, K, 0x9abc));
+        let build = |masking_bed: PathBuf| {
+            build_test_graph(
+                vec![GeneralSequenceBlockData::Dynamic((dataset.clone(), 0))],
+                test_dir.join("graph.fa"),
+                TestBuild {
+                    masking_bed: Some(masking_bed),
+                    ..Default::default()
+                },
+            )
+        };
+
+        let missing_bed = test_dir.join("missing.bed");
+        assert!(matches!(
+            build(missing_bed.clone()),
+            Err(AssemblerError::MaskingBed { path, .. }) if path == missing_bed
+        ));
+
+        let malformed_bed = test_dir.join("malformed.bed");
+        std::fs::write(&malformed_bed, "genome0\tnot-a-position\t100\n").unwrap();
+        assert!(matches!(
+            build(malformed_bed.clone()),
+            Err(AssemblerError::MaskingBed { path, .. }) if path == malformed_bed
+        ));
+
+        // The run fails before writing the graph
+        assert!(!test_dir.join("graph.fa").exists());
+    }
+
+    #[test]
+    fn unwritable_minimizers_dumps_are_rejected() {
+        let test_dir = TestDir::new("invalid-minimizers-dump");
+        let dataset = Arc::new(SyntheticDataset::generate(1, 2000, 250, K, 0xabcd));
+        let dump_file = test_dir.join("missing-dir").join("minimizers.tsv");
+
+        // The dump is global, so no other pipeline can run while it is set
+        let _options_guard = GLOBAL_OPTIONS_LOCK.write();
+        *debug::DEBUG_MINIMIZERS_DUMP.write().unwrap() = Some((dump_file.clone(), 1));
+        let result = build_test_graph_locked(
+            vec![GeneralSequenceBlockData::Dynamic((dataset, 0))],
+            test_dir.join("graph.fa"),
+            TestBuild::default(),
+        );
+        *debug::DEBUG_MINIMIZERS_DUMP.write().unwrap() = None;
+
+        assert!(matches!(
+            result,
+            Err(AssemblerError::MinimizersDump { path, .. }) if path == dump_file
+        ));
+    }
+
+    #[test]
+    fn query_with_different_k_is_rejected() {
+        let test_dir = TestDir::new("metadata");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 2000, 250, K, 0x89ab));
+        let graph_file = build_test_graph(
+            vec![GeneralSequenceBlockData::Dynamic((dataset, 0))],
+            test_dir.join("graph.fa"),
+            TestBuild::default(),
+        )
+        .unwrap();
+
+        let same_k = GGCATInstance::check_graph_metadata(&graph_file, K, None, false);
+        let different_k = GGCATInstance::check_graph_metadata(&graph_file, 27, None, false);
+        let forward_only = GGCATInstance::check_graph_metadata(&graph_file, K, None, true);
+
+        assert_eq!(same_k, Ok(()));
+        assert_eq!(
+            different_k,
+            Err(GraphMetadataMismatch::KmerLength {
+                graph: K,
+                query: 27
+            })
+        );
+        assert_eq!(
+            forward_only,
+            Err(GraphMetadataMismatch::ForwardOnly {
+                graph: false,
+                query: true
+            })
+        );
+    }
+
+    #[test]
+    fn query_graph_with_different_k_fails() {
+        let test_dir = TestDir::new("metadata-query");
+
+        let genome = SyntheticDataset::generate(1, 2000, 250, K, 0x89ab).genomes[0].clone();
+        let genome_file = test_dir.write_fasta("genome.fa", &[&genome]);
+        let graph_file = build_test_graph(
+            vec![GeneralSequenceBlockData::FASTA(genome_file.clone())],
+            test_dir.join("graph.fa"),
+            TestBuild::default(),
+        )
+        .unwrap();
+
+        let _options_guard = GLOBAL_OPTIONS_LOCK.read();
+        let result = test_instance().query_graph(
+            graph_file,
+            genome_file,
+            test_dir.join("query-results"),
+            27,
+            THREADS,
+            false,
+            None,
+            false,
+            ColoredQueryOutputFormat::JsonLinesWithNumbers,
+            false,
+            None,
+            false,
+            None,
+        );
+
+        assert_eq!(
+            result,
+            Err(GraphMetadataMismatch::KmerLength {
+                graph: K,
+                query: 27
+            })
+        );
+        assert!(!test_dir.join("query-results.csv").exists());
+    }
+
+    #[test]
+    fn query_degenerate_kmers() {
+        let test_dir = TestDir::new("degenerate");
+
+        let genome = SyntheticDataset::generate(1, 5000, 250, K, 0x89ab).genomes[0].clone();
+        let genome_file = test_dir.write_fasta("genome.fa", &[&genome]);
+
+        let graph_file = build_test_graph(
+            vec![GeneralSequenceBlockData::FASTA(genome_file)],
+            test_dir.join("graph.fa"),
+            TestBuild::default(),
+        )
+        .unwrap();
+
+        // The first query has a single N, the second one has two N at a distance of 10 bases,
+        // so the 21 kmers that include both of them have 16 instantiations
+        let queries: Vec<_> = [&[100][..], &[100, 110]]
+            .into_iter()
+            .map(|degenerate_positions| {
+                let mut query = genome[1000..1200].to_vec();
+                for position in degenerate_positions {
+                    query[*position] = b'N';
+                }
+                query
+            })
+            .collect();
+        let query_file = test_dir.write_fasta("query.fa", &[&queries[0], &queries[1]]);
+
+        let query = |max_degenerate_expansions, output_name| {
+            query_test_graph(
+                graph_file.clone(),
+                query_file.clone(),
+                test_dir.join(output_name),
+                TestQuery {
+                    max_degenerate_expansions,
+                    ..Default::default()
+                },
+            )
+            .1
+        };
+
+        let plain_output = query(None, "plain-results");
+        let expanded_output = query(Some(4), "expanded-results");
+
+        assert_eq!(
+            plain_output.lines().collect::<Vec<_>>(),
+            [
+                "query_index,matched_kmers,query_kmers,match_percentage",
+                "0,139,170,0.82",
+                "1,129,170,0.76",
+            ]
+        );
+        assert_eq!(
+            expanded_output.lines().collect::<Vec<_>>(),
+            [
+                "query_index,matched_kmers,query_kmers,match_percentage",
+                "0,170,170,1.00",
+                "1,149,170,0.88",
+            ]
+        );
+    }
+
+    #[test]
+    fn union_overlapping_colored_graphs() {
+        let test_dir = TestDir::new("union");
+
+        // The two genomes share their first half
+        let dataset = Arc::new(SyntheticDataset::generate(2, 5000, 250, K, 0x89AB));
+
+        let graphs: Vec<_> = (0..2)
+            .map(|genome| {
+                build_test_graph(
+                    vec![GeneralSequenceBlockData::Dynamic((dataset.clone(), genome))],
+                    test_dir.join(format!("graph{}.fa", genome)),
+                    TestBuild {
+                        color_names: Some(&[format!("genome{}", genome)]),
+                        colors: true,
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let union_graph = {
+            let _options_guard = GLOBAL_OPTIONS_LOCK.read();
+            test_instance()
+                .union_graphs(graphs, test_dir.join("union.fa"), K, THREADS, false, None)
+                .unwrap()
+        };
+
+        let color_names: Vec<_> =
+            GGCATInstance::dump_colors(GGCATInstance::get_colormap_file(&union_graph)).collect();
+        let report =
+            selftest::check_reconstruction(&dataset, GraphReader::open(&union_graph), K, true)
+                .unwrap();
+
+        assert_eq!(color_names, ["genome0", "genome1"]);
+        assert!(report.is_valid(), "{}", report);
+    }
+
+    #[test]
+    fn colored_graph_is_split_by_color() {
+        let test_dir = TestDir::new("color-split");
+
+        // The two genomes share their first half
+        let dataset = Arc::new(SyntheticDataset::generate(2, 5000, 250, K, 0x1530));
+        let color_names = ["genome0".to_string(), "genome1".to_string()];
+
+        let graph_file = build_test_graph(
+            (0..2)
+                .map(|genome| GeneralSequenceBlockData::Dynamic((dataset.clone(), genome)))
+                .collect(),
+            test_dir.join("graph.fa"),
+            TestBuild {
+                color_names: Some(&color_names),
+                colors: true,
+                split_output_by_color: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // Each color file has the unitigs of the graph with that color, in the same order
+        let mut expected = vec![String::new(); color_names.len()];
+        for unitig in GraphReader::open(&graph_file) {
+            let unitig = unitig.unwrap();
+            for color in &unitig.colors {
+                expected[*color as usize].push_str(&format!(
+                    ">{} LN:i:{}\n{}\n",
+                    unitig.index,
+                    unitig.sequence.len(),
+                    std::str::from_utf8(&unitig.sequence).unwrap()
+                ));
+            }
+        }
+
+        for (color, name) in color_names.iter().enumerate() {
+            let color_file = test_dir.join(format!("graph.{}.fa", name));
+            let contents = std::fs::read_to_string(&color_file).unwrap();
+            assert!(!contents.is_empty());
+            assert_eq!(contents, expected[color], "{}", color_file.display());
+        }
+    }
+
+    #[test]
+    fn colors_by_file_are_named_after_the_files() {
+        let test_dir = TestDir::new("color-by-file");
+
+        // The three genomes share their first half
+        let dataset = SyntheticDataset::generate(3, 5000, 250, K, 0xF11E);
+        let inputs = ["sal1.fa", "sal2.fasta", "sal3.fna"]
+            .iter()
+            .zip(&dataset.genomes)
+            .map(|(name, genome)| {
+                GeneralSequenceBlockData::FASTA(test_dir.write_fasta(name, &[genome.as_slice()]))
+            })
+            .collect();
+
+        let graph_file = build_test_graph(
+            inputs,
+            test_dir.join("graph.fa"),
+            TestBuild {
+                color_by_file: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let color_names: Vec<_> =
+            GGCATInstance::dump_colors(GGCATInstance::get_colormap_file(&graph_file)).collect();
+        assert_eq!(color_names, ["sal1", "sal2", "sal3"]);
+
+        // The kmers of each file have its color
+        let report =
+            selftest::check_reconstruction(&dataset, GraphReader::open(&graph_file), K, true)
+                .unwrap();
+        assert!(report.is_valid(), "{}", report);
+    }
+
+    #[test]
+    fn second_bucket_keeps_the_same_kmers() {
+        let test_dir = TestDir::new("second-bucket");
+
+        let dataset = Arc::new(SyntheticDataset::generate(2, 20000, 250, K, 0x2B2B));
+        let color_names = ["genome0".to_string(), "genome1".to_string()];
+
+        // The canonical kmers of the graph with their colors, sorted
+        let build_kmers = |use_second_bucket: bool| {
+            let graph_file = {
+                let _options_guard = GLOBAL_OPTIONS_LOCK.write();
+                config::USE_SECOND_BUCKET.store(use_second_bucket, Ordering::Relaxed);
+                let graph_file = build_test_graph_locked(
+                    (0..2)
+                        .map(|genome| GeneralSequenceBlockData::Dynamic((dataset.clone(), genome)))
+                        .collect(),
+                    test_dir.join(format!("graph-{}.fa", use_second_bucket)),
+                    TestBuild {
+                        color_names: Some(&color_names),
+                        colors: true,
+                        ..Default::default()
+                    },
+                );
+                config::USE_SECOND_BUCKET.store(false, Ordering::Relaxed);
+                graph_file.unwrap()
+            };
+
+            let mut kmers: Vec<_> = GraphReader::open(&graph_file)
+                .kmers(K)
+                .map(|kmer| {
+                    let kmer = kmer.unwrap();
+                    (kmer.kmer, kmer.colors)
+                })
+                .collect();
+            kmers.sort_unstable();
+            kmers
+        };
+
+        let without_second_bucket = build_kmers(false);
+        let with_second_bucket = build_kmers(true);
+
+        assert!(!without_second_bucket.is_empty());
+        assert_eq!(with_second_bucket, without_second_bucket);
+    }
+
+    #[test]
+    fn channel_builds_the_same_graph_as_the_file() {
+        let test_dir = TestDir::new("channel");
+
+        let dataset = SyntheticDataset::generate(1, 20000, 250, K, 0xC4A1);
+        let reads: Vec<_> = dataset.genomes[0]
+            .windows(250)
+            .step_by(125)
+            .map(|read| read.to_vec())
+            .collect();
+        let reads_file = test_dir.write_fasta(
+            "reads.fa",
+            &reads.iter().map(|read| read.as_slice()).collect::<Vec<_>>(),
+        );
+
+        // The canonical kmers of the graph, sorted
+        let graph_kmers = |graph_file: PathBuf| {
+            let mut kmers: Vec<_> = GraphReader::open(&graph_file)
+                .kmers(K)
+                .map(|kmer| kmer.unwrap().kmer)
+                .collect();
+            kmers.sort_unstable();
+            kmers
+        };
+
+        let file_graph = build_test_graph(
+            vec![GeneralSequenceBlockData::FASTA(reads_file)],
+            test_dir.join("file-graph.fa"),
+            TestBuild::default(),
+        )
+        .unwrap();
+
+        // A small capacity forces the producer to wait for the pipeline
+        let (sender, stream) = ChannelSequencesStream::new(16, 20000);
+        let producer = std::thread::spawn(move || {
+            for read in reads {
+                sender.send_read(&read).unwrap();
+            }
+            sender.finish();
+        });
+        let channel_graph = build_test_graph(
+            vec![GeneralSequenceBlockData::Dynamic((stream, 0))],
+            test_dir.join("channel-graph.fa"),
+            TestBuild::default(),
+        )
+        .unwrap();
+        producer.join().unwrap();
+
+        let file_kmers = graph_kmers(file_graph);
+        assert!(!file_kmers.is_empty());
+        assert_eq!(graph_kmers(channel_graph), file_kmers);
+    }
+
+    #[test]
+    fn compaction_not_converged_is_an_error() {
+        let test_dir = TestDir::new("not-converged");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 50000, 250, K, 0x1280));
+
+        let result = build_test_graph(
+            vec![GeneralSequenceBlockData::Dynamic((dataset, 0))],
+            test_dir.join("graph.fa"),
+            TestBuild {
+                max_compaction_iterations: Some(1),
+                ..Default::default()
+            },
+        );
+
+        match result {
+            Err(AssemblerError::CompactionNotConverged(error)) => {
+                assert_eq!(error.iterations, 1);
+                assert!(error.remaining_links > 0);
+            }
+            Err(error) => panic!("Unexpected error: {}", error),
+            Ok(_) => panic!("The compaction converged in a single iteration"),
+        }
+        assert!(!test_dir.join("graph.fa").exists());
+    }
+
+    #[test]
+    fn compaction_rounds_are_dumped() {
+        let test_dir = TestDir::new("round-dumps");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 50000, 250, K, 0x1290));
+        let graph_file = test_dir.join("graph.fa");
+
+        build_test_graph(
+            vec![GeneralSequenceBlockData::Dynamic((dataset.clone(), 0))],
+            graph_file.clone(),
+            TestBuild {
+                dump_compaction_rounds: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let rounds = (0..)
+            .take_while(|round| assembler::compaction_round_dump_path(&graph_file, *round).exists())
+            .count();
+        assert!(rounds > 1);
+
+        let mut dumped_unitigs = 0;
+        for round in 0..rounds {
+            let dump =
+                std::fs::read_to_string(assembler::compaction_round_dump_path(&graph_file, round))
+                    .unwrap();
+            for line in dump.lines() {
+                let (first, joined) = line.split_once('\t').unwrap();
+                assert!(first.split_once(':').is_some(), "{}", line);
+                assert!(
+                    joined
+                        .split(',')
+                        .all(|part| part.contains(':')
+                            && (part.ends_with('+') || part.ends_with('-')))
+                );
+                dumped_unitigs += 1;
+            }
+        }
+        assert!(dumped_unitigs > 0);
+
+        // A round file was written for each iteration: stopping one iteration earlier fails
+        let result = build_test_graph(
+            vec![GeneralSequenceBlockData::Dynamic((dataset, 0))],
+            test_dir.join("graph-capped.fa"),
+            TestBuild {
+                max_compaction_iterations: Some(rounds - 1),
+                ..Default::default()
+            },
+        );
+        match result {
+            Err(AssemblerError::CompactionNotConverged(error)) => {
+                assert_eq!(error.iterations, rounds - 1)
+            }
+            Err(error) => panic!("Unexpected error: {}", error),
+            Ok(_) => panic!("The compaction converged in {} iterations", rounds - 1),
+        }
+    }
+
+    #[test]
+    fn interrupted_compaction_resumes() {
+        let test_dir = TestDir::new("resume");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 50000, 250, K, 0x1380));
+        let inputs = || vec![GeneralSequenceBlockData::Dynamic((dataset.clone(), 0))];
+
+        let sorted_kmers = |graph_file: &Path| {
+            let mut kmers: Vec<_> = GraphReader::open(graph_file)
+                .kmers(K)
+                .map(|kmer| kmer.unwrap().kmer)
+                .collect();
+            kmers.sort_unstable();
+            kmers
+        };
+
+        let _options_guard = GLOBAL_OPTIONS_LOCK.write();
+
+        let expected =
+            build_test_graph_locked(inputs(), test_dir.join("expected.fa"), TestBuild::default())
+                .unwrap();
+
+        // The run is interrupted after the first compaction round, keeping its checkpoints
+        let interrupted = build_test_graph_locked(
+            inputs(),
+            test_dir.join("graph.fa"),
+            TestBuild {
+                max_compaction_iterations: Some(1),
+                checkpoint_compaction: true,
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            interrupted,
+            Err(AssemblerError::CompactionNotConverged(_))
+        ));
+
+        // Resumes it from the second round
+        *debug::DEBUG_ASSEMBLER_FIRST_STEP.lock() = AssemblerStartingStep::LinksCompaction;
+        debug::DEBUG_LINK_PHASE_ITERATION_START_STEP.store(1, Ordering::Relaxed);
+        let resumed = build_test_graph_locked(
+            inputs(),
+            test_dir.join("graph.fa"),
+            TestBuild {
+                checkpoint_compaction: true,
+                ..Default::default()
+            },
+        );
+        // Resuming a round that was never checkpointed fails instead
+        debug::DEBUG_LINK_PHASE_ITERATION_START_STEP.store(5, Ordering::Relaxed);
+        let not_checkpointed = build_test_graph_locked(
+            inputs(),
+            test_dir.join("missing.fa"),
+            TestBuild {
+                checkpoint_compaction: true,
+                ..Default::default()
+            },
+        );
+        *debug::DEBUG_ASSEMBLER_FIRST_STEP.lock() = AssemblerStartingStep::MinimizerBucketing;
+        debug::DEBUG_LINK_PHASE_ITERATION_START_STEP.store(0, Ordering::Relaxed);
+
+        assert_eq!(sorted_kmers(&resumed.unwrap()), sorted_kmers(&expected));
+        assert!(matches!(
+            not_checkpointed,
+            Err(AssemblerError::CompactionResume(_))
+        ));
+    }
+
+    #[test]
+    fn self_test_runs_in_memory() {
+        let _options_guard = GLOBAL_OPTIONS_LOCK.write();
+        let prefer_memory = config::PREFER_MEMORY.swap(false, Ordering::Relaxed);
+
+        for colored in [false, true] {
+            let report = test_instance()
+                .self_test(5000, K, THREADS, colored)
+                .unwrap();
+            assert!(report.is_valid(), "{}", report);
+            assert_eq!(report.colored, colored);
+            // The configured mode is restored after the run
+            assert!(!config::PREFER_MEMORY.load(Ordering::Relaxed));
+        }
+
+        config::PREFER_MEMORY.store(prefer_memory, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn invalid_max_unitig_length_is_rejected() {
+        let test_dir = TestDir::new("max-length");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 2000, 250, K, 0x89ab));
+        let build = |max_unitig_length, colors| {
+            build_test_graph(
+                vec![GeneralSequenceBlockData::Dynamic((dataset.clone(), 0))],
+                test_dir.join("graph.fa"),
+                TestBuild {
+                    colors,
+                    max_unitig_length: Some(max_unitig_length),
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert!(matches!(
+            build(K - 1, false),
+            Err(AssemblerError::MaxUnitigLengthTooShort {
+                max_unitig_length,
+                k: K
+            }) if max_unitig_length == K - 1
+        ));
+        assert!(matches!(
+            build(500, true),
+            Err(AssemblerError::MaxUnitigLengthWithColors)
+        ));
+    }
+
+    #[test]
+    fn split_unitigs_are_linked() {
+        const MAX_LENGTH: usize = 500;
+        let test_dir = TestDir::new("split");
+
+        let genome = SyntheticDataset::generate(1, 5000, 250, K, 0x4567).genomes[0].clone();
+        let genome_file = test_dir.write_fasta("genome.fa", &[&genome]);
+
+        let graph_file = build_test_graph(
+            vec![GeneralSequenceBlockData::FASTA(genome_file)],
+            test_dir.join("graph.fa"),
+            TestBuild {
+                extra_elab: ExtraElaboration::UnitigLinks,
+                max_unitig_length: Some(MAX_LENGTH),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let unitigs: Vec<_> = GraphReader::open(&graph_file)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // The pieces overlap by k - 1 bases, so no kmer is duplicated
+        assert!(unitigs
+            .iter()
+            .all(|unitig| unitig.sequence.len() <= MAX_LENGTH));
+        assert_eq!(
+            unitigs
+                .iter()
+                .map(|unitig| unitig.sequence.len() - K + 1)
+                .sum::<usize>(),
+            5000 - K + 1
+        );
+
+        // Each piece is linked to the one that continues it
+        let mut consecutive_pieces = 0;
+        for piece in &unitigs {
+            for next in &unitigs {
+                if piece.sequence[piece.sequence.len() - (K - 1)..] == next.sequence[..K - 1] {
+                    consecutive_pieces += 1;
+                    assert!(piece.links.iter().any(|link| !link.from_reverse
+                        && link.target == next.index
+                        && !link.to_reverse));
+                }
+            }
+        }
+        assert!(consecutive_pieces >= (5000 - K + 1).div_ceil(MAX_LENGTH - K + 1) - 1);
+    }
+
+    #[test]
+    fn unitigs_paths_cover_the_partial_unitigs() {
+        let test_dir = TestDir::new("paths");
+
+        let dataset = Arc::new(SyntheticDataset::generate(1, 5000, 250, K, 0x0123));
+        let graph_file = build_test_graph(
+            vec![GeneralSequenceBlockData::Dynamic((dataset, 0))],
+            test_dir.join("graph.fa"),
+            TestBuild {
+                output_unitigs_paths: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut unitigs_indexes: Vec<_> = GraphReader::open(&graph_file)
+            .map(|unitig| unitig.unwrap().index)
+            .collect();
+        let paths = std::fs::read_to_string(graph_file.with_extension("paths.tsv")).unwrap();
+
+        let mut paths_indexes = vec![];
+        let mut partial_unitigs = vec![];
+        for line in paths.lines() {
+            let (index, path) = line.split_once('\t').unwrap();
+            paths_indexes.push(index.parse::<u64>().unwrap());
+            // Each partial unitig is written with the orientation it is joined with
+            partial_unitigs.extend(
+                path.split(',')
+                    .map(|part| part[..part.len() - 1].to_string()),
+            );
+        }
+
+        // A path for each unitig, with the index of its sequence
+        unitigs_indexes.sort();
+        paths_indexes.sort();
+        assert_eq!(paths_indexes, unitigs_indexes);
+
+        // Each partial unitig is part of exactly one unitig
+        let partial_unitigs_count = partial_unitigs.len();
+        partial_unitigs.sort();
+        partial_unitigs.dedup();
+        assert!(partial_unitigs_count > paths_indexes.len());
+        assert_eq!(partial_unitigs.len(), partial_unitigs_count);
+    }
+
+    #[test]
+    fn quiet_builds_log_no_progress() {
+        let test_dir = TestDir::new("quiet-logging");
+        let genome = SyntheticDataset::generate(1, 5000, 250, K, 0x2345).genomes[0].clone();
+        let genome_file = test_dir.write_fasta("genome.fa", &[&genome]);
+
+        // The maximum level is global, so no other pipeline can run while it is changed
+        let _options_guard = GLOBAL_OPTIONS_LOCK.write();
+        let build_logged_records = |log_level: LogLevel| {
+            log::set_max_level(log_level.level_filter());
+            CAPTURING_LOGGER.capturing.store(true, Ordering::Relaxed);
+            build_test_graph_locked(
+                vec![GeneralSequenceBlockData::FASTA(genome_file.clone())],
+                test_dir.join(format!("graph-{:?}.fa", log_level)),
+                TestBuild::default(),
+            )
+            .unwrap();
+            CAPTURING_LOGGER.capturing.store(false, Ordering::Relaxed);
+            std::mem::take(&mut *CAPTURING_LOGGER.records.lock())
+        };
+
+        let default_records = build_logged_records(LogLevel::Default);
+        let quiet_records = build_logged_records(LogLevel::Quiet);
+        log::set_max_level(LogLevel::Default.level_filter());
+
+        // The progress is logged by default, and dropped by the quiet level
+        assert!(default_records
+            .iter()
+            .any(|(level, _)| *level == log::Level::Info));
+        assert!(
+            quiet_records
+                .iter()
+                .all(|(level, _)| *level <= log::Level::Warn),
+            "{:?}",
+            quiet_records
+        );
+    }
+
+    #[test]
+    fn annotating_with_a_missing_input_is_an_error() {
+        let test_dir = TestDir::new("annotate-missing");
+        let graph_file = test_dir.write_fasta("graph.fa", &[b"ACGTACGTACGTACGTACGTACGTACGTACGTA"]);
+        let graph = std::fs::read(&graph_file).unwrap();
+        let missing_input = test_dir.join("missing.fa");
+
+        let error = test_instance()
+            .annotate_graph_source_ids(graph_file.clone(), &[missing_input.clone()], K, None)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        let error = test_instance()
+            .annotate_graph_source_strands(graph_file.clone(), &[missing_input], K)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+
+        // The graph is left untouched
+        assert_eq!(std::fs::read(&graph_file).unwrap(), graph);
+    }
+
+    #[test]
+    fn forward_only_builds_tag_the_flipped_unitigs() {
+        let test_dir = TestDir::new("strand-tags");
+
+        // The genome and its reverse complement share no kmers in a forward-only graph
+        let genome = SyntheticDataset::generate(1, 5000, 250, K, 0x6789).genomes[0].clone();
+        let mut reverse_genome = vec![];
+        write_reverse_complement(&genome, &mut reverse_genome);
+        let genome_file = test_dir.write_fasta("genome.fa", &[&genome, &reverse_genome]);
+
+        let build_tagged_unitigs = |canonical_output, graph_name| {
+            let graph_file = build_test_graph(
+                vec![GeneralSequenceBlockData::FASTA(genome_file.clone())],
+                test_dir.join(graph_name),
+                TestBuild {
+                    forward_only: true,
+                    canonical_output,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            let graph = std::fs::read_to_string(graph_file).unwrap();
+            let mut unitigs: Vec<_> = graph
+                .lines()
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|record| {
+                    let strand = record[0]
+                        .split(' ')
+                        .find_map(|tag| tag.strip_prefix("ST:A:"));
+                    (record[1].to_string(), strand.unwrap().to_string())
+                })
+                .collect();
+            unitigs.sort();
+            unitigs
+        };
+
+        let canonical_genome =
+            String::from_utf8(genome.clone().min(reverse_genome.clone())).unwrap();
+        let forward_genome = String::from_utf8(genome.clone()).unwrap();
+
+        // Both the strands are written as the canonical sequence, the tag tells them apart
+        assert_eq!(
+            build_tagged_unitigs(true, "canonical-graph.fa"),
+            [
+                (canonical_genome.clone(), "+".to_string()),
+                (canonical_genome.clone(), "-".to_string()),
+            ]
+        );
+
+        // Without the canonical output each strand keeps its orientation and is tagged as forward
+        let mut strands = vec![
+            (forward_genome, "+".to_string()),
+            (
+                String::from_utf8(reverse_genome.clone()).unwrap(),
+                "+".to_string(),
+            ),
+        ];
+        strands.sort();
+        assert_eq!(build_tagged_unitigs(false, "graph.fa"), strands);
+    }
+
+    #[test]
+    fn sorting_a_missing_graph_is_an_error() {
+        let test_dir = TestDir::new("sort-missing");
+        let error = test_instance()
+            .sort_graph_output(test_dir.join("missing.fa"), OutputSorting::Length)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn deduplicating_a_missing_graph_is_an_error() {
+        let test_dir = TestDir::new("dedup-missing");
+        let error = test_instance()
+            .dedup_graph_output(test_dir.join("missing.fa"))
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
     }
 }
@@ -0,0 +1,147 @@
+use config::{
+    get_packets_pool_capacity, PacketsPoolExecutor, DEFAULT_PER_CPU_BUFFER_SIZE,
+    KMERS_TRANSFORM_READS_CHUNKS_SIZE, MAXIMUM_JIT_PROCESSED_BUCKETS, MAXIMUM_SECOND_BUCKETS_COUNT,
+    MAX_INTERMEDIATE_MAP_SIZE, READ_INTERMEDIATE_CHUNKS_SIZE, READ_INTERMEDIATE_QUEUE_MULTIPLIER,
+};
+use std::cmp::{max, min};
+use std::sync::atomic::Ordering;
+
+// Hashbrown maps are resized when 7/8 of the slots are used, each slot has a control byte
+const HASHMAP_LOAD_FACTOR: f64 = 7.0 / 8.0;
+// Colors subset hash (u128) and index (u32) of the colors DashMap, with the map overhead
+const COLORS_MAP_ENTRY_SIZE: u64 = 32;
+// Hash, unitig index and direction of each unitig end in the links stage
+const LINKS_HASH_ENTRY_SIZE: u64 = 24;
+
+/// Analytical model of the memory needed to build a graph, split by pipeline stage.
+/// It assumes that every kmer of the input is distinct, so it overestimates the maps of highly
+/// redundant (high coverage) inputs, and that the unitigs are about k bases long on average.
+/// It does not include the temporary files kept in memory, that are limited by GGCATConfig::memory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// Reads chunks of the input readers and the per-thread buffers of each bucket
+    pub minimizer_bucketing: u64,
+    /// Kmers hashmaps of the processors with their saved reads, and the packets of the bucket readers
+    pub kmers_merge: u64,
+    /// Colors subsets map, alive during the kmers merge
+    pub colors_map: u64,
+    /// Unitig ends hashes sorted in memory, one bucket for each thread
+    pub links_compaction: u64,
+}
+
+impl MemoryEstimate {
+    pub fn new(
+        total_bases: u64,
+        k: usize,
+        m: usize,
+        buckets_count: usize,
+        threads_count: usize,
+        colors_count: usize,
+    ) -> Self {
+        let buckets_count = max(1, buckets_count) as u64;
+        let threads_count = max(1, threads_count);
+        let total_kmers = total_bases.saturating_sub(k as u64 - 1);
+
+        // With random minimizers a super-kmer covers on average (w + 1) / 2 kmers,
+        // each one repeats k - 1 bases of the previous one
+        let window = k.saturating_sub(m) as u64 + 1;
+        let superkmers = total_kmers * 2 / (window + 1);
+        let superkmer_bytes = (k as u64 + window / 2).div_ceil(4);
+        let stored_bytes = (total_bases + superkmers * (k as u64 - 1)).div_ceil(4);
+
+        let readers_chunks = get_packets_pool_capacity(
+            PacketsPoolExecutor::MinimizerBucketingReader,
+            threads_count * READ_INTERMEDIATE_QUEUE_MULTIPLIER.load(Ordering::Relaxed),
+        ) as u64
+            * READ_INTERMEDIATE_CHUNKS_SIZE as u64;
+        let buckets_buffers =
+            threads_count as u64 * buckets_count * DEFAULT_PER_CPU_BUFFER_SIZE.as_bytes() as u64;
+
+        // A map holds at most a bucket, the larger buckets are split to keep each map
+        // under MAX_INTERMEDIATE_MAP_SIZE
+        let hash_size = match k {
+            0..=8 => 2,
+            9..=16 => 4,
+            17..=32 => 8,
+            _ => 16,
+        };
+        let map_entry_size = ((hash_size + 8) as f64 / HASHMAP_LOAD_FACTOR) as u64 + 1;
+        let bucket_kmers = total_kmers / buckets_count;
+        let map_bytes = min(bucket_kmers * map_entry_size, MAX_INTERMEDIATE_MAP_SIZE)
+            + stored_bytes / buckets_count;
+        let maps_count = get_packets_pool_capacity(
+            PacketsPoolExecutor::KmersTransformProcessor,
+            max(MAXIMUM_JIT_PROCESSED_BUCKETS, threads_count) + 2,
+        ) as u64;
+
+        let reads_packet_bytes =
+            max(16, KMERS_TRANSFORM_READS_CHUNKS_SIZE / k) as u64 * (superkmer_bytes + 16);
+        let reader_packets = get_packets_pool_capacity(
+            PacketsPoolExecutor::KmersTransformReader,
+            max(MAXIMUM_SECOND_BUCKETS_COUNT, threads_count) / 2,
+        ) as u64;
+
+        let colors_map = if colors_count > 0 {
+            let max_subsets = 1u64.checked_shl(colors_count as u32).unwrap_or(u64::MAX);
+            min(total_kmers / k as u64, max_subsets) * COLORS_MAP_ENTRY_SIZE
+        } else {
+            0
+        };
+
+        let bucket_unitig_ends = 2 * total_kmers / k as u64 / buckets_count;
+
+        Self {
+            minimizer_bucketing: readers_chunks + buckets_buffers,
+            kmers_merge: maps_count * map_bytes
+                + threads_count as u64 * reader_packets * reads_packet_bytes,
+            colors_map,
+            links_compaction: threads_count as u64 * bucket_unitig_ends * LINKS_HASH_ENTRY_SIZE,
+        }
+    }
+
+    /// The stages run one after the other, so the peak is the one of the largest stage
+    pub fn peak_bytes(&self) -> u64 {
+        max(
+            self.minimizer_bucketing,
+            max(self.kmers_merge + self.colors_map, self.links_compaction),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryEstimate;
+
+    const GIGABASE: u64 = 1_000_000_000;
+
+    #[test]
+    fn estimate_grows_with_the_input() {
+        let estimates: Vec<_> = [1, 10, 100, 1000]
+            .iter()
+            .map(|gb| MemoryEstimate::new(gb * GIGABASE, 31, 12, 1024, 16, 0))
+            .collect();
+
+        for pair in estimates.windows(2) {
+            assert!(pair[0].peak_bytes() <= pair[1].peak_bytes());
+            assert!(pair[0].kmers_merge <= pair[1].kmers_merge);
+        }
+
+        let colored = MemoryEstimate::new(10 * GIGABASE, 31, 12, 1024, 16, 100);
+        assert!(colored.peak_bytes() > estimates[1].peak_bytes());
+    }
+
+    #[test]
+    fn buckets_buffers_grow_with_the_buckets() {
+        // The per-bucket working sets get smaller, but the bucketing buffers grow
+        let estimates: Vec<_> = [1024, 2048, 4096, 8192]
+            .iter()
+            .map(|buckets| MemoryEstimate::new(100 * GIGABASE, 31, 12, *buckets, 16, 0))
+            .collect();
+
+        for pair in estimates.windows(2) {
+            assert!(pair[0].minimizer_bucketing < pair[1].minimizer_bucketing);
+            assert!(pair[0].links_compaction >= pair[1].links_compaction);
+            assert!(pair[0].kmers_merge >= pair[1].kmers_merge);
+        }
+    }
+}
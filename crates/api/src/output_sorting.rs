@@ -0,0 +1,227 @@
+use config::DEFAULT_OUTPUT_BUFFER_SIZE;
+use io::concurrent::structured_sequences::fasta::FastaWriter;
+use io::concurrent::structured_sequences::StructuredSequenceBackend;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+/// Order of the unitigs in the final output
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputSorting {
+    /// Keep the order in which the unitigs are built (by bucket)
+    None,
+    /// From the longest to the shortest unitig, the unitigs with the same length keep their order
+    Length,
+}
+
+/// Writer of the sorted records, compressed depending on the extension as the graph output
+pub(crate) struct SortedGraphWriter {
+    backend: FastaWriter<(), ()>,
+    buffer: Vec<u8>,
+}
+
+impl SortedGraphWriter {
+    pub fn new(path: &Path) -> Self {
+        let backend = FastaWriter::new_from_extension(
+            path,
+            config::OUTPUT_COMPRESSION_LEVEL.load(Ordering::Relaxed),
+        );
+        Self {
+            backend,
+            buffer: Vec::with_capacity(DEFAULT_OUTPUT_BUFFER_SIZE),
+        }
+    }
+}
+
+impl Write for SortedGraphWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= DEFAULT_OUTPUT_BUFFER_SIZE {
+            self.backend.flush_temp_buffer(&mut self.buffer);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.backend.flush_temp_buffer(&mut self.buffer);
+        Ok(())
+    }
+}
+
+/// Reads the next FASTA record (its header and sequence lines), returning its sequence length
+fn read_record(input: &mut impl BufRead, record: &mut Vec<u8>) -> std::io::Result<Option<u64>> {
+    record.clear();
+    if input.read_until(b'\n', record)? == 0 {
+        return Ok(None);
+    }
+
+    let mut sequence_length = 0;
+    while input
+        .fill_buf()?
+        .first()
+        .map(|b| *b != b'>')
+        .unwrap_or(false)
+    {
+        let line_start = record.len();
+        input.read_until(b'\n', record)?;
+        sequence_length += record[line_start..]
+            .iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .count() as u64;
+    }
+    Ok(Some(sequence_length))
+}
+
+// Sorts a run of records and writes each one with its length and size, as the input of the merge
+fn write_sorted_run(run: &mut Vec<(u64, Vec<u8>)>, run_file: &Path) -> std::io::Result<()> {
+    // Stable, so that the records with the same length keep their order
+    run.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut writer = BufWriter::new(File::create(run_file)?);
+    for (length, record) in run.drain(..) {
+        writer.write_all(&length.to_le_bytes())?;
+        writer.write_all(&(record.len() as u64).to_le_bytes())?;
+        writer.write_all(&record)?;
+    }
+    writer.flush()
+}
+
+fn read_run_record(run: &mut impl Read, record: &mut Vec<u8>) -> std::io::Result<Option<u64>> {
+    let mut length = [0; 8];
+    match run.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut size = [0; 8];
+    run.read_exact(&mut size)?;
+
+    record.resize(u64::from_le_bytes(size) as usize, 0);
+    run.read_exact(record)?;
+    Ok(Some(u64::from_le_bytes(length)))
+}
+
+/// Writes the FASTA records of the input sorted by decreasing sequence length. The records are
+/// sorted in runs of at most max_run_bytes, saved in temp_dir and then merged, so that the whole
+/// graph never needs to fit in memory. Returns the number of sorted records
+pub(crate) fn sort_records_by_length(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    temp_dir: &Path,
+    max_run_bytes: usize,
+) -> std::io::Result<u64> {
+    let mut run = vec![];
+    let mut run_bytes = 0;
+    let mut run_files: Vec<PathBuf> = vec![];
+    let mut records_count = 0;
+
+    let mut record = vec![];
+    while let Some(length) = read_record(&mut input, &mut record)? {
+        records_count += 1;
+        run_bytes += record.len();
+        run.push((length, std::mem::take(&mut record)));
+
+        if run_bytes >= max_run_bytes {
+            let run_file = temp_dir.join(format!("sorting-run-{}", run_files.len()));
+            write_sorted_run(&mut run, &run_file)?;
+            run_files.push(run_file);
+            run_bytes = 0;
+        }
+    }
+
+    // Everything fits in memory
+    if run_files.is_empty() {
+        run.sort_by(|a, b| b.0.cmp(&a.0));
+        for (_, record) in run {
+            output.write_all(&record)?;
+        }
+        output.flush()?;
+        return Ok(records_count);
+    }
+
+    if !run.is_empty() {
+        let run_file = temp_dir.join(format!("sorting-run-{}", run_files.len()));
+        write_sorted_run(&mut run, &run_file)?;
+        run_files.push(run_file);
+    }
+
+    let mut runs = run_files
+        .iter()
+        .map(|run_file| File::open(run_file).map(BufReader::new))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let mut records = vec![vec![]; runs.len()];
+
+    // The longest record first, the earlier runs first among the records with the same length
+    let mut heap = BinaryHeap::new();
+    for (index, run) in runs.iter_mut().enumerate() {
+        if let Some(length) = read_run_record(run, &mut records[index])? {
+            heap.push((length, std::cmp::Reverse(index)));
+        }
+    }
+
+    while let Some((_, std::cmp::Reverse(index))) = heap.pop() {
+        output.write_all(&records[index])?;
+        if let Some(length) = read_run_record(&mut runs[index], &mut records[index])? {
+            heap.push((length, std::cmp::Reverse(index)));
+        }
+    }
+
+    drop(runs);
+    for run_file in run_files {
+        std::fs::remove_file(run_file)?;
+    }
+
+    output.flush()?;
+    Ok(records_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sort_records_by_length;
+    use crate::tests::TestDir;
+    use std::io::Cursor;
+
+    const GRAPH: &str = ">0 LN:i:4 L:+:2:+\nACGT\n\
+        >1 LN:i:7\nACGTACG\n\
+        >2 LN:i:5\nACGTA\n\
+        >3 LN:i:7\nTTGTACG\n\
+        >4 LN:i:6\nACG\nTAC\n";
+
+    fn sorted_lengths(max_run_bytes: usize) -> (String, Vec<usize>) {
+        let temp_dir = TestDir::new("sorting");
+
+        let mut output = vec![];
+        let count = sort_records_by_length(
+            Cursor::new(GRAPH),
+            &mut output,
+            temp_dir.path(),
+            max_run_bytes,
+        )
+        .unwrap();
+        assert_eq!(count, 5);
+
+        let output = String::from_utf8(output).unwrap();
+        let lengths = output
+            .split('>')
+            .skip(1)
+            .map(|record| record.lines().skip(1).map(|l| l.len()).sum())
+            .collect();
+        (output, lengths)
+    }
+
+    #[test]
+    fn length_sorted_output_is_non_increasing() {
+        // In memory, and with a run for every record
+        for max_run_bytes in [1 << 20, 1] {
+            let (output, lengths) = sorted_lengths(max_run_bytes);
+
+            assert!(lengths.windows(2).all(|pair| pair[0] >= pair[1]));
+            assert_eq!(
+                output,
+                ">1 LN:i:7\nACGTACG\n>3 LN:i:7\nTTGTACG\n>4 LN:i:6\nACG\nTAC\n\
+                >2 LN:i:5\nACGTA\n>0 LN:i:4 L:+:2:+\nACGT\n"
+            );
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::graph_reader::{GraphReadError, Unitig};
+use config::ColorIndexType;
+use io::concurrent::structured_sequences::{canonical_sequence, write_reverse_complement};
+use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
+use io::sequences_stream::general::DynamicSequencesStream;
+use io::sequences_stream::SequenceInfo;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Small xorshift generator, to have reproducible synthetic datasets
+struct XorShift64(u64);
+
+impl XorShift64 {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn next_bases(&mut self, count: usize) -> Vec<u8> {
+        (0..count)
+            .map(|_| b"ACGT"[(self.next() % 4) as usize])
+            .collect()
+    }
+}
+
+/// Random genomes with their error-free reads, kept in memory.
+/// Each genome is a separate block, to be used as a separate color
+pub struct SyntheticDataset {
+    pub genomes: Vec<Vec<u8>>,
+    reads: Vec<Vec<Vec<u8>>>,
+}
+
+impl SyntheticDataset {
+    /// Generates genomes_count genomes of genome_length bases, covered by reads of read_length bases
+    /// overlapping by at least k - 1 bases (one every two reads is reverse complemented).
+    /// All the genomes share their first half, so that some kmers have more than one color
+    pub fn generate(
+        genomes_count: usize,
+        genome_length: usize,
+        read_length: usize,
+        k: usize,
+        seed: u64,
+    ) -> Self {
+        assert!(read_length >= k && genome_length >= read_length);
+
+        let mut rng = XorShift64(seed.max(1));
+        let shared = rng.next_bases(genome_length / 2);
+
+        let genomes: Vec<_> = (0..genomes_count)
+            .map(|_| {
+                let mut genome = shared.clone();
+                genome.extend(rng.next_bases(genome_length - shared.len()));
+                genome
+            })
+            .collect();
+
+        let step = read_length - (k - 1);
+        let reads = genomes
+            .iter()
+            .map(|genome| {
+                let mut starts: Vec<_> = (0..=(genome.len() - read_length)).step_by(step).collect();
+                if *starts.last().unwrap() != genome.len() - read_length {
+                    starts.push(genome.len() - read_length);
+                }
+
+                starts
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, start)| {
+                        let read = &genome[start..start + read_length];
+                        let mut rc_read = vec![];
+                        if index % 2 == 0 {
+                            read.to_vec()
+                        } else {
+                            write_reverse_complement(read, &mut rc_read);
+                            rc_read
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { genomes, reads }
+    }
+}
+
+impl DynamicSequencesStream for SyntheticDataset {
+    fn read_block(
+        &self,
+        block: usize,
+        _copy_ident_data: bool,
+        _partial_read_copyback: Option<usize>,
+        callback: &mut dyn FnMut(DnaSequence, SequenceInfo),
+    ) {
+        for read in &self.reads[block] {
+            callback(
+                DnaSequence {
+                    ident_data: &[],
+                    seq: read,
+                    format: DnaSequencesFileType::FASTA,
+                },
+                SequenceInfo {
+                    color: None,
+                    pair_id: None,
+                },
+            );
+        }
+    }
+
+    fn estimated_base_count(&self, block: usize) -> u64 {
+        self.reads[block].iter().map(|r| r.len() as u64).sum()
+    }
+}
+
+/// Result of the reconstruction of a synthetic dataset
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub colored: bool,
+    pub genomes_kmers: u64,
+    pub graph_kmers: u64,
+    /// Kmers of the genomes not present in the graph
+    pub missing_kmers: u64,
+    /// Kmers of the graph not present in any genome
+    pub extra_kmers: u64,
+    /// Kmers present more than once in the graph
+    pub duplicate_kmers: u64,
+    /// Unitigs whose colors differ from the genomes that contain their kmers
+    pub wrong_colors: u64,
+    pub elapsed: Duration,
+}
+
+impl SelfTestReport {
+    pub fn is_valid(&self) -> bool {
+        self.missing_kmers + self.extra_kmers + self.duplicate_kmers + self.wrong_colors == 0
+    }
+}
+
+impl Display for SelfTestReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Self test ({}) completed in {:.2}s",
+            if self.colored { "colored" } else { "uncolored" },
+            self.elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "Genomes kmers: {} graph kmers: {}",
+            self.genomes_kmers, self.graph_kmers
+        )?;
+        writeln!(f, "Missing kmers: {}", self.missing_kmers)?;
+        writeln!(f, "Extra kmers: {}", self.extra_kmers)?;
+        writeln!(f, "Duplicate kmers: {}", self.duplicate_kmers)?;
+        write!(f, "Wrong colors: {}", self.wrong_colors)
+    }
+}
+
+/// Checks that the unitigs contain exactly the canonical kmers of the genomes, each one once,
+/// and (if colored) that each unitig has the colors of the genomes containing its kmers.
+/// The colors are checked per unitig, as a unitig can span multiple colors subsets
+pub(crate) fn check_reconstruction(
+    dataset: &SyntheticDataset,
+    unitigs: impl Iterator<Item = Result<Unitig, GraphReadError>>,
+    k: usize,
+    colored: bool,
+) -> Result<SelfTestReport, GraphReadError> {
+    let mut report = SelfTestReport {
+        colored,
+        ..Default::default()
+    };
+
+    let mut rc_buffer = vec![];
+    let mut expected_kmers: HashMap<Vec<u8>, Vec<ColorIndexType>> = HashMap::new();
+    for (color, genome) in dataset.genomes.iter().enumerate() {
+        for kmer in genome.windows(k) {
+            let kmer = canonical_sequence(kmer, &mut rc_buffer).0;
+            let colors = expected_kmers.entry(kmer.to_vec()).or_default();
+            if colors.last() != Some(&(color as ColorIndexType)) {
+                colors.push(color as ColorIndexType);
+            }
+        }
+    }
+    report.genomes_kmers = expected_kmers.len() as u64;
+
+    let mut found_kmers = HashSet::new();
+    let mut unitig_colors = vec![];
+    for unitig in unitigs {
+        let unitig = unitig?;
+        unitig_colors.clear();
+        for kmer in unitig.sequence.windows(k) {
+            report.graph_kmers += 1;
+            let kmer = canonical_sequence(kmer, &mut rc_buffer).0;
+            match expected_kmers.get(kmer) {
+                Some(colors) => {
+                    unitig_colors.extend_from_slice(colors);
+                    if !found_kmers.insert(kmer.to_vec()) {
+                        report.duplicate_kmers += 1;
+                    }
+                }
+                None => report.extra_kmers += 1,
+            }
+        }
+
+        unitig_colors.sort_unstable();
+        unitig_colors.dedup();
+        if colored && unitig_colors != unitig.colors {
+            report.wrong_colors += 1;
+        }
+    }
+
+    report.missing_kmers = report.genomes_kmers - found_kmers.len() as u64;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_reconstruction, SyntheticDataset};
+    use crate::graph_reader::Unitig;
+    use io::concurrent::structured_sequences::canonical_sequence;
+    use io::sequences_stream::general::DynamicSequencesStream;
+    use std::collections::HashMap;
+
+    const K: usize = 11;
+
+    fn kmers_unitigs(dataset: &SyntheticDataset) -> Vec<Unitig> {
+        let mut rc_buffer = vec![];
+        let mut kmers_colors = HashMap::new();
+        for (color, genome) in dataset.genomes.iter().enumerate() {
+            for kmer in genome.windows(K) {
+                let kmer = canonical_sequence(kmer, &mut rc_buffer).0.to_vec();
+                let colors: &mut Vec<u32> = kmers_colors.entry(kmer).or_default();
+                if !colors.contains(&(color as u32)) {
+                    colors.push(color as u32);
+                }
+            }
+        }
+
+        kmers_colors
+            .into_iter()
+            .enumerate()
+            .map(|(index, (sequence, colors))| Unitig {
+                index: index as u64,
+                sequence,
+                colors,
+                links: vec![],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_cover_the_genomes() {
+        let dataset = SyntheticDataset::generate(2, 1000, 100, K, 7);
+
+        for (block, genome) in dataset.genomes.iter().enumerate() {
+            let mut reads = vec![];
+            dataset.read_block(block, false, None, &mut |seq, _| {
+                reads.push(Unitig {
+                    index: reads.len() as u64,
+                    sequence: seq.seq.to_vec(),
+                    colors: vec![],
+                    links: vec![],
+                })
+            });
+
+            let single_genome = SyntheticDataset {
+                genomes: vec![genome.clone()],
+                reads: vec![],
+            };
+            let report =
+                check_reconstruction(&single_genome, reads.into_iter().map(Ok), K, false).unwrap();
+            assert_eq!(report.missing_kmers, 0);
+            assert_eq!(report.extra_kmers, 0);
+            // The reads overlap by k - 1 bases, sharing at least one kmer
+            assert!(report.duplicate_kmers > 0);
+        }
+    }
+
+    #[test]
+    fn colored_reconstruction() {
+        let dataset = SyntheticDataset::generate(2, 1000, 100, K, 7);
+        let mut unitigs = kmers_unitigs(&dataset);
+
+        let report =
+            check_reconstruction(&dataset, unitigs.clone().into_iter().map(Ok), K, true).unwrap();
+        assert!(report.is_valid(), "{}", report);
+        assert!(unitigs.iter().any(|u| u.colors.len() == 2));
+
+        unitigs.pop();
+        unitigs[0].colors.push(5);
+        let report = check_reconstruction(&dataset, unitigs.into_iter().map(Ok), K, true).unwrap();
+        assert_eq!(report.missing_kmers, 1);
+        assert_eq!(report.wrong_colors, 1);
+        assert!(!report.is_valid());
+    }
+}
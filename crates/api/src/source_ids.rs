@@ -0,0 +1,403 @@
+use crate::comparison::{
+    for_each_partition_kmer, for_each_partition_oriented_kmer, kmers_table_bytes_per_kmer,
+};
+use io::concurrent::structured_sequences::SequenceStrand;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// Default maximum number of source records ids kept for each unitig
+pub(crate) const MAX_SOURCE_IDS_PER_UNITIG: usize = 16;
+
+/// Memory used by each kmer of the graph kept in memory with its unitig, for both
+/// the source ids and the source strands
+pub(crate) const SOURCE_IDS_BYTES_PER_KMER: u64 =
+    kmers_table_bytes_per_kmer(std::mem::size_of::<(u128, (usize, bool))>());
+
+/// Name of an input record, the first word of its FASTA/FASTQ header
+pub(crate) fn record_name(ident: &[u8]) -> String {
+    let ident = ident
+        .strip_prefix(b">")
+        .or_else(|| ident.strip_prefix(b"@"))
+        .unwrap_or(ident);
+    let name_end = ident
+        .iter()
+        .position(|b| b.is_ascii_whitespace())
+        .unwrap_or(ident.len());
+    String::from_utf8_lossy(&ident[..name_end]).to_string()
+}
+
+/// Input records that contributed kmers to each unitig, indexed by the order of the unitigs
+/// in the graph. Only the max_ids records with the smallest indexes are kept for each unitig,
+/// so the result does not depend on the order in which the kmers are processed
+pub(crate) struct UnitigsSourceIds {
+    pub records_names: Vec<String>,
+    // Sorted records indexes of each unitig
+    ids: Vec<Vec<u32>>,
+    truncated: Vec<bool>,
+    max_ids: usize,
+}
+
+impl UnitigsSourceIds {
+    fn new(max_ids: usize) -> Self {
+        Self {
+            records_names: vec![],
+            ids: vec![],
+            truncated: vec![],
+            max_ids: max_ids.max(1),
+        }
+    }
+
+    fn add(&mut self, unitig: usize, record: u32) {
+        if unitig >= self.ids.len() {
+            self.ids.resize(unitig + 1, vec![]);
+            self.truncated.resize(unitig + 1, false);
+        }
+
+        let ids = &mut self.ids[unitig];
+        if let Err(position) = ids.binary_search(&record) {
+            ids.insert(position, record);
+            if ids.len() > self.max_ids {
+                ids.pop();
+                self.truncated[unitig] = true;
+            }
+        }
+    }
+
+    /// Names of the source records of the unitig, followed by ... if some were dropped
+    pub fn unitig_sources(&self, unitig: usize) -> Option<String> {
+        let ids = self.ids.get(unitig).filter(|ids| !ids.is_empty())?;
+        let mut sources = ids
+            .iter()
+            .map(|id| self.records_names[*id as usize].as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        if self.truncated[unitig] {
+            sources.push_str(",...");
+        }
+        Some(sources)
+    }
+}
+
+/// Finds the input records sharing a canonical kmer with each unitig. The graph streams its
+/// unitigs and the input streams its records with their headers. As in the graphs comparison,
+/// the kmers are split by hash in partitions_count partitions, read once per partition
+pub(crate) fn collect_source_ids(
+    read_graph: impl Fn(&mut dyn FnMut(&[u8])),
+    read_records: impl Fn(&mut dyn FnMut(&[u8], &[u8])),
+    k: usize,
+    partitions_count: usize,
+    max_ids_per_unitig: usize,
+) -> UnitigsSourceIds {
+    let partitions_count = partitions_count.max(1) as u64;
+    let mut source_ids = UnitigsSourceIds::new(max_ids_per_unitig);
+
+    let mut kmers_unitigs = HashMap::new();
+    // Index of the sequence being streamed, plus one
+    let sequences_count = Cell::new(0usize);
+    let records_names = Cell::new(vec![]);
+
+    for partition in 0..partitions_count {
+        let read_graph_sequences = |callback: &mut dyn FnMut(&[u8])| {
+            sequences_count.set(0);
+            read_graph(&mut |sequence| {
+                sequences_count.set(sequences_count.get() + 1);
+                callback(sequence)
+            });
+        };
+        for_each_partition_kmer(
+            &read_graph_sequences,
+            k,
+            partition,
+            partitions_count,
+            |kmer| {
+                // A kmer belongs to a single unitig
+                kmers_unitigs
+                    .entry(kmer)
+                    .or_insert(sequences_count.get() - 1);
+            },
+        );
+
+        let read_records_sequences = |callback: &mut dyn FnMut(&[u8])| {
+            sequences_count.set(0);
+            let mut names = records_names.take();
+            read_records(&mut |ident, sequence| {
+                // The names are the same in each partition
+                if partition == 0 {
+                    names.push(record_name(ident));
+                }
+                sequences_count.set(sequences_count.get() + 1);
+                callback(sequence)
+            });
+            records_names.set(names);
+        };
+        for_each_partition_kmer(
+            &read_records_sequences,
+            k,
+            partition,
+            partitions_count,
+            |kmer| {
+                if let Some(unitig) = kmers_unitigs.get(&kmer) {
+                    source_ids.add(*unitig, (sequences_count.get() - 1) as u32);
+                }
+            },
+        );
+
+        kmers_unitigs.clear();
+    }
+
+    source_ids.records_names = records_names.take();
+    source_ids
+}
+
+/// Kmers of the input records found on each strand of each unitig, indexed by the order
+/// of the unitigs in the graph
+pub(crate) struct UnitigsSourceStrands {
+    // Forward and reverse kmers of each unitig
+    counts: Vec<(u64, u64)>,
+}
+
+impl UnitigsSourceStrands {
+    fn add(&mut self, unitig: usize, reversed: bool) {
+        if unitig >= self.counts.len() {
+            self.counts.resize(unitig + 1, (0, 0));
+        }
+        let counts = &mut self.counts[unitig];
+        if reversed {
+            counts.1 += 1;
+        } else {
+            counts.0 += 1;
+        }
+    }
+
+    /// The strand of the unitig, as written in the graph, matched by most of the kmers of the
+    /// input records, None if no record matches it or both the strands are matched equally
+    pub fn dominant_strand(&self, unitig: usize) -> Option<SequenceStrand> {
+        let (forward, reverse) = self.counts.get(unitig).copied().unwrap_or_default();
+        match forward.cmp(&reverse) {
+            std::cmp::Ordering::Greater => Some(SequenceStrand::Forward),
+            std::cmp::Ordering::Less => Some(SequenceStrand::Reverse),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+/// Counts the kmers of the input records matching each unitig in its orientation or in the
+/// reverse complement one. The kmers are canonical also for the graphs built in canonical mode,
+/// so the strands of the records are recovered by reading them again, split in partitions
+/// as in collect_source_ids
+pub(crate) fn collect_source_strands(
+    read_graph: impl Fn(&mut dyn FnMut(&[u8])),
+    read_records: impl Fn(&mut dyn FnMut(&[u8])),
+    k: usize,
+    partitions_count: usize,
+) -> UnitigsSourceStrands {
+    let partitions_count = partitions_count.max(1) as u64;
+    let mut source_strands = UnitigsSourceStrands { counts: vec![] };
+
+    // Unitig of each kmer, and if the kmer is reversed in it
+    let mut kmers_unitigs = HashMap::new();
+    // Index of the unitig being streamed, plus one
+    let unitigs_count = Cell::new(0usize);
+
+    for partition in 0..partitions_count {
+        let read_graph_sequences = |callback: &mut dyn FnMut(&[u8])| {
+            unitigs_count.set(0);
+            read_graph(&mut |sequence| {
+                unitigs_count.set(unitigs_count.get() + 1);
+                callback(sequence)
+            });
+        };
+        for_each_partition_oriented_kmer(
+            &read_graph_sequences,
+            k,
+            partition,
+            partitions_count,
+            |kmer, reversed| {
+                kmers_unitigs
+                    .entry(kmer)
+                    .or_insert((unitigs_count.get() - 1, reversed));
+            },
+        );
+
+        for_each_partition_oriented_kmer(
+            &read_records,
+            k,
+            partition,
+            partitions_count,
+            |kmer, reversed| {
+                if let Some((unitig, unitig_reversed)) = kmers_unitigs.get(&kmer) {
+                    source_strands.add(*unitig, reversed != *unitig_reversed);
+                }
+            },
+        );
+
+        kmers_unitigs.clear();
+    }
+
+    source_strands
+}
+
+/// Copies the graph adding to the header of each unitig the tags written by annotate,
+/// called with the index of the unitig. Returns the number of unitigs annotated with any tag
+fn annotate_unitigs_headers<W: Write>(
+    mut input: impl BufRead,
+    mut output: W,
+    mut annotate: impl FnMut(usize, &mut W) -> std::io::Result<bool>,
+) -> std::io::Result<u64> {
+    let mut unitigs_count = 0;
+    let mut annotated_count = 0;
+
+    let mut line = vec![];
+    while input.read_until(b'\n', &mut line)? > 0 {
+        if line.starts_with(b">") {
+            while line
+                .last()
+                .map(|b| b.is_ascii_whitespace())
+                .unwrap_or(false)
+            {
+                line.pop();
+            }
+            output.write_all(&line)?;
+            if annotate(unitigs_count, &mut output)? {
+                annotated_count += 1;
+            }
+            output.write_all(b"\n")?;
+            unitigs_count += 1;
+        } else {
+            output.write_all(&line)?;
+        }
+        line.clear();
+    }
+
+    output.flush()?;
+    Ok(annotated_count)
+}
+
+/// Copies the graph adding to the header of each unitig the names of its source records,
+/// as SI:Z:<name>[,<name>]*. Returns the number of annotated unitigs
+pub(crate) fn write_source_ids(
+    input: impl BufRead,
+    output: impl Write,
+    source_ids: &UnitigsSourceIds,
+) -> std::io::Result<u64> {
+    annotate_unitigs_headers(input, output, |unitig, output| {
+        Ok(match source_ids.unitig_sources(unitig) {
+            Some(sources) => {
+                write!(output, " SI:Z:{}", sources)?;
+                true
+            }
+            None => false,
+        })
+    })
+}
+
+/// Copies the graph adding to the header of each unitig the strand matched by most of the
+/// kmers of its source records, as RS:A:+ or RS:A:-. Returns the number of annotated unitigs
+pub(crate) fn write_source_strands(
+    input: impl BufRead,
+    output: impl Write,
+    source_strands: &UnitigsSourceStrands,
+) -> std::io::Result<u64> {
+    annotate_unitigs_headers(input, output, |unitig, output| {
+        Ok(match source_strands.dominant_strand(unitig) {
+            Some(strand) => {
+                write!(output, " RS:A:{}", strand.as_char())?;
+                true
+            }
+            None => false,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        collect_source_ids, collect_source_strands, write_source_ids, write_source_strands,
+    };
+    use std::io::Cursor;
+
+    const GRAPH: &str = ">0 LN:i:7\nAAACGTC\n>1 LN:i:4\nGGGT\n";
+    const RECORDS: &[(&str, &str)] = &[
+        (">first sample A", "AAACG"),
+        (">second", "ATATA"),
+        (">third", "CGTC"),
+        (">fourth", "CGTTT"),
+    ];
+
+    fn annotated_graph(max_ids: usize, partitions_count: usize) -> String {
+        let source_ids = collect_source_ids(
+            |callback: &mut dyn FnMut(&[u8])| {
+                for line in GRAPH.lines().filter(|l| !l.starts_with('>')) {
+                    callback(line.as_bytes());
+                }
+            },
+            |callback: &mut dyn FnMut(&[u8], &[u8])| {
+                for (ident, sequence) in RECORDS {
+                    callback(ident.as_bytes(), sequence.as_bytes());
+                }
+            },
+            3,
+            partitions_count,
+            max_ids,
+        );
+
+        let mut output = vec![];
+        write_source_ids(Cursor::new(GRAPH), &mut output, &source_ids).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn records_contributing_to_a_unitig_are_listed() {
+        // Every record but the second shares kmers with the first unitig,
+        // the fourth one only in reverse complement
+        for partitions_count in [1, 3] {
+            assert_eq!(
+                annotated_graph(16, partitions_count),
+                ">0 LN:i:7 SI:Z:first,third,fourth\nAAACGTC\n>1 LN:i:4\nGGGT\n"
+            );
+        }
+
+        // Only the first records are kept
+        assert_eq!(
+            annotated_graph(2, 2),
+            ">0 LN:i:7 SI:Z:first,third,...\nAAACGTC\n>1 LN:i:4\nGGGT\n"
+        );
+    }
+
+    fn strands_annotated_graph(records: &[&str]) -> String {
+        let source_strands = collect_source_strands(
+            |callback: &mut dyn FnMut(&[u8])| {
+                for line in GRAPH.lines().filter(|l| !l.starts_with('>')) {
+                    callback(line.as_bytes());
+                }
+            },
+            |callback: &mut dyn FnMut(&[u8])| {
+                for sequence in records {
+                    callback(sequence.as_bytes());
+                }
+            },
+            3,
+            2,
+        );
+
+        let mut output = vec![];
+        write_source_strands(Cursor::new(GRAPH), &mut output, &source_strands).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn unitigs_are_tagged_with_the_strand_of_their_records() {
+        // All the records are taken from the forward strand of the first unitig
+        assert_eq!(
+            strands_annotated_graph(&["AAACG", "CGTC", "AACGT"]),
+            ">0 LN:i:7 RS:A:+\nAAACGTC\n>1 LN:i:4\nGGGT\n"
+        );
+
+        // Reverse strand records, the second unitig is matched equally on both the strands
+        assert_eq!(
+            strands_annotated_graph(&["GACGTTT", "ACCC", "GGGT"]),
+            ">0 LN:i:7 RS:A:-\nAAACGTC\n>1 LN:i:4\nGGGT\n"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use crate::graph_reader::{GraphReadError, Unitig};
+use io::concurrent::structured_sequences::{is_canonical_sequence, write_reverse_complement};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+
+fn orientation_sign(reversed: bool) -> char {
+    if reversed {
+        '-'
+    } else {
+        '+'
+    }
+}
+
+/// Adds the kmers of a query sequence (canonical unless forward_only), skipping the ones with bases
+/// other than ACGT
+pub(crate) fn add_query_kmers(
+    sequence: &[u8],
+    k: usize,
+    forward_only: bool,
+    kmers: &mut HashSet<Vec<u8>>,
+) {
+    let mut rc_kmer = vec![];
+    for stretch in sequence.split(|b| !matches!(b, b'A' | b'C' | b'G' | b'T')) {
+        for kmer in stretch.windows(k) {
+            if forward_only || is_canonical_sequence(kmer) {
+                kmers.insert(kmer.to_vec());
+            } else {
+                write_reverse_complement(kmer, &mut rc_kmer);
+                kmers.insert(rc_kmer.clone());
+            }
+        }
+    }
+}
+
+/// The unitigs sharing at least one kmer with the query, and the targets of the links of all
+/// the unitigs of the graph
+pub(crate) struct QueryMatches {
+    pub matching_unitigs: HashSet<u64>,
+    pub adjacency: HashMap<u64, Vec<u64>>,
+}
+
+pub(crate) fn find_query_matches(
+    graph: impl Iterator<Item = Result<Unitig, GraphReadError>>,
+    query_kmers: &HashSet<Vec<u8>>,
+    k: usize,
+    forward_only: bool,
+) -> Result<QueryMatches, GraphReadError> {
+    let mut matching_unitigs = HashSet::new();
+    let mut adjacency = HashMap::new();
+    let mut rc_kmer = vec![];
+
+    for unitig in graph {
+        let unitig = unitig?;
+        let matches_query = unitig.sequence.windows(k).any(|kmer| {
+            if forward_only || is_canonical_sequence(kmer) {
+                query_kmers.contains(kmer)
+            } else {
+                write_reverse_complement(kmer, &mut rc_kmer);
+                query_kmers.contains(&rc_kmer)
+            }
+        });
+        if matches_query {
+            matching_unitigs.insert(unitig.index);
+        }
+        if !unitig.links.is_empty() {
+            adjacency.insert(
+                unitig.index,
+                unitig.links.iter().map(|link| link.target).collect(),
+            );
+        }
+    }
+
+    Ok(QueryMatches {
+        matching_unitigs,
+        adjacency,
+    })
+}
+
+/// Expands the matching unitigs following up to hops links (in any direction)
+pub(crate) fn expand_neighborhood(matches: &QueryMatches, hops: usize) -> HashSet<u64> {
+    let mut selected = matches.matching_unitigs.clone();
+    let mut queue: VecDeque<_> = selected.iter().map(|unitig| (*unitig, 0)).collect();
+
+    while let Some((unitig, distance)) = queue.pop_front() {
+        if distance == hops {
+            continue;
+        }
+        for target in matches.adjacency.get(&unitig).into_iter().flatten() {
+            if selected.insert(*target) {
+                queue.push_back((*target, distance + 1));
+            }
+        }
+    }
+
+    selected
+}
+
+/// Writes the selected unitigs and the links between them in GFA format. Each link is listed
+/// in the graph from both its ends, only one of the two is written. Returns the segments and links counts
+pub(crate) fn write_subgraph_gfa(
+    graph: impl Iterator<Item = Result<Unitig, GraphReadError>>,
+    selected: &HashSet<u64>,
+    k: usize,
+    mut output: impl Write,
+) -> std::io::Result<(u64, u64)> {
+    let mut segments = vec![];
+    let mut links = vec![];
+
+    for unitig in graph {
+        let unitig = unitig?;
+        if !selected.contains(&unitig.index) {
+            continue;
+        }
+        for link in &unitig.links {
+            if selected.contains(&link.target)
+                && (unitig.index, link.from_reverse) <= (link.target, !link.to_reverse)
+            {
+                links.push((unitig.index, link.clone()));
+            }
+        }
+        segments.push((unitig.index, unitig.sequence));
+    }
+
+    writeln!(output, "H\tVN:Z:1.0")?;
+    for (index, sequence) in &segments {
+        write!(output, "S\t{}\t", index)?;
+        output.write_all(sequence)?;
+        writeln!(output, "\tLN:i:{}", sequence.len())?;
+    }
+    for (index, link) in &links {
+        writeln!(
+            output,
+            "L\t{}\t{}\t{}\t{}\t{}M",
+            index,
+            orientation_sign(link.from_reverse),
+            link.target,
+            orientation_sign(link.to_reverse),
+            k - 1
+        )?;
+    }
+
+    Ok((segments.len() as u64, links.len() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_query_kmers, expand_neighborhood, find_query_matches, write_subgraph_gfa};
+    use crate::graph_reader::{FastaUnitigs, GraphReader};
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    // A chain 0 - 1 - 2 - 3 - 4 with k = 4, 2 is the central unitig
+    const GRAPH: &str = concat!(
+        ">0 LN:i:6 L:+:1:+\nAACCGG\n",
+        ">1 LN:i:6 L:-:0:- L:+:2:+\nCGGTAT\n",
+        ">2 LN:i:6 L:-:1:- L:+:3:+\nTATGGA\n",
+        ">3 LN:i:6 L:-:2:- L:+:4:+\nGGACTC\n",
+        ">4 LN:i:6 L:-:3:-\nCTCAGT\n",
+    );
+
+    fn open_graph() -> GraphReader<FastaUnitigs<Cursor<Vec<u8>>>> {
+        GraphReader::new(Cursor::new(GRAPH.as_bytes().to_vec()), None)
+    }
+
+    #[test]
+    fn extract_central_unitig_with_its_neighbors() {
+        const K: usize = 4;
+
+        // A query matching only the central unitig, given in reverse complement
+        let mut query_kmers = HashSet::new();
+        add_query_kmers(b"CCATA", K, false, &mut query_kmers);
+
+        let matches = find_query_matches(open_graph(), &query_kmers, K, false).unwrap();
+        assert_eq!(matches.matching_unitigs, HashSet::from([2]));
+
+        let selected = expand_neighborhood(&matches, 1);
+        assert_eq!(selected, HashSet::from([1, 2, 3]));
+
+        let mut output = vec![];
+        let (segments_count, links_count) =
+            write_subgraph_gfa(open_graph(), &selected, K, &mut output).unwrap();
+        assert_eq!((segments_count, links_count), (3, 2));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "H\tVN:Z:1.0\n",
+                "S\t1\tCGGTAT\tLN:i:6\n",
+                "S\t2\tTATGGA\tLN:i:6\n",
+                "S\t3\tGGACTC\tLN:i:6\n",
+                "L\t1\t+\t2\t+\t3M\n",
+                "L\t2\t+\t3\t+\t3M\n",
+            )
+        );
+
+        assert_eq!(expand_neighborhood(&matches, 0), HashSet::from([2]));
+        assert_eq!(expand_neighborhood(&matches, 2).len(), 5);
+    }
+}
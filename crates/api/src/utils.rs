@@ -9,14 +9,21 @@ pub enum HashType {
     RabinKarp128 = 4,
 }
 
-pub(crate) fn get_hash_static_id(
-    hash_type: HashType,
-    k: usize,
-    forward_only: bool,
-) -> DynamicDispatch<()> {
-    use hashes::*;
+impl HashType {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            HashType::Auto => "auto",
+            HashType::SeqHash => "seqhash",
+            HashType::RabinKarp32 => "rabin-karp32",
+            HashType::RabinKarp64 => "rabin-karp64",
+            HashType::RabinKarp128 => "rabin-karp128",
+        }
+    }
+}
 
-    let hash_type = match hash_type {
+/// The hash actually used for the given k, replacing the automatic choice
+pub(crate) fn resolve_hash_type(hash_type: HashType, k: usize) -> HashType {
+    match hash_type {
         HashType::Auto => {
             if k <= 64 {
                 HashType::SeqHash
@@ -25,9 +32,17 @@ pub(crate) fn get_hash_static_id(
             }
         }
         x => x,
-    };
+    }
+}
 
-    match hash_type {
+pub(crate) fn get_hash_static_id(
+    hash_type: HashType,
+    k: usize,
+    forward_only: bool,
+) -> DynamicDispatch<()> {
+    use hashes::*;
+
+    match resolve_hash_type(hash_type, k) {
         HashType::SeqHash => {
             if k <= 8 {
                 if forward_only {
@@ -0,0 +1,300 @@
+use crate::graph_reader::{parse_header, GraphLink};
+use io::concurrent::structured_sequences::{is_canonical_sequence, write_reverse_complement};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use xxhash_rust::xxh3::xxh3_128;
+
+/// Counts of the invariants violations found in a graph
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GraphValidationReport {
+    pub unitigs_count: u64,
+    pub links_count: u64,
+    /// Links referencing a unitig not present in the graph
+    pub missing_link_targets: u64,
+    /// Links whose k-1 overlap does not match between the two unitigs
+    pub mismatched_overlaps: u64,
+    /// Unitigs equal (up to reverse complement) to another one
+    pub duplicate_unitigs: u64,
+    /// Unitigs with the same index of a previous one
+    pub duplicate_indices: u64,
+    /// Colors subsets not present in the colormap
+    pub invalid_colors: u64,
+    /// Headers that cannot be parsed
+    pub malformed_headers: u64,
+}
+
+impl GraphValidationReport {
+    pub fn violations_count(&self) -> u64 {
+        self.missing_link_targets
+            + self.mismatched_overlaps
+            + self.duplicate_unitigs
+            + self.duplicate_indices
+            + self.invalid_colors
+            + self.malformed_headers
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.violations_count() == 0
+    }
+}
+
+impl Display for GraphValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Checked {} unitigs and {} links",
+            self.unitigs_count, self.links_count
+        )?;
+        writeln!(f, "Missing link targets: {}", self.missing_link_targets)?;
+        writeln!(f, "Mismatched overlaps: {}", self.mismatched_overlaps)?;
+        writeln!(f, "Duplicate unitigs: {}", self.duplicate_unitigs)?;
+        writeln!(f, "Duplicate indices: {}", self.duplicate_indices)?;
+        writeln!(f, "Invalid colors: {}", self.invalid_colors)?;
+        write!(f, "Malformed headers: {}", self.malformed_headers)
+    }
+}
+
+/// Error of a graph validation that cannot be started
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphValidationError {
+    InvalidKmerLength(usize),
+}
+
+impl Display for GraphValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphValidationError::InvalidKmerLength(kmer_length) => write!(
+                f,
+                "Invalid k-mers length {}, it must be at least 2",
+                kmer_length
+            ),
+        }
+    }
+}
+
+impl Error for GraphValidationError {}
+
+/// Checks the invariants of a graph streaming its unitigs: every link references an existing unitig,
+/// the linked unitigs overlap by k-1 bases, no unitig index or sequence is repeated and
+/// (if a colormap is available) all the colors subsets exist.
+/// Only the k-1 bases at the ends of each unitig and a hash of its sequence are kept in memory
+pub(crate) struct GraphValidator {
+    k: usize,
+    colors_subsets_count: Option<u64>,
+    report: GraphValidationReport,
+    /// First and last k-1 bases of each unitig, None if the unitig is shorter than k-1 bases
+    ends: HashMap<u64, Option<(Vec<u8>, Vec<u8>)>>,
+    canonical_hashes: HashSet<u128>,
+    links: Vec<(u64, GraphLink)>,
+    colors: Vec<(u64, u64)>,
+    rc_sequence: Vec<u8>,
+}
+
+impl GraphValidator {
+    pub fn new(k: usize, colors_subsets_count: Option<u64>) -> Result<Self, GraphValidationError> {
+        if k < 2 {
+            return Err(GraphValidationError::InvalidKmerLength(k));
+        }
+
+        Ok(Self {
+            k,
+            colors_subsets_count,
+            report: GraphValidationReport::default(),
+            ends: HashMap::new(),
+            canonical_hashes: HashSet::new(),
+            links: vec![],
+            colors: vec![],
+            rc_sequence: vec![],
+        })
+    }
+
+    pub fn add_unitig(&mut self, header: &[u8], sequence: &[u8]) {
+        self.report.unitigs_count += 1;
+
+        let mut links = vec![];
+        self.colors.clear();
+        let index = match parse_header(header, &mut links, &mut self.colors) {
+            Some(index) => index,
+            None => {
+                self.report.malformed_headers += 1;
+                return;
+            }
+        };
+
+        // The links of a repeated index cannot be checked, as their source is ambiguous
+        if self.ends.contains_key(&index) {
+            self.report.duplicate_indices += 1;
+            return;
+        }
+
+        if let Some(subsets_count) = self.colors_subsets_count {
+            self.report.invalid_colors += self
+                .colors
+                .iter()
+                .filter(|(subset, _)| *subset >= subsets_count)
+                .count() as u64;
+        }
+
+        let canonical_hash = if is_canonical_sequence(sequence) {
+            xxh3_128(sequence)
+        } else {
+            write_reverse_complement(sequence, &mut self.rc_sequence);
+            xxh3_128(&self.rc_sequence)
+        };
+
+        if !self.canonical_hashes.insert(canonical_hash) {
+            self.report.duplicate_unitigs += 1;
+        }
+
+        let overlap = self.k - 1;
+        self.ends.insert(
+            index,
+            (sequence.len() >= overlap).then(|| {
+                (
+                    sequence[..overlap].to_vec(),
+                    sequence[sequence.len() - overlap..].to_vec(),
+                )
+            }),
+        );
+        self.links
+            .extend(links.into_iter().map(|link| (index, link)));
+    }
+
+    pub fn finish(mut self) -> GraphValidationReport {
+        self.report.links_count = self.links.len() as u64;
+
+        let mut source_end = vec![];
+        let mut target_begin = vec![];
+
+        for (index, link) in self.links {
+            let target = match self.ends.get(&link.target) {
+                Some(target) => target,
+                None => {
+                    self.report.missing_link_targets += 1;
+                    continue;
+                }
+            };
+
+            let (source, target) = match (&self.ends[&index], target) {
+                (Some(source), Some(target)) => (source, target),
+                _ => {
+                    self.report.mismatched_overlaps += 1;
+                    continue;
+                }
+            };
+
+            // The end of the (oriented) source unitig must match the beginning of the (oriented) target
+            if link.from_reverse {
+                write_reverse_complement(&source.0, &mut source_end);
+            } else {
+                source_end.clear();
+                source_end.extend_from_slice(&source.1);
+            }
+
+            if link.to_reverse {
+                write_reverse_complement(&target.1, &mut target_begin);
+            } else {
+                target_begin.clear();
+                target_begin.extend_from_slice(&target.0);
+            }
+
+            if source_end != target_begin {
+                self.report.mismatched_overlaps += 1;
+            }
+        }
+
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GraphValidationError, GraphValidationReport, GraphValidator};
+
+    const K: usize = 4;
+
+    fn validate(
+        graph: &[(&str, &str)],
+        colors_subsets_count: Option<u64>,
+    ) -> GraphValidationReport {
+        let mut validator = GraphValidator::new(K, colors_subsets_count).unwrap();
+        for (header, sequence) in graph {
+            validator.add_unitig(header.as_bytes(), sequence.as_bytes());
+        }
+        validator.finish()
+    }
+
+    // ACGTA -> GTAAC, with the reverse link from the reverse complement of GTAAC
+    const VALID_GRAPH: &[(&str, &str)] = &[
+        (">0 LN:i:5 L:+:1:+ C:0:2", "ACGTA"),
+        (">1 LN:i:5 L:-:0:- C:1:2", "GTAAC"),
+        (">2 LN:i:4 C:1:1", "TTTG"),
+    ];
+
+    #[test]
+    fn valid_graph() {
+        let report = validate(VALID_GRAPH, Some(2));
+        assert!(report.is_valid(), "{}", report);
+        assert_eq!(report.unitigs_count, 3);
+        assert_eq!(report.links_count, 2);
+    }
+
+    #[test]
+    fn missing_link_target() {
+        let mut graph = VALID_GRAPH.to_vec();
+        graph[2].0 = ">2 LN:i:4 L:+:7:+ C:1:1";
+        let report = validate(&graph, Some(2));
+        assert_eq!(report.missing_link_targets, 1);
+        assert_eq!(report.violations_count(), 1);
+    }
+
+    #[test]
+    fn mismatched_overlap() {
+        let mut graph = VALID_GRAPH.to_vec();
+        graph[0].0 = ">0 LN:i:5 L:+:1:- C:0:2";
+        let report = validate(&graph, Some(2));
+        assert_eq!(report.mismatched_overlaps, 1);
+        assert_eq!(report.violations_count(), 1);
+    }
+
+    #[test]
+    fn duplicate_unitig() {
+        let mut graph = VALID_GRAPH.to_vec();
+        // Reverse complement of unitig 1
+        graph.push((">3 LN:i:5", "GTTAC"));
+        let report = validate(&graph, Some(2));
+        assert_eq!(report.duplicate_unitigs, 1);
+        assert_eq!(report.violations_count(), 1);
+    }
+
+    #[test]
+    fn invalid_color() {
+        let mut graph = VALID_GRAPH.to_vec();
+        graph[2].0 = ">2 LN:i:4 C:a:1";
+        let report = validate(&graph, Some(2));
+        assert_eq!(report.invalid_colors, 1);
+        assert_eq!(report.violations_count(), 1);
+
+        // Colors are not checked without a colormap
+        assert!(validate(&graph, None).is_valid());
+    }
+
+    #[test]
+    fn duplicate_index() {
+        let mut graph = VALID_GRAPH.to_vec();
+        graph.push((">1 LN:i:4", "CCCA"));
+        let report = validate(&graph, Some(2));
+        assert_eq!(report.duplicate_indices, 1);
+        assert_eq!(report.violations_count(), 1);
+    }
+
+    #[test]
+    fn invalid_kmer_length() {
+        assert_eq!(
+            GraphValidator::new(0, None).err(),
+            Some(GraphValidationError::InvalidKmerLength(0))
+        );
+        assert!(GraphValidator::new(1, None).is_err());
+    }
+}
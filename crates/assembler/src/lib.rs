@@ -4,34 +4,57 @@
 use crate::pipeline::build_unitigs::build_unitigs;
 use crate::pipeline::compute_matchtigs::{compute_matchtigs_thread, MatchtigsStorageBackend};
 use crate::pipeline::hashes_sorting::hashes_sorting;
-use crate::pipeline::links_compaction::links_compaction;
+use crate::pipeline::links_compaction::{
+    checkpointed_round_buckets, compaction_round_dump_path, compaction_round_maps_paths,
+    links_compaction, links_compaction_completed, merge_compaction_round_maps, sync_file,
+    CompactionTrend, LinksCompactionBuffers, LinksCompactionCheckpoint,
+};
 use crate::pipeline::maximal_unitig_links::build_maximal_unitigs_links;
 use crate::pipeline::reorganize_reads::reorganize_reads;
+use crate::structs::unitigs_paths::UnitigsPathsWriter;
 use ::dynamic_dispatch::dynamic_dispatch;
 use assembler_kmers_merge::structs::RetType;
+use assembler_minimizer_bucketing::{MaskedIntervals, MinimizersDump};
+use colors::colors_manager::ColorMapReader;
 use colors::colors_manager::ColorsManager;
 use colors::colors_manager::ColorsMergeManager;
+use colors::storage::deserializer::ColorsDeserializer;
+use colors::DefaultColorsSerializer;
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    INTERMEDIATE_COMPRESSION_LEVEL_FAST, INTERMEDIATE_COMPRESSION_LEVEL_SLOW, KEEP_FILES,
-    MAXIMUM_SECOND_BUCKETS_LOG, MINIMUM_LOG_DELTA_TIME,
+    get_compression_level_info, get_memory_mode, get_temp_buckets_path, get_temp_file_path,
+    remove_orphan_temp_buckets, spread_temp_buckets, ColorIndexType, SwapPriority,
+    CHECKPOINT_CHECKSUMS, DEBUG_MINIMIZERS_DUMP, INTERMEDIATE_COMPRESSION_LEVEL_FAST,
+    INTERMEDIATE_COMPRESSION_LEVEL_SLOW, KEEP_FILES, MAXIMUM_SECOND_BUCKETS_LOG, MAX_INPUT_WEIGHT,
+    MINIMUM_LOG_DELTA_TIME, OUTPUT_COMPRESSION_LEVEL, SYNC_CHECKPOINTS,
 };
 use hashes::{HashFunctionFactory, MinimizerHashFunctionFactory};
 use io::concurrent::structured_sequences::binary::StructSeqBinaryWriter;
+use io::concurrent::structured_sequences::color_split::{
+    color_files_paths, ColorFilesWriter, ColorSplit, ColorSplitWriter, MAX_OPEN_COLOR_FILES,
+};
 use io::concurrent::structured_sequences::fasta::FastaWriter;
-use io::concurrent::structured_sequences::StructuredSequenceWriter;
+use io::concurrent::structured_sequences::fastg::StructSeqFastgWriter;
+use io::concurrent::structured_sequences::sharded::{ShardedOutput, ShardedWriter};
+use io::concurrent::structured_sequences::{
+    IdentSequenceWriter, OutputFlushInterval, StructuredSequenceBackend, StructuredSequenceWriter,
+};
 use io::sequences_stream::general::GeneralSequenceBlockData;
-use io::{compute_stats_from_input_blocks, generate_bucket_names};
+use io::structs::unitig_link::LinksCorruptionError;
+use io::varint::DecodeError;
+use io::{choose_buckets_count_log, compute_stats_from_input_blocks, generate_bucket_names};
 use parallel_processor::buckets::concurrent::BucketsThreadBuffer;
 use parallel_processor::buckets::writers::compressed_binary_writer::CompressedCheckpointSize;
 use parallel_processor::buckets::writers::lock_free_binary_writer::LockFreeBinaryWriter;
 use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::memory_data_size::MemoryDataSize;
+use parallel_processor::memory_fs::file::internal::MemoryFileMode;
 use parallel_processor::memory_fs::{MemoryFs, RemoveFileMode};
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::fs::remove_file;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Instant;
@@ -40,6 +63,10 @@ mod pipeline;
 mod structs;
 
 pub use pipeline::compute_matchtigs::MatchtigMode;
+pub use pipeline::links_compaction::{
+    compaction_round_dump_path, CheckpointWriteError, CompactionNotConvergedError,
+    CompactionResumeError,
+};
 
 #[derive(Clone, PartialEq, PartialOrd)]
 pub enum AssemblerStartingStep {
@@ -52,6 +79,339 @@ pub enum AssemblerStartingStep {
     MaximalUnitigsLinks = 6,
 }
 
+/// Error raised when the graph cannot be built
+#[derive(Clone, Debug)]
+pub enum AssemblerError {
+    /// The pieces of a split unitig overlap by k - 1 bases, so they must be at least k bases long
+    MaxUnitigLengthTooShort {
+        max_unitig_length: usize,
+        k: usize,
+    },
+    /// The colors are tracked on the whole unitig and cannot be split together with it
+    MaxUnitigLengthWithColors,
+    LinksCorruption(LinksCorruptionError),
+    LinkMappingsCorruption(DecodeError),
+    /// The links compaction reached the maximum iterations count, no graph is written
+    CompactionNotConverged(CompactionNotConvergedError),
+    /// The checkpoints of the links compaction do not allow to resume from the requested iteration
+    CompactionResume(CompactionResumeError),
+    /// A links compaction checkpoint could not be written, the compaction cannot be resumed from it
+    CheckpointWrite(CheckpointWriteError),
+    /// The output can be split by color only when building a colored graph
+    ColorSplitWithoutColors,
+    /// A weight is required for each input stream
+    InputWeightsCountMismatch {
+        weights_count: usize,
+        inputs_count: usize,
+    },
+    /// The weight of an input must be positive
+    ZeroInputWeight {
+        input_index: usize,
+    },
+    /// The weight of an input must not exceed MAX_INPUT_WEIGHT
+    InputWeightTooLarge {
+        input_index: usize,
+        weight: usize,
+    },
+    /// The masking BED file cannot be read or is malformed
+    MaskingBed {
+        path: PathBuf,
+        message: String,
+    },
+    /// The minimizers dump file cannot be created or written
+    MinimizersDump {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl Display for AssemblerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssemblerError::MaxUnitigLengthTooShort {
+                max_unitig_length,
+                k,
+            } => write!(
+                f,
+                "The maximum unitig length ({}) must be at least k ({})",
+                max_unitig_length, k
+            ),
+            AssemblerError::MaxUnitigLengthWithColors => write!(
+                f,
+                "The maximum unitig length is not supported when building a colored graph"
+            ),
+            AssemblerError::LinksCorruption(error) => write!(f, "{}", error),
+            AssemblerError::LinkMappingsCorruption(error) => {
+                write!(f, "Corrupted link mappings bucket: {}", error)
+            }
+            AssemblerError::CompactionNotConverged(error) => write!(f, "{}", error),
+            AssemblerError::CompactionResume(error) => write!(f, "{}", error),
+            AssemblerError::CheckpointWrite(error) => write!(f, "{}", error),
+            AssemblerError::ColorSplitWithoutColors => write!(
+                f,
+                "The output can be split by color only when building a colored graph"
+            ),
+            AssemblerError::InputWeightsCountMismatch {
+                weights_count,
+                inputs_count,
+            } => write!(
+                f,
+                "{} input weights were given for {} inputs, a weight is required for each input",
+                weights_count, inputs_count
+            ),
+            AssemblerError::ZeroInputWeight { input_index } => write!(
+                f,
+                "The weight of the input {} must be positive",
+                input_index
+            ),
+            AssemblerError::InputWeightTooLarge {
+                input_index,
+                weight,
+            } => write!(
+                f,
+                "The weight {} of the input {} is larger than the maximum weight {}",
+                weight, input_index, MAX_INPUT_WEIGHT
+            ),
+            AssemblerError::MaskingBed { path, message } => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+            AssemblerError::MinimizersDump { path, message } => write!(
+                f,
+                "Cannot write the minimizers dump file {}: {}",
+                path.display(),
+                message
+            ),
+        }
+    }
+}
+
+impl Error for AssemblerError {}
+
+impl From<LinksCorruptionError> for AssemblerError {
+    fn from(error: LinksCorruptionError) -> Self {
+        AssemblerError::LinksCorruption(error)
+    }
+}
+
+impl From<DecodeError> for AssemblerError {
+    fn from(error: DecodeError) -> Self {
+        AssemblerError::LinkMappingsCorruption(error)
+    }
+}
+
+impl From<CompactionNotConvergedError> for AssemblerError {
+    fn from(error: CompactionNotConvergedError) -> Self {
+        AssemblerError::CompactionNotConverged(error)
+    }
+}
+
+impl From<CompactionResumeError> for AssemblerError {
+    fn from(error: CompactionResumeError) -> Self {
+        AssemblerError::CompactionResume(error)
+    }
+}
+
+impl From<CheckpointWriteError> for AssemblerError {
+    fn from(error: CheckpointWriteError) -> Self {
+        AssemblerError::CheckpointWrite(error)
+    }
+}
+
+/// Checks the parameters of a run, before starting any work
+fn check_parameters(
+    k: usize,
+    max_unitig_length: Option<usize>,
+    colors_enabled: bool,
+    split_output_by_color: bool,
+    inputs_count: usize,
+    input_weights: Option<&[usize]>,
+) -> Result<(), AssemblerError> {
+    if split_output_by_color && !colors_enabled {
+        return Err(AssemblerError::ColorSplitWithoutColors);
+    }
+    if let Some(input_weights) = input_weights {
+        if input_weights.len() != inputs_count {
+            return Err(AssemblerError::InputWeightsCountMismatch {
+                weights_count: input_weights.len(),
+                inputs_count,
+            });
+        }
+        if let Some(input_index) = input_weights.iter().position(|weight| *weight == 0) {
+            return Err(AssemblerError::ZeroInputWeight { input_index });
+        }
+        if let Some((input_index, weight)) = input_weights
+            .iter()
+            .enumerate()
+            .find(|(_, weight)| **weight > MAX_INPUT_WEIGHT)
+        {
+            return Err(AssemblerError::InputWeightTooLarge {
+                input_index,
+                weight: *weight,
+            });
+        }
+    }
+    if let Some(max_unitig_length) = max_unitig_length {
+        if colors_enabled {
+            return Err(AssemblerError::MaxUnitigLengthWithColors);
+        }
+        if max_unitig_length < k {
+            return Err(AssemblerError::MaxUnitigLengthTooShort {
+                max_unitig_length,
+                k,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Loads the intervals of the masking BED file, if any
+fn load_masked_intervals(
+    masking_bed: Option<&Path>,
+) -> Result<Option<Arc<MaskedIntervals>>, AssemblerError> {
+    let Some(masking_bed) = masking_bed else {
+        return Ok(None);
+    };
+    let intervals =
+        MaskedIntervals::load_bed(masking_bed).map_err(|err| AssemblerError::MaskingBed {
+            path: masking_bed.to_path_buf(),
+            message: err.to_string(),
+        })?;
+    log::info!(
+        "Masking {} intervals from {}",
+        intervals.intervals_count(),
+        masking_bed.display()
+    );
+    Ok(Some(Arc::new(intervals)))
+}
+
+/// Creates the file of the minimizers dump, if requested as (file, sample interval)
+fn create_minimizers_dump(
+    dump_config: Option<&(PathBuf, u64)>,
+) -> Result<Option<Arc<MinimizersDump>>, AssemblerError> {
+    let Some((path, sample_interval)) = dump_config else {
+        return Ok(None);
+    };
+    let dump = MinimizersDump::create(path, *sample_interval).map_err(|err| {
+        AssemblerError::MinimizersDump {
+            path: path.clone(),
+            message: err.to_string(),
+        }
+    })?;
+    Ok(Some(Arc::new(dump)))
+}
+
+/// Splitting of the final unitigs to a <graph name>.<color name>.fa file for each color,
+/// in the directory of the output file. The colormap must be already finalized
+fn create_color_split(output_file: &Path) -> ColorSplit {
+    let mut colormap = ColorsDeserializer::<DefaultColorsSerializer>::new(
+        output_file.with_extension("colors.dat"),
+        true,
+    );
+    let color_names: Vec<_> = (0..colormap.colors_count())
+        .map(|color| {
+            colormap
+                .get_color_name(color as ColorIndexType, true)
+                .to_string()
+        })
+        .collect();
+
+    // The graph name without its extensions, as in graph.fasta.lz4
+    let graph_name = output_file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let graph_name = graph_name.split('.').next().unwrap_or_default();
+    let output_dir = output_file.parent().unwrap_or(Path::new(""));
+
+    ColorSplit::new(
+        ColorFilesWriter::new(
+            color_files_paths(output_dir, graph_name, &color_names),
+            MAX_OPEN_COLOR_FILES,
+        ),
+        Box::new(move |subset, colors| colormap.get_color_mappings(subset, colors)),
+    )
+}
+
+/// Creates the writer of the final unitigs, with each output shard written by the backend returned
+/// by create_backend for its path and compression level
+fn create_final_unitigs_writer<
+    ColorInfo: IdentSequenceWriter,
+    LinksInfo: IdentSequenceWriter,
+    Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+>(
+    output_file: &Path,
+    output_shards: usize,
+    canonical_output: bool,
+    strand_tags: bool,
+    split_output_by_color: bool,
+    create_backend: impl Fn(&Path, u32) -> Backend,
+) -> StructuredSequenceWriter<
+    ColorInfo,
+    LinksInfo,
+    ColorSplitWriter<ColorInfo, LinksInfo, ShardedWriter<ColorInfo, LinksInfo, Backend>>,
+> {
+    StructuredSequenceWriter::new(ColorSplitWriter::new(
+        ShardedWriter::new(output_file, output_shards, |path| {
+            create_backend(path, OUTPUT_COMPRESSION_LEVEL.load(Ordering::Relaxed))
+        }),
+        split_output_by_color.then(|| create_color_split(output_file)),
+    ))
+    .with_canonical_output(canonical_output)
+    .with_strand_tags(strand_tags)
+    .with_flush_interval(OutputFlushInterval::from_config())
+}
+
+/// The parameters of an assembler run, besides its inputs, output and resources
+pub struct AssemblerOptions {
+    /// Minimum multiplicity required to keep a kmer
+    pub min_multiplicity: usize,
+    /// Forces the log2 of the buckets count, instead of choosing it from the input size
+    pub buckets_count_log: Option<usize>,
+    /// Links compaction iteration to start from, when starting from the links compaction
+    pub loopit_number: Option<usize>,
+    /// Compression level of the intermediate files
+    pub default_compression_level: Option<u32>,
+    /// Writes the links between the maximal unitigs
+    pub generate_maximal_unitigs_links: bool,
+    /// Computes the matchtigs of the given kind instead of the unitigs
+    pub compute_tigs_mode: Option<MatchtigMode>,
+    /// Stops after computing the buckets statistics
+    pub only_bstats: bool,
+    /// Splits the unitigs longer than this value in pieces overlapping by k - 1 bases
+    pub max_unitig_length: Option<usize>,
+    /// Debug output of the partial unitigs composing each final unitig
+    pub output_unitigs_paths: bool,
+    /// Saturates the kmers counts at this value
+    pub max_kmer_count: Option<usize>,
+    /// Number of shards of the output graph
+    pub output_shards: usize,
+    /// Assigns a separate color to each input
+    pub color_by_file: bool,
+    /// Writes each unitig in canonical orientation
+    pub canonical_output: bool,
+    /// Fails if the links compaction has not converged after this number of iterations
+    pub max_compaction_iterations: Option<usize>,
+    /// Dumps the unitigs completed in each links compaction round
+    pub dump_compaction_rounds: bool,
+    /// Checkpoints each links compaction round
+    pub checkpoint_compaction: bool,
+    /// Writes the graph in the FASTG format
+    pub fastg_output: bool,
+    /// Tags each unitig with its strand relative to the input reads, reversed only if flipped
+    /// by the canonical output
+    pub strand_tags: bool,
+    /// BED file of the intervals masked with N before the bucketing
+    pub masking_bed: Option<PathBuf>,
+    /// Reference whose kmers are kept with reference_min_multiplicity
+    pub reference: Option<PathBuf>,
+    /// Minimum multiplicity required to keep a kmer of the reference
+    pub reference_min_multiplicity: usize,
+    /// Weight of each input, counting its kmers this number of times
+    pub input_weights: Option<Vec<usize>>,
+    /// Also writes the unitigs of each color to a separate file
+    pub split_output_by_color: bool,
+}
+
 #[dynamic_dispatch(BucketingHash = [
     hashes::cn_nthash::CanonicalNtHashIteratorFactory,
     #[cfg(not(feature = "devel-build"))] hashes::fw_nthash::ForwardNtHashIteratorFactory
@@ -88,21 +448,61 @@ pub fn run_assembler<
     output_file: PathBuf,
     temp_dir: Option<PathBuf>,
     threads_count: usize,
-    min_multiplicity: usize,
-    buckets_count_log: Option<usize>,
-    loopit_number: Option<usize>,
-    default_compression_level: Option<u32>,
-    generate_maximal_unitigs_links: bool,
-    compute_tigs_mode: Option<MatchtigMode>,
-    only_bstats: bool,
-) -> PathBuf {
+    options: AssemblerOptions,
+) -> Result<ShardedOutput, AssemblerError> {
+    let AssemblerOptions {
+        min_multiplicity,
+        buckets_count_log,
+        loopit_number,
+        default_compression_level,
+        generate_maximal_unitigs_links,
+        compute_tigs_mode,
+        only_bstats,
+        max_unitig_length,
+        output_unitigs_paths,
+        max_kmer_count,
+        output_shards,
+        color_by_file,
+        canonical_output,
+        max_compaction_iterations,
+        dump_compaction_rounds,
+        checkpoint_compaction,
+        fastg_output,
+        strand_tags,
+        masking_bed,
+        reference,
+        reference_min_multiplicity,
+        input_weights,
+        split_output_by_color,
+    } = options;
+
+    check_parameters(
+        k,
+        max_unitig_length,
+        AssemblerColorsManager::COLORS_ENABLED,
+        split_output_by_color,
+        input_blocks.len(),
+        input_weights.as_deref(),
+    )?;
+
+    // Opened before starting any work, so that an invalid file fails the run immediately
+    let minimizers_dump_config = DEBUG_MINIMIZERS_DUMP.read().unwrap().clone();
+    let (masked_intervals, minimizers_dump) = if step <= AssemblerStartingStep::MinimizerBucketing {
+        (
+            load_masked_intervals(masking_bed.as_deref())?,
+            create_minimizers_dump(minimizers_dump_config.as_ref())?,
+        )
+    } else {
+        (None, None)
+    };
+
     let temp_dir = temp_dir.unwrap_or(PathBuf::new());
 
     PHASES_TIMES_MONITOR.write().init();
 
     let file_stats = compute_stats_from_input_blocks(&input_blocks);
 
-    let buckets_count_log = buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log);
+    let buckets_count_log = choose_buckets_count_log(buckets_count_log, &file_stats, threads_count);
 
     if let Some(default_compression_level) = default_compression_level {
         INTERMEDIATE_COMPRESSION_LEVEL_SLOW.store(default_compression_level, Ordering::Relaxed);
@@ -111,6 +511,14 @@ pub fn run_assembler<
 
     let buckets_count = 1 << buckets_count_log;
 
+    // The colors of a unitig cannot be reversed together with its sequence
+    let canonical_output = if canonical_output && AssemblerColorsManager::COLORS_ENABLED {
+        log::warn!("The canonical output is not supported with colors, unitigs will be written in their assembly orientation");
+        false
+    } else {
+        canonical_output
+    };
+
     let global_colors_table = Arc::new(
         AssemblerColorsManager::ColorsMergeManagerType::create_colors_table(
             output_file.with_extension("colors.dat"),
@@ -129,15 +537,35 @@ pub fn run_assembler<
             threads_count,
             k,
             m,
+            color_by_file,
+            masked_intervals,
+            minimizers_dump.clone(),
+            input_weights.as_deref(),
         )
     } else {
         (
-            generate_bucket_names(temp_dir.join("bucket"), buckets_count, None),
-            temp_dir.join("buckets-counters.dat"),
+            generate_bucket_names(
+                get_temp_buckets_path(temp_dir.join("bucket")),
+                buckets_count,
+                None,
+            ),
+            get_temp_file_path(temp_dir.join("buckets-counters.dat")),
         )
     };
 
-    println!(
+    if let Some((dump, (path, sample_interval))) = minimizers_dump.zip(minimizers_dump_config) {
+        dump.flush().map_err(|err| AssemblerError::MinimizersDump {
+            path: path.clone(),
+            message: err.to_string(),
+        })?;
+        log::info!(
+            "Dumped the minimizers of one every {} reads to {}",
+            sample_interval,
+            path.display()
+        );
+    }
+
+    log::info!(
         "Temp buckets files size: {:.2}",
         MemoryDataSize::from_bytes(fs_extra::dir::get_size(&temp_dir).unwrap_or(0) as usize)
     );
@@ -146,10 +574,11 @@ pub fn run_assembler<
         PHASES_TIMES_MONITOR
             .write()
             .print_stats("Completed minimizer bucketing.".to_string());
-        return PathBuf::new();
+        return Ok(ShardedOutput::Single(PathBuf::new()));
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
+        remove_orphan_temp_buckets(&temp_dir);
     }
 
     if only_bstats {
@@ -167,7 +596,7 @@ pub fn run_assembler<
                 m,
             );
         });
-        return PathBuf::new();
+        return Ok(ShardedOutput::Single(PathBuf::new()));
     }
 
     let RetType { sequences, hashes } = if step <= AssemblerStartingStep::KmersMerge {
@@ -177,6 +606,9 @@ pub fn run_assembler<
             global_colors_table.clone(),
             buckets_count,
             min_multiplicity,
+            max_kmer_count,
+            reference,
+            reference_min_multiplicity,
             temp_dir.as_path(),
             k,
             m,
@@ -184,18 +616,27 @@ pub fn run_assembler<
         )
     } else {
         RetType {
-            sequences: generate_bucket_names(temp_dir.join("result"), buckets_count, None),
-            hashes: generate_bucket_names(temp_dir.join("hashes"), buckets_count, None),
+            sequences: generate_bucket_names(
+                get_temp_buckets_path(temp_dir.join("result")),
+                buckets_count,
+                None,
+            ),
+            hashes: generate_bucket_names(
+                get_temp_buckets_path(temp_dir.join("hashes")),
+                buckets_count,
+                None,
+            ),
         }
     };
     if last_step <= AssemblerStartingStep::KmersMerge {
         PHASES_TIMES_MONITOR
             .write()
             .print_stats("Completed kmers merge.".to_string());
-        return PathBuf::new();
+        return Ok(ShardedOutput::Single(PathBuf::new()));
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
+        remove_orphan_temp_buckets(&temp_dir);
     }
 
     AssemblerColorsManager::ColorsMergeManagerType::print_color_stats(&global_colors_table);
@@ -205,22 +646,35 @@ pub fn run_assembler<
     let mut links = if step <= AssemblerStartingStep::HashesSorting {
         hashes_sorting::<MergingHash, _>(hashes, temp_dir.as_path(), buckets_count)
     } else {
-        generate_bucket_names(temp_dir.join("links"), buckets_count, None)
+        generate_bucket_names(
+            get_temp_buckets_path(temp_dir.join("links")),
+            buckets_count,
+            None,
+        )
     };
     if last_step <= AssemblerStartingStep::HashesSorting {
         PHASES_TIMES_MONITOR
             .write()
             .print_stats("Hashes sorting.".to_string());
-        return PathBuf::new();
+        return Ok(ShardedOutput::Single(PathBuf::new()));
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
+        remove_orphan_temp_buckets(&temp_dir);
     }
 
     let mut loop_iteration = loopit_number.unwrap_or(0);
 
-    let unames = generate_bucket_names(temp_dir.join("unitigs_map"), buckets_count, None);
-    let rnames = generate_bucket_names(temp_dir.join("results_map"), buckets_count, None);
+    let unames = generate_bucket_names(
+        get_temp_buckets_path(temp_dir.join("unitigs_map")),
+        buckets_count,
+        None,
+    );
+    let rnames = generate_bucket_names(
+        get_temp_buckets_path(temp_dir.join("results_map")),
+        buckets_count,
+        None,
+    );
 
     // let mut links_manager = UnitigLinksManager::new(buckets_count);
 
@@ -235,7 +689,10 @@ pub fn run_assembler<
 
         let result_map_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
             buckets_count,
-            temp_dir.join("results_map"),
+            spread_temp_buckets(
+                get_temp_buckets_path(temp_dir.join("results_map")),
+                buckets_count,
+            ),
             &(
                 get_memory_mode(SwapPriority::FinalMaps),
                 LockFreeBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
@@ -244,7 +701,10 @@ pub fn run_assembler<
 
         let final_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
             buckets_count,
-            temp_dir.join("unitigs_map"),
+            spread_temp_buckets(
+                get_temp_buckets_path(temp_dir.join("unitigs_map")),
+                buckets_count,
+            ),
             &(
                 get_memory_mode(SwapPriority::FinalMaps),
                 LockFreeBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
@@ -252,8 +712,13 @@ pub fn run_assembler<
         ));
 
         if loop_iteration != 0 {
+            if checkpoint_compaction {
+                LinksCompactionCheckpoint::read(temp_dir.as_path()).and_then(|checkpoint| {
+                    checkpoint.validate_resume(temp_dir.as_path(), loop_iteration, buckets_count)
+                })?;
+            }
             links = generate_bucket_names(
-                temp_dir.join(format!("linksi{}", loop_iteration - 1)),
+                get_temp_buckets_path(temp_dir.join(format!("linksi{}", loop_iteration - 1))),
                 buckets_count,
                 None,
             );
@@ -264,12 +729,14 @@ pub fn run_assembler<
             .start_phase("phase: links compaction".to_string());
 
         let mut log_timer = Instant::now();
+        let mut compaction_trend = CompactionTrend::new(loop_iteration);
 
+        let compaction_buffers = LinksCompactionBuffers::from_config();
         let links_scoped_buffer = ScopedThreadLocal::new(move || {
-            BucketsThreadBuffer::new(DEFAULT_PER_CPU_BUFFER_SIZE, buckets_count)
+            BucketsThreadBuffer::new(compaction_buffers.links, buckets_count)
         });
         let results_map_scoped_buffer = ScopedThreadLocal::new(move || {
-            BucketsThreadBuffer::new(DEFAULT_PER_CPU_BUFFER_SIZE, buckets_count)
+            BucketsThreadBuffer::new(compaction_buffers.results, buckets_count)
         });
 
         let result = loop {
@@ -281,35 +748,171 @@ pub fn run_assembler<
             };
 
             if do_logging {
-                println!("Iteration: {}", loop_iteration);
+                log::info!("Iteration: {}", loop_iteration);
             }
 
-            let (new_links, remaining) = links_compaction(
+            let round_dump = if dump_compaction_rounds {
+                Some(UnitigsPathsWriter::new(compaction_round_dump_path(
+                    &output_file,
+                    loop_iteration,
+                )))
+            } else {
+                None
+            };
+
+            // Each checkpointed round writes its maps in separate buckets, merged at the end
+            let round_maps_buckets = if checkpoint_compaction {
+                let (results_map, unitigs_map) =
+                    compaction_round_maps_paths(temp_dir.as_path(), loop_iteration);
+                Some((
+                    Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
+                        buckets_count,
+                        spread_temp_buckets(results_map, buckets_count),
+                        &(
+                            MemoryFileMode::DiskOnly,
+                            LockFreeBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
+                        ),
+                    )),
+                    Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
+                        buckets_count,
+                        spread_temp_buckets(unitigs_map, buckets_count),
+                        &(
+                            MemoryFileMode::DiskOnly,
+                            LockFreeBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
+                        ),
+                    )),
+                ))
+            } else {
+                None
+            };
+
+            let round_inputs = if checkpoint_compaction {
+                links.clone()
+            } else {
+                vec![]
+            };
+
+            let (new_links, stats) = links_compaction(
                 links,
                 temp_dir.as_path(),
                 buckets_count,
                 loop_iteration,
-                &result_map_buckets,
-                &final_buckets,
+                round_maps_buckets
+                    .as_ref()
+                    .map(|(results_map, _)| results_map)
+                    .unwrap_or(&result_map_buckets),
+                round_maps_buckets
+                    .as_ref()
+                    .map(|(_, unitigs_map)| unitigs_map)
+                    .unwrap_or(&final_buckets),
+                round_dump.as_ref(),
+                checkpoint_compaction,
                 // &links_manager,
                 &links_scoped_buffer,
                 &results_map_scoped_buffer,
-            );
+                compaction_buffers,
+            )?;
+
+            let remaining = stats.remaining_links;
+            if compaction_trend.record(&stats) {
+                log::warn!(
+                    "The links compaction is not converging, the remaining links did not decrease at iteration {} (joined: {} lonely: {}), trend: {}",
+                    loop_iteration,
+                    stats.joined_links,
+                    stats.lonely_links,
+                    compaction_trend.format()
+                );
+            }
 
             if do_logging {
-                println!(
-                    "Remaining: {} {}",
+                log::info!(
+                    "Remaining: {} joined: {} lonely: {} {}",
                     remaining,
+                    stats.joined_links,
+                    stats.lonely_links,
                     PHASES_TIMES_MONITOR
                         .read()
                         .get_formatted_counter_without_memory()
                 );
             }
 
+            // Flushes the round dump
+            drop(round_dump);
+
+            if let Some((results_map, unitigs_map)) = round_maps_buckets {
+                results_map.finalize();
+                unitigs_map.finalize();
+                MemoryFs::flush_all_to_disk();
+
+                // The output links are the input of the next round, so they are part of the
+                // checkpoint, synced before the manifest and before the round inputs are removed
+                let round_buckets =
+                    checkpointed_round_buckets(temp_dir.as_path(), loop_iteration, buckets_count);
+
+                LinksCompactionCheckpoint {
+                    buckets_count,
+                    last_completed_iteration: loop_iteration,
+                }
+                .write(
+                    temp_dir.as_path(),
+                    &round_buckets,
+                    CHECKPOINT_CHECKSUMS.load(Ordering::Relaxed),
+                    if SYNC_CHECKPOINTS.load(Ordering::Relaxed) {
+                        Some(&sync_file)
+                    } else {
+                        None
+                    },
+                )?;
+
+                // The round is completed, so its inputs are not needed anymore to resume
+                for link_file in round_inputs {
+                    MemoryFs::remove_file(
+                        &link_file,
+                        RemoveFileMode::Remove {
+                            remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                        },
+                    )
+                    .unwrap();
+                }
+            }
+
             links = new_links;
-            if remaining == 0 {
-                println!("Completed compaction with {} iters", loop_iteration);
-                break (final_buckets.finalize(), result_map_buckets.finalize());
+            match links_compaction_completed(loop_iteration, remaining, max_compaction_iterations) {
+                Ok(true) => {
+                    log::info!("Completed compaction with {} iters", loop_iteration);
+                    log::info!(
+                        "Compaction remaining links trend: {}",
+                        compaction_trend.format()
+                    );
+                    if checkpoint_compaction {
+                        merge_compaction_round_maps(
+                            temp_dir.as_path(),
+                            loop_iteration + 1,
+                            buckets_count,
+                            &result_map_buckets,
+                            &final_buckets,
+                            compaction_buffers,
+                        )?;
+                    }
+                    break (final_buckets.finalize(), result_map_buckets.finalize());
+                }
+                Ok(false) => {}
+                Err(error) => {
+                    // The assembly is incomplete, drop the links left before failing,
+                    // unless they are checkpointed to resume from the next iteration
+                    if !checkpoint_compaction {
+                        for link_file in links {
+                            MemoryFs::remove_file(
+                                &link_file,
+                                RemoveFileMode::Remove {
+                                    remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                                },
+                            )
+                            .unwrap();
+                        }
+                    }
+                    return Err(error.into());
+                }
             }
             loop_iteration += 1;
         };
@@ -332,26 +935,29 @@ pub fn run_assembler<
         PHASES_TIMES_MONITOR
             .write()
             .print_stats("Links Compaction.".to_string());
-        return PathBuf::new();
+        return Ok(ShardedOutput::Single(PathBuf::new()));
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
+        remove_orphan_temp_buckets(&temp_dir);
     }
 
-    let final_unitigs_file = StructuredSequenceWriter::new(match output_file.extension() {
-        Some(ext) => match ext.to_string_lossy().to_string().as_str() {
-            "lz4" => FastaWriter::new_compressed_lz4(&output_file, 2),
-            "gz" => FastaWriter::new_compressed_gzip(&output_file, 2),
-            _ => FastaWriter::new_plain(&output_file),
-        },
-        None => FastaWriter::new_plain(&output_file),
-    });
+    // With the maximal unitigs links the final unitigs are written by a second writer,
+    // after the links are computed
+    let final_unitigs_file = create_final_unitigs_writer(
+        &output_file,
+        output_shards,
+        canonical_output,
+        strand_tags,
+        split_output_by_color && (!generate_maximal_unitigs_links || compute_tigs_mode.is_some()),
+        |path, level| FastaWriter::new_from_extension(path, level),
+    );
 
     // Temporary file to store maximal unitigs data without links info, if further processing is requested
     let compressed_temp_unitigs_file =
         if generate_maximal_unitigs_links || compute_tigs_mode.is_some() {
             Some(StructuredSequenceWriter::new(StructSeqBinaryWriter::new(
-                temp_dir.join("maximal_unitigs.tmp"),
+                get_temp_file_path(temp_dir.join("maximal_unitigs.tmp")),
                 &(
                     get_memory_mode(SwapPriority::FinalMaps as usize),
                     CompressedCheckpointSize::new_from_size(MemoryDataSize::from_mebioctets(4)),
@@ -377,23 +983,31 @@ pub fn run_assembler<
                 temp_dir.as_path(),
                 compressed_temp_unitigs_file.as_ref().unwrap(),
                 buckets_count,
-            )
+            )?
         } else {
-            reorganize_reads::<BucketingHash, MergingHash, AssemblerColorsManager, FastaWriter<_, _>>(
+            reorganize_reads::<BucketingHash, MergingHash, AssemblerColorsManager, _>(
                 sequences,
                 reads_map,
                 temp_dir.as_path(),
                 &final_unitigs_file,
                 buckets_count,
-            )
+            )?
         }
     } else {
         (
-            generate_bucket_names(temp_dir.join("reads_bucket"), buckets_count, Some("tmp")),
-            (generate_bucket_names(temp_dir.join("reads_bucket_lonely"), 1, Some("tmp"))
-                .into_iter()
-                .next()
-                .unwrap()),
+            generate_bucket_names(
+                get_temp_buckets_path(temp_dir.join("reads_bucket")),
+                buckets_count,
+                Some("tmp"),
+            ),
+            (generate_bucket_names(
+                get_temp_file_path(temp_dir.join("reads_bucket_lonely")),
+                1,
+                Some("tmp"),
+            )
+            .into_iter()
+            .next()
+            .unwrap()),
         )
     };
 
@@ -401,15 +1015,24 @@ pub fn run_assembler<
         PHASES_TIMES_MONITOR
             .write()
             .print_stats("Reorganize reads.".to_string());
-        return PathBuf::new();
+        return Ok(ShardedOutput::Single(PathBuf::new()));
     } else {
         MemoryFs::flush_all_to_disk();
         MemoryFs::free_memory();
+        remove_orphan_temp_buckets(&temp_dir);
     }
 
     // links_manager.compute_id_offsets();
 
     if step <= AssemblerStartingStep::BuildUnitigs {
+        let unitigs_paths_writer = if output_unitigs_paths {
+            Some(UnitigsPathsWriter::new(
+                output_file.with_extension("paths.tsv"),
+            ))
+        } else {
+            None
+        };
+
         if generate_maximal_unitigs_links || compute_tigs_mode.is_some() {
             build_unitigs::<
                 BucketingHash,
@@ -422,15 +1045,24 @@ pub fn run_assembler<
                 temp_dir.as_path(),
                 compressed_temp_unitigs_file.as_ref().unwrap(),
                 k,
-            );
+                max_unitig_length,
+                unitigs_paths_writer.as_ref(),
+            )?;
         } else {
-            build_unitigs::<BucketingHash, MergingHash, AssemblerColorsManager, FastaWriter<_, _>>(
+            build_unitigs::<
+                BucketingHash,
+                MergingHash,
+                AssemblerColorsManager,
+                ColorSplitWriter<_, _, ShardedWriter<_, _, FastaWriter<_, _>>>,
+            >(
                 reorganized_reads,
                 unitigs_map,
                 temp_dir.as_path(),
                 &final_unitigs_file,
                 k,
-            );
+                max_unitig_length,
+                unitigs_paths_writer.as_ref(),
+            )?;
         }
     }
 
@@ -473,28 +1105,59 @@ pub fn run_assembler<
                     temp_dir.as_path(),
                     &StructuredSequenceWriter::new(matchtigs_backend),
                     k,
+                    false,
                 );
 
                 handle.join().unwrap();
+            } else if generate_maximal_unitigs_links && fastg_output {
+                final_unitigs_file.finalize();
+
+                let fastg_unitigs_file = create_final_unitigs_writer(
+                    &output_file,
+                    output_shards,
+                    canonical_output,
+                    strand_tags,
+                    split_output_by_color,
+                    |path, level| StructSeqFastgWriter::new_from_extension(path, level),
+                );
+
+                build_maximal_unitigs_links::<
+                    BucketingHash,
+                    MergingHash,
+                    AssemblerColorsManager,
+                    ColorSplitWriter<_, _, ShardedWriter<_, _, StructSeqFastgWriter<_, _>>>,
+                >(
+                    temp_path,
+                    temp_dir.as_path(),
+                    &fastg_unitigs_file,
+                    k,
+                    canonical_output,
+                );
+                fastg_unitigs_file.finalize();
             } else if generate_maximal_unitigs_links {
                 final_unitigs_file.finalize();
 
-                let final_unitigs_file =
-                    StructuredSequenceWriter::new(match output_file.extension() {
-                        Some(ext) => match ext.to_string_lossy().to_string().as_str() {
-                            "lz4" => FastaWriter::new_compressed_lz4(&output_file, 2),
-                            "gz" => FastaWriter::new_compressed_gzip(&output_file, 2),
-                            _ => FastaWriter::new_plain(&output_file),
-                        },
-                        None => FastaWriter::new_plain(&output_file),
-                    });
+                let final_unitigs_file = create_final_unitigs_writer(
+                    &output_file,
+                    output_shards,
+                    canonical_output,
+                    strand_tags,
+                    split_output_by_color,
+                    |path, level| FastaWriter::new_from_extension(path, level),
+                );
 
                 build_maximal_unitigs_links::<
                     BucketingHash,
                     MergingHash,
                     AssemblerColorsManager,
-                    FastaWriter<_, _>,
-                >(temp_path, temp_dir.as_path(), &final_unitigs_file, k);
+                    ColorSplitWriter<_, _, ShardedWriter<_, _, FastaWriter<_, _>>>,
+                >(
+                    temp_path,
+                    temp_dir.as_path(),
+                    &final_unitigs_file,
+                    k,
+                    canonical_output,
+                );
                 final_unitigs_file.finalize();
             }
         } else {
@@ -510,5 +1173,8 @@ pub fn run_assembler<
         .write()
         .print_stats("Compacted De Bruijn graph construction completed.".to_string());
 
-    output_file
+    Ok(ShardedWriter::<(), (), FastaWriter<(), ()>>::get_output(
+        &output_file,
+        output_shards,
+    ))
 }
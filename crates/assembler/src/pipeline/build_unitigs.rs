@@ -1,4 +1,5 @@
 use crate::pipeline::reorganize_reads::ReorganizedReadsExtraData;
+use crate::structs::unitigs_paths::{PendingUnitigsPaths, UnitigsPathsWriter};
 use colors::colors_manager::color_types::PartialUnitigsColorStructure;
 use colors::colors_manager::ColorsMergeManager;
 use colors::colors_manager::{color_types, ColorsManager};
@@ -11,14 +12,15 @@ use io::concurrent::structured_sequences::{StructuredSequenceBackend, Structured
 use io::concurrent::temp_reads::creads_utils::CompressedReadsBucketDataSerializer;
 use io::concurrent::temp_reads::extra_data::SequenceExtraDataTempBufferManagement;
 use io::get_bucket_index;
-use io::structs::unitig_link::{UnitigFlags, UnitigIndex, UnitigLinkSerializer};
-use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+use io::structs::unitig_link::{read_links_bucket, LinksCorruptionError, UnitigFlags, UnitigIndex};
 use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBinaryReader;
 use parallel_processor::buckets::readers::lock_free_binary_reader::LockFreeBinaryReader;
 use parallel_processor::buckets::readers::BucketReader;
 use parallel_processor::memory_fs::RemoveFileMode;
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use rayon::prelude::*;
+use std::cmp::min;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
 
@@ -56,6 +58,35 @@ impl FastaCompatibleRead for [u8] {
     }
 }
 
+/// Computes the ranges of the pieces a unitig of length `len` must be split into
+/// so that each one is at most `max_length` bases long.
+/// Consecutive pieces overlap by k - 1 bases, so that the k-mers content is preserved
+/// and the original sequence can be rebuilt by merging the overlaps.
+/// `max_length` is checked to be at least k when the run starts
+pub fn split_unitig_ranges(
+    len: usize,
+    max_length: usize,
+    k: usize,
+) -> impl Iterator<Item = Range<usize>> {
+    debug_assert!(max_length >= k);
+
+    let mut start = 0;
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let end = min(start + max_length, len);
+        let range = start..end;
+        if end == len {
+            done = true;
+        } else {
+            start = end - (k - 1);
+        }
+        Some(range)
+    })
+}
+
 type CompressedReadsDataSerializerUnitigsBuilding<H, MH, CX> = CompressedReadsBucketDataSerializer<
     ReorganizedReadsExtraData<PartialUnitigsColorStructure<H, MH, CX>>,
     typenum::U0,
@@ -73,7 +104,9 @@ pub fn build_unitigs<
     _temp_path: &Path,
     out_file: &StructuredSequenceWriter<PartialUnitigsColorStructure<H, MH, CX>, (), BK>,
     k: usize,
-) {
+    max_unitig_length: Option<usize>,
+    paths_writer: Option<&UnitigsPathsWriter>,
+) -> Result<(), LinksCorruptionError> {
     PHASES_TIMES_MONITOR
         .write()
         .start_phase("phase: unitigs building".to_string());
@@ -90,7 +123,7 @@ pub fn build_unitigs<
         inputs
             .par_iter()
             .enumerate()
-            .for_each(|(_index, (read_file, unitigs_map_file))| {
+            .try_for_each(|(_index, (read_file, unitigs_map_file))| {
                 let mut tmp_final_unitigs_buffer =
                     FastaWriterConcurrentBuffer::new(out_file, DEFAULT_OUTPUT_BUFFER_SIZE, true);
 
@@ -114,63 +147,64 @@ pub fn build_unitigs<
                 let mut unitigs_hashmap = HashMap::new();
                 let mut unitigs_tmp_vec = Vec::new();
 
-                let mut deserializer = UnitigLinkSerializer::new();
-
                 let mut counter: usize = 0;
-                while let Some(link) =
-                                deserializer.read_from(&mut unitigs_map_stream, &mut unitigs_tmp_vec, &mut ())
-                {
-                    let start_unitig = UnitigIndex::new(
-                        bucket_index,
-                        link.entry() as usize,
-                        link.flags().is_reverse_complemented(),
-                    );
+                read_links_bucket(
+                    &mut unitigs_map_stream,
+                    bucket_index,
+                    &mut unitigs_tmp_vec,
+                    |link, unitigs_tmp_vec| {
+                        let start_unitig = UnitigIndex::new(
+                            bucket_index,
+                            link.entry() as usize,
+                            link.flags().is_reverse_complemented(),
+                        );
 
-                    let is_circular = link
-                        .entries
-                        .get_slice(&unitigs_tmp_vec)
-                        .last()
-                        .map(|u| u == &start_unitig)
-                        .unwrap_or(false);
-
-                    assert!(!unitigs_hashmap.contains_key(&start_unitig));
-                    unitigs_hashmap.insert(
-                        start_unitig,
-                        (
-                            counter,
-                            FinalUnitigInfo {
-                                is_start: true,
-                                is_circular,
-                                flags: link.flags(),
-                            },
-                        ),
-                    );
+                        let entries = link.entries.get_slice(unitigs_tmp_vec);
+
+                        let is_circular = entries
+                            .last()
+                            .map(|u| u == &start_unitig)
+                            .unwrap_or(false);
+
+                        assert!(!unitigs_hashmap.contains_key(&start_unitig));
+                        unitigs_hashmap.insert(
+                            start_unitig,
+                            (
+                                counter,
+                                FinalUnitigInfo {
+                                    is_start: true,
+                                    is_circular,
+                                    flags: link.flags(),
+                                },
+                            ),
+                        );
 
-                    counter += 1;
-
-                    for el in link.entries.get_slice(&unitigs_tmp_vec) {
-                        if *el != start_unitig {
-                            assert!(!unitigs_hashmap.contains_key(el));
-                            unitigs_hashmap.insert(
-                                *el,
-                                (
-                                    counter,
-                                    FinalUnitigInfo {
-                                        is_start: false,
-                                        is_circular,
-                                        flags: UnitigFlags::new_direction(
-                                            /*unused*/ false,
-                                            el.is_reverse_complemented(),
-                                        ),
-                                    },
-                                ),
-                            );
-                            counter += 1;
+                        counter += 1;
+
+                        for el in entries {
+                            if *el != start_unitig {
+                                assert!(!unitigs_hashmap.contains_key(el));
+                                unitigs_hashmap.insert(
+                                    *el,
+                                    (
+                                        counter,
+                                        FinalUnitigInfo {
+                                            is_start: false,
+                                            is_circular,
+                                            flags: UnitigFlags::new_direction(
+                                                /*unused*/ false,
+                                                el.is_reverse_complemented(),
+                                            ),
+                                        },
+                                    ),
+                                );
+                                counter += 1;
+                            }
                         }
-                    }
 
-                    unitigs_tmp_vec.clear();
-                }
+                        unitigs_tmp_vec.clear();
+                    },
+                )?;
 
                 drop(unitigs_map_stream);
                 drop(unitigs_map_reader);
@@ -201,11 +235,14 @@ pub fn build_unitigs<
                             CompressedReadIndipendent::from_read(&seq, &mut temp_storage),
                             unitig_info,
                             index.color,
+                            index.unitig,
                         ));
                     },
                 );
 
                 let mut temp_sequence = Vec::new();
+                let mut current_path = Vec::new();
+                let mut pending_paths = PendingUnitigsPaths::new();
 
                 let mut final_unitig_color =
                     CX::ColorsMergeManagerType::<H, MH>::alloc_unitig_color_structure();
@@ -217,6 +254,7 @@ pub fn build_unitigs<
                     let is_circular = sequence[0].as_ref().unwrap().1.is_circular;
 
                     temp_sequence.clear();
+                    current_path.clear();
                     CX::ColorsMergeManagerType::<H, MH>::reset_unitig_color_structure(
                         &mut final_unitig_color,
                     );
@@ -228,12 +266,17 @@ pub fn build_unitigs<
                     } else {
                         itertools::Either::Left(sequence.iter())
                     } {
-                        let (read, FinalUnitigInfo { flags, .. }, color) = upart.as_ref().unwrap();
+                        let (read, FinalUnitigInfo { flags, .. }, color, unitig) =
+                            upart.as_ref().unwrap();
 
                         let compr_read = read.as_reference(&temp_storage);
                         if compr_read.bases_count() == 0 {
                             continue 'uloop;
                         }
+
+                        if paths_writer.is_some() {
+                            current_path.push((*unitig, flags.is_reverse_complemented()));
+                        }
                         if is_first {
                             if flags.is_reverse_complemented() {
                                 temp_sequence.extend(compr_read.as_reverse_complement_bases_iter());
@@ -290,20 +333,62 @@ pub fn build_unitigs<
                         CX::ColorsMergeManagerType::<H, MH>::pop_base(&mut final_unitig_color);
                     }
 
-                    let writable_color =
-                        CX::ColorsMergeManagerType::<H, MH>::encode_part_unitigs_colors(
-                            &mut final_unitig_color,
-                            &mut final_color_extra_buffer,
-                        );
+                    match max_unitig_length {
+                        // Rejected with colors when the run starts, as the colors are tracked on the whole unitig
+                        Some(max_length) if temp_sequence.len() > max_length => {
+                            // The links between consecutive pieces are recovered from their k - 1 overlaps
+                            // when building the maximal unitigs links
+                            for range in
+                                split_unitig_ranges(temp_sequence.len(), max_length, k)
+                            {
+                                let writable_color =
+                                    CX::ColorsMergeManagerType::<H, MH>::encode_part_unitigs_colors(
+                                        &mut final_unitig_color,
+                                        &mut final_color_extra_buffer,
+                                    );
+
+                                let flushed_index = tmp_final_unitigs_buffer.add_read(
+                                    &temp_sequence[range],
+                                    None,
+                                    writable_color,
+                                    &final_color_extra_buffer,
+                                    (),
+                                    &(),
+                                );
 
-                    tmp_final_unitigs_buffer.add_read(
-                        temp_sequence.as_slice(),
-                        None,
-                        writable_color,
-                        &final_color_extra_buffer,
-                        (),
-                        &(),
-                    );
+                                // Each piece keeps the path of the whole unitig
+                                if let Some(paths_writer) = paths_writer {
+                                    if let Some(first_index) = flushed_index {
+                                        pending_paths.flush(paths_writer, first_index);
+                                    }
+                                    pending_paths.add_path(&current_path);
+                                }
+                            }
+                        }
+                        _ => {
+                            let writable_color =
+                                CX::ColorsMergeManagerType::<H, MH>::encode_part_unitigs_colors(
+                                    &mut final_unitig_color,
+                                    &mut final_color_extra_buffer,
+                                );
+
+                            let flushed_index = tmp_final_unitigs_buffer.add_read(
+                                temp_sequence.as_slice(),
+                                None,
+                                writable_color,
+                                &final_color_extra_buffer,
+                                (),
+                                &(),
+                            );
+
+                            if let Some(paths_writer) = paths_writer {
+                                if let Some(first_index) = flushed_index {
+                                    pending_paths.flush(paths_writer, first_index);
+                                }
+                                pending_paths.add_path(&current_path);
+                            }
+                        }
+                    }
 
                     // write_fasta_entry::<H, MH, CX, _>(
                     //     &mut ident_buffer,
@@ -319,7 +404,48 @@ pub fn build_unitigs<
                 //     color_types::PartialUnitigsColorStructure<H, MH, CX>,
                 // >::clear_temp_buffer(&mut color_extra_buffer);
 
-                tmp_final_unitigs_buffer.finalize();
-            });
-    });
+                let first_index = tmp_final_unitigs_buffer.finalize();
+                if let Some(paths_writer) = paths_writer {
+                    pending_paths.flush(paths_writer, first_index);
+                }
+                Ok(())
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_unitig_ranges;
+
+    #[test]
+    fn split_long_unitig() {
+        let k = 31;
+        let max_length = 1000;
+        let sequence: Vec<u8> = (0..3 * max_length)
+            .map(|i| b"ACGT"[(i * 7 + i / 3) % 4])
+            .collect();
+
+        let ranges: Vec<_> = split_unitig_ranges(sequence.len(), max_length, k).collect();
+
+        // Each piece after the first one adds max_length - (k - 1) new bases
+        let expected_pieces = (sequence.len() - (k - 1)).div_ceil(max_length - (k - 1));
+        assert_eq!(ranges.len(), expected_pieces);
+
+        for window in ranges.windows(2) {
+            assert_eq!(window[0].end - window[1].start, k - 1);
+        }
+
+        let mut rebuilt = sequence[ranges[0].clone()].to_vec();
+        for range in &ranges[1..] {
+            assert!(range.len() <= max_length);
+            rebuilt.extend_from_slice(&sequence[range.start + k - 1..range.end]);
+        }
+        assert_eq!(rebuilt, sequence);
+    }
+
+    #[test]
+    fn short_unitig_not_split() {
+        let ranges: Vec<_> = split_unitig_ranges(100, 1000, 31).collect();
+        assert_eq!(ranges, vec![0..100]);
+    }
 }
@@ -13,7 +13,7 @@ use hashes::{HashFunctionFactory, MinimizerHashFunctionFactory};
 use io::compressed_read::CompressedReadIndipendent;
 use io::concurrent::structured_sequences::concurrent::FastaWriterConcurrentBuffer;
 use io::concurrent::structured_sequences::{
-    IdentSequenceWriter, StructuredSequenceBackend, StructuredSequenceWriter,
+    IdentSequenceWriter, SequenceStrand, StructuredSequenceBackend, StructuredSequenceWriter,
 };
 use io::concurrent::temp_reads::extra_data::SequenceExtraDataTempBufferManagement;
 use libmatchtigs::{
@@ -197,6 +197,7 @@ impl<ColorInfo: IdentSequenceWriter> StructuredSequenceBackend<ColorInfo, Double
         buffer: &mut Self::SequenceTempBuffer,
         sequence_index: u64,
         sequence: &[u8],
+        _strand: Option<SequenceStrand>,
         color_info: ColorInfo,
         links_info: DoubleMaximalUnitigLinks,
         extra_buffers: &(
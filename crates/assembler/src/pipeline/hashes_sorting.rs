@@ -1,12 +1,13 @@
 use std::path::{Path, PathBuf};
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use config::{
-    get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_memory_mode, get_temp_buckets_path, spread_temp_buckets, SwapPriority,
+    DEBUG_LINKS_DIAGNOSTICS, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
 };
 use hashes::HashFunctionFactory;
-use io::structs::hash_entry::{Direction, HashCompare, HashEntrySerializer};
+use io::structs::hash_entry::{Direction, HashCompare, HashEntry, HashEntrySerializer};
 use io::structs::unitig_link::{UnitigFlags, UnitigIndex, UnitigLink, UnitigLinkSerializer};
 use parallel_processor::buckets::concurrent::{BucketsThreadBuffer, BucketsThreadDispatcher};
 use parallel_processor::buckets::readers::lock_free_binary_reader::LockFreeBinaryReader;
@@ -22,6 +23,143 @@ use rayon::iter::ParallelIterator;
 use utils::fast_rand_bool::FastRandBool;
 use utils::vec_slice::VecSlice;
 
+/// How the partial unitigs sharing an ending kmer are joined.
+///
+/// With an even k a kmer can be palindromic, equal to its own reverse complement, so each of its
+/// ends can be read as the other one. A palindromic kmer shared by two partial unitigs is linked
+/// as any other kmer, as the pairing of the two ends does not depend on its orientation.
+/// A partial unitig whose end is the only one with a palindromic kmer continues into its own
+/// reverse complement instead: it is not joined to itself, since a unitig cannot contain the same
+/// kmer twice, and it links to itself in both directions in the maximal unitigs links
+#[derive(Debug, PartialEq, Eq)]
+enum EndingKmerLinks {
+    /// The indexes of the forward and backward ends in the group, with their reverse complement flags
+    Pair {
+        fw: usize,
+        bw: usize,
+        reverse_complemented: [bool; 2],
+    },
+    /// A palindromic kmer ending a partial unitig that continues in its own reverse complement
+    PalindromeHairpin,
+    /// A kmer with a single end, not palindromic
+    Spurious,
+    /// More ends than the two of a kmer
+    Collision,
+}
+
+impl EndingKmerLinks {
+    fn from_group<H: Copy>(x: &[HashEntry<H>]) -> Self {
+        let is_palindrome = x.iter().any(|e| e.is_palindrome());
+        match x.len() {
+            // Both ends of a single kmer partial unitig
+            2 if is_palindrome
+                && x[0].bucket() == x[1].bucket()
+                && x[0].entry() == x[1].entry() =>
+            {
+                Self::PalindromeHairpin
+            }
+            2 => {
+                let mut reverse_complemented = [false, false];
+
+                // Can happen with canonical kmers, we should reverse-complement one of the strands
+                // the direction reverse is implicit as x[1] is treated as if it had the opposite of the x[0] direction
+                if x[0].direction() == x[1].direction() {
+                    reverse_complemented[1] = true;
+                }
+
+                let (fw, bw) = match x[0].direction() {
+                    Direction::Forward => (0, 1),
+                    Direction::Backward => (1, 0),
+                };
+
+                Self::Pair {
+                    fw,
+                    bw,
+                    reverse_complemented,
+                }
+            }
+            1 if is_palindrome => Self::PalindromeHairpin,
+            1 => Self::Spurious,
+            _ => Self::Collision,
+        }
+    }
+}
+
+/// Why a group of ending kmers is not linked as a pair of opposite ends of the same kmer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum UnlinkedReason {
+    /// A single end, not palindromic
+    SingleEnd,
+    /// Two ends with the same direction, linked after reverse complementing one of the two
+    SameDirection,
+    /// More than two ends
+    Collision,
+}
+
+impl UnlinkedReason {
+    const ALL: [UnlinkedReason; 3] = [Self::SingleEnd, Self::SameDirection, Self::Collision];
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::SingleEnd => "single end",
+            Self::SameDirection => "same direction pair",
+            Self::Collision => "more than 2 ends",
+        }
+    }
+}
+
+/// Counts of the ending kmers groups not linked as a pair of opposite ends, by reason.
+/// Enabled by DEBUG_LINKS_DIAGNOSTICS, to understand why a kmer did not produce the expected link
+#[derive(Default)]
+struct LinksDiagnostics {
+    counts: [AtomicU64; UnlinkedReason::ALL.len()],
+}
+
+impl LinksDiagnostics {
+    /// Counts and logs the group if it is not a pair of opposite ends, returning the reason
+    fn record<H: Copy + std::fmt::Debug>(
+        &self,
+        group: &[HashEntry<H>],
+        links: &EndingKmerLinks,
+    ) -> Option<UnlinkedReason> {
+        let reason = match links {
+            EndingKmerLinks::Pair { .. } if group[0].direction() == group[1].direction() => {
+                UnlinkedReason::SameDirection
+            }
+            EndingKmerLinks::Pair { .. } | EndingKmerLinks::PalindromeHairpin => return None,
+            EndingKmerLinks::Spurious => UnlinkedReason::SingleEnd,
+            EndingKmerLinks::Collision => UnlinkedReason::Collision,
+        };
+
+        self.counts[reason as usize].fetch_add(1, Ordering::Relaxed);
+        log::info!(
+            "Links diagnostics: hash {:?} {} with ends (bucket/entry/direction): {}",
+            group[0].hash,
+            reason.description(),
+            group
+                .iter()
+                .map(|e| format!("{}/{}/{:?}", e.bucket(), e.entry(), e.direction()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        Some(reason)
+    }
+
+    fn report(&self) -> String {
+        UnlinkedReason::ALL
+            .iter()
+            .map(|reason| {
+                format!(
+                    "{}: {}",
+                    reason.description(),
+                    self.counts[*reason as usize].load(Ordering::Relaxed)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
 pub fn hashes_sorting<H: HashFunctionFactory, P: AsRef<Path>>(
     file_hashes_inputs: Vec<PathBuf>,
     output_dir: P,
@@ -33,13 +171,22 @@ pub fn hashes_sorting<H: HashFunctionFactory, P: AsRef<Path>>(
 
     let links_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
         buckets_count,
-        output_dir.as_ref().join("links"),
+        spread_temp_buckets(
+            get_temp_buckets_path(output_dir.as_ref().join("links")),
+            buckets_count,
+        ),
         &(
             get_memory_mode(SwapPriority::LinksBuckets),
             LockFreeBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
         ),
     ));
 
+    let palindromes_linked = AtomicU64::new(0);
+    let palindromes_hairpins = AtomicU64::new(0);
+    let diagnostics = DEBUG_LINKS_DIAGNOSTICS
+        .load(Ordering::Relaxed)
+        .then(LinksDiagnostics::default);
+
     let buckets_thread_buffers = ScopedThreadLocal::new(move || {
         BucketsThreadBuffer::new(DEFAULT_PER_CPU_BUFFER_SIZE, buckets_count)
     });
@@ -69,20 +216,16 @@ pub fn hashes_sorting<H: HashFunctionFactory, P: AsRef<Path>>(
             let mut unitigs_vec = Vec::new();
 
             for x in hashes_vec.group_by(|a, b| a.hash == b.hash) {
-                match x.len() {
-                    2 => {
-                        let mut reverse_complemented = [false, false];
-
-                        // Can happen with canonical kmers, we should reverse-complement one of the strands
-                        // the direction reverse is implicit as x[1] is treated as if it had the opposite of the x[0] direction
-                        if x[0].direction() == x[1].direction() {
-                            reverse_complemented[1] = true;
-                        }
+                let links = EndingKmerLinks::from_group(x);
+                if let Some(diagnostics) = &diagnostics {
+                    diagnostics.record(x, &links);
+                }
 
-                        let (fw, bw) = match x[0].direction() {
-                            Direction::Forward => (0, 1),
-                            Direction::Backward => (1, 0),
-                        };
+                match links {
+                    EndingKmerLinks::Pair { fw, bw, reverse_complemented } => {
+                        if x[0].is_palindrome() {
+                            palindromes_linked.fetch_add(1, Ordering::Relaxed);
+                        }
 
                         let (slice_fw, slice_bw) = if rand_bool.get_randbool() {
                             unitigs_vec.push(UnitigIndex::new(x[bw].bucket(), x[bw].entry() as usize, reverse_complemented[bw]));
@@ -112,15 +255,145 @@ pub fn hashes_sorting<H: HashFunctionFactory, P: AsRef<Path>>(
                             ),
                         );
                     },
-                    1 => {
-                        println!("Warning spurious hash detected ({:?}) with index {}, this is a bug or a collision in the KmersMerge phase!", x[0].hash, x[0].entry());
+                    EndingKmerLinks::PalindromeHairpin => {
+                        palindromes_hairpins.fetch_add(1, Ordering::Relaxed);
+                    }
+                    EndingKmerLinks::Spurious => {
+                        log::warn!("Spurious hash detected ({:?}) with index {}, this is a bug or a collision in the KmersMerge phase!", x[0].hash, x[0].entry());
                     }
-                    _ => {
-                        println!("More than 2 equal hashes found in hashes sorting phase, this indicates an hash ({}) collision!",  x[0].hash);
+                    EndingKmerLinks::Collision => {
+                        log::warn!("More than 2 equal hashes found in hashes sorting phase, this indicates an hash ({}) collision!",  x[0].hash);
                     }
                 }
             }
             buffers.put_back(links_tmp.finalize().0);
         });
+
+    log::info!(
+        "Palindromic kmers at the partial unitigs ends: {} linked, {} ending a unitig in its own reverse complement",
+        palindromes_linked.into_inner(),
+        palindromes_hairpins.into_inner()
+    );
+
+    if let Some(diagnostics) = diagnostics {
+        log::info!(
+            "Links diagnostics, ending kmers not linked as opposite ends: {}",
+            diagnostics.report()
+        );
+    }
+
     links_buckets.finalize()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{EndingKmerLinks, LinksDiagnostics, UnlinkedReason};
+    use hashes::cn_seqhash::u64::CanonicalSeqHashFactory;
+    use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+    use io::compressed_read::CompressedReadIndipendent;
+    use io::structs::hash_entry::{Direction, HashEntry};
+
+    fn ending_kmer_hash(kmer: &[u8]) -> (u64, bool) {
+        CanonicalSeqHashFactory::initialize(kmer.len());
+        let mut storage = vec![];
+        let read = CompressedReadIndipendent::from_plain(kmer, &mut storage);
+        let hash = CanonicalSeqHashFactory::new(read.as_reference(&storage), kmer.len())
+            .iter()
+            .next()
+            .unwrap();
+        (hash.to_unextendable(), hash.is_rc_symmetric())
+    }
+
+    #[test]
+    fn even_k_palindrome_is_linked_once() {
+        // ACGTACGT is its own reverse complement
+        let (hash, palindrome) = ending_kmer_hash(b"ACGTACGT");
+        assert!(palindrome);
+        assert!(!ending_kmer_hash(b"ACGTTCGT").1);
+
+        // Two partial unitigs ending with the palindrome, a single link joins them
+        let group = [
+            HashEntry::new(hash, 3, 10, Direction::Forward, palindrome),
+            HashEntry::new(hash, 5, 2, Direction::Forward, palindrome),
+        ];
+        assert!(group.iter().all(|e| e.is_palindrome()));
+        assert_eq!(
+            EndingKmerLinks::from_group(&group),
+            EndingKmerLinks::Pair {
+                fw: 0,
+                bw: 1,
+                reverse_complemented: [false, true],
+            }
+        );
+
+        let group = [
+            HashEntry::new(hash, 5, 2, Direction::Backward, palindrome),
+            HashEntry::new(hash, 3, 10, Direction::Forward, palindrome),
+        ];
+        assert_eq!(
+            EndingKmerLinks::from_group(&group),
+            EndingKmerLinks::Pair {
+                fw: 1,
+                bw: 0,
+                reverse_complemented: [false, false],
+            }
+        );
+
+        // A single end continues in its own reverse complement, that is not a spurious hash
+        let hairpin = [HashEntry::new(hash, 3, 10, Direction::Forward, palindrome)];
+        assert_eq!(
+            EndingKmerLinks::from_group(&hairpin),
+            EndingKmerLinks::PalindromeHairpin
+        );
+        let single_kmer = [
+            HashEntry::new(hash, 3, 10, Direction::Forward, palindrome),
+            HashEntry::new(hash, 3, 10, Direction::Backward, palindrome),
+        ];
+        assert_eq!(
+            EndingKmerLinks::from_group(&single_kmer),
+            EndingKmerLinks::PalindromeHairpin
+        );
+        let spurious = [HashEntry::new(hash, 3, 10, Direction::Forward, false)];
+        assert_eq!(
+            EndingKmerLinks::from_group(&spurious),
+            EndingKmerLinks::Spurious
+        );
+    }
+
+    #[test]
+    fn links_diagnostics_reasons() {
+        let (hash, _) = ending_kmer_hash(b"ACGTTCGT");
+        let diagnostics = LinksDiagnostics::default();
+
+        let record = |group: &[HashEntry<u64>]| {
+            diagnostics.record(group, &EndingKmerLinks::from_group(group))
+        };
+
+        // Three ends sharing the same kmer
+        let collision = [
+            HashEntry::new(hash, 1, 4, Direction::Forward, false),
+            HashEntry::new(hash, 2, 7, Direction::Backward, false),
+            HashEntry::new(hash, 6, 1, Direction::Forward, false),
+        ];
+        assert_eq!(record(&collision), Some(UnlinkedReason::Collision));
+
+        let same_direction = [
+            HashEntry::new(hash, 1, 4, Direction::Backward, false),
+            HashEntry::new(hash, 2, 7, Direction::Backward, false),
+        ];
+        assert_eq!(record(&same_direction), Some(UnlinkedReason::SameDirection));
+
+        let pair = [
+            HashEntry::new(hash, 1, 4, Direction::Forward, false),
+            HashEntry::new(hash, 2, 7, Direction::Backward, false),
+        ];
+        assert_eq!(record(&pair), None);
+        assert_eq!(record(&pair[..1]), Some(UnlinkedReason::SingleEnd));
+        assert_eq!(record(&collision), Some(UnlinkedReason::Collision));
+
+        assert_eq!(
+            diagnostics.report(),
+            "single end: 1, same direction pair: 1, more than 2 ends: 2"
+        );
+    }
+}
@@ -1,9 +1,17 @@
-use crate::structs::link_mapping::{LinkMapping, LinkMappingSerializer};
+use crate::structs::link_mapping::{read_link_mappings_bucket, LinkMapping, LinkMappingSerializer};
+use crate::structs::unitigs_paths::UnitigsPathsWriter;
 use config::{
-    get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_memory_mode, get_temp_buckets_path, get_temp_file_path, spread_temp_buckets,
+    BucketIndexType, SwapPriority, DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PER_CPU_BUFFER_SIZE,
+    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES, LINKS_COMPACTION_LINKS_BUFFER_SIZE,
+    LINKS_COMPACTION_RESULTS_BUFFER_SIZE,
 };
-use io::get_bucket_index;
-use io::structs::unitig_link::{UnitigFlags, UnitigIndex, UnitigLink, UnitigLinkSerializer};
+use io::structs::unitig_link::{
+    read_links_bucket, LinksCorruptionError, UnitigFlags, UnitigIndex, UnitigLink,
+    UnitigLinkSerializer,
+};
+use io::varint::DecodeError;
+use io::{generate_bucket_names, get_bucket_index};
 use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
 use parallel_processor::buckets::concurrent::{BucketsThreadBuffer, BucketsThreadDispatcher};
 use parallel_processor::buckets::readers::lock_free_binary_reader::LockFreeBinaryReader;
@@ -11,16 +19,564 @@ use parallel_processor::buckets::single::SingleBucketThreadDispatcher;
 use parallel_processor::buckets::writers::lock_free_binary_writer::LockFreeBinaryWriter;
 use parallel_processor::buckets::MultiThreadBuckets;
 use parallel_processor::fast_smart_bucket_sort::{fast_smart_radix_sort, SortKey};
+use parallel_processor::memory_data_size::MemoryDataSize;
 use parallel_processor::memory_fs::RemoveFileMode;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
-use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use utils::fast_rand_bool::FastRandBool;
 use utils::vec_slice::VecSlice;
 
+// A buffer holds at least a few links, even the ones of the longest unitigs
+const MIN_LINKS_COMPACTION_BUFFER_SIZE: usize = 1024;
+
+/// Per-bucket buffer sizes of the links compaction dispatchers. Each thread holds a buffer for each
+/// bucket, writing it to the bucket when full: smaller buffers use less memory (threads times
+/// buckets times the size) but are flushed more often, with more contention on the buckets
+#[derive(Clone, Copy)]
+pub struct LinksCompactionBuffers {
+    /// Buffers of the links, compacted again in the next iteration or written as final links
+    pub links: MemoryDataSize,
+    /// Buffers of the unitigs mappings, read when building the unitigs
+    pub results: MemoryDataSize,
+}
+
+impl LinksCompactionBuffers {
+    fn buffer_size(configured_size: usize) -> MemoryDataSize {
+        if configured_size != 0 {
+            MemoryDataSize::from_bytes(configured_size.max(MIN_LINKS_COMPACTION_BUFFER_SIZE))
+        } else {
+            DEFAULT_PER_CPU_BUFFER_SIZE
+        }
+    }
+
+    /// Buffers of the configured sizes in bytes, 0 keeps the default size
+    pub fn new(links_buffer_size: usize, results_buffer_size: usize) -> Self {
+        Self {
+            links: Self::buffer_size(links_buffer_size),
+            results: Self::buffer_size(results_buffer_size),
+        }
+    }
+
+    /// Buffers of the sizes from the global configuration
+    pub fn from_config() -> Self {
+        Self::new(
+            LINKS_COMPACTION_LINKS_BUFFER_SIZE.load(Ordering::Relaxed),
+            LINKS_COMPACTION_RESULTS_BUFFER_SIZE.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Number of times a buffer of the given size is written to its bucket while
+    /// buffering written_bytes
+    pub fn flushes_count(buffer_size: MemoryDataSize, written_bytes: u64) -> u64 {
+        written_bytes.div_ceil(buffer_size.as_bytes() as u64)
+    }
+}
+
+/// Error raised when the links compaction did not converge within the maximum number of iterations
+#[derive(Clone, Debug)]
+pub struct CompactionNotConvergedError {
+    pub iterations: usize,
+    pub remaining_links: u64,
+}
+
+impl Display for CompactionNotConvergedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Links compaction did not converge after {} iterations, {} links remaining: the assembly is incomplete",
+            self.iterations, self.remaining_links
+        )
+    }
+}
+
+impl Error for CompactionNotConvergedError {}
+
+/// Checks if the links compaction is completed after the given (0-based) iteration,
+/// failing if there are still links to compact and the maximum iterations count is reached
+pub fn links_compaction_completed(
+    iteration: usize,
+    remaining_links: u64,
+    max_iterations: Option<usize>,
+) -> Result<bool, CompactionNotConvergedError> {
+    if remaining_links == 0 {
+        return Ok(true);
+    }
+
+    match max_iterations {
+        Some(max_iterations) if iteration + 1 >= max_iterations => {
+            Err(CompactionNotConvergedError {
+                iterations: iteration + 1,
+                remaining_links,
+            })
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Links counters of a links compaction iteration, summed over all the buckets
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinksCompactionStats {
+    /// Pairs of unitigs joined in the iteration
+    pub joined_links: u64,
+    /// Unitigs ends without a link, sealed in the iteration
+    pub lonely_links: u64,
+    /// Links left for the next iterations, the compaction is completed when they reach 0
+    pub remaining_links: u64,
+}
+
+/// Remaining links of each compaction iteration, to detect early a compaction that stopped
+/// shrinking the graph and is not going to converge
+#[derive(Clone, Debug, Default)]
+pub struct CompactionTrend {
+    first_iteration: usize,
+    remaining_links: Vec<u64>,
+}
+
+impl CompactionTrend {
+    /// Starts tracking the trend from the given iteration (non zero when the compaction is resumed)
+    pub fn new(first_iteration: usize) -> Self {
+        Self {
+            first_iteration,
+            remaining_links: vec![],
+        }
+    }
+
+    /// Records the stats of the next iteration, returning true if the remaining links did not
+    /// decrease since the previous iteration while there are still links to compact
+    pub fn record(&mut self, stats: &LinksCompactionStats) -> bool {
+        let stalled = match self.remaining_links.last() {
+            Some(&previous) => stats.remaining_links != 0 && stats.remaining_links >= previous,
+            None => false,
+        };
+        self.remaining_links.push(stats.remaining_links);
+        stalled
+    }
+
+    /// The remaining links of each recorded iteration, as iteration:links pairs
+    pub fn format(&self) -> String {
+        self.remaining_links
+            .iter()
+            .enumerate()
+            .map(|(index, links)| format!("{}:{}", self.first_iteration + index, links))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+}
+
+/// Path of the text dump of the unitigs completed during the given links compaction round.
+/// Each line describes a unitig sealed in that round, see format_round_dump_unitig
+pub fn compaction_round_dump_path(output_file: &Path, round: usize) -> PathBuf {
+    output_file.with_extension(format!("unitigs_round_{}.tsv", round))
+}
+
+/// Formats a unitig completed in a compaction round as a line of the round dump:
+/// <bucket>:<entry>\t<bucket>:<entry><+/->,... with the partial unitig the compacted link starts from,
+/// followed by the partial unitigs joined to it, the sign telling if they are reverse complemented
+fn format_round_dump_unitig(
+    buffer: &mut Vec<u8>,
+    bucket_index: BucketIndexType,
+    entry: u64,
+    joined: &[UnitigIndex],
+) {
+    write!(buffer, "{}:{}\t", bucket_index, entry).unwrap();
+    for (i, part) in joined.iter().enumerate() {
+        if i > 0 {
+            buffer.push(b',');
+        }
+        write!(
+            buffer,
+            "{}:{}{}",
+            part.bucket(),
+            part.index(),
+            if part.is_reverse_complemented() {
+                '-'
+            } else {
+                '+'
+            }
+        )
+        .unwrap();
+    }
+    buffer.push(b'\n');
+}
+
+/// Error raised when the links compaction cannot be resumed from the requested iteration
+#[derive(Clone, Debug)]
+pub enum CompactionResumeError {
+    MissingCheckpoint(PathBuf),
+    MalformedCheckpoint(PathBuf),
+    BucketsCountMismatch {
+        checkpoint: usize,
+        current: usize,
+    },
+    IterationNotCompleted {
+        iteration: usize,
+        last_completed: usize,
+    },
+    MissingBucket(PathBuf),
+    CorruptBucket {
+        path: PathBuf,
+    },
+}
+
+impl Display for CompactionResumeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompactionResumeError::MissingCheckpoint(path) => write!(
+                f,
+                "Cannot resume the links compaction, the checkpoint {} does not exist",
+                path.display()
+            ),
+            CompactionResumeError::MalformedCheckpoint(path) => write!(
+                f,
+                "Cannot resume the links compaction, the checkpoint {} is malformed",
+                path.display()
+            ),
+            CompactionResumeError::BucketsCountMismatch { checkpoint, current } => write!(
+                f,
+                "Cannot resume the links compaction, the checkpoint has {} buckets instead of {}",
+                checkpoint, current
+            ),
+            CompactionResumeError::IterationNotCompleted {
+                iteration,
+                last_completed,
+            } => write!(
+                f,
+                "Cannot resume the links compaction from iteration {}, the last completed one is {}",
+                iteration, last_completed
+            ),
+            CompactionResumeError::MissingBucket(path) => write!(
+                f,
+                "Cannot resume the links compaction, the bucket {} is missing",
+                path.display()
+            ),
+            CompactionResumeError::CorruptBucket { path } => write!(
+                f,
+                "Cannot resume the links compaction, the bucket {} does not match its checksum",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Error for CompactionResumeError {}
+
+/// Error raised when a links compaction checkpoint (its manifest, or the checksum or the sync of
+/// a completed bucket) cannot be written
+#[derive(Clone, Debug)]
+pub struct CheckpointWriteError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl CheckpointWriteError {
+    fn new(path: &Path, err: std::io::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl Display for CheckpointWriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cannot write the links compaction checkpoint file {}: {}",
+            self.path.display(),
+            self.message
+        )
+    }
+}
+
+impl Error for CheckpointWriteError {}
+
+/// Buckets of the results map and of the unitigs map written by a checkpointed links compaction round
+pub fn compaction_round_maps_paths(temp_dir: &Path, round: usize) -> (PathBuf, PathBuf) {
+    (
+        get_temp_buckets_path(temp_dir.join(format!("results_map_r{}", round))),
+        get_temp_buckets_path(temp_dir.join(format!("unitigs_map_r{}", round))),
+    )
+}
+
+/// Buckets of the output links of a links compaction round, read by the next round
+pub fn compaction_round_links_path(temp_dir: &Path, round: usize) -> PathBuf {
+    get_temp_buckets_path(temp_dir.join(format!("linksi{}", round)))
+}
+
+/// Buckets completed by a checkpointed links compaction round, all needed to resume from the next
+/// iteration: its output links and the maps it wrote
+pub fn checkpointed_round_buckets(
+    temp_dir: &Path,
+    round: usize,
+    buckets_count: usize,
+) -> Vec<PathBuf> {
+    let (results_map, unitigs_map) = compaction_round_maps_paths(temp_dir, round);
+    let mut buckets = generate_bucket_names(
+        compaction_round_links_path(temp_dir, round),
+        buckets_count,
+        None,
+    );
+    buckets.extend(generate_bucket_names(results_map, buckets_count, None));
+    buckets.extend(generate_bucket_names(unitigs_map, buckets_count, None));
+    buckets
+}
+
+/// Manifest of a checkpointed links compaction, recording the last completed iteration.
+/// Each checkpointed round writes its maps in separate buckets, so the compaction can be
+/// resumed after the last completed iteration starting from its output links
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinksCompactionCheckpoint {
+    pub buckets_count: usize,
+    pub last_completed_iteration: usize,
+}
+
+impl LinksCompactionCheckpoint {
+    fn manifest_path(temp_dir: &Path) -> PathBuf {
+        get_temp_file_path(temp_dir.join("links_compaction.checkpoint"))
+    }
+
+    /// Writes the manifest. With checksums, a CRC32 of each completed bucket is stored next to it,
+    /// to detect a corrupted bucket when resuming. With sync_file, the completed buckets and then
+    /// the manifest itself are synced to disk before the manifest replaces the previous one,
+    /// so that a checkpoint is never recorded before the buckets it refers to are durable
+    pub fn write(
+        &self,
+        temp_dir: &Path,
+        completed_buckets: &[PathBuf],
+        checksums: bool,
+        sync_file: Option<&dyn Fn(&Path) -> std::io::Result<()>>,
+    ) -> Result<(), CheckpointWriteError> {
+        if checksums {
+            for bucket in completed_buckets {
+                write_bucket_checksum(bucket)
+                    .map_err(|err| CheckpointWriteError::new(&bucket_checksum_path(bucket), err))?;
+            }
+        }
+
+        if let Some(sync_file) = sync_file {
+            for bucket in completed_buckets {
+                sync_file(bucket).map_err(|err| CheckpointWriteError::new(bucket, err))?;
+                if checksums {
+                    let checksum_path = bucket_checksum_path(bucket);
+                    sync_file(&checksum_path)
+                        .map_err(|err| CheckpointWriteError::new(&checksum_path, err))?;
+                }
+            }
+        }
+
+        // Replace the manifest atomically, to never leave a partially written one after a crash
+        let manifest_path = Self::manifest_path(temp_dir);
+        let temp_path = manifest_path.with_extension("checkpoint.tmp");
+        std::fs::write(
+            &temp_path,
+            format!(
+                "buckets_count={}\nlast_completed_iteration={}\n",
+                self.buckets_count, self.last_completed_iteration
+            ),
+        )
+        .map_err(|err| CheckpointWriteError::new(&temp_path, err))?;
+
+        if let Some(sync_file) = sync_file {
+            sync_file(&temp_path).map_err(|err| CheckpointWriteError::new(&temp_path, err))?;
+        }
+        std::fs::rename(&temp_path, &manifest_path)
+            .map_err(|err| CheckpointWriteError::new(&manifest_path, err))?;
+        // The rename is durable only after its directory is synced
+        if let Some(sync_file) = sync_file {
+            sync_file(temp_dir).map_err(|err| CheckpointWriteError::new(temp_dir, err))?;
+        }
+        Ok(())
+    }
+
+    pub fn read(temp_dir: &Path) -> Result<Self, CompactionResumeError> {
+        let manifest_path = Self::manifest_path(temp_dir);
+        let manifest = std::fs::read_to_string(&manifest_path)
+            .map_err(|_| CompactionResumeError::MissingCheckpoint(manifest_path.clone()))?;
+
+        let mut buckets_count = None;
+        let mut last_completed_iteration = None;
+        for line in manifest.lines() {
+            match line.split_once('=') {
+                Some(("buckets_count", value)) => buckets_count = value.parse().ok(),
+                Some(("last_completed_iteration", value)) => {
+                    last_completed_iteration = value.parse().ok()
+                }
+                _ => {}
+            }
+        }
+
+        match (buckets_count, last_completed_iteration) {
+            (Some(buckets_count), Some(last_completed_iteration)) => Ok(Self {
+                buckets_count,
+                last_completed_iteration,
+            }),
+            _ => Err(CompactionResumeError::MalformedCheckpoint(manifest_path)),
+        }
+    }
+
+    /// Checks that the compaction can restart from the given (non zero) iteration: it must not
+    /// skip any round, and both the input links of the iteration and the maps of all the
+    /// previous rounds must be available, and match their checksums if they were written
+    pub fn validate_resume(
+        &self,
+        temp_dir: &Path,
+        iteration: usize,
+        buckets_count: usize,
+    ) -> Result<(), CompactionResumeError> {
+        if self.buckets_count != buckets_count {
+            return Err(CompactionResumeError::BucketsCountMismatch {
+                checkpoint: self.buckets_count,
+                current: buckets_count,
+            });
+        }
+
+        if iteration == 0 || iteration > self.last_completed_iteration + 1 {
+            return Err(CompactionResumeError::IterationNotCompleted {
+                iteration,
+                last_completed: self.last_completed_iteration,
+            });
+        }
+
+        let links = compaction_round_links_path(temp_dir, iteration - 1);
+        let mut required_buckets = generate_bucket_names(links, buckets_count, None);
+        for round in 0..iteration {
+            let (results_map, unitigs_map) = compaction_round_maps_paths(temp_dir, round);
+            required_buckets.extend(generate_bucket_names(results_map, buckets_count, None));
+            required_buckets.extend(generate_bucket_names(unitigs_map, buckets_count, None));
+        }
+
+        if let Some(bucket) = required_buckets.iter().find(|bucket| !bucket.exists()) {
+            return Err(CompactionResumeError::MissingBucket(bucket.clone()));
+        }
+
+        match required_buckets
+            .into_iter()
+            .find(|bucket| verify_bucket_checksum(bucket) == Some(false))
+        {
+            Some(path) => Err(CompactionResumeError::CorruptBucket { path }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Path of the CRC32 of a checkpointed bucket
+fn bucket_checksum_path(bucket: &Path) -> PathBuf {
+    let mut path = bucket.as_os_str().to_owned();
+    path.push(".crc32");
+    PathBuf::from(path)
+}
+
+fn compute_bucket_checksum(bucket: &Path) -> std::io::Result<u32> {
+    let mut file = File::open(bucket)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; DEFAULT_OUTPUT_BUFFER_SIZE];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+/// Stores the CRC32 of the contents of a finalized bucket next to it. The checksum is computed
+/// once the bucket is complete rather than while writing it, as the buckets writers come from
+/// parallel-processor: only the checkpointed buckets (the output links and the maps of each round)
+/// are covered, and they are verified by validate_resume before a resumed compaction reads them
+pub fn write_bucket_checksum(bucket: &Path) -> std::io::Result<()> {
+    let checksum = compute_bucket_checksum(bucket)?;
+    std::fs::write(bucket_checksum_path(bucket), format!("{:08x}\n", checksum))
+}
+
+/// Checks a bucket against its stored CRC32, None if the bucket has no checksum
+pub fn verify_bucket_checksum(bucket: &Path) -> Option<bool> {
+    let expected = std::fs::read_to_string(bucket_checksum_path(bucket)).ok()?;
+    let matches = match (
+        u32::from_str_radix(expected.trim(), 16),
+        compute_bucket_checksum(bucket),
+    ) {
+        (Ok(expected), Ok(checksum)) => expected == checksum,
+        _ => false,
+    };
+    Some(matches)
+}
+
+/// Flushes the contents of a file (or of a directory entries) to disk
+pub fn sync_file(path: &Path) -> std::io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+/// Copies the maps written by the checkpointed rounds 0..rounds_count in the final maps buckets
+pub fn merge_compaction_round_maps(
+    temp_dir: &Path,
+    rounds_count: usize,
+    buckets_count: usize,
+    result_map_buckets: &Arc<MultiThreadBuckets<LockFreeBinaryWriter>>,
+    final_buckets: &Arc<MultiThreadBuckets<LockFreeBinaryWriter>>,
+    buffers: LinksCompactionBuffers,
+) -> Result<(), DecodeError> {
+    (0..buckets_count)
+        .into_par_iter()
+        .try_for_each(|bucket_index| {
+            let mut results_tmp = SingleBucketThreadDispatcher::<_, LinkMappingSerializer>::new(
+                buffers.results,
+                bucket_index as BucketIndexType,
+                result_map_buckets,
+            );
+            let mut final_links_tmp = SingleBucketThreadDispatcher::<_, UnitigLinkSerializer>::new(
+                buffers.links,
+                bucket_index as BucketIndexType,
+                final_buckets,
+            );
+
+            let mut unitigs_vec = Vec::new();
+
+            for round in 0..rounds_count {
+                let (results_map, unitigs_map) = compaction_round_maps_paths(temp_dir, round);
+
+                let results_map_reader = LockFreeBinaryReader::new(
+                    &generate_bucket_names(results_map, buckets_count, None)[bucket_index],
+                    RemoveFileMode::Remove {
+                        remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                    },
+                    DEFAULT_PREFETCH_AMOUNT,
+                );
+                read_link_mappings_bucket(results_map_reader.get_single_stream(), |mapping| {
+                    results_tmp.add_element(&(), &mapping);
+                })?;
+
+                let unitigs_map_reader = LockFreeBinaryReader::new(
+                    &generate_bucket_names(unitigs_map, buckets_count, None)[bucket_index],
+                    RemoveFileMode::Remove {
+                        remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                    },
+                    DEFAULT_PREFETCH_AMOUNT,
+                );
+                let mut unitigs_map_stream = unitigs_map_reader.get_single_stream();
+
+                let mut deserializer = UnitigLinkSerializer::new();
+                while let Some(link) =
+                    deserializer.read_from(&mut unitigs_map_stream, &mut unitigs_vec, &mut ())
+                {
+                    final_links_tmp.add_element(&unitigs_vec, &link);
+                }
+                unitigs_vec.clear();
+            }
+
+            results_tmp.finalize();
+            final_links_tmp.finalize();
+            Ok(())
+        })
+}
+
 pub fn links_compaction(
     links_inputs: Vec<PathBuf>,
     output_dir: impl AsRef<Path>,
@@ -28,25 +584,32 @@ pub fn links_compaction(
     elab_index: usize,
     result_map_buckets: &Arc<MultiThreadBuckets<LockFreeBinaryWriter>>,
     final_buckets: &Arc<MultiThreadBuckets<LockFreeBinaryWriter>>,
+    round_dump: Option<&UnitigsPathsWriter>,
+    // Keeps the input links after reading them, to resume from this iteration if it is interrupted
+    keep_inputs: bool,
     // links_manager: &UnitigLinksManager,
+    // Sized as buffers, shared by the iterations
     link_thread_buffers: &ScopedThreadLocal<BucketsThreadBuffer>,
     result_thread_buffers: &ScopedThreadLocal<BucketsThreadBuffer>,
-) -> (Vec<PathBuf>, u64) {
+    buffers: LinksCompactionBuffers,
+) -> Result<(Vec<PathBuf>, LinksCompactionStats), LinksCorruptionError> {
+    let total_joined = AtomicU64::new(0);
+    let total_lonely = AtomicU64::new(0);
     let totsum = AtomicU64::new(0);
 
     let links_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
         buckets_count,
-        output_dir
-            .as_ref()
-            .to_path_buf()
-            .join(format!("linksi{}", elab_index)),
+        spread_temp_buckets(
+            compaction_round_links_path(output_dir.as_ref(), elab_index),
+            buckets_count,
+        ),
         &(
             get_memory_mode(SwapPriority::LinksBuckets),
             LockFreeBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
         ),
     ));
 
-    links_inputs.par_iter().for_each(|input| {
+    links_inputs.par_iter().try_for_each(|input| {
         let bucket_index = get_bucket_index(input);
 
         let mut link_buffers = link_thread_buffers.get();
@@ -55,10 +618,11 @@ pub fn links_compaction(
             link_buffers.take(),
         );
         let mut final_links_tmp = SingleBucketThreadDispatcher::<_, UnitigLinkSerializer>::new(
-            DEFAULT_PER_CPU_BUFFER_SIZE,
+            buffers.links,
             bucket_index,
             &final_buckets,
         );
+        let mut round_dump_buffer = Vec::new();
         // let mut thread_links_manager = ThreadUnitigsLinkManager::new(links_manager, bucket_index);
 
         let mut result_buffers = result_thread_buffers.get();
@@ -71,8 +635,12 @@ pub fn links_compaction(
 
         let file_reader = LockFreeBinaryReader::new(
             input,
-            RemoveFileMode::Remove {
-                remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+            if keep_inputs {
+                RemoveFileMode::Keep
+            } else {
+                RemoveFileMode::Remove {
+                    remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
+                }
             },
             DEFAULT_PREFETCH_AMOUNT,
         );
@@ -85,11 +653,13 @@ pub fn links_compaction(
         let mut current_unitigs_vec = Vec::new();
         let mut final_unitigs_vec = Vec::new();
 
-        let mut deserializer = UnitigLinkSerializer::new();
-        while let Some(entry) = deserializer.read_from(&mut stream, &mut last_unitigs_vec, &mut ())
-        {
-            vec.push(entry);
-        }
+        // Validate the links once, all the following accesses can use the unchecked slices
+        read_links_bucket(
+            &mut stream,
+            bucket_index,
+            &mut last_unitigs_vec,
+            |entry, _| vec.push(entry),
+        )?;
 
         drop(file_reader);
 
@@ -111,6 +681,8 @@ pub fn links_compaction(
 
         fast_smart_radix_sort::<_, Compare, false>(&mut vec[..]);
 
+        let mut join_links = 0;
+        let mut not_links = 0;
         let mut rem_links = 0;
 
         for x in vec.group_by_mut(|a, b| a.entry() == b.entry()) {
@@ -182,7 +754,7 @@ pub fn links_compaction(
                     // Update the complemented status to match the one of the new entry
                     flags.set_reverse_complement(new_entry.is_reverse_complemented());
 
-                    // join_links += 1;
+                    join_links += 1;
                     assert!(flags.end_sealed() || !flags.begin_sealed());
 
                     (
@@ -216,7 +788,7 @@ pub fn links_compaction(
                     assert!(!flags.begin_sealed() || is_lonely);
 
                     if is_lonely {
-                        // not_links += 1;
+                        not_links += 1;
                         flags.seal_beginning();
 
                         if flags.end_sealed() {
@@ -228,10 +800,20 @@ pub fn links_compaction(
 
                             // thread_links_manager.notify_add_read();
 
-                            final_links_tmp.add_element(
-                                &final_unitigs_vec,
-                                &UnitigLink::new(entry.entry(), flags, entries),
-                            );
+                            let final_link = UnitigLink::new(entry.entry(), flags, entries);
+                            final_links_tmp.add_element(&final_unitigs_vec, &final_link);
+                            if let Some(round_dump) = round_dump {
+                                format_round_dump_unitig(
+                                    &mut round_dump_buffer,
+                                    bucket_index,
+                                    entry.entry(),
+                                    linked,
+                                );
+                                if round_dump_buffer.len() >= DEFAULT_OUTPUT_BUFFER_SIZE {
+                                    round_dump.write_formatted(&round_dump_buffer);
+                                    round_dump_buffer.clear();
+                                }
+                            }
 
                             results_tmp.add_element(
                                 bucket_index,
@@ -275,10 +857,20 @@ pub fn links_compaction(
 
                         // thread_links_manager.notify_add_read();
 
-                        final_links_tmp.add_element(
-                            &final_unitigs_vec,
-                            &UnitigLink::new(entry.entry(), flags, entries),
-                        );
+                        let final_link = UnitigLink::new(entry.entry(), flags, entries);
+                        final_links_tmp.add_element(&final_unitigs_vec, &final_link);
+                        if let Some(round_dump) = round_dump {
+                            format_round_dump_unitig(
+                                &mut round_dump_buffer,
+                                bucket_index,
+                                entry.entry(),
+                                unitig_entries,
+                            );
+                            if round_dump_buffer.len() >= DEFAULT_OUTPUT_BUFFER_SIZE {
+                                round_dump.write_formatted(&round_dump_buffer);
+                                round_dump_buffer.clear();
+                            }
+                        }
 
                         for link in unitig_entries.iter() {
                             results_tmp.add_element(
@@ -346,11 +938,317 @@ pub fn links_compaction(
             }
         }
 
+        total_joined.fetch_add(join_links, Ordering::Relaxed);
+        total_lonely.fetch_add(not_links, Ordering::Relaxed);
         totsum.fetch_add(rem_links, Ordering::Relaxed);
         link_buffers.put_back(links_tmp.finalize().0);
         final_links_tmp.finalize();
+        if let Some(round_dump) = round_dump {
+            round_dump.write_formatted(&round_dump_buffer);
+        }
         result_buffers.put_back(results_tmp.finalize().0);
-    });
+        Ok(())
+    })?;
+
+    Ok((
+        links_buckets.finalize(),
+        LinksCompactionStats {
+            joined_links: total_joined.into_inner(),
+            lonely_links: total_lonely.into_inner(),
+            remaining_links: totsum.into_inner(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        checkpointed_round_buckets, compaction_round_dump_path, compaction_round_links_path,
+        compaction_round_maps_paths, format_round_dump_unitig, links_compaction_completed,
+        CheckpointWriteError, CompactionResumeError, CompactionTrend, LinksCompactionBuffers,
+        LinksCompactionCheckpoint, LinksCompactionStats,
+    };
+    use config::DEFAULT_PER_CPU_BUFFER_SIZE;
+    use io::generate_bucket_names;
+    use io::structs::unitig_link::UnitigIndex;
+    use std::cell::RefCell;
+    use std::path::{Path, PathBuf};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn compaction_iterations_cap() {
+        assert!(links_compaction_completed(0, 0, Some(1)).unwrap());
+        assert!(!links_compaction_completed(0, 10, None).unwrap());
+        assert!(!links_compaction_completed(1, 10, Some(3)).unwrap());
+
+        // A graph still having links to compact after the last allowed iteration
+        let error = links_compaction_completed(2, 10, Some(3)).unwrap_err();
+        assert_eq!(error.iterations, 3);
+        assert_eq!(error.remaining_links, 10);
+        assert!(error.to_string().contains("10 links remaining"));
+
+        // Converging exactly at the last allowed iteration is fine
+        assert!(links_compaction_completed(2, 0, Some(3)).unwrap());
+    }
+
+    #[test]
+    fn compaction_convergence_trend() {
+        let stats = |joined_links, remaining_links| LinksCompactionStats {
+            joined_links,
+            lonely_links: 0,
+            remaining_links,
+        };
+
+        // A compaction shrinking the graph in the first two iterations and then getting stuck
+        let mut trend = CompactionTrend::new(0);
+        assert!(!trend.record(&stats(400, 1000)));
+        assert!(!trend.record(&stats(300, 600)));
+        assert!(trend.record(&stats(0, 600)));
+        assert_eq!(trend.format(), "0:1000 -> 1:600 -> 2:600");
+
+        // A resumed compaction reporting from its first iteration, and converging
+        let mut trend = CompactionTrend::new(3);
+        assert!(!trend.record(&stats(10, 20)));
+        assert!(!trend.record(&stats(20, 0)));
+        assert_eq!(trend.format(), "3:20 -> 4:0");
+    }
+
+    #[test]
+    fn configured_buffer_sizes() {
+        let default_buffers = LinksCompactionBuffers::new(0, 0);
+        assert_eq!(
+            default_buffers.links.as_bytes(),
+            DEFAULT_PER_CPU_BUFFER_SIZE.as_bytes()
+        );
+        assert_eq!(
+            default_buffers.results.as_bytes(),
+            DEFAULT_PER_CPU_BUFFER_SIZE.as_bytes()
+        );
+
+        let buffers = LinksCompactionBuffers::new(64 * 1024, 1);
+        assert_eq!(buffers.links.as_bytes(), 64 * 1024);
+        assert_eq!(buffers.results.as_bytes(), 1024);
+
+        // The same links written through a smaller buffer are flushed more often
+        let small_buffers = LinksCompactionBuffers::new(2048, 0);
+        let written_bytes = 1 << 20;
+        assert_eq!(
+            LinksCompactionBuffers::flushes_count(small_buffers.links, written_bytes),
+            512
+        );
+        assert!(
+            LinksCompactionBuffers::flushes_count(small_buffers.links, written_bytes)
+                > LinksCompactionBuffers::flushes_count(default_buffers.links, written_bytes)
+        );
+    }
+
+    #[test]
+    fn compaction_round_dump_paths() {
+        let output_file = Path::new("/tmp/graph.fa");
+        let round0 = compaction_round_dump_path(output_file, 0);
+        let round1 = compaction_round_dump_path(output_file, 1);
+
+        assert_eq!(round0, Path::new("/tmp/graph.unitigs_round_0.tsv"));
+        assert_ne!(round0, round1);
+        assert_ne!(round0.as_path(), output_file);
+        assert_eq!(round1.parent(), output_file.parent());
+    }
+
+    #[test]
+    fn round_dump_lines() {
+        let mut buffer = vec![];
+        format_round_dump_unitig(
+            &mut buffer,
+            2,
+            15,
+            &[UnitigIndex::new(0, 7, true), UnitigIndex::new(5, 3, false)],
+        );
+        // A circular unitig closing on its first partial unitig
+        format_round_dump_unitig(&mut buffer, 1, 4, &[UnitigIndex::new(1, 4, false)]);
+
+        assert_eq!(buffer, b"2:15\t0:7-,5:3+\n1:4\t1:4+\n");
+    }
+
+    #[test]
+    fn compaction_resume_checkpoint() {
+        const BUCKETS_COUNT: usize = 4;
+
+        let test_dir = TestDir::new("compaction");
+        let temp_dir = test_dir.path();
+
+        assert!(matches!(
+            LinksCompactionCheckpoint::read(temp_dir),
+            Err(CompactionResumeError::MissingCheckpoint(_))
+        ));
+
+        let checkpoint = LinksCompactionCheckpoint {
+            buckets_count: BUCKETS_COUNT,
+            last_completed_iteration: 2,
+        };
+        checkpoint.write(temp_dir, &[], false, None).unwrap();
+        assert_eq!(
+            LinksCompactionCheckpoint::read(temp_dir).unwrap(),
+            checkpoint
+        );
+
+        // Resume from iteration 3, with the maps of rounds 0..3 and the output links of round 2
+        let mut buckets = generate_bucket_names(temp_dir.join("linksi2"), BUCKETS_COUNT, None);
+        for round in 0..3 {
+            let (results_map, unitigs_map) = compaction_round_maps_paths(temp_dir, round);
+            buckets.extend(generate_bucket_names(results_map, BUCKETS_COUNT, None));
+            buckets.extend(generate_bucket_names(unitigs_map, BUCKETS_COUNT, None));
+        }
+        for bucket in &buckets[1..] {
+            std::fs::write(bucket, []).unwrap();
+        }
+
+        let missing = checkpoint.validate_resume(temp_dir, 3, BUCKETS_COUNT);
+        std::fs::write(&buckets[0], []).unwrap();
+        let resumed = checkpoint.validate_resume(temp_dir, 3, BUCKETS_COUNT);
+        let skipped = checkpoint.validate_resume(temp_dir, 4, BUCKETS_COUNT);
+        let other_buckets = checkpoint.validate_resume(temp_dir, 3, BUCKETS_COUNT * 2);
+
+        assert!(
+            matches!(missing, Err(CompactionResumeError::MissingBucket(bucket)) if bucket == buckets[0])
+        );
+        assert!(resumed.is_ok());
+        assert!(matches!(
+            skipped,
+            Err(CompactionResumeError::IterationNotCompleted {
+                iteration: 4,
+                last_completed: 2
+            })
+        ));
+        assert!(matches!(
+            other_buckets,
+            Err(CompactionResumeError::BucketsCountMismatch {
+                checkpoint: BUCKETS_COUNT,
+                current: 8
+            })
+        ));
+    }
+
+    /// Writes a checksummed checkpoint of round 0, then flips a bit in the middle of the bucket
+    /// corrupted_index of the round, returning the resume results before and after corrupting it
+    fn resume_with_corrupted_bucket(
+        name: &str,
+        corrupted_index: usize,
+    ) -> (
+        Vec<PathBuf>,
+        Result<(), CompactionResumeError>,
+        Result<(), CompactionResumeError>,
+    ) {
+        let test_dir = TestDir::new(&format!("compaction-{}", name));
+        let temp_dir = test_dir.path();
+
+        // The links of round 0 and its maps, to resume from iteration 1
+        let round_buckets = checkpointed_round_buckets(temp_dir, 0, 1);
+        for (index, bucket) in round_buckets.iter().enumerate() {
+            std::fs::write(bucket, vec![index as u8; 1000]).unwrap();
+        }
+
+        let checkpoint = LinksCompactionCheckpoint {
+            buckets_count: 1,
+            last_completed_iteration: 0,
+        };
+        checkpoint
+            .write(temp_dir, &round_buckets, true, None)
+            .unwrap();
+        let intact = checkpoint.validate_resume(temp_dir, 1, 1);
+
+        let mut contents = std::fs::read(&round_buckets[corrupted_index]).unwrap();
+        contents[500] ^= 0x10;
+        std::fs::write(&round_buckets[corrupted_index], contents).unwrap();
+        let corrupted = checkpoint.validate_resume(temp_dir, 1, 1);
+
+        (round_buckets, intact, corrupted)
+    }
+
+    #[test]
+    fn corrupted_checkpoint_bucket_is_rejected() {
+        let (round_buckets, intact, corrupted) = resume_with_corrupted_bucket("checksum", 1);
+
+        assert!(intact.is_ok());
+        assert!(matches!(
+            corrupted,
+            Err(CompactionResumeError::CorruptBucket { path }) if path == round_buckets[1]
+        ));
+    }
+
+    #[test]
+    fn corrupted_checkpoint_links_are_rejected() {
+        // The first bucket of the round is its output links, the input of the resumed iteration
+        let (round_buckets, intact, corrupted) = resume_with_corrupted_bucket("links-checksum", 0);
+
+        assert!(intact.is_ok());
+        assert!(matches!(
+            corrupted,
+            Err(CompactionResumeError::CorruptBucket { path }) if path == round_buckets[0]
+        ));
+    }
+
+    #[test]
+    fn synced_checkpoint_buckets_before_the_manifest() {
+        let test_dir = TestDir::new("compaction-sync");
+        let temp_dir = test_dir.path();
+
+        // The output links of the round are read when resuming, so they are synced as its maps
+        let buckets = checkpointed_round_buckets(temp_dir, 0, 1);
+        let links = compaction_round_links_path(temp_dir, 0);
+        assert_eq!(buckets[0], generate_bucket_names(links, 1, None)[0]);
+
+        // Records each synced file, with whether the manifest was already in place
+        let synced = RefCell::new(vec![]);
+        let mock_sync = |path: &Path| -> std::io::Result<()> {
+            let recorded = LinksCompactionCheckpoint::read(temp_dir).is_ok();
+            synced.borrow_mut().push((path.to_path_buf(), recorded));
+            Ok(())
+        };
+
+        let checkpoint = LinksCompactionCheckpoint {
+            buckets_count: 1,
+            last_completed_iteration: 0,
+        };
+        checkpoint
+            .write(temp_dir, &buckets, false, Some(&mock_sync))
+            .unwrap();
+        let written = LinksCompactionCheckpoint::read(temp_dir);
+
+        assert_eq!(written.unwrap(), checkpoint);
+        let synced = synced.into_inner();
+        let synced_paths: Vec<PathBuf> = synced.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(synced_paths[..3], buckets[..]);
+        assert_eq!(synced_paths[4], temp_dir);
+        // Only the directory is synced after the manifest has been recorded
+        assert_eq!(
+            synced
+                .iter()
+                .map(|(_, recorded)| *recorded)
+                .collect::<Vec<_>>(),
+            [false, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn failed_checkpoint_sync_is_an_error() {
+        let test_dir = TestDir::new("compaction-sync-error");
+        let temp_dir = test_dir.path();
+
+        let (results_map, unitigs_map) = compaction_round_maps_paths(temp_dir, 0);
+        let buckets = vec![results_map, unitigs_map];
+        let failing_sync = |_: &Path| -> std::io::Result<()> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+        };
+
+        let checkpoint = LinksCompactionCheckpoint {
+            buckets_count: 1,
+            last_completed_iteration: 0,
+        };
+        let result = checkpoint.write(temp_dir, &buckets, false, Some(&failing_sync));
+        let recorded = LinksCompactionCheckpoint::read(temp_dir);
 
-    (links_buckets.finalize(), totsum.load(Ordering::Relaxed))
+        assert!(matches!(result, Err(CheckpointWriteError { path, .. }) if path == buckets[0]));
+        // The manifest is not recorded when its buckets could not be synced
+        assert!(recorded.is_err());
+    }
 }
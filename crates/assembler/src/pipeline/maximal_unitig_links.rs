@@ -15,13 +15,16 @@ use crate::pipeline::maximal_unitig_links::maximal_unitig_index::{
 use colors::colors_manager::color_types::PartialUnitigsColorStructure;
 use colors::colors_manager::ColorsManager;
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, SwapPriority,
-    DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_compression_level_info, get_memory_mode, get_temp_buckets_path, spread_temp_buckets,
+    BucketIndexType, SwapPriority, DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PER_CPU_BUFFER_SIZE,
+    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
 };
 use hashes::ExtendableHashTraitType;
 use hashes::{HashFunction, HashFunctionFactory, HashableSequence, MinimizerHashFunctionFactory};
 use io::concurrent::structured_sequences::concurrent::FastaWriterConcurrentBuffer;
-use io::concurrent::structured_sequences::{StructuredSequenceBackend, StructuredSequenceWriter};
+use io::concurrent::structured_sequences::{
+    is_canonical_sequence, StructuredSequenceBackend, StructuredSequenceWriter,
+};
 use io::concurrent::temp_reads::creads_utils::CompressedReadsBucketDataSerializer;
 use io::concurrent::temp_reads::extra_data::SequenceExtraDataTempBufferManagement;
 use parallel_processor::buckets::concurrent::{BucketsThreadBuffer, BucketsThreadDispatcher};
@@ -54,6 +57,8 @@ pub fn build_maximal_unitigs_links<
         BK,
     >,
     k: usize,
+    // The output writer emits the unitigs in canonical orientation, so the links must be flipped accordingly
+    canonical_output: bool,
 ) {
     // TODO: Parametrize depending on the reads count!
     const DEFAULT_BUCKET_HASHES_SIZE_LOG: usize = 8;
@@ -74,7 +79,10 @@ pub fn build_maximal_unitigs_links<
         let maximal_unitigs_extremities_hashes_buckets =
             Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
                 buckets_count,
-                temp_dir.join("mu-hashes"),
+                spread_temp_buckets(
+                    get_temp_buckets_path(temp_dir.join("mu-hashes")),
+                    buckets_count,
+                ),
                 &(
                     get_memory_mode(SwapPriority::HashBuckets),
                     CompressedBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
@@ -87,6 +95,7 @@ pub fn build_maximal_unitigs_links<
                 .into_par_iter()
                 .for_each(|_| {
                     let mut unitigs_partial_count = 0;
+                    let mut temp_sequence_buffer = Vec::new();
 
                     let mut hashes_tmp = BucketsThreadDispatcher::<
                         _,
@@ -115,6 +124,12 @@ pub fn build_maximal_unitigs_links<
                                 let read_len = read.bases_count();
                                 unitigs_partial_count += 1;
 
+                                let flipped = canonical_output && {
+                                    temp_sequence_buffer.clear();
+                                    temp_sequence_buffer.extend(read.as_bases_iter());
+                                    !is_canonical_sequence(&temp_sequence_buffer)
+                                };
+
                                 let first_hash = MH::new(read.sub_slice(0..(k - 1)), k - 1)
                                     .iter()
                                     .next()
@@ -140,6 +155,7 @@ pub fn build_maximal_unitigs_links<
                                         index,
                                         MaximalUnitigPosition::Beginning,
                                         first_hash.is_forward(),
+                                        flipped,
                                     ),
                                 );
 
@@ -156,6 +172,7 @@ pub fn build_maximal_unitigs_links<
                                             index,
                                             MaximalUnitigPosition::Beginning,
                                             !first_hash.is_forward(),
+                                            flipped,
                                         ),
                                     );
                                 }
@@ -172,6 +189,7 @@ pub fn build_maximal_unitigs_links<
                                         index,
                                         MaximalUnitigPosition::Ending,
                                         !last_hash.is_forward(),
+                                        flipped,
                                     ),
                                 );
 
@@ -188,6 +206,7 @@ pub fn build_maximal_unitigs_links<
                                             index,
                                             MaximalUnitigPosition::Ending,
                                             last_hash.is_forward(),
+                                            flipped,
                                         ),
                                     );
                                 }
@@ -219,7 +238,10 @@ pub fn build_maximal_unitigs_links<
 
         let maximal_links_buckets = Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
             buckets_count,
-            temp_dir.join("maximal-links"),
+            spread_temp_buckets(
+                get_temp_buckets_path(temp_dir.join("maximal-links")),
+                buckets_count,
+            ),
             &(
                 get_memory_mode(SwapPriority::LinksBuckets),
                 CompressedBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
@@ -275,9 +297,11 @@ pub fn build_maximal_unitigs_links<
                             .map(|v| {
                                 MaximalUnitigIndex::new(
                                     v.entry(),
-                                    MaximalUnitigFlags::new_direction(
+                                    MaximalUnitigFlags::new_output_direction(
                                         val.position() == MaximalUnitigPosition::Beginning,
                                         v.position() == MaximalUnitigPosition::Ending,
+                                        val.flipped(),
+                                        v.flipped(),
                                     ),
                                 )
                             }),
@@ -22,7 +22,8 @@ pub struct MaximalHashEntry<H: Copy> {
 }
 
 impl<H: Copy> MaximalHashEntry<H> {
-    const ENTRY_OFFSET: usize = 2;
+    const ENTRY_OFFSET: usize = 3;
+    const FLIPPED_OFFSET: usize = 2;
     const DIRECTION_OFFSET: usize = 1;
     const POSITION_OFFSET: usize = 0;
 
@@ -31,6 +32,7 @@ impl<H: Copy> MaximalHashEntry<H> {
         entry: u64,
         position: MaximalUnitigPosition,
         direction_forward: bool,
+        flipped: bool,
     ) -> Self {
         Self {
             hash,
@@ -39,7 +41,8 @@ impl<H: Copy> MaximalHashEntry<H> {
                     MaximalUnitigPosition::Ending => 1,
                     MaximalUnitigPosition::Beginning => 0,
                 }) << Self::POSITION_OFFSET)
-                | ((if direction_forward { 1 } else { 0 }) << Self::DIRECTION_OFFSET),
+                | ((if direction_forward { 1 } else { 0 }) << Self::DIRECTION_OFFSET)
+                | ((flipped as u64) << Self::FLIPPED_OFFSET),
         }
     }
 
@@ -58,6 +61,11 @@ impl<H: Copy> MaximalHashEntry<H> {
     pub fn direction(&self) -> bool {
         ((self.encoded >> Self::DIRECTION_OFFSET) & 0x1) == 1
     }
+
+    /// The unitig of this entry is written as its reverse complement (canonical output)
+    pub fn flipped(&self) -> bool {
+        ((self.encoded >> Self::FLIPPED_OFFSET) & 0x1) == 1
+    }
 }
 
 pub struct MaximalHashEntrySerializer<H: Serialize + DeserializeOwned + Copy>(PhantomData<H>);
@@ -31,6 +31,17 @@ impl MaximalUnitigFlags {
         )
     }
 
+    /// Direction of a link between two unitigs, where each one can be written flipped
+    /// (as its reverse complement) in the output
+    pub const fn new_output_direction(
+        flip_current: bool,
+        flip_other: bool,
+        current_flipped: bool,
+        other_flipped: bool,
+    ) -> MaximalUnitigFlags {
+        Self::new_direction(flip_current ^ current_flipped, flip_other ^ other_flipped)
+    }
+
     pub fn flip_other(&self) -> bool {
         self.get_bit(Self::FLIP_OTHER)
     }
@@ -261,6 +272,32 @@ impl IdentSequenceWriter for DoubleMaximalUnitigLinks {
         todo!()
     }
 
+    fn write_as_fastg(
+        &self,
+        stream: &mut impl Write,
+        extra_buffer: &Self::TempBuffer,
+        reverse_complement: bool,
+    ) {
+        // A link leaving from the end of the (flipped) current unitig reaches the (flipped) other one
+        let mut separator = ":";
+        for entries in &self.0 {
+            let entries = entries.entries.get_slice(extra_buffer);
+            for entry in entries {
+                if entry.flags.flip_current() == reverse_complement {
+                    write!(
+                        stream,
+                        "{}EDGE_{}{}",
+                        separator,
+                        entry.index,
+                        if entry.flags.flip_other() { "'" } else { "" },
+                    )
+                    .unwrap();
+                    separator = ",";
+                }
+            }
+        }
+    }
+
     fn parse_as_ident<'a>(_ident: &[u8], _extra_buffer: &mut Self::TempBuffer) -> Option<Self> {
         unimplemented!()
     }
@@ -269,3 +306,121 @@ impl IdentSequenceWriter for DoubleMaximalUnitigLinks {
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DoubleMaximalUnitigLinks, MaximalUnitigFlags, MaximalUnitigIndex, MaximalUnitigLink,
+    };
+    use io::concurrent::structured_sequences::concurrent::FastaWriterConcurrentBuffer;
+    use io::concurrent::structured_sequences::fasta::FastaWriter;
+    use io::concurrent::structured_sequences::fastg::StructSeqFastgWriter;
+    use io::concurrent::structured_sequences::StructuredSequenceWriter;
+    use utils::test_dir::TestDir;
+    use utils::vec_slice::VecSlice;
+
+    #[test]
+    fn canonical_output_flips_unitig_and_links() {
+        let test_dir = TestDir::new("canonical-output");
+        let path = test_dir.join("unitigs.fa");
+
+        let writer = StructuredSequenceWriter::<(), DoubleMaximalUnitigLinks, _>::new(
+            FastaWriter::new_plain(&path),
+        )
+        .with_canonical_output(true);
+
+        // TTTGC is greater than its reverse complement GCAAA, so it is written flipped,
+        // while its neighbor AAGCT is already canonical
+        let links_buffer = vec![MaximalUnitigIndex::new(
+            1,
+            MaximalUnitigFlags::new_output_direction(false, false, true, false),
+        )];
+        let links = DoubleMaximalUnitigLinks([
+            MaximalUnitigLink::new(0, VecSlice::new(0, 1)),
+            MaximalUnitigLink::new(0, VecSlice::new(1, 0)),
+        ]);
+
+        let mut buffer = FastaWriterConcurrentBuffer::new(&writer, 1024, false);
+        buffer.add_read(b"TTTGC", None, (), &(), links, &links_buffer);
+        buffer.add_read(
+            b"AAGCT",
+            None,
+            (),
+            &(),
+            DoubleMaximalUnitigLinks::EMPTY,
+            &vec![],
+        );
+        buffer.finalize();
+        writer.finalize();
+
+        let output = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(output, ">0 LN:i:5 L:-:1:+\nGCAAA\n>1 LN:i:5\nAAGCT\n");
+    }
+
+    #[test]
+    fn fastg_branching_node() {
+        let test_dir = TestDir::new("fastg-output");
+        let path = test_dir.join("unitigs.fastg");
+
+        let writer = StructuredSequenceWriter::<(), DoubleMaximalUnitigLinks, _>::new(
+            StructSeqFastgWriter::new_plain(&path),
+        );
+
+        // With k = 3, AACGT branches at its end both to GTA and to GTAA, the reverse complement of TTAC
+        let unitigs: [(&[u8], Vec<MaximalUnitigIndex>); 3] = [
+            (
+                b"AACGT",
+                vec![
+                    MaximalUnitigIndex::new(1, MaximalUnitigFlags::new_direction(false, false)),
+                    MaximalUnitigIndex::new(2, MaximalUnitigFlags::new_direction(false, true)),
+                ],
+            ),
+            (
+                b"GTA",
+                vec![MaximalUnitigIndex::new(
+                    0,
+                    MaximalUnitigFlags::new_direction(true, true),
+                )],
+            ),
+            (
+                b"TTAC",
+                vec![MaximalUnitigIndex::new(
+                    0,
+                    MaximalUnitigFlags::new_direction(false, true),
+                )],
+            ),
+        ];
+
+        let mut buffer = FastaWriterConcurrentBuffer::new(&writer, 1024, false);
+        for (sequence, links_buffer) in &unitigs {
+            let links = DoubleMaximalUnitigLinks([
+                MaximalUnitigLink::new(0, VecSlice::new(0, links_buffer.len())),
+                MaximalUnitigLink::new(0, VecSlice::new(links_buffer.len(), 0)),
+            ]);
+            buffer.add_read(sequence, None, (), &(), links, links_buffer);
+        }
+        buffer.finalize();
+        writer.finalize();
+
+        let output = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            [
+                ">EDGE_0:EDGE_1,EDGE_2';",
+                "AACGT",
+                ">EDGE_0';",
+                "ACGTT",
+                ">EDGE_1;",
+                "GTA",
+                ">EDGE_1':EDGE_0';",
+                "TAC",
+                ">EDGE_2:EDGE_0';",
+                "TTAC",
+                ">EDGE_2';",
+                "GTAA",
+            ]
+        );
+    }
+}
@@ -1,13 +1,13 @@
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_compression_level_info, get_memory_mode, get_temp_buckets_path, spread_temp_buckets,
+    SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
 };
 use hashes::{HashFunctionFactory, HashableSequence, MinimizerHashFunctionFactory};
 use io::concurrent::temp_reads::creads_utils::{
     CompressedReadsBucketData, CompressedReadsBucketDataSerializer,
 };
 
-use crate::structs::link_mapping::{LinkMapping, LinkMappingSerializer};
+use crate::structs::link_mapping::{read_link_mappings_bucket, LinkMapping};
 use colors::colors_manager::color_types::PartialUnitigsColorStructure;
 use colors::colors_manager::{color_types, ColorsManager};
 use config::DEFAULT_OUTPUT_BUFFER_SIZE;
@@ -19,6 +19,7 @@ use io::concurrent::temp_reads::extra_data::{
 };
 use io::get_bucket_index;
 use io::structs::unitig_link::UnitigIndex;
+use io::varint::DecodeError;
 use parallel_processor::buckets::concurrent::{BucketsThreadBuffer, BucketsThreadDispatcher};
 use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBinaryReader;
 use parallel_processor::buckets::readers::lock_free_binary_reader::LockFreeBinaryReader;
@@ -138,14 +139,17 @@ pub fn reorganize_reads<
     temp_path: &Path,
     out_file: &StructuredSequenceWriter<PartialUnitigsColorStructure<H, MH, CX>, (), BK>,
     buckets_count: usize,
-) -> (Vec<PathBuf>, PathBuf) {
+) -> Result<(Vec<PathBuf>, PathBuf), DecodeError> {
     PHASES_TIMES_MONITOR
         .write()
         .start_phase("phase: reads reorganization".to_string());
 
     let buckets = Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
         buckets_count,
-        temp_path.join("reads_bucket"),
+        spread_temp_buckets(
+            get_temp_buckets_path(temp_path.join("reads_bucket")),
+            buckets_count,
+        ),
         &(
             get_memory_mode(SwapPriority::ReorganizeReads),
             CompressedBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
@@ -162,7 +166,7 @@ pub fn reorganize_reads<
         BucketsThreadBuffer::new(DEFAULT_PER_CPU_BUFFER_SIZE, buckets_count)
     });
 
-    inputs.par_iter().for_each(|(read_file, mapping_file)| {
+    let result = inputs.par_iter().try_for_each(|(read_file, mapping_file)| {
         let mut buffers = reads_thread_buffers.get();
 
         let mut tmp_reads_buffer = BucketsThreadDispatcher::<
@@ -183,16 +187,16 @@ pub fn reorganize_reads<
 
         let bucket_index = get_bucket_index(read_file);
 
-        LockFreeBinaryReader::new(
+        let mappings_reader = LockFreeBinaryReader::new(
             mapping_file,
             RemoveFileMode::Remove {
                 remove_fs: !KEEP_FILES.load(Ordering::Relaxed),
             },
             DEFAULT_PREFETCH_AMOUNT,
-        )
-        .decode_all_bucket_items::<LinkMappingSerializer, _>((), &mut (), |link, _| {
+        );
+        read_link_mappings_bucket(mappings_reader.get_single_stream(), |link| {
             mappings.push(link);
-        });
+        })?;
 
         parallel_processor::make_comparer!(Compare, LinkMapping, entry: u64);
         fast_smart_radix_sort::<_, Compare, false>(&mut mappings[..]);
@@ -265,8 +269,10 @@ pub fn reorganize_reads<
         buffers.put_back(tmp_reads_buffer.finalize().0);
         tmp_lonely_unitigs_buffer.finalize();
 
-        assert_eq!(map_index, mappings.len())
+        assert_eq!(map_index, mappings.len());
+        Ok(())
     });
+    result?;
 
-    (buckets.finalize(), PathBuf::new())
+    Ok((buckets.finalize(), PathBuf::new()))
 }
@@ -1 +1,2 @@
 pub mod link_mapping;
+pub mod unitigs_paths;
@@ -1,6 +1,6 @@
 use byteorder::ReadBytesExt;
 use config::BucketIndexType;
-use io::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
+use io::varint::{encode_varint, try_decode_varint, DecodeError, VARINT_MAX_SIZE};
 use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
 use std::io::{Read, Write};
 
@@ -10,6 +10,35 @@ pub struct LinkMapping {
     pub entry: u64,
 }
 
+impl LinkMapping {
+    /// Reads the next mapping, returning Ok(None) on a clean end of stream
+    /// and an error if the stream ends or is corrupted in the middle of a mapping
+    pub fn from_stream(mut stream: impl Read) -> Result<Option<LinkMapping>, DecodeError> {
+        let bucket = match try_decode_varint(|| stream.read_u8().ok())? {
+            Some(bucket) => bucket,
+            None => return Ok(None),
+        };
+        let entry =
+            try_decode_varint(|| stream.read_u8().ok())?.ok_or(DecodeError::TruncatedVarint)?;
+        Ok(Some(LinkMapping {
+            bucket: bucket as BucketIndexType,
+            entry,
+        }))
+    }
+}
+
+/// Reads all the mappings of a bucket, returning an error if it ends or is corrupted
+/// in the middle of a mapping
+pub fn read_link_mappings_bucket(
+    mut stream: impl Read,
+    mut callback: impl FnMut(LinkMapping),
+) -> Result<(), DecodeError> {
+    while let Some(mapping) = LinkMapping::from_stream(&mut stream)? {
+        callback(mapping);
+    }
+    Ok(())
+}
+
 pub struct LinkMappingSerializer;
 
 impl BucketItemSerializer for LinkMappingSerializer {
@@ -41,16 +70,13 @@ impl BucketItemSerializer for LinkMappingSerializer {
 
     fn read_from<'a, S: Read>(
         &mut self,
-        mut stream: S,
+        stream: S,
         _read_buffer: &'a mut Self::ReadBuffer,
         _: &mut Self::ExtraDataBuffer,
     ) -> Option<Self::ReadType<'a>> {
-        let bucket = decode_varint(|| stream.read_u8().ok())?;
-        let entry = decode_varint(|| stream.read_u8().ok())?;
-        Some(LinkMapping {
-            bucket: bucket as BucketIndexType,
-            entry,
-        })
+        // A corrupted mapping ends the stream, the buckets are read with read_link_mappings_bucket
+        // to detect it
+        LinkMapping::from_stream(stream).ok().flatten()
     }
 
     #[inline(always)]
@@ -58,3 +84,69 @@ impl BucketItemSerializer for LinkMappingSerializer {
         VARINT_MAX_SIZE * 2
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{read_link_mappings_bucket, LinkMapping, LinkMappingSerializer};
+    use io::varint::DecodeError;
+    use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+    use std::io::Cursor;
+
+    #[test]
+    fn truncated_mapping_in_stream() {
+        let mut serializer = LinkMappingSerializer::new();
+        let mut buffer = vec![];
+        for (bucket, entry) in [(3, 1 << 30), (200, 5)] {
+            serializer.write_to(&LinkMapping { bucket, entry }, &mut buffer, &(), &());
+        }
+
+        // Clean end of stream after the two mappings
+        let mut stream = Cursor::new(&buffer);
+        assert_eq!(
+            LinkMapping::from_stream(&mut stream)
+                .unwrap()
+                .unwrap()
+                .entry,
+            1 << 30
+        );
+        assert_eq!(
+            LinkMapping::from_stream(&mut stream)
+                .unwrap()
+                .unwrap()
+                .bucket,
+            200
+        );
+        assert!(LinkMapping::from_stream(&mut stream).unwrap().is_none());
+
+        // Truncated in the middle of the first entry varint
+        let mut stream = Cursor::new(&buffer[..3]);
+        assert_eq!(
+            LinkMapping::from_stream(&mut stream).unwrap_err(),
+            DecodeError::TruncatedVarint
+        );
+    }
+
+    #[test]
+    fn truncated_mappings_bucket_is_an_error() {
+        let mut serializer = LinkMappingSerializer::new();
+        let mut buffer = vec![];
+        for (bucket, entry) in [(3, 1 << 30), (200, 5)] {
+            serializer.write_to(&LinkMapping { bucket, entry }, &mut buffer, &(), &());
+        }
+
+        let mut entries = vec![];
+        read_link_mappings_bucket(Cursor::new(&buffer), |mapping| entries.push(mapping.entry))
+            .unwrap();
+        assert_eq!(entries, vec![1 << 30, 5]);
+
+        // Truncated in the middle of the second mapping, after the first one is read
+        let mut entries = vec![];
+        assert_eq!(
+            read_link_mappings_bucket(Cursor::new(&buffer[..buffer.len() - 1]), |mapping| {
+                entries.push(mapping.entry)
+            }),
+            Err(DecodeError::TruncatedVarint)
+        );
+        assert_eq!(entries, vec![1 << 30]);
+    }
+}
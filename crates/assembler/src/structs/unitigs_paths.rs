@@ -0,0 +1,141 @@
+use config::DEFAULT_OUTPUT_BUFFER_SIZE;
+use io::structs::unitig_link::UnitigIndex;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Debug sidecar file that stores, for each final unitig, the ordered list of the
+/// partial unitigs (bucket/entry pairs coming from the kmers merge step) that compose it.
+/// Each line has the format: <unitig index>\t<bucket>:<entry><+/->,...
+/// where the sign tells if the partial unitig was reverse complemented when joined.
+pub struct UnitigsPathsWriter {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl UnitigsPathsWriter {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            writer: Mutex::new(BufWriter::with_capacity(
+                DEFAULT_OUTPUT_BUFFER_SIZE,
+                File::create(path).unwrap(),
+            )),
+        }
+    }
+
+    pub fn format_path(
+        buffer: &mut Vec<u8>,
+        unitig_index: u64,
+        path: impl Iterator<Item = (UnitigIndex, bool)>,
+    ) {
+        write!(buffer, "{}\t", unitig_index).unwrap();
+        for (i, (part, reverse_complemented)) in path.enumerate() {
+            if i > 0 {
+                buffer.push(b',');
+            }
+            write!(
+                buffer,
+                "{}:{}{}",
+                part.bucket(),
+                part.index(),
+                if reverse_complemented { '-' } else { '+' }
+            )
+            .unwrap();
+        }
+        buffer.push(b'\n');
+    }
+
+    pub fn write_formatted(&self, buffer: &[u8]) {
+        self.writer.lock().write_all(buffer).unwrap();
+    }
+}
+
+/// Per thread buffer of the paths of the unitigs not yet flushed to the output,
+/// as their final index is known only after the sequences flush
+pub struct PendingUnitigsPaths {
+    parts: Vec<(UnitigIndex, bool)>,
+    ends: Vec<usize>,
+    formatted: Vec<u8>,
+}
+
+impl PendingUnitigsPaths {
+    pub fn new() -> Self {
+        Self {
+            parts: Vec::new(),
+            ends: Vec::new(),
+            formatted: Vec::new(),
+        }
+    }
+
+    pub fn add_path(&mut self, path: &[(UnitigIndex, bool)]) {
+        self.parts.extend_from_slice(path);
+        self.ends.push(self.parts.len());
+    }
+
+    /// Assigns consecutive indexes starting from first_index to the pending paths and writes them
+    pub fn flush(&mut self, writer: &UnitigsPathsWriter, first_index: u64) {
+        let mut start = 0;
+        for (offset, end) in self.ends.iter().enumerate() {
+            UnitigsPathsWriter::format_path(
+                &mut self.formatted,
+                first_index + offset as u64,
+                self.parts[start..*end].iter().copied(),
+            );
+            start = *end;
+        }
+        writer.write_formatted(&self.formatted);
+
+        self.formatted.clear();
+        self.parts.clear();
+        self.ends.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PendingUnitigsPaths, UnitigsPathsWriter};
+    use io::structs::unitig_link::UnitigIndex;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn path_follows_concatenation_order() {
+        // Small chain of three partial unitigs, the middle one joined in reverse complement
+        let chain = [
+            (UnitigIndex::new(3, 10, false), false),
+            (UnitigIndex::new(0, 7, true), true),
+            (UnitigIndex::new(5, 2, false), false),
+        ];
+
+        let mut buffer = vec![];
+        UnitigsPathsWriter::format_path(&mut buffer, 42, chain.iter().copied());
+        assert_eq!(buffer, b"42\t3:10+,0:7-,5:2+\n");
+
+        // A backwards unitig is built by iterating its parts in reverse order
+        buffer.clear();
+        UnitigsPathsWriter::format_path(&mut buffer, 43, chain.iter().rev().copied());
+        assert_eq!(buffer, b"43\t5:2+,0:7-,3:10+\n");
+    }
+
+    #[test]
+    fn pending_paths_get_the_flushed_indexes() {
+        let test_dir = TestDir::new("paths");
+        let path = test_dir.join("paths.tsv");
+        let writer = UnitigsPathsWriter::new(&path);
+
+        let mut pending = PendingUnitigsPaths::new();
+        pending.add_path(&[(UnitigIndex::new(1, 4, false), false)]);
+        pending.add_path(&[
+            (UnitigIndex::new(2, 8, false), false),
+            (UnitigIndex::new(3, 1, true), true),
+        ]);
+        // The sequences of the first two unitigs were flushed starting from index 10
+        pending.flush(&writer, 10);
+        pending.add_path(&[(UnitigIndex::new(0, 5, false), true)]);
+        pending.flush(&writer, 20);
+        drop(writer);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(contents, "10\t1:4+\n11\t2:8+,3:1-\n20\t0:5-\n");
+    }
+}
@@ -80,7 +80,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         if MH::INVERTIBLE {
             for (hash, rhentry) in map_struct.rhash_map.iter() {
                 let count = rhentry.get_kmer_multiplicity();
-                if count < global_data.min_multiplicity {
+                if !global_data.thresholds.is_solid(hash, count) {
                     continue;
                 }
 
@@ -137,7 +137,10 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                     };
 
                     let count = rhentry.get_kmer_multiplicity();
-                    if count < global_data.min_multiplicity {
+                    if !global_data
+                        .thresholds
+                        .is_solid(&hash.to_unextendable(), count)
+                    {
                         continue;
                     }
 
@@ -197,7 +200,8 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                 &mut map_struct.temp_colors,
                 &mut map_struct.rhash_map,
                 global_data.k,
-                global_data.min_multiplicity,
+                // The kmers of the reference can have a lower threshold
+                global_data.thresholds.lowest(),
             );
         }
 
@@ -273,7 +277,10 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                             idx,
                         );
                         if let Some(hash) = map_struct.rhash_map.get(&new_hash.to_unextendable()) {
-                            if hash.get_kmer_multiplicity() >= global_data.min_multiplicity {
+                            if global_data
+                                .thresholds
+                                .is_solid(&new_hash.to_unextendable(), hash.get_kmer_multiplicity())
+                            {
                                 // println!("Forward match extend read {:x?}!", new_hash);
                                 count += 1;
                                 temp_data = (new_hash, idx);
@@ -291,8 +298,10 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                                 if let Some(hash) =
                                     map_struct.rhash_map.get(&bw_hash.to_unextendable())
                                 {
-                                    if hash.get_kmer_multiplicity() >= global_data.min_multiplicity
-                                    {
+                                    if global_data.thresholds.is_solid(
+                                        &bw_hash.to_unextendable(),
+                                        hash.get_kmer_multiplicity(),
+                                    ) {
                                         if ocount > 0 {
                                             break 'ext_loop (current_hash, false);
                                         }
@@ -389,6 +398,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                 read_index,
                 fw_merge,
                 Direction::Forward,
+                fw_hash.is_rc_symmetric(),
                 buckets_count_bits,
             );
 
@@ -399,6 +409,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                 read_index,
                 bw_merge,
                 Direction::Backward,
+                bw_hash.is_rc_symmetric(),
                 buckets_count_bits,
             );
         });
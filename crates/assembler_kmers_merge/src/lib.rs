@@ -1,15 +1,19 @@
 use crate::final_executor::ParallelKmersMergeFinalExecutor;
 use crate::map_processor::{ParallelKmersMergeMapProcessor, KMERGE_TEMP_DIR};
 use crate::preprocessor::ParallelKmersMergePreprocessor;
+use crate::reference::{MultiplicityThresholds, ReferenceKmers};
 use crate::structs::{ResultsBucket, RetType};
-use assembler_minimizer_bucketing::AssemblerMinimizerBucketingExecutorFactory;
+use assembler_minimizer_bucketing::{
+    AssemblerMinimizerBucketingExecutorFactory, AssemblerMinimizerBucketingGlobalData,
+    WeightedSeqColorData,
+};
 use colors::colors_manager::color_types::{
     GlobalColorsTableWriter, MinimizerBucketingSeqColorDataType,
 };
 use colors::colors_manager::{color_types, ColorsManager};
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, SwapPriority,
-    MINIMUM_SUBBUCKET_KMERS_COUNT, RESPLITTING_MAX_K_M_DIFFERENCE,
+    get_compression_level_info, get_memory_mode, get_temp_buckets_path, spread_temp_buckets,
+    BucketIndexType, SwapPriority, MINIMUM_SUBBUCKET_KMERS_COUNT, RESPLITTING_MAX_K_M_DIFFERENCE,
 };
 use crossbeam::queue::*;
 use hashes::HashFunctionFactory;
@@ -28,7 +32,7 @@ use parallel_processor::execution_manager::memory_tracker::MemoryTracker;
 #[cfg(feature = "mem-analysis")]
 use parallel_processor::mem_tracker::MemoryInfo;
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
-use std::cmp::min;
+use std::cmp::{max, min};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicU64;
@@ -38,6 +42,7 @@ use utils::owned_drop::OwnedDrop;
 mod final_executor;
 mod map_processor;
 mod preprocessor;
+mod reference;
 pub mod structs;
 
 pub struct GlobalMergeData<
@@ -48,12 +53,13 @@ pub struct GlobalMergeData<
     k: usize,
     m: usize,
     buckets_count: usize,
-    min_multiplicity: usize,
+    thresholds: MultiplicityThresholds,
+    max_multiplicity: usize,
     colors_global_table: Arc<GlobalColorsTableWriter<H, MH, CX>>,
     output_results_buckets:
         ArrayQueue<ResultsBucket<color_types::PartialUnitigsColorStructure<H, MH, CX>>>,
     hashes_buckets: Arc<MultiThreadBuckets<LockFreeBinaryWriter>>,
-    global_resplit_data: Arc<MinimizerBucketingCommonData<()>>,
+    global_resplit_data: Arc<MinimizerBucketingCommonData<AssemblerMinimizerBucketingGlobalData>>,
     sequences_size_total: AtomicU64,
     hasnmap_kmers_total: AtomicU64,
     kmer_batches_count: AtomicU64,
@@ -70,7 +76,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 {
     type SequencesResplitterFactory = AssemblerMinimizerBucketingExecutorFactory<H, CX>;
     type GlobalExtraData = GlobalMergeData<H, MH, CX>;
-    type AssociatedExtraData = MinimizerBucketingSeqColorDataType<CX>;
+    type AssociatedExtraData = WeightedSeqColorData<MinimizerBucketingSeqColorDataType<CX>>;
 
     type PreprocessorType = ParallelKmersMergePreprocessor<H, MH, CX>;
     type MapProcessorType = ParallelKmersMergeMapProcessor<H, MH, CX>;
@@ -116,13 +122,14 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         entry: u64,
         do_merge: bool,
         direction: Direction,
+        palindrome: bool,
         buckets_count_bits: usize,
     ) {
         if do_merge {
             hashes_tmp.add_element(
                 MH::get_bucket(0, buckets_count_bits, hash),
                 &(),
-                &HashEntry::new(hash, bucket, entry, direction),
+                &HashEntry::new(hash, bucket, entry, direction, palindrome),
             );
         }
     }
@@ -139,6 +146,9 @@ pub fn kmers_merge<
     colors_global_table: Arc<GlobalColorsTableWriter<H, MH, CX>>,
     buckets_count: usize,
     min_multiplicity: usize,
+    max_multiplicity: Option<usize>,
+    reference: Option<PathBuf>,
+    reference_min_multiplicity: usize,
     out_directory: P,
     k: usize,
     m: usize,
@@ -152,9 +162,22 @@ pub fn kmers_merge<
     MH::initialize(k);
     *KMERGE_TEMP_DIR.write() = Some(out_directory.as_ref().to_path_buf());
 
+    let thresholds = MultiplicityThresholds {
+        min_multiplicity,
+        reference: reference.map(|reference| {
+            (
+                ReferenceKmers::from_file::<MH>(reference, k),
+                reference_min_multiplicity,
+            )
+        }),
+    };
+
     let hashes_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
         buckets_count,
-        out_directory.as_ref().join("hashes"),
+        spread_temp_buckets(
+            get_temp_buckets_path(out_directory.as_ref().join("hashes")),
+            buckets_count,
+        ),
         &(
             get_memory_mode(SwapPriority::HashBuckets),
             LockFreeBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
@@ -165,7 +188,10 @@ pub fn kmers_merge<
 
     let reads_buckets = MultiThreadBuckets::<CompressedBinaryWriter>::new(
         buckets_count,
-        out_directory.as_ref().join("result"),
+        spread_temp_buckets(
+            get_temp_buckets_path(out_directory.as_ref().join("result")),
+            buckets_count,
+        ),
         &(
             get_memory_mode(SwapPriority::ResultBuckets),
             CompressedBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
@@ -192,7 +218,9 @@ pub fn kmers_merge<
         k,
         m,
         buckets_count,
-        min_multiplicity,
+        thresholds,
+        // The cap must not drop kmers that would be kept otherwise
+        max_multiplicity: max_multiplicity.map_or(usize::MAX, |m| max(m, min_multiplicity)),
         colors_global_table,
         output_results_buckets,
         hashes_buckets: hashes_buckets.clone(),
@@ -206,7 +234,7 @@ pub fn kmers_merge<
             buckets_count,
             k,
             1,
-            (),
+            AssemblerMinimizerBucketingGlobalData::default(),
         )),
         sequences_size_total: AtomicU64::new(0),
         hasnmap_kmers_total: AtomicU64::new(0),
@@ -328,6 +356,9 @@ mod tests {
             global_colors_table.clone(),
             buckets_count,
             min_multiplicity,
+            None,
+            None,
+            1,
             Path::new(TEMP_DIR),
             k,
             m,
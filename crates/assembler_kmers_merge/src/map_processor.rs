@@ -1,4 +1,5 @@
 use crate::ParallelKmersMergeFactory;
+use assembler_minimizer_bucketing::WeightedSeqColorData;
 use colors::colors_manager::color_types::MinimizerBucketingSeqColorDataType;
 use colors::colors_manager::{color_types, ColorsManager};
 use colors::colors_manager::{ColorsMergeManager, MinimizerBucketingSeqColorData};
@@ -113,6 +114,21 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
     }
 }
 
+/// Counts an occurrence of a kmer with the weight of its sequence, saturating at max_multiplicity.
+/// Returns true if the occurrence made the kmer reach min_multiplicity, checked on the counter as
+/// a weighted sequence can skip over the exact threshold value
+#[inline(always)]
+fn count_kmer_occurrence<CHI>(
+    entry: &mut MapEntry<CHI>,
+    weight: usize,
+    max_multiplicity: usize,
+    min_multiplicity: usize,
+) -> bool {
+    let previous_counter = entry.get_counter();
+    entry.add_saturating(weight, max_multiplicity);
+    previous_counter < min_multiplicity && entry.get_counter() >= min_multiplicity
+}
+
 pub struct ParallelKmersMergeMapProcessor<
     H: MinimizerHashFunctionFactory,
     MH: HashFunctionFactory,
@@ -164,7 +180,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         global_data: &<ParallelKmersMergeFactory<H, MH, CX> as KmersTransformExecutorFactory>::GlobalExtraData,
         batch: &Vec<(
             u8,
-            MinimizerBucketingSeqColorDataType<CX>,
+            WeightedSeqColorData<MinimizerBucketingSeqColorDataType<CX>>,
             CompressedReadIndipendent,
         )>,
         extra_data_buffer: &<MinimizerBucketingSeqColorDataType<CX> as SequenceExtraDataTempBufferManagement>::TempBuffer,
@@ -177,7 +193,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         let mut kmers_count = 0;
         let mut unique_kmers_count = 0;
 
-        for (flags, color, read) in batch.iter() {
+        for (flags, WeightedSeqColorData { color, weight }, read) in batch.iter() {
             let read = read.as_reference(ref_sequences);
 
             let hashes = MH::new(read, k);
@@ -210,7 +226,15 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                         | ((end_ignored as u8) << (is_forward as u8)),
                 );
 
-                entry.incr();
+                let min_multiplicity = global_data
+                    .thresholds
+                    .kmer_min_multiplicity(&hash.to_unextendable());
+                let reached_threshold = count_kmer_occurrence(
+                    entry,
+                    *weight,
+                    global_data.max_multiplicity,
+                    min_multiplicity,
+                );
 
                 CX::ColorsMergeManagerType::<H, MH>::add_temp_buffer_structure_el(
                     &mut map_packet.temp_colors,
@@ -219,7 +243,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                     entry,
                 );
 
-                if entry.get_counter() == global_data.min_multiplicity {
+                if reached_threshold {
                     min_idx = min(min_idx, idx / 4);
                     max_idx = max(max_idx, idx);
                 }
@@ -231,6 +255,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
                 global_data.k,
                 global_data.m,
                 *flags,
+                *weight,
             );
 
             if !MH::INVERTIBLE {
@@ -296,3 +321,26 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
         map_packet
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::count_kmer_occurrence;
+    use structs::map_entry::MapEntry;
+
+    #[test]
+    fn weighted_occurrences_saturate_on_the_max_multiplicity() {
+        const MAX_MULTIPLICITY: usize = 10;
+        const MIN_MULTIPLICITY: usize = 4;
+
+        // A kmer seen in sequences of weight 3, with a cap that is not a multiple of the weight
+        let mut entry = MapEntry::new(());
+        let reached_threshold: Vec<_> = (0..5)
+            .map(|_| count_kmer_occurrence(&mut entry, 3, MAX_MULTIPLICITY, MIN_MULTIPLICITY))
+            .collect();
+
+        // The second occurrence skips from 3 to 6 over the threshold, and it is reported once
+        assert_eq!(reached_threshold, [false, true, false, false, false]);
+        assert_eq!(entry.get_counter(), MAX_MULTIPLICITY);
+        assert_eq!(entry.get_kmer_multiplicity(), MAX_MULTIPLICITY);
+    }
+}
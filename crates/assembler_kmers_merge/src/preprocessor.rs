@@ -4,7 +4,9 @@ use config::BucketIndexType;
 use config::READ_FLAG_INCL_END;
 use hashes::ExtendableHashTraitType;
 use hashes::HashFunction;
-use hashes::{HashFunctionFactory, HashableSequence, MinimizerHashFunctionFactory};
+use hashes::{
+    minimizer_order_key, HashFunctionFactory, HashableSequence, MinimizerHashFunctionFactory,
+};
 use io::compressed_read::CompressedRead;
 use kmers_transform::{KmersTransformExecutorFactory, KmersTransformPreprocessor};
 use std::marker::PhantomData;
@@ -50,7 +52,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 
         let minimizer = hashes
             .iter()
-            .min_by_key(|k| H::get_full_minimizer(k.to_unextendable()))
+            .min_by_key(|k| minimizer_order_key::<H>(k.to_unextendable()))
             .unwrap();
 
         H::get_bucket(
@@ -0,0 +1,281 @@
+use config::SPLIT_ON_SOFTMASK;
+use hashes::{unambiguous_ranges, ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+use io::compressed_read::{CompressedReadIndipendent, ReadsPacking};
+use io::sequences_reader::SequencesReader;
+use std::cmp::{max, min};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+// About 1% of false positives
+const BLOOM_BITS_PER_KMER: usize = 10;
+const BLOOM_HASHES_COUNT: u64 = 7;
+const BLOOM_MIN_BITS_LOG: u32 = 16;
+
+/// Bloom filter of the kmers of a reference, with the same hashes used by the kmers merge maps.
+/// A kmer not in the reference can be reported as present with a low probability,
+/// but a kmer of the reference is always found
+pub(crate) struct ReferenceKmers {
+    bits: Vec<u64>,
+    bits_mask: u64,
+}
+
+impl ReferenceKmers {
+    fn with_capacity(kmers_count: usize) -> Self {
+        let bits_log = max(
+            BLOOM_MIN_BITS_LOG,
+            (kmers_count * BLOOM_BITS_PER_KMER)
+                .next_power_of_two()
+                .ilog2(),
+        );
+        Self {
+            bits: vec![0; (1usize << bits_log) / 64],
+            bits_mask: (1u64 << bits_log) - 1,
+        }
+    }
+
+    // Double hashing from a single hash, the second one is odd to cover all the bits
+    fn bits_indexes(&self, hash: &impl Hash) -> impl Iterator<Item = u64> {
+        let mut hasher = DefaultHasher::new();
+        hash.hash(&mut hasher);
+        let first = hasher.finish();
+        let second = first.rotate_left(32) | 1;
+        let bits_mask = self.bits_mask;
+        (0..BLOOM_HASHES_COUNT).map(move |i| first.wrapping_add(i.wrapping_mul(second)) & bits_mask)
+    }
+
+    fn insert(&mut self, hash: &impl Hash) {
+        for bit in self.bits_indexes(hash) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn contains(&self, hash: &impl Hash) -> bool {
+        self.bits_indexes(hash)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    // Calls the callback with each stretch of only ACGT bases at least k long
+    fn for_each_valid_stretch(sequence: &[u8], k: usize, mut callback: impl FnMut(&[u8])) {
+        for stretch in sequence.split(|b| !matches!(b, b'A' | b'C' | b'G' | b'T')) {
+            if stretch.len() >= k {
+                callback(stretch);
+            }
+        }
+    }
+
+    /// Builds the filter from the sequences streamed by read_sequences, that is called twice,
+    /// first to count the kmers and size the filter, then to add them.
+    /// With the 4 bits packing each sequence is packed as a whole, keeping its ambiguous bases,
+    /// otherwise it is split in stretches of only ACGT bases
+    pub fn from_sequences<MH: HashFunctionFactory>(
+        read_sequences: impl Fn(&mut dyn FnMut(&[u8])),
+        k: usize,
+        packing: ReadsPacking,
+    ) -> Self {
+        let mut kmers_count = 0;
+        let mut storage = vec![];
+        read_sequences(&mut |sequence| match packing {
+            ReadsPacking::TwoBits => Self::for_each_valid_stretch(sequence, k, |stretch| {
+                kmers_count += stretch.len() - k + 1;
+            }),
+            ReadsPacking::FourBitsIupac => {
+                storage.clear();
+                let read = CompressedReadIndipendent::from_plain_iupac(sequence, &mut storage);
+                for range in unambiguous_ranges(&read.as_iupac_reference(&storage), k) {
+                    kmers_count += range.len() - k + 1;
+                }
+            }
+        });
+
+        let mut reference = Self::with_capacity(kmers_count);
+        read_sequences(&mut |sequence| match packing {
+            ReadsPacking::TwoBits => Self::for_each_valid_stretch(sequence, k, |stretch| {
+                storage.clear();
+                let read = CompressedReadIndipendent::from_plain(stretch, &mut storage);
+                for hash in MH::new(read.as_reference(&storage), k).iter() {
+                    reference.insert(&hash.to_unextendable());
+                }
+            }),
+            ReadsPacking::FourBitsIupac => {
+                storage.clear();
+                let read = CompressedReadIndipendent::from_plain_iupac(sequence, &mut storage);
+                let read = read.as_iupac_reference(&storage);
+                for range in unambiguous_ranges(&read, k) {
+                    for hash in MH::new(read.sub_slice(range), k).iter() {
+                        reference.insert(&hash.to_unextendable());
+                    }
+                }
+            }
+        });
+        reference
+    }
+
+    /// Builds the filter of the kmers of a FASTA/FASTQ (possibly compressed) reference,
+    /// packing its reads as selected in the global configuration
+    pub fn from_file<MH: HashFunctionFactory>(reference: impl AsRef<Path>, k: usize) -> Self {
+        let packing = ReadsPacking::from_config();
+        Self::from_sequences::<MH>(
+            |callback| {
+                // The 4 bits packing needs the ambiguity codes, that are replaced by N when normalized
+                let mut reader = match packing {
+                    ReadsPacking::TwoBits => SequencesReader::new(),
+                    ReadsPacking::FourBitsIupac => SequencesReader::new_raw(),
+                };
+                let split_on_softmask = SPLIT_ON_SOFTMASK.load(Ordering::Relaxed);
+                let mut masked = vec![];
+                reader.process_file_extended(
+                    reference.as_ref(),
+                    |sequence| {
+                        if packing == ReadsPacking::FourBitsIupac && split_on_softmask {
+                            masked.clear();
+                            masked.extend(sequence.seq.iter().map(|b| {
+                                if b.is_ascii_lowercase() {
+                                    b'N'
+                                } else {
+                                    *b
+                                }
+                            }));
+                            callback(&masked)
+                        } else {
+                            callback(sequence.seq)
+                        }
+                    },
+                    None,
+                    false,
+                    false,
+                )
+            },
+            k,
+            packing,
+        )
+    }
+}
+
+/// Minimum multiplicity needed to keep a kmer, lower for the kmers of the reference (if any)
+pub(crate) struct MultiplicityThresholds {
+    pub min_multiplicity: usize,
+    pub reference: Option<(ReferenceKmers, usize)>,
+}
+
+impl MultiplicityThresholds {
+    /// The lowest threshold of any kmer
+    pub fn lowest(&self) -> usize {
+        match &self.reference {
+            Some((_, reference_min_multiplicity)) => {
+                min(self.min_multiplicity, *reference_min_multiplicity)
+            }
+            None => self.min_multiplicity,
+        }
+    }
+
+    #[inline(always)]
+    pub fn kmer_min_multiplicity(&self, hash: &impl Hash) -> usize {
+        match &self.reference {
+            Some((reference, reference_min_multiplicity))
+                if *reference_min_multiplicity < self.min_multiplicity
+                    && reference.contains(hash) =>
+            {
+                *reference_min_multiplicity
+            }
+            _ => self.min_multiplicity,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_solid(&self, hash: &impl Hash, multiplicity: usize) -> bool {
+        // The reference is checked only for the kmers below the default threshold
+        multiplicity >= self.min_multiplicity || multiplicity >= self.kmer_min_multiplicity(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MultiplicityThresholds, ReferenceKmers};
+    use hashes::cn_seqhash::u64::CanonicalSeqHashFactory;
+    use hashes::{ExtendableHashTraitType, HashFunction, HashFunctionFactory};
+    use io::compressed_read::{CompressedReadIndipendent, ReadsPacking};
+
+    const K: usize = 15;
+
+    fn kmer_hash(kmer: &[u8]) -> u64 {
+        let mut storage = vec![];
+        let read = CompressedReadIndipendent::from_plain(kmer, &mut storage);
+        let hash = CanonicalSeqHashFactory::new(read.as_reference(&storage), K)
+            .iter()
+            .next()
+            .unwrap();
+        hash.to_unextendable()
+    }
+
+    #[test]
+    fn reference_kmers_have_a_lower_threshold() {
+        CanonicalSeqHashFactory::initialize(K);
+        let reference_sequences: &[&[u8]] = &[b"ACGTTGCAAGGCTTANNACGTA", b"GGATCCATTACGGCATTAGCA"];
+        let reference = ReferenceKmers::from_sequences::<CanonicalSeqHashFactory>(
+            |callback| {
+                for sequence in reference_sequences {
+                    callback(sequence);
+                }
+            },
+            K,
+            ReadsPacking::TwoBits,
+        );
+
+        let thresholds = MultiplicityThresholds {
+            min_multiplicity: 3,
+            reference: Some((reference, 1)),
+        };
+        assert_eq!(thresholds.lowest(), 1);
+
+        // In the reverse complement of the reference
+        let reference_kmer = kmer_hash(b"TGCCGTAATGGATCC");
+        let novel_kmer = kmer_hash(b"TTTTTCCCCCGGGGG");
+
+        // Both seen once, only the reference one is kept
+        assert!(thresholds.is_solid(&reference_kmer, 1));
+        assert!(!thresholds.is_solid(&novel_kmer, 1));
+        assert!(thresholds.is_solid(&novel_kmer, 3));
+        assert_eq!(thresholds.kmer_min_multiplicity(&reference_kmer), 1);
+        assert_eq!(thresholds.kmer_min_multiplicity(&novel_kmer), 3);
+    }
+
+    #[test]
+    fn iupac_packing_keeps_the_same_kmers() {
+        CanonicalSeqHashFactory::initialize(K);
+        // Ambiguity codes, a gap and a lowercase base, the kmers around them are skipped
+        let reference_sequences: &[&[u8]] = &[
+            b"ACGTTGCAAGGCTTARYACGTAGGCATTCAGGTCAAGCT",
+            b"GGATCCATTACGGCATT-GCAGGTTCCAGTAACGTTGAnGG",
+        ];
+        let build = |packing| {
+            ReferenceKmers::from_sequences::<CanonicalSeqHashFactory>(
+                |callback| {
+                    for sequence in reference_sequences {
+                        // The 2 bits packing reads the normalized sequences
+                        let sequence: Vec<_> = match packing {
+                            ReadsPacking::TwoBits => sequence
+                                .iter()
+                                .map(|b| match b.to_ascii_uppercase() {
+                                    b @ (b'A' | b'C' | b'G' | b'T') => b,
+                                    _ => b'N',
+                                })
+                                .collect(),
+                            ReadsPacking::FourBitsIupac => sequence.to_vec(),
+                        };
+                        callback(&sequence);
+                    }
+                },
+                K,
+                packing,
+            )
+        };
+
+        let two_bits = build(ReadsPacking::TwoBits);
+        let four_bits = build(ReadsPacking::FourBitsIupac);
+        assert_eq!(two_bits.bits, four_bits.bits);
+        // The kmer right after the ambiguity codes
+        assert!(four_bits.contains(&kmer_hash(b"ACGTAGGCATTCAGG")));
+    }
+}
@@ -1,4 +1,7 @@
+mod minimizers_dump;
+
 use ::dynamic_dispatch::dynamic_dispatch;
+use byteorder::ReadBytesExt;
 use colors::colors_manager::color_types::MinimizerBucketingSeqColorDataType;
 use colors::colors_manager::{ColorsManager, MinimizerBucketingSeqColorData};
 use colors::parsers::{SequenceIdent, SingleSequenceInfo};
@@ -8,24 +11,39 @@ use hashes::rolling::minqueue::RollingMinQueue;
 use hashes::ExtendableHashTraitType;
 use hashes::HashFunction;
 use hashes::MinimizerHashFunctionFactory;
-use io::concurrent::temp_reads::extra_data::SequenceExtraDataTempBufferManagement;
+use io::concurrent::temp_reads::extra_data::{
+    SequenceExtraDataConsecutiveCompression, SequenceExtraDataTempBufferManagement,
+};
 use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
 use io::sequences_stream::general::{GeneralSequenceBlockData, GeneralSequencesStream};
 use io::sequences_stream::SequenceInfo;
+use io::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
 use minimizer_bucketing::{
     GenericMinimizerBucketing, MinimizerBucketingCommonData, MinimizerBucketingExecutor,
     MinimizerBucketingExecutorFactory, MinimizerInputSequence,
 };
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use std::cmp::max;
+use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+pub use crate::minimizers_dump::MinimizersDump;
+pub use minimizer_bucketing::masking::MaskedIntervals;
+
+/// Global data of the assembler bucketing, the resplitting of the kmers merge uses the default one
+#[derive(Default)]
+pub struct AssemblerMinimizerBucketingGlobalData {
+    pub minimizers_dump: Option<Arc<MinimizersDump>>,
+}
+
 pub struct AssemblerMinimizerBucketingExecutor<H: MinimizerHashFunctionFactory, CX: ColorsManager> {
     minimizer_queue: RollingMinQueue<H>,
-    global_data: Arc<MinimizerBucketingCommonData<()>>,
+    global_data: Arc<MinimizerBucketingCommonData<AssemblerMinimizerBucketingGlobalData>>,
+    // Minimizers and buckets of the parts of the current sequence, if it is dumped
+    dumped_minimizers: Vec<(u64, BucketIndexType)>,
     _phantom: PhantomData<CX>,
 }
 
@@ -34,6 +52,10 @@ pub struct AssemblerPreprocessInfo<CX: ColorsManager> {
     color_info_buffer: <MinimizerBucketingSeqColorDataType<CX> as SequenceExtraDataTempBufferManagement>::TempBuffer,
     include_first: bool,
     include_last: bool,
+    // Weight of the input file of the current sequence
+    weight: usize,
+    // Input and read index of the current read, if it is sampled for the minimizers dump
+    dumped_read: Option<(usize, u64)>,
 }
 
 impl<CX: ColorsManager> Default for AssemblerPreprocessInfo<CX> {
@@ -44,13 +66,105 @@ impl<CX: ColorsManager> Default for AssemblerPreprocessInfo<CX> {
                     <MinimizerBucketingSeqColorDataType<CX> as SequenceExtraDataTempBufferManagement>::new_temp_buffer(),
             include_first: false,
             include_last: false,
+            weight: 1,
+            dumped_read: None,
         }
     }
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct InputFileInfo {
     file_color: ColorIndexType,
+    // Ignore the colors provided by the sequences stream and use the file color
+    force_file_color: bool,
+    // Each kmer of the file contributes this value to the kmers multiplicity
+    weight: usize,
+}
+
+impl Default for InputFileInfo {
+    fn default() -> Self {
+        Self {
+            file_color: 0,
+            force_file_color: false,
+            weight: 1,
+        }
+    }
+}
+
+/// Colors of a bucketed sequence, together with the weight of its input file.
+/// The weight is stored once for each bucket record and is added to the counter
+/// of each kmer of the sequence during the kmers merge
+#[derive(Clone, Debug)]
+pub struct WeightedSeqColorData<C: MinimizerBucketingSeqColorData> {
+    pub color: C,
+    pub weight: usize,
+}
+
+impl<C: MinimizerBucketingSeqColorData> SequenceExtraDataTempBufferManagement
+    for WeightedSeqColorData<C>
+{
+    type TempBuffer = C::TempBuffer;
+
+    #[inline(always)]
+    fn new_temp_buffer() -> C::TempBuffer {
+        C::new_temp_buffer()
+    }
+
+    #[inline(always)]
+    fn clear_temp_buffer(buffer: &mut C::TempBuffer) {
+        C::clear_temp_buffer(buffer);
+    }
+
+    fn copy_temp_buffer(dest: &mut C::TempBuffer, src: &C::TempBuffer) {
+        C::copy_temp_buffer(dest, src);
+    }
+
+    #[inline(always)]
+    fn copy_extra_from(extra: Self, src: &C::TempBuffer, dst: &mut C::TempBuffer) -> Self {
+        Self {
+            color: C::copy_extra_from(extra.color, src, dst),
+            weight: extra.weight,
+        }
+    }
+}
+
+impl<C: MinimizerBucketingSeqColorData> SequenceExtraDataConsecutiveCompression
+    for WeightedSeqColorData<C>
+{
+    type LastData = C::LastData;
+
+    #[inline(always)]
+    fn decode_extended(
+        buffer: &mut Self::TempBuffer,
+        reader: &mut impl Read,
+        last_data: Self::LastData,
+    ) -> Option<Self> {
+        let weight = decode_varint(|| reader.read_u8().ok())? as usize;
+        Some(Self {
+            color: C::decode_extended(buffer, reader, last_data)?,
+            weight,
+        })
+    }
+
+    #[inline(always)]
+    fn encode_extended(
+        &self,
+        buffer: &Self::TempBuffer,
+        writer: &mut impl Write,
+        last_data: Self::LastData,
+    ) {
+        encode_varint(|bytes| writer.write_all(bytes), self.weight as u64).unwrap();
+        self.color.encode_extended(buffer, writer, last_data);
+    }
+
+    #[inline(always)]
+    fn max_size(&self) -> usize {
+        self.color.max_size() + VARINT_MAX_SIZE
+    }
+
+    fn obtain_last_data(&self, last_data: Self::LastData) -> Self::LastData {
+        self.color.obtain_last_data(last_data)
+    }
 }
 
 pub struct AssemblerMinimizerBucketingExecutorFactory<
@@ -61,8 +175,8 @@ pub struct AssemblerMinimizerBucketingExecutorFactory<
 impl<H: MinimizerHashFunctionFactory, CX: ColorsManager> MinimizerBucketingExecutorFactory
     for AssemblerMinimizerBucketingExecutorFactory<H, CX>
 {
-    type GlobalData = ();
-    type ExtraData = MinimizerBucketingSeqColorDataType<CX>;
+    type GlobalData = AssemblerMinimizerBucketingGlobalData;
+    type ExtraData = WeightedSeqColorData<MinimizerBucketingSeqColorDataType<CX>>;
     type PreprocessInfo = AssemblerPreprocessInfo<CX>;
     type StreamInfo = InputFileInfo;
 
@@ -77,6 +191,7 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager> MinimizerBucketingExecu
         Self::ExecutorType {
             minimizer_queue: RollingMinQueue::new(global_data.k - global_data.m),
             global_data: global_data.clone(),
+            dumped_minimizers: vec![],
             _phantom: PhantomData,
         }
     }
@@ -90,7 +205,7 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
         &mut self,
         stream_info: &<AssemblerMinimizerBucketingExecutorFactory<H, CX> as MinimizerBucketingExecutorFactory>::StreamInfo,
         sequence_info: SequenceInfo,
-        _read_index: u64,
+        read_index: u64,
         sequence: &DnaSequence,
         preprocess_info: &mut <AssemblerMinimizerBucketingExecutorFactory<H, CX> as MinimizerBucketingExecutorFactory>::PreprocessInfo,
     ) {
@@ -100,7 +215,11 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
 
         preprocess_info.color_info = MinimizerBucketingSeqColorDataType::<CX>::create(
             SingleSequenceInfo {
-                static_color: sequence_info.color.unwrap_or(stream_info.file_color),
+                static_color: if stream_info.force_file_color {
+                    stream_info.file_color
+                } else {
+                    sequence_info.color.unwrap_or(stream_info.file_color)
+                },
                 sequence_ident: match sequence.format {
                     DnaSequencesFileType::FASTA | DnaSequencesFileType::FASTQ => {
                         SequenceIdent::FASTA(sequence.ident_data)
@@ -117,6 +236,14 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
         );
         preprocess_info.include_first = true;
         preprocess_info.include_last = true;
+        preprocess_info.weight = stream_info.weight;
+        preprocess_info.dumped_read = self
+            .global_data
+            .global_data
+            .minimizers_dump
+            .as_ref()
+            .filter(|dump| dump.is_sampled(read_index))
+            .map(|_| (stream_info.file_color as usize, read_index));
     }
 
     #[inline(always)]
@@ -131,12 +258,14 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
             &mut preprocess_info.color_info_buffer,
         );
         preprocess_info.color_info = MinimizerBucketingSeqColorDataType::<CX>::copy_extra_from(
-            extra_data.clone(),
+            extra_data.color.clone(),
             extra_data_buffer,
             &mut preprocess_info.color_info_buffer,
         );
         preprocess_info.include_first = (flags & READ_FLAG_INCL_BEGIN) != 0;
         preprocess_info.include_last = (flags & READ_FLAG_INCL_END) != 0;
+        preprocess_info.weight = extra_data.weight;
+        preprocess_info.dumped_read = None;
     }
 
     fn process_sequence<
@@ -178,14 +307,21 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
             if (H::get_full_minimizer(min_hash) != H::get_full_minimizer(last_hash))
                 && (preprocess_info.include_last || end_index != index)
             {
+                let bucket = H::get_bucket(used_bits, first_bits, last_hash);
+                if preprocess_info.dumped_read.is_some() {
+                    self.dumped_minimizers.push((H::get_u64(last_hash), bucket));
+                }
                 push_sequence(
-                    H::get_bucket(used_bits, first_bits, last_hash),
+                    bucket,
                     H::get_bucket(used_bits + first_bits, second_bits, last_hash),
                     sequence.get_subslice((max(1, last_index) - 1)..(index + self.global_data.k)),
                     include_first as u8,
-                    preprocess_info
-                        .color_info
-                        .get_subslice((max(1, last_index) - 1)..(index + 1)), // FIXME: Check if the subslice is correct
+                    WeightedSeqColorData {
+                        color: preprocess_info
+                            .color_info
+                            .get_subslice((max(1, last_index) - 1)..(index + 1)), // FIXME: Check if the subslice is correct
+                        weight: preprocess_info.weight,
+                    },
                     &preprocess_info.color_info_buffer,
                 );
                 last_index = index + 1;
@@ -196,16 +332,28 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
 
         let start_index = max(1, last_index) - 1;
         let include_last = preprocess_info.include_last; // Always include the last element of the sequence in the last entry
+        let bucket = H::get_bucket(used_bits, first_bits, last_hash);
         push_sequence(
-            H::get_bucket(used_bits, first_bits, last_hash),
+            bucket,
             H::get_bucket(used_bits + first_bits, second_bits, last_hash),
             sequence.get_subslice(start_index..sequence.seq_len()),
             include_first as u8 | ((include_last as u8) << 1),
-            preprocess_info
-                .color_info
-                .get_subslice(start_index..(sequence.seq_len() + 1 - self.global_data.k)), // FIXME: Check if the subslice is correct,
+            WeightedSeqColorData {
+                color: preprocess_info
+                    .color_info
+                    .get_subslice(start_index..(sequence.seq_len() + 1 - self.global_data.k)), // FIXME: Check if the subslice is correct,
+                weight: preprocess_info.weight,
+            },
             &preprocess_info.color_info_buffer,
         );
+
+        if let Some((input_index, read_index)) = preprocess_info.dumped_read {
+            self.dumped_minimizers.push((H::get_u64(last_hash), bucket));
+            if let Some(dump) = &self.global_data.global_data.minimizers_dump {
+                dump.write_read(input_index, read_index, &self.dumped_minimizers);
+            }
+            self.dumped_minimizers.clear();
+        }
     }
 }
 
@@ -223,6 +371,10 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
     threads_count: usize,
     k: usize,
     m: usize,
+    color_by_file: bool,
+    masked_intervals: Option<Arc<MaskedIntervals>>,
+    minimizers_dump: Option<Arc<MinimizersDump>>,
+    input_weights: Option<&[usize]>,
 ) -> (Vec<PathBuf>, PathBuf) {
     H::initialize(k);
 
@@ -238,6 +390,8 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
                 f,
                 InputFileInfo {
                     file_color: i as ColorIndexType,
+                    force_file_color: color_by_file,
+                    weight: input_weights.map_or(1, |weights| weights[i]),
                 },
             )
         })
@@ -246,6 +400,8 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
     input_files.sort_by_cached_key(|(file, _)| file.estimated_bases_count());
     input_files.reverse();
 
+    let global_data = AssemblerMinimizerBucketingGlobalData { minimizers_dump };
+
     GenericMinimizerBucketing::do_bucketing::<
         AssemblerMinimizerBucketingExecutorFactory<H, CX>,
         GeneralSequencesStream,
@@ -256,9 +412,197 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
         threads_count,
         k,
         m,
-        (),
+        global_data,
         Some(k - 1),
         false,
         k,
+        masked_intervals,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AssemblerMinimizerBucketingExecutorFactory, AssemblerMinimizerBucketingGlobalData,
+        InputFileInfo, MinimizersDump, WeightedSeqColorData,
+    };
+    use colors::non_colored::NonColoredManager;
+    use config::BucketIndexType;
+    use hashes::cn_nthash::CanonicalNtHashIteratorFactory;
+    use hashes::{
+        minimizer_order_key, ExtendableHashTraitType, HashFunction, MinimizerHashFunctionFactory,
+    };
+    use io::concurrent::temp_reads::extra_data::SequenceExtraDataConsecutiveCompression;
+    use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
+    use io::sequences_stream::SequenceInfo;
+    use minimizer_bucketing::{
+        MinimizerBucketingCommonData, MinimizerBucketingExecutor, MinimizerBucketingExecutorFactory,
+    };
+    use std::sync::Arc;
+    use utils::test_dir::TestDir;
+
+    type H = CanonicalNtHashIteratorFactory;
+    type Factory = AssemblerMinimizerBucketingExecutorFactory<H, NonColoredManager>;
+
+    const K: usize = 15;
+    const M: usize = 7;
+    const BUCKETS_BITS: usize = 8;
+
+    /// Buckets the reads, returning the buckets where their parts were pushed
+    fn bucket_reads(dump: &Arc<MinimizersDump>, reads: &[&[u8]]) -> Vec<BucketIndexType> {
+        let global_data = Arc::new(MinimizerBucketingCommonData::new(
+            K,
+            M,
+            1 << BUCKETS_BITS,
+            K - 1,
+            4,
+            AssemblerMinimizerBucketingGlobalData {
+                minimizers_dump: Some(dump.clone()),
+            },
+        ));
+        let mut executor = Factory::new(&global_data);
+        let mut preprocess_info = Default::default();
+        let mut pushed_buckets = vec![];
+
+        for (read_index, read) in reads.iter().enumerate() {
+            executor.preprocess_dna_sequence(
+                &InputFileInfo::default(),
+                SequenceInfo {
+                    color: None,
+                    pair_id: None,
+                },
+                read_index as u64,
+                &DnaSequence {
+                    ident_data: b"read",
+                    seq: read,
+                    format: DnaSequencesFileType::FASTA,
+                },
+                &mut preprocess_info,
+            );
+            executor.process_sequence(
+                &preprocess_info,
+                *read,
+                0..read.len(),
+                0,
+                BUCKETS_BITS,
+                2,
+                |bucket, _, _, _, _, _| pushed_buckets.push(bucket),
+            );
+        }
+        dump.flush().unwrap();
+        pushed_buckets
+    }
+
+    #[test]
+    fn dumped_minimizers_match_the_chosen_buckets() {
+        let test_dir = TestDir::new("minimizers-dump");
+        let path = test_dir.join("minimizers.tsv");
+        let dump = Arc::new(MinimizersDump::create(&path, 1).unwrap());
+
+        // A single kmer, its reverse complement and a longer read split in several parts
+        let kmer: &[u8] = b"ACGGTCATTGCAAGT";
+        let kmer_rc: &[u8] = b"ACTTGCAATGACCGT";
+        let read: &[u8] = b"TTGACCGATCGGATCCAAGTCTTGACGGCATGCAATCGTAGGCTTACAGTTAGC";
+        let pushed_buckets = bucket_reads(&dump, &[kmer, kmer_rc, read]);
+
+        let expected_minimizer = H::new(kmer, M)
+            .iter()
+            .map(|hash| hash.to_unextendable())
+            .min_by_key(|hash| minimizer_order_key::<H>(*hash))
+            .unwrap();
+        let expected_bucket = H::get_bucket(0, BUCKETS_BITS, expected_minimizer);
+
+        let dumped = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Vec<&str>> = dumped
+            .lines()
+            .map(|line| line.split('\t').collect())
+            .collect();
+
+        let expected_line = format!("0:0\t{}\t{}", expected_minimizer, expected_bucket);
+        assert_eq!(lines[0].join("\t"), expected_line);
+        assert_eq!(lines[1][1..], lines[0][1..]);
+        assert_eq!(lines[1][0], "0:1");
+
+        // Each dumped bucket is the one the part of the read was pushed to
+        assert_eq!(lines.len(), pushed_buckets.len());
+        assert!(lines.len() > 3);
+        for (line, bucket) in lines.iter().zip(pushed_buckets) {
+            assert_eq!(line[2], bucket.to_string());
+        }
+    }
+
+    #[test]
+    fn weighted_reads_are_pushed_once_with_their_weight() {
+        let global_data = Arc::new(MinimizerBucketingCommonData::new(
+            K,
+            M,
+            1 << BUCKETS_BITS,
+            K - 1,
+            4,
+            AssemblerMinimizerBucketingGlobalData::default(),
+        ));
+        let mut executor = Factory::new(&global_data);
+
+        let read: &[u8] = b"TTGACCGATCGGATCCAAGTCTTGACGGCATGCAATCGTAGGCTTACAGTTAGC";
+        let mut bucket_parts = |weight: usize| {
+            let mut preprocess_info = Default::default();
+            let mut pushed = vec![];
+            executor.preprocess_dna_sequence(
+                &InputFileInfo {
+                    weight,
+                    ..Default::default()
+                },
+                SequenceInfo {
+                    color: None,
+                    pair_id: None,
+                },
+                0,
+                &DnaSequence {
+                    ident_data: b"read",
+                    seq: read,
+                    format: DnaSequencesFileType::FASTA,
+                },
+                &mut preprocess_info,
+            );
+            executor.process_sequence(
+                &preprocess_info,
+                read,
+                0..read.len(),
+                0,
+                BUCKETS_BITS,
+                2,
+                |bucket, _, part: &[u8], _, extra: WeightedSeqColorData<_>, _| {
+                    pushed.push((bucket, part.to_vec(), extra.weight))
+                },
+            );
+            pushed
+        };
+
+        let unweighted = bucket_parts(1);
+        let weighted = bucket_parts(1000);
+
+        // The parts of the read are not duplicated, each one carries the weight of the file
+        assert_eq!(weighted.len(), unweighted.len());
+        for ((bucket, part, weight), (unweighted_bucket, unweighted_part, _)) in
+            weighted.iter().zip(unweighted.iter())
+        {
+            assert_eq!((bucket, part), (unweighted_bucket, unweighted_part));
+            assert_eq!(*weight, 1000);
+        }
+
+        // The weight is stored in the bucket record
+        let record = WeightedSeqColorData {
+            color: NonColoredManager::default(),
+            weight: 1000,
+        };
+        let mut encoded = vec![];
+        record.encode_extended(&(), &mut encoded, Default::default());
+        let decoded = WeightedSeqColorData::<NonColoredManager>::decode_from_slice_extended(
+            &mut (),
+            &encoded,
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(decoded.weight, 1000);
+    }
+}
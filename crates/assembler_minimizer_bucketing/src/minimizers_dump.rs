@@ -0,0 +1,48 @@
+use config::BucketIndexType;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Diagnostic dump of the minimizer and bucket chosen for each part of a sample of the reads,
+/// written as <input>:<read index> \t <minimizer hash> \t <bucket> lines by the bucketing itself
+pub struct MinimizersDump {
+    writer: Mutex<BufWriter<File>>,
+    sample_interval: u64,
+}
+
+impl MinimizersDump {
+    /// Dumps one every sample_interval reads of each input
+    pub fn create(path: impl AsRef<Path>, sample_interval: u64) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(File::create(path)?)),
+            sample_interval: sample_interval.max(1),
+        })
+    }
+
+    pub fn is_sampled(&self, read_index: u64) -> bool {
+        read_index % self.sample_interval == 0
+    }
+
+    /// Writes the (minimizer, bucket) of each part of a read, in the order of the read
+    pub fn write_read(
+        &self,
+        input_index: usize,
+        read_index: u64,
+        minimizers: &[(u64, BucketIndexType)],
+    ) {
+        let mut writer = self.writer.lock().unwrap();
+        for (minimizer, bucket) in minimizers {
+            writeln!(
+                writer,
+                "{}:{}\t{}\t{}",
+                input_index, read_index, minimizer, bucket
+            )
+            .unwrap();
+        }
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+}
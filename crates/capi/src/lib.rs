@@ -3,9 +3,13 @@ use std::sync::Arc;
 use std::{mem::transmute, path::PathBuf};
 
 use ggcat_api::{
-    ColorIndexType, DnaSequence, DnaSequencesFileType, DynamicSequencesStream, SequenceInfo,
+    AlignmentFileBlockData, ColorIndexType, DnaSequence, DnaSequencesFileType,
+    DynamicSequencesStream, SequenceInfo,
+};
+use ggcat_api::{
+    BuildOptions, ExtraElaboration, GGCATConfig, GGCATInstance, GeneralSequenceBlockData, LogLevel,
+    PacketsPoolsCapacities,
 };
-use ggcat_api::{ExtraElaboration, GGCATConfig, GGCATInstance, GeneralSequenceBlockData};
 
 #[repr(transparent)]
 struct GGCATInstanceFFI(GGCATInstance);
@@ -20,16 +24,35 @@ fn ggcat_create(config: ffi::GGCATConfigFFI) -> &'static GGCATInstanceFFI {
         memory: config.memory,
         prefer_memory: config.prefer_memory,
         total_threads_count: config.total_threads_count,
+        allow_oversubscribe: false,
         intermediate_compression_level: if config.intermediate_compression_level != u32::MAX {
             Some(config.intermediate_compression_level)
         } else {
             None
         },
+        output_compression_level: None,
+        bgzf_output: false,
+        output_flush_sequences: None,
+        output_flush_bytes: None,
         stats_file: if config.use_stats_file {
             Some(PathBuf::from(config.stats_file))
         } else {
             None
         },
+        minimizer_hash_seed: 0,
+        temp_files_prefix: None,
+        extra_temp_dirs: vec![],
+        split_on_softmask: false,
+        iupac_reads_packing: false,
+        interleaved_paired_reads: false,
+        use_second_bucket: false,
+        reads_buffer_capacity: None,
+        links_compaction_links_buffer: None,
+        links_compaction_results_buffer: None,
+        packets_pools_capacities: PacketsPoolsCapacities::default(),
+        sync_checkpoints: false,
+        checkpoint_checksums: false,
+        log_level: LogLevel::Default,
     });
     unsafe { std::mem::transmute(instance) }
 }
@@ -79,25 +102,32 @@ fn ggcat_build(
             } else {
                 None
             },
-            kmer_length,
-            threads_count,
-            forward_only,
-            if minimizer_length == usize::MAX {
-                None
-            } else {
-                Some(minimizer_length)
-            },
-            colors,
-            min_multiplicity,
-            match extra_elab {
-                EXTRA_ELABORATION_STEP_NONE => ExtraElaboration::None,
-                EXTRA_ELABORATION_STEP_UNITIG_LINKS => ExtraElaboration::UnitigLinks,
-                EXTRA_ELABORATION_STEP_GREEDY_MATCHTIGS => ExtraElaboration::GreedyMatchtigs,
-                EXTRA_ELABORATION_STEP_EULERTIGS => ExtraElaboration::Eulertigs,
-                EXTRA_ELABORATION_STEP_PATHTIGS => ExtraElaboration::Pathtigs,
-                _ => panic!("Invalid extra_elab value: {}", extra_elab),
+            BuildOptions {
+                kmer_length,
+                threads_count,
+                forward_only,
+                minimizer_length: if minimizer_length == usize::MAX {
+                    None
+                } else {
+                    Some(minimizer_length)
+                },
+                colors,
+                min_multiplicity,
+                extra_elab: match extra_elab {
+                    EXTRA_ELABORATION_STEP_NONE => ExtraElaboration::None,
+                    EXTRA_ELABORATION_STEP_UNITIG_LINKS => ExtraElaboration::UnitigLinks,
+                    EXTRA_ELABORATION_STEP_GREEDY_MATCHTIGS => ExtraElaboration::GreedyMatchtigs,
+                    EXTRA_ELABORATION_STEP_EULERTIGS => ExtraElaboration::Eulertigs,
+                    EXTRA_ELABORATION_STEP_PATHTIGS => ExtraElaboration::Pathtigs,
+                    _ => panic!("Invalid extra_elab value: {}", extra_elab),
+                },
+                ..Default::default()
             },
         )
+        // The C++ bindings have no error channel
+        .unwrap_or_else(|error| panic!("Cannot build the graph: {}", error))
+        .single_file()
+        .unwrap()
         .to_str()
         .unwrap()
         .to_string()
@@ -140,6 +170,8 @@ fn ggcat_build_from_files(
                 if f.ends_with(".gfa") {
                     todo!("GFA support is not implemented yet");
                     // GeneralSequenceBlockData::GFA() // PathBuf::from(f))
+                } else if f.ends_with(".bam") || f.ends_with(".cram") {
+                    GeneralSequenceBlockData::Alignment(AlignmentFileBlockData::new(PathBuf::from(f)))
                 } else {
                     GeneralSequenceBlockData::FASTA(PathBuf::from(f))
                 }
@@ -235,6 +267,7 @@ fn ggcat_build_from_streams(
                     },
                     SequenceInfo {
                         color: Some(info.color),
+                        pair_id: None,
                     },
                 );
             }
@@ -336,7 +369,13 @@ fn ggcat_query_graph(
                 }
                 _ => panic!("Invalid color_output_format value: {}", color_output_format),
             },
+            false,
+            None,
+            false,
+            None,
         )
+        // The C++ bindings have no error channel
+        .unwrap_or_else(|error| panic!("Cannot query the graph: {}", error))
         .to_str()
         .unwrap()
         .to_string()
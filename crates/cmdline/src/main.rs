@@ -8,7 +8,10 @@ extern crate test;
 mod benchmarks;
 
 use backtrace::Backtrace;
-use ggcat_api::{ExtraElaboration, GGCATConfig, GGCATInstance};
+use ggcat_api::{
+    BuildOptions, ExtraElaboration, GGCATConfig, GGCATInstance, LogLevel, PacketsPoolsCapacities,
+    ShardedOutput,
+};
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Write};
 use std::panic;
@@ -57,7 +60,9 @@ use colors::colors_manager::ColorMapReader;
 use colors::storage::deserializer::ColorsDeserializer;
 use colors::DefaultColorsSerializer;
 use config::ColorIndexType;
+use io::sequences_stream::alignment::{AlignmentFileBlockData, AlignmentRecordsFilter};
 use io::sequences_stream::general::GeneralSequenceBlockData;
+use io::sequences_stream::tar_archive::TarMemberBlockData;
 use parallel_processor::memory_fs::MemoryFs;
 use std::io::BufRead;
 use structopt::clap::{arg_enum, ArgGroup};
@@ -68,6 +73,17 @@ enum CliArgs {
     Query(QueryArgs),
     DumpColors(DumpColorsArgs),
     Matches(MatchesArgs),
+    Validate(ValidateArgs),
+    ValidateInput(ValidateInputArgs),
+    Compare(CompareArgs),
+    /// Fraction of the kmers of each graph contained in the other one
+    Containment(CompareArgs),
+    Union(UnionArgs),
+    Filter(FilterArgs),
+    /// Extract in GFA format the unitigs matching a query, with their neighborhood
+    Extract(ExtractArgs),
+    #[structopt(name = "selftest")]
+    SelfTest(SelfTestArgs),
     // Utils(CmdUtilsArgs),
 }
 
@@ -90,9 +106,20 @@ struct CommonArgs {
     #[structopt(long = "minimizer-length")]
     pub minimizer_length: Option<usize>,
 
-    /// Directory for temporary files (default .temp_files)
-    #[structopt(short = "t", long = "temp-dir", default_value = ".temp_files")]
-    pub temp_dir: PathBuf,
+    /// Directory for temporary files (default .temp_files).
+    /// Multiple comma separated directories can be specified to spread the temporary buckets across them
+    #[structopt(
+        short = "t",
+        long = "temp-dir",
+        default_value = ".temp_files",
+        use_delimiter = true
+    )]
+    pub temp_dir: Vec<PathBuf>,
+
+    /// Run id prepended to the temporary files names, so that concurrent runs can share a temporary directory.
+    /// A random one is used by default, resuming a run with --checkpoint-compaction requires the same run id
+    #[structopt(long = "temp-files-prefix")]
+    pub temp_files_prefix: Option<String>,
 
     /// Keep intermediate temporary files for debugging purposes
     #[structopt(long = "keep-temp-files")]
@@ -101,6 +128,11 @@ struct CommonArgs {
     #[structopt(short = "j", long, default_value = "16")]
     pub threads_count: usize,
 
+    /// Use the given threads count even if it exceeds the available cores,
+    /// instead of clamping it to the cores count
+    #[structopt(long = "allow-oversubscribe")]
+    pub allow_oversubscribe: bool,
+
     /// Hash type used to identify kmers
     #[structopt(short = "w", long, default_value = "Auto")]
     pub hash_type: HashType,
@@ -128,8 +160,103 @@ struct CommonArgs {
     #[structopt(long = "intermediate-compression-level")]
     pub intermediate_compression_level: Option<u32>,
 
+    /// The level of gzip or lz4 compression of the output, used when its name ends with .gz or .lz4
+    #[structopt(long = "output-compression-level")]
+    pub output_compression_level: Option<u32>,
+
+    /// Write a .gz output as BGZF, with .gzi and .fai indexes to fetch single unitigs (samtools faidx)
+    #[structopt(long = "bgzf-output")]
+    pub bgzf_output: bool,
+
+    /// Flush the output every this number of unitigs, so that the partial output
+    /// can be read while the assembly is still running
+    #[structopt(long = "output-flush-sequences")]
+    pub output_flush_sequences: Option<u64>,
+
+    /// Flush the output every this number of unitigs bases
+    #[structopt(long = "output-flush-bytes")]
+    pub output_flush_bytes: Option<u64>,
+
+    /// Seed used to randomize the minimizers hashes, useful to avoid skewed buckets on pathological inputs.
+    /// The same seed gives reproducible results, 0 uses the default hash values
+    #[structopt(long = "seed", default_value = "0")]
+    pub seed: u64,
+
+    /// Treat the soft-masked (lowercase) bases of the inputs as N, splitting the reads at the masked regions.
+    /// By default they are read as the corresponding uppercase bases
+    #[structopt(long = "split-on-softmask")]
+    pub split_on_softmask: bool,
+
+    /// Pack the reads of the reference with 4 bits per base, keeping the IUPAC ambiguity codes
+    /// instead of splitting the reads at them. Only the kmers without ambiguous bases are used,
+    /// the reads take twice the memory
+    #[structopt(long = "iupac-packing")]
+    pub iupac_packing: bool,
+
+    /// Read the FASTQ inputs as interleaved paired-end reads (R1, R2, R1, R2, ...). Each two consecutive
+    /// records must be mates, named <name>/1 and <name>/2 or <name> 1... and <name> 2...
+    #[structopt(long = "interleaved")]
+    pub interleaved: bool,
+
+    /// Store the second level bucket of each read in the temporary buckets. It avoids recomputing the minimizers
+    /// when the large buckets are split, but makes the temporary files bigger (one extra byte per read)
+    #[structopt(long = "use-second-bucket")]
+    pub use_second_bucket: bool,
+
+    /// Number of reads in each packet sent to the k-mers processors. Smaller values reduce the memory
+    /// usage, larger values can improve the throughput. By default it is chosen depending on k
+    #[structopt(long = "reads-buffer-capacity")]
+    pub reads_buffer_capacity: Option<usize>,
+
+    /// Bytes buffered by each thread for each bucket of the links in the links compaction (default 4096).
+    /// Smaller buffers reduce the memory usage with many buckets and threads, but are flushed more often
+    #[structopt(long = "links-compaction-links-buffer")]
+    pub links_compaction_links_buffer: Option<usize>,
+
+    /// Bytes buffered by each thread for each bucket of the unitigs mappings in the links compaction (default 4096)
+    #[structopt(long = "links-compaction-results-buffer")]
+    pub links_compaction_results_buffer: Option<usize>,
+
+    /// Number of reads chunks buffered by the input readers. If the readers are often waiting
+    /// for free buffers, increase it. By default 2 for each thread
+    #[structopt(long = "bucketing-reader-pool-capacity")]
+    pub bucketing_reader_pool_capacity: Option<usize>,
+
+    /// Number of reads packets of each bucket reader in the k-mers processing phase.
+    /// By default it depends on the number of buckets processed at the same time
+    #[structopt(long = "transform-reader-pool-capacity")]
+    pub transform_reader_pool_capacity: Option<usize>,
+
+    /// Number of k-mers hashmaps shared by the processors in the k-mers processing phase,
+    /// each one is as large as a bucket. By default one for each thread (at least 16) plus two
+    #[structopt(long = "transform-processor-pool-capacity")]
+    pub transform_processor_pool_capacity: Option<usize>,
+
+    /// Sync the checkpointed temporary files to disk before recording each checkpoint (see --checkpoint-compaction),
+    /// so that the completed steps are not lost if the machine crashes. Slows down the checkpoints
+    #[structopt(long = "sync-checkpoints")]
+    pub sync_checkpoints: bool,
+
+    /// Store a checksum of each checkpointed temporary file (see --checkpoint-compaction), verified when
+    /// resuming to reject the files corrupted on disk. Slows down the checkpoints
+    #[structopt(long = "checkpoint-checksums")]
+    pub checkpoint_checksums: bool,
+
+    /// Print only the warnings and the final results, hiding the progress of the buckets processing
+    #[structopt(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print also the detailed statistics of the buckets
+    #[structopt(short, long)]
+    pub verbose: bool,
+
     #[structopt(long = "only-bstats", hidden = true)]
     pub only_bstats: bool,
+
+    /// Log the ending kmers that are not linked as a pair of opposite ends (single ends,
+    /// same direction pairs and groups of more than two ends), with counts by reason
+    #[structopt(long = "debug-links-diagnostics", hidden = true)]
+    pub debug_links_diagnostics: bool,
 }
 
 #[derive(StructOpt, Debug)]
@@ -138,7 +265,10 @@ struct AssemblerArgs {
     /// The input files
     pub input: Vec<PathBuf>,
 
-    /// The lists of input files
+    /// The lists of input files, one per line. A file can be followed by a tab and an integer weight,
+    /// the number of times each of its kmers is counted towards the minimum multiplicity
+    /// (e.g. to trust a reference more than the reads), up to 65536. The default weight 1 counts each
+    /// kmer once
     #[structopt(short = "l", long = "input-lists")]
     pub input_lists: Vec<PathBuf>,
 
@@ -146,6 +276,56 @@ struct AssemblerArgs {
     #[structopt(short, long)]
     pub colors: bool,
 
+    /// Write each unitig in canonical orientation (the lexicographically smaller between the unitig
+    /// and its reverse complement), flipping the links orientations accordingly.
+    /// With --forward-only the flipped unitigs are tagged with ST:A:-, the other ones with ST:A:+
+    #[structopt(long = "canonical-output")]
+    pub canonical_output: bool,
+
+    /// Stop with an error if the links compaction has not converged after this number of iterations,
+    /// to bound the runtime on pathological graphs
+    #[structopt(long = "max-compaction-iterations")]
+    pub max_compaction_iterations: Option<usize>,
+
+    /// Dump the unitigs completed in each links compaction round to <output>.unitigs_round_<n>.tsv,
+    /// listing the partial unitigs joined in each of them
+    #[structopt(long = "dump-compaction-rounds")]
+    pub dump_compaction_rounds: bool,
+
+    /// Write the minimizer hash and the bucket chosen for each part of a sample of the reads to this
+    /// file, as <input index>:<read index> <tab> <minimizer hash> <tab> <bucket> lines
+    #[structopt(long = "dump-minimizers")]
+    pub dump_minimizers: Option<PathBuf>,
+
+    /// Dump the minimizers of one every this number of reads of each input (see --dump-minimizers)
+    #[structopt(long = "dump-minimizers-sample", default_value = "1000")]
+    pub dump_minimizers_sample: u64,
+
+    /// Checkpoint each links compaction round in the temporary directory, so that an interrupted
+    /// compaction can be resumed after the last completed round by running again with the same
+    /// output file and --step LinksCompaction -n <round>
+    #[structopt(long = "checkpoint-compaction")]
+    pub checkpoint_compaction: bool,
+
+    /// BED file of input sequences intervals (sequence name, 0-based start, end) to be replaced with N
+    /// before building the graph, splitting the reads at the masked regions
+    #[structopt(long = "mask-bed")]
+    pub mask_bed: Option<PathBuf>,
+
+    /// FASTA/FASTQ reference (possibly compressed) whose kmers are kept with the lower
+    /// --reference-min-multiplicity, to recover the low coverage regions supported by the reference.
+    /// The reference kmers are matched with a Bloom filter, so a few other kmers can be kept too
+    #[structopt(long = "reference")]
+    pub reference: Option<PathBuf>,
+
+    /// Minimum multiplicity required to keep a kmer of the reference
+    #[structopt(long = "reference-min-multiplicity", default_value = "1")]
+    pub reference_min_multiplicity: usize,
+
+    /// Enable colors, assigning a color to each input file named after the file without its extension
+    #[structopt(long = "color-by-file")]
+    pub color_by_file: bool,
+
     /// Minimum multiplicity required to keep a kmer
     #[structopt(short = "s", long = "min-multiplicity", default_value = "2")]
     pub min_multiplicity: usize,
@@ -185,6 +365,95 @@ struct AssemblerArgs {
     #[structopt(long = "pathtigs", group = "output-mode")]
     pub pathtigs: bool,
 
+    /// Write the maximal unitigs and their connections as a SPAdes-style FASTG graph,
+    /// with the edges EDGE_<id> and their reverse complements EDGE_<id>'
+    #[structopt(long = "fastg", group = "output-mode")]
+    pub fastg: bool,
+
+    /// Write the uncompacted de Bruijn graph in GFA format, with a segment for each kmer and
+    /// a link for each k-1 overlap, instead of the maximal unitigs. The output is not compressed
+    /// and is much larger than the unitigs, all the kmers are kept in memory while writing it
+    #[structopt(long = "kmers-graph", group = "output-mode")]
+    pub kmers_graph: bool,
+
+    /// Keep the secondary alignments when reading BAM/CRAM inputs
+    #[structopt(long = "keep-secondary-alignments")]
+    pub keep_secondary_alignments: bool,
+
+    /// Keep the supplementary alignments when reading BAM/CRAM inputs
+    #[structopt(long = "keep-supplementary-alignments")]
+    pub keep_supplementary_alignments: bool,
+
+    /// Keep the reads marked as duplicates when reading BAM/CRAM inputs
+    #[structopt(long = "keep-duplicate-reads")]
+    pub keep_duplicate_reads: bool,
+
+    /// Reference file (indexed fasta) used to decode CRAM inputs
+    #[structopt(long = "cram-reference")]
+    pub cram_reference: Option<PathBuf>,
+
+    /// Split the unitigs longer than this value in pieces overlapping by k - 1 bases,
+    /// linked to each other with -e. Must be at least k, not supported with colors
+    #[structopt(long = "max-unitig-length")]
+    pub max_unitig_length: Option<usize>,
+
+    /// Debug option to write, for each unitig, the ordered partial unitigs composing it (<output>.paths.tsv)
+    #[structopt(long = "output-unitigs-paths")]
+    pub output_unitigs_paths: bool,
+
+    /// Saturate the kmers counts at this value to bound the counters on highly repetitive regions.
+    /// The kmers are still kept, only their tracked count is capped
+    #[structopt(long = "max-kmer-count")]
+    pub max_kmer_count: Option<usize>,
+
+    /// Split the output in this number of shards (<name>.<shard>.<extensions>),
+    /// each one a valid standalone graph, listed in the <name>.shards.txt index
+    #[structopt(long = "output-shards", default_value = "1")]
+    pub output_shards: usize,
+
+    /// When building a colored graph, also write the unitigs of each color to a separate
+    /// <output name>.<color name>.fa file in the output directory, while the graph is written.
+    /// A unitig shared by multiple colors is written to each of their files
+    #[structopt(long = "split-output-by-color")]
+    pub split_output_by_color: bool,
+
+    /// After building a colored graph, also write its colors dictionary to <output name>.colors.tsv,
+    /// listing the name of each color and the colors of each colors subset found in the graph
+    #[structopt(long = "colors-dictionary")]
+    pub colors_dictionary: bool,
+
+    /// Order of the unitigs in the output file: none keeps the order in which they are built,
+    /// length sorts them from the longest to the shortest. The sorting does not need to keep
+    /// the whole graph in memory
+    #[structopt(long = "sort-output", default_value = "None")]
+    pub sort_output: OutputSorting,
+
+    /// Drop the unitigs repeating the sequence of a previous unitig (in any orientation), merging
+    /// their colors and links into the kept one. A safeguard against duplicates in the output
+    #[structopt(long = "dedup-output")]
+    pub dedup_output: bool,
+
+    /// Keep only the links between unitigs sharing at least one color, so that each color forms
+    /// a separate subgraph. Applied to the output, the unitigs are not changed
+    #[structopt(long = "intra-color-links-only")]
+    pub intra_color_links_only: bool,
+
+    /// Add to the header of each unitig the names of the input records sharing a kmer with it,
+    /// as SI:Z:<name>[,<name>]*. The inputs are read again after building the graph
+    #[structopt(long = "source-ids")]
+    pub source_ids: bool,
+
+    /// Maximum number of source records names written for each unitig, the ones of the first
+    /// records are kept and followed by ... if some were dropped
+    #[structopt(long = "max-source-ids", default_value = "16")]
+    pub max_source_ids: usize,
+
+    /// Add to the header of each unitig the strand, relative to its sequence, of most of the
+    /// input kmers matching it, as RS:A:+ or RS:A:-. Useful with canonical graphs, where the
+    /// strand of the reads is lost. The inputs are read again after building the graph
+    #[structopt(long = "source-strands")]
+    pub source_strands: bool,
+
     #[structopt(flatten)]
     pub common_args: CommonArgs,
 }
@@ -195,6 +464,113 @@ struct DumpColorsArgs {
     output_file: PathBuf,
 }
 
+#[derive(StructOpt, Debug)]
+struct ValidateArgs {
+    /// The input graph, with the colormap file (if any) in the same folder
+    input_graph: PathBuf,
+
+    /// Specifies the k-mers length
+    #[structopt(short, long = "kmer-length")]
+    pub kmer_length: usize,
+}
+
+#[derive(StructOpt, Debug)]
+struct ValidateInputArgs {
+    /// The FASTA/FASTQ input files to check
+    input: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct CompareArgs {
+    /// The first graph
+    graph_a: PathBuf,
+
+    /// The second graph
+    graph_b: PathBuf,
+
+    /// Specifies the k-mers length
+    #[structopt(short, long = "kmer-length")]
+    pub kmer_length: usize,
+
+    /// Maximum suggested memory usage (GB) for the kmers sets
+    #[structopt(short = "m", long, default_value = "2")]
+    pub memory: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct UnionArgs {
+    /// The graphs to be merged, with their colormap files (if any) in the same folder.
+    /// The colors with the same name are merged, an uncolored graph is given a single color named after its file
+    #[structopt(required = true, min_values = 2)]
+    graphs: Vec<PathBuf>,
+
+    #[structopt(short = "o", long = "output-file", default_value = "output.fasta.lz4")]
+    pub output_file: PathBuf,
+
+    #[structopt(flatten)]
+    pub common_args: CommonArgs,
+}
+
+#[derive(StructOpt, Debug)]
+struct FilterArgs {
+    /// The input colored graph, with its colormap file in the same folder
+    input_graph: PathBuf,
+
+    /// Write only the unitigs that contain this color (by name) in at least one of their kmers
+    #[structopt(long = "filter-color")]
+    pub filter_color: String,
+
+    /// Keep the links between the written unitigs
+    #[structopt(long = "keep-links")]
+    pub keep_links: bool,
+
+    #[structopt(short = "o", long = "output-file", default_value = "output.fasta")]
+    pub output_file: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+struct ExtractArgs {
+    /// The input graph, built with links (-e) to extract the neighborhood of the matching unitigs
+    input_graph: PathBuf,
+
+    /// The FASTA/FASTQ query sequences, the unitigs sharing at least one kmer with them are extracted
+    input_query: PathBuf,
+
+    /// Specifies the k-mers length
+    #[structopt(short, long = "kmer-length")]
+    pub kmer_length: usize,
+
+    /// Treats reverse complementary kmers as different
+    #[structopt(short = "f", long)]
+    pub forward_only: bool,
+
+    /// Also extract the unitigs reachable from the matching ones within this number of links
+    #[structopt(long = "hops", default_value = "1")]
+    pub hops: usize,
+
+    #[structopt(short = "o", long = "output-file", default_value = "subgraph.gfa")]
+    pub output_file: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+struct SelfTestArgs {
+    /// Length of each synthetic genome
+    #[structopt(long = "genome-length", default_value = "1000000")]
+    pub genome_length: usize,
+
+    #[structopt(flatten)]
+    pub common_args: CommonArgs,
+}
+
+arg_enum! {
+    /// Order of the unitigs in the output
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum OutputSorting {
+        None,
+        Length,
+    }
+}
+
 arg_enum! {
     /// Format of the queries output
     #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -225,6 +601,27 @@ struct QueryArgs {
     #[structopt(short = "x", long, default_value = "MinimizerBucketing")]
     pub step: QuerierStartingStep,
 
+    /// Also match the reverse complement of each query against a forward only graph,
+    /// reporting the orientation of the matches. Has no effect on canonical graphs
+    #[structopt(long = "query-both-strands")]
+    pub query_both_strands: bool,
+
+    /// Expands the query kmers containing degenerate IUPAC bases (as N or R) into all their
+    /// concrete kmers, matching if any of them is found. Kmers with more instantiations
+    /// than this value are skipped
+    #[structopt(long = "max-degenerate-expansions")]
+    pub max_degenerate_expansions: Option<usize>,
+
+    /// Append the results to the existing output file instead of replacing it, flushing them
+    /// when the query completes. Useful to collect the results of multiple query batches in one file
+    #[structopt(long = "append-output")]
+    pub append_output: bool,
+
+    /// Report for each query only the colors found in at least this number of its matched kmers,
+    /// instead of all the colors of any matched kmer
+    #[structopt(long = "color-quorum")]
+    pub color_quorum: Option<usize>,
+
     #[structopt(flatten)]
     pub common_args: CommonArgs,
 }
@@ -238,17 +635,48 @@ struct QueryArgs {
 
 fn initialize(args: &CommonArgs, out_file: &PathBuf) -> &'static GGCATInstance {
     let instance = GGCATInstance::create(GGCATConfig {
-        temp_dir: Some(args.temp_dir.clone()),
+        temp_dir: args.temp_dir.first().cloned(),
         memory: args.memory,
         prefer_memory: args.prefer_memory,
         total_threads_count: args.threads_count,
+        allow_oversubscribe: args.allow_oversubscribe,
         intermediate_compression_level: args.intermediate_compression_level,
+        output_compression_level: args.output_compression_level,
+        bgzf_output: args.bgzf_output,
+        output_flush_sequences: args.output_flush_sequences,
+        output_flush_bytes: args.output_flush_bytes,
         stats_file: Some(out_file.with_extension("stats.log")),
+        minimizer_hash_seed: args.seed,
+        temp_files_prefix: args.temp_files_prefix.clone(),
+        extra_temp_dirs: args.temp_dir.iter().skip(1).cloned().collect(),
+        split_on_softmask: args.split_on_softmask,
+        iupac_reads_packing: args.iupac_packing,
+        interleaved_paired_reads: args.interleaved,
+        use_second_bucket: args.use_second_bucket,
+        reads_buffer_capacity: args.reads_buffer_capacity,
+        links_compaction_links_buffer: args.links_compaction_links_buffer,
+        links_compaction_results_buffer: args.links_compaction_results_buffer,
+        packets_pools_capacities: PacketsPoolsCapacities {
+            minimizer_bucketing_reader: args.bucketing_reader_pool_capacity,
+            kmers_transform_reader: args.transform_reader_pool_capacity,
+            kmers_transform_processor: args.transform_processor_pool_capacity,
+        },
+        sync_checkpoints: args.sync_checkpoints,
+        checkpoint_checksums: args.checkpoint_checksums,
+        log_level: if args.quiet {
+            LogLevel::Quiet
+        } else if args.verbose {
+            LogLevel::Verbose
+        } else {
+            LogLevel::Default
+        },
     });
 
     ggcat_api::debug::DEBUG_KEEP_FILES.store(args.keep_temp_files, Ordering::Relaxed);
     *ggcat_api::debug::BUCKETS_COUNT_LOG_FORCE.lock() = args.buckets_count_log;
     ggcat_api::debug::DEBUG_ONLY_BSTATS.store(args.only_bstats, Ordering::Relaxed);
+    ggcat_api::debug::DEBUG_LINKS_DIAGNOSTICS
+        .store(args.debug_links_diagnostics, Ordering::Relaxed);
     *ggcat_api::debug::DEBUG_HASH_TYPE.lock() = match args.hash_type {
         HashType::Auto => ggcat_api::HashType::Auto,
         HashType::SeqHash => ggcat_api::HashType::SeqHash,
@@ -257,7 +685,7 @@ fn initialize(args: &CommonArgs, out_file: &PathBuf) -> &'static GGCATInstance {
         HashType::RabinKarp128 => ggcat_api::HashType::RabinKarp128,
     };
 
-    println!(
+    log::info!(
         "Using m: {} with k: {}",
         args.minimizer_length
             .unwrap_or(compute_best_m(args.kmer_length)),
@@ -287,11 +715,27 @@ fn convert_assembler_step(step: AssemblerStartingStep) -> assembler::AssemblerSt
 
 fn run_assembler_from_args(instance: &GGCATInstance, args: AssemblerArgs) {
     let mut inputs = args.input.clone();
+    let mut input_weights = vec![1; inputs.len()];
 
     for list in args.input_lists {
         for input in BufReader::new(File::open(list).unwrap()).lines() {
             if let Ok(input) = input {
+                // An optional second column is the weight of the file
+                let (input, weight) = match input.split_once('\t') {
+                    Some((input, weight)) => match weight.trim().parse::<usize>() {
+                        Ok(weight) if weight > 0 => (input.to_string(), weight),
+                        _ => {
+                            println!(
+                                "ERROR: Invalid weight '{}' of the input {}, it must be a positive integer!",
+                                weight, input
+                            );
+                            exit(1);
+                        }
+                    },
+                    None => (input, 1),
+                };
                 inputs.push(PathBuf::from(input));
+                input_weights.push(weight);
             }
         }
     }
@@ -301,44 +745,310 @@ fn run_assembler_from_args(instance: &GGCATInstance, args: AssemblerArgs) {
         exit(1);
     }
 
-    let color_names: Vec<_> = inputs
-        .iter()
-        .map(|f| f.file_name().unwrap().to_string_lossy().to_string())
-        .collect();
+    if args.split_output_by_color && !args.colors {
+        println!("ERROR: The output can be split by color only when building a colored graph!");
+        exit(1);
+    }
+
+    if args.colors_dictionary && !args.colors {
+        println!("ERROR: The colors dictionary can be written only when building a colored graph!");
+        exit(1);
+    }
+
+    // The unitigs are split by color while they are written, before they are renumbered or removed
+    if args.split_output_by_color && (args.sort_output != OutputSorting::None || args.dedup_output)
+    {
+        println!("ERROR: The output cannot be split by color when it is sorted or deduplicated!");
+        exit(1);
+    }
 
-    let inputs = inputs
+    if args.sort_output != OutputSorting::None && (args.output_shards > 1 || args.fastg) {
+        println!("ERROR: The output can be sorted only when written in a single FASTA file!");
+        exit(1);
+    }
+
+    if args.dedup_output && (args.output_shards > 1 || args.fastg) {
+        println!("ERROR: The output can be deduplicated only when written in a single FASTA file!");
+        exit(1);
+    }
+
+    if args.kmers_graph
+        && (args.colors
+            || args.output_shards > 1
+            || args.sort_output != OutputSorting::None
+            || args.dedup_output
+            || args.source_ids
+            || args.source_strands
+            || args.split_output_by_color)
+    {
+        println!("ERROR: The kmers graph can be written only for an uncolored graph in a single file, without other output processing!");
+        exit(1);
+    }
+
+    if args.intra_color_links_only
+        && (!args.colors || !args.generate_maximal_unitigs_links || args.output_shards > 1)
+    {
+        println!("ERROR: The links can be filtered by color only when building a colored graph with links in a single FASTA file!");
+        exit(1);
+    }
+
+    if (args.source_ids || args.source_strands)
+        && (args.output_shards > 1
+            || args.fastg
+            || inputs.iter().any(|input| {
+                TarMemberBlockData::is_tar_archive(input)
+                    || AlignmentFileBlockData::is_alignment_file(input)
+            }))
+    {
+        println!("ERROR: The source ids and strands can be added only with FASTA/FASTQ inputs and a single FASTA output!");
+        exit(1);
+    }
+
+    if let Some(max_unitig_length) = args.max_unitig_length {
+        if args.colors || args.color_by_file {
+            println!(
+                "ERROR: The maximum unitig length is not supported when building a colored graph!"
+            );
+            exit(1);
+        }
+        if max_unitig_length < args.common_args.kmer_length {
+            println!(
+                "ERROR: The maximum unitig length ({}) must be at least k ({})!",
+                max_unitig_length, args.common_args.kmer_length
+            );
+            exit(1);
+        }
+    }
+
+    if let Some(reference) = &args.reference {
+        if !reference.exists() {
+            println!("ERROR: Reference file {} not found!", reference.display());
+            exit(1);
+        }
+        if args.reference_min_multiplicity >= args.min_multiplicity {
+            println!("Warning: the reference minimum multiplicity is not lower than the minimum multiplicity, the reference has no effect");
+        }
+    }
+
+    // The inputs are read again to find the source records of the unitigs
+    let source_inputs = if args.source_ids || args.source_strands {
+        inputs.clone()
+    } else {
+        vec![]
+    };
+
+    let alignments_filter = AlignmentRecordsFilter {
+        skip_secondary: !args.keep_secondary_alignments,
+        skip_supplementary: !args.keep_supplementary_alignments,
+        skip_duplicates: !args.keep_duplicate_reads,
+    };
+
+    // Each sequences file inside a tar archive is a separate input, named after the file
+    // and weighted as the archive
+    let (color_names, inputs): (Vec<_>, Vec<_>) = inputs
         .into_iter()
-        .map(|x| GeneralSequenceBlockData::FASTA(x))
-        .collect();
+        .zip(input_weights)
+        .flat_map(|(x, weight)| {
+            if TarMemberBlockData::is_tar_archive(&x) {
+                TarMemberBlockData::list_archive_members(&x, &args.common_args.temp_dir[0])
+                    .into_iter()
+                    .map(|member| {
+                        (
+                            member
+                                .member
+                                .file_name()
+                                .unwrap()
+                                .to_string_lossy()
+                                .to_string(),
+                            (GeneralSequenceBlockData::Tar(member), weight),
+                        )
+                    })
+                    .collect()
+            } else {
+                let color_name = x.file_name().unwrap().to_string_lossy().to_string();
+                vec![(
+                    color_name,
+                    (
+                        if AlignmentFileBlockData::is_alignment_file(&x) {
+                            GeneralSequenceBlockData::Alignment(AlignmentFileBlockData {
+                                path: x,
+                                reference: args.cram_reference.clone(),
+                                filter: alignments_filter,
+                            })
+                        } else {
+                            GeneralSequenceBlockData::FASTA(x)
+                        },
+                        weight,
+                    ),
+                )]
+            }
+        })
+        .unzip();
+    let (inputs, input_weights): (Vec<_>, Vec<_>) = inputs.into_iter().unzip();
 
     *ggcat_api::debug::DEBUG_ASSEMBLER_FIRST_STEP.lock() = convert_assembler_step(args.step);
     *ggcat_api::debug::DEBUG_ASSEMBLER_LAST_STEP.lock() = convert_assembler_step(args.last_step);
     ggcat_api::debug::DEBUG_LINK_PHASE_ITERATION_START_STEP.store(args.number, Ordering::Relaxed);
+    *ggcat_api::debug::DEBUG_MINIMIZERS_DUMP.write().unwrap() = args
+        .dump_minimizers
+        .clone()
+        .map(|path| (path, args.dump_minimizers_sample));
+
+    // The unitigs are expanded to the kmers graph once built
+    let kmers_graph_file = args.output_file.clone();
+    // The colormap is shared by all the shards
+    let sharded_colormap_file = GGCATInstance::get_colormap_file(&args.output_file);
+    let output = instance
+        .build_graph(
+            inputs,
+            if args.kmers_graph {
+                args.output_file.with_extension("unitigs.fa")
+            } else {
+                args.output_file
+            },
+            Some(&color_names),
+            BuildOptions {
+                kmer_length: args.common_args.kmer_length,
+                threads_count: args.common_args.threads_count,
+                forward_only: args.common_args.forward_only,
+                minimizer_length: args.common_args.minimizer_length,
+                colors: args.colors,
+                min_multiplicity: args.min_multiplicity,
+                extra_elab: if args.generate_maximal_unitigs_links {
+                    ExtraElaboration::UnitigLinks
+                } else if args.greedy_matchtigs {
+                    ExtraElaboration::GreedyMatchtigs
+                } else if args.eulertigs {
+                    ExtraElaboration::Eulertigs
+                } else if args.pathtigs {
+                    ExtraElaboration::Pathtigs
+                } else if args.fastg {
+                    ExtraElaboration::FastgLinks
+                } else {
+                    ExtraElaboration::None
+                },
+                max_unitig_length: args.max_unitig_length,
+                output_unitigs_paths: args.output_unitigs_paths,
+                max_kmer_count: args.max_kmer_count,
+                output_shards: args.output_shards,
+                color_by_file: args.color_by_file,
+                canonical_output: args.canonical_output,
+                max_compaction_iterations: args.max_compaction_iterations,
+                dump_compaction_rounds: args.dump_compaction_rounds,
+                checkpoint_compaction: args.checkpoint_compaction,
+                masking_bed: args.mask_bed,
+                reference: args.reference,
+                reference_min_multiplicity: args.reference_min_multiplicity,
+                input_weights: Some(input_weights),
+                split_output_by_color: args.split_output_by_color,
+            },
+        )
+        .unwrap_or_else(|error| {
+            println!("ERROR: {}", error);
+            exit(1);
+        });
+
+    let output_file = match output {
+        ShardedOutput::Single(output_file) => output_file,
+        // The output processing is not supported with multiple shards
+        ShardedOutput::Shards(shards) => {
+            println!("Final output saved to {} shards:", shards.len());
+            for shard in shards {
+                println!("{}", shard.display());
+            }
 
-    let output_file = instance.build_graph(
-        inputs,
-        args.output_file,
-        Some(&color_names),
-        args.common_args.kmer_length,
-        args.common_args.threads_count,
-        args.common_args.forward_only,
-        args.common_args.minimizer_length,
-        args.colors,
-        args.min_multiplicity,
-        if args.generate_maximal_unitigs_links {
-            ExtraElaboration::UnitigLinks
-        } else if args.greedy_matchtigs {
-            ExtraElaboration::GreedyMatchtigs
-        } else if args.eulertigs {
-            ExtraElaboration::Eulertigs
-        } else if args.pathtigs {
-            ExtraElaboration::Pathtigs
-        } else {
-            ExtraElaboration::None
-        },
-    );
+            if args.colors_dictionary {
+                let dictionary_file =
+                    GGCATInstance::export_colors_dictionary(sharded_colormap_file);
+                println!("Colors dictionary saved to: {}", dictionary_file.display());
+            }
+            return;
+        }
+    };
+
+    let output_file = if args.dedup_output {
+        instance
+            .dedup_graph_output(output_file)
+            .unwrap_or_else(|error| {
+                println!("ERROR: {}", error);
+                exit(1);
+            })
+            .0
+    } else {
+        output_file
+    };
+
+    let output_file = if args.intra_color_links_only {
+        instance.filter_graph_intra_color_links(output_file).0
+    } else {
+        output_file
+    };
+
+    let output_file = instance
+        .sort_graph_output(
+            output_file,
+            match args.sort_output {
+                OutputSorting::None => ggcat_api::OutputSorting::None,
+                OutputSorting::Length => ggcat_api::OutputSorting::Length,
+            },
+        )
+        .unwrap_or_else(|error| {
+            println!("ERROR: {}", error);
+            exit(1);
+        });
+
+    let output_file = if args.source_ids {
+        instance
+            .annotate_graph_source_ids(
+                output_file,
+                &source_inputs,
+                args.common_args.kmer_length,
+                Some(args.max_source_ids),
+            )
+            .unwrap_or_else(|error| {
+                println!("ERROR: {}", error);
+                exit(1);
+            })
+    } else {
+        output_file
+    };
+
+    let output_file = if args.source_strands {
+        instance
+            .annotate_graph_source_strands(
+                output_file,
+                &source_inputs,
+                args.common_args.kmer_length,
+            )
+            .unwrap_or_else(|error| {
+                println!("ERROR: {}", error);
+                exit(1);
+            })
+    } else {
+        output_file
+    };
+
+    let output_file = if args.kmers_graph {
+        let kmers_graph_file = GGCATInstance::write_kmers_graph(
+            output_file.clone(),
+            args.common_args.kmer_length,
+            args.common_args.forward_only,
+            kmers_graph_file,
+        );
+        let _ = std::fs::remove_file(GGCATInstance::get_metadata_file(&output_file));
+        let _ = std::fs::remove_file(output_file);
+        kmers_graph_file
+    } else {
+        output_file
+    };
 
     println!("Final output saved to: {}", output_file.display());
+
+    if args.colors_dictionary {
+        let dictionary_file =
+            GGCATInstance::export_colors_dictionary(GGCATInstance::get_colormap_file(&output_file));
+        println!("Colors dictionary saved to: {}", dictionary_file.display());
+    }
 }
 
 fn convert_querier_step(step: QuerierStartingStep) -> querier::QuerierStartingStep {
@@ -353,27 +1063,37 @@ fn convert_querier_step(step: QuerierStartingStep) -> querier::QuerierStartingSt
 fn run_querier_from_args(instance: &GGCATInstance, args: QueryArgs) -> PathBuf {
     *ggcat_api::debug::DEBUG_QUERIER_FIRST_STEP.lock() = convert_querier_step(args.step);
 
-    instance.query_graph(
-        args.input_graph,
-        args.input_query,
-        args.output_file_prefix,
-        args.common_args.kmer_length,
-        args.common_args.threads_count,
-        args.common_args.forward_only,
-        args.common_args.minimizer_length,
-        args.colors,
-        match args
-            .colored_query_output_format
-            .unwrap_or(ColoredQueryOutputFormat::JsonLinesWithNumbers)
-        {
-            ColoredQueryOutputFormat::JsonLinesWithNumbers => {
-                querier::ColoredQueryOutputFormat::JsonLinesWithNumbers
-            }
-            ColoredQueryOutputFormat::JsonLinesWithNames => {
-                querier::ColoredQueryOutputFormat::JsonLinesWithNames
-            }
-        },
-    )
+    let input_graph = args.input_graph.clone();
+    instance
+        .query_graph(
+            args.input_graph,
+            args.input_query,
+            args.output_file_prefix,
+            args.common_args.kmer_length,
+            args.common_args.threads_count,
+            args.common_args.forward_only,
+            args.common_args.minimizer_length,
+            args.colors,
+            match args
+                .colored_query_output_format
+                .unwrap_or(ColoredQueryOutputFormat::JsonLinesWithNumbers)
+            {
+                ColoredQueryOutputFormat::JsonLinesWithNumbers => {
+                    querier::ColoredQueryOutputFormat::JsonLinesWithNumbers
+                }
+                ColoredQueryOutputFormat::JsonLinesWithNames => {
+                    querier::ColoredQueryOutputFormat::JsonLinesWithNames
+                }
+            },
+            args.query_both_strands,
+            args.max_degenerate_expansions,
+            args.append_output,
+            args.color_quorum,
+        )
+        .unwrap_or_else(|error| {
+            println!("ERROR: Cannot query {}: {}!", input_graph.display(), error);
+            exit(1);
+        })
 }
 
 instrumenter::global_setup_instrumenter!();
@@ -478,6 +1198,124 @@ fn main() {
 
             return; // Skip final memory deallocation
         }
+        CliArgs::Validate(args) => {
+            let report = GGCATInstance::validate_graph(args.input_graph, args.kmer_length)
+                .unwrap_or_else(|error| {
+                    println!("ERROR: {}", error);
+                    exit(1);
+                });
+            println!("{}", report);
+
+            if !report.is_valid() {
+                println!("Found {} violations!", report.violations_count());
+                exit(1);
+            }
+            return; // Skip final memory deallocation
+        }
+        CliArgs::ValidateInput(args) => {
+            let mut fatal_issues = 0;
+            for input in args.input {
+                println!("Checking {}", input.display());
+                let report = GGCATInstance::validate_input(input);
+                println!("{}", report);
+                fatal_issues += report.fatal_issues_count;
+            }
+
+            if fatal_issues > 0 {
+                println!("Found {} fatal issues!", fatal_issues);
+                exit(1);
+            }
+            return; // Skip final memory deallocation
+        }
+        CliArgs::Compare(args) => {
+            let report = GGCATInstance::compare_graphs(
+                args.graph_a,
+                args.graph_b,
+                args.kmer_length,
+                args.memory,
+            );
+            println!("{}", report);
+            return; // Skip final memory deallocation
+        }
+        CliArgs::Containment(args) => {
+            let report = GGCATInstance::graphs_containment(
+                args.graph_a,
+                args.graph_b,
+                args.kmer_length,
+                args.memory,
+            );
+            println!("{}", report);
+            return; // Skip final memory deallocation
+        }
+        CliArgs::Union(args) => {
+            let instance = initialize(&args.common_args, &args.output_file);
+
+            let output_file = instance
+                .union_graphs(
+                    args.graphs,
+                    args.output_file,
+                    args.common_args.kmer_length,
+                    args.common_args.threads_count,
+                    args.common_args.forward_only,
+                    args.common_args.minimizer_length,
+                )
+                .unwrap_or_else(|error| {
+                    println!("ERROR: {}", error);
+                    exit(1);
+                });
+            println!("Final output saved to: {}", output_file.display());
+        }
+        CliArgs::Filter(args) => {
+            let output_file = GGCATInstance::filter_graph_by_color(
+                args.input_graph,
+                &args.filter_color,
+                args.output_file,
+                args.keep_links,
+            );
+            println!("Final output saved to: {}", output_file.display());
+            return; // Skip final memory deallocation
+        }
+        CliArgs::Extract(args) => {
+            let output_file = GGCATInstance::extract_subgraph(
+                args.input_graph,
+                args.input_query,
+                args.kmer_length,
+                args.forward_only,
+                args.hops,
+                args.output_file,
+            );
+            println!("Final output saved to: {}", output_file.display());
+            return; // Skip final memory deallocation
+        }
+        CliArgs::SelfTest(args) => {
+            let instance = initialize(
+                &args.common_args,
+                &args.common_args.temp_dir[0].join("selftest"),
+            );
+
+            let mut failed = false;
+            for colored in [false, true] {
+                let report = instance
+                    .self_test(
+                        args.genome_length,
+                        args.common_args.kmer_length,
+                        args.common_args.threads_count,
+                        colored,
+                    )
+                    .unwrap_or_else(|error| {
+                        println!("ERROR: {}", error);
+                        exit(1);
+                    });
+                println!("{}", report);
+                failed |= !report.is_valid();
+            }
+
+            if failed {
+                println!("Self test failed, the graph does not match the synthetic genomes!");
+                exit(1);
+            }
+            println!("Self test passed");
+        }
     }
 
     // Ensure termination
@@ -0,0 +1,60 @@
+use criterion::*;
+use ggcat_colors::colors_memmap_writer::ColorsMemMapWriter;
+use ggcat_colors::DefaultColorsSerializer;
+use rand::{Rng, SeedableRng};
+
+const COLORS_COUNT: u32 = 256;
+const SUBSETS_COUNT: usize = 100000;
+const BATCH_SIZE: usize = 1024;
+
+// Many distinct subsets, so that almost every request serializes a new one
+fn generate_subsets(seed: u64) -> Vec<Vec<u32>> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+
+    (0..SUBSETS_COUNT)
+        .map(|_| {
+            let mut subset: Vec<_> = (0..rng.gen_range(1..16))
+                .map(|_| rng.gen_range(0..COLORS_COUNT))
+                .collect();
+            subset.sort_unstable();
+            subset.dedup();
+            subset
+        })
+        .collect()
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let subsets = generate_subsets(0);
+    let color_names: Vec<_> = (0..COLORS_COUNT).map(|c| format!("color{}", c)).collect();
+    let file = std::env::temp_dir().join(format!("ggcat-colors-bench-{}.dat", std::process::id()));
+
+    let mut group = c.benchmark_group("colors-serialization");
+    group.sample_size(10);
+
+    group.bench_function("per-subset", |b| {
+        b.iter(|| {
+            let writer = ColorsMemMapWriter::<DefaultColorsSerializer>::new(&file, &color_names);
+            for subset in &subsets {
+                black_box(writer.get_id(subset));
+            }
+        })
+    });
+
+    group.bench_function(&format!("batched-{}", BATCH_SIZE), |b| {
+        let mut ids = vec![];
+        b.iter(|| {
+            let writer = ColorsMemMapWriter::<DefaultColorsSerializer>::new(&file, &color_names);
+            for batch in subsets.chunks(BATCH_SIZE) {
+                let batch: Vec<_> = batch.iter().map(|s| s.as_slice()).collect();
+                writer.get_ids_batch(&batch, &mut ids);
+                black_box(&ids);
+            }
+        })
+    });
+
+    group.finish();
+    let _ = std::fs::remove_file(&file);
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -61,6 +61,8 @@ impl<T: Copy, P: ChunksWriter<TargetData = T>> AsyncSliceQueue<T, P> {
         buffer_min_size: usize,
         max_slices_buffers_count: usize,
         slices_buffers_size: usize,
+        // Index of the first added slice, to continue the numbering of an existing queue
+        first_index: u64,
         async_processor: P,
     ) -> Self {
         let (sender, receiver) = crossbeam::channel::bounded(max_slices_buffers_count);
@@ -75,7 +77,13 @@ impl<T: Copy, P: ChunksWriter<TargetData = T>> AsyncSliceQueue<T, P> {
             chunks_buffers_pool: (sender, receiver),
             available_buffers: SegQueue::new(),
             slices_queue: SegQueue::new(),
-            push_lock: Mutex::new((0, (0, Vec::with_capacity(slices_buffers_size)))),
+            push_lock: Mutex::new((
+                first_index,
+                (
+                    first_index as ColorIndexType,
+                    Vec::with_capacity(slices_buffers_size),
+                ),
+            )),
             current_slice: RwLock::new(None),
             buffer_min_size,
             async_processor,
@@ -99,6 +107,12 @@ impl<T: Copy, P: ChunksWriter<TargetData = T>> AsyncSliceQueue<T, P> {
     }
 
     pub fn add_data(&self, data: &[T]) -> u64 {
+        self.add_data_batch(data, 1)
+    }
+
+    /// Adds data that contains slices_count consecutive slices, returning the index of the first one.
+    /// The slices are kept in the same chunk, so they are flushed together
+    pub fn add_data_batch(&self, data: &[T], slices_count: u64) -> u64 {
         let mut current_slice = self.current_slice.read();
 
         loop {
@@ -158,7 +172,7 @@ impl<T: Copy, P: ChunksWriter<TargetData = T>> AsyncSliceQueue<T, P> {
                         slice: slice_range,
                     });
                     let unique_index = push_lock.0;
-                    push_lock.0 += 1;
+                    push_lock.0 += slices_count;
 
                     drop(push_lock);
                     return unique_index;
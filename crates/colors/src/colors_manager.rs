@@ -148,12 +148,14 @@ pub trait ColorsMergeManager<H: MinimizerHashFunctionFactory, MH: HashFunctionFa
         entry: &mut MapEntry<Self::HashMapTempColorIndex>,
     );
 
+    /// Adds a sequence whose kmers were counted with the given weight
     fn add_temp_buffer_sequence(
         data: &mut Self::ColorsBufferTempStructure,
         sequence: CompressedRead,
         k: usize,
         m: usize,
         flags: u8,
+        weight: usize,
     );
 
     /// Temporary storage for colors associated with a single kmer in the hashmap (holds the color subset index)
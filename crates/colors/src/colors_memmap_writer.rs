@@ -1,4 +1,6 @@
 // use crate::storage::roaring::ColorsStorage;
+use crate::colors_manager::ColorMapReader;
+use crate::storage::deserializer::ColorsDeserializer;
 use crate::storage::serializer::ColorsSerializer;
 use crate::storage::ColorsSerializerTrait;
 use config::ColorIndexType;
@@ -6,32 +8,127 @@ use dashmap::DashMap;
 use hashes::dummy_hasher::DummyHasherBuilder;
 use rand::{thread_rng, RngCore};
 use siphasher::sip128::{Hasher128, SipHasher13};
+use std::collections::HashMap;
 use std::hash::Hash;
 use std::path::Path;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Hash function of the colors subsets, used as the keys of the subsets map
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorsHashFunction {
+    /// SipHash 1-3 with random keys, different for each map
+    #[default]
+    SipHash,
+    /// Seeded xxh3-128, faster and giving the same hash to a subset on every platform and run
+    Xxh3 { seed: u64 },
+}
 
 pub struct ColorsMemMapWriter<C: ColorsSerializerTrait> {
     colors: DashMap<u128, ColorIndexType, DummyHasherBuilder>,
     colors_storage: ColorsSerializer<C>,
+    hash_function: ColorsHashFunction,
     hash_keys: (u64, u64),
+    /// Id of the only possible subset when building with a single color, returned directly
+    /// to avoid hashing and looking up the subset of every kmer
+    single_color_id: Option<ColorIndexType>,
 }
 
 impl<C: ColorsSerializerTrait> ColorsMemMapWriter<C> {
     pub fn new(file: impl AsRef<Path>, color_names: &[String]) -> Self {
+        Self::create(file, color_names, true, ColorsHashFunction::SipHash)
+    }
+
+    pub fn with_hash_function(
+        file: impl AsRef<Path>,
+        color_names: &[String],
+        hash_function: ColorsHashFunction,
+    ) -> Self {
+        Self::create(file, color_names, true, hash_function)
+    }
+
+    fn create(
+        file: impl AsRef<Path>,
+        color_names: &[String],
+        single_color_fast_path: bool,
+        hash_function: ColorsHashFunction,
+    ) -> Self {
         let mut rng = thread_rng();
+        let colors_storage = ColorsSerializer::new(file, color_names);
+
+        let single_color_id = if single_color_fast_path && color_names.len() == 1 {
+            Some(colors_storage.serialize_colors(&[0]))
+        } else {
+            None
+        };
+
         Self {
             colors: DashMap::with_hasher(DummyHasherBuilder),
-            colors_storage: ColorsSerializer::new(file, color_names),
+            colors_storage,
+            hash_function,
             hash_keys: (rng.next_u64(), rng.next_u64()),
+            single_color_id,
+        }
+    }
+
+    /// Reopens an existing colors file adding the new color names after the existing ones.
+    /// The existing subsets are loaded in memory, so that they keep their ids when requested again
+    pub fn reopen_append(file: impl AsRef<Path>, new_color_names: &[String]) -> Self {
+        let mut rng = thread_rng();
+        let hash_keys = (rng.next_u64(), rng.next_u64());
+        let colors = DashMap::with_hasher(DummyHasherBuilder);
+
+        let mut deserializer = ColorsDeserializer::<C>::new(&file, false);
+        let mut subset = Vec::new();
+        for index in 0..deserializer.colors_subsets_count() as ColorIndexType {
+            deserializer.get_color_mappings(index, &mut subset);
+            colors.insert(
+                Self::hash_colors_with(ColorsHashFunction::SipHash, hash_keys, &subset),
+                index,
+            );
+        }
+        drop(deserializer);
+
+        Self {
+            colors,
+            colors_storage: ColorsSerializer::reopen_append(file, new_color_names),
+            hash_function: ColorsHashFunction::SipHash,
+            hash_keys,
+            single_color_id: None,
+        }
+    }
+
+    fn hash_colors_with(
+        hash_function: ColorsHashFunction,
+        hash_keys: (u64, u64),
+        colors: &[ColorIndexType],
+    ) -> u128 {
+        match hash_function {
+            ColorsHashFunction::SipHash => {
+                let mut hasher = SipHasher13::new_with_keys(hash_keys.0, hash_keys.1);
+                colors.hash(&mut hasher);
+                hasher.finish128().as_u128()
+            }
+            ColorsHashFunction::Xxh3 { seed } => {
+                let mut hasher = Xxh3::with_seed(seed);
+                // Fixed endianness, to get the same hashes on every platform
+                for color in colors {
+                    hasher.update(&color.to_le_bytes());
+                }
+                hasher.digest128()
+            }
         }
     }
 
     fn hash_colors(&self, colors: &[ColorIndexType]) -> u128 {
-        let mut hasher = SipHasher13::new_with_keys(self.hash_keys.0, self.hash_keys.1);
-        colors.hash(&mut hasher);
-        hasher.finish128().as_u128()
+        Self::hash_colors_with(self.hash_function, self.hash_keys, colors)
     }
 
     pub fn get_id(&self, colors: &[ColorIndexType]) -> ColorIndexType {
+        if let Some(single_color_id) = self.single_color_id {
+            debug_assert_eq!(colors, &[0]);
+            return single_color_id;
+        }
+
         let hash = self.hash_colors(colors);
 
         match self.colors.get(&hash) {
@@ -44,7 +141,199 @@ impl<C: ColorsSerializerTrait> ColorsMemMapWriter<C> {
         }
     }
 
+    /// Same as calling get_id for each subset, but the new subsets are serialized together,
+    /// with consecutive ids assigned in submission order. The ids are written to ids in the
+    /// same order of the subsets
+    pub fn get_ids_batch(&self, subsets: &[&[ColorIndexType]], ids: &mut Vec<ColorIndexType>) {
+        ids.clear();
+
+        if let Some(single_color_id) = self.single_color_id {
+            ids.resize(subsets.len(), single_color_id);
+            return;
+        }
+
+        let mut new_subsets = vec![];
+        let mut new_hashes = vec![];
+        let mut new_positions = HashMap::new();
+        let mut pending_ids = vec![];
+
+        for subset in subsets {
+            let hash = self.hash_colors(subset);
+            match self.colors.get(&hash) {
+                Some(id) => ids.push(*id),
+                None => {
+                    // The id is relative to the first new subset until the batch is serialized
+                    let new_position = *new_positions.entry(hash).or_insert_with(|| {
+                        new_subsets.push(*subset);
+                        new_hashes.push(hash);
+                        new_subsets.len() - 1
+                    });
+                    pending_ids.push(ids.len());
+                    ids.push(new_position as ColorIndexType);
+                }
+            }
+        }
+
+        if new_subsets.is_empty() {
+            return;
+        }
+
+        let first_id = self.colors_storage.serialize_colors_batch(&new_subsets);
+        for (offset, hash) in new_hashes.into_iter().enumerate() {
+            self.colors
+                .insert(hash, first_id + offset as ColorIndexType);
+        }
+        for position in pending_ids {
+            ids[position] += first_id;
+        }
+    }
+
     pub fn print_stats(&self) {
         self.colors_storage.print_stats();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorsHashFunction, ColorsMemMapWriter};
+    use crate::colors_manager::ColorMapReader;
+    use crate::storage::deserializer::ColorsDeserializer;
+    use crate::DefaultColorsSerializer;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn reopen_append_keeps_ids() {
+        let test_dir = TestDir::new("colors-append");
+        let file = test_dir.join("graph.colors.dat");
+
+        let subsets: Vec<Vec<u32>> = vec![vec![0], vec![1], vec![0, 1]];
+        let ids: Vec<_> = {
+            let writer = ColorsMemMapWriter::<DefaultColorsSerializer>::new(
+                &file,
+                &["a".to_string(), "b".to_string()],
+            );
+            subsets.iter().map(|s| writer.get_id(s)).collect()
+        };
+
+        let new_id = {
+            let writer = ColorsMemMapWriter::<DefaultColorsSerializer>::reopen_append(
+                &file,
+                &["c".to_string()],
+            );
+            for (subset, id) in subsets.iter().zip(ids.iter()) {
+                assert_eq!(writer.get_id(subset), *id);
+            }
+            writer.get_id(&[1, 2])
+        };
+        assert!(!ids.contains(&new_id));
+
+        let mut deserializer = ColorsDeserializer::<DefaultColorsSerializer>::new(&file, true);
+        assert_eq!(deserializer.colors_count(), 3);
+        assert_eq!(deserializer.get_color_name(2, false), "c");
+        assert_eq!(deserializer.colors_subsets_count(), 4);
+
+        let mut colors = vec![];
+        for (subset, id) in subsets.iter().zip(ids.iter()) {
+            deserializer.get_color_mappings(*id, &mut colors);
+            assert_eq!(&colors, subset);
+        }
+        deserializer.get_color_mappings(new_id, &mut colors);
+        assert_eq!(colors, vec![1, 2]);
+    }
+
+    #[test]
+    fn batched_ids_follow_submission_order() {
+        let test_dir = TestDir::new("colors-batch");
+        let file = test_dir.join("graph.colors.dat");
+
+        let subsets: Vec<&[u32]> = vec![&[0, 2], &[1], &[0, 2], &[0, 1, 2], &[2]];
+        let (known_id, ids) = {
+            let writer = ColorsMemMapWriter::<DefaultColorsSerializer>::new(
+                &file,
+                &["a".to_string(), "b".to_string(), "c".to_string()],
+            );
+            let known_id = writer.get_id(&[1]);
+
+            let mut ids = vec![];
+            writer.get_ids_batch(&subsets, &mut ids);
+
+            let mut repeated_ids = vec![];
+            writer.get_ids_batch(&subsets, &mut repeated_ids);
+            assert_eq!(ids, repeated_ids);
+            (known_id, ids)
+        };
+
+        assert_eq!(ids[1], known_id);
+        assert_eq!(ids[0], ids[2]);
+        assert_eq!(ids[3], ids[0] + 1);
+        assert_eq!(ids[4], ids[0] + 2);
+
+        let mut deserializer = ColorsDeserializer::<DefaultColorsSerializer>::new(&file, true);
+        assert_eq!(deserializer.colors_subsets_count(), 4);
+
+        let mut colors = vec![];
+        for (subset, id) in subsets.iter().zip(ids.iter()) {
+            deserializer.get_color_mappings(*id, &mut colors);
+            assert_eq!(&colors, subset);
+        }
+    }
+
+    #[test]
+    fn single_color_fast_path() {
+        let test_dir = TestDir::new("colors-single");
+
+        let read_colormap = |single_color_fast_path: bool| {
+            let file = test_dir.join(format!("graph-{}.colors.dat", single_color_fast_path));
+
+            let ids: Vec<_> = {
+                let writer = ColorsMemMapWriter::<DefaultColorsSerializer>::create(
+                    &file,
+                    &["a".to_string()],
+                    single_color_fast_path,
+                    ColorsHashFunction::SipHash,
+                );
+                (0..100).map(|_| writer.get_id(&[0])).collect()
+            };
+
+            let mut deserializer = ColorsDeserializer::<DefaultColorsSerializer>::new(&file, true);
+            let mut colors = vec![];
+            deserializer.get_color_mappings(ids[0], &mut colors);
+            let subsets_count = deserializer.colors_subsets_count();
+            (ids, subsets_count, colors)
+        };
+
+        let fast = read_colormap(true);
+        assert_eq!(fast, read_colormap(false));
+        assert!(fast.0.iter().all(|id| *id == fast.0[0]));
+        assert_eq!(fast.1, 1);
+        assert_eq!(fast.2, vec![0]);
+    }
+
+    #[test]
+    fn seeded_hash_is_deterministic() {
+        let subsets: Vec<&[u32]> = vec![&[0], &[0, 2], &[1, 2], &[0, 1, 2]];
+        let color_names = ["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let test_dir = TestDir::new("colors-xxh3");
+
+        let write_subsets = |name: &str, seed: u64| {
+            let file = test_dir.join(format!("{}.colors.dat", name));
+            let (hashes, ids): (Vec<_>, Vec<_>) = {
+                let writer = ColorsMemMapWriter::<DefaultColorsSerializer>::with_hash_function(
+                    &file,
+                    &color_names,
+                    ColorsHashFunction::Xxh3 { seed },
+                );
+                subsets
+                    .iter()
+                    .map(|s| (writer.hash_colors(s), writer.get_id(s)))
+                    .unzip()
+            };
+            (hashes, ids)
+        };
+
+        let first = write_subsets("first", 42);
+        assert_eq!(first, write_subsets("second", 42));
+        assert_ne!(first.0, write_subsets("other-seed", 7).0);
+    }
+}
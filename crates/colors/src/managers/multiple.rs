@@ -181,6 +181,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
         k: usize,
         m: usize,
         flags: u8,
+        weight: usize,
     ) {
         let decr_val =
             ((sequence.bases_count() == k) && (flags & READ_FLAG_INCL_END) == 0) as usize;
@@ -199,6 +200,10 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
         data.sequences[bucket]
             .buffer
             .extend_from_slice(&data.last_color.to_ne_bytes());
+        encode_varint(
+            |b| data.sequences[bucket].buffer.extend_from_slice(b),
+            weight as u64,
+        );
 
         let kmer_length_dist_flag = if sequence.bases_count() > k {
             0
@@ -228,6 +233,12 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
         k: usize,
         min_multiplicity: usize,
     ) {
+        // The subsets of each sub-bucket are serialized in a single batch, the kmers get their
+        // subset ids once the batch is written
+        let mut pending_subsets = vec![];
+        let mut pending_kmers = vec![];
+        let mut subsets_ids = vec![];
+
         for buffer in data.sequences.iter_mut() {
             data.temp_colors_buffer.clear();
 
@@ -237,7 +248,6 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
             let mut read_buf = vec![];
 
             let mut last_partition = 0..0;
-            let mut last_color = 0;
 
             loop {
                 // TODO: Distinguish between error and no more data
@@ -246,6 +256,8 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
                 }
 
                 let color = ColorIndexType::from_ne_bytes(color_buf);
+                // The kmers counter of the map entries includes the weight of the sequence
+                let weight = decode_varint(|| stream.read_u8().ok()).unwrap() as usize;
 
                 let (read_length, only_extra_ending) =
                     decode_varint_flags::<_, typenum::U1>(|| stream.read_u8().ok()).unwrap();
@@ -308,19 +320,24 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
                     let position = entry_count & !VISITED_BIT;
 
                     let col_count = data.temp_colors_buffer[position] as usize;
-                    data.temp_colors_buffer[position] += 1;
+                    data.temp_colors_buffer[position] += weight as ColorIndexType;
 
-                    assert_eq!(data.temp_colors_buffer[position + col_count], 0);
-                    data.temp_colors_buffer[position + col_count] = color;
+                    // A weighted sequence fills a color slot for each unit of its weight
+                    let color_slots = (position + col_count)..(position + col_count + weight);
+                    assert!(
+                        data.temp_colors_buffer[color_slots.clone()]
+                            .iter()
+                            .all(|slot| *slot == 0)
+                    );
+                    data.temp_colors_buffer[color_slots.clone()].fill(color);
 
-                    let has_all_colors = (position + col_count + 1)
-                        == data.temp_colors_buffer.len()
-                        || data.temp_colors_buffer[position + col_count + 1] != 0;
+                    let has_all_colors = color_slots.end == data.temp_colors_buffer.len()
+                        || data.temp_colors_buffer[color_slots.end] != 0;
 
                     // All colors were added, let's assign the final color
                     if has_all_colors {
-                        let colors_range = &mut data.temp_colors_buffer
-                            [(position + 1)..(position + col_count + 1)];
+                        let colors_range =
+                            &mut data.temp_colors_buffer[(position + 1)..color_slots.end];
 
                         colors_range.sort_unstable();
 
@@ -330,16 +347,30 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
 
                         let unique_colors = &data.temp_colors_buffer[new_partition.clone()];
 
-                        // Assign the subset color index to the current kmer
+                        // Queue the subset for the current kmer, consecutive kmers often share it
                         if unique_colors != &data.temp_colors_buffer[last_partition.clone()] {
-                            last_color = global_colors_table.get_id(unique_colors);
+                            pending_subsets.push(new_partition.clone());
                             last_partition = new_partition;
                         }
 
-                        entry.set_counter_after_check(VISITED_BIT | (last_color as usize));
+                        pending_kmers
+                            .push((kmer_hash.to_unextendable(), pending_subsets.len() - 1));
                     }
                 }
             }
+
+            let subsets: Vec<_> = pending_subsets
+                .drain(..)
+                .map(|range| &data.temp_colors_buffer[range])
+                .collect();
+            global_colors_table.get_ids_batch(&subsets, &mut subsets_ids);
+
+            // Assign the subset color index to the queued kmers
+            for (kmer_hash, subset) in pending_kmers.drain(..) {
+                map.get_mut(&kmer_hash)
+                    .unwrap()
+                    .set_counter_after_check(VISITED_BIT | (subsets_ids[subset] as usize));
+            }
         }
     }
 
@@ -621,6 +652,14 @@ impl IdentSequenceWriter for UnitigColorData {
         }
     }
 
+    fn write_colors_subsets(
+        &self,
+        subsets: &mut Vec<ColorIndexType>,
+        extra_buffer: &Self::TempBuffer,
+    ) {
+        subsets.extend(self.slice.clone().map(|i| extra_buffer.colors[i].color));
+    }
+
     #[allow(unused_variables)]
     fn parse_as_ident<'a>(ident: &[u8], colors_buffer: &mut Self::TempBuffer) -> Option<Self> {
         let mut colors_count = 0;
@@ -635,7 +674,7 @@ impl IdentSequenceWriter for UnitigColorData {
             colors_count += kmers_count
         }
         if colors_count == 0 {
-            println!("Warn: 0 colors for {:?}", std::str::from_utf8(ident));
+            log::warn!("Warn: 0 colors for {:?}", std::str::from_utf8(ident));
         }
 
         Some(UnitigColorData {
@@ -660,7 +699,7 @@ impl IdentSequenceWriter for UnitigColorData {
             }
         }
         if colors_count == 0 {
-            println!("Warn: 0 colors for {:?}", std::str::from_utf8(ident));
+            log::warn!("Warn: 0 colors for {:?}", std::str::from_utf8(ident));
         }
 
         Some(UnitigColorData {
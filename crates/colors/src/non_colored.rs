@@ -156,6 +156,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory> ColorsMergeManage
         _k: usize,
         _m: usize,
         _flags: u8,
+        _weight: usize,
     ) {
     }
 
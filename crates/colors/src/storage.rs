@@ -13,8 +13,16 @@ pub trait ColorsSerializerTrait: 'static {
     fn decode_color(reader: impl Read, out_vec: Option<&mut Vec<ColorIndexType>>);
     // fn decode_colors(reader: impl Read) -> ;
 
-    fn new(writer: ColorsFlushProcessing, checkpoint_distance: usize, colors_count: u64) -> Self;
+    fn new(
+        writer: ColorsFlushProcessing,
+        checkpoint_distance: usize,
+        colors_count: u64,
+        first_subset_index: u64,
+    ) -> Self;
     fn serialize_colors(&self, colors: &[ColorIndexType]) -> ColorIndexType;
+    /// Serializes multiple subsets with consecutive indices in submission order, returning the index
+    /// of the first one
+    fn serialize_colors_batch(&self, subsets: &[&[ColorIndexType]]) -> ColorIndexType;
     fn get_subsets_count(&self) -> u64;
     fn print_stats(&self);
     fn finalize(self) -> ColorsFlushProcessing;
@@ -1,14 +1,17 @@
 use crate::colors_manager::ColorMapReader;
-use crate::storage::serializer::{ColorsFileHeader, ColorsIndexEntry, ColorsIndexMap};
+use crate::storage::serializer::{read_colors_file_header, ColorsIndexEntry, ColorsIndexMap};
 use crate::storage::ColorsSerializerTrait;
 use config::ColorIndexType;
-use desse::Desse;
-use desse::DesseSized;
 use replace_with::replace_with_or_abort;
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Path of the colors dictionary sidecar of a colors file (graph.colors.dat -> graph.colors.tsv)
+pub fn colors_dictionary_file(colors_file: impl AsRef<Path>) -> PathBuf {
+    colors_file.as_ref().with_extension("tsv")
+}
 
 pub struct ColorsDeserializer<DS: ColorsSerializerTrait> {
     colormap_file: lz4::Decoder<BufReader<File>>,
@@ -26,13 +29,12 @@ unsafe impl<DS: ColorsSerializerTrait> Send for ColorsDeserializer<DS> {}
 
 impl<DS: ColorsSerializerTrait> ColorsDeserializer<DS> {
     pub fn new(file: impl AsRef<Path>, read_color_names: bool) -> Self {
-        let mut file = File::open(file).unwrap();
-
-        let mut header_buffer = [0; ColorsFileHeader::SIZE];
-        file.read_exact(&mut header_buffer).unwrap();
+        let path = file.as_ref();
+        let mut file = File::open(path).unwrap();
 
-        let header: ColorsFileHeader = ColorsFileHeader::deserialize_from(&header_buffer);
-        assert_eq!(header.magic, DS::MAGIC);
+        let header = read_colors_file_header::<DS>(&mut file).unwrap_or_else(|err| {
+            panic!("Cannot read the colors file {}: {}", path.display(), err)
+        });
 
         let color_names = if read_color_names {
             let mut compressed_stream = lz4::Decoder::new(BufReader::new(file)).unwrap();
@@ -130,6 +132,42 @@ impl<DS: ColorsSerializerTrait> ColorsDeserializer<DS> {
         DS::decode_color(&mut self.colormap_file, Some(out_vec));
         self.current_index += 1;
     }
+
+    /// Writes the colors dictionary as tab separated values, to join the colors and the subsets
+    /// indices (as found in the graph) to the color names without parsing the colors file.
+    /// Each color is listed as color<TAB>index<TAB>name, followed by each colors subset as
+    /// subset<TAB>index<TAB>comma separated indices of its colors.
+    /// The color names must have been read when creating the deserializer
+    pub fn write_dictionary(&mut self, output: &mut impl Write) -> std::io::Result<()> {
+        writeln!(output, "# kind\tindex\tvalue")?;
+        for (index, name) in self.color_names.iter().enumerate() {
+            writeln!(output, "color\t{}\t{}", index, name)?;
+        }
+
+        let mut colors = vec![];
+        for subset in 0..self.colors_index.subsets_count as ColorIndexType {
+            colors.clear();
+            self.get_color_mappings(subset, &mut colors);
+            write!(output, "subset\t{}\t", subset)?;
+            for (i, color) in colors.iter().enumerate() {
+                if i > 0 {
+                    write!(output, ",")?;
+                }
+                write!(output, "{}", color)?;
+            }
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the colors dictionary of a colors file to its sidecar (see colors_dictionary_file)
+    pub fn export_dictionary(colors_file: impl AsRef<Path>) -> std::io::Result<PathBuf> {
+        let dictionary_file = colors_dictionary_file(&colors_file);
+        let mut output = BufWriter::new(File::create(&dictionary_file)?);
+        Self::new(colors_file, true).write_dictionary(&mut output)?;
+        output.flush()?;
+        Ok(dictionary_file)
+    }
 }
 
 impl<DS: ColorsSerializerTrait> ColorMapReader for ColorsDeserializer<DS> {
@@ -149,3 +187,55 @@ impl<DS: ColorsSerializerTrait> ColorMapReader for ColorsDeserializer<DS> {
         self.colors_index.subsets_count as u64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{colors_dictionary_file, ColorsDeserializer};
+    use crate::storage::serializer::ColorsSerializer;
+    use crate::DefaultColorsSerializer;
+    use std::collections::HashSet;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn dictionary_lists_every_color_once() {
+        let test_dir = TestDir::new("colors-dictionary");
+        let file = test_dir.join("graph.colors.dat");
+
+        let names: Vec<_> = (0..5).map(|i| format!("genome{}", i)).collect();
+        let serializer = ColorsSerializer::<DefaultColorsSerializer>::new(&file, &names);
+        let subsets: &[&[u32]] = &[&[0], &[1, 3], &[0, 2, 4], &[4]];
+        let mut expected_subsets: Vec<_> = subsets
+            .iter()
+            .map(|subset| {
+                let colors: Vec<_> = subset.iter().map(|c| c.to_string()).collect();
+                (serializer.serialize_colors(subset), colors.join(","))
+            })
+            .collect();
+        expected_subsets.sort();
+        serializer.finalize().unwrap();
+
+        let dictionary_file =
+            ColorsDeserializer::<DefaultColorsSerializer>::export_dictionary(&file).unwrap();
+        assert_eq!(dictionary_file, colors_dictionary_file(&file));
+        assert_eq!(dictionary_file, test_dir.join("graph.colors.tsv"));
+
+        let dictionary = std::fs::read_to_string(&dictionary_file).unwrap();
+
+        let mut colors = HashSet::new();
+        let mut subsets_lines = vec![];
+        for line in dictionary.lines().skip(1) {
+            let fields: Vec<_> = line.split('\t').collect();
+            match fields[0] {
+                "color" => {
+                    assert!(colors.insert(fields[1].parse::<usize>().unwrap()));
+                    assert_eq!(fields[2], names[fields[1].parse::<usize>().unwrap()]);
+                }
+                "subset" => subsets_lines.push((fields[1].parse().unwrap(), fields[2].to_string())),
+                kind => panic!("Unexpected dictionary entry {}", kind),
+            }
+        }
+
+        assert_eq!(colors, (0..names.len()).collect());
+        assert_eq!(subsets_lines, expected_subsets);
+    }
+}
@@ -61,7 +61,7 @@ impl RoaringBitmapInstance {
 
         // Flush the partial bitmap
         if strided_color >= self.checkpoint_distance as u32 {
-            println!("Flushing with offset: {}", self.offset);
+            log::debug!("Flushing with offset: {}", self.offset);
             self.flush(writer);
         }
 
@@ -87,6 +87,21 @@ pub struct RoaringColorsSerializer {
     colors_index: AtomicU32,
 }
 
+impl RoaringColorsSerializer {
+    fn append_colors(&self, color_index: ColorIndexType, colors: &[ColorIndexType]) {
+        let target_bitmap = color_index % self.roaring_bitmaps.len() as ColorIndexType;
+
+        loop {
+            let mut bitmap_lock = self.roaring_bitmaps[target_bitmap as usize].lock();
+            if bitmap_lock.try_append(color_index, colors.iter().copied(), &self.writer) {
+                break;
+            }
+            drop(bitmap_lock);
+            std::thread::yield_now();
+        }
+    }
+}
+
 impl ColorsSerializerTrait for RoaringColorsSerializer {
     const MAGIC: [u8; 16] = *b"GGCAT_CMAP_ROARG";
 
@@ -95,7 +110,12 @@ impl ColorsSerializerTrait for RoaringColorsSerializer {
         todo!()
     }
 
-    fn new(writer: ColorsFlushProcessing, checkpoint_distance: usize, colors_count: u64) -> Self {
+    fn new(
+        writer: ColorsFlushProcessing,
+        checkpoint_distance: usize,
+        colors_count: u64,
+        first_subset_index: u64,
+    ) -> Self {
         todo!("Fix meaning of 'stride'!");
         let stride = rayon::current_num_threads() as ColorIndexType;
 
@@ -111,26 +131,25 @@ impl ColorsSerializerTrait for RoaringColorsSerializer {
                 })
                 .collect(),
             writer,
-            colors_index: AtomicU32::new(0),
+            colors_index: AtomicU32::new(first_subset_index as ColorIndexType),
             colors_count,
         }
     }
 
     fn serialize_colors(&self, colors: &[ColorIndexType]) -> ColorIndexType {
         let color_index = self.colors_index.fetch_add(1, Ordering::Relaxed);
+        self.append_colors(color_index, colors);
+        color_index
+    }
 
-        let target_bitmap = color_index % self.roaring_bitmaps.len() as ColorIndexType;
-
-        loop {
-            let mut bitmap_lock = self.roaring_bitmaps[target_bitmap as usize].lock();
-            if bitmap_lock.try_append(color_index, colors.iter().copied(), &self.writer) {
-                break;
-            }
-            drop(bitmap_lock);
-            std::thread::yield_now();
+    fn serialize_colors_batch(&self, subsets: &[&[ColorIndexType]]) -> ColorIndexType {
+        let first_index = self
+            .colors_index
+            .fetch_add(subsets.len() as ColorIndexType, Ordering::Relaxed);
+        for (offset, colors) in subsets.iter().enumerate() {
+            self.append_colors(first_index + offset as ColorIndexType, colors);
         }
-
-        color_index
+        first_index
     }
 
     fn get_subsets_count(&self) -> u64 {
@@ -138,7 +157,7 @@ impl ColorsSerializerTrait for RoaringColorsSerializer {
     }
 
     fn print_stats(&self) {
-        println!(
+        log::info!(
             "Subsets count: {} witn {} colors",
             self.get_subsets_count(),
             self.colors_count
@@ -7,8 +7,54 @@ use config::DEFAULT_OUTPUT_BUFFER_SIZE;
 use io::varint::{decode_varint, encode_varint};
 use std::io::{Read, Write};
 
+// Never the first value of the differences encoding, that starts from 2
+const BITMAP_SUBSET_TAG: u64 = 0;
+
+fn varint_size(value: u64) -> usize {
+    encode_varint(|b| b.len(), value)
+}
+
+/// Encodings of the sorted colors subsets. The differences between consecutive colors are
+/// written as varints, with the runs of equal differences collapsed. The dense subsets are
+/// instead written as a bitmap of the colors from the first one, that is chosen by
+/// serialize_colors_auto when it is smaller
 pub struct ColorIndexSerializer;
 impl ColorIndexSerializer {
+    fn bitmap_size(colors: &[ColorIndexType]) -> usize {
+        let first_color = colors[0];
+        let bitmap_bytes = (colors[colors.len() - 1] - first_color) as usize / 8 + 1;
+        varint_size(BITMAP_SUBSET_TAG)
+            + varint_size(first_color as u64)
+            + varint_size(bitmap_bytes as u64)
+            + bitmap_bytes
+    }
+
+    pub fn serialize_colors_bitmap(mut writer: impl Write, colors: &[ColorIndexType]) {
+        let first_color = colors[0];
+        let mut bitmap = vec![0u8; (colors[colors.len() - 1] - first_color) as usize / 8 + 1];
+        for color in colors {
+            let bit = (color - first_color) as usize;
+            bitmap[bit / 8] |= 1 << (bit % 8);
+        }
+
+        encode_varint(|b| writer.write_all(b), BITMAP_SUBSET_TAG).unwrap();
+        encode_varint(|b| writer.write_all(b), first_color as u64).unwrap();
+        encode_varint(|b| writer.write_all(b), bitmap.len() as u64).unwrap();
+        writer.write_all(&bitmap).unwrap();
+    }
+
+    /// Appends the subset to the buffer with the smallest encoding, the differences encoding
+    /// for the sparse subsets (many colors and few of them in each subset) and the bitmap
+    /// for the dense ones
+    pub fn serialize_colors_auto(buffer: &mut Vec<u8>, colors: &[ColorIndexType]) {
+        let start = buffer.len();
+        Self::serialize_colors(&mut *buffer, colors);
+        if Self::bitmap_size(colors) < buffer.len() - start {
+            buffer.truncate(start);
+            Self::serialize_colors_bitmap(&mut *buffer, colors);
+        }
+    }
+
     pub fn serialize_colors(mut writer: impl Write, colors: &[ColorIndexType]) {
         encode_varint(|b| writer.write_all(b), (colors[0] as u64) + 2).unwrap();
 
@@ -63,7 +109,26 @@ impl ColorIndexSerializer {
         mut reader: impl Read,
         mut add_color: impl FnMut(ColorIndexType),
     ) -> Option<()> {
-        add_color((decode_varint(|| reader.read_u8().ok())? - 2) as ColorIndexType);
+        let first_value = decode_varint(|| reader.read_u8().ok())?;
+        if first_value == BITMAP_SUBSET_TAG {
+            let first_color = decode_varint(|| reader.read_u8().ok())? as ColorIndexType;
+            let bitmap_bytes = decode_varint(|| reader.read_u8().ok())? as ColorIndexType;
+
+            let mut last_color = None;
+            for byte_index in 0..bitmap_bytes {
+                let byte = reader.read_u8().ok()?;
+                for bit in 0..8 {
+                    if byte & (1 << bit) != 0 {
+                        let color = first_color + byte_index * 8 + bit;
+                        add_color(color - last_color.unwrap_or(0));
+                        last_color = Some(color);
+                    }
+                }
+            }
+            return Some(());
+        }
+
+        add_color((first_value - 2) as ColorIndexType);
         loop {
             let result = decode_varint(|| reader.read_u8().ok())? as ColorIndexType;
             if result == 0 {
@@ -118,12 +183,18 @@ impl ColorsSerializerTrait for RunLengthColorsSerializer {
         }
     }
 
-    fn new(writer: ColorsFlushProcessing, checkpoint_distance: usize, _colors_count: u64) -> Self {
+    fn new(
+        writer: ColorsFlushProcessing,
+        checkpoint_distance: usize,
+        _colors_count: u64,
+        first_subset_index: u64,
+    ) -> Self {
         Self {
             async_buffer: AsyncSliceQueue::new(
                 DEFAULT_OUTPUT_BUFFER_SIZE,
                 rayon::current_num_threads(),
                 checkpoint_distance,
+                first_subset_index,
                 writer,
             ),
         }
@@ -132,17 +203,29 @@ impl ColorsSerializerTrait for RunLengthColorsSerializer {
     fn serialize_colors(&self, colors: &[u32]) -> u32 {
         unsafe {
             TEMP_COLOR_BUFFER.clear();
-            ColorIndexSerializer::serialize_colors(&mut TEMP_COLOR_BUFFER, colors);
+            ColorIndexSerializer::serialize_colors_auto(&mut TEMP_COLOR_BUFFER, colors);
             self.async_buffer.add_data(TEMP_COLOR_BUFFER.as_slice()) as ColorIndexType
         }
     }
 
+    fn serialize_colors_batch(&self, subsets: &[&[u32]]) -> u32 {
+        unsafe {
+            TEMP_COLOR_BUFFER.clear();
+            for colors in subsets {
+                ColorIndexSerializer::serialize_colors_auto(&mut TEMP_COLOR_BUFFER, colors);
+            }
+            self.async_buffer
+                .add_data_batch(TEMP_COLOR_BUFFER.as_slice(), subsets.len() as u64)
+                as ColorIndexType
+        }
+    }
+
     fn get_subsets_count(&self) -> u64 {
         self.async_buffer.get_counter()
     }
 
     fn print_stats(&self) {
-        println!("Total color subsets: {}", self.async_buffer.get_counter())
+        log::info!("Total color subsets: {}", self.async_buffer.get_counter())
     }
 
     fn finalize(self) -> ColorsFlushProcessing {
@@ -168,6 +251,56 @@ mod tests {
 
         ColorIndexSerializer::deserialize_colors(&mut cursor, &mut des_colors);
         assert_eq!(colors, des_colors.as_slice());
+
+        let mut buffer = Vec::new();
+        ColorIndexSerializer::serialize_colors_auto(&mut buffer, colors);
+        ColorIndexSerializer::deserialize_colors(Cursor::new(buffer), &mut des_colors);
+        assert_eq!(colors, des_colors.as_slice());
+    }
+
+    // Subsets of about density_ppm / 10^6 * colors_count random colors
+    fn random_subsets(
+        colors_count: u32,
+        density_ppm: u64,
+        subsets_count: usize,
+    ) -> Vec<Vec<ColorIndexType>> {
+        let mut state = 0x9E3779B97F4A7C15u64;
+        let mut next_random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        (0..subsets_count)
+            .map(|_| {
+                let mut subset: Vec<_> = (0..colors_count)
+                    .filter(|_| next_random() % 1_000_000 < density_ppm)
+                    .collect();
+                if subset.is_empty() {
+                    subset.push((next_random() % colors_count as u64) as ColorIndexType);
+                }
+                subset
+            })
+            .collect()
+    }
+
+    // Total size of the subsets with the differences, bitmap and automatic encodings
+    fn encoded_sizes(subsets: &[Vec<ColorIndexType>]) -> [usize; 3] {
+        let mut encoded = [Vec::new(), Vec::new(), Vec::new()];
+        for subset in subsets {
+            ColorIndexSerializer::serialize_colors(&mut encoded[0], subset);
+            ColorIndexSerializer::serialize_colors_bitmap(&mut encoded[1], subset);
+            ColorIndexSerializer::serialize_colors_auto(&mut encoded[2], subset);
+        }
+
+        let mut cursor = Cursor::new(&encoded[2]);
+        let mut des_colors = Vec::new();
+        for subset in subsets {
+            ColorIndexSerializer::deserialize_colors(&mut cursor, &mut des_colors);
+            assert_eq!(subset, &des_colors);
+        }
+
+        encoded.map(|buffer| buffer.len())
     }
 
     #[test]
@@ -184,5 +317,21 @@ mod tests {
         color_subset_encoding(&[
             3, 6, 9, 12, 70, 71, 62, 63, 64, 88, 95, 100, 105, 110, 198, 384,
         ]);
+
+        color_subset_encoding(&[0, 2, 3, 5, 7, 8, 9, 11, 12, 14, 15, 17]);
+        color_subset_encoding(&[1000, 1001, 1003, 1004, 1006]);
+    }
+
+    #[test]
+    fn subsets_encoding_by_density() {
+        // Many colors with a few of them in each subset
+        let [differences, bitmap, auto] = encoded_sizes(&random_subsets(20000, 500, 200));
+        assert!(differences * 10 < bitmap);
+        assert!(auto <= differences);
+
+        // Half of the colors in each subset
+        let [differences, bitmap, auto] = encoded_sizes(&random_subsets(512, 500_000, 200));
+        assert!(bitmap < differences);
+        assert!(auto <= bitmap);
     }
 }
@@ -5,14 +5,16 @@ use desse::{Desse, DesseSized};
 use io::chunks_writer::ChunksWriter;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::mem::ManuallyDrop;
 use std::ops::{Deref, DerefMut};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 
-const STORAGE_VERSION: u64 = 1;
+// The version 2 adds the bitmap subsets, the files of the version 1 can still be read
+const STORAGE_VERSION: u64 = 2;
 
 #[derive(Debug, Desse, DesseSized, Default)]
 pub(crate) struct ColorsFileHeader {
@@ -37,72 +39,261 @@ pub(crate) struct ColorsIndexMap {
     pub subsets_count: u64,
 }
 
+/// Error of a colors file that cannot be read
+#[derive(Debug)]
+pub enum ColorsFileError {
+    Io(std::io::Error),
+    /// The header is still empty, the writing process stopped before finalizing the file
+    NotFinalized,
+    WrongMagic,
+    UnsupportedVersion(u64),
+    Truncated {
+        expected_size: u64,
+        actual_size: u64,
+    },
+}
+
+impl Display for ColorsFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorsFileError::Io(err) => write!(f, "{}", err),
+            ColorsFileError::NotFinalized => write!(
+                f,
+                "the colors file was not finalized, the process writing it was interrupted"
+            ),
+            ColorsFileError::WrongMagic => write!(f, "not a colors file of this format"),
+            ColorsFileError::UnsupportedVersion(version) => {
+                write!(f, "unsupported colors file version {}", version)
+            }
+            ColorsFileError::Truncated {
+                expected_size,
+                actual_size,
+            } => write!(
+                f,
+                "the colors file is truncated, its size is {} instead of {}",
+                actual_size, expected_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ColorsFileError {}
+
+/// Temporary file where a colors file is written until it is finalized, and then renamed to it.
+/// A crash while writing cannot leave a partial colors file in place of the complete one
+pub fn colors_temp_file(file: impl AsRef<Path>) -> PathBuf {
+    let mut temp_file = file.as_ref().as_os_str().to_owned();
+    temp_file.push(".tmp");
+    PathBuf::from(temp_file)
+}
+
+/// Reads and checks the header of a colors file, leaving the file positioned after it
+pub(crate) fn read_colors_file_header<SI: ColorsSerializerTrait>(
+    file: &mut File,
+) -> Result<ColorsFileHeader, ColorsFileError> {
+    let actual_size = file.metadata().map_err(ColorsFileError::Io)?.len();
+
+    if actual_size < ColorsFileHeader::SIZE as u64 {
+        return Err(ColorsFileError::Truncated {
+            expected_size: ColorsFileHeader::SIZE as u64,
+            actual_size,
+        });
+    }
+    let mut header_buffer = [0; ColorsFileHeader::SIZE];
+    file.read_exact(&mut header_buffer)
+        .map_err(ColorsFileError::Io)?;
+    let header: ColorsFileHeader = ColorsFileHeader::deserialize_from(&header_buffer);
+
+    // The header is written only when the file is finalized
+    if header.magic == [0; 16] {
+        return Err(ColorsFileError::NotFinalized);
+    }
+    if header.magic != SI::MAGIC {
+        return Err(ColorsFileError::WrongMagic);
+    }
+    if !(1..=STORAGE_VERSION).contains(&header.version) {
+        return Err(ColorsFileError::UnsupportedVersion(header.version));
+    }
+    if actual_size < header.total_size {
+        return Err(ColorsFileError::Truncated {
+            expected_size: header.total_size,
+            actual_size,
+        });
+    }
+
+    Ok(header)
+}
+
+/// Checks that a colors file has been completely written, reading only its header
+pub fn validate_colors_file<SI: ColorsSerializerTrait>(
+    file: impl AsRef<Path>,
+) -> Result<(), ColorsFileError> {
+    let mut file = File::open(file).map_err(ColorsFileError::Io)?;
+    read_colors_file_header::<SI>(&mut file).map(|_| ())
+}
+
+/// Writer of a colors file. The file is written to a temporary file (see colors_temp_file)
+/// that replaces it only when finalized, with finalize or when dropped (but not while panicking)
 pub struct ColorsSerializer<SI: ColorsSerializerTrait> {
     colors_count: u64,
     serializer_impl: ManuallyDrop<SI>,
+    file: PathBuf,
+    temp_file: PathBuf,
+    finalized: bool,
 }
 
 impl<SI: ColorsSerializerTrait> ColorsSerializer<SI> {
-    pub fn new(file: impl AsRef<Path>, color_names: &[String]) -> Self {
+    fn create_with_color_names(file: impl AsRef<Path>, color_names: &[String]) -> File {
         let mut colormap_file = File::create(file).unwrap();
 
         colormap_file
             .write_all(&ColorsFileHeader::default().serialize()[..])
             .unwrap();
 
-        colormap_file = {
-            let mut color_names_stream = lz4::EncoderBuilder::new()
-                .level(4)
-                .build(colormap_file)
-                .unwrap();
-            bincode::serialize_into(&mut color_names_stream, color_names).unwrap();
+        let mut color_names_stream = lz4::EncoderBuilder::new()
+            .level(4)
+            .build(colormap_file)
+            .unwrap();
+        bincode::serialize_into(&mut color_names_stream, color_names).unwrap();
 
-            let (cf, res) = color_names_stream.finish();
-            res.unwrap();
-            cf
-        };
+        let (cf, res) = color_names_stream.finish();
+        res.unwrap();
+        cf
+    }
 
+    fn from_parts(
+        file: &Path,
+        temp_file: PathBuf,
+        mut colormap_file: File,
+        index_map: ColorsIndexMap,
+        colors_count: u64,
+        uncompressed_size: u64,
+    ) -> Self {
         let file_offset = colormap_file.stream_position().unwrap();
+        let first_subset_index = index_map.subsets_count;
 
         let color_processor = ColorsFlushProcessing {
-            colormap_file: Mutex::new((
-                BufWriter::new(colormap_file),
-                ColorsIndexMap {
-                    pairs: vec![],
-                    subsets_count: 0,
-                },
-            )),
+            colormap_file: Mutex::new((BufWriter::new(colormap_file), index_map)),
             offset: AtomicU64::new(file_offset),
-            uncompressed_size: AtomicU64::new(0),
+            uncompressed_size: AtomicU64::new(uncompressed_size),
         };
 
-        let colors_count = color_names.len() as u64;
-
         Self {
             colors_count,
             serializer_impl: ManuallyDrop::new(SI::new(
                 color_processor,
                 COLORS_SINGLE_BATCH_SIZE as usize,
                 colors_count,
+                first_subset_index,
             )),
+            file: file.to_path_buf(),
+            temp_file,
+            finalized: false,
+        }
+    }
+
+    pub fn new(file: impl AsRef<Path>, color_names: &[String]) -> Self {
+        let file = file.as_ref();
+        let temp_file = colors_temp_file(file);
+        if temp_file.exists() {
+            log::warn!(
+                "Overwriting the partially written colors file {} of a previous run",
+                temp_file.display()
+            );
+        }
+        let colormap_file = Self::create_with_color_names(&temp_file, color_names);
+
+        Self::from_parts(
+            file,
+            temp_file,
+            colormap_file,
+            ColorsIndexMap {
+                pairs: vec![],
+                subsets_count: 0,
+            },
+            color_names.len() as u64,
+            0,
+        )
+    }
+
+    /// Reopens an existing colors file to add new colors and subsets. The existing subsets keep their
+    /// indices and the new ones are numbered after them. The file is rewritten, as the color names
+    /// at its beginning are followed by the subsets data, and it is replaced only when finalized
+    pub fn reopen_append(file: impl AsRef<Path>, new_color_names: &[String]) -> Self {
+        let file = file.as_ref();
+        let mut old_file = File::open(file).unwrap();
+        let header = read_colors_file_header::<SI>(&mut old_file).unwrap_or_else(|err| {
+            panic!(
+                "Cannot append to the colors file {}: {}",
+                file.display(),
+                err
+            )
+        });
+
+        let mut color_names: Vec<String> =
+            bincode::deserialize_from(lz4::Decoder::new(BufReader::new(&mut old_file)).unwrap())
+                .unwrap();
+        color_names.extend_from_slice(new_color_names);
+
+        old_file.seek(SeekFrom::Start(header.index_offset)).unwrap();
+        let mut index_map: ColorsIndexMap = bincode::deserialize_from(&mut old_file).unwrap();
+
+        // Copy the subsets data after the new color names, moving the chunks offsets accordingly
+        let temp_file = colors_temp_file(file);
+        let mut colormap_file = Self::create_with_color_names(&temp_file, &color_names);
+
+        let old_data_offset = index_map
+            .pairs
+            .first()
+            .map(|p| p.file_offset)
+            .unwrap_or(header.index_offset);
+        let data_offset = colormap_file.stream_position().unwrap();
+
+        old_file.seek(SeekFrom::Start(old_data_offset)).unwrap();
+        std::io::copy(
+            &mut (&mut old_file).take(header.index_offset - old_data_offset),
+            &mut colormap_file,
+        )
+        .unwrap();
+
+        for pair in &mut index_map.pairs {
+            pair.file_offset = pair.file_offset - old_data_offset + data_offset;
         }
+
+        drop(old_file);
+
+        Self::from_parts(
+            file,
+            temp_file,
+            colormap_file,
+            index_map,
+            color_names.len() as u64,
+            header.total_uncompressed_size,
+        )
     }
 
     pub fn serialize_colors(&self, colors: &[ColorIndexType]) -> ColorIndexType {
         self.serializer_impl.serialize_colors(colors)
     }
 
+    pub fn serialize_colors_batch(&self, subsets: &[&[ColorIndexType]]) -> ColorIndexType {
+        self.serializer_impl.serialize_colors_batch(subsets)
+    }
+
     pub fn print_stats(&self) {
         self.serializer_impl.print_stats()
     }
-}
 
-fn bincode_serialize_ref<S: Write, D: Serialize>(ser: &mut S, data: &D) {
-    bincode::serialize_into(ser, data).unwrap();
-}
+    /// Completes the colors file, writing its index and header, and atomically replaces
+    /// the previous file (if any) with it
+    pub fn finalize(mut self) -> std::io::Result<()> {
+        self.finalize_file()
+    }
+
+    fn finalize_file(&mut self) -> std::io::Result<()> {
+        // Never finalized twice, even if the first attempt fails
+        self.finalized = true;
 
-impl<SI: ColorsSerializerTrait> Drop for ColorsSerializer<SI> {
-    fn drop(&mut self) {
         let subsets_count = self.serializer_impl.get_subsets_count();
 
         let chunks_writer =
@@ -113,34 +304,61 @@ impl<SI: ColorsSerializerTrait> Drop for ColorsSerializer<SI> {
         index_map.pairs.sort();
         index_map.subsets_count = subsets_count;
 
-        colors_file.flush().unwrap();
-
-        let index_position = colors_file.stream_position().unwrap();
-
-        bincode_serialize_ref(colors_file, index_map);
-        colors_file.flush().unwrap();
-
-        let total_size = colors_file.stream_position().unwrap();
-        colors_file.seek(SeekFrom::Start(0)).unwrap();
-
-        colors_file
-            .write_all(
-                &ColorsFileHeader {
-                    magic: SI::MAGIC,
-                    version: STORAGE_VERSION,
-                    index_offset: index_position,
-                    colors_count: self.colors_count,
-                    subsets_count,
-                    total_size,
-                    total_uncompressed_size: chunks_writer
-                        .uncompressed_size
-                        .load(Ordering::Relaxed),
-                }
-                .serialize()[..],
-            )
-            .unwrap();
+        colors_file.flush()?;
+
+        let index_position = colors_file.stream_position()?;
+
+        bincode_serialize_ref(colors_file, index_map)?;
+        colors_file.flush()?;
+
+        let total_size = colors_file.stream_position()?;
+        colors_file.seek(SeekFrom::Start(0))?;
+
+        // The header is written last, so a file without it is detected as not finalized
+        colors_file.write_all(
+            &ColorsFileHeader {
+                magic: SI::MAGIC,
+                version: STORAGE_VERSION,
+                index_offset: index_position,
+                colors_count: self.colors_count,
+                subsets_count,
+                total_size,
+                total_uncompressed_size: chunks_writer.uncompressed_size.load(Ordering::Relaxed),
+            }
+            .serialize()[..],
+        )?;
+
+        colors_file.flush()?;
+        colors_file.get_ref().sync_all()?;
+        drop(colors_lock);
+        drop(chunks_writer);
+
+        std::fs::rename(&self.temp_file, &self.file)?;
+        #[cfg(unix)]
+        if let Some(dir) = self.file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+fn bincode_serialize_ref<S: Write, D: Serialize>(ser: &mut S, data: &D) -> std::io::Result<()> {
+    bincode::serialize_into(ser, data)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+}
 
-        colors_file.flush().unwrap();
+impl<SI: ColorsSerializerTrait> Drop for ColorsSerializer<SI> {
+    fn drop(&mut self) {
+        // A panic while writing leaves the partial temporary file, the colors file is not replaced
+        if !self.finalized && !std::thread::panicking() {
+            self.finalize_file().unwrap_or_else(|err| {
+                panic!(
+                    "Cannot finalize the colors file {}: {}",
+                    self.file.display(),
+                    err
+                )
+            });
+        }
     }
 }
 
@@ -209,3 +427,78 @@ impl ChunksWriter for ColorsFlushProcessing {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{colors_temp_file, validate_colors_file, ColorsFileError, ColorsSerializer};
+    use crate::DefaultColorsSerializer;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn interrupted_finalize_is_detected() {
+        let test_dir = TestDir::new("colors-finalize");
+        let file = test_dir.join("graph.colors.dat");
+
+        let serializer =
+            ColorsSerializer::<DefaultColorsSerializer>::new(&file, &["a".to_string()]);
+        serializer.serialize_colors(&[0]);
+        // Nothing replaces the colors file until it is finalized
+        assert!(!file.exists());
+        serializer.finalize().unwrap();
+        assert!(validate_colors_file::<DefaultColorsSerializer>(&file).is_ok());
+        assert!(!colors_temp_file(&file).exists());
+
+        // The process dies while appending, before finalizing the file
+        let serializer =
+            ColorsSerializer::<DefaultColorsSerializer>::reopen_append(&file, &["b".to_string()]);
+        serializer.serialize_colors(&[0, 1]);
+        std::mem::forget(serializer);
+
+        // The previous file is still complete, the partial one is detected as such
+        let previous_valid = validate_colors_file::<DefaultColorsSerializer>(&file).is_ok();
+        let partial = validate_colors_file::<DefaultColorsSerializer>(colors_temp_file(&file));
+
+        // A file truncated after being finalized is rejected
+        let truncated_file = test_dir.join("truncated.colors.dat");
+        let contents = std::fs::read(&file).unwrap();
+        std::fs::write(&truncated_file, &contents[..contents.len() - 1]).unwrap();
+        let truncated = validate_colors_file::<DefaultColorsSerializer>(&truncated_file);
+
+        assert!(previous_valid);
+        assert!(matches!(partial, Err(ColorsFileError::NotFinalized)));
+        assert!(matches!(
+            truncated,
+            Err(ColorsFileError::Truncated { expected_size, actual_size })
+                if actual_size + 1 == expected_size
+        ));
+    }
+
+    #[test]
+    fn panic_while_writing_keeps_the_previous_file() {
+        let test_dir = TestDir::new("colors-panic");
+        let file = test_dir.join("graph.colors.dat");
+
+        let serializer =
+            ColorsSerializer::<DefaultColorsSerializer>::new(&file, &["a".to_string()]);
+        serializer.serialize_colors(&[0]);
+        serializer.finalize().unwrap();
+        let previous_contents = std::fs::read(&file).unwrap();
+
+        // The serializer is dropped while unwinding from a panic in the middle of the writing
+        let result = std::panic::catch_unwind(|| {
+            let serializer = ColorsSerializer::<DefaultColorsSerializer>::reopen_append(
+                &file,
+                &["b".to_string()],
+            );
+            serializer.serialize_colors(&[0, 1]);
+            panic!("Interrupted while writing the colors");
+        });
+
+        let contents = std::fs::read(&file).unwrap();
+        let partial = validate_colors_file::<DefaultColorsSerializer>(colors_temp_file(&file));
+
+        assert!(result.is_err());
+        assert_eq!(contents, previous_contents);
+        assert!(matches!(partial, Err(ColorsFileError::NotFinalized)));
+    }
+}
@@ -4,7 +4,10 @@ use parallel_processor::buckets::writers::compressed_binary_writer::{
 };
 use parallel_processor::memory_data_size::MemoryDataSize;
 use parallel_processor::memory_fs::file::internal::MemoryFileMode;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::RwLock;
 use std::time::Duration;
 
 pub type BucketIndexType = u16;
@@ -46,11 +49,13 @@ pub const MAX_BUCKET_SIZE: u64 = 192 * 1024 * 1024;
 pub const MIN_BUCKETS_COUNT_LOG: usize = 10;
 pub const MAX_BUCKETS_COUNT_LOG: usize = 13;
 pub const MAX_RESPLIT_BUCKETS_COUNT_LOG: usize = 9;
+// Buckets processed in parallel for each thread: with fewer buckets than threads some threads
+// stay idle, with too many of them the per-bucket overhead dominates
+pub const MAX_BUCKETS_PER_THREAD: usize = 16;
+pub const SUGGESTED_BUCKETS_PER_THREAD: usize = 4;
 
 pub const MIN_BUCKET_CHUNKS_FOR_READING_THREAD: usize = 2;
 
-pub const USE_SECOND_BUCKET: bool = false;
-
 pub const RESPLITTING_MAX_K_M_DIFFERENCE: usize = 10;
 
 pub const MINIMUM_SUBBUCKET_KMERS_COUNT: usize = 1024 * 32;
@@ -64,6 +69,11 @@ pub const MAX_INTERMEDIATE_MAP_SIZE: u64 = 1024 * 1024 * 32;
 pub const READ_FLAG_INCL_BEGIN: u8 = 1 << 0;
 pub const READ_FLAG_INCL_END: u8 = 1 << 1;
 
+/// Maximum weight of an input. Each occurrence of a kmer adds the weight of its input to the kmer
+/// counter, so the bound keeps the counters far from their limit, and the colored builds, that
+/// store a color slot for each counted occurrence, from using a slot per unit of weight without end
+pub const MAX_INPUT_WEIGHT: usize = 1 << 16;
+
 pub const COLORS_SINGLE_BATCH_SIZE: u64 = 20000;
 pub const QUERIES_COUNT_MIN_BATCH: u64 = 1000;
 
@@ -85,9 +95,156 @@ impl SwapPriority {
 
 // Functions depending on global config parameters set at runtime
 pub static KEEP_FILES: AtomicBool = AtomicBool::new(false);
+/// Log the ending kmers that are not linked as a pair of opposite ends in the hashes sorting
+pub static DEBUG_LINKS_DIAGNOSTICS: AtomicBool = AtomicBool::new(false);
+/// Dump the minimizer and bucket of one every N reads of the bucketing to a file: (file, N)
+pub static DEBUG_MINIMIZERS_DUMP: RwLock<Option<(PathBuf, u64)>> = RwLock::new(None);
 pub static INTERMEDIATE_COMPRESSION_LEVEL_SLOW: AtomicU32 = AtomicU32::new(3);
 pub static INTERMEDIATE_COMPRESSION_LEVEL_FAST: AtomicU32 = AtomicU32::new(0);
+/// Compression level of the .gz and .lz4 final outputs
+pub static OUTPUT_COMPRESSION_LEVEL: AtomicU32 = AtomicU32::new(2);
+/// Write the .gz final outputs as BGZF, indexed for random access
+pub static OUTPUT_BGZF: AtomicBool = AtomicBool::new(false);
 pub static PREFER_MEMORY: AtomicBool = AtomicBool::new(false);
+/// Seed mixed into the minimizers hashes to randomize the buckets assignment (0 keeps the plain nthash values)
+pub static MINIMIZER_HASH_SEED: AtomicU64 = AtomicU64::new(0);
+
+/// Additional temporary directories (usually on different disks) where the buckets are spread
+pub static EXTRA_TEMP_DIRS: RwLock<Vec<PathBuf>> = RwLock::new(Vec::new());
+
+/// Read the soft-masked (lowercase) bases of the inputs as N, splitting the reads at the masked regions
+pub static SPLIT_ON_SOFTMASK: AtomicBool = AtomicBool::new(false);
+
+/// Pack the reference reads with 4 bits per base instead of 2, keeping their IUPAC ambiguity codes.
+/// Only the kmers without ambiguous bases are hashed, the storage of the reads is doubled
+pub static IUPAC_READS_PACKING: AtomicBool = AtomicBool::new(false);
+
+/// Read the FASTQ inputs as interleaved paired-end reads, giving the same pair id to each two consecutive records
+pub static INTERLEAVED_PAIRED_READS: AtomicBool = AtomicBool::new(false);
+
+/// Store the second level bucket of each read in the minimizer buckets, so that the kmers transform
+/// can split a bucket without recomputing the minimizers. It costs one extra byte per read in the
+/// intermediate files, so it helps only when the buckets are split often (large or skewed datasets)
+pub static USE_SECOND_BUCKET: AtomicBool = AtomicBool::new(false);
+
+/// Sync the buckets of a completed stage to disk before a checkpoint manifest records it, so that
+/// a crash cannot lose them after the checkpoint. Off by default as it slows down the checkpoints
+pub static SYNC_CHECKPOINTS: AtomicBool = AtomicBool::new(false);
+
+/// Store a CRC32 of each checkpointed bucket, verified when resuming from the checkpoint to detect
+/// the buckets corrupted on disk. Off by default, as it requires reading back all the buckets.
+/// Only the links compaction checkpoints are covered, the other intermediate buckets are not checked
+pub static CHECKPOINT_CHECKSUMS: AtomicBool = AtomicBool::new(false);
+
+/// Number of reads in each packet sent by the kmers transform reader to the processors,
+/// 0 derives it from KMERS_TRANSFORM_READS_CHUNKS_SIZE and k
+pub static KMERS_TRANSFORM_READS_BUFFER_CAPACITY: AtomicUsize = AtomicUsize::new(0);
+
+/// Bytes buffered by each thread for each bucket of the links written by the links compaction,
+/// 0 uses DEFAULT_PER_CPU_BUFFER_SIZE
+pub static LINKS_COMPACTION_LINKS_BUFFER_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Bytes buffered by each thread for each bucket of the unitigs mappings written by the links
+/// compaction, 0 uses DEFAULT_PER_CPU_BUFFER_SIZE
+pub static LINKS_COMPACTION_RESULTS_BUFFER_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Flush the final output file every this number of sequences, so that a partial output
+/// can be read while the assembly is still running (0 disables it)
+pub static OUTPUT_FLUSH_SEQUENCES: AtomicU64 = AtomicU64::new(0);
+
+/// Flush the final output file every this number of sequence bytes (0 disables it)
+pub static OUTPUT_FLUSH_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Allow more worker threads than the available cores, instead of clamping the threads counts
+pub static ALLOW_OVERSUBSCRIBE: AtomicBool = AtomicBool::new(false);
+
+/// Threads count actually used for the requested one, at most the available cores
+/// unless the oversubscription is allowed
+pub fn clamp_threads_count(requested: usize, available: usize, allow_oversubscribe: bool) -> usize {
+    if allow_oversubscribe {
+        requested.max(1)
+    } else {
+        requested.min(available).max(1)
+    }
+}
+
+/// Clamps the requested threads count to the cores detected on this machine, logging the clamp
+pub fn effective_threads_count(requested: usize) -> usize {
+    let available = std::thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(requested);
+    let threads_count = clamp_threads_count(
+        requested,
+        available,
+        ALLOW_OVERSUBSCRIBE.load(Ordering::Relaxed),
+    );
+    if threads_count < requested {
+        log::warn!(
+            "Using {} threads instead of {}, as only {} cores are available",
+            threads_count,
+            requested,
+            available
+        );
+    }
+    threads_count
+}
+
+/// Executors types that allocate their output packets from a pool
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PacketsPoolExecutor {
+    MinimizerBucketingReader,
+    KmersTransformReader,
+    KmersTransformProcessor,
+}
+
+/// Capacities of the packets pools of each executor type, None keeps the default one.
+/// An executor blocks when its pool is empty, waiting for the downstream executors to release
+/// a packet, so an undersized pool stalls the pipeline while a larger one uses more memory.
+/// The memory held by a pool is its capacity times the packets size:
+/// - minimizer bucketing reader: chunks of READ_INTERMEDIATE_CHUNKS_SIZE bytes, by default
+///   READ_INTERMEDIATE_QUEUE_MULTIPLIER per compute thread, so that each thread has a chunk ready
+/// - kmers transform reader: reads packets (see KMERS_TRANSFORM_READS_BUFFER_CAPACITY), by default
+///   half the maximum number of buckets in flight and at least two for each sub-bucket being read
+/// - kmers transform processor: the kmers hashmaps, as large as a sub-bucket, by default one for
+///   each compute thread (at least MAXIMUM_JIT_PROCESSED_BUCKETS) plus two
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PacketsPoolsCapacities {
+    pub minimizer_bucketing_reader: Option<usize>,
+    pub kmers_transform_reader: Option<usize>,
+    pub kmers_transform_processor: Option<usize>,
+}
+
+impl PacketsPoolsCapacities {
+    pub const fn new() -> Self {
+        Self {
+            minimizer_bucketing_reader: None,
+            kmers_transform_reader: None,
+            kmers_transform_processor: None,
+        }
+    }
+
+    /// Capacity of the pool of the given executor type, or default_capacity if not configured
+    pub fn capacity(&self, executor: PacketsPoolExecutor, default_capacity: usize) -> usize {
+        match executor {
+            PacketsPoolExecutor::MinimizerBucketingReader => self.minimizer_bucketing_reader,
+            PacketsPoolExecutor::KmersTransformReader => self.kmers_transform_reader,
+            PacketsPoolExecutor::KmersTransformProcessor => self.kmers_transform_processor,
+        }
+        .unwrap_or(default_capacity)
+        .max(1)
+    }
+}
+
+pub static PACKETS_POOLS_CAPACITIES: RwLock<PacketsPoolsCapacities> =
+    RwLock::new(PacketsPoolsCapacities::new());
+
+/// Capacity of the packets pool of an executor type, from the global configuration
+pub fn get_packets_pool_capacity(executor: PacketsPoolExecutor, default_capacity: usize) -> usize {
+    PACKETS_POOLS_CAPACITIES
+        .read()
+        .unwrap()
+        .capacity(executor, default_capacity)
+}
 
 pub fn get_memory_mode(swap_priority: usize) -> MemoryFileMode {
     if PREFER_MEMORY.load(Ordering::Relaxed) {
@@ -97,9 +254,313 @@ pub fn get_memory_mode(swap_priority: usize) -> MemoryFileMode {
     }
 }
 
+/// Verbosity of the messages printed by the pipeline stages
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LogLevel {
+    /// Only warnings and errors
+    Quiet,
+    /// Also the stages summaries and the progress of the buckets processing
+    #[default]
+    Default,
+    /// Also the detailed statistics of the buckets
+    Verbose,
+}
+
+impl LogLevel {
+    pub fn level_filter(self) -> log::LevelFilter {
+        match self {
+            LogLevel::Quiet => log::LevelFilter::Warn,
+            LogLevel::Default => log::LevelFilter::Info,
+            LogLevel::Verbose => log::LevelFilter::Debug,
+        }
+    }
+}
+
+struct ConsoleLogger;
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        match record.level() {
+            log::Level::Error => eprintln!("ERROR: {}", record.args()),
+            log::Level::Warn => eprintln!("WARNING: {}", record.args()),
+            _ => println!("{}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static CONSOLE_LOGGER: ConsoleLogger = ConsoleLogger;
+
+/// Prints the stages messages on stdout and the warnings and errors on stderr, with the given
+/// verbosity. If the application has already installed its own logger, both the logger and its
+/// maximum level are left untouched
+pub fn init_logging(level: LogLevel) {
+    if log::set_logger(&CONSOLE_LOGGER).is_ok() {
+        log::set_max_level(level.level_filter());
+    }
+}
+
 pub fn get_compression_level_info() -> CompressionLevelInfo {
     CompressionLevelInfo {
         fast_disk: INTERMEDIATE_COMPRESSION_LEVEL_FAST.load(Ordering::Relaxed),
         slow_disk: INTERMEDIATE_COMPRESSION_LEVEL_SLOW.load(Ordering::Relaxed),
     }
 }
+
+/// Run id prepended to the temporary files names, so that concurrent runs sharing a temporary
+/// directory do not overwrite each other files (empty for no prefix)
+pub static TEMP_FILES_PREFIX: RwLock<String> = RwLock::new(String::new());
+
+/// Sets the run id of the temporary files. The characters that could be confused with the buckets
+/// indexes extensions or with a path separator are replaced with underscores
+pub fn set_temp_files_prefix(prefix: &str) {
+    *TEMP_FILES_PREFIX.write().unwrap() = prefix
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+}
+
+/// Prepends the prefix to the file name of a temporary path
+pub fn prefixed_temp_file_path(path: PathBuf, prefix: &str) -> PathBuf {
+    match path.file_name() {
+        Some(file_name) if !prefix.is_empty() => {
+            let file_name = format!("{}_{}", prefix, file_name.to_string_lossy());
+            path.with_file_name(file_name)
+        }
+        _ => path,
+    }
+}
+
+/// Prepends the run id to the file name of a temporary path
+pub fn get_temp_file_path(path: PathBuf) -> PathBuf {
+    prefixed_temp_file_path(path, &TEMP_FILES_PREFIX.read().unwrap())
+}
+
+/// The base path of a buckets set, a file inside the run temporary directory with the run id
+/// prepended to its name. The bucket files are named from it as in io::generate_bucket_names
+pub fn get_temp_buckets_path(path: PathBuf) -> PathBuf {
+    get_temp_file_path(path)
+}
+
+/// Spreads the bucket files of a new buckets set across the run temporary directory and the
+/// extra temporary directories, and returns the unchanged base path of the set.
+/// Must be called before the buckets are created, see spread_temp_buckets_in
+pub fn spread_temp_buckets(base_path: PathBuf, buckets_count: usize) -> PathBuf {
+    spread_temp_buckets_in(&base_path, buckets_count, &EXTRA_TEMP_DIRS.read().unwrap());
+    base_path
+}
+
+/// Removes the bucket files written in the extra temporary directories whose link in the
+/// run temporary directory was already removed by the buckets reader
+pub fn remove_orphan_temp_buckets(run_dir: &Path) {
+    remove_orphan_temp_buckets_in(run_dir, &EXTRA_TEMP_DIRS.read().unwrap());
+}
+
+fn temp_bucket_file_path(base_path: &Path, index: usize) -> PathBuf {
+    base_path.with_extension(index.to_string())
+}
+
+#[cfg(unix)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Assigns the buckets round robin by index to the run temporary directory and to the
+/// extra_dirs, so that every set is spread across all the disks. MultiThreadBuckets creates
+/// all the bucket files of a set next to its base path, so each bucket assigned to an extra
+/// directory is a link in the run directory to a file in the extra directory, where the run
+/// temporary directory name is kept. The readers take the link paths and follow them, and
+/// if a link cannot be created the bucket is simply written in the run directory
+fn spread_temp_buckets_in(base_path: &Path, buckets_count: usize, extra_dirs: &[PathBuf]) {
+    let (file_name, run_dir) = match (base_path.file_name(), base_path.parent()) {
+        (Some(file_name), Some(run_dir)) if !extra_dirs.is_empty() => (file_name, run_dir),
+        _ => return,
+    };
+
+    let target_dirs: Vec<_> = extra_dirs
+        .iter()
+        .map(|extra_dir| match run_dir.file_name() {
+            Some(run_dir_name) => extra_dir.join(run_dir_name),
+            None => extra_dir.clone(),
+        })
+        .collect();
+    for target_dir in &target_dirs {
+        let _ = create_dir_all(target_dir);
+    }
+
+    for index in 0..buckets_count {
+        let link = temp_bucket_file_path(base_path, index);
+        // Removes the buckets left by a previous run of the same step
+        let _ = std::fs::remove_file(&link);
+
+        let dir_index = index % (target_dirs.len() + 1);
+        if dir_index == 0 {
+            continue;
+        }
+
+        let target = temp_bucket_file_path(&target_dirs[dir_index - 1].join(file_name), index);
+        let _ = std::fs::remove_file(&target);
+        let _ = symlink_file(&target, &link);
+    }
+}
+
+fn remove_orphan_temp_buckets_in(run_dir: &Path, extra_dirs: &[PathBuf]) {
+    let run_dir_name = match run_dir.file_name() {
+        Some(run_dir_name) => run_dir_name,
+        None => return,
+    };
+
+    for extra_dir in extra_dirs {
+        let entries = match std::fs::read_dir(extra_dir.join(run_dir_name)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            if std::fs::symlink_metadata(run_dir.join(entry.file_name())).is_err() {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        clamp_threads_count, effective_threads_count, remove_orphan_temp_buckets_in,
+        spread_temp_buckets_in, LogLevel, PacketsPoolExecutor, PacketsPoolsCapacities,
+    };
+    use log::Level;
+    use std::fs::create_dir_all;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn log_levels() {
+        // Checks the filters without installing them, as the maximum level is global to the process
+        // Quiet hides the per-bucket progress messages, but not the warnings
+        let quiet = LogLevel::Quiet.level_filter();
+        assert!(Level::Info > quiet);
+        assert!(Level::Warn <= quiet);
+
+        let default = LogLevel::Default.level_filter();
+        assert!(Level::Info <= default);
+        assert!(Level::Debug > default);
+
+        let verbose = LogLevel::Verbose.level_filter();
+        assert!(Level::Debug <= verbose);
+        assert!(Level::Trace > verbose);
+    }
+
+    #[test]
+    fn executors_get_their_pool_capacity() {
+        let capacities = PacketsPoolsCapacities {
+            minimizer_bucketing_reader: Some(64),
+            kmers_transform_reader: Some(8),
+            kmers_transform_processor: None,
+        };
+
+        assert_eq!(
+            capacities.capacity(PacketsPoolExecutor::MinimizerBucketingReader, 16),
+            64
+        );
+        assert_eq!(
+            capacities.capacity(PacketsPoolExecutor::KmersTransformReader, 16),
+            8
+        );
+        assert_eq!(
+            capacities.capacity(PacketsPoolExecutor::KmersTransformProcessor, 18),
+            18
+        );
+
+        // A pool always has at least one packet
+        let empty = PacketsPoolsCapacities {
+            kmers_transform_reader: Some(0),
+            ..PacketsPoolsCapacities::new()
+        };
+        assert_eq!(
+            empty.capacity(PacketsPoolExecutor::KmersTransformReader, 16),
+            1
+        );
+    }
+
+    #[test]
+    fn buckets_spread_across_temp_dirs() {
+        let base = TestDir::new("temp-dirs");
+        let run_dir = base.join("main").join("build_graph_test");
+        let extra_dirs = vec![base.join("extra0"), base.join("extra1")];
+        create_dir_all(&run_dir).unwrap();
+
+        let buckets_path = run_dir.join("bucket");
+        spread_temp_buckets_in(&buckets_path, 6, &extra_dirs);
+
+        // The buckets are written through their paths in the run directory
+        for index in 0..6 {
+            std::fs::write(
+                buckets_path.with_extension(index.to_string()),
+                [index as u8],
+            )
+            .unwrap();
+        }
+
+        let dirs = [
+            run_dir.clone(),
+            extra_dirs[0].join("build_graph_test"),
+            extra_dirs[1].join("build_graph_test"),
+        ];
+        for index in 0..6 {
+            let bucket_name = format!("bucket.{}", index);
+            for (dir_index, dir) in dirs.iter().enumerate() {
+                let bucket_file = std::fs::symlink_metadata(dir.join(&bucket_name));
+                assert_eq!(
+                    bucket_file.is_ok_and(|file| file.is_file()),
+                    dir_index == index % dirs.len()
+                );
+            }
+            assert_eq!(
+                std::fs::read(run_dir.join(&bucket_name)).unwrap(),
+                [index as u8]
+            );
+        }
+
+        // The files of the consumed buckets are removed with their links
+        std::fs::remove_file(buckets_path.with_extension("1")).unwrap();
+        remove_orphan_temp_buckets_in(&run_dir, &extra_dirs);
+        assert!(!dirs[1].join("bucket.1").exists());
+        assert!(dirs[1].join("bucket.4").exists());
+        assert!(dirs[2].join("bucket.2").exists());
+
+        // Without extra directories the buckets stay in the run directory
+        spread_temp_buckets_in(&run_dir.join("links"), 6, &[]);
+        assert_eq!(std::fs::read_dir(&dirs[1]).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn threads_count_clamped_to_cores() {
+        let cores = std::thread::available_parallelism().unwrap().get();
+        assert_eq!(effective_threads_count(10000), cores);
+        assert_eq!(effective_threads_count(1), 1);
+
+        assert_eq!(clamp_threads_count(10000, 4, false), 4);
+        assert_eq!(clamp_threads_count(10000, 4, true), 10000);
+        assert_eq!(clamp_threads_count(0, 4, false), 1);
+    }
+}
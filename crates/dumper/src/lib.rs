@@ -8,8 +8,8 @@ use colors::DefaultColorsSerializer;
 use config::{
     ColorIndexType, INTERMEDIATE_COMPRESSION_LEVEL_FAST, INTERMEDIATE_COMPRESSION_LEVEL_SLOW,
 };
-use io::compute_stats_from_input_blocks;
 use io::sequences_stream::general::GeneralSequenceBlockData;
+use io::{choose_buckets_count_log, compute_stats_from_input_blocks};
 use parallel_processor::memory_fs::MemoryFs;
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use pipeline::dumper_colormap_reading::colormap_reading;
@@ -57,7 +57,7 @@ pub fn dump_unitigs(
     let file_stats =
         compute_stats_from_input_blocks(&[GeneralSequenceBlockData::FASTA(graph_input.clone())]);
 
-    let buckets_count_log = buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log);
+    let buckets_count_log = choose_buckets_count_log(buckets_count_log, &file_stats, threads_count);
 
     if let Some(default_compression_level) = default_compression_level {
         INTERMEDIATE_COMPRESSION_LEVEL_SLOW.store(default_compression_level, Ordering::Relaxed);
@@ -2,8 +2,9 @@ use byteorder::ReadBytesExt;
 use colors::colors_manager::color_types::SingleKmerColorDataType;
 use colors::colors_manager::ColorsManager;
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES, MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
+    get_compression_level_info, get_memory_mode, spread_temp_buckets, SwapPriority,
+    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
 };
 use io::concurrent::temp_reads::extra_data::{SequenceExtraData, SequenceExtraDataOwned};
 use io::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
@@ -98,7 +99,7 @@ pub fn counters_sorting<CX: ColorsManager>(
     let color_buckets = if CX::COLORS_ENABLED {
         Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
             buckets_count,
-            colored_buckets_path,
+            spread_temp_buckets(colored_buckets_path, buckets_count),
             &(
                 get_memory_mode(SwapPriority::MinimizerBuckets),
                 MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
@@ -166,7 +166,7 @@ impl<CX: ColorsManager> MinimizerBucketingExecutor<DumperMinimizerBucketingExecu
                 if CX::COLORS_ENABLED
                     && (color.debug_count() != sequence.seq.len() - self.global_data.k + 1)
                 {
-                    println!(
+                    log::warn!(
                         "WARN: Sequence does not have enough colors, please check matching k size:\n{}\n{}",
                         std::str::from_utf8(sequence.ident_data).unwrap(),
                         std::str::from_utf8(sequence.seq).unwrap()
@@ -278,5 +278,6 @@ pub fn minimizer_bucketing<CX: ColorsManager>(
         None,
         CX::COLORS_ENABLED,
         k,
+        None,
     )
 }
@@ -0,0 +1,203 @@
+use criterion::measurement::WallTime;
+use criterion::*;
+use ggcat_hashes::*;
+use rand::{RngCore, SeedableRng};
+
+const SEQUENCE_LENGTH: usize = 100_000;
+const MEMBERSHIP_KMERS_COUNT: usize = 1000;
+const BENCH_K_VALUES: [usize; 8] = [7, 15, 21, 31, 47, 63, 95, 127];
+
+/// Random compressed bases (0..4)
+fn generate_compressed_bases(len: usize, seed: u64) -> Vec<u8> {
+    let mut rng = pcg_rand::Pcg32::seed_from_u64(seed);
+    (0..len).map(|_| (rng.next_u32() % 4) as u8).collect()
+}
+
+// Complement of a compressed base, A (0) <-> T (2), C (1) <-> G (3)
+fn reverse_complement(kmer: &[u8]) -> Vec<u8> {
+    kmer.iter().rev().map(|b| b ^ 2).collect()
+}
+
+fn kmer_hash<F: HashFunctionFactory>(kmer: &[u8]) -> F::HashTypeUnextendable {
+    F::new(kmer, kmer.len())
+        .iter()
+        .next()
+        .unwrap()
+        .to_unextendable()
+}
+
+/// Checks that a kmer and its reverse complement have the same hash for the canonical factories,
+/// and a different one for the forward factories unless the kmer is palindromic
+fn check_canonical_membership<F: HashFunctionFactory>(name: &str, k: usize, canonical: bool) {
+    F::initialize(k);
+    let mut bases = generate_compressed_bases(k * MEMBERSHIP_KMERS_COUNT, k as u64);
+    // A palindrome, if k is even
+    if k % 2 == 0 {
+        let half = bases[..k / 2].to_vec();
+        bases[k / 2..k].copy_from_slice(&reverse_complement(&half));
+    }
+
+    for kmer in bases.chunks_exact(k) {
+        let rc_kmer = reverse_complement(kmer);
+        let same_hash = kmer_hash::<F>(kmer) == kmer_hash::<F>(&rc_kmer);
+        assert_eq!(
+            same_hash,
+            canonical || kmer == rc_kmer.as_slice(),
+            "Wrong canonical membership for {} with k {}",
+            name,
+            k
+        );
+    }
+}
+
+fn bench_factory<F: HashFunctionFactory>(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    max_k: usize,
+    canonical: bool,
+    sequence: &[u8],
+) {
+    for k in BENCH_K_VALUES.into_iter().filter(|k| *k <= max_k) {
+        check_canonical_membership::<F>(name, k, canonical);
+
+        F::initialize(k);
+        group.bench_with_input(BenchmarkId::new(name, k), &k, |b, k| {
+            b.iter(|| {
+                for hash in F::new(sequence, *k).iter() {
+                    black_box(hash);
+                }
+            })
+        });
+    }
+}
+
+/// Throughput of the kmers hashing of a long sequence with each hash factory, at the k values
+/// each one supports, to compare them for a given k
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let sequence = generate_compressed_bases(SEQUENCE_LENGTH, 0);
+    let sequence = sequence.as_slice();
+
+    let mut group = c.benchmark_group("kmers-hashing");
+    group.throughput(Throughput::Elements(SEQUENCE_LENGTH as u64));
+
+    bench_factory::<cn_nthash::CanonicalNtHashIteratorFactory>(
+        &mut group,
+        "cn_nthash",
+        usize::MAX,
+        true,
+        sequence,
+    );
+    bench_factory::<fw_nthash::ForwardNtHashIteratorFactory>(
+        &mut group,
+        "fw_nthash",
+        usize::MAX,
+        false,
+        sequence,
+    );
+
+    bench_factory::<cn_seqhash::u16::CanonicalSeqHashFactory>(
+        &mut group,
+        "cn_seqhash_u16",
+        8,
+        true,
+        sequence,
+    );
+    bench_factory::<cn_seqhash::u32::CanonicalSeqHashFactory>(
+        &mut group,
+        "cn_seqhash_u32",
+        16,
+        true,
+        sequence,
+    );
+    bench_factory::<cn_seqhash::u64::CanonicalSeqHashFactory>(
+        &mut group,
+        "cn_seqhash_u64",
+        32,
+        true,
+        sequence,
+    );
+    bench_factory::<cn_seqhash::u128::CanonicalSeqHashFactory>(
+        &mut group,
+        "cn_seqhash_u128",
+        64,
+        true,
+        sequence,
+    );
+    bench_factory::<fw_seqhash::u16::ForwardSeqHashFactory>(
+        &mut group,
+        "fw_seqhash_u16",
+        8,
+        false,
+        sequence,
+    );
+    bench_factory::<fw_seqhash::u32::ForwardSeqHashFactory>(
+        &mut group,
+        "fw_seqhash_u32",
+        16,
+        false,
+        sequence,
+    );
+    bench_factory::<fw_seqhash::u64::ForwardSeqHashFactory>(
+        &mut group,
+        "fw_seqhash_u64",
+        32,
+        false,
+        sequence,
+    );
+    bench_factory::<fw_seqhash::u128::ForwardSeqHashFactory>(
+        &mut group,
+        "fw_seqhash_u128",
+        64,
+        false,
+        sequence,
+    );
+
+    bench_factory::<cn_rkhash::u32::CanonicalRabinKarpHashFactory>(
+        &mut group,
+        "cn_rkhash_u32",
+        usize::MAX,
+        true,
+        sequence,
+    );
+    bench_factory::<cn_rkhash::u64::CanonicalRabinKarpHashFactory>(
+        &mut group,
+        "cn_rkhash_u64",
+        usize::MAX,
+        true,
+        sequence,
+    );
+    bench_factory::<cn_rkhash::u128::CanonicalRabinKarpHashFactory>(
+        &mut group,
+        "cn_rkhash_u128",
+        usize::MAX,
+        true,
+        sequence,
+    );
+    bench_factory::<fw_rkhash::u32::ForwardRabinKarpHashFactory>(
+        &mut group,
+        "fw_rkhash_u32",
+        usize::MAX,
+        false,
+        sequence,
+    );
+    bench_factory::<fw_rkhash::u64::ForwardRabinKarpHashFactory>(
+        &mut group,
+        "fw_rkhash_u64",
+        usize::MAX,
+        false,
+        sequence,
+    );
+    bench_factory::<fw_rkhash::u128::ForwardRabinKarpHashFactory>(
+        &mut group,
+        "fw_rkhash_u128",
+        usize::MAX,
+        false,
+        sequence,
+    );
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+
+criterion_main!(benches);
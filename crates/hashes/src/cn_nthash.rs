@@ -1,7 +1,7 @@
 //! NtHash impl adapted from https://github.com/luizirber/nthash.git
 
 use crate::dummy_hasher::DummyHasherBuilder;
-use crate::nthash_base::{h, rc};
+use crate::nthash_base::{h, minimizer_hash_seed, rc, seeded_hash};
 use crate::{ExtendableHashTraitType, HashFunction, HashFunctionFactory, HashableSequence};
 use config::{BucketIndexType, MinimizerType};
 use dynamic_dispatch::dynamic_dispatch;
@@ -90,7 +90,7 @@ impl ExtendableHashTraitType for ExtCanonicalNtHash {
     type HashTypeUnextendable = u64;
     #[inline(always)]
     fn to_unextendable(self) -> Self::HashTypeUnextendable {
-        min(self.0, self.1)
+        seeded_hash(min(self.0, self.1), minimizer_hash_seed())
     }
 
     #[inline(always)]
@@ -238,11 +238,62 @@ fn cnc_nt_manual_roll_rev(
 
 #[cfg(test)]
 mod tests {
-    use crate::cn_nthash::CanonicalNtHashIteratorFactory;
-    use crate::tests::test_hash_function;
+    use crate::cn_nthash::{
+        CanonicalNtHashIterator, CanonicalNtHashIteratorFactory, ExtCanonicalNtHash,
+    };
+    use crate::nthash_base::seeded_hash;
+    use crate::tests::{rng, test_hash_function};
+    use crate::{HashFunction, HashFunctionFactory};
+    use rand::RngCore;
+    use std::cmp::min;
 
     #[test]
     fn cn_nthash_test() {
         test_hash_function::<CanonicalNtHashIteratorFactory>(&(32..512).collect::<Vec<_>>(), true);
     }
+
+    #[test]
+    fn cn_nthash_seeded_buckets() {
+        const M: usize = 15;
+        const BUCKETS_LOG: usize = 4;
+        const MMERS_COUNT: usize = 1024;
+
+        let mut rng = rng(42);
+
+        let fixed_bucket = |hash: u64| {
+            CanonicalNtHashIteratorFactory::get_bucket(0, BUCKETS_LOG, seeded_hash(hash, 0))
+        };
+
+        // Skewed input: all the m-mers fall in the same bucket with the fixed hash
+        let mut skewed_hashes = vec![];
+        while skewed_hashes.len() < MMERS_COUNT {
+            let mmer: Vec<u8> = (0..M)
+                .map(|_| b"ACGT"[(rng.next_u32() % 4) as usize])
+                .collect();
+            let ExtCanonicalNtHash(fw, rc) = CanonicalNtHashIterator::new(&mmer[..], M)
+                .unwrap()
+                .iter()
+                .next()
+                .unwrap();
+            let hash = min(fw, rc);
+            if fixed_bucket(hash) == 0 {
+                skewed_hashes.push(hash);
+            }
+        }
+
+        for seed in [0x1234_5678_9abc_def0, 0x0fed_cba9_8765_4321] {
+            let mut buckets = [0usize; 1 << BUCKETS_LOG];
+            for hash in &skewed_hashes {
+                buckets[CanonicalNtHashIteratorFactory::get_bucket(
+                    0,
+                    BUCKETS_LOG,
+                    seeded_hash(*hash, seed),
+                ) as usize] += 1;
+            }
+
+            // With the fixed hash the largest bucket holds all the m-mers
+            let max_bucket = *buckets.iter().max().unwrap();
+            assert!(max_bucket < MMERS_COUNT / 4, "Buckets: {:?}", buckets);
+        }
+    }
 }
@@ -1,7 +1,7 @@
 //! NtHash impl adapted from https://github.com/luizirber/nthash.git
 
 use crate::dummy_hasher::DummyHasherBuilder;
-use crate::nthash_base::h;
+use crate::nthash_base::{h, minimizer_hash_seed, seeded_hash};
 use crate::{ExtendableHashTraitType, HashFunction, HashFunctionFactory, HashableSequence};
 use config::{BucketIndexType, MinimizerType};
 use dynamic_dispatch::dynamic_dispatch;
@@ -76,7 +76,7 @@ impl ExtendableHashTraitType for ExtForwardNtHash {
     type HashTypeUnextendable = u64;
     #[inline(always)]
     fn to_unextendable(self) -> Self::HashTypeUnextendable {
-        self.0
+        seeded_hash(self.0, minimizer_hash_seed())
     }
 
     #[inline(always)]
@@ -17,6 +17,7 @@ pub mod rolling;
 
 use std::fmt::{Debug, Display};
 use std::hash::{BuildHasher, Hash};
+use std::ops::Range;
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -137,6 +138,18 @@ pub trait MinimizerHashFunctionFactory: HashFunctionFactory {
     ) -> MinimizerType;
 }
 
+/// Ordering key used to select the minimizer among the hashes of a window: the smallest full
+/// minimizer wins, and the ties (distinct hashes truncated to the same full minimizer) are broken
+/// by the smallest whole hash. The key depends only on the hash and not on its position, so the
+/// selection does not depend on the iteration order, and with a canonical hash a read and its
+/// reverse complement always select the same minimizer (and bucket)
+#[inline(always)]
+pub fn minimizer_order_key<H: MinimizerHashFunctionFactory>(
+    hash: H::HashTypeUnextendable,
+) -> (MinimizerType, H::HashTypeUnextendable) {
+    (H::get_full_minimizer(hash), hash)
+}
+
 pub trait HashFunction<HF: HashFunctionFactory> {
     type IteratorType: Iterator<Item = HF::HashTypeExtendable>;
     type EnumerableIteratorType: Iterator<Item = (usize, HF::HashTypeExtendable)>;
@@ -148,6 +161,39 @@ pub trait HashFunction<HF: HashFunctionFactory> {
 pub trait HashableSequence: Clone {
     unsafe fn get_unchecked_cbase(&self, index: usize) -> u8;
     fn bases_count(&self) -> usize;
+
+    /// Sequences that keep the IUPAC ambiguity codes have bases without a 2 bits value,
+    /// get_unchecked_cbase is meaningless for them and the kmers containing them must not be hashed
+    #[inline(always)]
+    unsafe fn is_unchecked_base_ambiguous(&self, _index: usize) -> bool {
+        false
+    }
+}
+
+/// Maximal ranges of a sequence without ambiguous bases that contain at least one kmer,
+/// each one can be hashed separately with the hash functions
+pub fn unambiguous_ranges<S: HashableSequence>(
+    seq: &S,
+    k: usize,
+) -> impl Iterator<Item = Range<usize>> + '_ {
+    let bases_count = seq.bases_count();
+    let mut position = 0;
+
+    std::iter::from_fn(move || {
+        while position < bases_count {
+            while position < bases_count && unsafe { seq.is_unchecked_base_ambiguous(position) } {
+                position += 1;
+            }
+            let start = position;
+            while position < bases_count && !unsafe { seq.is_unchecked_base_ambiguous(position) } {
+                position += 1;
+            }
+            if position - start >= k {
+                return Some(start..position);
+            }
+        }
+        None
+    })
 }
 
 impl HashableSequence for &[u8] {
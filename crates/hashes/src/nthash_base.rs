@@ -1,8 +1,31 @@
+use config::MINIMIZER_HASH_SEED;
+use std::sync::atomic::Ordering;
+
 pub const HASH_A: u64 = 0x3c8b_fbb3_95c6_0474;
 pub const HASH_C: u64 = 0x3193_c185_62a0_2b4c;
 pub const HASH_G: u64 = 0x2032_3ed0_8257_2324;
 pub const HASH_T: u64 = 0x2955_49f5_4be2_4456;
 
+/// Mixes the seed into a nthash value, so that the minimizers ordering and the buckets assignment
+/// change between different seeds. A zero seed leaves the original nthash values unchanged
+#[inline(always)]
+pub fn seeded_hash(hash: u64, seed: u64) -> u64 {
+    if seed == 0 {
+        return hash;
+    }
+
+    // Splitmix64 finalizer, a bijection that spreads the high bits into the low (bucket) bits
+    let mut x = hash ^ seed;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[inline(always)]
+pub fn minimizer_hash_seed() -> u64 {
+    MINIMIZER_HASH_SEED.load(Ordering::Relaxed)
+}
+
 #[inline(always)]
 pub fn h(c: u8) -> u64 {
     unsafe { *H_LOOKUP.get_unchecked(c as usize) }
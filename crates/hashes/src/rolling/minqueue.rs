@@ -1,4 +1,4 @@
-use crate::MinimizerHashFunctionFactory;
+use crate::{minimizer_order_key, MinimizerHashFunctionFactory};
 use std::cmp::min_by_key;
 use std::marker::PhantomData;
 
@@ -12,6 +12,9 @@ pub struct RollingMinQueue<H: MinimizerHashFunctionFactory> {
 }
 
 impl<H: MinimizerHashFunctionFactory> RollingMinQueue<H> {
+    /// Rolling minimum over windows of size consecutive hashes, the w of a (w, m) minimizers scheme.
+    /// The bucketing always uses the windows spanning a single kmer, so that the bucket of a kmer
+    /// depends only on the kmer itself and not on the read containing it
     pub fn new(size: usize) -> RollingMinQueue<H> {
         let capacity = size.next_power_of_two();
         let mut queue = Vec::with_capacity(capacity);
@@ -43,7 +46,7 @@ impl<H: MinimizerHashFunctionFactory> RollingMinQueue<H> {
                 self.queue.get_unchecked_mut(i).1 = min_by_key(
                     self.queue.get_unchecked_mut(i).1,
                     self.queue.get_unchecked_mut((i + 1) & self.capacity_mask).1,
-                    |x| H::get_full_minimizer(*x),
+                    |x| minimizer_order_key::<H>(*x),
                 );
             }
             i = i.wrapping_sub(1) & self.capacity_mask;
@@ -70,7 +73,7 @@ impl<H: MinimizerHashFunctionFactory> RollingMinQueue<H> {
             self.minimum = min_by_key(
                 self.minimum,
                 (x, (self.index + self.size) & self.capacity_mask),
-                |x| H::get_full_minimizer(x.0),
+                |x| minimizer_order_key::<H>(x.0),
             );
             self.index = (self.index + 1) & self.capacity_mask;
 
@@ -83,7 +86,7 @@ impl<H: MinimizerHashFunctionFactory> RollingMinQueue<H> {
                 self.queue
                     .get_unchecked_mut((self.index.wrapping_sub(self.size)) & self.capacity_mask)
                     .1,
-                |x| H::get_full_minimizer(*x),
+                |x| minimizer_order_key::<H>(*x),
             )
         })
     }
@@ -93,7 +96,7 @@ impl<H: MinimizerHashFunctionFactory> RollingMinQueue<H> {
 mod tests {
     use crate::fw_nthash::ForwardNtHashIteratorFactory;
     use crate::rolling::minqueue::RollingMinQueue;
-    use crate::MinimizerHashFunctionFactory;
+    use crate::{minimizer_order_key, MinimizerHashFunctionFactory};
     use rand::{RngCore, SeedableRng};
 
     #[test]
@@ -120,11 +123,79 @@ mod tests {
                 item,
                 *items[index..index + MINWINDOW]
                     .iter()
-                    .min_by_key(|x| ForwardNtHashIteratorFactory::get_full_minimizer(**x))
+                    .min_by_key(|x| minimizer_order_key::<ForwardNtHashIteratorFactory>(**x))
                     .unwrap(),
                 "Error slice: {:?}",
                 &items[index..index + MINWINDOW]
             );
         }
     }
+
+    #[test]
+    fn minimizer_ties_are_deterministic() {
+        // Distinct hashes truncated to the same full minimizer
+        let high: u64 = (7 << 32) | 5;
+        let low: u64 = (3 << 32) | 5;
+        let other: u64 = (1 << 32) | 9;
+        assert_eq!(
+            ForwardNtHashIteratorFactory::get_full_minimizer(high),
+            ForwardNtHashIteratorFactory::get_full_minimizer(low)
+        );
+
+        let items = [high, other, low, other, high, low, other, high];
+        let mut reversed = items;
+        reversed.reverse();
+
+        for window in [2, 3, 4] {
+            let mut queue = RollingMinQueue::<ForwardNtHashIteratorFactory>::new(window);
+            let forward: Vec<_> = queue.make_iter(items.iter().copied()).collect();
+
+            let mut queue = RollingMinQueue::<ForwardNtHashIteratorFactory>::new(window);
+            let mut backward: Vec<_> = queue.make_iter(reversed.iter().copied()).collect();
+            backward.reverse();
+
+            // The tie is broken by the whole hash, independently from the positions
+            for (index, minimizer) in forward.iter().enumerate() {
+                let window_items = &items[index..index + window];
+                if window_items.contains(&high) && window_items.contains(&low) {
+                    assert_eq!(*minimizer, low);
+                }
+            }
+            assert_eq!(forward, backward, "Window {}", window);
+        }
+    }
+
+    #[test]
+    fn minimizers_density() {
+        const SIZE: usize = 1000000;
+
+        let mut random = pcg_rand::Pcg64::seed_from_u64(7);
+        let items: Vec<_> = (0..SIZE).map(|_| random.next_u64()).collect();
+
+        for window in [4, 8, 16, 24] {
+            let mut queue = RollingMinQueue::<ForwardNtHashIteratorFactory>::new(window);
+
+            let mut last_minimizer = None;
+            let mut windows_count = 0;
+            let mut minimizers_count = 0;
+            for minimizer in queue.make_iter(items.iter().copied()) {
+                windows_count += 1;
+                if last_minimizer != Some(minimizer) {
+                    minimizers_count += 1;
+                    last_minimizer = Some(minimizer);
+                }
+            }
+
+            // The expected density of random minimizers is 2 / (w + 1)
+            let density = minimizers_count as f64 / windows_count as f64;
+            let expected = 2.0 / (window + 1) as f64;
+            assert!(
+                (density - expected).abs() < expected * 0.05,
+                "Window {}: density {} expected {}",
+                window,
+                density,
+                expected
+            );
+        }
+    }
 }
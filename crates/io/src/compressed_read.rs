@@ -6,6 +6,7 @@ use std::iter::FromIterator;
 use std::marker::PhantomData;
 use std::ops::Range;
 use std::slice::from_raw_parts;
+use std::sync::atomic::Ordering;
 use utils::Utils;
 
 #[derive(Copy, Clone)]
@@ -25,6 +26,83 @@ impl<'a> Debug for CompressedRead<'a> {
     }
 }
 
+/// Packing of the bases of the compressed reads. The 2 bits packing can store only A, C, G and T,
+/// the 4 bits packing keeps all the IUPAC codes, at the cost of twice the storage
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadsPacking {
+    TwoBits,
+    FourBitsIupac,
+}
+
+impl ReadsPacking {
+    pub const fn bases_per_byte(&self) -> usize {
+        match self {
+            ReadsPacking::TwoBits => 4,
+            ReadsPacking::FourBitsIupac => 2,
+        }
+    }
+
+    pub const fn packed_bytes_count(&self, bases_count: usize) -> usize {
+        (bases_count + self.bases_per_byte() - 1) / self.bases_per_byte()
+    }
+
+    /// The packing selected for the run in the global configuration, 2 bits by default
+    pub fn from_config() -> Self {
+        if config::IUPAC_READS_PACKING.load(Ordering::Relaxed) {
+            ReadsPacking::FourBitsIupac
+        } else {
+            ReadsPacking::TwoBits
+        }
+    }
+}
+
+// IUPAC codes as a bitmask of their bases: A = 1, C = 2, G = 4, T = 8, 0 is a gap
+const IUPAC_LETTERS: &[u8; 16] = b"-ACMGRSVTWYHKDBN";
+// 2 bits values of the unambiguous codes, the ambiguous ones have no valid value
+const IUPAC_TO_CBASE: [u8; 16] = [0, 0, 1, 0, 3, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+// Concrete bases of each code, in ACGT order
+const IUPAC_CODE_BASES: [&[u8]; 16] = [
+    b"", b"A", b"C", b"AC", b"G", b"AG", b"CG", b"ACG", b"T", b"AT", b"CT", b"ACT", b"GT", b"AGT",
+    b"CGT", b"ACGT",
+];
+
+/// 4 bits code of an IUPAC letter (U is read as T and . as a gap),
+/// None for the characters that are not nucleotide codes
+#[inline(always)]
+pub fn iupac_code(base: u8) -> Option<u8> {
+    match base.to_ascii_uppercase() {
+        b'U' => Some(8),
+        b'.' => Some(0),
+        base => IUPAC_LETTERS
+            .iter()
+            .position(|l| *l == base)
+            .map(|code| code as u8),
+    }
+}
+
+/// Concrete bases represented by an IUPAC letter, empty for the gaps and the other characters
+#[inline(always)]
+pub fn iupac_bases(base: u8) -> &'static [u8] {
+    iupac_code(base).map_or(b"", |code| IUPAC_CODE_BASES[code as usize])
+}
+
+/// 4 bits code of an IUPAC letter, the unknown characters are stored as N
+#[inline(always)]
+pub fn compress_iupac_base(base: u8) -> u8 {
+    iupac_code(base).unwrap_or(15)
+}
+
+#[inline(always)]
+pub fn decompress_iupac_base(code: u8) -> u8 {
+    IUPAC_LETTERS[code as usize]
+}
+
+/// The complement of a code swaps A with T and C with G, so it reverses its bits
+#[inline(always)]
+pub fn complement_iupac_base(code: u8) -> u8 {
+    (code.reverse_bits() >> 4) & 0xF
+}
+
 #[derive(Copy, Clone)]
 pub struct CompressedReadIndipendent {
     start: usize,
@@ -66,6 +144,131 @@ impl CompressedReadIndipendent {
     pub fn bases_count(&self) -> usize {
         self.size
     }
+
+    pub fn from_plain_packed(
+        plain: &[u8],
+        storage: &mut Vec<u8>,
+        packing: ReadsPacking,
+    ) -> CompressedReadIndipendent {
+        match packing {
+            ReadsPacking::TwoBits => Self::from_plain(plain, storage),
+            ReadsPacking::FourBitsIupac => Self::from_plain_iupac(plain, storage),
+        }
+    }
+
+    /// Stores the read with 4 bits for each base, keeping its IUPAC ambiguity codes
+    pub fn from_plain_iupac(plain: &[u8], storage: &mut Vec<u8>) -> CompressedReadIndipendent {
+        let start = storage.len() * 2;
+
+        IupacCompressedRead::compress_from_plain(plain, |b| {
+            storage.extend_from_slice(b);
+        });
+
+        CompressedReadIndipendent {
+            start,
+            size: plain.len(),
+        }
+    }
+
+    pub fn as_iupac_reference<'a>(&self, storage: &'a [u8]) -> IupacCompressedRead<'a> {
+        IupacCompressedRead::from_compressed_reads(storage, self.start, self.size)
+    }
+}
+
+/// Read packed with 4 bits for each base (ReadsPacking::FourBitsIupac), keeping the ambiguity codes
+#[derive(Copy, Clone)]
+pub struct IupacCompressedRead<'a> {
+    size: usize,
+    start: u8,
+    data: &'a [u8],
+}
+
+impl<'a> Debug for IupacCompressedRead<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.to_string())
+    }
+}
+
+impl<'a> IupacCompressedRead<'a> {
+    #[inline(always)]
+    fn compress_from_plain(seq: &[u8], mut writer: impl FnMut(&[u8])) {
+        for chunk in seq.chunks(8) {
+            let mut value = 0u32;
+            for aa in chunk.iter().rev() {
+                value = (value << 4) | compress_iupac_base(*aa) as u32;
+            }
+            writer(&value.to_le_bytes()[..(chunk.len() + 1) / 2])
+        }
+    }
+
+    #[inline]
+    pub fn from_compressed_reads(reads: &'a [u8], reads_offset: usize, bases_count: usize) -> Self {
+        let byte_start = reads_offset / 2;
+        let byte_offset = reads_offset % 2;
+        Self {
+            size: bases_count,
+            start: byte_offset as u8,
+            data: &reads[byte_start..byte_start + ((byte_offset + bases_count + 1) / 2)],
+        }
+    }
+
+    pub fn get_packed_slice(&self) -> &[u8] {
+        self.data
+    }
+
+    pub fn sub_slice(&self, range: Range<usize>) -> IupacCompressedRead<'a> {
+        assert!(range.start <= range.end && range.end <= self.size);
+        Self::from_compressed_reads(
+            self.data,
+            self.start as usize + range.start,
+            range.end - range.start,
+        )
+    }
+
+    /// 4 bits code of the base at the given index
+    #[inline(always)]
+    pub unsafe fn get_base_unchecked(&self, index: usize) -> u8 {
+        let index = index + self.start as usize;
+        (*self.data.get_unchecked(index / 2) >> ((index % 2) * 4)) & 0xF
+    }
+
+    pub fn as_bases_iter(&'a self) -> impl Iterator<Item = u8> + 'a {
+        (0..self.size).map(move |i| unsafe { decompress_iupac_base(self.get_base_unchecked(i)) })
+    }
+
+    pub fn as_reverse_complement_bases_iter(&'a self) -> impl Iterator<Item = u8> + 'a {
+        (0..self.size).rev().map(move |i| unsafe {
+            decompress_iupac_base(complement_iupac_base(self.get_base_unchecked(i)))
+        })
+    }
+
+    pub fn to_string(&self) -> String {
+        String::from_iter(
+            (0..self.size)
+                .map(|i| unsafe { decompress_iupac_base(self.get_base_unchecked(i)) as char }),
+        )
+    }
+
+    pub fn get_length(&self) -> usize {
+        self.size
+    }
+}
+
+impl<'a> HashableSequence for IupacCompressedRead<'a> {
+    #[inline(always)]
+    unsafe fn get_unchecked_cbase(&self, index: usize) -> u8 {
+        IUPAC_TO_CBASE[self.get_base_unchecked(index) as usize]
+    }
+
+    #[inline(always)]
+    fn bases_count(&self) -> usize {
+        self.size
+    }
+
+    #[inline(always)]
+    unsafe fn is_unchecked_base_ambiguous(&self, index: usize) -> bool {
+        !self.get_base_unchecked(index).is_power_of_two()
+    }
 }
 
 impl<'a> CompressedRead<'a> {
@@ -257,3 +460,71 @@ impl<'a> HashableSequence for CompressedRead<'a> {
         self.size
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{iupac_bases, CompressedReadIndipendent, ReadsPacking};
+    use hashes::{unambiguous_ranges, HashableSequence};
+    use utils::Utils;
+
+    const IUPAC_ALPHABET: &[u8] = b"ACGTRYSWKMBDHVN-";
+
+    #[test]
+    fn iupac_packing_round_trip() {
+        let mut storage = vec![];
+        let read = CompressedReadIndipendent::from_plain_packed(
+            IUPAC_ALPHABET,
+            &mut storage,
+            ReadsPacking::FourBitsIupac,
+        );
+        assert_eq!(
+            storage.len(),
+            ReadsPacking::FourBitsIupac.packed_bytes_count(IUPAC_ALPHABET.len())
+        );
+
+        let iupac_read = read.as_iupac_reference(&storage);
+        assert_eq!(iupac_read.to_string().as_bytes(), IUPAC_ALPHABET);
+        assert_eq!(
+            iupac_read
+                .as_reverse_complement_bases_iter()
+                .collect::<Vec<_>>(),
+            b"-NBDHVKMWSRYACGT"
+        );
+        // A slice that does not start at a byte boundary
+        assert_eq!(iupac_read.sub_slice(3..6).to_string(), "TRY");
+
+        // Lowercase and uracil are normalized, unknown characters become N
+        let mut storage = vec![];
+        let read = CompressedReadIndipendent::from_plain_iupac(b"acgu*", &mut storage);
+        assert_eq!(read.as_iupac_reference(&storage).to_string(), "ACGTN");
+    }
+
+    #[test]
+    fn iupac_letters_bases() {
+        assert_eq!(iupac_bases(b'a'), b"A");
+        assert_eq!(iupac_bases(b'U'), b"T");
+        assert_eq!(iupac_bases(b'R'), b"AG");
+        assert_eq!(iupac_bases(b'B'), b"CGT");
+        assert_eq!(iupac_bases(b'N'), b"ACGT");
+        // Gaps and unknown characters have no bases
+        assert_eq!(iupac_bases(b'-'), b"");
+        assert_eq!(iupac_bases(b'*'), b"");
+    }
+
+    #[test]
+    fn iupac_read_hashes_only_unambiguous_bases() {
+        let mut storage = vec![];
+        let read = CompressedReadIndipendent::from_plain_iupac(b"ACGTNACGRTTCAGA", &mut storage);
+        let iupac_read = read.as_iupac_reference(&storage);
+
+        assert_eq!(
+            unambiguous_ranges(&iupac_read, 4).collect::<Vec<_>>(),
+            [0..4, 9..15]
+        );
+        let cbases: Vec<_> = (0..4)
+            .map(|i| unsafe { iupac_read.get_unchecked_cbase(i) })
+            .collect();
+        let expected: Vec<_> = b"ACGT".iter().map(|b| Utils::compress_base(*b)).collect();
+        assert_eq!(cbases, expected);
+    }
+}
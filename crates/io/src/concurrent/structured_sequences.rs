@@ -3,15 +3,42 @@ use parking_lot::{Condvar, Mutex};
 use std::io::Write;
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 
+pub mod bgzf;
 pub mod binary;
+pub mod color_split;
 pub mod concurrent;
 pub mod fasta;
+pub mod fastg;
+pub mod output_stream;
+pub mod sharded;
 
 pub trait IdentSequenceWriter: SequenceExtraDataConsecutiveCompression + Sized {
     fn write_as_ident(&self, stream: &mut impl Write, extra_buffer: &Self::TempBuffer);
     fn write_as_gfa(&self, stream: &mut impl Write, extra_buffer: &Self::TempBuffer);
 
+    /// Writes the FASTG successors (:EDGE_j,EDGE_k') of the sequence, or of its reverse complement.
+    /// Only the links info has successors, so nothing is written by default
+    #[allow(unused_variables)]
+    fn write_as_fastg(
+        &self,
+        stream: &mut impl Write,
+        extra_buffer: &Self::TempBuffer,
+        reverse_complement: bool,
+    ) {
+    }
+
+    /// Appends the colors subsets of the sequence, that are resolved to its colors when the output
+    /// is split by color. Only the colors info has subsets, so nothing is appended by default
+    #[allow(unused_variables)]
+    fn write_colors_subsets(
+        &self,
+        subsets: &mut Vec<config::ColorIndexType>,
+        extra_buffer: &Self::TempBuffer,
+    ) {
+    }
+
     fn parse_as_ident<'a>(ident: &[u8], extra_buffer: &mut Self::TempBuffer) -> Option<Self>;
 
     fn parse_as_gfa<'a>(ident: &[u8], extra_buffer: &mut Self::TempBuffer) -> Option<Self>;
@@ -31,6 +58,69 @@ impl IdentSequenceWriter for () {
     }
 }
 
+#[inline(always)]
+fn complement_base(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        _ => base,
+    }
+}
+
+/// Checks if a sequence is not greater than its reverse complement
+pub fn is_canonical_sequence(sequence: &[u8]) -> bool {
+    for (base, rc_base) in sequence.iter().zip(sequence.iter().rev()) {
+        let rc_base = complement_base(*rc_base);
+        if *base != rc_base {
+            return *base < rc_base;
+        }
+    }
+    true
+}
+
+pub fn write_reverse_complement(sequence: &[u8], output: &mut Vec<u8>) {
+    output.clear();
+    output.extend(sequence.iter().rev().map(|b| complement_base(*b)));
+}
+
+/// The sequence or its reverse complement (written to rc_buffer), whichever is canonical,
+/// and whether it is the reverse complement
+pub fn canonical_sequence<'a>(sequence: &'a [u8], rc_buffer: &'a mut Vec<u8>) -> (&'a [u8], bool) {
+    if is_canonical_sequence(sequence) {
+        (sequence, false)
+    } else {
+        write_reverse_complement(sequence, rc_buffer);
+        (rc_buffer.as_slice(), true)
+    }
+}
+
+/// The strand of an output sequence relative to the input reads, only known for stranded
+/// (forward-only) graphs where a k-mer and its reverse complement are distinct
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SequenceStrand {
+    Forward,
+    Reverse,
+}
+
+impl SequenceStrand {
+    pub fn as_char(&self) -> char {
+        match self {
+            SequenceStrand::Forward => '+',
+            SequenceStrand::Reverse => '-',
+        }
+    }
+
+    /// The strand of the reverse complement of the sequence
+    pub fn flip(self) -> Self {
+        match self {
+            SequenceStrand::Forward => SequenceStrand::Reverse,
+            SequenceStrand::Reverse => SequenceStrand::Forward,
+        }
+    }
+}
+
 pub trait StructuredSequenceBackend<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>:
     Sync + Send
 {
@@ -42,6 +132,7 @@ pub trait StructuredSequenceBackend<ColorInfo: IdentSequenceWriter, LinksInfo: I
         buffer: &mut Self::SequenceTempBuffer,
         sequence_index: u64,
         sequence: &[u8],
+        strand: Option<SequenceStrand>,
 
         color_info: ColorInfo,
         links_info: LinksInfo,
@@ -52,9 +143,43 @@ pub trait StructuredSequenceBackend<ColorInfo: IdentSequenceWriter, LinksInfo: I
 
     fn flush_temp_buffer(&mut self, buffer: &mut Self::SequenceTempBuffer);
 
+    /// Flushes the data written so far to the output file, so that it can be read before finalize.
+    /// The temp buffers are self contained after flush_temp_buffer, so nothing is done by default
+    fn flush_output(&mut self) {}
+
     fn finalize(self);
 }
 
+/// Forces a flush of the output file after the given number of sequences or sequence bytes
+/// have been written, to make the partial output of long runs readable
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OutputFlushInterval {
+    pub sequences: Option<u64>,
+    pub bytes: Option<u64>,
+}
+
+impl OutputFlushInterval {
+    /// The interval set in the global configuration, disabled by default
+    pub fn from_config() -> Self {
+        let sequences = config::OUTPUT_FLUSH_SEQUENCES.load(Ordering::Relaxed);
+        let bytes = config::OUTPUT_FLUSH_BYTES.load(Ordering::Relaxed);
+        Self {
+            sequences: (sequences > 0).then_some(sequences),
+            bytes: (bytes > 0).then_some(bytes),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sequences.is_some() || self.bytes.is_some()
+    }
+
+    pub fn is_reached(&self, sequences: u64, bytes: u64) -> bool {
+        sequences > 0
+            && (self.sequences.is_some_and(|limit| sequences >= limit)
+                || self.bytes.is_some_and(|limit| bytes >= limit))
+    }
+}
+
 pub struct StructuredSequenceWriter<
     ColorInfo: IdentSequenceWriter,
     LinksInfo: IdentSequenceWriter,
@@ -63,6 +188,11 @@ pub struct StructuredSequenceWriter<
     current_index: Mutex<(u64, u64)>,
     backend: Mutex<Backend>,
     index_condvar: Condvar,
+    canonical_output: bool,
+    strand_tags: bool,
+    flush_interval: OutputFlushInterval,
+    // Sequences and bytes written since the last output flush
+    unflushed_output: Mutex<(u64, u64)>,
     _phantom: PhantomData<(ColorInfo, LinksInfo, Backend)>,
 }
 
@@ -77,10 +207,36 @@ impl<
             current_index: Mutex::new((0, 0)),
             backend: Mutex::new(backend),
             index_condvar: Condvar::new(),
+            canonical_output: false,
+            strand_tags: false,
+            flush_interval: OutputFlushInterval::default(),
+            unflushed_output: Mutex::new((0, 0)),
             _phantom: PhantomData,
         }
     }
 
+    /// Writes each sequence in its canonical orientation, the reverse complement is written if it
+    /// is smaller than the sequence. The links info must already refer to the output orientations
+    pub fn with_canonical_output(mut self, canonical_output: bool) -> Self {
+        self.canonical_output = canonical_output;
+        self
+    }
+
+    /// Tags each sequence with its strand relative to the input reads, that is reversed only if
+    /// the sequence was flipped by the canonical output. Meaningful only for stranded graphs
+    /// written with the canonical output, otherwise every sequence is tagged as forward
+    pub fn with_strand_tags(mut self, strand_tags: bool) -> Self {
+        self.strand_tags = strand_tags;
+        self
+    }
+
+    /// Periodically flushes the output file, so that the sequences written so far can be read
+    /// before the writer is finalized
+    pub fn with_flush_interval(mut self, flush_interval: OutputFlushInterval) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
     fn write_sequences<'a>(
         &self,
         buffer: &mut Backend::SequenceTempBuffer,
@@ -103,17 +259,35 @@ impl<
         };
 
         let mut current_index = start_sequence_index;
+        let mut sequences_bytes = 0;
+        let mut rc_sequence = vec![];
         // Write the sequences to a temporary buffer
         for (sequence, color_info, links_info) in sequences {
+            let flipped = self.canonical_output && !is_canonical_sequence(sequence);
+            let sequence = if flipped {
+                write_reverse_complement(sequence, &mut rc_sequence);
+                rc_sequence.as_slice()
+            } else {
+                sequence
+            };
+
+            let strand = self.strand_tags.then_some(if flipped {
+                SequenceStrand::Reverse
+            } else {
+                SequenceStrand::Forward
+            });
+
             Backend::write_sequence(
                 buffer,
                 current_index,
                 sequence,
+                strand,
                 color_info,
                 links_info,
                 extra_buffers,
             );
             current_index += 1;
+            sequences_bytes += sequence.len() as u64;
         }
 
         loop {
@@ -121,7 +295,21 @@ impl<
             let mut index_lock = self.current_index.lock();
 
             if index_lock.1 == start_sequence_index {
-                self.backend.lock().flush_temp_buffer(buffer);
+                let mut backend = self.backend.lock();
+                backend.flush_temp_buffer(buffer);
+                if self.flush_interval.is_enabled() {
+                    let mut unflushed_output = self.unflushed_output.lock();
+                    unflushed_output.0 += sequences_count;
+                    unflushed_output.1 += sequences_bytes;
+                    if self
+                        .flush_interval
+                        .is_reached(unflushed_output.0, unflushed_output.1)
+                    {
+                        backend.flush_output();
+                        *unflushed_output = (0, 0);
+                    }
+                }
+                drop(backend);
                 index_lock.1 += sequences_count;
 
                 self.index_condvar.notify_all();
@@ -0,0 +1,134 @@
+use config::DEFAULT_OUTPUT_BUFFER_SIZE;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Maximum uncompressed size of a block, as in htslib, so that a compressed block
+/// always fits the 64KB limit of the BGZF format
+const BGZF_BLOCK_DATA_SIZE: usize = 0xff00;
+const BGZF_MAX_BLOCK_SIZE: usize = 0x10000;
+const BGZF_HEADER_SIZE: usize = 18;
+const BGZF_FOOTER_SIZE: usize = 8;
+
+/// Empty block marking the end of a BGZF file
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Path of the .gzi index of a BGZF file (graph.fa.gz -> graph.fa.gz.gzi)
+pub fn bgzf_index_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut index_path = path.as_ref().as_os_str().to_owned();
+    index_path.push(".gzi");
+    PathBuf::from(index_path)
+}
+
+/// Gzip compatible writer that compresses the data in independent blocks (BGZF), writing
+/// next to it a .gzi index of the blocks offsets, so that the output can be accessed randomly
+pub struct BgzfWriter {
+    file: BufWriter<File>,
+    index_path: PathBuf,
+    level: Compression,
+    block: Vec<u8>,
+    compressed_block: Vec<u8>,
+    compressed_offset: u64,
+    uncompressed_offset: u64,
+    // Compressed and uncompressed offsets of the start of each block after the first one
+    blocks_offsets: Vec<(u64, u64)>,
+}
+
+impl BgzfWriter {
+    pub fn new(path: impl AsRef<Path>, level: u32) -> Self {
+        Self {
+            file: BufWriter::with_capacity(
+                DEFAULT_OUTPUT_BUFFER_SIZE,
+                File::create(&path).unwrap(),
+            ),
+            index_path: bgzf_index_path(&path),
+            level: Compression::new(level),
+            block: Vec::with_capacity(BGZF_BLOCK_DATA_SIZE),
+            compressed_block: Vec::with_capacity(BGZF_MAX_BLOCK_SIZE),
+            compressed_offset: 0,
+            uncompressed_offset: 0,
+            blocks_offsets: vec![],
+        }
+    }
+
+    fn deflate_block(&mut self, level: Compression) -> std::io::Result<()> {
+        self.compressed_block.clear();
+        let mut encoder = DeflateEncoder::new(&mut self.compressed_block, level);
+        encoder.write_all(&self.block)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> std::io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        self.deflate_block(self.level)?;
+        if BGZF_HEADER_SIZE + self.compressed_block.len() + BGZF_FOOTER_SIZE > BGZF_MAX_BLOCK_SIZE {
+            // Incompressible data, the stored block is only a few bytes larger than the data
+            self.deflate_block(Compression::none())?;
+        }
+
+        let block_size = BGZF_HEADER_SIZE + self.compressed_block.len() + BGZF_FOOTER_SIZE;
+        let mut crc = Crc::new();
+        crc.update(&self.block);
+
+        // Gzip header with the BC extra field storing the block size minus one
+        self.file.write_all(&[
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+        ])?;
+        self.file
+            .write_all(&((block_size - 1) as u16).to_le_bytes())?;
+        self.file.write_all(&self.compressed_block)?;
+        self.file.write_all(&crc.sum().to_le_bytes())?;
+        self.file
+            .write_all(&(self.block.len() as u32).to_le_bytes())?;
+
+        if self.compressed_offset > 0 {
+            self.blocks_offsets
+                .push((self.compressed_offset, self.uncompressed_offset));
+        }
+        self.compressed_offset += block_size as u64;
+        self.uncompressed_offset += self.block.len() as u64;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Writes the last block, the end of file marker and the .gzi index
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.write_block()?;
+        self.file.write_all(&BGZF_EOF)?;
+        self.file.flush()?;
+
+        let mut index = BufWriter::new(File::create(&self.index_path)?);
+        index.write_all(&(self.blocks_offsets.len() as u64).to_le_bytes())?;
+        for (compressed_offset, uncompressed_offset) in &self.blocks_offsets {
+            index.write_all(&compressed_offset.to_le_bytes())?;
+            index.write_all(&uncompressed_offset.to_le_bytes())?;
+        }
+        index.flush()
+    }
+}
+
+impl Write for BgzfWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = buf.len().min(BGZF_BLOCK_DATA_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..written]);
+        if self.block.len() == BGZF_BLOCK_DATA_SIZE {
+            self.write_block()?;
+        }
+        Ok(written)
+    }
+
+    /// Ends the current block, BGZF blocks can be smaller than the maximum size
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_block()?;
+        self.file.flush()
+    }
+}
@@ -1,4 +1,7 @@
-use crate::concurrent::structured_sequences::{IdentSequenceWriter, StructuredSequenceBackend};
+use crate::concurrent::structured_sequences::fasta::write_fasta_header;
+use crate::concurrent::structured_sequences::{
+    IdentSequenceWriter, SequenceStrand, StructuredSequenceBackend,
+};
 use crate::concurrent::temp_reads::creads_utils::{
     CompressedReadsBucketData, CompressedReadsBucketDataSerializer,
 };
@@ -8,13 +11,15 @@ use crate::concurrent::temp_reads::extra_data::{
 };
 use crate::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
 use byteorder::ReadBytesExt;
-use config::DEFAULT_PER_CPU_BUFFER_SIZE;
+use config::{DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT};
 use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBinaryReader;
 use parallel_processor::buckets::writers::compressed_binary_writer::{
     CompressedBinaryWriter, CompressedCheckpointSize, CompressionLevelInfo,
 };
 use parallel_processor::buckets::LockFreeBucket;
 use parallel_processor::memory_fs::file::internal::MemoryFileMode;
+use parallel_processor::memory_fs::RemoveFileMode;
 use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
@@ -157,6 +162,7 @@ impl<
         buffer: &mut Self::SequenceTempBuffer,
         sequence_index: u64,
         sequence: &[u8],
+        _strand: Option<SequenceStrand>,
 
         color_info: ColorInfo,
         links_info: LinksInfo,
@@ -184,3 +190,79 @@ impl<
         self.writer.finalize();
     }
 }
+
+/// Sequential reader of the sequences written by StructSeqBinaryWriter. The colors and links must
+/// not depend on the previous sequence, as the writer restarts the consecutive compression at each
+/// flushed buffer
+pub struct StructSeqBinaryReader<
+    ColorInfo: IdentSequenceWriter + SequenceExtraData,
+    LinksInfo: IdentSequenceWriter + SequenceExtraData,
+> {
+    reader: CompressedBinaryReader,
+    deserializer: CompressedReadsBucketDataSerializer<
+        (u64, ColorInfo, LinksInfo),
+        typenum::consts::U0,
+        false,
+    >,
+    read_buffer: Vec<u8>,
+    extra_buffers: (ColorInfo::TempBuffer, LinksInfo::TempBuffer),
+}
+
+impl<
+        ColorInfo: IdentSequenceWriter + SequenceExtraData,
+        LinksInfo: IdentSequenceWriter + SequenceExtraData,
+    > StructSeqBinaryReader<ColorInfo, LinksInfo>
+{
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            reader: CompressedBinaryReader::new(
+                path.as_ref(),
+                RemoveFileMode::Keep,
+                DEFAULT_PREFETCH_AMOUNT,
+            ),
+            deserializer: CompressedReadsBucketDataSerializer::new(),
+            read_buffer: vec![],
+            extra_buffers: <(u64, ColorInfo, LinksInfo)>::new_temp_buffer(),
+        }
+    }
+
+    /// Reads the bases of the next sequence, returning its index, colors and links, or None at
+    /// the end of the file. The colors and links refer to extra_buffers() until the next read
+    pub fn read_sequence(&mut self, sequence: &mut Vec<u8>) -> Option<(u64, ColorInfo, LinksInfo)> {
+        <(u64, ColorInfo, LinksInfo)>::clear_temp_buffer(&mut self.extra_buffers);
+        let (_, _, extra, read) = self.deserializer.read_from(
+            self.reader.get_single_stream(),
+            &mut self.read_buffer,
+            &mut self.extra_buffers,
+        )?;
+
+        sequence.clear();
+        sequence.extend(read.as_bases_iter());
+        Some(extra)
+    }
+
+    pub fn extra_buffers(&self) -> &(ColorInfo::TempBuffer, LinksInfo::TempBuffer) {
+        &self.extra_buffers
+    }
+
+    /// Reads the next sequence together with its header, in the same format written by FastaWriter.
+    /// Returns false at the end of the file
+    pub fn read_fasta_record(&mut self, header: &mut Vec<u8>, sequence: &mut Vec<u8>) -> bool {
+        let (index, color_info, links_info) = match self.read_sequence(sequence) {
+            Some(extra) => extra,
+            None => return false,
+        };
+
+        header.clear();
+        write_fasta_header(
+            header,
+            index,
+            sequence.len(),
+            None,
+            &color_info,
+            &links_info,
+            &self.extra_buffers,
+        );
+        true
+    }
+}
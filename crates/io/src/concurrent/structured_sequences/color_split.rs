@@ -0,0 +1,359 @@
+use crate::concurrent::structured_sequences::{
+    IdentSequenceWriter, SequenceStrand, StructuredSequenceBackend,
+};
+use config::ColorIndexType;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Default maximum number of per-color files kept open at the same time
+pub const MAX_OPEN_COLOR_FILES: usize = 256;
+
+/// Maps a colors subset to the colors it contains, appending them to the vector
+pub type SubsetColorsResolver =
+    Box<dyn FnMut(ColorIndexType, &mut Vec<ColorIndexType>) + Send + Sync>;
+
+/// Output file of each color, named <prefix>.<color name>.fa in the given directory.
+/// The characters of the names that are not safe in a file name are replaced with '_',
+/// colors whose names clash are distinguished by their index
+pub fn color_files_paths(output_dir: &Path, prefix: &str, color_names: &[String]) -> Vec<PathBuf> {
+    let mut used_names = HashSet::new();
+
+    color_names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let mut file_name: String = name
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            if !used_names.insert(file_name.clone()) {
+                file_name = format!("{}.{}", file_name, index);
+            }
+            output_dir.join(format!("{}.{}.fa", prefix, file_name))
+        })
+        .collect()
+}
+
+/// Writer of one file for each color, that keeps at most max_open_files open at the same time,
+/// closing the least recently used one when needed and reopening it in append mode
+pub struct ColorFilesWriter {
+    paths: Vec<PathBuf>,
+    open_files: HashMap<usize, BufWriter<File>>,
+    // Least recently opened file first
+    open_order: VecDeque<usize>,
+    max_open_files: usize,
+    created: Vec<bool>,
+}
+
+impl ColorFilesWriter {
+    pub fn new(paths: Vec<PathBuf>, max_open_files: usize) -> Self {
+        Self {
+            created: vec![false; paths.len()],
+            paths,
+            open_files: HashMap::new(),
+            open_order: VecDeque::new(),
+            max_open_files: max_open_files.max(1),
+        }
+    }
+
+    fn get_file(&mut self, color: usize) -> std::io::Result<&mut BufWriter<File>> {
+        if !self.open_files.contains_key(&color) {
+            if self.open_files.len() >= self.max_open_files {
+                if let Some(oldest) = self.open_order.pop_front() {
+                    if let Some(mut file) = self.open_files.remove(&oldest) {
+                        file.flush()?;
+                    }
+                }
+            }
+
+            // The first open truncates any previous output, the following ones append to it
+            let file = if self.created[color] {
+                OpenOptions::new().append(true).open(&self.paths[color])?
+            } else {
+                self.created[color] = true;
+                File::create(&self.paths[color])?
+            };
+            self.open_files.insert(color, BufWriter::new(file));
+            self.open_order.push_back(color);
+        }
+        Ok(self.open_files.get_mut(&color).unwrap())
+    }
+
+    /// Writes the unitig to the file of each of its colors
+    pub fn write_unitig(
+        &mut self,
+        index: u64,
+        sequence: &[u8],
+        colors: &[ColorIndexType],
+    ) -> std::io::Result<()> {
+        for color in colors {
+            let file = self.get_file(*color as usize)?;
+            writeln!(file, ">{} LN:i:{}", index, sequence.len())?;
+            file.write_all(sequence)?;
+            file.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the open files and creates the empty files of the colors without unitigs
+    pub fn finalize(mut self) -> std::io::Result<Vec<PathBuf>> {
+        for (_, mut file) in self.open_files.drain() {
+            file.flush()?;
+        }
+        for (path, created) in self.paths.iter().zip(self.created.iter()) {
+            if !created {
+                File::create(path)?;
+            }
+        }
+        Ok(self.paths)
+    }
+}
+
+/// Splitting of the sequences by color, resolving their colors subsets
+pub struct ColorSplit {
+    files: ColorFilesWriter,
+    resolver: SubsetColorsResolver,
+}
+
+impl ColorSplit {
+    pub fn new(files: ColorFilesWriter, resolver: SubsetColorsResolver) -> Self {
+        Self { files, resolver }
+    }
+}
+
+/// The sequences of a flushed batch, with their colors subsets, kept until the batch is written
+pub struct ColorSplitTempBuffer {
+    // Sequence index, range in sequences and range in subsets
+    records: Vec<(u64, Range<usize>, Range<usize>)>,
+    sequences: Vec<u8>,
+    subsets: Vec<ColorIndexType>,
+}
+
+/// Backend that also writes each sequence to the file of each of its colors (see ColorFilesWriter),
+/// while the sequences are written to the output, without reading back the output graph.
+/// Without a split it only forwards the sequences to the output backend
+pub struct ColorSplitWriter<
+    ColorInfo: IdentSequenceWriter,
+    LinksInfo: IdentSequenceWriter,
+    Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+> {
+    backend: Backend,
+    split: Option<ColorSplit>,
+    colors_buffer: Vec<ColorIndexType>,
+    _phantom: PhantomData<(ColorInfo, LinksInfo)>,
+}
+
+unsafe impl<
+        ColorInfo: IdentSequenceWriter,
+        LinksInfo: IdentSequenceWriter,
+        Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+    > Send for ColorSplitWriter<ColorInfo, LinksInfo, Backend>
+{
+}
+
+unsafe impl<
+        ColorInfo: IdentSequenceWriter,
+        LinksInfo: IdentSequenceWriter,
+        Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+    > Sync for ColorSplitWriter<ColorInfo, LinksInfo, Backend>
+{
+}
+
+impl<
+        ColorInfo: IdentSequenceWriter,
+        LinksInfo: IdentSequenceWriter,
+        Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+    > ColorSplitWriter<ColorInfo, LinksInfo, Backend>
+{
+    pub fn new(backend: Backend, split: Option<ColorSplit>) -> Self {
+        Self {
+            backend,
+            split,
+            colors_buffer: vec![],
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<
+        ColorInfo: IdentSequenceWriter,
+        LinksInfo: IdentSequenceWriter,
+        Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+    > StructuredSequenceBackend<ColorInfo, LinksInfo>
+    for ColorSplitWriter<ColorInfo, LinksInfo, Backend>
+{
+    type SequenceTempBuffer = (Backend::SequenceTempBuffer, ColorSplitTempBuffer);
+
+    fn alloc_temp_buffer() -> Self::SequenceTempBuffer {
+        (
+            Backend::alloc_temp_buffer(),
+            ColorSplitTempBuffer {
+                records: vec![],
+                sequences: vec![],
+                subsets: vec![],
+            },
+        )
+    }
+
+    fn write_sequence(
+        buffer: &mut Self::SequenceTempBuffer,
+        sequence_index: u64,
+        sequence: &[u8],
+        strand: Option<SequenceStrand>,
+
+        color_info: ColorInfo,
+        links_info: LinksInfo,
+        extra_buffers: &(ColorInfo::TempBuffer, LinksInfo::TempBuffer),
+    ) {
+        let split_buffer = &mut buffer.1;
+        let sequence_start = split_buffer.sequences.len();
+        let subsets_start = split_buffer.subsets.len();
+        split_buffer.sequences.extend_from_slice(sequence);
+        color_info.write_colors_subsets(&mut split_buffer.subsets, &extra_buffers.0);
+        split_buffer.records.push((
+            sequence_index,
+            sequence_start..split_buffer.sequences.len(),
+            subsets_start..split_buffer.subsets.len(),
+        ));
+
+        Backend::write_sequence(
+            &mut buffer.0,
+            sequence_index,
+            sequence,
+            strand,
+            color_info,
+            links_info,
+            extra_buffers,
+        );
+    }
+
+    fn get_path(&self) -> PathBuf {
+        self.backend.get_path()
+    }
+
+    fn flush_temp_buffer(&mut self, buffer: &mut Self::SequenceTempBuffer) {
+        self.backend.flush_temp_buffer(&mut buffer.0);
+
+        let split_buffer = &mut buffer.1;
+        if let Some(split) = &mut self.split {
+            for (index, sequence, subsets) in split_buffer.records.iter() {
+                self.colors_buffer.clear();
+                for subset in &split_buffer.subsets[subsets.clone()] {
+                    (split.resolver)(*subset, &mut self.colors_buffer);
+                }
+                self.colors_buffer.sort_unstable();
+                self.colors_buffer.dedup();
+
+                split
+                    .files
+                    .write_unitig(
+                        *index,
+                        &split_buffer.sequences[sequence.clone()],
+                        &self.colors_buffer,
+                    )
+                    .unwrap_or_else(|err| {
+                        panic!("Cannot write the per-color unitigs files: {}", err)
+                    });
+            }
+        }
+        split_buffer.records.clear();
+        split_buffer.sequences.clear();
+        split_buffer.subsets.clear();
+    }
+
+    fn flush_output(&mut self) {
+        self.backend.flush_output();
+    }
+
+    fn finalize(self) {
+        self.backend.finalize();
+        if let Some(split) = self.split {
+            split
+                .files
+                .finalize()
+                .unwrap_or_else(|err| panic!("Cannot write the per-color unitigs files: {}", err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{color_files_paths, ColorFilesWriter, ColorSplit, ColorSplitWriter};
+    use crate::concurrent::structured_sequences::fasta::FastaWriter;
+    use crate::concurrent::structured_sequences::StructuredSequenceBackend;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn unitigs_are_written_to_each_of_their_colors() {
+        let test_dir = TestDir::new("color-split");
+
+        let color_names = ["a".to_string(), "b/1".to_string(), "c".to_string()];
+        let paths = color_files_paths(test_dir.path(), "graph", &color_names);
+        assert_eq!(paths[1], test_dir.join("graph.b_1.fa"));
+
+        // Only one file open at a time, so the files are reopened when needed
+        let mut writer = ColorFilesWriter::new(paths, 1);
+        let unitigs: [(&[u8], &[u32]); 4] = [
+            (b"ACGTAC", &[0]),
+            (b"TACGG", &[0, 1]),
+            (b"CCGTAC", &[1, 2]),
+            (b"GGATC", &[2]),
+        ];
+        for (index, (sequence, colors)) in unitigs.iter().enumerate() {
+            writer.write_unitig(index as u64, sequence, colors).unwrap();
+        }
+        let paths = writer.finalize().unwrap();
+
+        let contents: Vec<_> = paths
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect();
+
+        assert_eq!(
+            contents,
+            [
+                ">0 LN:i:6\nACGTAC\n>1 LN:i:5\nTACGG\n",
+                ">1 LN:i:5\nTACGG\n>2 LN:i:6\nCCGTAC\n",
+                ">2 LN:i:6\nCCGTAC\n>3 LN:i:5\nGGATC\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn uncolored_sequences_are_only_forwarded() {
+        let test_dir = TestDir::new("color-split-forward");
+        let output_file = test_dir.join("graph.fa");
+        let color_file = test_dir.join("graph.a.fa");
+
+        type Writer = ColorSplitWriter<(), (), FastaWriter<(), ()>>;
+
+        // The sequences without colors subsets are not written to any color file
+        let mut writer = Writer::new(
+            FastaWriter::new_plain(&output_file),
+            Some(ColorSplit::new(
+                ColorFilesWriter::new(vec![color_file.clone()], 1),
+                Box::new(|_, _| unreachable!()),
+            )),
+        );
+        let mut buffer = Writer::alloc_temp_buffer();
+        Writer::write_sequence(&mut buffer, 0, b"ACGT", None, (), (), &((), ()));
+        writer.flush_temp_buffer(&mut buffer);
+        writer.finalize();
+
+        let output = std::fs::read_to_string(&output_file).unwrap();
+        let color_output = std::fs::read_to_string(&color_file).unwrap();
+
+        assert_eq!(output, ">0 LN:i:4\nACGT\n");
+        assert_eq!(color_output, "");
+    }
+}
@@ -83,7 +83,13 @@ impl<
             result = Some(self.flush());
             self.current_index = Some(sequence_index);
         }
-        else if self.auto_flush && Self::will_overflow(&self.seq_buf, sequence.len()) {
+        else if self.auto_flush
+            && (Self::will_overflow(&self.seq_buf, sequence.len())
+                || self
+                    .target
+                    .flush_interval
+                    .is_reached(self.sequences.len() as u64, self.seq_buf.len() as u64))
+        {
             result = Some(self.flush());
         }
 
@@ -1,15 +1,112 @@
-use crate::concurrent::structured_sequences::{IdentSequenceWriter, StructuredSequenceBackend};
-use config::{DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PER_CPU_BUFFER_SIZE};
-use flate2::write::GzEncoder;
-use flate2::Compression;
-use lz4::{BlockMode, BlockSize, ContentChecksum};
+use crate::concurrent::structured_sequences::output_stream::OutputStream;
+use crate::concurrent::structured_sequences::{
+    IdentSequenceWriter, SequenceStrand, StructuredSequenceBackend,
+};
+use config::DEFAULT_PER_CPU_BUFFER_SIZE;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 
+/// Path of the .fai index of a FASTA file (graph.fa.gz -> graph.fa.gz.fai)
+pub fn fasta_index_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut index_path = path.as_ref().as_os_str().to_owned();
+    index_path.push(".fai");
+    PathBuf::from(index_path)
+}
+
+/// Builds the samtools .fai index of the written records, parsing the uncompressed output
+/// as it is flushed. Each sequence is written on a single line
+struct FastaIndexBuilder {
+    index: BufWriter<File>,
+    offset: u64,
+    in_header: bool,
+    name: Vec<u8>,
+    name_complete: bool,
+    sequence_offset: u64,
+    sequence_length: u64,
+}
+
+impl FastaIndexBuilder {
+    fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            index: BufWriter::new(File::create(fasta_index_path(path)).unwrap()),
+            offset: 0,
+            in_header: false,
+            name: vec![],
+            name_complete: false,
+            sequence_offset: 0,
+            sequence_length: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.offset += 1;
+            if self.in_header {
+                match byte {
+                    b'\n' => {
+                        self.in_header = false;
+                        self.sequence_offset = self.offset;
+                        self.sequence_length = 0;
+                    }
+                    b' ' | b'\t' => self.name_complete = true,
+                    _ if !self.name_complete => self.name.push(byte),
+                    _ => {}
+                }
+            } else {
+                match byte {
+                    b'>' if self.sequence_length == 0 => {
+                        self.in_header = true;
+                        self.name.clear();
+                        self.name_complete = false;
+                    }
+                    b'\n' => {
+                        self.index.write_all(&self.name).unwrap();
+                        writeln!(
+                            self.index,
+                            "\t{}\t{}\t{}\t{}",
+                            self.sequence_length,
+                            self.sequence_offset,
+                            self.sequence_length,
+                            self.sequence_length + 1
+                        )
+                        .unwrap();
+                        self.sequence_length = 0;
+                    }
+                    _ => self.sequence_length += 1,
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) {
+        self.index.flush().unwrap();
+    }
+}
+
+/// Writes the header of a sequence, without the trailing newline, in the format
+/// ><index> LN:i:<len> [ST:A:<strand>] followed by its colors and links
+pub fn write_fasta_header<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>(
+    buffer: &mut Vec<u8>,
+    sequence_index: u64,
+    sequence_len: usize,
+    strand: Option<SequenceStrand>,
+    color_info: &ColorInfo,
+    links_info: &LinksInfo,
+    extra_buffers: &(ColorInfo::TempBuffer, LinksInfo::TempBuffer),
+) {
+    write!(buffer, ">{} LN:i:{}", sequence_index, sequence_len).unwrap();
+    if let Some(strand) = strand {
+        write!(buffer, " ST:A:{}", strand.as_char()).unwrap();
+    }
+    color_info.write_as_ident(buffer, &extra_buffers.0);
+    links_info.write_as_ident(buffer, &extra_buffers.1);
+}
+
 pub struct FastaWriter<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter> {
-    writer: Box<dyn Write>,
+    writer: Option<OutputStream>,
+    index: Option<FastaIndexBuilder>,
     path: PathBuf,
     _phantom: PhantomData<(ColorInfo, LinksInfo)>,
 }
@@ -27,53 +124,37 @@ unsafe impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter> Sync
 impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
     FastaWriter<ColorInfo, LinksInfo>
 {
-    pub fn new_compressed_gzip(path: impl AsRef<Path>, level: u32) -> Self {
-        let compress_stream = GzEncoder::new(
-            BufWriter::with_capacity(DEFAULT_OUTPUT_BUFFER_SIZE, File::create(&path).unwrap()),
-            Compression::new(level),
-        );
-
+    fn with_stream(path: impl AsRef<Path>, writer: OutputStream) -> Self {
+        // A BGZF output can be accessed randomly, index its records
+        let index = matches!(writer, OutputStream::Bgzf(_)).then(|| FastaIndexBuilder::new(&path));
         FastaWriter {
-            writer: Box::new(BufWriter::with_capacity(
-                DEFAULT_OUTPUT_BUFFER_SIZE,
-                compress_stream,
-            )),
+            writer: Some(writer),
+            index,
             path: path.as_ref().to_path_buf(),
             _phantom: PhantomData,
         }
     }
 
+    pub fn new_compressed_gzip(path: impl AsRef<Path>, level: u32) -> Self {
+        Self::with_stream(&path, OutputStream::new_compressed_gzip(&path, level))
+    }
+
     pub fn new_compressed_lz4(path: impl AsRef<Path>, level: u32) -> Self {
-        let compress_stream = lz4::EncoderBuilder::new()
-            .level(level)
-            .checksum(ContentChecksum::NoChecksum)
-            .block_mode(BlockMode::Linked)
-            .block_size(BlockSize::Max1MB)
-            .build(BufWriter::with_capacity(
-                DEFAULT_OUTPUT_BUFFER_SIZE,
-                File::create(&path).unwrap(),
-            ))
-            .unwrap();
+        Self::with_stream(&path, OutputStream::new_compressed_lz4(&path, level))
+    }
 
-        FastaWriter {
-            writer: Box::new(BufWriter::with_capacity(
-                DEFAULT_OUTPUT_BUFFER_SIZE,
-                compress_stream,
-            )),
-            path: path.as_ref().to_path_buf(),
-            _phantom: PhantomData,
-        }
+    /// Writes the output as BGZF, with a .gzi index of the blocks and a .fai index of the sequences
+    pub fn new_compressed_bgzf(path: impl AsRef<Path>, level: u32) -> Self {
+        Self::with_stream(&path, OutputStream::new_compressed_bgzf(&path, level))
     }
 
     pub fn new_plain(path: impl AsRef<Path>) -> Self {
-        FastaWriter {
-            writer: Box::new(BufWriter::with_capacity(
-                DEFAULT_OUTPUT_BUFFER_SIZE,
-                File::create(&path).unwrap(),
-            )),
-            path: path.as_ref().to_path_buf(),
-            _phantom: PhantomData,
-        }
+        Self::with_stream(&path, OutputStream::new_plain(&path))
+    }
+
+    /// Compresses the output with gzip or lz4 if the path ends with .gz or .lz4
+    pub fn new_from_extension(path: impl AsRef<Path>, level: u32) -> Self {
+        Self::with_stream(&path, OutputStream::from_extension(&path, level))
     }
 }
 
@@ -90,14 +171,21 @@ impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
         buffer: &mut Self::SequenceTempBuffer,
         sequence_index: u64,
         sequence: &[u8],
+        strand: Option<SequenceStrand>,
 
         color_info: ColorInfo,
         links_info: LinksInfo,
         extra_buffers: &(ColorInfo::TempBuffer, LinksInfo::TempBuffer),
     ) {
-        write!(buffer, ">{} LN:i:{}", sequence_index, sequence.len()).unwrap();
-        color_info.write_as_ident(buffer, &extra_buffers.0);
-        links_info.write_as_ident(buffer, &extra_buffers.1);
+        write_fasta_header(
+            buffer,
+            sequence_index,
+            sequence.len(),
+            strand,
+            &color_info,
+            &links_info,
+            extra_buffers,
+        );
         buffer.extend_from_slice(b"\n");
         buffer.extend_from_slice(sequence);
         buffer.extend_from_slice(b"\n");
@@ -108,17 +196,233 @@ impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
     }
 
     fn flush_temp_buffer(&mut self, buffer: &mut Self::SequenceTempBuffer) {
-        self.writer.write_all(buffer).unwrap();
+        self.writer.as_mut().unwrap().write_all(buffer).unwrap();
+        if let Some(index) = &mut self.index {
+            index.update(buffer);
+        }
         buffer.clear();
     }
 
-    fn finalize(self) {}
+    fn flush_output(&mut self) {
+        self.writer.as_mut().unwrap().flush().unwrap();
+    }
+
+    fn finalize(mut self) {
+        if let Some(writer) = self.writer.take() {
+            writer.finish();
+        }
+        if let Some(index) = self.index.take() {
+            index.finish();
+        }
+    }
 }
 
 impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter> Drop
     for FastaWriter<ColorInfo, LinksInfo>
 {
     fn drop(&mut self) {
-        self.writer.flush().unwrap();
+        // The compressed streams are complete only after their trailer is written
+        if let Some(writer) = self.writer.take() {
+            writer.finish();
+        }
+        if let Some(index) = self.index.take() {
+            index.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FastaWriter;
+    use crate::concurrent::structured_sequences::concurrent::FastaWriterConcurrentBuffer;
+    use crate::concurrent::structured_sequences::{OutputFlushInterval, StructuredSequenceWriter};
+    use std::io::{Read, Seek, SeekFrom};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn stranded_unitigs_keep_their_strand() {
+        let test_dir = TestDir::new("strand-tags");
+        let path = test_dir.join("unitigs.fa");
+
+        let writer = StructuredSequenceWriter::<(), (), _>::new(FastaWriter::new_plain(&path))
+            .with_canonical_output(true)
+            .with_strand_tags(true);
+
+        // In a stranded graph TTTGC and its reverse complement GCAAA are distinct unitigs, both are
+        // written as GCAAA but only the first one is tagged as coming from the reverse strand
+        let mut buffer = FastaWriterConcurrentBuffer::new(&writer, 1024, false);
+        buffer.add_read(b"TTTGC", None, (), &(), (), &());
+        buffer.add_read(b"GCAAA", None, (), &(), (), &());
+        buffer.finalize();
+        writer.finalize();
+
+        let output = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(output, ">0 LN:i:5 ST:A:-\nGCAAA\n>1 LN:i:5 ST:A:+\nGCAAA\n");
+    }
+
+    #[test]
+    fn partial_output_is_readable() {
+        let test_dir = TestDir::new("flush-interval");
+        let path = test_dir.join("unitigs.fa");
+
+        let writer = StructuredSequenceWriter::<(), (), _>::new(FastaWriter::new_plain(&path))
+            .with_flush_interval(OutputFlushInterval {
+                sequences: Some(2),
+                bytes: None,
+            });
+
+        // The buffer is large enough for all the reads, only the flush interval writes them out
+        let mut buffer = FastaWriterConcurrentBuffer::new(&writer, 1024, true);
+        for read in [&b"ACGTA"[..], b"TTTGC", b"GGCCA"] {
+            buffer.add_read(read, None, (), &(), (), &());
+        }
+
+        // Read the file while the writer is still open
+        let partial_output = std::fs::read_to_string(&path).unwrap();
+
+        buffer.finalize();
+        writer.finalize();
+        let output = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(partial_output, ">0 LN:i:5\nACGTA\n>1 LN:i:5\nTTTGC\n");
+        assert_eq!(output, partial_output + ">2 LN:i:5\nGGCCA\n");
+    }
+
+    fn write_reads(path: &std::path::Path) {
+        let writer =
+            StructuredSequenceWriter::<(), (), _>::new(FastaWriter::new_from_extension(path, 6));
+
+        // A small buffer, to push the reads through the encoder in multiple flushes
+        let mut buffer = FastaWriterConcurrentBuffer::new(&writer, 16, false);
+        for read in [&b"ACGTACGTAC"[..], b"TTTGC", b"GGGGCCCCAAAATTTT", b"CA"] {
+            buffer.add_read(read, None, (), &(), (), &());
+        }
+        buffer.finalize();
+        writer.finalize();
+    }
+
+    #[test]
+    fn compressed_output_matches_plain_output() {
+        let test_dir = TestDir::new("compressed");
+        let plain_path = test_dir.join("unitigs.fa");
+        let gzip_path = test_dir.join("unitigs.fa.gz");
+        let lz4_path = test_dir.join("unitigs.fa.lz4");
+
+        write_reads(&plain_path);
+        write_reads(&gzip_path);
+        write_reads(&lz4_path);
+
+        let plain = std::fs::read(&plain_path).unwrap();
+
+        let mut gzip = vec![];
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(&gzip_path).unwrap())
+            .read_to_end(&mut gzip)
+            .unwrap();
+
+        let mut lz4 = vec![];
+        lz4::Decoder::new(std::fs::File::open(&lz4_path).unwrap())
+            .unwrap()
+            .read_to_end(&mut lz4)
+            .unwrap();
+
+        assert!(!plain.is_empty());
+        assert_eq!(gzip, plain);
+        assert_eq!(lz4, plain);
+    }
+
+    #[test]
+    fn bgzf_output_fetches_unitigs_by_name() {
+        let test_dir = TestDir::new("bgzf");
+        let path = test_dir.join("unitigs.fa.gz");
+        let gzi_path = crate::concurrent::structured_sequences::bgzf::bgzf_index_path(&path);
+        let fai_path = super::fasta_index_path(&path);
+
+        // Enough unitigs to span multiple blocks
+        let mut state = 12345u64;
+        let unitigs: Vec<Vec<u8>> = (0..2000)
+            .map(|i| {
+                (0..50 + i % 100)
+                    .map(|_| {
+                        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        b"ACGT"[(state >> 62) as usize]
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let writer =
+            StructuredSequenceWriter::<(), (), _>::new(FastaWriter::new_compressed_bgzf(&path, 6));
+        let mut buffer = FastaWriterConcurrentBuffer::new(&writer, 4096, false);
+        for unitig in &unitigs {
+            buffer.add_read(unitig, None, (), &(), (), &());
+        }
+        buffer.finalize();
+        writer.finalize();
+
+        // The whole file is still a valid multi member gzip
+        let mut output = vec![];
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(&path).unwrap())
+            .read_to_end(&mut output)
+            .unwrap();
+        let mut expected = vec![];
+        for (index, unitig) in unitigs.iter().enumerate() {
+            expected.extend_from_slice(format!(">{} LN:i:{}\n", index, unitig.len()).as_bytes());
+            expected.extend_from_slice(unitig);
+            expected.push(b'\n');
+        }
+        assert_eq!(output, expected);
+
+        let fai = std::fs::read_to_string(&fai_path).unwrap();
+        let gzi = std::fs::read(&gzi_path).unwrap();
+
+        let read_u64 = |position: usize| {
+            u64::from_le_bytes(gzi[position * 8..(position + 1) * 8].try_into().unwrap())
+        };
+        let blocks: Vec<(u64, u64)> = (0..read_u64(0) as usize)
+            .map(|block| (read_u64(1 + block * 2), read_u64(2 + block * 2)))
+            .collect();
+        assert!(blocks.len() > 1);
+
+        let fetch = |name: &str| {
+            let entry: Vec<u64> = fai
+                .lines()
+                .find_map(|line| line.strip_prefix(&format!("{}\t", name)))
+                .unwrap()
+                .split('\t')
+                .map(|field| field.parse().unwrap())
+                .collect();
+            let (length, offset) = (entry[0], entry[1]);
+
+            // Decompress from the last block starting before the unitig
+            let (compressed_offset, uncompressed_offset) = blocks
+                .iter()
+                .copied()
+                .take_while(|(_, uncompressed_offset)| *uncompressed_offset <= offset)
+                .last()
+                .unwrap_or((0, 0));
+            let mut file = std::fs::File::open(&path).unwrap();
+            file.seek(SeekFrom::Start(compressed_offset)).unwrap();
+            let mut decoder = flate2::read::MultiGzDecoder::new(file);
+            std::io::copy(
+                &mut (&mut decoder).take(offset - uncompressed_offset),
+                &mut std::io::sink(),
+            )
+            .unwrap();
+            let mut sequence = vec![0; length as usize];
+            decoder.read_exact(&mut sequence).unwrap();
+            sequence
+        };
+
+        let fetched = [fetch("1234"), fetch("0"), fetch("1999")];
+
+        assert_eq!(fai.lines().count(), unitigs.len());
+        assert_eq!(
+            fetched,
+            [
+                unitigs[1234].clone(),
+                unitigs[0].clone(),
+                unitigs[1999].clone()
+            ]
+        );
     }
 }
@@ -0,0 +1,153 @@
+use crate::concurrent::structured_sequences::output_stream::OutputStream;
+use crate::concurrent::structured_sequences::{
+    write_reverse_complement, IdentSequenceWriter, SequenceStrand, StructuredSequenceBackend,
+};
+use config::DEFAULT_PER_CPU_BUFFER_SIZE;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// Writes the sequences as SPAdes-style FASTG edges. Each sequence i is written both as the edge
+/// EDGE_i and as its reverse complement EDGE_i', each one followed by the list of its successors
+/// in the format >EDGE_i:EDGE_j,EDGE_k'; as given by the links info. With strand tags each header
+/// is followed by ST:A:<strand>, the reverse complement edge getting the opposite strand of the
+/// sequence. The colors are not written
+pub struct StructSeqFastgWriter<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter> {
+    writer: Option<OutputStream>,
+    path: PathBuf,
+    _phantom: PhantomData<(ColorInfo, LinksInfo)>,
+}
+
+unsafe impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter> Send
+    for StructSeqFastgWriter<ColorInfo, LinksInfo>
+{
+}
+
+unsafe impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter> Sync
+    for StructSeqFastgWriter<ColorInfo, LinksInfo>
+{
+}
+
+impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
+    StructSeqFastgWriter<ColorInfo, LinksInfo>
+{
+    pub fn new_plain(path: impl AsRef<Path>) -> Self {
+        StructSeqFastgWriter {
+            writer: Some(OutputStream::new_plain(&path)),
+            path: path.as_ref().to_path_buf(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Compresses the output with gzip or lz4 if the path ends with .gz or .lz4
+    pub fn new_from_extension(path: impl AsRef<Path>, level: u32) -> Self {
+        StructSeqFastgWriter {
+            writer: Some(OutputStream::from_extension(&path, level)),
+            path: path.as_ref().to_path_buf(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter>
+    StructuredSequenceBackend<ColorInfo, LinksInfo> for StructSeqFastgWriter<ColorInfo, LinksInfo>
+{
+    type SequenceTempBuffer = (Vec<u8>, Vec<u8>);
+
+    fn alloc_temp_buffer() -> Self::SequenceTempBuffer {
+        (
+            Vec::with_capacity(DEFAULT_PER_CPU_BUFFER_SIZE.as_bytes()),
+            vec![],
+        )
+    }
+
+    fn write_sequence(
+        (buffer, rc_sequence): &mut Self::SequenceTempBuffer,
+        sequence_index: u64,
+        sequence: &[u8],
+        strand: Option<SequenceStrand>,
+
+        _color_info: ColorInfo,
+        links_info: LinksInfo,
+        extra_buffers: &(ColorInfo::TempBuffer, LinksInfo::TempBuffer),
+    ) {
+        write_reverse_complement(sequence, rc_sequence);
+
+        for (reverse, sequence) in [(false, sequence), (true, rc_sequence.as_slice())] {
+            write!(
+                buffer,
+                ">EDGE_{}{}",
+                sequence_index,
+                if reverse { "'" } else { "" }
+            )
+            .unwrap();
+            links_info.write_as_fastg(buffer, &extra_buffers.1, reverse);
+            buffer.extend_from_slice(b";");
+            if let Some(strand) = strand {
+                let strand = if reverse { strand.flip() } else { strand };
+                write!(buffer, " ST:A:{}", strand.as_char()).unwrap();
+            }
+            buffer.extend_from_slice(b"\n");
+            buffer.extend_from_slice(sequence);
+            buffer.extend_from_slice(b"\n");
+        }
+    }
+
+    fn get_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn flush_temp_buffer(&mut self, (buffer, _): &mut Self::SequenceTempBuffer) {
+        self.writer.as_mut().unwrap().write_all(buffer).unwrap();
+        buffer.clear();
+    }
+
+    fn flush_output(&mut self) {
+        self.writer.as_mut().unwrap().flush().unwrap();
+    }
+
+    fn finalize(mut self) {
+        if let Some(writer) = self.writer.take() {
+            writer.finish();
+        }
+    }
+}
+
+impl<ColorInfo: IdentSequenceWriter, LinksInfo: IdentSequenceWriter> Drop
+    for StructSeqFastgWriter<ColorInfo, LinksInfo>
+{
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            writer.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StructSeqFastgWriter;
+    use crate::concurrent::structured_sequences::concurrent::FastaWriterConcurrentBuffer;
+    use crate::concurrent::structured_sequences::StructuredSequenceWriter;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn stranded_edges_keep_their_strand() {
+        let test_dir = TestDir::new("fastg-strand");
+        let path = test_dir.join("unitigs.fastg");
+
+        let writer =
+            StructuredSequenceWriter::<(), (), _>::new(StructSeqFastgWriter::new_plain(&path))
+                .with_canonical_output(true)
+                .with_strand_tags(true);
+
+        // TTTGC is written flipped as GCAAA, so its edge comes from the reverse strand and the
+        // reverse complement edge from the forward one
+        let mut buffer = FastaWriterConcurrentBuffer::new(&writer, 1024, false);
+        buffer.add_read(b"TTTGC", None, (), &(), (), &());
+        buffer.finalize();
+        writer.finalize();
+
+        let output = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(output, ">EDGE_0; ST:A:-\nGCAAA\n>EDGE_0'; ST:A:+\nTTTGC\n");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::concurrent::structured_sequences::bgzf::{bgzf_index_path, BgzfWriter};
+use crate::concurrent::structured_sequences::fasta::fasta_index_path;
+use config::{DEFAULT_OUTPUT_BUFFER_SIZE, OUTPUT_BGZF};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lz4::{BlockMode, BlockSize, ContentChecksum};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+/// Output file of the structured sequences writers, optionally compressed.
+/// The compressed streams are terminated only by finish, that must be called before dropping them
+pub enum OutputStream {
+    Plain(BufWriter<File>),
+    Gzip(BufWriter<GzEncoder<BufWriter<File>>>),
+    Lz4(BufWriter<lz4::Encoder<BufWriter<File>>>),
+    Bgzf(BufWriter<BgzfWriter>),
+}
+
+impl OutputStream {
+    pub fn new_plain(path: impl AsRef<Path>) -> Self {
+        OutputStream::Plain(BufWriter::with_capacity(
+            DEFAULT_OUTPUT_BUFFER_SIZE,
+            File::create(&path).unwrap(),
+        ))
+    }
+
+    pub fn new_compressed_gzip(path: impl AsRef<Path>, level: u32) -> Self {
+        let compress_stream = GzEncoder::new(
+            BufWriter::with_capacity(DEFAULT_OUTPUT_BUFFER_SIZE, File::create(&path).unwrap()),
+            Compression::new(level),
+        );
+
+        OutputStream::Gzip(BufWriter::with_capacity(
+            DEFAULT_OUTPUT_BUFFER_SIZE,
+            compress_stream,
+        ))
+    }
+
+    pub fn new_compressed_lz4(path: impl AsRef<Path>, level: u32) -> Self {
+        let compress_stream = lz4::EncoderBuilder::new()
+            .level(level)
+            .checksum(ContentChecksum::NoChecksum)
+            .block_mode(BlockMode::Linked)
+            .block_size(BlockSize::Max1MB)
+            .build(BufWriter::with_capacity(
+                DEFAULT_OUTPUT_BUFFER_SIZE,
+                File::create(&path).unwrap(),
+            ))
+            .unwrap();
+
+        OutputStream::Lz4(BufWriter::with_capacity(
+            DEFAULT_OUTPUT_BUFFER_SIZE,
+            compress_stream,
+        ))
+    }
+
+    pub fn new_compressed_bgzf(path: impl AsRef<Path>, level: u32) -> Self {
+        OutputStream::Bgzf(BufWriter::with_capacity(
+            DEFAULT_OUTPUT_BUFFER_SIZE,
+            BgzfWriter::new(path, level),
+        ))
+    }
+
+    /// Selects the compression from the extension of the path (.gz or .lz4), else writes a plain file.
+    /// A .gz output is written as BGZF if enabled in the config
+    pub fn from_extension(path: impl AsRef<Path>, level: u32) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gz") if OUTPUT_BGZF.load(Ordering::Relaxed) => {
+                Self::new_compressed_bgzf(path, level)
+            }
+            Some("gz") => Self::new_compressed_gzip(path, level),
+            Some("lz4") => Self::new_compressed_lz4(path, level),
+            _ => Self::new_plain(path),
+        }
+    }
+
+    /// Flushes the buffered data through the encoder and writes the compressed stream trailer
+    pub fn finish(self) {
+        match self {
+            OutputStream::Plain(mut writer) => writer.flush().unwrap(),
+            OutputStream::Gzip(writer) => {
+                let encoder = writer
+                    .into_inner()
+                    .unwrap_or_else(|err| panic!("Cannot flush the gzip output: {}", err));
+                encoder.finish().unwrap().flush().unwrap();
+            }
+            OutputStream::Lz4(writer) => {
+                let encoder = writer
+                    .into_inner()
+                    .unwrap_or_else(|err| panic!("Cannot flush the lz4 output: {}", err));
+                let (mut file, result) = encoder.finish();
+                result.unwrap();
+                file.flush().unwrap();
+            }
+            OutputStream::Bgzf(writer) => {
+                let encoder = writer
+                    .into_inner()
+                    .unwrap_or_else(|err| panic!("Cannot flush the bgzf output: {}", err));
+                encoder
+                    .finish()
+                    .unwrap_or_else(|err| panic!("Cannot write the bgzf index: {}", err));
+            }
+        }
+    }
+}
+
+/// Renames an output file together with its .gzi and .fai indexes, if they were written
+pub fn rename_output(from: impl AsRef<Path>, to: impl AsRef<Path>) -> std::io::Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    std::fs::rename(from, to)?;
+    for index_path in [bgzf_index_path, fasta_index_path] {
+        let from_index = index_path(from);
+        if from_index.exists() {
+            std::fs::rename(from_index, index_path(to))?;
+        }
+    }
+    Ok(())
+}
+
+impl Write for OutputStream {
+    #[inline(always)]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputStream::Plain(writer) => writer.write(buf),
+            OutputStream::Gzip(writer) => writer.write(buf),
+            OutputStream::Lz4(writer) => writer.write(buf),
+            OutputStream::Bgzf(writer) => writer.write(buf),
+        }
+    }
+
+    #[inline(always)]
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self {
+            OutputStream::Plain(writer) => writer.write_all(buf),
+            OutputStream::Gzip(writer) => writer.write_all(buf),
+            OutputStream::Lz4(writer) => writer.write_all(buf),
+            OutputStream::Bgzf(writer) => writer.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputStream::Plain(writer) => writer.flush(),
+            OutputStream::Gzip(writer) => writer.flush(),
+            OutputStream::Lz4(writer) => writer.flush(),
+            OutputStream::Bgzf(writer) => writer.flush(),
+        }
+    }
+}
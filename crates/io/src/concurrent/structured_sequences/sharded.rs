@@ -0,0 +1,274 @@
+use crate::concurrent::structured_sequences::{
+    IdentSequenceWriter, SequenceStrand, StructuredSequenceBackend,
+};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// The files written by a ShardedWriter
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShardedOutput {
+    /// A single shard, written directly to the output file
+    Single(PathBuf),
+    /// The shards paths, each one a valid standalone file
+    Shards(Vec<PathBuf>),
+}
+
+impl ShardedOutput {
+    /// The output file, if it is not sharded
+    pub fn single_file(self) -> Option<PathBuf> {
+        match self {
+            ShardedOutput::Single(path) => Some(path),
+            ShardedOutput::Shards(_) => None,
+        }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        match self {
+            ShardedOutput::Single(path) => std::slice::from_ref(path),
+            ShardedOutput::Shards(paths) => paths,
+        }
+    }
+}
+
+/// Backend that splits the output across multiple shards, each one a valid standalone file.
+/// The sequences are assigned in round robin to the shards, one flushed batch at a time,
+/// so the consecutive compression data of the binary format (that is reset at each flush)
+/// never crosses the shard boundaries.
+/// With more than one shard, an index file listing the shards paths is written on finalize
+pub struct ShardedWriter<
+    ColorInfo: IdentSequenceWriter,
+    LinksInfo: IdentSequenceWriter,
+    Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+> {
+    shards: Vec<Backend>,
+    next_shard: usize,
+    index_path: PathBuf,
+    _phantom: PhantomData<(ColorInfo, LinksInfo)>,
+}
+
+unsafe impl<
+        ColorInfo: IdentSequenceWriter,
+        LinksInfo: IdentSequenceWriter,
+        Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+    > Send for ShardedWriter<ColorInfo, LinksInfo, Backend>
+{
+}
+
+unsafe impl<
+        ColorInfo: IdentSequenceWriter,
+        LinksInfo: IdentSequenceWriter,
+        Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+    > Sync for ShardedWriter<ColorInfo, LinksInfo, Backend>
+{
+}
+
+impl<
+        ColorInfo: IdentSequenceWriter,
+        LinksInfo: IdentSequenceWriter,
+        Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+    > ShardedWriter<ColorInfo, LinksInfo, Backend>
+{
+    /// Creates shards_count shards for the output file. A single shard is written directly to
+    /// the output file, otherwise the shards are named <name>.<shard>.<extensions>
+    pub fn new(
+        output_file: impl AsRef<Path>,
+        shards_count: usize,
+        create_shard: impl Fn(&Path) -> Backend,
+    ) -> Self {
+        let output_file = output_file.as_ref();
+        assert!(
+            shards_count > 0,
+            "The output shards count must be at least 1"
+        );
+
+        let shards = if shards_count == 1 {
+            vec![create_shard(output_file)]
+        } else {
+            (0..shards_count)
+                .map(|shard| create_shard(&Self::get_shard_path(output_file, shard)))
+                .collect()
+        };
+
+        Self {
+            shards,
+            next_shard: 0,
+            index_path: Self::get_index_path(output_file, shards_count),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn get_shard_path(output_file: &Path, shard: usize) -> PathBuf {
+        let file_name = output_file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        output_file.with_file_name(match file_name.split_once('.') {
+            Some((name, extensions)) => format!("{}.{}.{}", name, shard, extensions),
+            None => format!("{}.{}", file_name, shard),
+        })
+    }
+
+    /// The files written for the output file
+    pub fn get_output(output_file: &Path, shards_count: usize) -> ShardedOutput {
+        if shards_count == 1 {
+            ShardedOutput::Single(output_file.to_path_buf())
+        } else {
+            ShardedOutput::Shards(
+                (0..shards_count)
+                    .map(|shard| Self::get_shard_path(output_file, shard))
+                    .collect(),
+            )
+        }
+    }
+
+    /// The path of the shards index, or the output file itself if it is not sharded
+    pub fn get_index_path(output_file: &Path, shards_count: usize) -> PathBuf {
+        if shards_count == 1 {
+            output_file.to_path_buf()
+        } else {
+            output_file.with_extension("shards.txt")
+        }
+    }
+}
+
+impl<
+        ColorInfo: IdentSequenceWriter,
+        LinksInfo: IdentSequenceWriter,
+        Backend: StructuredSequenceBackend<ColorInfo, LinksInfo>,
+    > StructuredSequenceBackend<ColorInfo, LinksInfo>
+    for ShardedWriter<ColorInfo, LinksInfo, Backend>
+{
+    type SequenceTempBuffer = Backend::SequenceTempBuffer;
+
+    fn alloc_temp_buffer() -> Self::SequenceTempBuffer {
+        Backend::alloc_temp_buffer()
+    }
+
+    fn write_sequence(
+        buffer: &mut Self::SequenceTempBuffer,
+        sequence_index: u64,
+        sequence: &[u8],
+        strand: Option<SequenceStrand>,
+
+        color_info: ColorInfo,
+        links_info: LinksInfo,
+        extra_buffers: &(ColorInfo::TempBuffer, LinksInfo::TempBuffer),
+    ) {
+        Backend::write_sequence(
+            buffer,
+            sequence_index,
+            sequence,
+            strand,
+            color_info,
+            links_info,
+            extra_buffers,
+        );
+    }
+
+    fn get_path(&self) -> PathBuf {
+        self.index_path.clone()
+    }
+
+    fn flush_temp_buffer(&mut self, buffer: &mut Self::SequenceTempBuffer) {
+        self.shards[self.next_shard].flush_temp_buffer(buffer);
+        self.next_shard = (self.next_shard + 1) % self.shards.len();
+    }
+
+    fn flush_output(&mut self) {
+        for shard in self.shards.iter_mut() {
+            shard.flush_output();
+        }
+    }
+
+    fn finalize(self) {
+        if self.shards.len() > 1 {
+            let mut index = BufWriter::new(File::create(&self.index_path).unwrap());
+            for shard in self.shards.iter() {
+                writeln!(index, "{}", shard.get_path().display()).unwrap();
+            }
+        }
+
+        for shard in self.shards {
+            shard.finalize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardedWriter;
+    use crate::concurrent::structured_sequences::fasta::FastaWriter;
+    use crate::concurrent::structured_sequences::StructuredSequenceBackend;
+    use std::path::PathBuf;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn shards_reassemble_unitigs() {
+        const SHARDS_COUNT: usize = 3;
+
+        let test_dir = TestDir::new("shards");
+        let output_file = test_dir.join("output.fasta");
+
+        let unitigs: Vec<_> = (0..100u64)
+            .map(|i| b"ACGT".repeat(1 + i as usize % 5))
+            .collect();
+
+        let mut writer =
+            ShardedWriter::<(), (), FastaWriter<(), ()>>::new(&output_file, SHARDS_COUNT, |path| {
+                FastaWriter::new_plain(path)
+            });
+        let index_path = writer.get_path();
+
+        let mut buffer = ShardedWriter::<(), (), FastaWriter<(), ()>>::alloc_temp_buffer();
+        for batch in unitigs.chunks(7).enumerate() {
+            for (offset, unitig) in batch.1.iter().enumerate() {
+                ShardedWriter::<(), (), FastaWriter<(), ()>>::write_sequence(
+                    &mut buffer,
+                    (batch.0 * 7 + offset) as u64,
+                    unitig,
+                    None,
+                    (),
+                    (),
+                    &((), ()),
+                );
+            }
+            writer.flush_temp_buffer(&mut buffer);
+        }
+        writer.finalize();
+
+        let shards: Vec<_> = std::fs::read_to_string(&index_path)
+            .unwrap()
+            .lines()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(shards.len(), SHARDS_COUNT);
+        assert_eq!(
+            ShardedWriter::<(), (), FastaWriter<(), ()>>::get_output(&output_file, SHARDS_COUNT)
+                .paths(),
+            shards.as_slice()
+        );
+
+        let mut reassembled = vec![];
+        for shard in shards {
+            let contents = std::fs::read_to_string(&shard).unwrap();
+            let mut lines = contents.lines();
+            while let (Some(header), Some(sequence)) = (lines.next(), lines.next()) {
+                let index: u64 = header[1..].split(' ').next().unwrap().parse().unwrap();
+                reassembled.push((index, sequence.as_bytes().to_vec()));
+            }
+        }
+        reassembled.sort();
+
+        assert_eq!(
+            reassembled,
+            unitigs
+                .into_iter()
+                .enumerate()
+                .map(|(i, unitig)| (i as u64, unitig))
+                .collect::<Vec<_>>()
+        );
+    }
+}
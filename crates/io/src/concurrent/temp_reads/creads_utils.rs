@@ -139,7 +139,7 @@ impl<
             read_buffer.set_len(buffer_start + bytes);
         }
 
-        stream.read_exact(&mut read_buffer[buffer_start..]).unwrap();
+        stream.read_exact(&mut read_buffer[buffer_start..]).ok()?;
 
         Some((
             flags,
@@ -162,3 +162,63 @@ impl<
             + if WITH_SECOND_BUCKET { 1 } else { 0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CompressedReadsBucketData, CompressedReadsBucketDataSerializer};
+    use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+    use std::collections::HashSet;
+
+    const K: usize = 15;
+
+    fn roundtrip_kmers<const WITH_SECOND_BUCKET: bool>(reads: &[Vec<u8>]) -> HashSet<String> {
+        let mut serializer =
+            CompressedReadsBucketDataSerializer::<(), typenum::U2, WITH_SECOND_BUCKET>::new();
+
+        let mut bucket = vec![];
+        for (index, read) in reads.iter().enumerate() {
+            let element = CompressedReadsBucketData::new(read, 1, index as u8);
+            serializer.write_to(&element, &mut bucket, &(), &());
+        }
+
+        serializer.reset();
+        let mut stream = bucket.as_slice();
+        let mut read_buffer = vec![];
+        let mut kmers = HashSet::new();
+        let mut index = 0;
+        while let Some((flags, second_bucket, _, read)) =
+            serializer.read_from(&mut stream, &mut read_buffer, &mut ())
+        {
+            assert_eq!(flags, 1);
+            assert_eq!(
+                second_bucket,
+                if WITH_SECOND_BUCKET { index as u8 } else { 0 }
+            );
+            let read = read.to_string();
+            for start in 0..=(read.len() - K) {
+                kmers.insert(read[start..start + K].to_string());
+            }
+            index += 1;
+        }
+        assert_eq!(index, reads.len());
+
+        kmers
+    }
+
+    #[test]
+    fn second_bucket_does_not_change_kmers() {
+        let reads: Vec<_> = (0..300)
+            .map(|i| {
+                (0..(K + i % 37))
+                    .map(|j| b"ACGT"[(i * 7 + j * j) % 4])
+                    .collect()
+            })
+            .collect();
+
+        let without_second_bucket = roundtrip_kmers::<false>(&reads);
+        let with_second_bucket = roundtrip_kmers::<true>(&reads);
+
+        assert!(!without_second_bucket.is_empty());
+        assert_eq!(without_second_bucket, with_second_bucket);
+    }
+}
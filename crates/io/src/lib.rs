@@ -1,7 +1,10 @@
 #![feature(let_chains)]
 
 use crate::sequences_stream::general::GeneralSequenceBlockData;
-use config::{BucketIndexType, MAX_BUCKETS_COUNT_LOG, MAX_BUCKET_SIZE, MIN_BUCKETS_COUNT_LOG};
+use config::{
+    BucketIndexType, MAX_BUCKETS_COUNT_LOG, MAX_BUCKETS_PER_THREAD, MAX_BUCKET_SIZE,
+    MIN_BUCKETS_COUNT_LOG, SUGGESTED_BUCKETS_PER_THREAD,
+};
 use std::cmp::{max, min};
 use std::path::{Path, PathBuf};
 
@@ -77,3 +80,128 @@ pub fn compute_stats_from_input_blocks(blocks: &[GeneralSequenceBlockData]) -> F
         // best_lz4_compression_level: 0,
     }
 }
+
+fn suggested_buckets_count_log(threads_count: usize) -> usize {
+    min(
+        MAX_BUCKETS_COUNT_LOG,
+        (max(1, threads_count) * SUGGESTED_BUCKETS_PER_THREAD)
+            .next_power_of_two()
+            .ilog2() as usize,
+    )
+}
+
+/// Warning for a buckets count that is a poor fit for the threads count, with a better value
+pub fn buckets_count_fit_warning(buckets_count_log: usize, threads_count: usize) -> Option<String> {
+    let buckets_count = 1 << buckets_count_log;
+
+    // With the maximum buckets count there is no better value to suggest for more threads
+    if buckets_count < threads_count && buckets_count_log < MAX_BUCKETS_COUNT_LOG {
+        Some(format!(
+            "Only {} buckets for {} threads, some threads will be idle. Consider using {} buckets (-b {})",
+            buckets_count,
+            threads_count,
+            1 << suggested_buckets_count_log(threads_count),
+            suggested_buckets_count_log(threads_count)
+        ))
+    } else if buckets_count > MAX_BUCKETS_PER_THREAD * threads_count {
+        Some(format!(
+            "{} buckets for {} threads, the buckets overhead can dominate. Consider using {} buckets (-b {})",
+            buckets_count,
+            threads_count,
+            1 << suggested_buckets_count_log(threads_count),
+            suggested_buckets_count_log(threads_count)
+        ))
+    } else {
+        None
+    }
+}
+
+/// Buckets count (log) to use for the given threads. A forced count is kept, with a warning if it is
+/// a poor fit for the threads count. The count derived from the input size is raised if it leaves
+/// threads without buckets, up to MAX_BUCKETS_COUNT_LOG, but never lowered, as it keeps the buckets
+/// under MAX_BUCKET_SIZE
+pub fn choose_buckets_count_log(
+    forced_buckets_count_log: Option<usize>,
+    file_stats: &FilesStatsInfo,
+    threads_count: usize,
+) -> usize {
+    match forced_buckets_count_log {
+        Some(buckets_count_log) => {
+            if let Some(warning) = buckets_count_fit_warning(buckets_count_log, threads_count) {
+                log::warn!("{}", warning);
+            }
+            buckets_count_log
+        }
+        None if (1 << file_stats.best_buckets_count_log) < threads_count => {
+            let buckets_count_log = suggested_buckets_count_log(threads_count);
+            log::info!(
+                "Using {} buckets to have enough buckets for {} threads",
+                1 << buckets_count_log,
+                threads_count
+            );
+            buckets_count_log
+        }
+        None => file_stats.best_buckets_count_log,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        buckets_count_fit_warning, choose_buckets_count_log, generate_bucket_names,
+        get_bucket_index, FilesStatsInfo,
+    };
+    use config::prefixed_temp_file_path;
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    #[test]
+    fn buckets_count_fit_for_threads() {
+        // 4 buckets for 64 threads
+        let warning = buckets_count_fit_warning(2, 64).unwrap();
+        assert!(warning.contains("256 buckets (-b 8)"));
+        assert!(buckets_count_fit_warning(14, 64).is_some());
+        assert!(buckets_count_fit_warning(8, 64).is_none());
+
+        let file_stats = FilesStatsInfo {
+            best_buckets_count_log: 10,
+        };
+        // Forced counts are kept, the derived ones are raised to fit the threads
+        assert_eq!(choose_buckets_count_log(Some(2), &file_stats, 64), 2);
+        assert_eq!(choose_buckets_count_log(None, &file_stats, 64), 10);
+        assert_eq!(choose_buckets_count_log(None, &file_stats, 4096), 13);
+        assert_eq!(choose_buckets_count_log(None, &file_stats, 100000), 13);
+        assert!(buckets_count_fit_warning(13, 100000).is_none());
+    }
+
+    #[test]
+    fn prefixed_runs_do_not_collide() {
+        let temp_dir = Path::new("/tmp/shared-temp-dir");
+
+        let run_buckets = |prefix: &str| {
+            ["bucket", "linksi3", "results_map", "unitigs_map"]
+                .iter()
+                .flat_map(|name| {
+                    generate_bucket_names(
+                        prefixed_temp_file_path(temp_dir.join(name), prefix),
+                        16,
+                        Some("lz4"),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let run_a = run_buckets("1a2b3c4d");
+        let run_b = run_buckets("run-2");
+
+        let names_a: HashSet<_> = run_a.iter().collect();
+        assert_eq!(names_a.len(), run_a.len());
+        assert!(run_b.iter().all(|bucket| !names_a.contains(bucket)));
+
+        for (index, bucket) in run_a.iter().chain(run_b.iter()).enumerate() {
+            assert_eq!(bucket.parent(), Some(temp_dir));
+            assert_eq!(get_bucket_index(bucket) as usize, index % 16);
+        }
+        assert_eq!(run_b[3], temp_dir.join("run-2_bucket.3.lz4"),);
+    }
+}
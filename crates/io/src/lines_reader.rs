@@ -2,9 +2,11 @@ use bstr::ByteSlice;
 use config::DEFAULT_OUTPUT_BUFFER_SIZE;
 use parallel_processor::mt_debug_counters::counter::{AtomicCounter, AvgMode, SumMode};
 use parallel_processor::mt_debug_counters::{declare_avg_counter_i64, declare_counter_i64};
+use std::cmp::max;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use streaming_libdeflate_rs::decompress_file_buffered;
 
 pub struct LinesReader {
@@ -22,6 +24,31 @@ static COUNTER_THREADS_READ_BYTES: AtomicCounter<SumMode> =
 static COUNTER_THREADS_READ_BYTES_AVG: AtomicCounter<AvgMode> =
     declare_avg_counter_i64!("line_read_bytes_avg", false);
 
+/// Total decompressed bytes read from the input files, used to report the reading progress
+static DECOMPRESSED_BYTES_COUNT: AtomicU64 = AtomicU64::new(0);
+
+pub fn get_decompressed_bytes_count() -> u64 {
+    DECOMPRESSED_BYTES_COUNT.load(Ordering::Relaxed)
+}
+
+/// Progress percentage of the processed bytes w.r.t. the estimated decompressed size,
+/// capped as the estimation can be lower than the real size
+pub fn estimated_progress_percentage(processed_bytes: u64, estimated_bytes: u64) -> f64 {
+    (processed_bytes as f64 / max(1, estimated_bytes) as f64 * 100.0).min(100.0)
+}
+
+/// Opens a file as a decompressed stream, depending on its gz or lz4 extension
+pub fn open_decompressed_file(path: impl AsRef<Path>) -> Box<dyn Read + Send> {
+    let path = path.as_ref();
+    let file = File::open(path).expect(&format!("Cannot open file {}", path.display()));
+
+    match path.extension() {
+        Some(ext) if ext == "gz" => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Some(ext) if ext == "lz4" => Box::new(lz4::Decoder::new(file).unwrap()),
+        _ => Box::new(file),
+    }
+}
+
 impl LinesReader {
     pub(crate) fn new() -> Self {
         Self {
@@ -40,6 +67,7 @@ impl LinesReader {
         while let Ok(count) = stream.read(self.buffer.as_mut_slice()) {
             COUNTER_THREADS_READ_BYTES.inc_by(count as i64);
             COUNTER_THREADS_READ_BYTES_AVG.add_value(count as i64);
+            DECOMPRESSED_BYTES_COUNT.fetch_add(count as u64, Ordering::Relaxed);
             COUNTER_THREADS_BUSY_READING.sub(1);
             if count == 0 {
                 COUNTER_THREADS_PROCESSING_READS.inc();
@@ -65,15 +93,13 @@ impl LinesReader {
             if let Err(_err) = decompress_file_buffered(
                 &path,
                 |data| {
+                    DECOMPRESSED_BYTES_COUNT.fetch_add(data.len() as u64, Ordering::Relaxed);
                     callback(data);
                     Ok(())
                 },
                 DEFAULT_OUTPUT_BUFFER_SIZE,
             ) {
-                println!(
-                    "WARNING: Error while reading file {}",
-                    path.as_ref().display()
-                );
+                log::warn!("Error while reading file {}", path.as_ref().display());
             }
             callback(&[]);
         } else if path.as_ref().extension().filter(|x| *x == "lz4").is_some() {
@@ -83,20 +109,14 @@ impl LinesReader {
             .unwrap();
             self.read_stream_buffered(file, callback)
                 .unwrap_or_else(|_| {
-                    println!(
-                        "WARNING: Error while reading file {}",
-                        path.as_ref().display()
-                    );
+                    log::warn!("Error while reading file {}", path.as_ref().display());
                 });
         } else {
             let file =
                 File::open(&path).expect(&format!("Cannot open file {}", path.as_ref().display()));
             self.read_stream_buffered(file, callback)
                 .unwrap_or_else(|_| {
-                    println!(
-                        "WARNING: Error while reading file {}",
-                        path.as_ref().display()
-                    );
+                    log::warn!("Error while reading file {}", path.as_ref().display());
                 });
         }
 
@@ -134,48 +154,153 @@ impl LinesReader {
         }
     }
 
+    /// Splits the data chunks in lines, the name is used only for the warnings
+    fn lines_splitter<'a>(
+        name: &'a Path,
+        mut callback: impl FnMut(
+                &[u8],
+                bool, /* partial (line continues on next call) */
+                bool, /* finished (last line) */
+            ) + 'a,
+    ) -> impl FnMut(&[u8]) + 'a {
+        let mut line_pending = false;
+
+        move |mut buffer: &[u8]| {
+            // File finished
+            if buffer.len() == 0 {
+                if line_pending {
+                    log::warn!("No newline at ending of file '{}'", name.display());
+                }
+                callback(&[], false, true);
+                return;
+            }
+
+            loop {
+                let (full, line) = Self::split_line(&mut buffer);
+
+                if full {
+                    callback(line, false, false);
+                } else {
+                    line_pending = line.len() > 0;
+                    if line_pending {
+                        callback(line, true, false);
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
     pub fn process_lines(
         &mut self,
         file: impl AsRef<Path>,
-        mut callback: impl FnMut(
+        callback: impl FnMut(
             &[u8],
             bool, /* partial (line continues on next call) */
             bool, /* finished (last line) */
         ),
         remove: bool,
     ) {
-        let mut line_pending = false;
-
         self.read_binary_file(
             file.as_ref(),
-            |mut buffer: &[u8]| {
-                // File finished
-                if buffer.len() == 0 {
-                    if line_pending {
-                        eprintln!(
-                            "WARNING: No newline at ending of file '{}'",
-                            file.as_ref().display()
-                        );
-                    }
-                    callback(&[], false, true);
-                    return;
-                }
+            Self::lines_splitter(file.as_ref(), callback),
+            remove,
+        );
+    }
 
-                loop {
-                    let (full, line) = Self::split_line(&mut buffer);
-
-                    if full {
-                        callback(line, false, false);
-                    } else {
-                        line_pending = line.len() > 0;
-                        if line_pending {
-                            callback(line, true, false);
-                        }
-                        break;
-                    }
-                }
+    /// Same as process_lines, reading the lines from an already opened (and decompressed) stream
+    pub fn process_stream_lines(
+        &mut self,
+        stream: impl Read,
+        name: &Path,
+        callback: impl FnMut(
+            &[u8],
+            bool, /* partial (line continues on next call) */
+            bool, /* finished (last line) */
+        ),
+    ) {
+        self.read_stream_buffered(stream, Self::lines_splitter(name, callback))
+            .unwrap_or_else(|_| {
+                log::warn!("Error while reading file {}", name.display());
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimated_progress_percentage, get_decompressed_bytes_count, LinesReader};
+    use crate::sequences_stream::fasta::FastaFileSequencesStream;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs::File;
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn gzip_progress_monotonic() {
+        let test_dir = TestDir::new("gzip-progress");
+        let path = test_dir.join("reads.fa.gz");
+
+        let mut contents = Vec::new();
+        for i in 0..20000 {
+            writeln!(contents, ">read{}", i).unwrap();
+            writeln!(contents, "{}", "ACGTTGCAAC".repeat(1 + i % 7)).unwrap();
+        }
+
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(&contents).unwrap();
+        encoder.finish().unwrap();
+
+        // The estimation comes from the gzip trailer, so it is exact for small files.
+        // The bases count, that chooses the buckets count, keeps the compressed size estimation
+        let estimated = FastaFileSequencesStream::get_estimated_decompressed_size(&path);
+        assert_eq!(estimated, contents.len() as u64);
+        assert_eq!(
+            FastaFileSequencesStream::get_estimated_bases_count(&path),
+            std::fs::metadata(&path).unwrap().len() / 2
+        );
+
+        // The progress reported while reading, from the decompressed bytes counter.
+        // Other tests can read files at the same time, so the counter can only be higher
+        let start_bytes = get_decompressed_bytes_count();
+        let mut progresses = vec![];
+        LinesReader::new().process_lines(
+            &path,
+            |_, _, _| {
+                progresses.push(estimated_progress_percentage(
+                    get_decompressed_bytes_count() - start_bytes,
+                    estimated,
+                ));
             },
-            remove,
+            true,
         );
+        let decompressed_bytes = get_decompressed_bytes_count() - start_bytes;
+
+        assert!(decompressed_bytes >= contents.len() as u64);
+        assert!(progresses.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(*progresses.last().unwrap(), 100.0);
+    }
+
+    #[test]
+    fn multi_member_gzip_size_is_not_trusted() {
+        let test_dir = TestDir::new("multi-member");
+        let path = test_dir.join("reads.fa.gz");
+
+        // Two concatenated members, the trailer has only the size of the small last one
+        let mut file = File::create(&path).unwrap();
+        for reads_count in [20000, 1] {
+            let mut encoder = GzEncoder::new(&mut file, Compression::default());
+            for i in 0..reads_count {
+                writeln!(encoder, ">read{}", i).unwrap();
+                writeln!(encoder, "{}", "ACGTTGCAAC".repeat(1 + i % 7)).unwrap();
+            }
+            encoder.finish().unwrap();
+        }
+        drop(file);
+
+        let estimated = FastaFileSequencesStream::get_estimated_decompressed_size(&path);
+        let bases_count = FastaFileSequencesStream::get_estimated_bases_count(&path);
+
+        assert_eq!(estimated, bases_count);
     }
 }
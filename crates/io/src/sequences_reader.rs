@@ -1,8 +1,10 @@
 use crate::lines_reader::LinesReader;
-use config::DEFAULT_OUTPUT_BUFFER_SIZE;
+use config::{DEFAULT_OUTPUT_BUFFER_SIZE, SPLIT_ON_SOFTMASK};
 use nightly_quirks::branch_pred::unlikely;
 use std::cmp::max;
+use std::io::Read;
 use std::path::Path;
+use std::sync::atomic::Ordering;
 
 const IDENT_STATE: usize = 0;
 const SEQ_STATE: usize = 1;
@@ -36,62 +38,123 @@ const SEQ_LETTERS_MAPPING: [u8; 256] = {
     lookup
 };
 
+/// Same as SEQ_LETTERS_MAPPING, but the soft-masked (lowercase) bases become N,
+/// so that the reads are split at the masked regions
+const SEQ_LETTERS_MAPPING_SOFTMASK_AS_N: [u8; 256] = {
+    let mut lookup = [b'N'; 256];
+    lookup[b'A' as usize] = b'A';
+    lookup[b'C' as usize] = b'C';
+    lookup[b'G' as usize] = b'G';
+    lookup[b'T' as usize] = b'T';
+    lookup
+};
+
 pub struct SequencesReader {
     lines_reader: LinesReader,
+    normalize: Option<&'static [u8; 256]>,
 }
 
 impl SequencesReader {
     pub fn new() -> Self {
         Self {
             lines_reader: LinesReader::new(),
+            normalize: Some(Self::letters_mapping(SPLIT_ON_SOFTMASK.load(Ordering::Relaxed))),
+        }
+    }
+
+    /// Creates a reader that returns the bases as found in the input, without replacing
+    /// the ones that are not ACGT with N
+    pub fn new_raw() -> Self {
+        Self {
+            lines_reader: LinesReader::new(),
+            normalize: None,
         }
     }
 
-    fn normalize_sequence(seq: &mut [u8]) {
+    /// Replaces also the soft-masked (lowercase) bases with N, overriding the global setting.
+    /// It has no effect on a raw reader
+    pub fn with_split_on_softmask(mut self, split_on_softmask: bool) -> Self {
+        if self.normalize.is_some() {
+            self.normalize = Some(Self::letters_mapping(split_on_softmask));
+        }
+        self
+    }
+
+    fn letters_mapping(split_on_softmask: bool) -> &'static [u8; 256] {
+        if split_on_softmask {
+            &SEQ_LETTERS_MAPPING_SOFTMASK_AS_N
+        } else {
+            &SEQ_LETTERS_MAPPING
+        }
+    }
+
+    fn normalize_with_mapping(seq: &mut [u8], mapping: &[u8; 256]) {
         for el in seq.iter_mut() {
-            *el = SEQ_LETTERS_MAPPING[*el as usize];
+            *el = mapping[*el as usize];
         }
     }
 
-    pub fn process_file_extended<F: FnMut(DnaSequence)>(
-        &mut self,
-        source: impl AsRef<Path>,
-        func: F,
-        line_split_copyback: Option<usize>,
-        copy_ident: bool,
-        remove_file: bool,
-    ) {
+    pub(crate) fn normalize_sequence(seq: &mut [u8]) {
+        Self::normalize_with_mapping(
+            seq,
+            Self::letters_mapping(SPLIT_ON_SOFTMASK.load(Ordering::Relaxed)),
+        );
+    }
+
+    /// Detects the FASTA/FASTQ file type from the extensions of a file name,
+    /// skipping the compression ones
+    pub fn get_file_type(source: impl AsRef<Path>) -> Option<DnaSequencesFileType> {
         const FASTQ_EXTS: &[&str] = &["fq", "fastq"];
         const FASTA_EXTS: &[&str] = &["fa", "fasta", "fna", "ffn"];
 
-        let mut file_type = None;
-        let mut tmp = source.as_ref().file_name().unwrap().to_str().unwrap();
+        let mut tmp = source.as_ref().file_name()?.to_str()?;
         let mut path: &Path = tmp.as_ref();
 
         while let Some(ext) = path.extension() {
             if FASTQ_EXTS.contains(&ext.to_str().unwrap()) {
-                file_type = Some(DnaSequencesFileType::FASTQ);
-                break;
+                return Some(DnaSequencesFileType::FASTQ);
             }
             if FASTA_EXTS.contains(&ext.to_str().unwrap()) {
-                file_type = Some(DnaSequencesFileType::FASTA);
-                break;
+                return Some(DnaSequencesFileType::FASTA);
             }
             tmp = &tmp[0..tmp.len() - ext.len() - 1];
             path = tmp.as_ref()
         }
+        None
+    }
 
-        match file_type {
+    pub fn process_file_extended<F: FnMut(DnaSequence)>(
+        &mut self,
+        source: impl AsRef<Path>,
+        func: F,
+        line_split_copyback: Option<usize>,
+        copy_ident: bool,
+        remove_file: bool,
+    ) {
+        match Self::get_file_type(&source) {
             None => panic!(
                 "Cannot recognize file type of '{}'",
                 source.as_ref().display()
             ),
             Some(ftype) => match ftype {
                 DnaSequencesFileType::FASTA => {
-                    self.process_fasta(source, func, line_split_copyback, copy_ident, remove_file);
+                    self.lines_reader.process_lines(
+                        source,
+                        Self::fasta_lines_parser(
+                            func,
+                            line_split_copyback,
+                            copy_ident,
+                            self.normalize,
+                        ),
+                        remove_file,
+                    );
                 }
                 DnaSequencesFileType::FASTQ => {
-                    self.process_fastq(source, func, remove_file);
+                    self.lines_reader.process_lines(
+                        source,
+                        Self::fastq_lines_parser(func, self.normalize),
+                        remove_file,
+                    );
                 }
                 DnaSequencesFileType::GFA => {
                     todo!()
@@ -103,14 +166,46 @@ impl SequencesReader {
         }
     }
 
-    fn process_fasta(
+    /// Same as process_file_extended, reading the sequences from an already opened
+    /// (and decompressed) stream. The name is used to detect the file type
+    pub fn process_stream_extended<F: FnMut(DnaSequence)>(
         &mut self,
-        source: impl AsRef<Path>,
-        mut func: impl FnMut(DnaSequence),
+        stream: impl Read,
+        name: &Path,
+        func: F,
         line_split_copyback: Option<usize>,
         copy_ident: bool,
-        remove_file: bool,
     ) {
+        match Self::get_file_type(name) {
+            Some(DnaSequencesFileType::FASTA) => {
+                self.lines_reader.process_stream_lines(
+                    stream,
+                    name,
+                    Self::fasta_lines_parser(
+                        func,
+                        line_split_copyback,
+                        copy_ident,
+                        self.normalize,
+                    ),
+                );
+            }
+            Some(DnaSequencesFileType::FASTQ) => {
+                self.lines_reader.process_stream_lines(
+                    stream,
+                    name,
+                    Self::fastq_lines_parser(func, self.normalize),
+                );
+            }
+            _ => panic!("Cannot recognize file type of '{}'", name.display()),
+        }
+    }
+
+    fn fasta_lines_parser(
+        mut func: impl FnMut(DnaSequence),
+        line_split_copyback: Option<usize>,
+        copy_ident: bool,
+        normalize: Option<&'static [u8; 256]>,
+    ) -> impl FnMut(&[u8], bool, bool) {
         let mut intermediate = [Vec::new(), Vec::new()];
         let mut on_comment = false;
         let mut state = SEQ_STATE;
@@ -121,122 +216,118 @@ impl SequencesReader {
             line_split_copyback.unwrap_or(0) * 2,
         );
 
-        self.lines_reader.process_lines(
-            source,
-            |line: &[u8], partial, finished| {
-                if on_comment {
-                    on_comment = !partial;
-                }
-                // If a new ident line is found (or it's the last line)
-                else if finished || (new_line && line.len() > 0 && line[0] == b'>') {
-                    if intermediate[SEQ_STATE].len() > 0 {
-                        Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
-                        func(DnaSequence {
-                            ident_data: &intermediate[IDENT_STATE],
-                            seq: &intermediate[SEQ_STATE],
-                            format: DnaSequencesFileType::FASTA,
-                        });
+        move |line: &[u8], partial: bool, finished: bool| {
+            if on_comment {
+                on_comment = !partial;
+            }
+            // If a new ident line is found (or it's the last line)
+            else if finished || (new_line && line.len() > 0 && line[0] == b'>') {
+                if intermediate[SEQ_STATE].len() > 0 {
+                    if let Some(mapping) = normalize {
+                        Self::normalize_with_mapping(&mut intermediate[SEQ_STATE], mapping);
                     }
+                    func(DnaSequence {
+                        ident_data: &intermediate[IDENT_STATE],
+                        seq: &intermediate[SEQ_STATE],
+                        format: DnaSequencesFileType::FASTA,
+                    });
+                }
 
-                    if copy_ident {
-                        intermediate[IDENT_STATE].clear();
-                        intermediate[IDENT_STATE].extend_from_slice(line);
-                    }
-                    intermediate[SEQ_STATE].clear();
+                if copy_ident {
+                    intermediate[IDENT_STATE].clear();
+                    intermediate[IDENT_STATE].extend_from_slice(line);
+                }
+                intermediate[SEQ_STATE].clear();
 
-                    state = if partial { IDENT_STATE } else { SEQ_STATE };
-                } else if new_line && line.len() > 0 && line[0] == b';' {
-                    on_comment = true;
-                } else if state == IDENT_STATE {
-                    if copy_ident {
-                        intermediate[IDENT_STATE].extend_from_slice(line);
-                    }
+                state = if partial { IDENT_STATE } else { SEQ_STATE };
+            } else if new_line && line.len() > 0 && line[0] == b';' {
+                on_comment = true;
+            } else if state == IDENT_STATE {
+                if copy_ident {
+                    intermediate[IDENT_STATE].extend_from_slice(line);
+                }
 
-                    if !partial {
-                        state = SEQ_STATE;
-                    }
-                } else {
-                    intermediate[SEQ_STATE].extend_from_slice(line);
+                if !partial {
+                    state = SEQ_STATE;
                 }
+            } else {
+                intermediate[SEQ_STATE].extend_from_slice(line);
+            }
 
-                if let Some(copyback) = line_split_copyback &&
-                    (intermediate[SEQ_STATE].len() >= flush_size) {
-                    Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
-                    func(DnaSequence {
-                        ident_data: &intermediate[IDENT_STATE],
-                        seq: &intermediate[SEQ_STATE],
-                        format: DnaSequencesFileType::FASTQ
-                    });
-                    let copy_start = intermediate[SEQ_STATE].len() - copyback;
-                    intermediate[SEQ_STATE].copy_within(copy_start.., 0);
-                    intermediate[SEQ_STATE].truncate(copyback);
+            if let Some(copyback) = line_split_copyback &&
+                (intermediate[SEQ_STATE].len() >= flush_size) {
+                if let Some(mapping) = normalize {
+                    Self::normalize_with_mapping(&mut intermediate[SEQ_STATE], mapping);
                 }
+                func(DnaSequence {
+                    ident_data: &intermediate[IDENT_STATE],
+                    seq: &intermediate[SEQ_STATE],
+                    format: DnaSequencesFileType::FASTQ
+                });
+                let copy_start = intermediate[SEQ_STATE].len() - copyback;
+                intermediate[SEQ_STATE].copy_within(copy_start.., 0);
+                intermediate[SEQ_STATE].truncate(copyback);
+            }
 
-                new_line = !partial;
-            },
-            remove_file,
-        );
+            new_line = !partial;
+        }
     }
 
-    fn process_fastq(
-        &mut self,
-        source: impl AsRef<Path>,
+    fn fastq_lines_parser(
         mut func: impl FnMut(DnaSequence),
+        normalize: Option<&'static [u8; 256]>,
         // get_quality: bool,
-        remove_file: bool,
-    ) {
+    ) -> impl FnMut(&[u8], bool, bool) {
         let mut state = IDENT_STATE;
         let mut skipped_plus = false;
 
         let mut intermediate = [Vec::new(), Vec::new(), Vec::new()];
 
-        self.lines_reader.process_lines(
-            source,
-            |line: &[u8], partial, finished| {
-                if unlikely(finished) {
+        move |line: &[u8], partial: bool, finished: bool| {
+            if unlikely(finished) {
+                return;
+            }
+
+            if state == QUAL_STATE {
+                if !skipped_plus {
+                    if !partial {
+                        skipped_plus = true;
+                    }
                     return;
                 }
 
-                if state == QUAL_STATE {
-                    if !skipped_plus {
-                        if !partial {
-                            skipped_plus = true;
-                        }
-                        return;
+                // if get_quality {
+                //     intermediate[state].extend_from_slice(line);
+                // }
+
+                if !partial {
+                    if let Some(mapping) = normalize {
+                        Self::normalize_with_mapping(&mut intermediate[SEQ_STATE], mapping);
                     }
+                    func(DnaSequence {
+                        ident_data: &intermediate[IDENT_STATE],
+                        seq: &intermediate[SEQ_STATE],
+                        // qual: if get_quality {
+                        //     Some(&intermediate[QUAL_STATE])
+                        // } else {
+                        //     None
+                        // },
+                        format: DnaSequencesFileType::FASTQ,
+                    });
 
-                    // if get_quality {
-                    //     intermediate[state].extend_from_slice(line);
-                    // }
+                    intermediate[IDENT_STATE].clear();
+                    intermediate[SEQ_STATE].clear();
+                    intermediate[QUAL_STATE].clear();
 
-                    if !partial {
-                        Self::normalize_sequence(&mut intermediate[SEQ_STATE]);
-                        func(DnaSequence {
-                            ident_data: &intermediate[IDENT_STATE],
-                            seq: &intermediate[SEQ_STATE],
-                            // qual: if get_quality {
-                            //     Some(&intermediate[QUAL_STATE])
-                            // } else {
-                            //     None
-                            // },
-                            format: DnaSequencesFileType::FASTQ,
-                        });
-
-                        intermediate[IDENT_STATE].clear();
-                        intermediate[SEQ_STATE].clear();
-                        intermediate[QUAL_STATE].clear();
-
-                        skipped_plus = false;
-                    }
-                } else {
-                    intermediate[state].extend_from_slice(line);
+                    skipped_plus = false;
                 }
+            } else {
+                intermediate[state].extend_from_slice(line);
+            }
 
-                if !partial {
-                    state = (state + 1) % 3;
-                }
-            },
-            remove_file,
-        );
+            if !partial {
+                state = (state + 1) % 3;
+            }
+        }
     }
 }
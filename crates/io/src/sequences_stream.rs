@@ -1,5 +1,9 @@
+pub mod alignment;
+pub mod channel;
 pub mod fasta;
 pub mod general;
+pub mod interleaved;
+pub mod tar_archive;
 
 use crate::sequences_reader::DnaSequence;
 use config::ColorIndexType;
@@ -7,6 +11,8 @@ use config::ColorIndexType;
 #[derive(Copy, Clone)]
 pub struct SequenceInfo {
     pub color: Option<ColorIndexType>,
+    /// Shared by the two mates of a pair, when reading an interleaved paired-end FASTQ
+    pub pair_id: Option<u64>,
 }
 
 pub trait GenericSequencesStream: 'static {
@@ -14,6 +20,12 @@ pub trait GenericSequencesStream: 'static {
 
     fn new() -> Self;
 
+    /// Estimated decompressed size of the block, used only to report the reading progress.
+    /// 0 if the size is not known in advance
+    fn estimated_decompressed_size(_block: &Self::SequenceBlockData) -> u64 {
+        0
+    }
+
     fn read_block(
         &mut self,
         block: &Self::SequenceBlockData,
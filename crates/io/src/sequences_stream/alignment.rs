@@ -0,0 +1,228 @@
+use crate::concurrent::structured_sequences::write_reverse_complement;
+use crate::sequences_reader::{DnaSequence, DnaSequencesFileType, SequencesReader};
+use crate::sequences_stream::{GenericSequencesStream, SequenceInfo};
+use noodles_fasta as fasta;
+use noodles_sam::alignment::Record;
+use noodles_util::alignment;
+use std::io;
+use std::path::PathBuf;
+
+/// Selects which alignment records are skipped while reading a BAM/CRAM file
+#[derive(Copy, Clone, Debug)]
+pub struct AlignmentRecordsFilter {
+    pub skip_secondary: bool,
+    pub skip_supplementary: bool,
+    pub skip_duplicates: bool,
+}
+
+impl Default for AlignmentRecordsFilter {
+    fn default() -> Self {
+        Self {
+            skip_secondary: true,
+            skip_supplementary: true,
+            skip_duplicates: true,
+        }
+    }
+}
+
+pub struct AlignmentFileBlockData {
+    /// The BAM/CRAM input file
+    pub path: PathBuf,
+    /// The reference used to decode reference-based CRAM records
+    pub reference: Option<PathBuf>,
+    pub filter: AlignmentRecordsFilter,
+}
+
+impl AlignmentFileBlockData {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            reference: None,
+            filter: AlignmentRecordsFilter::default(),
+        }
+    }
+
+    pub fn is_alignment_file(path: &PathBuf) -> bool {
+        path.extension()
+            .map(|x| x == "bam" || x == "cram")
+            .unwrap_or(false)
+    }
+}
+
+pub struct AlignmentFileSequencesStream {
+    sequence_buffer: Vec<u8>,
+    rc_buffer: Vec<u8>,
+}
+
+impl AlignmentFileSequencesStream {
+    pub fn get_estimated_bases_count(file: &PathBuf) -> u64 {
+        // BAM/CRAM files store compressed qualities and names along with the bases,
+        // so the file size is a rough upper bound of the bases count
+        std::fs::metadata(file)
+            .expect(&format!("Error while opening file {}", file.display()))
+            .len()
+    }
+
+    /// Fills the sequence buffer with the record bases, restoring the original read orientation
+    fn load_read_sequence(&mut self, bases: impl Iterator<Item = u8>, reverse: bool) {
+        self.sequence_buffer.clear();
+        self.sequence_buffer.extend(bases);
+        SequencesReader::normalize_sequence(&mut self.sequence_buffer);
+        if reverse {
+            write_reverse_complement(&self.sequence_buffer, &mut self.rc_buffer);
+            std::mem::swap(&mut self.sequence_buffer, &mut self.rc_buffer);
+        }
+    }
+
+    fn read_records(
+        &mut self,
+        block: &AlignmentFileBlockData,
+        mut callback: impl FnMut(DnaSequence, SequenceInfo),
+    ) -> io::Result<()> {
+        let mut builder = alignment::io::reader::Builder::default();
+
+        if let Some(reference) = &block.reference {
+            let repository = fasta::io::indexed_reader::Builder::default()
+                .build_from_path(reference)
+                .map(fasta::repository::adapters::IndexedReader::new)
+                .map(fasta::Repository::new)
+                .map_err(|err| {
+                    io::Error::new(
+                        err.kind(),
+                        format!(
+                            "cannot open reference file {}: {}",
+                            reference.display(),
+                            err
+                        ),
+                    )
+                })?;
+            builder = builder.set_reference_sequence_repository(repository);
+        }
+
+        let mut reader = builder.build_from_path(&block.path)?;
+        let header = reader.read_header()?;
+
+        for record in reader.records(&header) {
+            let record = record?;
+            let flags = record.flags()?;
+
+            if (block.filter.skip_secondary && flags.is_secondary())
+                || (block.filter.skip_supplementary && flags.is_supplementary())
+                || (block.filter.skip_duplicates && flags.is_duplicate())
+            {
+                continue;
+            }
+
+            self.load_read_sequence(record.sequence().iter(), flags.is_reverse_complemented());
+
+            if self.sequence_buffer.is_empty() {
+                continue;
+            }
+
+            callback(
+                DnaSequence {
+                    ident_data: &[],
+                    seq: &self.sequence_buffer,
+                    // Alignment records are reads, so they are handled like FASTQ records
+                    format: DnaSequencesFileType::FASTQ,
+                },
+                SequenceInfo {
+                    color: None,
+                    pair_id: None,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+impl GenericSequencesStream for AlignmentFileSequencesStream {
+    type SequenceBlockData = AlignmentFileBlockData;
+
+    fn new() -> Self {
+        Self {
+            sequence_buffer: Vec::new(),
+            rc_buffer: Vec::new(),
+        }
+    }
+
+    fn estimated_decompressed_size(block: &Self::SequenceBlockData) -> u64 {
+        Self::get_estimated_bases_count(&block.path)
+    }
+
+    fn read_block(
+        &mut self,
+        block: &Self::SequenceBlockData,
+        _copy_ident_data: bool,
+        _partial_read_copyback: Option<usize>,
+        callback: impl FnMut(DnaSequence, SequenceInfo),
+    ) {
+        // The sequences streams have no error channel, as for the other inputs
+        if let Err(err) = self.read_records(block, callback) {
+            panic!("Error while reading file {}: {}", block.path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AlignmentFileBlockData, AlignmentFileSequencesStream};
+    use crate::sequences_stream::GenericSequencesStream;
+    use noodles_bam as bam;
+    use noodles_sam as sam;
+    use sam::alignment::io::Write;
+    use sam::alignment::record::Flags;
+    use sam::alignment::RecordBuf;
+    use std::fs::File;
+    use utils::test_dir::TestDir;
+
+    fn make_record(flags: Flags, sequence: &[u8]) -> RecordBuf {
+        RecordBuf::builder()
+            .set_flags(flags)
+            .set_sequence(sequence.to_vec().into())
+            .build()
+    }
+
+    #[test]
+    fn read_tiny_bam() {
+        let test_dir = TestDir::new("tiny-bam");
+        let path = test_dir.join("reads.bam");
+
+        let header = sam::Header::default();
+        let mut writer = bam::io::Writer::new(File::create(&path).unwrap());
+        writer.write_header(&header).unwrap();
+
+        for record in [
+            make_record(Flags::UNMAPPED, b"ACGTTGCA"),
+            make_record(Flags::REVERSE_COMPLEMENTED, b"AACCG"),
+            make_record(Flags::SECONDARY, b"GGGG"),
+            make_record(Flags::SUPPLEMENTARY, b"CCCC"),
+            make_record(Flags::DUPLICATE, b"TTTT"),
+        ] {
+            writer.write_alignment_record(&header, &record).unwrap();
+        }
+        writer.try_finish().unwrap();
+        drop(writer);
+
+        let mut sequences = vec![];
+        AlignmentFileSequencesStream::new().read_block(
+            &AlignmentFileBlockData::new(path),
+            false,
+            None,
+            |seq, _| sequences.push(seq.seq.to_vec()),
+        );
+
+        assert_eq!(sequences, vec![b"ACGTTGCA".to_vec(), b"CGGTT".to_vec()]);
+    }
+
+    #[test]
+    fn corrupted_bam_is_an_error() {
+        let test_dir = TestDir::new("corrupted-bam");
+        let path = test_dir.join("reads.bam");
+        std::fs::write(&path, b"BAM\x01 not a valid header").unwrap();
+
+        let result = AlignmentFileSequencesStream::new()
+            .read_records(&AlignmentFileBlockData::new(path), |_, _| {});
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::sequences_reader::{DnaSequence, DnaSequencesFileType, SequencesReader};
+use crate::sequences_stream::general::DynamicSequencesStream;
+use crate::sequences_stream::SequenceInfo;
+use parking_lot::Mutex;
+use std::sync::mpsc::{sync_channel, Receiver, SendError, SyncSender};
+use std::sync::Arc;
+
+/// Producer side of a ChannelSequencesStream.
+/// Sending blocks when the channel is full, so that the producer is slowed down
+/// if the minimizer bucketing cannot keep up with the reads
+pub struct ChannelReadsSender {
+    sender: SyncSender<Vec<u8>>,
+}
+
+impl ChannelReadsSender {
+    pub fn send_read(&self, read: &[u8]) -> Result<(), SendError<Vec<u8>>> {
+        self.sender.send(read.to_vec())
+    }
+
+    /// Signals that no more reads will be sent, allowing the pipeline to flush the buckets
+    pub fn finish(self) {}
+}
+
+/// Push-based sequences source, that receives the reads from an external producer
+/// (for example a network service) instead of reading them from a file.
+/// It is exposed as a single block to be used with GeneralSequenceBlockData::Dynamic
+pub struct ChannelSequencesStream {
+    receiver: Mutex<Receiver<Vec<u8>>>,
+    estimated_bases_count: u64,
+}
+
+impl ChannelSequencesStream {
+    /// Creates a new stream, with at most capacity reads pending in the channel.
+    /// The estimated bases count is used to choose the number of buckets
+    pub fn new(capacity: usize, estimated_bases_count: u64) -> (ChannelReadsSender, Arc<Self>) {
+        let (sender, receiver) = sync_channel(capacity);
+        (
+            ChannelReadsSender { sender },
+            Arc::new(Self {
+                receiver: Mutex::new(receiver),
+                estimated_bases_count,
+            }),
+        )
+    }
+}
+
+impl DynamicSequencesStream for ChannelSequencesStream {
+    fn read_block(
+        &self,
+        block: usize,
+        _copy_ident_data: bool,
+        _partial_read_copyback: Option<usize>,
+        callback: &mut dyn FnMut(DnaSequence, SequenceInfo),
+    ) {
+        assert_eq!(block, 0, "A channel sequences stream has a single block");
+
+        let receiver = self.receiver.lock();
+
+        // The iteration ends when the sender is dropped
+        for mut read in receiver.iter() {
+            SequencesReader::normalize_sequence(&mut read);
+            callback(
+                DnaSequence {
+                    ident_data: &[],
+                    seq: &read,
+                    format: DnaSequencesFileType::FASTQ,
+                },
+                SequenceInfo {
+                    color: None,
+                    pair_id: None,
+                },
+            );
+        }
+    }
+
+    fn estimated_base_count(&self, _block: usize) -> u64 {
+        self.estimated_bases_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChannelSequencesStream;
+    use crate::sequences_stream::general::{GeneralSequenceBlockData, GeneralSequencesStream};
+    use crate::sequences_stream::GenericSequencesStream;
+    use std::io::Write;
+    use utils::test_dir::TestDir;
+
+    fn read_all(block: &GeneralSequenceBlockData) -> Vec<Vec<u8>> {
+        let mut sequences = vec![];
+        GeneralSequencesStream::new().read_block(block, false, None, |seq, _| {
+            sequences.push(seq.seq.to_vec())
+        });
+        sequences
+    }
+
+    #[test]
+    fn channel_matches_file() {
+        let reads: Vec<_> = (0..1000).map(|i| b"ACGGTCAT".repeat(1 + i % 9)).collect();
+
+        let test_dir = TestDir::new("channel");
+        let path = test_dir.join("reads.fa");
+        let mut file = std::fs::File::create(&path).unwrap();
+        for (i, read) in reads.iter().enumerate() {
+            writeln!(file, ">{}", i).unwrap();
+            file.write_all(read).unwrap();
+            writeln!(file).unwrap();
+        }
+        drop(file);
+
+        // A small capacity forces the producer to wait for the consumer
+        let (sender, stream) = ChannelSequencesStream::new(4, 0);
+        let producer = std::thread::spawn(move || {
+            for read in reads {
+                sender.send_read(&read).unwrap();
+            }
+            sender.finish();
+        });
+
+        let from_channel = read_all(&GeneralSequenceBlockData::Dynamic((stream, 0)));
+        producer.join().unwrap();
+
+        let from_file = read_all(&GeneralSequenceBlockData::FASTA(path));
+
+        assert_eq!(from_file.len(), 1000);
+        assert_eq!(from_channel, from_file);
+    }
+}
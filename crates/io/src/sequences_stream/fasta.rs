@@ -1,12 +1,73 @@
 use crate::sequences_reader::{DnaSequence, SequencesReader};
+use crate::sequences_stream::interleaved::{
+    check_pairs_complete, paired_sequence_info, InterleavedPairs,
+};
 use crate::sequences_stream::{GenericSequencesStream, SequenceInfo};
+use byteorder::{LittleEndian, ReadBytesExt};
+use config::INTERLEAVED_PAIRED_READS;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 
 pub struct FastaFileSequencesStream {
     sequences_reader: SequencesReader,
+    interleaved: bool,
 }
 
 impl FastaFileSequencesStream {
+    /// Reads the FASTQ files as interleaved paired-end reads, overriding the global setting
+    pub fn with_interleaved(mut self, interleaved: bool) -> Self {
+        self.interleaved = interleaved;
+        self
+    }
+
+    /// Reads the ISIZE trailer of a gzip file, that stores the decompressed size modulo 2^32 of its
+    /// last member. The value is used only when the file looks like a single member: BGZF files are
+    /// made of many small members, and a size lower than twice the compressed one (much less than
+    /// the usual compression of sequences) belongs to a smaller last member or wrapped around 2^32
+    fn get_gzip_decompressed_size(file: &PathBuf, compressed_length: u64) -> Option<u64> {
+        const GZIP_ISIZE_MAX_FILE_SIZE: u64 = 1 << 32;
+        const GZIP_MIN_COMPRESSION_RATIO: u64 = 2;
+        const GZIP_FLAG_EXTRA: u8 = 1 << 2;
+
+        if compressed_length < 18 || compressed_length >= GZIP_ISIZE_MAX_FILE_SIZE {
+            return None;
+        }
+
+        let mut file = File::open(file).ok()?;
+        let mut header = [0; 10];
+        file.read_exact(&mut header).ok()?;
+        if header[..2] != [0x1f, 0x8b] {
+            return None;
+        }
+
+        // BGZF marks each member with a BC subfield of the extra field
+        if header[3] & GZIP_FLAG_EXTRA != 0 {
+            let extra_length = file.read_u16::<LittleEndian>().ok()? as usize;
+            let mut extra = vec![0; extra_length];
+            file.read_exact(&mut extra).ok()?;
+
+            let mut subfields = extra.as_slice();
+            while subfields.len() >= 4 {
+                let subfield_length = u16::from_le_bytes([subfields[2], subfields[3]]) as usize;
+                if subfields[..2] == *b"BC" {
+                    return None;
+                }
+                subfields = &subfields[(4 + subfield_length).min(subfields.len())..];
+            }
+        }
+
+        file.seek(SeekFrom::End(-4)).ok()?;
+        let decompressed_size = file.read_u32::<LittleEndian>().ok()? as u64;
+
+        if decompressed_size >= GZIP_MIN_COMPRESSION_RATIO * compressed_length {
+            Some(decompressed_size)
+        } else {
+            None
+        }
+    }
+
     pub fn get_estimated_bases_count(file: &PathBuf) -> u64 {
         // TODO: Improve this ratio estimation
         const COMPRESSED_READS_RATIO: f64 = 0.5;
@@ -26,6 +87,22 @@ impl FastaFileSequencesStream {
         };
         file_bases_count
     }
+
+    /// Estimated decompressed size in bytes, headers and qualities included, used only to report
+    /// the reading progress. For gzip files it comes from the trailer when it can be trusted, the
+    /// buckets count is still chosen from the bases count estimation
+    pub fn get_estimated_decompressed_size(file: &PathBuf) -> u64 {
+        let length = std::fs::metadata(file)
+            .expect(&format!("Error while opening file {}", file.display()))
+            .len();
+
+        if file.extension().map(|x| x == "gz").unwrap_or(false) {
+            if let Some(decompressed_size) = Self::get_gzip_decompressed_size(file, length) {
+                return decompressed_size;
+            }
+        }
+        Self::get_estimated_bases_count(file)
+    }
 }
 
 impl GenericSequencesStream for FastaFileSequencesStream {
@@ -34,9 +111,14 @@ impl GenericSequencesStream for FastaFileSequencesStream {
     fn new() -> Self {
         Self {
             sequences_reader: SequencesReader::new(),
+            interleaved: INTERLEAVED_PAIRED_READS.load(Ordering::Relaxed),
         }
     }
 
+    fn estimated_decompressed_size(block: &Self::SequenceBlockData) -> u64 {
+        Self::get_estimated_decompressed_size(block)
+    }
+
     fn read_block(
         &mut self,
         block: &Self::SequenceBlockData,
@@ -44,12 +126,17 @@ impl GenericSequencesStream for FastaFileSequencesStream {
         partial_read_copyback: Option<usize>,
         mut callback: impl FnMut(DnaSequence, SequenceInfo),
     ) {
+        let mut pairs = self.interleaved.then(InterleavedPairs::new);
         self.sequences_reader.process_file_extended(
             block,
-            |x| callback(x, SequenceInfo { color: None }),
+            |x| {
+                let info = paired_sequence_info(&mut pairs, &x, block);
+                callback(x, info)
+            },
             partial_read_copyback,
             copy_ident_data,
             false,
         );
+        check_pairs_complete(pairs, block);
     }
 }
@@ -1,6 +1,9 @@
 use crate::sequences_reader::DnaSequence;
+use crate::sequences_stream::alignment::AlignmentFileSequencesStream;
 use crate::sequences_stream::fasta::FastaFileSequencesStream;
+use crate::sequences_stream::tar_archive::TarArchiveSequencesStream;
 use crate::sequences_stream::{GenericSequencesStream, SequenceInfo};
+use std::path::Path;
 use std::sync::Arc;
 
 pub trait DynamicSequencesStream: Sync + Send + 'static {
@@ -18,6 +21,9 @@ pub trait DynamicSequencesStream: Sync + Send + 'static {
 pub enum GeneralSequenceBlockData {
     FASTA(<FastaFileSequencesStream as GenericSequencesStream>::SequenceBlockData),
     GFA(),
+    /// BAM or CRAM file
+    Alignment(<AlignmentFileSequencesStream as GenericSequencesStream>::SequenceBlockData),
+    Tar(<TarArchiveSequencesStream as GenericSequencesStream>::SequenceBlockData),
     Dynamic((Arc<dyn DynamicSequencesStream>, usize)),
 }
 
@@ -30,15 +36,61 @@ impl GeneralSequenceBlockData {
             GeneralSequenceBlockData::GFA() => {
                 todo!()
             }
+            GeneralSequenceBlockData::Alignment(block) => {
+                AlignmentFileSequencesStream::get_estimated_bases_count(&block.path)
+            }
+            GeneralSequenceBlockData::Tar(block) => {
+                TarArchiveSequencesStream::estimated_decompressed_size(block)
+            }
             GeneralSequenceBlockData::Dynamic((reader, block)) => {
                 reader.estimated_base_count(*block)
             }
         }
     }
+
+    /// Estimated decompressed size of the input, used only to report the reading progress
+    pub fn estimated_decompressed_size(&self) -> u64 {
+        match self {
+            GeneralSequenceBlockData::FASTA(path) => {
+                FastaFileSequencesStream::get_estimated_decompressed_size(path)
+            }
+            _ => self.estimated_bases_count(),
+        }
+    }
+
+    /// The color name of the index-th input when each input is a separate color,
+    /// the file name without its extensions (and compression suffix)
+    pub fn get_file_color_name(&self, index: usize) -> String {
+        match self {
+            GeneralSequenceBlockData::FASTA(path) => get_file_color_name(path),
+            GeneralSequenceBlockData::Alignment(block) => get_file_color_name(&block.path),
+            GeneralSequenceBlockData::Tar(block) => get_file_color_name(&block.member),
+            GeneralSequenceBlockData::GFA() | GeneralSequenceBlockData::Dynamic(_) => {
+                format!("input{}", index)
+            }
+        }
+    }
+}
+
+fn get_file_color_name(path: &Path) -> String {
+    let mut name = Path::new(path.file_name().unwrap_or_default());
+    if name
+        .extension()
+        .map(|x| x == "gz" || x == "lz4")
+        .unwrap_or(false)
+    {
+        name = Path::new(name.file_stem().unwrap());
+    }
+    name.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
 }
 
 pub struct GeneralSequencesStream {
     fasta_file_reader: Option<FastaFileSequencesStream>,
+    alignment_file_reader: Option<AlignmentFileSequencesStream>,
+    tar_archive_reader: Option<TarArchiveSequencesStream>,
 }
 
 impl GenericSequencesStream for GeneralSequencesStream {
@@ -47,9 +99,15 @@ impl GenericSequencesStream for GeneralSequencesStream {
     fn new() -> Self {
         Self {
             fasta_file_reader: None,
+            alignment_file_reader: None,
+            tar_archive_reader: None,
         }
     }
 
+    fn estimated_decompressed_size(block: &Self::SequenceBlockData) -> u64 {
+        block.estimated_decompressed_size()
+    }
+
     fn read_block(
         &mut self,
         block: &Self::SequenceBlockData,
@@ -72,6 +130,28 @@ impl GenericSequencesStream for GeneralSequencesStream {
             GeneralSequenceBlockData::GFA() => {
                 unimplemented!();
             }
+            GeneralSequenceBlockData::Alignment(block) => {
+                if self.alignment_file_reader.is_none() {
+                    self.alignment_file_reader = Some(AlignmentFileSequencesStream::new());
+                }
+                self.alignment_file_reader.as_mut().unwrap().read_block(
+                    block,
+                    copy_ident_data,
+                    partial_read_copyback,
+                    callback,
+                );
+            }
+            GeneralSequenceBlockData::Tar(block) => {
+                if self.tar_archive_reader.is_none() {
+                    self.tar_archive_reader = Some(TarArchiveSequencesStream::new());
+                }
+                self.tar_archive_reader.as_mut().unwrap().read_block(
+                    block,
+                    copy_ident_data,
+                    partial_read_copyback,
+                    callback,
+                );
+            }
             GeneralSequenceBlockData::Dynamic((reader, index)) => {
                 reader.read_block(
                     *index,
@@ -83,3 +163,26 @@ impl GenericSequencesStream for GeneralSequencesStream {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::GeneralSequenceBlockData;
+    use std::path::PathBuf;
+
+    #[test]
+    fn colors_named_after_files() {
+        let inputs = [
+            GeneralSequenceBlockData::FASTA(PathBuf::from("inputs/sal1.fa")),
+            GeneralSequenceBlockData::FASTA(PathBuf::from("inputs/sal2.fasta.gz")),
+            GeneralSequenceBlockData::FASTA(PathBuf::from("sal3.fq.lz4")),
+        ];
+
+        let color_names: Vec<_> = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, input)| input.get_file_color_name(i))
+            .collect();
+
+        assert_eq!(color_names, ["sal1", "sal2", "sal3"]);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::sequences_reader::{DnaSequence, DnaSequencesFileType};
+use crate::sequences_stream::SequenceInfo;
+use std::path::Path;
+
+/// Splits a FASTQ ident line (without the leading @) in the read name and the comment
+fn split_ident(ident: &[u8]) -> (&[u8], &[u8]) {
+    let ident = ident.strip_prefix(b"@").unwrap_or(ident);
+    match ident.iter().position(|b| b.is_ascii_whitespace()) {
+        Some(position) => (&ident[..position], ident[position + 1..].trim_ascii_start()),
+        None => (ident, &[]),
+    }
+}
+
+/// Checks that two records are the mates of a pair, either named <name>/1 and <name>/2
+/// or sharing the name with a comment starting with 1 and 2 (e.g. "<name> 1:N:0:ACGT")
+fn is_mates_pair(first_ident: &[u8], second_ident: &[u8]) -> bool {
+    let (first_name, first_comment) = split_ident(first_ident);
+    let (second_name, second_comment) = split_ident(second_ident);
+
+    match (
+        first_name.strip_suffix(b"/1"),
+        second_name.strip_suffix(b"/2"),
+    ) {
+        (Some(first_stem), Some(second_stem)) => first_stem == second_stem,
+        _ => {
+            first_name == second_name
+                && first_comment.first() == Some(&b'1')
+                && second_comment.first() == Some(&b'2')
+        }
+    }
+}
+
+/// Assigns the pair ids to the records of an interleaved paired-end FASTQ (R1, R2, R1, R2, ...),
+/// the two mates of the n-th pair (counting from 0) both get the id n
+pub struct InterleavedPairs {
+    records_count: u64,
+    first_mate_ident: Vec<u8>,
+}
+
+impl InterleavedPairs {
+    pub fn new() -> Self {
+        Self {
+            records_count: 0,
+            first_mate_ident: vec![],
+        }
+    }
+
+    /// Returns the pair id of the next record, or an error describing the offending record
+    /// if it is not the mate of the previous one
+    pub fn next_pair_id(&mut self, sequence: &DnaSequence) -> Result<u64, String> {
+        let pair_id = self.records_count / 2;
+        if self.records_count % 2 == 0 {
+            self.first_mate_ident.clear();
+            self.first_mate_ident.extend_from_slice(sequence.ident_data);
+        } else if !is_mates_pair(&self.first_mate_ident, sequence.ident_data) {
+            return Err(format!(
+                "record {} '{}' is not the mate of record {} '{}' (expected the <name>/1, <name>/2 or <name> 1, <name> 2 names)",
+                self.records_count + 1,
+                String::from_utf8_lossy(sequence.ident_data),
+                self.records_count,
+                String::from_utf8_lossy(&self.first_mate_ident),
+            ));
+        }
+        self.records_count += 1;
+
+        Ok(pair_id)
+    }
+
+    /// Checks that the last pair is complete
+    pub fn finish(&self) -> Result<(), String> {
+        if self.records_count % 2 != 0 {
+            Err(format!(
+                "the last record {} '{}' has no mate",
+                self.records_count,
+                String::from_utf8_lossy(&self.first_mate_ident),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The info of a sequence read from source, with its pair id if the source is an interleaved FASTQ.
+/// The FASTA sequences are never paired
+pub(crate) fn paired_sequence_info(
+    pairs: &mut Option<InterleavedPairs>,
+    sequence: &DnaSequence,
+    source: &Path,
+) -> SequenceInfo {
+    let pair_id = match pairs {
+        Some(pairs) if matches!(sequence.format, DnaSequencesFileType::FASTQ) => {
+            Some(pairs.next_pair_id(sequence).unwrap_or_else(|err| {
+                panic!(
+                    "Invalid interleaved paired reads in {}: {}",
+                    source.display(),
+                    err
+                )
+            }))
+        }
+        _ => None,
+    };
+
+    SequenceInfo {
+        color: None,
+        pair_id,
+    }
+}
+
+pub(crate) fn check_pairs_complete(pairs: Option<InterleavedPairs>, source: &Path) {
+    if let Some(pairs) = pairs {
+        pairs.finish().unwrap_or_else(|err| {
+            panic!(
+                "Invalid interleaved paired reads in {}: {}",
+                source.display(),
+                err
+            )
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_mates_pair;
+    use crate::sequences_stream::fasta::FastaFileSequencesStream;
+    use crate::sequences_stream::GenericSequencesStream;
+    use utils::test_dir::TestDir;
+
+    fn read_pair_ids(name: &str, contents: &[u8]) -> Vec<(String, Option<u64>)> {
+        let test_dir = TestDir::new(&format!("interleaved-{}", name));
+        let path = test_dir.join("reads.fq");
+        std::fs::write(&path, contents).unwrap();
+
+        let mut records = vec![];
+        FastaFileSequencesStream::new()
+            .with_interleaved(true)
+            .read_block(&path, true, None, |seq, info| {
+                records.push((
+                    String::from_utf8_lossy(seq.ident_data).into_owned(),
+                    info.pair_id,
+                ))
+            });
+        records
+    }
+
+    #[test]
+    fn interleaved_pair_ids() {
+        let records = read_pair_ids(
+            "valid",
+            concat!(
+                "@r1/1\nACGT\n+\nIIII\n",
+                "@r1/2\nTTGA\n+\nIIII\n",
+                "@r2 1:N:0:ACGT\nGGCA\n+\nIIII\n",
+                "@r2 2:N:0:ACGT\nCATT\n+\nIIII\n",
+            )
+            .as_bytes(),
+        );
+        assert_eq!(
+            records,
+            [
+                ("@r1/1".to_string(), Some(0)),
+                ("@r1/2".to_string(), Some(0)),
+                ("@r2 1:N:0:ACGT".to_string(), Some(1)),
+                ("@r2 2:N:0:ACGT".to_string(), Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "record 4 '@r3/1' is not the mate of record 3 '@r2/1'")]
+    fn interleaved_mismatched_mates() {
+        read_pair_ids(
+            "mismatched",
+            b"@r1/1\nACGT\n+\nIIII\n@r1/2\nTTGA\n+\nIIII\n@r2/1\nGGCA\n+\nIIII\n@r3/1\nCATT\n+\nIIII\n",
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the last record 3 '@r2/1' has no mate")]
+    fn interleaved_missing_last_mate() {
+        read_pair_ids(
+            "unpaired",
+            b"@r1/1\nACGT\n+\nIIII\n@r1/2\nTTGA\n+\nIIII\n@r2/1\nGGCA\n+\nIIII\n",
+        );
+    }
+
+    #[test]
+    fn mates_names() {
+        assert!(is_mates_pair(b"@a/1", b"@a/2"));
+        assert!(is_mates_pair(b"@a 1", b"@a 2"));
+        assert!(!is_mates_pair(b"@a/1", b"@b/2"));
+        assert!(!is_mates_pair(b"@a/2", b"@a/1"));
+        assert!(!is_mates_pair(b"@a", b"@a"));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::sequences_reader::{DnaSequence, SequencesReader};
+use crate::sequences_stream::interleaved::{
+    check_pairs_complete, paired_sequence_info, InterleavedPairs,
+};
+use crate::sequences_stream::{GenericSequencesStream, SequenceInfo};
+use config::{get_temp_file_path, INTERLEAVED_PAIRED_READS};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tar::Archive;
+
+/// The uncompressed tar data the members are read from: the archive itself, or for a compressed
+/// archive a temporary copy decompressed once, removed when the last of its members is dropped
+struct TarData {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl Drop for TarData {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// A FASTA/FASTQ file stored inside a tar archive, read without extracting it
+pub struct TarMemberBlockData {
+    /// The tar archive, optionally gzip compressed (.tar.gz or .tgz)
+    pub archive: PathBuf,
+    /// The path of the sequences file inside the archive
+    pub member: PathBuf,
+    /// Position of the member data inside the uncompressed tar data
+    data_position: u64,
+    size: u64,
+    data: Arc<TarData>,
+}
+
+impl TarMemberBlockData {
+    pub fn is_tar_archive(path: &PathBuf) -> bool {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+    }
+
+    fn is_compressed(archive: &Path) -> bool {
+        archive
+            .extension()
+            .map(|x| x == "gz" || x == "tgz")
+            .unwrap_or(false)
+    }
+
+    /// Decompresses a compressed archive to a temporary tar file inside temp_dir
+    fn decompress_archive(archive: &Path, temp_dir: &Path) -> TarData {
+        // Archives with the same name in different directories get different temporary files
+        static DECOMPRESSED_ARCHIVES: AtomicUsize = AtomicUsize::new(0);
+
+        std::fs::create_dir_all(temp_dir).unwrap();
+        let path = get_temp_file_path(temp_dir.join(format!(
+            "{}.{}.tar",
+            archive.file_name().unwrap().to_string_lossy(),
+            DECOMPRESSED_ARCHIVES.fetch_add(1, Ordering::Relaxed)
+        )));
+
+        let file = File::open(archive).expect(&format!("Cannot open file {}", archive.display()));
+        let mut decoder = flate2::read::MultiGzDecoder::new(file);
+        let mut output = BufWriter::new(
+            File::create(&path).expect(&format!("Cannot create file {}", path.display())),
+        );
+        std::io::copy(&mut decoder, &mut output)
+            .and_then(|_| output.flush())
+            .expect(&format!(
+                "Cannot decompress tar archive {}",
+                archive.display()
+            ));
+
+        TarData {
+            path,
+            temporary: true,
+        }
+    }
+
+    /// Lists the sequences files contained in a tar archive, in archive order, one block for each of them.
+    /// The members that are not FASTA/FASTQ files (by extension) are skipped.
+    /// A compressed archive is decompressed once to a temporary file in temp_dir, shared by its members
+    pub fn list_archive_members(archive: &Path, temp_dir: &Path) -> Vec<TarMemberBlockData> {
+        let data = Arc::new(if Self::is_compressed(archive) {
+            Self::decompress_archive(archive, temp_dir)
+        } else {
+            TarData {
+                path: archive.to_path_buf(),
+                temporary: false,
+            }
+        });
+
+        let mut tar = Archive::new(
+            File::open(&data.path).expect(&format!("Cannot open file {}", data.path.display())),
+        );
+        let mut members = vec![];
+
+        for entry in tar
+            .entries()
+            .expect(&format!("Cannot read tar archive {}", archive.display()))
+        {
+            let entry = entry.expect(&format!("Cannot read tar archive {}", archive.display()));
+
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let member = entry
+                .path()
+                .expect(&format!("Invalid member path in {}", archive.display()))
+                .to_path_buf();
+
+            if SequencesReader::get_file_type(&member).is_none() {
+                continue;
+            }
+
+            members.push(TarMemberBlockData {
+                archive: archive.to_path_buf(),
+                member,
+                data_position: entry.raw_file_position(),
+                size: entry.size(),
+                data: data.clone(),
+            });
+        }
+
+        members
+    }
+}
+
+/// Reads the sequences of a single member of a tar archive, seeking directly to the member
+/// inside the uncompressed tar data
+pub struct TarArchiveSequencesStream {
+    sequences_reader: SequencesReader,
+    interleaved: bool,
+}
+
+impl GenericSequencesStream for TarArchiveSequencesStream {
+    type SequenceBlockData = TarMemberBlockData;
+
+    fn new() -> Self {
+        Self {
+            sequences_reader: SequencesReader::new(),
+            interleaved: INTERLEAVED_PAIRED_READS.load(Ordering::Relaxed),
+        }
+    }
+
+    fn estimated_decompressed_size(block: &Self::SequenceBlockData) -> u64 {
+        block.size
+    }
+
+    fn read_block(
+        &mut self,
+        block: &Self::SequenceBlockData,
+        copy_ident_data: bool,
+        partial_read_copyback: Option<usize>,
+        mut callback: impl FnMut(DnaSequence, SequenceInfo),
+    ) {
+        let source = block.archive.join(&block.member);
+        let mut pairs = self.interleaved.then(InterleavedPairs::new);
+        let mut paired_callback = |x: DnaSequence| {
+            let info = paired_sequence_info(&mut pairs, &x, &source);
+            callback(x, info)
+        };
+
+        let mut file = File::open(&block.data.path)
+            .expect(&format!("Cannot open file {}", block.data.path.display()));
+        file.seek(SeekFrom::Start(block.data_position)).unwrap();
+        self.sequences_reader.process_stream_extended(
+            file.take(block.size),
+            &block.member,
+            &mut paired_callback,
+            partial_read_copyback,
+            copy_ident_data,
+        );
+        check_pairs_complete(pairs, &source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TarMemberBlockData;
+    use crate::sequences_stream::general::{GeneralSequenceBlockData, GeneralSequencesStream};
+    use crate::sequences_stream::GenericSequencesStream;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use utils::test_dir::TestDir;
+
+    fn append_file(builder: &mut tar::Builder<impl Write>, path: &str, contents: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents).unwrap();
+    }
+
+    fn write_archive(path: &PathBuf, writer: impl Write) {
+        let mut builder = tar::Builder::new(writer);
+        append_file(
+            &mut builder,
+            "genomes/sal1.fa",
+            b">s1\nACGTACGT\nTTGA\n>s2\nGGGCCC\n",
+        );
+        append_file(&mut builder, "README", b"Not a sequences file\n");
+        append_file(&mut builder, "sal2.fasta", b">s3\nCATTAG\n");
+        builder.into_inner().unwrap().flush().unwrap();
+        assert!(TarMemberBlockData::is_tar_archive(path));
+    }
+
+    fn read_members(archive: &PathBuf, temp_dir: &Path) -> Vec<(String, Vec<Vec<u8>>)> {
+        TarMemberBlockData::list_archive_members(archive, temp_dir)
+            .into_iter()
+            .enumerate()
+            .map(|(index, member)| {
+                let block = GeneralSequenceBlockData::Tar(member);
+                let mut sequences = vec![];
+                GeneralSequencesStream::new().read_block(&block, false, None, |seq, _| {
+                    sequences.push(seq.seq.to_vec())
+                });
+                (block.get_file_color_name(index), sequences)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn read_tar_members() {
+        let test_dir = TestDir::new("tar");
+        let temp_dir = test_dir.join("temp");
+
+        let plain = test_dir.join("reads.tar");
+        write_archive(&plain, File::create(&plain).unwrap());
+
+        let compressed = test_dir.join("reads.tar.gz");
+        write_archive(
+            &compressed,
+            GzEncoder::new(File::create(&compressed).unwrap(), Compression::default()),
+        );
+
+        let expected = vec![
+            (
+                "sal1".to_string(),
+                vec![b"ACGTACGTTTGA".to_vec(), b"GGGCCC".to_vec()],
+            ),
+            ("sal2".to_string(), vec![b"CATTAG".to_vec()]),
+        ];
+
+        let from_plain = read_members(&plain, &temp_dir);
+        let from_compressed = read_members(&compressed, &temp_dir);
+
+        // The compressed archive is decompressed once, to a temporary file shared by its
+        // members and removed with them
+        let members = TarMemberBlockData::list_archive_members(&compressed, &temp_dir);
+        assert_eq!(members.len(), 2);
+        assert!(Arc::ptr_eq(&members[0].data, &members[1].data));
+        let decompressed = members[0].data.path.clone();
+        assert!(decompressed.starts_with(&temp_dir));
+        assert!(decompressed.exists());
+        drop(members);
+        assert!(!decompressed.exists());
+
+        assert_eq!(from_plain, expected);
+        assert_eq!(from_compressed, expected);
+    }
+}
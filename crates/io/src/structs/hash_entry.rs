@@ -23,18 +23,28 @@ pub struct HashEntry<H: Copy> {
     // pub bucket: BucketIndexType,
     // pub entry: u64,
     // pub direction: Direction,
+    // pub palindrome: bool,
 }
 
 impl<H: Copy> HashEntry<H> {
-    const ENTRY_OFFSET: usize = (size_of::<BucketIndexType>() * 8) + 1;
-    const BUCKET_OFFSET: usize = 1;
+    const ENTRY_OFFSET: usize = (size_of::<BucketIndexType>() * 8) + 2;
+    const BUCKET_OFFSET: usize = 2;
+    const PALINDROME_OFFSET: usize = 1;
     const DIRECTION_OFFSET: usize = 0;
 
-    pub fn new(hash: H, bucket: BucketIndexType, entry: u64, direction: Direction) -> Self {
+    /// palindrome is set if the kmer is its own reverse complement (only possible with an even k)
+    pub fn new(
+        hash: H,
+        bucket: BucketIndexType,
+        entry: u64,
+        direction: Direction,
+        palindrome: bool,
+    ) -> Self {
         Self {
             hash,
             encoded: (entry << Self::ENTRY_OFFSET)
                 | ((bucket as u64) << Self::BUCKET_OFFSET)
+                | ((palindrome as u64) << Self::PALINDROME_OFFSET)
                 | ((match direction {
                     Direction::Forward => 1,
                     Direction::Backward => 0,
@@ -50,6 +60,10 @@ impl<H: Copy> HashEntry<H> {
         (self.encoded >> Self::BUCKET_OFFSET) as BucketIndexType
     }
 
+    pub fn is_palindrome(&self) -> bool {
+        (self.encoded >> Self::PALINDROME_OFFSET) & 0x1 != 0
+    }
+
     pub fn direction(&self) -> Direction {
         if (self.encoded >> Self::DIRECTION_OFFSET) & 0x1 == 0 {
             Direction::Backward
@@ -3,8 +3,9 @@ use crate::varint::{decode_varint, encode_varint, VARINT_MAX_SIZE};
 use byteorder::ReadBytesExt;
 use config::BucketIndexType;
 use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+use std::error::Error;
 use std::fmt;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::io::{Read, Write};
 use utils::vec_slice::VecSlice;
@@ -241,6 +242,53 @@ pub struct UnitigLink {
     pub entries: VecSlice<UnitigIndex>,
 }
 
+/// Error raised when the links read from disk are corrupted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinksCorruptionError {
+    /// A link references entries outside of its backing buffer
+    EntriesOutOfBounds {
+        bucket: BucketIndexType,
+        entry: u64,
+        slice_pos: usize,
+        slice_len: usize,
+        backing_len: usize,
+    },
+    /// The bucket ends in the middle of a link
+    TruncatedLink {
+        bucket: BucketIndexType,
+        links_read: usize,
+    },
+}
+
+impl Display for LinksCorruptionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LinksCorruptionError::EntriesOutOfBounds {
+                bucket,
+                entry,
+                slice_pos,
+                slice_len,
+                backing_len,
+            } => write!(
+                f,
+                "Corrupted link data in bucket {} for entry {}: entries range {}..{} is out of bounds (buffer length {})",
+                bucket,
+                entry,
+                slice_pos,
+                slice_pos.saturating_add(*slice_len),
+                backing_len
+            ),
+            LinksCorruptionError::TruncatedLink { bucket, links_read } => write!(
+                f,
+                "Corrupted link data in bucket {}: the bucket ends in the middle of a link, after {} links",
+                bucket, links_read
+            ),
+        }
+    }
+}
+
+impl Error for LinksCorruptionError {}
+
 impl UnitigLink {
     const ENTRY_OFFSET: usize = 8;
 
@@ -259,6 +307,23 @@ impl UnitigLink {
         UnitigFlags(self.encoded as u8)
     }
 
+    /// Checked access to the link entries, to validate links loaded from disk
+    pub fn try_get_entries<'a>(
+        &self,
+        bucket: BucketIndexType,
+        backing: &'a Vec<UnitigIndex>,
+    ) -> Result<&'a [UnitigIndex], LinksCorruptionError> {
+        self.entries.try_get_slice(backing).ok_or_else(|| {
+            LinksCorruptionError::EntriesOutOfBounds {
+                bucket,
+                entry: self.entry(),
+                slice_pos: self.entries.pos,
+                slice_len: self.entries.len(),
+                backing_len: backing.len(),
+            }
+        })
+    }
+
     pub fn change_flags(&mut self, change_fn: impl FnOnce(&mut UnitigFlags)) {
         let mut flags = self.flags();
         change_fn(&mut flags);
@@ -336,3 +401,129 @@ impl BucketItemSerializer for UnitigLinkSerializer {
         16 + element.entries.len() * VARINT_MAX_SIZE * 2
     }
 }
+
+/// Stream that can check if it is at the end without consuming a byte,
+/// to tell a link boundary from a link truncated in the middle
+struct LinksStream<S: Read> {
+    inner: S,
+    peeked: Option<u8>,
+}
+
+impl<S: Read> LinksStream<S> {
+    fn is_finished(&mut self) -> bool {
+        if self.peeked.is_none() {
+            self.peeked = self.inner.read_u8().ok();
+        }
+        self.peeked.is_none()
+    }
+}
+
+impl<S: Read> Read for LinksStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match (self.peeked.take(), buf.first_mut()) {
+            (Some(byte), Some(first)) => {
+                *first = byte;
+                Ok(1)
+            }
+            (peeked, _) => {
+                self.peeked = peeked;
+                self.inner.read(buf)
+            }
+        }
+    }
+}
+
+/// Reads all the links of a bucket, checking that each one is complete and that its entries
+/// are inside read_buffer, where they are appended. The callback receives each checked link
+/// with read_buffer, that it can clear if the entries are not needed anymore
+pub fn read_links_bucket<S: Read>(
+    stream: S,
+    bucket: BucketIndexType,
+    read_buffer: &mut Vec<UnitigIndex>,
+    mut callback: impl FnMut(UnitigLink, &mut Vec<UnitigIndex>),
+) -> Result<(), LinksCorruptionError> {
+    let mut stream = LinksStream {
+        inner: stream,
+        peeked: None,
+    };
+    let mut deserializer = UnitigLinkSerializer::new();
+    let mut links_read = 0;
+
+    while !stream.is_finished() {
+        let link = deserializer
+            .read_from(&mut stream, read_buffer, &mut ())
+            .ok_or(LinksCorruptionError::TruncatedLink { bucket, links_read })?;
+        link.try_get_entries(bucket, read_buffer)?;
+        callback(link, read_buffer);
+        links_read += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read_links_bucket, LinksCorruptionError, UnitigFlags, UnitigIndex, UnitigLink,
+        UnitigLinkSerializer,
+    };
+    use parallel_processor::buckets::bucket_writer::BucketItemSerializer;
+    use std::io::Cursor;
+    use utils::vec_slice::VecSlice;
+
+    fn links_bucket() -> Vec<u8> {
+        let entries = vec![
+            UnitigIndex::new(3, 10, false),
+            UnitigIndex::new(5, 200, true),
+            UnitigIndex::new(1, 7, false),
+        ];
+        let mut serializer = UnitigLinkSerializer::new();
+        let mut bucket = vec![];
+        serializer.write_to(
+            &UnitigLink::new(1, UnitigFlags::new_empty(), VecSlice::new(0, 2)),
+            &mut bucket,
+            &entries,
+            &(),
+        );
+        serializer.write_to(
+            &UnitigLink::new(300, UnitigFlags::new_empty(), VecSlice::new(2, 1)),
+            &mut bucket,
+            &entries,
+            &(),
+        );
+        bucket
+    }
+
+    fn read_entries(bucket: &[u8]) -> Result<Vec<(u64, usize)>, LinksCorruptionError> {
+        let mut links = vec![];
+        read_links_bucket(Cursor::new(bucket), 0, &mut vec![], |link, read_buffer| {
+            links.push((link.entry(), link.entries.get_slice(read_buffer).len()))
+        })?;
+        Ok(links)
+    }
+
+    #[test]
+    fn read_complete_links_bucket() {
+        assert_eq!(read_entries(&links_bucket()), Ok(vec![(1, 2), (300, 1)]));
+        assert_eq!(read_entries(&[]), Ok(vec![]));
+    }
+
+    #[test]
+    fn truncated_links_bucket_is_an_error() {
+        let bucket = links_bucket();
+
+        assert_eq!(
+            read_entries(&bucket[..bucket.len() - 1]),
+            Err(LinksCorruptionError::TruncatedLink {
+                bucket: 0,
+                links_read: 1
+            })
+        );
+        assert_eq!(
+            read_entries(&bucket[..2]),
+            Err(LinksCorruptionError::TruncatedLink {
+                bucket: 0,
+                links_read: 0
+            })
+        );
+    }
+}
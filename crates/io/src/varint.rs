@@ -1,7 +1,12 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 use std::mem::MaybeUninit;
 
+/// Maximum encoded size of a u64 with flags, enough for up to 6 flags bits
 pub const VARINT_FLAGS_MAX_SIZE: usize = 10;
-pub const VARINT_MAX_SIZE: usize = 9;
+/// Maximum encoded size of a u64, 7 bits for each byte
+pub const VARINT_MAX_SIZE: usize = (u64::BITS as usize + 6) / 7;
 
 #[inline(always)]
 #[allow(clippy::uninit_assumed_init)]
@@ -99,12 +104,121 @@ pub fn decode_varint(mut read_byte: impl FnMut() -> Option<u8>) -> Option<u64> {
     Some(result)
 }
 
+/// Error raised when a varint stream ends or overflows in the middle of a number
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    TruncatedVarint,
+    VarintOverflow,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TruncatedVarint => f.write_str("Stream ended in the middle of a varint"),
+            DecodeError::VarintOverflow => f.write_str("Varint does not fit in 64 bits"),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Like decode_varint, but distinguishes a clean end of stream (Ok(None))
+/// from a stream that ends in the middle of a number
+#[inline(always)]
+pub fn try_decode_varint(
+    mut read_byte: impl FnMut() -> Option<u8>,
+) -> Result<Option<u64>, DecodeError> {
+    let mut value = match read_byte() {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let mut result = 0;
+    let mut offset = 0u32;
+    loop {
+        // The last byte can carry only the highest bit of the value
+        if offset >= u64::BITS || (value & 0b1111111) >> (u64::BITS - offset).min(7) != 0 {
+            return Err(DecodeError::VarintOverflow);
+        }
+        result |= ((value & 0b1111111) as u64) << offset;
+        if (value & 0b10000000) == 0 {
+            break;
+        }
+        offset += 7;
+        value = read_byte().ok_or(DecodeError::TruncatedVarint)?;
+    }
+    Ok(Some(result))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::varint::{decode_varint, decode_varint_flags, encode_varint, encode_varint_flags};
+    use crate::varint::{
+        decode_varint, decode_varint_flags, encode_varint, encode_varint_flags, try_decode_varint,
+        DecodeError,
+    };
+    use crate::varint::{VARINT_FLAGS_MAX_SIZE, VARINT_MAX_SIZE};
     use byteorder::ReadBytesExt;
+    use rand::{Rng, SeedableRng};
     use std::io::{Cursor, Write};
 
+    #[test]
+    fn varints_full_range() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(35);
+
+        let edge_values = [
+            0,
+            1,
+            127,
+            128,
+            (1 << 63) - 1,
+            1 << 63,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+        // Random values of all the bit lengths
+        let random_values = (0..100000).map(|_| rng.gen::<u64>() >> rng.gen_range(0..64));
+
+        let mut result: Vec<u8> = vec![];
+        for value in edge_values.into_iter().chain(random_values) {
+            result.clear();
+            encode_varint(|b| result.write_all(b), value).unwrap();
+            assert!(result.len() <= VARINT_MAX_SIZE);
+
+            let mut cursor = Cursor::new(&result);
+            assert_eq!(decode_varint(|| cursor.read_u8().ok()), Some(value));
+            assert_eq!(cursor.position() as usize, result.len());
+
+            let mut cursor = Cursor::new(&result);
+            assert_eq!(try_decode_varint(|| cursor.read_u8().ok()), Ok(Some(value)));
+
+            result.clear();
+            let flags = (value % 4) as u8;
+            encode_varint_flags::<_, _, typenum::U2>(|b| result.write_all(b), value, flags)
+                .unwrap();
+            assert!(result.len() <= VARINT_FLAGS_MAX_SIZE);
+
+            let mut cursor = Cursor::new(&result);
+            assert_eq!(
+                decode_varint_flags::<_, typenum::U2>(|| cursor.read_u8().ok()),
+                Some((value, flags))
+            );
+        }
+
+        assert_eq!(VARINT_MAX_SIZE, 10);
+    }
+
+    #[test]
+    fn varint_overflow() {
+        // 10 bytes carrying more than 64 bits
+        let mut bytes = vec![0xFF; 9];
+        bytes.push(0x02);
+        let mut cursor = Cursor::new(&bytes);
+        assert_eq!(
+            try_decode_varint(|| cursor.read_u8().ok()),
+            Err(DecodeError::VarintOverflow)
+        );
+    }
+
     #[test]
     fn varints() {
         let mut result: Vec<u8> = vec![];
@@ -135,4 +249,23 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn truncated_varint() {
+        let mut result: Vec<u8> = vec![];
+        encode_varint(|b| result.write_all(b), 1 << 20).unwrap();
+        encode_varint(|b| result.write_all(b), 1 << 40).unwrap();
+        let full_len = result.len();
+        result.pop();
+
+        let mut cursor = Cursor::new(&result);
+        let mut read = || try_decode_varint(|| cursor.read_u8().ok());
+        assert_eq!(read(), Ok(Some(1 << 20)));
+        assert_eq!(read(), Err(DecodeError::TruncatedVarint));
+
+        let mut cursor = Cursor::new(&result[..full_len - 6]);
+        let mut read = || try_decode_varint(|| cursor.read_u8().ok());
+        assert_eq!(read(), Ok(Some(1 << 20)));
+        assert_eq!(read(), Ok(None));
+    }
 }
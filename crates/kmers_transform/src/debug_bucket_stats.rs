@@ -3,8 +3,8 @@ use config::{
     USE_SECOND_BUCKET,
 };
 use hashes::{
-    ExtendableHashTraitType, HashFunction, HashFunctionFactory, HashableSequence,
-    MinimizerHashFunctionFactory,
+    minimizer_order_key, ExtendableHashTraitType, HashFunction, HashFunctionFactory,
+    HashableSequence, MinimizerHashFunctionFactory,
 };
 use io::compressed_read::CompressedRead;
 use io::concurrent::temp_reads::creads_utils::CompressedReadsBucketDataSerializer;
@@ -14,6 +14,7 @@ use parallel_processor::buckets::readers::async_binary_reader::{
 use parallel_processor::memory_fs::RemoveFileMode;
 use std::collections::HashSet;
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 
 fn get_sequence_bucket<C, H: MinimizerHashFunctionFactory>(
     k: usize,
@@ -30,7 +31,7 @@ fn get_sequence_bucket<C, H: MinimizerHashFunctionFactory>(
 
     let minimizer = hashes
         .iter()
-        .min_by_key(|k| H::get_full_minimizer(k.to_unextendable()))
+        .min_by_key(|k| minimizer_order_key::<H>(k.to_unextendable()))
         .unwrap();
 
     H::get_bucket(
@@ -40,6 +41,45 @@ fn get_sequence_bucket<C, H: MinimizerHashFunctionFactory>(
     )
 }
 
+fn collect_bucket_stats<
+    H: MinimizerHashFunctionFactory,
+    MH: HashFunctionFactory,
+    const WITH_SECOND_BUCKET: bool,
+>(
+    reader: &AsyncBinaryReader,
+    buckets_count: usize,
+    second_buckets_log_max: usize,
+    k: usize,
+    m: usize,
+    hash_maps: &mut [HashSet<MH::HashTypeUnextendable>],
+    total_counters: &mut [usize],
+) {
+    let reader_thread = AsyncReaderThread::new(DEFAULT_OUTPUT_BUFFER_SIZE, 4);
+
+    let mut items_iterator = reader.get_items_stream::<CompressedReadsBucketDataSerializer<
+        (),
+        typenum::U2,
+        WITH_SECOND_BUCKET,
+    >>(reader_thread.clone(), Vec::new(), ());
+
+    while let Some((read_info, _)) = items_iterator.next() {
+        let orig_bucket = get_sequence_bucket::<(), H>(
+            k,
+            m,
+            &read_info,
+            buckets_count.ilog2() as usize,
+            second_buckets_log_max,
+        ) as usize;
+
+        let hashes = MH::new(read_info.3, k);
+
+        for hash in hashes.iter() {
+            total_counters[orig_bucket] += 1;
+            hash_maps[orig_bucket].insert(hash.to_unextendable());
+        }
+    }
+}
+
 pub fn compute_stats_for_bucket<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory>(
     bucket: PathBuf,
     bucket_index: usize,
@@ -57,37 +97,34 @@ pub fn compute_stats_for_bucket<H: MinimizerHashFunctionFactory, MH: HashFunctio
 
     let file_size = reader.get_file_size();
 
-    let reader_thread = AsyncReaderThread::new(DEFAULT_OUTPUT_BUFFER_SIZE, 4);
-
     let second_buckets_max = 1 << second_buckets_log_max;
 
     let mut hash_maps = (0..second_buckets_max)
         .map(|_| HashSet::new())
         .collect::<Vec<_>>();
 
-    let mut items_iterator = reader.get_items_stream::<CompressedReadsBucketDataSerializer<
-        (),
-        typenum::U2,
-        { USE_SECOND_BUCKET },
-    >>(reader_thread.clone(), Vec::new(), ());
-
     let mut total_counters = vec![0; second_buckets_max];
 
-    while let Some((read_info, _)) = items_iterator.next() {
-        let orig_bucket = get_sequence_bucket::<(), H>(
+    if USE_SECOND_BUCKET.load(Ordering::Relaxed) {
+        collect_bucket_stats::<H, MH, true>(
+            &reader,
+            buckets_count,
+            second_buckets_log_max,
             k,
             m,
-            &read_info,
-            buckets_count.ilog2() as usize,
+            &mut hash_maps,
+            &mut total_counters,
+        );
+    } else {
+        collect_bucket_stats::<H, MH, false>(
+            &reader,
+            buckets_count,
             second_buckets_log_max,
-        ) as usize;
-
-        let hashes = MH::new(read_info.3, k);
-
-        for hash in hashes.iter() {
-            total_counters[orig_bucket] += 1;
-            hash_maps[orig_bucket].insert(hash.to_unextendable());
-        }
+            k,
+            m,
+            &mut hash_maps,
+            &mut total_counters,
+        );
     }
 
     let counters_string = hash_maps
@@ -100,8 +137,8 @@ pub fn compute_stats_for_bucket<H: MinimizerHashFunctionFactory, MH: HashFunctio
     let tot_seqs = total_counters.iter().sum::<usize>();
     let uniq_seqs = hash_maps.iter().map(|h| h.len()).sum::<usize>();
 
-    println!("Stats for bucket: {}", bucket_index);
-    println!(
+    log::debug!("Stats for bucket: {}", bucket_index);
+    log::debug!(
         "FSIZE: {} SEQUENCES: {}/{} UNIQUE_RATIO: {} COMPR_RATIO: {} ",
         file_size,
         tot_seqs,
@@ -109,5 +146,5 @@ pub fn compute_stats_for_bucket<H: MinimizerHashFunctionFactory, MH: HashFunctio
         (tot_seqs as f64 / uniq_seqs as f64),
         (file_size as f64 / tot_seqs as f64)
     );
-    println!("Results: {}", counters_string);
+    log::debug!("Results: {}", counters_string);
 }
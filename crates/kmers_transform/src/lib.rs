@@ -6,9 +6,12 @@ mod reader;
 use crate::processor::KmersTransformProcessor;
 use crate::reader::{InputBucketDesc, KmersTransformReader};
 use crate::resplitter::KmersTransformResplitter;
+use crate::scratch_buffers::ScratchBuffersPool;
 use config::{
-    BucketIndexType, KEEP_FILES, KMERS_TRANSFORM_READS_CHUNKS_SIZE, MAXIMUM_JIT_PROCESSED_BUCKETS,
-    MAXIMUM_SECOND_BUCKETS_COUNT, MINIMUM_LOG_DELTA_TIME, PACKETS_PRIORITY_FILES,
+    get_packets_pool_capacity, BucketIndexType, PacketsPoolExecutor, DEFAULT_PER_CPU_BUFFER_SIZE,
+    KEEP_FILES, KMERS_TRANSFORM_READS_BUFFER_CAPACITY, KMERS_TRANSFORM_READS_CHUNKS_SIZE,
+    MAXIMUM_JIT_PROCESSED_BUCKETS, MAXIMUM_SECOND_BUCKETS_COUNT, MINIMUM_LOG_DELTA_TIME,
+    PACKETS_PRIORITY_FILES, USE_SECOND_BUCKET,
 };
 use io::compressed_read::{CompressedRead, CompressedReadIndipendent};
 use io::concurrent::temp_reads::extra_data::{
@@ -37,6 +40,7 @@ pub mod debug_bucket_stats;
 pub mod processor;
 mod reads_buffer;
 mod resplitter;
+mod scratch_buffers;
 mod writer;
 
 pub trait KmersTransformExecutorFactory: Sized + 'static + Sync + Send {
@@ -152,7 +156,11 @@ pub struct KmersTransformContext<F: KmersTransformExecutorFactory> {
     compute_threads_count: usize,
     read_threads_count: usize,
     max_second_buckets_count_log2: usize,
+    use_second_bucket: bool,
+    reads_buffer_capacity: usize,
     temp_dir: PathBuf,
+    // Buffers of the bucket readers, to avoid allocating them for each bucket
+    reader_scratch_buffers: ScratchBuffersPool,
 
     total_sequences: AtomicU64,
     total_kmers: AtomicU64,
@@ -161,6 +169,17 @@ pub struct KmersTransformContext<F: KmersTransformExecutorFactory> {
     reader_init_lock: tokio::sync::Mutex<()>,
 }
 
+/// Capacity of the reads packets filled by the reader, a packet is sent to the processors as soon as
+/// it is full. Smaller packets reduce the memory held by the in-flight packets, larger packets
+/// reduce the per-packet scheduling overhead
+fn reads_buffer_capacity(k: usize, configured_capacity: usize) -> usize {
+    if configured_capacity != 0 {
+        configured_capacity
+    } else {
+        max(16, KMERS_TRANSFORM_READS_CHUNKS_SIZE / k)
+    }
+}
+
 impl<F: KmersTransformExecutorFactory> KmersTransform<F> {
     pub fn new(
         file_inputs: Vec<PathBuf>,
@@ -263,7 +282,16 @@ impl<F: KmersTransformExecutorFactory> KmersTransform<F> {
             compute_threads_count,
             read_threads_count,
             max_second_buckets_count_log2: MAXIMUM_SECOND_BUCKETS_COUNT.ilog2() as usize,
+            use_second_bucket: USE_SECOND_BUCKET.load(Ordering::Relaxed),
+            reads_buffer_capacity: reads_buffer_capacity(
+                k,
+                KMERS_TRANSFORM_READS_BUFFER_CAPACITY.load(Ordering::Relaxed),
+            ),
             temp_dir: temp_dir.to_path_buf(),
+            reader_scratch_buffers: ScratchBuffersPool::new(
+                DEFAULT_PER_CPU_BUFFER_SIZE.as_bytes(),
+                read_threads_count,
+            ),
             total_sequences: AtomicU64::new(0),
             total_kmers: AtomicU64::new(0),
             unique_kmers: AtomicU64::new(0),
@@ -305,10 +333,7 @@ impl<F: KmersTransformExecutorFactory> KmersTransform<F> {
             PoolAllocMode::Distinct {
                 capacity: self.global_context.max_buckets,
             },
-            max(
-                16,
-                KMERS_TRANSFORM_READS_CHUNKS_SIZE / self.global_context.k,
-            ),
+            self.global_context.reads_buffer_capacity,
             &self.global_context,
         );
 
@@ -318,7 +343,10 @@ impl<F: KmersTransformExecutorFactory> KmersTransform<F> {
             .register_executors::<KmersTransformProcessor<F>>(
                 min_maps_count + 2,
                 PoolAllocMode::Shared {
-                    capacity: min_maps_count + 2,
+                    capacity: get_packets_pool_capacity(
+                        PacketsPoolExecutor::KmersTransformProcessor,
+                        min_maps_count + 2,
+                    ),
                 },
                 (),
                 &self.global_context,
@@ -437,7 +465,7 @@ impl<F: KmersTransformExecutorFactory> KmersTransform<F> {
                     * (eta_total_buckets_size as f64)) as u64,
             );
 
-            println!(
+            log::info!(
                 "Processing bucket {}{} of [{}{}[R:{}]] {} phase eta: {:.0?} est. tot: {:.0?}",
                 processed_count,
                 if extra_processed_buckets_count > 0 {
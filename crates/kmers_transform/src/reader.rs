@@ -6,11 +6,10 @@ use crate::{
     KmersTransformPreprocessor,
 };
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_OUTPUT_BUFFER_SIZE,
-    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    get_compression_level_info, get_memory_mode, get_packets_pool_capacity, PacketsPoolExecutor,
+    SwapPriority, DEFAULT_OUTPUT_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
     MAXIMUM_JIT_PROCESSED_BUCKETS, MAX_INTERMEDIATE_MAP_SIZE, MIN_BUCKET_CHUNKS_FOR_READING_THREAD,
     PACKETS_PRIORITY_DEFAULT, PACKETS_PRIORITY_REWRITTEN, PARTIAL_VECS_CHECKPOINT_SIZE,
-    USE_SECOND_BUCKET,
 };
 use instrumenter::local_setup_instrumenter;
 use io::compressed_read::CompressedReadIndipendent;
@@ -116,6 +115,32 @@ struct BucketsInfo {
     second_buckets_log_max: usize,
     file_size: usize,
     used_hash_bits: usize,
+    // Only the buckets written by the minimizer bucketing store the second bucket
+    with_second_bucket: bool,
+}
+
+/// Sub-buckets assigned on average to each processing group of a bucket, to balance the groups sizes
+const SECOND_BUCKETS_PER_GROUP: usize = 8;
+
+/// Chooses the (log2) count of the second buckets a bucket is split into: the buckets with an
+/// outlier sub-bucket use the maximum count, to isolate it as much as possible, while the others
+/// use just enough sub-buckets to balance the groups of about min_bucket_size kmers they are split into
+fn second_buckets_log_for_bucket(
+    estimated_kmers: f64,
+    min_bucket_size: u64,
+    has_outlier: bool,
+    max_log: usize,
+) -> usize {
+    if has_outlier {
+        return max_log;
+    }
+
+    let groups_count = (estimated_kmers / max(1, min_bucket_size) as f64).ceil() as usize;
+    let second_buckets_count = max(1, groups_count).saturating_mul(SECOND_BUCKETS_PER_GROUP);
+    min(
+        second_buckets_count.next_power_of_two().ilog2() as usize,
+        max_log,
+    )
 }
 
 impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
@@ -123,11 +148,47 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
         global_context: &KmersTransformContext<F>,
         file: Packet<InputBucketDesc>,
     ) -> BucketsInfo {
-        let second_buckets_log_max = min(
+        let total_sequences = global_context.total_sequences.load(Ordering::Relaxed);
+        let total_kmers = global_context.total_kmers.load(Ordering::Relaxed);
+        let unique_kmers = global_context.unique_kmers.load(Ordering::Relaxed);
+
+        let unique_estimator_factor = if total_sequences > 0 {
+            if F::HAS_COLORS {
+                (unique_kmers as f64 / total_sequences as f64)
+                    .max(total_kmers as f64 / total_sequences as f64 / 48.0)
+            } else {
+                unique_kmers as f64 / total_sequences as f64
+            }
+        } else {
+            global_context.k as f64 / 2.0
+        };
+
+        let is_outlier_size = |count: u64| {
+            !file.resplitted
+                && (total_sequences > 0)
+                && (count as f64 * unique_estimator_factor
+                    >= (MAX_INTERMEDIATE_MAP_SIZE / F::MapProcessorType::MAP_SIZE as u64) as f64)
+        };
+
+        let max_second_buckets_log = min(
             file.sub_bucket_counters.len().ilog2() as usize,
             global_context.max_second_buckets_count_log2,
         );
 
+        // Small buckets are split in fewer sub-buckets, the outlier ones in the maximum count
+        let second_buckets_log_max = second_buckets_log_for_bucket(
+            file.sub_bucket_counters
+                .iter()
+                .map(|c| c.count)
+                .sum::<u64>() as f64
+                * unique_estimator_factor,
+            global_context.min_bucket_size,
+            file.sub_bucket_counters
+                .iter()
+                .any(|c| is_outlier_size(c.count)),
+            max_second_buckets_log,
+        );
+
         let reader = AsyncBinaryReader::new(
             &file.path,
             true,
@@ -146,12 +207,15 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
 
         let mut sequences_count = 0;
 
-        let mut bucket_sizes: VecDeque<_> = (0..(1 << second_buckets_log_max))
-            .map(|i| {
-                sequences_count += file.sub_bucket_counters[i].count;
-                (file.sub_bucket_counters[i].clone(), i)
-            })
+        // The reads are assigned to the sub-buckets by the lowest bits of their second bucket,
+        // so each sub-bucket collects the counters with the same lowest bits
+        let mut bucket_sizes: VecDeque<_> = (0..second_buckets_max)
+            .map(|i| (BucketCounter { count: 0 }, i))
             .collect();
+        for (i, counter) in file.sub_bucket_counters.iter().enumerate() {
+            sequences_count += counter.count;
+            bucket_sizes[i % second_buckets_max].0.count += counter.count;
+        }
 
         let file_size = reader.get_file_size();
 
@@ -159,31 +223,13 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
 
         let mut has_outliers = false;
 
-        let total_sequences = global_context.total_sequences.load(Ordering::Relaxed);
-        let total_kmers = global_context.total_kmers.load(Ordering::Relaxed);
-        let unique_kmers = global_context.unique_kmers.load(Ordering::Relaxed);
-
-        let unique_estimator_factor = if total_sequences > 0 {
-            if F::HAS_COLORS {
-                (unique_kmers as f64 / total_sequences as f64)
-                    .max(total_kmers as f64 / total_sequences as f64 / 48.0)
-            } else {
-                unique_kmers as f64 / total_sequences as f64
-            }
-        } else {
-            global_context.k as f64 / 2.0
-        };
-
         while bucket_sizes.len() > 0 {
             let buckets_count = queue.len();
             let mut smallest_bucket = queue.pop().unwrap();
 
             let biggest_sub_bucket = bucket_sizes.pop_back().unwrap();
 
-            let is_outlier = !file.resplitted
-                && (total_sequences > 0)
-                && (biggest_sub_bucket.0.count as f64 * unique_estimator_factor
-                    >= (MAX_INTERMEDIATE_MAP_SIZE / F::MapProcessorType::MAP_SIZE as u64) as f64);
+            let is_outlier = is_outlier_size(biggest_sub_bucket.0.count);
 
             // if is_outlier {
             //     println!(
@@ -313,6 +359,9 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
             second_buckets_log_max,
             file_size,
             used_hash_bits: file.used_hash_bits,
+            with_second_bucket: global_context.use_second_bucket
+                && !file.resplitted
+                && !file.rewritten,
         }
     }
 
@@ -365,7 +414,7 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
     }
 
     #[instrumenter::track]
-    async fn read_bucket(
+    async fn read_bucket<const WITH_SECOND_BUCKET: bool>(
         global_context: &KmersTransformContext<F>,
         ops: &ExecutorAddressOperations<'_, Self>,
         bucket_info: &BucketsInfo,
@@ -378,7 +427,7 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
 
         let mut buffers = Vec::with_capacity(bucket_info.addresses.len());
 
-        let mut rewrite_buffer = Vec::with_capacity(DEFAULT_PER_CPU_BUFFER_SIZE.as_bytes());
+        let mut rewrite_buffer = global_context.reader_scratch_buffers.take();
 
         track!(
             {
@@ -400,7 +449,7 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
             .get_items_stream::<CompressedReadsBucketDataSerializer<
                 F::AssociatedExtraData,
                 F::FLAGS_COUNT,
-                { USE_SECOND_BUCKET },
+                WITH_SECOND_BUCKET,
             >>(
                 async_reader_thread.clone(),
                 Vec::new(),
@@ -411,12 +460,17 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
             let bucket = if has_single_addr {
                 0
             } else {
-                let orig_bucket = preprocessor.get_sequence_bucket(
-                    global_extra_data,
-                    &read_info,
-                    bucket_info.used_hash_bits,
-                    bucket_info.second_buckets_log_max,
-                ) as usize;
+                let orig_bucket = if WITH_SECOND_BUCKET {
+                    // The stored second bucket has at least second_buckets_log_max bits
+                    read_info.1 as usize % (1 << bucket_info.second_buckets_log_max)
+                } else {
+                    preprocessor.get_sequence_bucket(
+                        global_extra_data,
+                        &read_info,
+                        bucket_info.used_hash_bits,
+                        bucket_info.second_buckets_log_max,
+                    ) as usize
+                };
 
                 bucket_info.buckets_remapping[orig_bucket]
             };
@@ -434,7 +488,7 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
             buffers[bucket].reads.push((flags, extra_data, ind_read));
 
             let packets_pool = &packets_pool;
-            if buffers[bucket].reads.len() == buffers[bucket].reads.capacity() {
+            if buffers[bucket].is_full() {
                 match &bucket_info.addresses[bucket] {
                     AddressMode::Send(address) => {
                         replace_with_async(&mut buffers[bucket], |mut buffer| async move {
@@ -479,6 +533,10 @@ impl<F: KmersTransformExecutorFactory> KmersTransformReader<F> {
                 }
             }
         }
+
+        global_context
+            .reader_scratch_buffers
+            .give_back(rewrite_buffer);
     }
 }
 
@@ -543,21 +601,35 @@ impl<F: KmersTransformExecutorFactory> AsyncExecutor for KmersTransformReader<F>
                     let address = &address;
                     let buckets_info = &buckets_info;
                     let packets_pool = address
-                        .pool_alloc_await(max(
-                            global_context.max_buckets / 2,
-                            2 * buckets_info.addresses.len(),
+                        .pool_alloc_await(get_packets_pool_capacity(
+                            PacketsPoolExecutor::KmersTransformReader,
+                            max(
+                                global_context.max_buckets / 2,
+                                2 * buckets_info.addresses.len(),
+                            ),
                         ))
                         .await;
 
                     spawner.spawn_executor(async move {
-                        Self::read_bucket(
-                            global_context,
-                            address,
-                            buckets_info,
-                            async_thread,
-                            packets_pool,
-                        )
-                        .await;
+                        if buckets_info.with_second_bucket {
+                            Self::read_bucket::<true>(
+                                global_context,
+                                address,
+                                buckets_info,
+                                async_thread,
+                                packets_pool,
+                            )
+                            .await;
+                        } else {
+                            Self::read_bucket::<false>(
+                                global_context,
+                                address,
+                                buckets_info,
+                                async_thread,
+                                packets_pool,
+                            )
+                            .await;
+                        }
                     });
                 }
 
@@ -651,3 +723,32 @@ impl<F: KmersTransformExecutorFactory> AsyncExecutor for KmersTransformReader<F>
 //     fn finalize<E: ExecutorOperations<Self>>(&mut self, _ops: E) {
 //         assert_eq!(buffers.len(), 0);
 //     }
+
+#[cfg(test)]
+mod tests {
+    use super::second_buckets_log_for_bucket;
+
+    #[test]
+    fn larger_buckets_get_more_second_buckets() {
+        const MIN_BUCKET_SIZE: u64 = 1_000_000;
+        const MAX_LOG: usize = 8;
+
+        // A bucket fitting in a single group only needs to balance a single group
+        let small = second_buckets_log_for_bucket(10_000.0, MIN_BUCKET_SIZE, false, MAX_LOG);
+        assert_eq!(small, 3);
+
+        let large = second_buckets_log_for_bucket(20_000_000.0, MIN_BUCKET_SIZE, false, MAX_LOG);
+        assert!(large > small);
+        assert_eq!(large, MAX_LOG);
+
+        let medium = second_buckets_log_for_bucket(4_000_000.0, MIN_BUCKET_SIZE, false, MAX_LOG);
+        assert!(small < medium && medium < large);
+
+        // The outliers always use the finest split
+        assert_eq!(
+            second_buckets_log_for_bucket(10_000.0, MIN_BUCKET_SIZE, true, MAX_LOG),
+            MAX_LOG
+        );
+        assert_eq!(second_buckets_log_for_bucket(0.0, 0, false, MAX_LOG), 3);
+    }
+}
@@ -40,3 +40,32 @@ impl<E: SequenceExtraDataTempBufferManagement + 'static> PacketTrait for ReadsBu
         self.reads.len() * size_of::<(u8, E, CompressedReadIndipendent)>() + self.reads_buffer.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ReadsBuffer;
+    use io::compressed_read::CompressedReadIndipendent;
+    use parallel_processor::execution_manager::objects_pool::PoolObjectTrait;
+
+    fn count_flushes(capacity: usize, reads_count: usize) -> usize {
+        let mut buffer = ReadsBuffer::<()>::allocate_new(&capacity);
+        let mut flushes = 0;
+
+        for _ in 0..reads_count {
+            let read = CompressedReadIndipendent::from_plain(b"ACGTACGT", &mut buffer.reads_buffer);
+            buffer.reads.push((0, (), read));
+            if buffer.is_full() {
+                flushes += 1;
+                buffer.reset();
+            }
+        }
+        flushes
+    }
+
+    #[test]
+    fn configured_capacity_sets_flush_frequency() {
+        assert_eq!(count_flushes(16, 1024), 64);
+        assert_eq!(count_flushes(256, 1024), 4);
+        assert_eq!(count_flushes(2048, 1024), 0);
+    }
+}
@@ -2,9 +2,10 @@ use crate::reader::{InputBucketDesc, KmersTransformReader};
 use crate::reads_buffer::ReadsBuffer;
 use crate::{KmersTransformContext, KmersTransformExecutorFactory};
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, SwapPriority,
-    DEFAULT_PER_CPU_BUFFER_SIZE, MAXIMUM_JIT_PROCESSED_BUCKETS, MAX_RESPLIT_BUCKETS_COUNT_LOG,
-    MINIMIZER_BUCKETS_CHECKPOINT_SIZE, PACKETS_PRIORITY_DONE_RESPLIT,
+    get_compression_level_info, get_memory_mode, get_temp_buckets_path, spread_temp_buckets,
+    BucketIndexType, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE, MAXIMUM_JIT_PROCESSED_BUCKETS,
+    MAX_RESPLIT_BUCKETS_COUNT_LOG, MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
+    PACKETS_PRIORITY_DONE_RESPLIT,
 };
 use hashes::HashableSequence;
 use instrumenter::local_setup_instrumenter;
@@ -79,10 +80,13 @@ impl<F: KmersTransformExecutorFactory> KmersTransformResplitter<F> {
 
         let buckets = Arc::new(MultiThreadBuckets::new(
             1 << subsplit_buckets_count_log,
-            global_context.temp_dir.join(format!(
-                "resplit-bucket{}",
-                BUCKET_RESPLIT_COUNTER.fetch_add(1, Ordering::Relaxed)
-            )),
+            spread_temp_buckets(
+                get_temp_buckets_path(global_context.temp_dir.join(format!(
+                    "resplit-bucket{}",
+                    BUCKET_RESPLIT_COUNTER.fetch_add(1, Ordering::Relaxed)
+                ))),
+                1 << subsplit_buckets_count_log,
+            ),
             &(
                 get_memory_mode(SwapPriority::MinimizerBuckets),
                 MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
@@ -0,0 +1,63 @@
+use parking_lot::Mutex;
+
+/// Scratch byte buffers shared by the concurrent bucket readers. A returned buffer keeps its
+/// capacity, so it is not allocated again for each bucket. At most max_pooled buffers are kept,
+/// the number of readers that can run at the same time
+pub(crate) struct ScratchBuffersPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    buffer_capacity: usize,
+    max_pooled: usize,
+}
+
+impl ScratchBuffersPool {
+    pub fn new(buffer_capacity: usize, max_pooled: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::with_capacity(max_pooled)),
+            buffer_capacity,
+            max_pooled,
+        }
+    }
+
+    /// Takes an empty buffer from the pool, allocating it if none is available
+    pub fn take(&self) -> Vec<u8> {
+        self.buffers
+            .lock()
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(self.buffer_capacity))
+    }
+
+    pub fn give_back(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        let mut buffers = self.buffers.lock();
+        if buffers.len() < self.max_pooled {
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScratchBuffersPool;
+
+    #[test]
+    fn returned_buffers_are_reused() {
+        let pool = ScratchBuffersPool::new(1024, 2);
+
+        let mut buffer = pool.take();
+        assert!(buffer.capacity() >= 1024);
+        buffer.extend_from_slice(b"ACGT");
+        let allocation = buffer.as_ptr();
+        pool.give_back(buffer);
+
+        let buffer = pool.take();
+        assert_eq!(buffer.as_ptr(), allocation);
+        assert!(buffer.is_empty());
+
+        // Only max_pooled buffers are kept
+        let buffers = [buffer, pool.take(), pool.take()];
+        for buffer in buffers {
+            pool.give_back(buffer);
+        }
+        assert_eq!(pool.buffers.lock().len(), 2);
+    }
+}
@@ -52,7 +52,7 @@ impl CountersAnalyzer {
     }
 
     pub fn print_debug(&self) {
-        println!("************** BUCKETS DEBUG: **************");
+        log::debug!("************** BUCKETS DEBUG: **************");
         // for (i, cnt_bucket) in self.counters.iter().enumerate() {
         //     let mut buffer = String::new();
         //     for cnt_sub_bucket in cnt_bucket.iter() {
@@ -64,8 +64,8 @@ impl CountersAnalyzer {
         //     }
         //     println!("{} SIZES: {}", i, buffer);
         // }
-        println!("Sub-bucket median: {}", self.median);
-        println!(
+        log::debug!("Sub-bucket median: {}", self.median);
+        log::debug!(
             "Sub-bucket maximum: {}",
             self.counters
                 .iter()
@@ -2,16 +2,19 @@
 #![feature(impl_trait_in_assoc_type)]
 
 pub mod counters_analyzer;
+pub mod masking;
 mod queue_data;
 mod reader;
 mod sequences_splitter;
 
 use crate::counters_analyzer::CountersAnalyzer;
+use crate::masking::MaskedIntervals;
 use crate::queue_data::MinimizerBucketingQueueData;
 use crate::reader::MinimizerBucketingFilesReader;
 use crate::sequences_splitter::SequencesSplitter;
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, SwapPriority,
+    get_compression_level_info, get_memory_mode, get_packets_pool_capacity, get_temp_buckets_path,
+    get_temp_file_path, spread_temp_buckets, BucketIndexType, PacketsPoolExecutor, SwapPriority,
     DEFAULT_PER_CPU_BUFFER_SIZE, MINIMIZER_BUCKETS_CHECKPOINT_SIZE, PACKETS_PRIORITY_DEFAULT,
     READ_INTERMEDIATE_CHUNKS_SIZE, READ_INTERMEDIATE_QUEUE_MULTIPLIER,
 };
@@ -24,6 +27,7 @@ use io::concurrent::temp_reads::creads_utils::{
 use io::concurrent::temp_reads::extra_data::{
     SequenceExtraDataConsecutiveCompression, SequenceExtraDataTempBufferManagement,
 };
+use io::lines_reader::{estimated_progress_percentage, get_decompressed_bytes_count};
 use io::sequences_reader::DnaSequence;
 use io::sequences_stream::{GenericSequencesStream, SequenceInfo};
 use parallel_processor::buckets::concurrent::{BucketsThreadBuffer, BucketsThreadDispatcher};
@@ -190,11 +194,17 @@ pub struct MinimizerBucketingExecutionContext<GlobalData> {
     pub executor_group_address: RwLock<Option<ExecutorAddress>>,
     pub processed_files: AtomicUsize,
     pub total_files: usize,
+    pub estimated_input_bytes: u64,
+    pub start_decompressed_bytes: u64,
     pub read_threads_count: usize,
     pub threads_count: usize,
 
     pub partial_read_copyback: Option<usize>,
     pub copy_ident: bool,
+    /// Write the second level bucket of each read, to be used by the kmers transform
+    pub use_second_bucket: bool,
+    /// Intervals of the input sequences replaced with N by the reader
+    pub masked_intervals: Option<Arc<MaskedIntervals>>,
 }
 
 pub struct GenericMinimizerBucketing;
@@ -209,7 +219,7 @@ struct MinimizerBucketingExecWriter<E: MinimizerBucketingExecutorFactory + Sync
 }
 
 impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBucketingExecWriter<E> {
-    async fn execute(
+    async fn execute<const WITH_SECOND_BUCKET: bool>(
         &self,
         context: &MinimizerBucketingExecutionContext<E::GlobalData>,
         ops: &ExecutorAddressOperations<'_, Self>,
@@ -220,11 +230,7 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBuck
 
         let mut tmp_reads_buffer = BucketsThreadDispatcher::<
             _,
-            CompressedReadsBucketDataSerializer<
-                E::ExtraData,
-                E::FLAGS_COUNT,
-                { USE_SECOND_BUCKET },
-            >,
+            CompressedReadsBucketDataSerializer<E::ExtraData, E::FLAGS_COUNT, WITH_SECOND_BUCKET>,
         >::new(
             &context.buckets,
             BucketsThreadBuffer::new(DEFAULT_PER_CPU_BUFFER_SIZE, context.buckets.count()),
@@ -306,9 +312,11 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBuck
             if do_print_log {
                 let current_file = context.current_file.load(Ordering::Relaxed);
                 let processed_files = context.processed_files.load(Ordering::Relaxed);
+                let processed_bytes =
+                    get_decompressed_bytes_count() - context.start_decompressed_bytes;
 
-                println!(
-                    "Elaborated {} sequences! [{} | {:.2}% qb] ({}[{}]/{} => {:.2}%) {}",
+                log::info!(
+                    "Elaborated {} sequences! [{} | {:.2}% qb] ({}[{}]/{} => {:.2}% | ~{:.2}% of input) {}",
                     SEQ_COUNT.load(Ordering::Relaxed),
                     VALID_BASES_COUNT.load(Ordering::Relaxed),
                     (VALID_BASES_COUNT.load(Ordering::Relaxed) as f64)
@@ -318,6 +326,7 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> MinimizerBuck
                     current_file,
                     context.total_files,
                     processed_files as f64 / max(1, context.total_files) as f64 * 100.0,
+                    estimated_progress_percentage(processed_bytes, context.estimated_input_bytes),
                     PHASES_TIMES_MONITOR
                         .read()
                         .get_formatted_counter_without_memory()
@@ -367,7 +376,11 @@ impl<E: MinimizerBucketingExecutorFactory + Sync + Send + 'static> AsyncExecutor
                 let mut spawner = address.make_spawner();
                 for _ in 0..max_concurrency {
                     spawner.spawn_executor(async {
-                        self.execute(global_params, &address).await;
+                        if global_params.use_second_bucket {
+                            self.execute::<true>(global_params, &address).await;
+                        } else {
+                            self.execute::<false>(global_params, &address).await;
+                        }
                     });
                 }
                 spawner.executors_await().await;
@@ -414,13 +427,23 @@ impl GenericMinimizerBucketing {
         partial_read_copyback: Option<usize>,
         copy_ident: bool,
         ignored_length: usize,
+        masked_intervals: Option<Arc<MaskedIntervals>>,
     ) -> (Vec<PathBuf>, PathBuf) {
+        let input_blocks: Vec<_> = input_blocks.collect();
+        let estimated_input_bytes = input_blocks
+            .iter()
+            .map(|(block, _)| S::estimated_decompressed_size(block))
+            .sum();
+
         let read_threads_count = max(1, threads_count / 2);
         let compute_threads_count = max(1, threads_count.saturating_sub(read_threads_count / 4));
 
         let buckets = Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
             buckets_count,
-            output_path.join("bucket"),
+            spread_temp_buckets(
+                get_temp_buckets_path(output_path.join("bucket")),
+                buckets_count,
+            ),
             &(
                 get_memory_mode(SwapPriority::MinimizerBuckets),
                 MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
@@ -441,6 +464,8 @@ impl GenericMinimizerBucketing {
             )),
             processed_files: AtomicUsize::new(0),
             total_files: input_blocks.len(),
+            estimated_input_bytes,
+            start_decompressed_bytes: get_decompressed_bytes_count(),
             common: Arc::new(MinimizerBucketingCommonData::new(
                 k,
                 m,
@@ -452,12 +477,17 @@ impl GenericMinimizerBucketing {
             threads_count: compute_threads_count,
             partial_read_copyback,
             read_threads_count,
-            copy_ident,
+            // The masked intervals are looked up by the sequence name
+            copy_ident: copy_ident || masked_intervals.is_some(),
+            use_second_bucket: USE_SECOND_BUCKET.load(Ordering::Relaxed),
+            masked_intervals,
         });
 
         {
-            let max_read_buffers_count =
-                compute_threads_count * READ_INTERMEDIATE_QUEUE_MULTIPLIER.load(Ordering::Relaxed);
+            let max_read_buffers_count = get_packets_pool_capacity(
+                PacketsPoolExecutor::MinimizerBucketingReader,
+                compute_threads_count * READ_INTERMEDIATE_QUEUE_MULTIPLIER.load(Ordering::Relaxed),
+            );
 
             let execution_context = ExecutionContext::new();
 
@@ -527,7 +557,7 @@ impl GenericMinimizerBucketing {
         let counters_analyzer = CountersAnalyzer::new(common_context.global_counters);
         // counters_analyzer.print_debug();
 
-        let counters_file = output_path.join("buckets-counters.dat");
+        let counters_file = get_temp_file_path(output_path.join("buckets-counters.dat"));
 
         counters_analyzer.serialize_to_file(&counters_file);
 
@@ -0,0 +1,275 @@
+use io::sequences_reader::DnaSequence;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum BedLoadError {
+    Io(std::io::Error),
+    InvalidLine { line: usize, content: String },
+}
+
+impl Display for BedLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BedLoadError::Io(err) => write!(f, "Cannot read the masking BED file: {}", err),
+            BedLoadError::InvalidLine { line, content } => write!(
+                f,
+                "Invalid masking BED line {}: '{}', expected <sequence name> <start> <end>",
+                line, content
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BedLoadError {}
+
+/// Intervals of the input sequences that are replaced with N before the bucketing,
+/// indexed by the sequence name (the ident up to the first whitespace)
+pub struct MaskedIntervals {
+    // For each sequence, sorted and non overlapping intervals
+    intervals: HashMap<Vec<u8>, Vec<Range<usize>>>,
+}
+
+impl MaskedIntervals {
+    pub fn load_bed(path: impl AsRef<Path>) -> Result<Self, BedLoadError> {
+        let file = File::open(path).map_err(BedLoadError::Io)?;
+        Self::from_bed(BufReader::new(file))
+    }
+
+    /// Parses the intervals from the first three columns of a BED file (0-based, end excluded)
+    pub fn from_bed(reader: impl BufRead) -> Result<Self, BedLoadError> {
+        let mut intervals: HashMap<Vec<u8>, Vec<Range<usize>>> = HashMap::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(BedLoadError::Io)?;
+            let trimmed = line.trim();
+            if trimmed.is_empty()
+                || trimmed.starts_with('#')
+                || trimmed.starts_with("track")
+                || trimmed.starts_with("browser")
+            {
+                continue;
+            }
+
+            let invalid_line = || BedLoadError::InvalidLine {
+                line: index + 1,
+                content: line.clone(),
+            };
+
+            let mut fields = trimmed.split_whitespace();
+            let name = fields.next().ok_or_else(invalid_line)?;
+            let start: usize = fields
+                .next()
+                .and_then(|f| f.parse().ok())
+                .ok_or_else(invalid_line)?;
+            let end: usize = fields
+                .next()
+                .and_then(|f| f.parse().ok())
+                .ok_or_else(invalid_line)?;
+
+            if start > end {
+                return Err(invalid_line());
+            }
+
+            intervals
+                .entry(name.as_bytes().to_vec())
+                .or_default()
+                .push(start..end);
+        }
+
+        for sequence_intervals in intervals.values_mut() {
+            sequence_intervals.sort_by_key(|r| r.start);
+
+            let mut merged: Vec<Range<usize>> = Vec::with_capacity(sequence_intervals.len());
+            for interval in sequence_intervals.drain(..) {
+                match merged.last_mut() {
+                    Some(last) if interval.start <= last.end => {
+                        last.end = last.end.max(interval.end);
+                    }
+                    _ => merged.push(interval),
+                }
+            }
+            *sequence_intervals = merged;
+        }
+
+        Ok(Self { intervals })
+    }
+
+    pub fn intervals_count(&self) -> usize {
+        self.intervals.values().map(|i| i.len()).sum()
+    }
+
+    fn sequence_name(ident_data: &[u8]) -> &[u8] {
+        let ident = match ident_data.first() {
+            Some(b'>') | Some(b'@') => &ident_data[1..],
+            _ => ident_data,
+        };
+        let name_end = ident
+            .iter()
+            .position(|c| c.is_ascii_whitespace())
+            .unwrap_or(ident.len());
+        &ident[..name_end]
+    }
+
+    /// Replaces with N the masked bases of the sequence named by ident_data, where sequence
+    /// starts at the given offset. Returns false if no bases were masked
+    pub fn mask(&self, ident_data: &[u8], offset: usize, sequence: &mut [u8]) -> bool {
+        let intervals = match self.intervals.get(Self::sequence_name(ident_data)) {
+            Some(intervals) => intervals,
+            None => return false,
+        };
+
+        let sequence_end = offset + sequence.len();
+        let first_interval = intervals.partition_point(|r| r.end <= offset);
+
+        let mut masked = false;
+        for interval in intervals[first_interval..]
+            .iter()
+            .take_while(|r| r.start < sequence_end)
+        {
+            let start = interval.start.max(offset) - offset;
+            let end = interval.end.min(sequence_end) - offset;
+            sequence[start..end].fill(b'N');
+            masked |= start < end;
+        }
+        masked
+    }
+}
+
+/// Applies the masked intervals to the sequences produced by a reader. Long sequences are read
+/// in chunks that overlap by copyback bases, the position of each chunk is tracked by assuming
+/// that consecutive chunks with the same ident belong to the same sequence
+pub(crate) struct SequencesMasker {
+    intervals: Arc<MaskedIntervals>,
+    copyback: usize,
+    last_ident: Vec<u8>,
+    next_offset: usize,
+    masked_sequence: Vec<u8>,
+}
+
+impl SequencesMasker {
+    pub fn new(intervals: Arc<MaskedIntervals>, copyback: Option<usize>) -> Self {
+        Self {
+            intervals,
+            copyback: copyback.unwrap_or(0),
+            last_ident: vec![],
+            next_offset: 0,
+            masked_sequence: vec![],
+        }
+    }
+
+    pub fn mask<'a>(&'a mut self, sequence: DnaSequence<'a>) -> DnaSequence<'a> {
+        let offset = if !self.last_ident.is_empty() && sequence.ident_data == self.last_ident {
+            self.next_offset
+        } else {
+            self.last_ident.clear();
+            self.last_ident.extend_from_slice(sequence.ident_data);
+            0
+        };
+        self.next_offset = offset + sequence.seq.len().saturating_sub(self.copyback);
+
+        self.masked_sequence.clear();
+        self.masked_sequence.extend_from_slice(sequence.seq);
+
+        if self
+            .intervals
+            .mask(sequence.ident_data, offset, &mut self.masked_sequence)
+        {
+            DnaSequence {
+                ident_data: sequence.ident_data,
+                seq: &self.masked_sequence,
+                format: sequence.format,
+            }
+        } else {
+            sequence
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MaskedIntervals, SequencesMasker};
+    use crate::sequences_splitter::SequencesSplitter;
+    use io::sequences_reader::{DnaSequence, DnaSequencesFileType};
+    use std::sync::Arc;
+
+    const READ: &[u8] = b"ACGTACGTAACCGGTTACGTTTGCAAGGCCTTAGCATCGA";
+
+    fn masked_fragments(bed: &str, ident: &[u8], k: usize) -> Vec<Vec<u8>> {
+        let intervals = Arc::new(MaskedIntervals::from_bed(bed.as_bytes()).unwrap());
+        let mut masker = SequencesMasker::new(intervals, None);
+
+        let masked = masker.mask(DnaSequence {
+            ident_data: ident,
+            seq: READ,
+            format: DnaSequencesFileType::FASTA,
+        });
+
+        let mut fragments = vec![];
+        SequencesSplitter::new(k)
+            .process_sequences(&masked, |fragment, _| fragments.push(fragment.to_vec()));
+        fragments
+    }
+
+    #[test]
+    fn masked_middle_region_splits_the_read() {
+        assert_eq!(
+            masked_fragments("# contaminant\nread1\t12\t20\n", b">read1 sample", 5),
+            vec![READ[..12].to_vec(), READ[20..].to_vec()]
+        );
+
+        // Other sequences are not affected
+        assert_eq!(
+            masked_fragments("read2\t12\t20\n", b">read1", 5),
+            vec![READ.to_vec()]
+        );
+    }
+
+    #[test]
+    fn overlapping_intervals_are_merged() {
+        let intervals =
+            MaskedIntervals::from_bed("chr1 10 20\nchr1 15 30\nchr1 40 50\n".as_bytes()).unwrap();
+        assert_eq!(intervals.intervals_count(), 2);
+
+        let mut sequence = vec![b'A'; 20];
+        assert!(intervals.mask(b">chr1", 25, &mut sequence));
+        assert_eq!(&sequence[..5], b"NNNNN");
+        assert!(sequence[5..15].iter().all(|&b| b == b'A'));
+        assert_eq!(&sequence[15..], b"NNNNN");
+    }
+
+    #[test]
+    fn chunks_are_masked_at_their_offset() {
+        let intervals = Arc::new(MaskedIntervals::from_bed("chr1 18 22\n".as_bytes()).unwrap());
+        let mut masker = SequencesMasker::new(intervals, Some(4));
+
+        let mut masked_chunks = vec![];
+        // The second chunk repeats the last 4 bases of the first one, so it starts at 16
+        for chunk in [&READ[..20], &READ[16..30]] {
+            let masked = masker.mask(DnaSequence {
+                ident_data: b">chr1",
+                seq: chunk,
+                format: DnaSequencesFileType::FASTA,
+            });
+            masked_chunks.push(masked.seq.to_vec());
+        }
+
+        assert_eq!(&masked_chunks[0][18..], b"NN");
+        assert_eq!(
+            &masked_chunks[1][..6],
+            &[READ[16], READ[17], b'N', b'N', b'N', b'N']
+        );
+        assert_eq!(&masked_chunks[1][6..], &READ[22..30]);
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        assert!(MaskedIntervals::from_bed("chr1 10\n".as_bytes()).is_err());
+        assert!(MaskedIntervals::from_bed("chr1 20 10\n".as_bytes()).is_err());
+    }
+}
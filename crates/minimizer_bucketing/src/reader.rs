@@ -1,3 +1,4 @@
+use crate::masking::SequencesMasker;
 use crate::queue_data::MinimizerBucketingQueueData;
 use crate::MinimizerBucketingExecutionContext;
 use io::sequences_stream::GenericSequencesStream;
@@ -65,6 +66,10 @@ impl<
 
             let mut max_len = 0;
 
+            let mut masker = context.masked_intervals.as_ref().map(|intervals| {
+                SequencesMasker::new(intervals.clone(), context.partial_read_copyback)
+            });
+
             sequences_stream.read_block(
                 &mut input_packet.0,
                 context.copy_ident,
@@ -72,6 +77,12 @@ impl<
                 |x, seq_info| {
                     let mut data = data_packet.deref_mut();
 
+                    // Mask before checking the length, to keep track of the chunks offsets
+                    let x = match &mut masker {
+                        Some(masker) => masker.mask(x),
+                        None => x,
+                    };
+
                     if x.seq.len() < context.common.ignored_length {
                         return;
                     }
@@ -11,6 +11,9 @@ impl SequencesSplitter {
         Self { k, valid_bases: 0 }
     }
 
+    /// Splits the sequence at the N bases, calling process_fn only on the fragments with at least k bases.
+    /// The minimizer bucketing executors rely on this to compute the hashes of each fragment,
+    /// so empty or all-N reads are skipped here
     #[inline]
     pub fn process_sequences(
         &mut self,
@@ -39,3 +42,71 @@ impl SequencesSplitter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SequencesSplitter;
+    use io::sequences_reader::{DnaSequence, DnaSequencesFileType, SequencesReader};
+    use std::path::Path;
+
+    fn split(seq: &[u8], k: usize) -> Vec<Vec<u8>> {
+        let mut fragments = vec![];
+        SequencesSplitter::new(k).process_sequences(
+            &DnaSequence {
+                ident_data: &[],
+                seq,
+                format: DnaSequencesFileType::FASTA,
+            },
+            |fragment, range| {
+                assert_eq!(fragment, &seq[range]);
+                fragments.push(fragment.to_vec())
+            },
+        );
+        fragments
+    }
+
+    #[test]
+    fn invalid_reads_are_skipped() {
+        assert!(split(b"", 5).is_empty());
+        assert!(split(&[b'N'; 100], 5).is_empty());
+        assert!(split(b"ACGT", 5).is_empty());
+        assert!(split(b"NNACGTNNCGTANN", 5).is_empty());
+
+        assert_eq!(
+            split(b"NNACGTANNCGNACGTTTTN", 5),
+            vec![b"ACGTA".to_vec(), b"ACGTTTT".to_vec()]
+        );
+    }
+
+    fn read_and_split(fasta: &[u8], split_on_softmask: bool, k: usize) -> Vec<Vec<u8>> {
+        let mut fragments = vec![];
+        SequencesReader::new()
+            .with_split_on_softmask(split_on_softmask)
+            .process_stream_extended(
+                fasta,
+                Path::new("reads.fa"),
+                |read| fragments.extend(split(read.seq, k)),
+                None,
+                false,
+            );
+        fragments
+    }
+
+    #[test]
+    fn softmasked_regions_split_only_when_requested() {
+        const READ: &[u8] = b">read\nACGTACGTacgtacgTTGCAAGGCnnCTTAGCA\n";
+
+        assert_eq!(
+            read_and_split(READ, false, 5),
+            vec![b"ACGTACGTACGTACGTTGCAAGGC".to_vec(), b"CTTAGCA".to_vec()]
+        );
+        assert_eq!(
+            read_and_split(READ, true, 5),
+            vec![
+                b"ACGTACGT".to_vec(),
+                b"TTGCAAGGC".to_vec(),
+                b"CTTAGCA".to_vec()
+            ]
+        );
+    }
+}
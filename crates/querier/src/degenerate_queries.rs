@@ -0,0 +1,135 @@
+use io::compressed_read::iupac_bases;
+use io::sequences_reader::SequencesReader;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+fn is_degenerate(base: u8) -> bool {
+    iupac_bases(base).len() > 1
+}
+
+fn is_valid(base: u8) -> bool {
+    !iupac_bases(base).is_empty()
+}
+
+/// Rewrites a query so that each of its kmers with degenerate bases is replaced by all its concrete
+/// instantiations. The query is written with the degenerate bases replaced by N, followed by the
+/// instantiations, each one separated by an N so that no kmers span two of them.
+/// The kmers with more than max_expansions instantiations are skipped, their count is returned
+pub(crate) fn expand_degenerate_query(
+    query: &[u8],
+    k: usize,
+    max_expansions: usize,
+    output: &mut Vec<u8>,
+) -> usize {
+    output.clear();
+    output.extend(query.iter().map(|&b| match iupac_bases(b) {
+        [base] => *base,
+        _ => b'N',
+    }));
+
+    let mut skipped_kmers = 0;
+    let mut kmer = vec![0; k];
+
+    for window in query.windows(k) {
+        // Kmers with characters that are not bases are never matched
+        if !window.iter().any(|&b| is_degenerate(b)) || !window.iter().all(|&b| is_valid(b)) {
+            continue;
+        }
+
+        let expansions = window.iter().try_fold(1usize, |count, &b| {
+            count
+                .checked_mul(iupac_bases(b).len())
+                .filter(|c| *c <= max_expansions)
+        });
+        let expansions = match expansions {
+            Some(expansions) => expansions,
+            None => {
+                skipped_kmers += 1;
+                continue;
+            }
+        };
+
+        for mut instance in 0..expansions {
+            for (position, &base) in window.iter().enumerate().rev() {
+                let choices = iupac_bases(base);
+                kmer[position] = choices[instance % choices.len()];
+                instance /= choices.len();
+            }
+            output.push(b'N');
+            output.extend_from_slice(&kmer);
+        }
+    }
+
+    skipped_kmers
+}
+
+/// Writes the queries with their degenerate kmers expanded, keeping one record for each query
+pub(crate) fn write_expanded_queries(
+    query_input: &Path,
+    output: &Path,
+    k: usize,
+    max_expansions: usize,
+) {
+    let mut writer = BufWriter::new(File::create(output).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create the queries file {}: {}",
+            output.display(),
+            err
+        )
+    }));
+    let mut expanded_query = vec![];
+    let mut query_index = 0;
+
+    // The sequences are read without normalization, so that the degenerate bases are kept
+    SequencesReader::new_raw().process_file_extended(
+        query_input,
+        |seq| {
+            let skipped_kmers =
+                expand_degenerate_query(seq.seq, k, max_expansions, &mut expanded_query);
+            if skipped_kmers > 0 {
+                log::warn!(
+                    "Skipped {} kmers of query {} with more than {} degenerate expansions",
+                    skipped_kmers,
+                    query_index,
+                    max_expansions
+                );
+            }
+            query_index += 1;
+
+            writer.write_all(b">").unwrap();
+            writer
+                .write_all(seq.ident_data.get(1..).unwrap_or_default())
+                .unwrap();
+            writer.write_all(b"\n").unwrap();
+            writer.write_all(&expanded_query).unwrap();
+            writer.write_all(b"\n").unwrap();
+        },
+        None,
+        true,
+        false,
+    );
+
+    writer.flush().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_degenerate_query;
+
+    #[test]
+    fn degenerate_kmers_are_expanded_up_to_the_limit() {
+        let mut output = vec![];
+
+        assert_eq!(expand_degenerate_query(b"ACRT", 3, 4, &mut output), 0);
+        assert_eq!(output, b"ACNTNACANACGNCATNCGT");
+
+        // Unknown characters are never expanded
+        assert_eq!(expand_degenerate_query(b"A-YA", 3, 4, &mut output), 0);
+        assert_eq!(output, b"ANNA");
+
+        // Two N in the same kmer give 16 instantiations
+        assert_eq!(expand_degenerate_query(b"ANNT", 3, 4, &mut output), 2);
+        assert_eq!(output, b"ANNT");
+    }
+}
@@ -1,26 +1,34 @@
 #![feature(slice_group_by)]
 #![feature(int_roundings)]
 
+use crate::degenerate_queries::write_expanded_queries;
 use crate::pipeline::colored_query_output::colored_query_output;
 use crate::pipeline::colormap_reading::colormap_reading;
 use crate::pipeline::counters_sorting::counters_sorting;
 use crate::pipeline::parallel_kmers_query::parallel_kmers_counting;
 use crate::pipeline::querier_minimizer_bucketing::minimizer_bucketing;
+use crate::query_result_writer::QueryResultWriter;
 use ::dynamic_dispatch::dynamic_dispatch;
 use colors::colors_manager::{ColorMapReader, ColorsManager, ColorsMergeManager};
 use colors::DefaultColorsSerializer;
-use config::{INTERMEDIATE_COMPRESSION_LEVEL_FAST, INTERMEDIATE_COMPRESSION_LEVEL_SLOW};
+use config::{
+    get_temp_buckets_path, get_temp_file_path, remove_orphan_temp_buckets,
+    INTERMEDIATE_COMPRESSION_LEVEL_FAST, INTERMEDIATE_COMPRESSION_LEVEL_SLOW,
+};
 use hashes::{HashFunctionFactory, MinimizerHashFunctionFactory};
+use io::concurrent::structured_sequences::write_reverse_complement;
 use io::sequences_reader::SequencesReader;
 use io::sequences_stream::general::GeneralSequenceBlockData;
-use io::{compute_stats_from_input_blocks, generate_bucket_names};
+use io::{choose_buckets_count_log, compute_stats_from_input_blocks, generate_bucket_names};
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
 
+mod degenerate_queries;
 mod pipeline;
+pub mod query_result_writer;
 mod structs;
 
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
@@ -37,6 +45,50 @@ pub enum ColoredQueryOutputFormat {
     JsonLinesWithNames,
 }
 
+/// Writes each query followed by its reverse complement, so that the query at index i
+/// is matched in the forward orientation as query 2 * i and in the reverse one as query 2 * i + 1
+fn write_both_strands_queries(query_input: &Path, output: &Path) {
+    let mut writer = BufWriter::new(File::create(output).unwrap_or_else(|err| {
+        panic!(
+            "Cannot create the queries file {}: {}",
+            output.display(),
+            err
+        )
+    }));
+    let mut rc_buffer = vec![];
+
+    SequencesReader::new().process_file_extended(
+        query_input,
+        |seq| {
+            write_reverse_complement(seq.seq, &mut rc_buffer);
+            for sequence in [seq.seq, rc_buffer.as_slice()] {
+                // The identifier keeps its FASTA/FASTQ marker, that is replaced with the FASTA one
+                writer.write_all(b">").unwrap();
+                writer
+                    .write_all(seq.ident_data.get(1..).unwrap_or_default())
+                    .unwrap();
+                writer.write_all(b"\n").unwrap();
+                writer.write_all(sequence).unwrap();
+                writer.write_all(b"\n").unwrap();
+            }
+        },
+        None,
+        true,
+        false,
+    );
+
+    writer.flush().unwrap();
+}
+
+/// Orientation of a query in the output, when querying both strands
+pub(crate) fn query_orientation(expanded_query_index: usize) -> &'static str {
+    if expanded_query_index % 2 == 0 {
+        "+"
+    } else {
+        "-"
+    }
+}
+
 #[dynamic_dispatch(BucketingHash = [
     hashes::cn_nthash::CanonicalNtHashIteratorFactory,
     #[cfg(not(feature = "devel-build"))]  hashes::fw_nthash::ForwardNtHashIteratorFactory
@@ -75,14 +127,52 @@ pub fn run_query<
     threads_count: usize,
     default_compression_level: Option<u32>,
     colored_query_output_format: ColoredQueryOutputFormat,
+    query_both_strands: bool,
+    max_degenerate_expansions: Option<usize>,
+    append_output: bool,
+    color_quorum: Option<usize>,
 ) -> PathBuf {
     let temp_dir = temp_dir.unwrap_or(PathBuf::new());
 
+    let output_file_name = if output_file_prefix.extension().is_none() {
+        if QuerierColorsManager::COLORS_ENABLED {
+            output_file_prefix.with_extension("jsonl")
+        } else {
+            output_file_prefix.with_extension("csv")
+        }
+    } else {
+        output_file_prefix
+    };
+    let original_query_input = query_input.clone();
+
+    let query_input = if let Some(max_degenerate_expansions) = max_degenerate_expansions {
+        let expanded_query = temp_dir.join("query-expanded.fa");
+        write_expanded_queries(&query_input, &expanded_query, k, max_degenerate_expansions);
+        expanded_query
+    } else {
+        query_input
+    };
+
+    let query_input = if query_both_strands {
+        let both_strands_query = temp_dir.join("query-both-strands.fa");
+        write_both_strands_queries(&query_input, &both_strands_query);
+        both_strands_query
+    } else {
+        query_input
+    };
+
     PHASES_TIMES_MONITOR.write().init();
 
     BucketingHash::initialize(k);
     MergingHash::initialize(k);
 
+    if QuerierColorsManager::COLORS_ENABLED && !graph_input.with_extension("colors.dat").exists() {
+        panic!(
+            "The graph {} has no colormap, it can be queried only without colors",
+            graph_input.display()
+        );
+    }
+
     let color_map = QuerierColorsManager::ColorsMergeManagerType::<BucketingHash, MergingHash>::open_colors_table(
         graph_input.with_extension("colors.dat"),
     );
@@ -93,7 +183,7 @@ pub fn run_query<
         GeneralSequenceBlockData::FASTA(query_input.clone()),
     ]);
 
-    let buckets_count_log = buckets_count_log.unwrap_or_else(|| file_stats.best_buckets_count_log);
+    let buckets_count_log = choose_buckets_count_log(buckets_count_log, &file_stats, threads_count);
 
     if let Some(default_compression_level) = default_compression_level {
         INTERMEDIATE_COMPRESSION_LEVEL_SLOW.store(default_compression_level, Ordering::Relaxed);
@@ -115,8 +205,12 @@ pub fn run_query<
     } else {
         (
             (
-                generate_bucket_names(temp_dir.join("bucket"), buckets_count, None),
-                temp_dir.join("buckets-counters.dat"),
+                generate_bucket_names(
+                    get_temp_buckets_path(temp_dir.join("bucket")),
+                    buckets_count,
+                    None,
+                ),
+                get_temp_file_path(temp_dir.join("buckets-counters.dat")),
             ),
             {
                 let queries_count = BufReader::new(File::open(&query_input).unwrap())
@@ -139,17 +233,41 @@ pub fn run_query<
             threads_count,
         )
     } else {
-        generate_bucket_names(temp_dir.join("counters"), buckets_count, None)
+        generate_bucket_names(
+            get_temp_buckets_path(temp_dir.join("counters")),
+            buckets_count,
+            None,
+        )
     };
+    remove_orphan_temp_buckets(&temp_dir);
+
+    let colored_buckets_prefix = get_temp_buckets_path(temp_dir.join("color_counters"));
 
-    let colored_buckets_prefix = temp_dir.join("color_counters");
+    // The results of this query are a batch of the output, appended to the previous ones if requested
+    let mut output = if append_output {
+        QueryResultWriter::open_append(&output_file_name)
+    } else {
+        QueryResultWriter::create(&output_file_name)
+    }
+    .unwrap_or_else(|err| {
+        panic!(
+            "Cannot open the query output {}: {}",
+            output_file_name.display(),
+            err
+        )
+    });
 
+    // The kmers count of each query is computed before the expansion of the degenerate kmers
     let query_kmers_count = {
         let mut sequences_lengths = vec![];
         SequencesReader::new().process_file_extended(
-            &query_input,
+            &original_query_input,
             |seq| {
-                sequences_lengths.push((seq.seq.len().saturating_sub(k - 1)) as u64);
+                let kmers_count = (seq.seq.len().saturating_sub(k - 1)) as u64;
+                sequences_lengths.push(kmers_count);
+                if query_both_strands {
+                    sequences_lengths.push(kmers_count);
+                }
             },
             None,
             false,
@@ -164,12 +282,14 @@ pub fn run_query<
             counters_buckets,
             colored_buckets_prefix,
             color_map.colors_subsets_count(),
-            output_file_prefix.clone(),
+            &mut output,
             &query_kmers_count,
+            query_both_strands,
         )
     } else {
         generate_bucket_names(colored_buckets_prefix, buckets_count, None)
     };
+    remove_orphan_temp_buckets(&temp_dir);
 
     if QuerierColorsManager::COLORS_ENABLED {
         let colormap_file = graph_input.with_extension("colors.dat");
@@ -183,26 +303,27 @@ pub fn run_query<
         colored_query_output::<BucketingHash, MergingHash, QuerierColorsManager>(
             &color_map,
             remapped_query_color_buckets,
-            output_file_prefix.clone(),
+            &mut output,
             temp_dir,
             &query_kmers_count,
             colored_query_output_format,
+            query_both_strands,
+            color_quorum,
         );
     }
 
+    // Flushes the batch, completing the compressed stream if any
+    let output_file_name = output.finish().unwrap_or_else(|err| {
+        panic!(
+            "Cannot write the query results to {}: {}",
+            output_file_name.display(),
+            err
+        )
+    });
+
     PHASES_TIMES_MONITOR
         .write()
         .print_stats("Query completed.".to_string());
 
-    let output_file_name = if output_file_prefix.extension().is_none() {
-        if QuerierColorsManager::COLORS_ENABLED {
-            output_file_prefix.with_extension("jsonl")
-        } else {
-            output_file_prefix.with_extension("csv")
-        }
-    } else {
-        output_file_prefix
-    };
-
     output_file_name
 }
@@ -1,12 +1,12 @@
+use crate::query_result_writer::QueryResultWriter;
 use crate::structs::query_colored_counters::{ColorsRange, QueryColoredCountersSerializer};
-use crate::ColoredQueryOutputFormat;
+use crate::{query_orientation, ColoredQueryOutputFormat};
 use colors::colors_manager::ColorMapReader;
 use colors::colors_manager::{ColorsManager, ColorsMergeManager};
 use config::{
     get_compression_level_info, get_memory_mode, ColorIndexType, SwapPriority,
     DEFAULT_PREFETCH_AMOUNT, KEEP_FILES, QUERIES_COUNT_MIN_BATCH,
 };
-use flate2::Compression;
 use hashes::{HashFunctionFactory, MinimizerHashFunctionFactory};
 use io::get_bucket_index;
 use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBinaryReader;
@@ -17,35 +17,24 @@ use parallel_processor::memory_fs::RemoveFileMode;
 use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parking_lot::{Condvar, Mutex};
 use rayon::prelude::*;
-use std::fs::File;
-use std::io::BufWriter;
 use std::io::Write;
 use std::ops::DerefMut;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-enum QueryOutputFileWriter {
-    Plain(File),
-    LZ4Compressed(lz4::Encoder<File>),
-    GzipCompressed(flate2::write::GzEncoder<File>),
-}
-
-impl Write for QueryOutputFileWriter {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        match self {
-            QueryOutputFileWriter::Plain(w) => w.write(buf),
-            QueryOutputFileWriter::LZ4Compressed(w) => w.write(buf),
-            QueryOutputFileWriter::GzipCompressed(w) => w.write(buf),
-        }
-    }
-
-    fn flush(&mut self) -> std::io::Result<()> {
-        match self {
-            QueryOutputFileWriter::Plain(w) => w.flush(),
-            QueryOutputFileWriter::LZ4Compressed(w) => w.flush(),
-            QueryOutputFileWriter::GzipCompressed(w) => w.flush(),
-        }
-    }
+/// Number of matched kmers of the query having each color, from the list of the colors of each
+/// group of matched kmers sorted by color. With a quorum, only the colors of at least
+/// that number of kmers are kept
+fn query_colors_presence(
+    sorted_colors_list: &[(ColorIndexType, u64)],
+    color_quorum: Option<usize>,
+) -> impl Iterator<Item = (ColorIndexType, u64)> + '_ {
+    sorted_colors_list
+        .group_by(|a, b| a.0 == b.0)
+        .map(|qc| (qc[0].0, qc.iter().map(|x| x.1).sum::<u64>()))
+        .filter(move |(_, color_presence)| {
+            color_quorum.map_or(true, |quorum| *color_presence >= quorum as u64)
+        })
 }
 
 pub fn colored_query_output<
@@ -55,10 +44,12 @@ pub fn colored_query_output<
 >(
     colormap: &<CX::ColorsMergeManagerType<H, MH> as ColorsMergeManager<H, MH>>::GlobalColorsTableReader,
     mut colored_query_buckets: Vec<PathBuf>,
-    output_file: PathBuf,
+    output: &mut QueryResultWriter,
     temp_dir: PathBuf,
     query_kmers_count: &[u64],
     colored_query_output_format: ColoredQueryOutputFormat,
+    query_both_strands: bool,
+    color_quorum: Option<usize>,
 ) {
     PHASES_TIMES_MONITOR
         .write()
@@ -76,31 +67,7 @@ pub fn colored_query_output<
     colored_query_buckets.reverse();
     let buckets_channel = Mutex::new(colored_query_buckets);
 
-    let output_file = if output_file.extension().is_none() {
-        output_file.with_extension("jsonl")
-    } else {
-        output_file
-    };
-
-    let query_output_file = File::create(&output_file).unwrap();
-
-    let query_output = Mutex::new((
-        BufWriter::new(
-            match output_file.extension().map(|e| e.to_str()).flatten() {
-                Some("lz4") => QueryOutputFileWriter::LZ4Compressed(
-                    lz4::EncoderBuilder::new()
-                        .level(4)
-                        .build(query_output_file)
-                        .unwrap(),
-                ),
-                Some("gz") => QueryOutputFileWriter::GzipCompressed(
-                    flate2::GzBuilder::new().write(query_output_file, Compression::default()),
-                ),
-                _ => QueryOutputFileWriter::Plain(query_output_file),
-            },
-        ),
-        0,
-    ));
+    let query_output = Mutex::new((output, 0));
     let output_sync_condvar = Condvar::new();
 
     (0..rayon::current_num_threads())
@@ -195,11 +162,20 @@ pub fn colored_query_output<
                     })
                 {
                     jsonline_buffer.clear();
-                    write!(
-                        jsonline_buffer,
-                        "{{\"query_index\":{}, \"matches\":{{",
-                        query
-                    )
+                    if query_both_strands {
+                        write!(
+                            jsonline_buffer,
+                            "{{\"query_index\":{}, \"orientation\":\"{}\", \"matches\":{{",
+                            query / 2,
+                            query_orientation(query)
+                        )
+                    } else {
+                        write!(
+                            jsonline_buffer,
+                            "{{\"query_index\":{}, \"matches\":{{",
+                            query
+                        )
+                    }
                     .unwrap();
 
                     temp_colors_list.clear();
@@ -210,10 +186,9 @@ pub fn colored_query_output<
                     }
                     temp_colors_list.sort_unstable_by_key(|r| r.0);
 
-                    for (i, qc) in temp_colors_list.group_by(|a, b| a.0 == b.0).enumerate() {
-                        let color_index = qc[0].0;
-                        let color_presence = qc.iter().map(|x| x.1).sum::<u64>();
-
+                    for (i, (color_index, color_presence)) in
+                        query_colors_presence(&temp_colors_list, color_quorum).enumerate()
+                    {
                         if i != 0 {
                             write!(jsonline_buffer, ",").unwrap();
                         }
@@ -261,16 +236,39 @@ pub fn colored_query_output<
                     queries_lock.deref_mut()
                 };
 
-                std::io::copy(&mut decompress_stream.get_single_stream(), queries_file).unwrap();
+                std::io::copy(&mut decompress_stream.get_single_stream(), queries_file)
+                    .unwrap_or_else(|err| {
+                        panic!(
+                            "Cannot write the query results to {}: {}",
+                            queries_file.path().display(),
+                            err
+                        )
+                    });
 
                 *query_write_index += 1;
                 output_sync_condvar.notify_all();
             }
         });
 
-    println!(
+    log::debug!(
         "Operations count: {} vs real {}",
         OPS_COUNT.load(Ordering::Relaxed),
         COL_COUNT.load(Ordering::Relaxed)
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::query_colors_presence;
+
+    #[test]
+    fn colors_below_the_quorum_are_dropped() {
+        // Query of 5 kmers, color 1 is found in 3 of them and color 2 in all of them
+        let colors_list = [(1, 2), (1, 1), (2, 3), (2, 2)];
+
+        let presence = |quorum| query_colors_presence(&colors_list, quorum).collect::<Vec<_>>();
+        assert_eq!(presence(None), [(1, 3), (2, 5)]);
+        assert_eq!(presence(Some(3)), [(1, 3), (2, 5)]);
+        assert_eq!(presence(Some(4)), [(2, 5)]);
+    }
+}
@@ -5,9 +5,10 @@ use crate::structs::query_colored_counters::{
 use colors::storage::deserializer::ColorsDeserializer;
 use colors::storage::ColorsSerializerTrait;
 use config::{
-    get_compression_level_info, get_memory_mode, BucketIndexType, ColorIndexType, SwapPriority,
-    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
-    MINIMIZER_BUCKETS_CHECKPOINT_SIZE, QUERIES_COUNT_MIN_BATCH,
+    get_compression_level_info, get_memory_mode, get_temp_buckets_path, spread_temp_buckets,
+    BucketIndexType, ColorIndexType, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
+    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES, MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
+    QUERIES_COUNT_MIN_BATCH,
 };
 use parallel_processor::buckets::concurrent::{BucketsThreadBuffer, BucketsThreadDispatcher};
 use parallel_processor::buckets::readers::compressed_binary_reader::CompressedBinaryReader;
@@ -34,11 +35,11 @@ pub fn colormap_reading<CD: ColorsSerializerTrait>(
         .start_phase("phase: colormap reading".to_string());
 
     let buckets_count = colored_query_buckets.len();
-    let buckets_prefix_path = temp_dir.join("query_colors");
+    let buckets_prefix_path = get_temp_buckets_path(temp_dir.join("query_colors"));
 
     let correct_color_buckets = Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
         buckets_count,
-        buckets_prefix_path,
+        spread_temp_buckets(buckets_prefix_path, buckets_count),
         &(
             get_memory_mode(SwapPriority::MinimizerBuckets),
             MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
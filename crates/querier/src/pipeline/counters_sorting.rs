@@ -1,9 +1,12 @@
+use crate::query_orientation;
+use crate::query_result_writer::QueryResultWriter;
 use byteorder::ReadBytesExt;
 use colors::colors_manager::color_types::SingleKmerColorDataType;
 use colors::colors_manager::ColorsManager;
 use config::{
-    get_compression_level_info, get_memory_mode, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    DEFAULT_PREFETCH_AMOUNT, KEEP_FILES, MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
+    get_compression_level_info, get_memory_mode, spread_temp_buckets, SwapPriority,
+    DEFAULT_PER_CPU_BUFFER_SIZE, DEFAULT_PREFETCH_AMOUNT, KEEP_FILES,
+    MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
 };
 use io::concurrent::temp_reads::extra_data::{
     SequenceExtraDataConsecutiveCompression, SequenceExtraDataOwned,
@@ -21,7 +24,7 @@ use parallel_processor::phase_times_monitor::PHASES_TIMES_MONITOR;
 use parallel_processor::utils::scoped_thread_local::ScopedThreadLocal;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -102,8 +105,9 @@ pub fn counters_sorting<CX: ColorsManager>(
     file_counters_inputs: Vec<PathBuf>,
     colored_buckets_path: PathBuf,
     colors_count: u64,
-    output_file: PathBuf,
+    output: &mut QueryResultWriter,
     query_kmers_count: &[u64],
+    query_both_strands: bool,
 ) -> Vec<PathBuf> {
     PHASES_TIMES_MONITOR
         .write()
@@ -122,7 +126,7 @@ pub fn counters_sorting<CX: ColorsManager>(
     let color_buckets = if CX::COLORS_ENABLED {
         Arc::new(MultiThreadBuckets::<CompressedBinaryWriter>::new(
             buckets_count,
-            colored_buckets_path,
+            spread_temp_buckets(colored_buckets_path, buckets_count),
             &(
                 get_memory_mode(SwapPriority::MinimizerBuckets),
                 MINIMIZER_BUCKETS_CHECKPOINT_SIZE,
@@ -219,39 +223,47 @@ pub fn counters_sorting<CX: ColorsManager>(
     });
 
     if !CX::COLORS_ENABLED {
-        let output_file = if output_file.extension().is_none() {
-            output_file.with_extension("csv")
-        } else {
-            output_file
-        };
-
-        let mut writer = csv::Writer::from_path(output_file).unwrap();
-        writer
-            .write_record(&[
-                "query_index",
-                "matched_kmers",
-                "query_kmers",
-                "match_percentage",
-            ])
-            .unwrap();
+        // The header is written only once when appending to previous results
+        let write_header = output.is_empty();
+        let mut writer = csv::Writer::from_writer(output);
+        let mut header = vec![
+            "query_index",
+            "matched_kmers",
+            "query_kmers",
+            "match_percentage",
+        ];
+        if query_both_strands {
+            header.push("orientation");
+        }
+        if write_header {
+            writer.write_record(&header).unwrap();
+        }
 
         for (query_index, (info, counter)) in query_kmers_count
             .iter()
             .zip(final_counters.iter())
             .enumerate()
         {
-            writer
-                .write_record(&[
-                    query_index.to_string(),
-                    counter.load(Ordering::Relaxed).to_string(),
-                    info.to_string(),
-                    format!(
-                        "{:.2}",
-                        (counter.load(Ordering::Relaxed) as f64 / *info as f64)
-                    ),
-                ])
-                .unwrap();
+            let mut record = vec![
+                if query_both_strands {
+                    query_index / 2
+                } else {
+                    query_index
+                }
+                .to_string(),
+                counter.load(Ordering::Relaxed).to_string(),
+                info.to_string(),
+                format!(
+                    "{:.2}",
+                    (counter.load(Ordering::Relaxed) as f64 / *info as f64)
+                ),
+            ];
+            if query_both_strands {
+                record.push(query_orientation(query_index).to_string());
+            }
+            writer.write_record(&record).unwrap();
         }
+        writer.flush().unwrap();
         vec![]
     } else {
         color_buckets.finalize()
@@ -8,13 +8,13 @@ use colors::colors_manager::color_types::{
 };
 use colors::colors_manager::{ColorsManager, MinimizerBucketingSeqColorData};
 use config::{
-    get_memory_mode, BucketIndexType, SwapPriority, DEFAULT_PER_CPU_BUFFER_SIZE,
-    MINIMUM_SUBBUCKET_KMERS_COUNT, RESPLITTING_MAX_K_M_DIFFERENCE,
+    get_memory_mode, get_temp_buckets_path, spread_temp_buckets, BucketIndexType, SwapPriority,
+    DEFAULT_PER_CPU_BUFFER_SIZE, MINIMUM_SUBBUCKET_KMERS_COUNT, RESPLITTING_MAX_K_M_DIFFERENCE,
 };
 use hashbrown::HashMap;
 use hashes::HashFunction;
 use hashes::HashFunctionFactory;
-use hashes::{ExtendableHashTraitType, MinimizerHashFunctionFactory};
+use hashes::{minimizer_order_key, ExtendableHashTraitType, MinimizerHashFunctionFactory};
 use io::compressed_read::CompressedRead;
 use io::compressed_read::CompressedReadIndipendent;
 use io::concurrent::temp_reads::extra_data::{
@@ -231,7 +231,7 @@ impl<H: MinimizerHashFunctionFactory, MH: HashFunctionFactory, CX: ColorsManager
 
         let minimizer = hashes
             .iter()
-            .min_by_key(|k| H::get_full_minimizer(k.to_unextendable()))
+            .min_by_key(|k| minimizer_order_key::<H>(k.to_unextendable()))
             .unwrap();
 
         H::get_bucket(
@@ -426,7 +426,10 @@ pub fn parallel_kmers_counting<
 
     let counters_buckets = Arc::new(MultiThreadBuckets::<LockFreeBinaryWriter>::new(
         buckets_count,
-        out_directory.as_ref().join("counters"),
+        spread_temp_buckets(
+            get_temp_buckets_path(out_directory.as_ref().join("counters")),
+            buckets_count,
+        ),
         &(
             get_memory_mode(SwapPriority::QueryCounters),
             LockFreeBinaryWriter::CHECKPOINT_SIZE_UNLIMITED,
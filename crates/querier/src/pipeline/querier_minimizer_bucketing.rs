@@ -165,7 +165,7 @@ impl<H: MinimizerHashFunctionFactory, CX: ColorsManager>
                 if CX::COLORS_ENABLED
                     && (color.debug_count() != sequence.seq.len() - self.global_data.k + 1)
                 {
-                    println!(
+                    log::warn!(
                         "WARN: Sequence does not have enough colors, please check matching k size:\n{}\n{}",
                         std::str::from_utf8(sequence.ident_data).unwrap(),
                         std::str::from_utf8(sequence.seq).unwrap()
@@ -300,6 +300,7 @@ pub fn minimizer_bucketing<H: MinimizerHashFunctionFactory, CX: ColorsManager>(
             None,
             CX::COLORS_ENABLED,
             0,
+            None,
         ),
         queries_count.load(Ordering::Relaxed) as u64,
     )
@@ -0,0 +1,171 @@
+use flate2::Compression;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+enum QueryOutputFileWriter {
+    Plain(File),
+    LZ4Compressed(lz4::Encoder<File>),
+    GzipCompressed(flate2::write::GzEncoder<File>),
+}
+
+impl Write for QueryOutputFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            QueryOutputFileWriter::Plain(w) => w.write(buf),
+            QueryOutputFileWriter::LZ4Compressed(w) => w.write(buf),
+            QueryOutputFileWriter::GzipCompressed(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            QueryOutputFileWriter::Plain(w) => w.flush(),
+            QueryOutputFileWriter::LZ4Compressed(w) => w.flush(),
+            QueryOutputFileWriter::GzipCompressed(w) => w.flush(),
+        }
+    }
+}
+
+/// Writer of the query results, compressed depending on the file extension (.lz4 or .gz).
+/// It can be kept open across multiple query batches: each batch is flushed when it ends,
+/// so a consumer tailing the file always sees the complete results of the ended batches
+pub struct QueryResultWriter {
+    path: PathBuf,
+    writer: BufWriter<QueryOutputFileWriter>,
+    // Nothing has been written to the file yet, neither by this writer nor before it was opened
+    is_empty: bool,
+}
+
+impl QueryResultWriter {
+    /// Creates the output file, truncating its previous contents
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::new(path.as_ref(), File::create(path.as_ref())?)
+    }
+
+    /// Opens the output file to append new results after the existing ones. The compressed formats
+    /// support concatenated streams, so the appended results are written in a new stream
+    pub fn open_append(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::new(
+            path.as_ref(),
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path.as_ref())?,
+        )
+    }
+
+    fn new(path: &Path, file: File) -> std::io::Result<Self> {
+        let is_empty = file.metadata()?.len() == 0;
+
+        let writer = match path.extension().and_then(|e| e.to_str()) {
+            Some("lz4") => QueryOutputFileWriter::LZ4Compressed(
+                lz4::EncoderBuilder::new().level(4).build(file)?,
+            ),
+            Some("gz") => QueryOutputFileWriter::GzipCompressed(
+                flate2::GzBuilder::new().write(file, Compression::default()),
+            ),
+            _ => QueryOutputFileWriter::Plain(file),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(writer),
+            is_empty,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if the output has no results yet, for example to write a header only once
+    pub fn is_empty(&self) -> bool {
+        self.is_empty
+    }
+
+    /// Ends a batch of results, flushing them (and the compressor buffers) to the file
+    pub fn end_batch(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Ends the last batch and completes the compressed stream, if any
+    pub fn finish(self) -> std::io::Result<PathBuf> {
+        match self.writer.into_inner().map_err(|err| err.into_error())? {
+            QueryOutputFileWriter::Plain(mut file) => file.flush()?,
+            QueryOutputFileWriter::LZ4Compressed(encoder) => {
+                let (_, result) = encoder.finish();
+                result?
+            }
+            QueryOutputFileWriter::GzipCompressed(encoder) => {
+                encoder.finish()?;
+            }
+        }
+        Ok(self.path)
+    }
+}
+
+impl Write for QueryResultWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !buf.is_empty() {
+            self.is_empty = false;
+        }
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryResultWriter;
+    use std::io::{Read, Write};
+    use utils::test_dir::TestDir;
+
+    #[test]
+    fn batches_are_appended_with_a_flush_between_them() {
+        let temp_dir = TestDir::new("query-writer");
+        let output_file = temp_dir.join("results.jsonl");
+        let gz_output_file = temp_dir.join("results.jsonl.gz");
+
+        let mut writer = QueryResultWriter::create(&output_file).unwrap();
+        assert!(writer.is_empty());
+        writeln!(writer, "{{\"query_index\":0}}").unwrap();
+        writer.end_batch().unwrap();
+        // The first batch is readable while the writer is still open
+        let first_batch = std::fs::read_to_string(&output_file).unwrap();
+
+        writeln!(writer, "{{\"query_index\":1}}").unwrap();
+        writer.end_batch().unwrap();
+        let both_batches = std::fs::read_to_string(&output_file).unwrap();
+        writer.finish().unwrap();
+
+        // A following run appends to the same output
+        let mut writer = QueryResultWriter::open_append(&output_file).unwrap();
+        assert!(!writer.is_empty());
+        writeln!(writer, "{{\"query_index\":2}}").unwrap();
+        writer.finish().unwrap();
+        let appended = std::fs::read_to_string(&output_file).unwrap();
+
+        // Each batch of a compressed output is a separate stream
+        for batch in 0..2 {
+            let mut writer = QueryResultWriter::open_append(&gz_output_file).unwrap();
+            writeln!(writer, "{{\"query_index\":{}}}", batch).unwrap();
+            writer.finish().unwrap();
+        }
+        let mut gz_contents = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(&gz_output_file).unwrap())
+            .read_to_string(&mut gz_contents)
+            .unwrap();
+
+        assert_eq!(first_batch, "{\"query_index\":0}\n");
+        assert_eq!(both_batches, "{\"query_index\":0}\n{\"query_index\":1}\n");
+        assert_eq!(
+            appended,
+            "{\"query_index\":0}\n{\"query_index\":1}\n{\"query_index\":2}\n"
+        );
+        assert_eq!(gz_contents, "{\"query_index\":0}\n{\"query_index\":1}\n");
+    }
+}
@@ -29,6 +29,27 @@ impl<CHI> MapEntry<CHI> {
         self.count_flags.set(self.count_flags.get() + 1);
     }
 
+    /// Increments the counter only while the kmer multiplicity is below the cap, so that
+    /// highly repetitive kmers saturate their count. The kmer itself is always kept
+    #[inline(always)]
+    pub fn incr_saturating(&mut self, max_multiplicity: usize) {
+        self.add_saturating(1, max_multiplicity);
+    }
+
+    /// Adds the weight of a kmer occurrence to the counter, saturating as [`Self::incr_saturating`]:
+    /// the multiplicity stops exactly at max_multiplicity, even if the weight would exceed it.
+    /// The counter never grows past its own bits, into the used marker and the flags
+    #[inline(always)]
+    pub fn add_saturating(&mut self, weight: usize, max_multiplicity: usize) {
+        let max_counter = max_multiplicity
+            .saturating_mul(1 << self.counter_multiplicity_shift())
+            .min(COUNTER_MASK);
+        let counter = self.get_counter();
+        if counter < max_counter {
+            self.set_counter_after_check(counter.saturating_add(weight).min(max_counter));
+        }
+    }
+
     #[inline(always)]
     pub fn set_used(&self) {
         self.count_flags.set(self.count_flags.get() | USED_MARKER);
@@ -60,10 +81,91 @@ impl<CHI> MapEntry<CHI> {
         (self.count_flags.get() >> FLAGS_SHIFT) as u8
     }
 
-    pub fn get_kmer_multiplicity(&self) -> usize {
+    #[inline(always)]
+    fn counter_multiplicity_shift(&self) -> u8 {
         // If the current set has both the partial sequences endings, we should divide the counter by 2,
         // as all the kmers are counted exactly two times
-        self.get_counter()
-            >> ((self.get_flags() == (READ_FLAG_INCL_BEGIN | READ_FLAG_INCL_END)) as u8)
+        (self.get_flags() == (READ_FLAG_INCL_BEGIN | READ_FLAG_INCL_END)) as u8
+    }
+
+    pub fn get_kmer_multiplicity(&self) -> usize {
+        self.get_counter() >> self.counter_multiplicity_shift()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MapEntry, COUNTER_MASK};
+    use config::{READ_FLAG_INCL_BEGIN, READ_FLAG_INCL_END};
+
+    #[test]
+    fn homopolymer_count_saturates() {
+        const K: usize = 31;
+        const MAX_MULTIPLICITY: usize = 100;
+
+        // A homopolymer run contains the same kmer at every position
+        let homopolymer = vec![b'A'; 10000];
+
+        let mut entry = MapEntry::new(());
+        let mut capped_entry = MapEntry::new(());
+        for _ in homopolymer.windows(K) {
+            entry.incr_saturating(usize::MAX);
+            capped_entry.incr_saturating(MAX_MULTIPLICITY);
+        }
+
+        assert_eq!(entry.get_kmer_multiplicity(), homopolymer.len() - K + 1);
+        assert_eq!(capped_entry.get_kmer_multiplicity(), MAX_MULTIPLICITY);
+
+        // Kmers counted twice saturate on their multiplicity, not on the raw counter
+        let mut double_counted_entry = MapEntry::new(());
+        double_counted_entry.update_flags(READ_FLAG_INCL_BEGIN | READ_FLAG_INCL_END);
+        for _ in 0..(MAX_MULTIPLICITY * 4) {
+            double_counted_entry.incr_saturating(MAX_MULTIPLICITY);
+        }
+        assert_eq!(
+            double_counted_entry.get_kmer_multiplicity(),
+            MAX_MULTIPLICITY
+        );
+    }
+
+    #[test]
+    fn weighted_occurrences_add_their_weight() {
+        let mut entry = MapEntry::new(());
+        entry.add_saturating(3, usize::MAX);
+        entry.add_saturating(1, usize::MAX);
+        assert_eq!(entry.get_kmer_multiplicity(), 4);
+
+        // A saturated counter ignores further weighted occurrences
+        entry.add_saturating(1000, 4);
+        assert_eq!(entry.get_kmer_multiplicity(), 4);
+
+        // A weight crossing the cap stops exactly on it
+        let mut capped_entry = MapEntry::new(());
+        capped_entry.add_saturating(3, 5);
+        capped_entry.add_saturating(3, 5);
+        assert_eq!(capped_entry.get_kmer_multiplicity(), 5);
+
+        let mut double_counted_entry = MapEntry::new(());
+        double_counted_entry.update_flags(READ_FLAG_INCL_BEGIN | READ_FLAG_INCL_END);
+        double_counted_entry.add_saturating(7, 3);
+        assert_eq!(double_counted_entry.get_kmer_multiplicity(), 3);
+        assert_eq!(
+            double_counted_entry.get_flags(),
+            READ_FLAG_INCL_BEGIN | READ_FLAG_INCL_END
+        );
+    }
+
+    #[test]
+    fn large_weights_keep_the_flags() {
+        let mut entry = MapEntry::new(());
+        entry.update_flags(READ_FLAG_INCL_BEGIN);
+        entry.set_counter_after_check(COUNTER_MASK - 1);
+
+        // Without a multiplicity cap the counter saturates on its own bits
+        entry.add_saturating(1000, usize::MAX);
+        entry.add_saturating(usize::MAX, usize::MAX);
+        assert_eq!(entry.get_counter(), COUNTER_MASK);
+        assert_eq!(entry.get_flags(), READ_FLAG_INCL_BEGIN);
+        assert!(!entry.is_used());
     }
 }
@@ -0,0 +1,87 @@
+use criterion::*;
+use ggcat_utils::numa::NumaTopology;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+const BUCKETS_COUNT: usize = 256;
+// About the size of a kmers hashmap of a bucket
+const BUCKET_ENTRIES: usize = 1 << 20;
+
+// Allocates and fills a bucket working memory, then reads it in a cache unfriendly order,
+// as the kmers merge does with the hashmap of each bucket
+fn process_bucket(bucket: usize) -> u64 {
+    let mut map = vec![0u64; BUCKET_ENTRIES];
+    for (i, entry) in map.iter_mut().enumerate() {
+        *entry = (i ^ bucket) as u64;
+    }
+
+    let mut position = bucket;
+    let mut sum = 0u64;
+    for _ in 0..BUCKET_ENTRIES {
+        position = (position.wrapping_mul(6364136223846793005).wrapping_add(1)) % BUCKET_ENTRIES;
+        sum = sum.wrapping_add(map[position]);
+    }
+    sum
+}
+
+fn thread_pool(threads_count: usize, topology: Option<NumaTopology>) -> ThreadPool {
+    let builder = ThreadPoolBuilder::new().num_threads(threads_count);
+    match topology {
+        Some(topology) => builder.start_handler(move |thread_index| {
+            topology
+                .pin_current_thread(topology.thread_node(thread_index, threads_count))
+                .unwrap();
+        }),
+        None => builder,
+    }
+    .build()
+    .unwrap()
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let threads_count = std::thread::available_parallelism()
+        .map(|t| t.get())
+        .unwrap_or(1);
+    let topology = NumaTopology::detect();
+    if topology.is_none() {
+        println!("Single NUMA node machine, the pinned threads cannot be compared");
+    }
+
+    let mut group = c.benchmark_group("numa-placement");
+    group.sample_size(10);
+
+    let unpinned = thread_pool(threads_count, None);
+    group.bench_function("unpinned", |b| {
+        b.iter(|| {
+            unpinned.install(|| {
+                black_box(
+                    (0..BUCKETS_COUNT)
+                        .into_par_iter()
+                        .map(process_bucket)
+                        .sum::<u64>(),
+                )
+            })
+        })
+    });
+
+    if let Some(topology) = topology {
+        let pinned = thread_pool(threads_count, Some(topology));
+        group.bench_function("pinned", |b| {
+            b.iter(|| {
+                pinned.install(|| {
+                    black_box(
+                        (0..BUCKETS_COUNT)
+                            .into_par_iter()
+                            .map(process_bucket)
+                            .sum::<u64>(),
+                    )
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,8 +1,10 @@
 #[macro_use]
 pub mod debug_functions;
 pub mod fast_rand_bool;
+pub mod numa;
 pub mod owned_drop;
 pub mod resource_counter;
+pub mod test_dir;
 pub mod vec_slice;
 
 use std::cmp::max;
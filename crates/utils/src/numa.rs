@@ -0,0 +1,124 @@
+use std::path::Path;
+
+const NODES_SYSFS_DIR: &str = "/sys/devices/system/node";
+
+/// Cpus of each NUMA node of the machine, as listed in /sys/devices/system/node
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NumaTopology {
+    pub nodes_cpus: Vec<Vec<usize>>,
+}
+
+/// Parses a kernel cpu list, as 0-3,8,10-11
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                cpus.extend(start.parse::<usize>().ok()?..=end.parse::<usize>().ok()?)
+            }
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+impl NumaTopology {
+    /// Detects the NUMA nodes of the machine, None if the topology is not available
+    /// (as on non Linux systems) or the machine has a single node with cpus
+    pub fn detect() -> Option<Self> {
+        Self::from_sysfs(Path::new(NODES_SYSFS_DIR))
+    }
+
+    fn from_sysfs(nodes_dir: &Path) -> Option<Self> {
+        let mut nodes: Vec<_> = std::fs::read_dir(nodes_dir)
+            .ok()?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let node_index: usize = entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix("node")?
+                    .parse()
+                    .ok()?;
+                let cpus =
+                    parse_cpu_list(&std::fs::read_to_string(entry.path().join("cpulist")).ok()?)?;
+                Some((node_index, cpus))
+            })
+            // Memory only nodes cannot run threads
+            .filter(|(_, cpus)| !cpus.is_empty())
+            .collect();
+        nodes.sort_unstable();
+
+        if nodes.len() < 2 {
+            return None;
+        }
+
+        Some(Self {
+            nodes_cpus: nodes.into_iter().map(|(_, cpus)| cpus).collect(),
+        })
+    }
+
+    pub fn nodes_count(&self) -> usize {
+        self.nodes_cpus.len()
+    }
+
+    /// Node of a thread of a pool: the threads are split in contiguous blocks, one for each node,
+    /// so that the threads with nearby indexes (that usually share work) run on the same node
+    pub fn thread_node(&self, thread_index: usize, threads_count: usize) -> usize {
+        let threads_count = threads_count.max(1);
+        (thread_index % threads_count) * self.nodes_count() / threads_count
+    }
+
+    /// Pins the current thread to the cpus of a node. The memory it allocates afterwards is
+    /// then placed on the same node by the default (first touch) kernel policy
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    pub fn pin_current_thread(&self, node: usize) -> std::io::Result<()> {
+        unsafe {
+            let mut cpu_set: libc::cpu_set_t = std::mem::zeroed();
+            for cpu in &self.nodes_cpus[node] {
+                libc::CPU_SET(*cpu, &mut cpu_set);
+            }
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &cpu_set) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(all(feature = "numa", target_os = "linux")))]
+    pub fn pin_current_thread(&self, _node: usize) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "threads pinning requires the numa feature on Linux",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cpu_list, NumaTopology};
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn topology_from_sysfs() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-a"), None);
+
+        let nodes_dir = TestDir::new("numa");
+        for (node, cpus) in [("node1", "4-7"), ("node0", "0-3"), ("node2", "")] {
+            std::fs::create_dir_all(nodes_dir.join(node)).unwrap();
+            std::fs::write(nodes_dir.join(node).join("cpulist"), cpus).unwrap();
+        }
+        let topology = NumaTopology::from_sysfs(nodes_dir.path());
+
+        // The memory only node is skipped
+        let topology = topology.unwrap();
+        assert_eq!(topology.nodes_cpus, [vec![0, 1, 2, 3], vec![4, 5, 6, 7]]);
+        let nodes: Vec<_> = (0..6).map(|t| topology.thread_node(t, 6)).collect();
+        assert_eq!(nodes, [0, 0, 0, 1, 1, 1]);
+    }
+}
@@ -39,6 +39,12 @@ impl<T> VecSlice<T> {
     pub fn get_slice<'a>(&self, vec: &'a Vec<T>) -> &'a [T] {
         &vec[self.pos..self.pos + self.len]
     }
+
+    /// Checked version of get_slice, to be used with slices coming from untrusted (deserialized) data
+    pub fn try_get_slice<'a>(&self, vec: &'a Vec<T>) -> Option<&'a [T]> {
+        vec.get(self.pos..self.pos.checked_add(self.len)?)
+    }
+
     pub fn get_slice_mut<'a>(&self, vec: &'a mut Vec<T>) -> &'a mut [T] {
         &mut vec[self.pos..self.pos + self.len]
     }
@@ -55,3 +61,26 @@ impl<T: Clone> VecSlice<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::VecSlice;
+
+    #[test]
+    fn try_get_slice_bounds() {
+        let backing = vec![1, 2, 3, 4];
+
+        assert_eq!(
+            VecSlice::new(1, 3).try_get_slice(&backing),
+            Some(&[2, 3, 4][..])
+        );
+        assert_eq!(
+            VecSlice::<i32>::EMPTY.try_get_slice(&backing),
+            Some(&[][..])
+        );
+
+        assert_eq!(VecSlice::new(2, 3).try_get_slice(&backing), None);
+        assert_eq!(VecSlice::new(5, 0).try_get_slice(&backing), None);
+        assert_eq!(VecSlice::new(usize::MAX, 2).try_get_slice(&backing), None);
+    }
+}